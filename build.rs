@@ -1,6 +1,22 @@
+#[cfg(any(
+    feature = "vulkan",
+    feature = "blas",
+    all(feature = "mps", target_os = "macos")
+))]
 use std::env;
+#[cfg(feature = "cuda")]
 use std::fs;
+#[cfg(any(
+    feature = "cuda",
+    feature = "vulkan",
+    all(feature = "mps", target_os = "macos")
+))]
 use std::path::PathBuf;
+#[cfg(any(
+    feature = "cuda",
+    feature = "vulkan",
+    all(feature = "mps", target_os = "macos")
+))]
 use std::process::Command;
 
 #[cfg(feature = "cuda")]
@@ -60,8 +76,7 @@ fn compile_vulkan_shaders() -> std::io::Result<()> {
     if status.success() {
         Ok(())
     } else {
-        Err(std::io::Error::new(
-            std::io::ErrorKind::Other,
+        Err(std::io::Error::other(
             "Failed to compile binary operations shader",
         ))
     }
@@ -79,7 +94,12 @@ fn compile_metal_shaders() -> std::io::Result<()> {
     // Create output directory if it doesn't exist
     std::fs::create_dir_all(&out_dir)?;
 
-    let shader_files = ["binary_ops.metal", "operations.metal", "reduction.metal"];
+    let shader_files = [
+        "binary_ops.metal",
+        "matrix_ops.metal",
+        "operations.metal",
+        "reduction.metal",
+    ];
 
     for shader in shader_files.iter() {
         let shader_path = shader_dir.join(shader);
@@ -106,10 +126,10 @@ fn compile_metal_shaders() -> std::io::Result<()> {
             .status()?;
 
         if !status.success() {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to compile {}", shader),
-            ));
+            return Err(std::io::Error::other(format!(
+                "Failed to compile {}",
+                shader
+            )));
         }
     }
 
@@ -137,10 +157,7 @@ fn compile_metal_shaders() -> std::io::Result<()> {
         .status()?;
 
     if !status.success() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "Failed to create metallib",
-        ));
+        return Err(std::io::Error::other("Failed to create metallib"));
     }
 
     Ok(())
@@ -209,6 +226,20 @@ fn main() {
         }
     }
 
+    // Link against a CBLAS implementation if the "blas" feature is enabled
+    #[cfg(feature = "blas")]
+    {
+        println!("cargo:rerun-if-env-changed=CETANA_BLAS_LIB");
+        println!("cargo:rerun-if-env-changed=CETANA_BLAS_LIB_DIR");
+
+        // OpenBLAS by default; set CETANA_BLAS_LIB=mkl_rt or blis to switch
+        let blas_lib = env::var("CETANA_BLAS_LIB").unwrap_or_else(|_| "openblas".to_string());
+        if let Ok(dir) = env::var("CETANA_BLAS_LIB_DIR") {
+            println!("cargo:rustc-link-search=native={}", dir);
+        }
+        println!("cargo:rustc-link-lib={}", blas_lib);
+    }
+
     // Compile Vulkan shaders only if the "vulkan" feature is enabled
     #[cfg(feature = "vulkan")]
     {
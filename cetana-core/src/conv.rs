@@ -0,0 +1,90 @@
+//! Convolution geometry and the im2col unfolding shared by every backend's conv2d.
+
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::{CoreError, CoreResult};
+
+/// Geometry of a 2D convolution in NCHW layout, with an `[out_channels, in_channels, kh, kw]`
+/// weight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Conv2dShape {
+    pub input: [usize; 4],
+    pub weight: [usize; 4],
+    pub stride: (usize, usize),
+    pub padding: (usize, usize),
+}
+
+impl Conv2dShape {
+    /// Checks that the weight fits the input and the padded kernel fits inside the image.
+    pub fn new(
+        input: [usize; 4],
+        weight: [usize; 4],
+        stride: (usize, usize),
+        padding: (usize, usize),
+    ) -> CoreResult<Self> {
+        if input[1] != weight[1]
+            || stride.0 == 0
+            || stride.1 == 0
+            || input[2] + 2 * padding.0 < weight[2]
+            || input[3] + 2 * padding.1 < weight[3]
+        {
+            return Err(CoreError::Shape(format!(
+                "Invalid conv2d shapes: input {:?}, weight {:?}, stride {:?}, padding {:?}",
+                input, weight, stride, padding
+            )));
+        }
+
+        Ok(Self {
+            input,
+            weight,
+            stride,
+            padding,
+        })
+    }
+
+    pub fn output(&self) -> [usize; 4] {
+        let [n, _, h, w] = self.input;
+        let [out_channels, _, kh, kw] = self.weight;
+        [
+            n,
+            out_channels,
+            (h + 2 * self.padding.0 - kh) / self.stride.0 + 1,
+            (w + 2 * self.padding.1 - kw) / self.stride.1 + 1,
+        ]
+    }
+
+    pub fn output_len(&self) -> usize {
+        self.output().iter().product()
+    }
+}
+
+/// Unfolds every receptive field of image `b` into a column of a
+/// `[in_channels * kh * kw, out_h * out_w]` matrix, so the convolution becomes one matmul.
+pub fn im2col(input: &[f32], shape: &Conv2dShape, b: usize) -> Vec<f32> {
+    let [_, channels, height, width] = shape.input;
+    let [_, _, kernel_h, kernel_w] = shape.weight;
+    let [_, _, out_h, out_w] = shape.output();
+
+    let out_spatial = out_h * out_w;
+    let mut columns = vec![0.0; channels * kernel_h * kernel_w * out_spatial];
+    for c in 0..channels {
+        for ky in 0..kernel_h {
+            for kx in 0..kernel_w {
+                let row = (c * kernel_h + ky) * kernel_w + kx;
+                for oy in 0..out_h {
+                    for ox in 0..out_w {
+                        let y = (oy * shape.stride.0 + ky) as isize - shape.padding.0 as isize;
+                        let x = (ox * shape.stride.1 + kx) as isize - shape.padding.1 as isize;
+                        if y >= 0 && x >= 0 && (y as usize) < height && (x as usize) < width {
+                            columns[row * out_spatial + oy * out_w + ox] = input
+                                [((b * channels + c) * height + y as usize) * width + x as usize];
+                        }
+                    }
+                }
+            }
+        }
+    }
+    columns
+}
@@ -0,0 +1,84 @@
+//! CPU kernels over row-major f32 slices. The host backend of `cetana` runs the same code.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// The `[m, k]` product of an `[m, n]` matrix and an `[n, k]` matrix.
+pub fn matmul(a: &[f32], b: &[f32], m: usize, n: usize, k: usize) -> Vec<f32> {
+    let mut result = vec![0.0; m * k];
+    let block_size = 32;
+
+    // Pre-transpose matrix B and store in contiguous memory
+    let b_trans = transpose(b, n, k);
+
+    // Process blocks with better cache utilization
+    for i0 in (0..m).step_by(block_size) {
+        for l0 in (0..n).step_by(block_size) {
+            for j0 in (0..k).step_by(block_size) {
+                let i_end = (i0 + block_size).min(m);
+                let l_end = (l0 + block_size).min(n);
+                let j_end = (j0 + block_size).min(k);
+
+                for i in i0..i_end {
+                    for l in l0..l_end {
+                        let a_val = a[i * n + l];
+                        let row_idx = i * k;
+                        for j in j0..j_end {
+                            result[row_idx + j] += a_val * b_trans[j * n + l];
+                        }
+                    }
+                }
+            }
+        }
+    }
+    result
+}
+
+/// The `[cols, rows]` transpose of a `[rows, cols]` matrix.
+pub fn transpose(a: &[f32], rows: usize, cols: usize) -> Vec<f32> {
+    let mut out = vec![0.0; rows * cols];
+    for j in 0..cols {
+        for i in 0..rows {
+            out[j * rows + i] = a[i * cols + j];
+        }
+    }
+    out
+}
+
+/// Adds `bias` to every row of width `bias.len()`.
+pub fn add_rows(a: &mut [f32], bias: &[f32]) {
+    for row in a.chunks_exact_mut(bias.len()) {
+        row.iter_mut().zip(bias).for_each(|(x, b)| *x += b);
+    }
+}
+
+pub fn relu(a: &mut [f32]) {
+    a.iter_mut().for_each(|x| *x = x.max(0.0));
+}
+
+pub fn sigmoid(a: &mut [f32]) {
+    a.iter_mut()
+        .for_each(|x| *x = 1.0 / (1.0 + libm::expf(-*x)));
+}
+
+pub fn tanh(a: &mut [f32]) {
+    a.iter_mut().for_each(|x| *x = libm::tanhf(*x));
+}
+
+pub fn swish(a: &mut [f32]) {
+    a.iter_mut().for_each(|x| *x /= 1.0 + libm::expf(-*x));
+}
+
+/// Softmax over each run of `len` values, shifted by the run's maximum so large inputs
+/// don't overflow.
+pub fn softmax(a: &mut [f32], len: usize) {
+    for row in a.chunks_exact_mut(len) {
+        let max = row.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let mut sum = 0.0;
+        for x in row.iter_mut() {
+            *x = libm::expf(*x - max);
+            sum += *x;
+        }
+        row.iter_mut().for_each(|x| *x /= sum);
+    }
+}
@@ -0,0 +1,56 @@
+//! The inference core of cetana, for `no_std` targets with an allocator.
+//!
+//! This is the subset of cetana needed to run a trained model: a plain host [`Tensor`],
+//! the CPU kernels behind it, the [`Linear`], [`Conv2d`] and activation layers, and
+//! reading weights from `.safetensors` bytes. It has no backends, autograd, training or
+//! file system access; models are trained with `cetana` and their weights saved with
+//! `save_safetensors`, then embedded or flashed alongside the program.
+//!
+//! ```ignore
+//! static WEIGHTS: &[u8] = include_bytes!("model.safetensors");
+//!
+//! let mut state = cetana_core::safetensors::decode(WEIGHTS)?;
+//! let model = Sequential::new()
+//!     .layer(Linear::from_state(&mut state, "fc1")?)
+//!     .layer(ReLU)
+//!     .layer(Linear::from_state(&mut state, "fc2")?);
+//! let output = model.forward(&Tensor::from_vec(input, &[1, 4])?)?;
+//! ```
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::string::String;
+use core::fmt::{Display, Formatter};
+
+pub mod conv;
+pub mod kernels;
+pub mod nn;
+pub mod safetensors;
+pub mod tensor;
+
+pub use nn::{Conv2d, Layer, Linear, ReLU, Sequential, Sigmoid, Softmax, Swish, Tanh};
+pub use safetensors::StateDict;
+pub use tensor::Tensor;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CoreError {
+    /// Shapes that don't fit the operation or the data.
+    Shape(String),
+    /// Malformed or unsupported weight data.
+    Format(String),
+}
+
+impl Display for CoreError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CoreError::Shape(s) => write!(f, "{}", s),
+            CoreError::Format(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+impl core::error::Error for CoreError {}
+
+pub type CoreResult<T> = Result<T, CoreError>;
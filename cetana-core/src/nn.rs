@@ -0,0 +1,247 @@
+//! Inference-only layers. Weights come from a [`StateDict`] under the same names `cetana`
+//! gives them, so a model saved there loads here.
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::kernels;
+use crate::safetensors::StateDict;
+use crate::tensor::Tensor;
+use crate::{CoreError, CoreResult};
+
+pub trait Layer {
+    fn forward(&self, input: &Tensor) -> CoreResult<Tensor>;
+}
+
+// `prefix.name`, or just `name` at the top level
+fn key(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.into()
+    } else {
+        format!("{}.{}", prefix, name)
+    }
+}
+
+/// y = xW^T + b, with a `[out_features, in_features]` weight.
+#[derive(Debug, Clone)]
+pub struct Linear {
+    weight_t: Tensor,
+    bias: Option<Tensor>,
+}
+
+impl Linear {
+    pub fn new(weight: Tensor, bias: Option<Tensor>) -> CoreResult<Self> {
+        let &[out_features, in_features] = weight.shape() else {
+            return Err(CoreError::Shape(format!(
+                "Linear weight must be 2D, got {:?}",
+                weight.shape()
+            )));
+        };
+        if bias
+            .as_ref()
+            .is_some_and(|b| b.data().len() != out_features)
+        {
+            return Err(CoreError::Shape(format!(
+                "Linear bias doesn't have {} values",
+                out_features
+            )));
+        }
+        // Stored transposed so every forward pass is a plain matmul
+        let weight_t = kernels::transpose(weight.data(), out_features, in_features);
+        Ok(Self {
+            weight_t: Tensor::from_vec(weight_t, &[in_features, out_features])?,
+            bias,
+        })
+    }
+
+    /// Takes `prefix.weight` and, if present, `prefix.bias` out of `state`.
+    pub fn from_state(state: &mut StateDict, prefix: &str) -> CoreResult<Self> {
+        let weight = state.take(&key(prefix, "weight"))?;
+        let bias = state.take(&key(prefix, "bias")).ok();
+        Self::new(weight, bias)
+    }
+}
+
+impl Layer for Linear {
+    fn forward(&self, input: &Tensor) -> CoreResult<Tensor> {
+        let mut output = input.matmul(&self.weight_t)?;
+        if let Some(bias) = &self.bias {
+            kernels::add_rows(output.data_mut(), bias.data());
+        }
+        Ok(output)
+    }
+}
+
+/// 2D convolution over NCHW input with an `[out_channels, in_channels, kh, kw]` weight.
+#[derive(Debug, Clone)]
+pub struct Conv2d {
+    weight: Tensor,
+    bias: Option<Tensor>,
+    stride: (usize, usize),
+    padding: (usize, usize),
+}
+
+impl Conv2d {
+    pub fn new(
+        weight: Tensor,
+        bias: Option<Tensor>,
+        stride: (usize, usize),
+        padding: (usize, usize),
+    ) -> CoreResult<Self> {
+        let &[out_channels, _, _, _] = weight.shape() else {
+            return Err(CoreError::Shape(format!(
+                "Conv2d weight must be 4D, got {:?}",
+                weight.shape()
+            )));
+        };
+        if bias
+            .as_ref()
+            .is_some_and(|b| b.data().len() != out_channels)
+        {
+            return Err(CoreError::Shape(format!(
+                "Conv2d bias doesn't have {} values",
+                out_channels
+            )));
+        }
+        Ok(Self {
+            weight,
+            bias,
+            stride,
+            padding,
+        })
+    }
+
+    /// Takes `prefix.weight` and, if present, `prefix.bias` out of `state`. The padding is
+    /// explicit: a `cetana` layer with `PaddingMode::Same` and stride 1 pads by half the
+    /// kernel size.
+    pub fn from_state(
+        state: &mut StateDict,
+        prefix: &str,
+        stride: (usize, usize),
+        padding: (usize, usize),
+    ) -> CoreResult<Self> {
+        let weight = state.take(&key(prefix, "weight"))?;
+        let bias = state.take(&key(prefix, "bias")).ok();
+        Self::new(weight, bias, stride, padding)
+    }
+}
+
+impl Layer for Conv2d {
+    fn forward(&self, input: &Tensor) -> CoreResult<Tensor> {
+        let mut output = input.conv2d(&self.weight, self.stride, self.padding)?;
+        if let Some(bias) = &self.bias {
+            let spatial = output.shape()[2] * output.shape()[3];
+            for (i, plane) in output.data_mut().chunks_exact_mut(spatial).enumerate() {
+                let b = bias.data()[i % bias.data().len()];
+                plane.iter_mut().for_each(|x| *x += b);
+            }
+        }
+        Ok(output)
+    }
+}
+
+macro_rules! activation {
+    ($(#[$doc:meta])* $name:ident, $kernel:expr) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, Copy, Default)]
+        pub struct $name;
+
+        impl Layer for $name {
+            fn forward(&self, input: &Tensor) -> CoreResult<Tensor> {
+                let mut output = input.clone();
+                let len = input.shape().last().copied().unwrap_or(1);
+                #[allow(clippy::redundant_closure_call)]
+                ($kernel)(output.data_mut(), len);
+                Ok(output)
+            }
+        }
+    };
+}
+
+activation!(ReLU, |data, _| kernels::relu(data));
+activation!(Sigmoid, |data, _| kernels::sigmoid(data));
+activation!(Tanh, |data, _| kernels::tanh(data));
+activation!(Swish, |data, _| kernels::swish(data));
+activation!(
+    /// Softmax over the last dimension.
+    Softmax,
+    kernels::softmax
+);
+
+/// Layers applied one after another.
+#[derive(Default)]
+pub struct Sequential {
+    layers: Vec<Box<dyn Layer>>,
+}
+
+impl Sequential {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn layer(mut self, layer: impl Layer + 'static) -> Self {
+        self.layers.push(Box::new(layer));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+}
+
+impl Layer for Sequential {
+    fn forward(&self, input: &Tensor) -> CoreResult<Tensor> {
+        let mut output = input.clone();
+        for layer in &self.layers {
+            output = layer.forward(&output)?;
+        }
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_layers() -> CoreResult<()> {
+        // [[1, 2], [-3, 4]] and bias [0.5, -10], as a saving `cetana` model would name them
+        let header = r#"{"fc.weight":{"dtype":"F32","shape":[2,2],"data_offsets":[0,16]},"fc.bias":{"dtype":"F32","shape":[2],"data_offsets":[16,24]}}"#;
+        let mut bytes = (header.len() as u64).to_le_bytes().to_vec();
+        bytes.extend_from_slice(header.as_bytes());
+        for value in [1.0f32, 2.0, -3.0, 4.0, 0.5, -10.0] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        let mut state = crate::safetensors::decode(&bytes)?;
+        let model = Sequential::new()
+            .layer(Linear::from_state(&mut state, "fc")?)
+            .layer(ReLU);
+        assert!(state.is_empty());
+
+        let output = model.forward(&Tensor::from_vec(vec![1.0, 1.0], &[1, 2])?)?;
+        assert_eq!(output.data(), &[3.5, 0.0]);
+
+        let logits = Tensor::from_vec(vec![0.0, 0.0, 1000.0, 1000.0], &[2, 2])?;
+        assert_eq!(Softmax.forward(&logits)?.data(), &[0.5; 4]);
+
+        // A 2x2 sum kernel over a 3x3 image, padded by one, plus a bias
+        let conv = Conv2d::new(
+            Tensor::from_vec(vec![1.0; 4], &[1, 1, 2, 2])?,
+            Some(Tensor::from_vec(vec![1.0], &[1])?),
+            (2, 2),
+            (1, 1),
+        )?;
+        let image = Tensor::from_vec((1..=9).map(|v| v as f32).collect(), &[1, 1, 3, 3])?;
+        let output = conv.forward(&image)?;
+        assert_eq!(output.shape(), &[1, 1, 2, 2]);
+        assert_eq!(output.data(), &[2.0, 6.0, 12.0, 29.0]);
+        Ok(())
+    }
+}
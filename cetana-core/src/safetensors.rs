@@ -0,0 +1,432 @@
+//! Reading `.safetensors` weights from bytes.
+//!
+//! A file is a little-endian u64 header length, a JSON header mapping each tensor name to
+//! its dtype, shape and byte range, and then the tensor data. Float, integer and boolean
+//! tensors are converted to f32. The optional `__metadata__` entry is skipped.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::tensor::Tensor;
+use crate::{CoreError, CoreResult};
+
+/// Named tensors in the order of their data in the file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StateDict {
+    entries: Vec<(String, Tensor)>,
+}
+
+impl StateDict {
+    pub fn get(&self, name: &str) -> Option<&Tensor> {
+        self.entries.iter().find(|(n, _)| n == name).map(|(_, t)| t)
+    }
+
+    /// Removes and returns the tensor called `name`, so layers can take their weights
+    /// without copying them.
+    pub fn take(&mut self, name: &str) -> CoreResult<Tensor> {
+        let index = self
+            .entries
+            .iter()
+            .position(|(n, _)| n == name)
+            .ok_or_else(|| CoreError::Format(format!("No tensor named {}", name)))?;
+        Ok(self.entries.remove(index).1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Tensor)> {
+        self.entries.iter().map(|(n, t)| (n.as_str(), t))
+    }
+}
+
+impl IntoIterator for StateDict {
+    type Item = (String, Tensor);
+    type IntoIter = alloc::vec::IntoIter<(String, Tensor)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+/// One tensor of a `.safetensors` file before conversion: its dtype name as written in
+/// the header, e.g. `"F16"`, and its little-endian data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawTensor<'a> {
+    pub name: String,
+    pub dtype: String,
+    pub shape: Vec<usize>,
+    pub data: &'a [u8],
+}
+
+impl RawTensor<'_> {
+    /// The data converted to f32.
+    pub fn to_tensor(&self) -> CoreResult<Tensor> {
+        let (size, convert) = dtype_decoder(&self.dtype).ok_or_else(|| {
+            CoreError::Format(format!("Unsupported safetensors dtype {}", self.dtype))
+        })?;
+        let values = self.data.chunks_exact(size).map(convert).collect();
+        Tensor::from_vec(values, &self.shape)
+    }
+}
+
+/// Reads every tensor of a `.safetensors` file's bytes.
+pub fn decode(bytes: &[u8]) -> CoreResult<StateDict> {
+    let entries = raw_tensors(bytes)?
+        .into_iter()
+        .map(|raw| Ok((raw.name.clone(), raw.to_tensor()?)))
+        .collect::<CoreResult<_>>()?;
+    Ok(StateDict { entries })
+}
+
+/// The header entries of a `.safetensors` file's bytes, in the order of their data, with
+/// each one's data borrowed from `bytes`. Every dtype must be one [`decode`] can convert.
+pub fn raw_tensors(bytes: &[u8]) -> CoreResult<Vec<RawTensor<'_>>> {
+    let invalid = |what: String| CoreError::Format(what);
+    let header_len = bytes
+        .get(..8)
+        .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| invalid("safetensors file is too short".into()))?;
+    let header = usize::try_from(header_len)
+        .ok()
+        .and_then(|len| bytes.get(8..8usize.checked_add(len)?))
+        .ok_or_else(|| invalid("safetensors header is truncated".into()))?;
+    let data = &bytes[8 + header.len()..];
+    let header = core::str::from_utf8(header)
+        .map_err(|_| invalid("safetensors header is not UTF-8".into()))?;
+
+    let Json::Object(entries) = Json::parse(header)? else {
+        return Err(invalid("safetensors header is not an object".into()));
+    };
+
+    let mut tensors = Vec::with_capacity(entries.len());
+    for (name, info) in entries {
+        if name == "__metadata__" {
+            continue;
+        }
+        let invalid = |what: &str| CoreError::Format(format!("{} of {}", what, name));
+
+        let dtype = info
+            .get("dtype")
+            .and_then(Json::as_str)
+            .ok_or_else(|| invalid("dtype"))?;
+        let (size, _) = dtype_decoder(dtype).ok_or_else(|| {
+            CoreError::Format(format!(
+                "Unsupported safetensors dtype {} of {}",
+                dtype, name
+            ))
+        })?;
+        let shape = info
+            .get("shape")
+            .and_then(Json::as_usizes)
+            .ok_or_else(|| invalid("shape"))?;
+        let offsets = info
+            .get("data_offsets")
+            .and_then(Json::as_usizes)
+            .ok_or_else(|| invalid("data offsets"))?;
+
+        let (begin, end) = match offsets[..] {
+            [begin, end] if begin <= end && end <= data.len() => (begin, end),
+            _ => return Err(invalid("data offsets")),
+        };
+        let len = shape
+            .iter()
+            .try_fold(size, |total, &dim| total.checked_mul(dim));
+        if len != Some(end - begin) {
+            return Err(invalid("data length"));
+        }
+
+        let raw = RawTensor {
+            dtype: dtype.to_string(),
+            shape,
+            data: &data[begin..end],
+            name,
+        };
+        tensors.push((begin, raw));
+    }
+
+    tensors.sort_by_key(|(begin, _)| *begin);
+    Ok(tensors.into_iter().map(|(_, raw)| raw).collect())
+}
+
+/// An IEEE half-precision value's bits as f32.
+pub fn f16_to_f32(bits: u16) -> f32 {
+    let sign = ((bits & 0x8000) as u32) << 16;
+    let exponent = ((bits >> 10) & 0x1f) as u32;
+    let mantissa = (bits & 0x3ff) as u32;
+    match exponent {
+        // Subnormal halves are normal f32s; 2^-24 is the half subnormal step
+        0 => {
+            let magnitude = mantissa as f32 * (1.0 / 16_777_216.0);
+            f32::from_bits(sign | magnitude.to_bits())
+        }
+        0x1f => f32::from_bits(sign | 0x7f80_0000 | (mantissa << 13)),
+        e => f32::from_bits(sign | ((e + 112) << 23) | (mantissa << 13)),
+    }
+}
+
+/// A bfloat16 value's bits as f32.
+pub fn bf16_to_f32(bits: u16) -> f32 {
+    f32::from_bits((bits as u32) << 16)
+}
+
+type Decoder = fn(&[u8]) -> f32;
+
+// The element size of a safetensors dtype and how to convert one element to f32
+fn dtype_decoder(dtype: &str) -> Option<(usize, Decoder)> {
+    Some(match dtype {
+        "F64" => (8, |b| f64::from_le_bytes(b.try_into().unwrap()) as f32),
+        "F32" => (4, |b| f32::from_le_bytes(b.try_into().unwrap())),
+        "F16" => (2, |b| f16_to_f32(u16::from_le_bytes([b[0], b[1]]))),
+        "BF16" => (2, |b| bf16_to_f32(u16::from_le_bytes([b[0], b[1]]))),
+        "I64" => (8, |b| i64::from_le_bytes(b.try_into().unwrap()) as f32),
+        "I32" => (4, |b| i32::from_le_bytes(b.try_into().unwrap()) as f32),
+        "I16" => (2, |b| i16::from_le_bytes([b[0], b[1]]) as f32),
+        "I8" => (1, |b| b[0] as i8 as f32),
+        "U64" => (8, |b| u64::from_le_bytes(b.try_into().unwrap()) as f32),
+        "U32" => (4, |b| u32::from_le_bytes(b.try_into().unwrap()) as f32),
+        "U16" => (2, |b| u16::from_le_bytes([b[0], b[1]]) as f32),
+        "U8" | "BOOL" => (1, |b| b[0] as f32),
+        _ => return None,
+    })
+}
+
+/// Just enough JSON for safetensors headers. Numbers keep their text so byte offsets
+/// beyond 2^53 aren't rounded.
+#[derive(Debug, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn parse(text: &str) -> Result<Json, CoreError> {
+        let mut parser = JsonParser {
+            bytes: text.as_bytes(),
+            pos: 0,
+        };
+        let value = parser.value(0)?;
+        parser.skip_whitespace();
+        if parser.pos != parser.bytes.len() {
+            return Err(parser.error());
+        }
+        Ok(value)
+    }
+
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_usizes(&self) -> Option<Vec<usize>> {
+        match self {
+            Json::Array(items) => items
+                .iter()
+                .map(|item| match item {
+                    Json::Number(n) => n.parse().ok(),
+                    _ => None,
+                })
+                .collect(),
+            _ => None,
+        }
+    }
+}
+
+struct JsonParser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+// Headers are flat apart from the per-tensor objects; this only stops hostile nesting
+const MAX_DEPTH: usize = 32;
+
+impl JsonParser<'_> {
+    fn error(&self) -> CoreError {
+        CoreError::Format(format!("safetensors header: bad JSON at byte {}", self.pos))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .bytes
+            .get(self.pos)
+            .is_some_and(u8::is_ascii_whitespace)
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        if self.bytes.get(self.pos) == Some(&byte) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> Result<Json, CoreError> {
+        if !self.bytes[self.pos..].starts_with(word.as_bytes()) {
+            return Err(self.error());
+        }
+        self.pos += word.len();
+        Ok(value)
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Json, CoreError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error());
+        }
+        self.skip_whitespace();
+        match self.bytes.get(self.pos) {
+            Some(b'{') => {
+                self.pos += 1;
+                let mut entries = Vec::new();
+                if !self.eat(b'}') {
+                    loop {
+                        self.skip_whitespace();
+                        let key = self.string()?;
+                        if !self.eat(b':') {
+                            return Err(self.error());
+                        }
+                        entries.push((key, self.value(depth + 1)?));
+                        if self.eat(b'}') {
+                            break;
+                        }
+                        if !self.eat(b',') {
+                            return Err(self.error());
+                        }
+                    }
+                }
+                Ok(Json::Object(entries))
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                if !self.eat(b']') {
+                    loop {
+                        items.push(self.value(depth + 1)?);
+                        if self.eat(b']') {
+                            break;
+                        }
+                        if !self.eat(b',') {
+                            return Err(self.error());
+                        }
+                    }
+                }
+                Ok(Json::Array(items))
+            }
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b'-' | b'0'..=b'9') => {
+                let start = self.pos;
+                while self
+                    .bytes
+                    .get(self.pos)
+                    .is_some_and(|b| matches!(b, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9'))
+                {
+                    self.pos += 1;
+                }
+                let text = core::str::from_utf8(&self.bytes[start..self.pos]).unwrap();
+                Ok(Json::Number(text.to_string()))
+            }
+            _ => Err(self.error()),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, CoreError> {
+        if self.bytes.get(self.pos) != Some(&b'"') {
+            return Err(self.error());
+        }
+        self.pos += 1;
+
+        let mut out = String::new();
+        loop {
+            let start = self.pos;
+            while self
+                .bytes
+                .get(self.pos)
+                .is_some_and(|&b| b != b'"' && b != b'\\')
+            {
+                self.pos += 1;
+            }
+            // The input is a &str and the run stops at ASCII, so it is valid UTF-8
+            out.push_str(core::str::from_utf8(&self.bytes[start..self.pos]).unwrap());
+
+            match self.bytes.get(self.pos) {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(out);
+                }
+                Some(b'\\') => {
+                    let escape = *self.bytes.get(self.pos + 1).ok_or_else(|| self.error())?;
+                    self.pos += 2;
+                    match escape {
+                        b'"' => out.push('"'),
+                        b'\\' => out.push('\\'),
+                        b'/' => out.push('/'),
+                        b'b' => out.push('\u{8}'),
+                        b'f' => out.push('\u{c}'),
+                        b'n' => out.push('\n'),
+                        b'r' => out.push('\r'),
+                        b't' => out.push('\t'),
+                        b'u' => out.push(self.unicode_escape()?),
+                        _ => return Err(self.error()),
+                    }
+                }
+                _ => return Err(self.error()),
+            }
+        }
+    }
+
+    // The four hex digits after `\u`, and a low surrogate's after them if needed
+    fn unicode_escape(&mut self) -> Result<char, CoreError> {
+        let high = self.hex4()?;
+        let code = if (0xd800..0xdc00).contains(&high) {
+            if !self.bytes[self.pos..].starts_with(b"\\u") {
+                return Err(self.error());
+            }
+            self.pos += 2;
+            let low = self.hex4()?;
+            if !(0xdc00..0xe000).contains(&low) {
+                return Err(self.error());
+            }
+            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error())
+    }
+
+    fn hex4(&mut self) -> Result<u32, CoreError> {
+        let digits = self
+            .bytes
+            .get(self.pos..self.pos + 4)
+            .and_then(|d| core::str::from_utf8(d).ok())
+            .and_then(|d| u32::from_str_radix(d, 16).ok())
+            .ok_or_else(|| self.error())?;
+        self.pos += 4;
+        Ok(digits)
+    }
+}
@@ -0,0 +1,114 @@
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::conv::{im2col, Conv2dShape};
+use crate::kernels;
+use crate::{CoreError, CoreResult};
+
+/// A row-major f32 tensor in host memory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tensor {
+    data: Vec<f32>,
+    shape: Vec<usize>,
+}
+
+impl Tensor {
+    pub fn from_vec(data: Vec<f32>, shape: &[usize]) -> CoreResult<Self> {
+        let expected: usize = shape.iter().product();
+        if data.len() != expected {
+            return Err(CoreError::Shape(format!(
+                "{} values don't fill shape {:?}",
+                data.len(),
+                shape
+            )));
+        }
+        Ok(Self {
+            data,
+            shape: shape.to_vec(),
+        })
+    }
+
+    pub fn zeros(shape: &[usize]) -> Self {
+        Self {
+            data: vec![0.0; shape.iter().product()],
+            shape: shape.to_vec(),
+        }
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    pub fn data(&self) -> &[f32] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [f32] {
+        &mut self.data
+    }
+
+    pub fn into_data(self) -> Vec<f32> {
+        self.data
+    }
+
+    pub fn reshape(mut self, shape: &[usize]) -> CoreResult<Self> {
+        if shape.iter().product::<usize>() != self.data.len() {
+            return Err(CoreError::Shape(format!(
+                "Can't reshape {:?} to {:?}",
+                self.shape, shape
+            )));
+        }
+        self.shape = shape.to_vec();
+        Ok(self)
+    }
+
+    /// The product of two 2D tensors.
+    pub fn matmul(&self, other: &Tensor) -> CoreResult<Tensor> {
+        let (m, n, k) = match (self.shape(), other.shape()) {
+            (&[m, n], &[n2, k]) if n == n2 => (m, n, k),
+            (a, b) => {
+                return Err(CoreError::Shape(format!(
+                    "Can't multiply {:?} by {:?}",
+                    a, b
+                )))
+            }
+        };
+        Tensor::from_vec(kernels::matmul(&self.data, &other.data, m, n, k), &[m, k])
+    }
+
+    /// 2D convolution (cross-correlation) of this `[batch, channels, height, width]` tensor
+    /// with a `[out_channels, channels, kh, kw]` weight.
+    pub fn conv2d(
+        &self,
+        weight: &Tensor,
+        stride: (usize, usize),
+        padding: (usize, usize),
+    ) -> CoreResult<Tensor> {
+        let (input, weight_shape) = match (self.shape(), weight.shape()) {
+            (&[n, c, h, w], &[oc, ic, kh, kw]) => ([n, c, h, w], [oc, ic, kh, kw]),
+            (a, b) => {
+                return Err(CoreError::Shape(format!(
+                    "conv2d expects 4D input and weight, got {:?} and {:?}",
+                    a, b
+                )))
+            }
+        };
+        let shape = Conv2dShape::new(input, weight_shape, stride, padding)?;
+        let [_, _, out_h, out_w] = shape.output();
+        let patch_size = input[1] * weight_shape[2] * weight_shape[3];
+
+        let mut output = Vec::with_capacity(shape.output_len());
+        for b in 0..input[0] {
+            let columns = im2col(&self.data, &shape, b);
+            output.extend(kernels::matmul(
+                &weight.data,
+                &columns,
+                weight_shape[0],
+                patch_size,
+                out_h * out_w,
+            ));
+        }
+        Tensor::from_vec(output, &shape.output())
+    }
+}
@@ -1,3 +1,21 @@
+extern "C" __global__ void vector_add_kernel(float *result, const float *a, const float *b, int n)
+{
+    int idx = blockIdx.x * blockDim.x + threadIdx.x;
+    if (idx < n)
+    {
+        result[idx] = a[idx] + b[idx];
+    }
+}
+
+extern "C" __global__ void vector_multiply_kernel(float *result, const float *a, const float *b, int n)
+{
+    int idx = blockIdx.x * blockDim.x + threadIdx.x;
+    if (idx < n)
+    {
+        result[idx] = a[idx] * b[idx];
+    }
+}
+
 extern "C" __global__ void vector_sub_kernel(float *result, const float *a, const float *b, int n)
 {
     int idx = blockIdx.x * blockDim.x + threadIdx.x;
@@ -69,6 +87,32 @@ extern "C" __global__ void matrix_multiply_kernel(float *result, const float *a,
     }
 }
 
+// Int8 matmul with i32 accumulation. `a` is [m, words] and `bt` (B transposed) is
+// [k, words], each row packed four int8 values per 32-bit word and zero padded.
+extern "C" __global__ void matrix_multiply_i8_kernel(int *result, const int *a, const int *bt,
+                                                     int m, int words, int k)
+{
+    int row = blockIdx.y * blockDim.y + threadIdx.y;
+    int col = blockIdx.x * blockDim.x + threadIdx.x;
+
+    if (row < m && col < k)
+    {
+        int acc = 0;
+        for (int i = 0; i < words; i++)
+        {
+            int x = a[row * words + i];
+            int y = bt[col * words + i];
+#if __CUDA_ARCH__ >= 610
+            acc = __dp4a(x, y, acc);
+#else
+            for (int shift = 0; shift < 32; shift += 8)
+                acc += (int)(signed char)(x >> shift) * (int)(signed char)(y >> shift);
+#endif
+        }
+        result[row * k + col] = acc;
+    }
+}
+
 extern "C" __global__ void vector_reduce_sum_kernel(float *result, const float *a, int n)
 {
     extern __shared__ float sdata[];
@@ -90,4 +134,161 @@ extern "C" __global__ void vector_reduce_sum_kernel(float *result, const float *
 
     if (tid == 0)
         result[blockIdx.x] = sdata[0];
-}
\ No newline at end of file
+}
+enum ReduceOpcode
+{
+    REDUCE_SUM,
+    REDUCE_MEAN,
+    REDUCE_MAX,
+    REDUCE_MIN,
+};
+
+// Reduces the middle dimension of `a` viewed as [outer, axis, inner], one thread per output
+extern "C" __global__ void reduce_axis_kernel(float *result, const float *a, int outer, int axis,
+                                              int inner, int op)
+{
+    int idx = blockIdx.x * blockDim.x + threadIdx.x;
+    if (idx >= outer * inner)
+        return;
+
+    const float *values = a + (idx / inner) * axis * inner + idx % inner;
+    float acc = op == REDUCE_MAX ? -INFINITY : (op == REDUCE_MIN ? INFINITY : 0.0f);
+    for (int i = 0; i < axis; i++)
+    {
+        float value = values[i * inner];
+        switch (op)
+        {
+        case REDUCE_MAX:
+            acc = fmaxf(acc, value);
+            break;
+        case REDUCE_MIN:
+            acc = fminf(acc, value);
+            break;
+        default:
+            acc += value;
+            break;
+        }
+    }
+    result[idx] = op == REDUCE_MEAN ? acc / axis : acc;
+}
+
+struct Conv2dGeometry
+{
+    int batch, channels, height, width;
+    int out_channels, kernel_h, kernel_w;
+    int stride_h, stride_w, pad_h, pad_w;
+    int out_h, out_w;
+};
+
+// Direct NCHW convolution, one thread per output element
+extern "C" __global__ void conv2d_kernel(float *result, const float *input, const float *weight,
+                                         Conv2dGeometry g)
+{
+    int idx = blockIdx.x * blockDim.x + threadIdx.x;
+    if (idx >= g.batch * g.out_channels * g.out_h * g.out_w)
+        return;
+
+    int ox = idx % g.out_w;
+    int oy = (idx / g.out_w) % g.out_h;
+    int oc = (idx / (g.out_w * g.out_h)) % g.out_channels;
+    int b = idx / (g.out_w * g.out_h * g.out_channels);
+
+    float acc = 0.0f;
+    for (int c = 0; c < g.channels; c++)
+    {
+        const float *image = input + (b * g.channels + c) * g.height * g.width;
+        const float *kernel = weight + (oc * g.channels + c) * g.kernel_h * g.kernel_w;
+        for (int ky = 0; ky < g.kernel_h; ky++)
+        {
+            int y = oy * g.stride_h + ky - g.pad_h;
+            if (y < 0 || y >= g.height)
+                continue;
+            for (int kx = 0; kx < g.kernel_w; kx++)
+            {
+                int x = ox * g.stride_w + kx - g.pad_w;
+                if (x >= 0 && x < g.width)
+                    acc += image[y * g.width + x] * kernel[ky * g.kernel_w + kx];
+            }
+        }
+    }
+    result[idx] = acc;
+}
+
+#define FUSED_MAX_INPUTS 8
+#define FUSED_MAX_OPS 32
+
+enum FusedOpcode
+{
+    FUSED_ADD,
+    FUSED_SUB,
+    FUSED_MUL,
+    FUSED_DIV,
+    FUSED_ADD_SCALAR,
+    FUSED_MUL_SCALAR,
+    FUSED_POW,
+    FUSED_NEG,
+    FUSED_EXP,
+    FUSED_LOG,
+    FUSED_SQRT,
+};
+
+// Passed by value, so a launch needs no extra device allocations
+struct FusedProgram
+{
+    const float *inputs[FUSED_MAX_INPUTS];
+    int opcodes[FUSED_MAX_OPS];
+    int operands[FUSED_MAX_OPS];
+    float scalars[FUSED_MAX_OPS];
+    int num_ops;
+};
+
+extern "C" __global__ void fused_elementwise_kernel(float *result, FusedProgram program, int n)
+{
+    int idx = blockIdx.x * blockDim.x + threadIdx.x;
+    if (idx >= n)
+        return;
+
+    float value = program.inputs[0][idx];
+    for (int i = 0; i < program.num_ops; i++)
+    {
+        const float *operand = program.inputs[program.operands[i]];
+        const float scalar = program.scalars[i];
+        switch (program.opcodes[i])
+        {
+        case FUSED_ADD:
+            value += operand[idx];
+            break;
+        case FUSED_SUB:
+            value -= operand[idx];
+            break;
+        case FUSED_MUL:
+            value *= operand[idx];
+            break;
+        case FUSED_DIV:
+            value /= operand[idx];
+            break;
+        case FUSED_ADD_SCALAR:
+            value += scalar;
+            break;
+        case FUSED_MUL_SCALAR:
+            value *= scalar;
+            break;
+        case FUSED_POW:
+            value = powf(value, scalar);
+            break;
+        case FUSED_NEG:
+            value = -value;
+            break;
+        case FUSED_EXP:
+            value = expf(value);
+            break;
+        case FUSED_LOG:
+            value = logf(value);
+            break;
+        case FUSED_SQRT:
+            value = sqrtf(value);
+            break;
+        }
+    }
+    result[idx] = value;
+}
@@ -0,0 +1,11 @@
+use cetana::bench::{self, BenchConfig};
+use cetana::MlResult;
+
+fn main() -> MlResult<()> {
+    println!("Timing backend ops on every available device...\n");
+
+    let report = bench::run_available(&BenchConfig::default())?;
+    println!("{}", report);
+
+    Ok(())
+}
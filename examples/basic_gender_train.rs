@@ -22,6 +22,9 @@ use rand::SeedableRng;
 
 type Float = f32;
 
+// Heights, weights and labels
+type Columns = (Vec<Float>, Vec<Float>, Vec<Float>);
+
 struct TrainingConfig {
     learning_rate: Float,
     epochs: usize,
@@ -156,7 +159,7 @@ fn normalize_data(data: &[Float]) -> Vec<Float> {
     data.iter().map(|&x| (x - min) / (max - min)).collect()
 }
 
-fn load_data() -> Result<(Vec<Float>, Vec<Float>, Vec<Float>), Box<dyn Error>> {
+fn load_data() -> Result<Columns, Box<dyn Error>> {
     let file = File::open("examples/datas/gender.csv")?;
     let mut rdr = ReaderBuilder::new().has_headers(true).from_reader(file);
 
@@ -5,16 +5,42 @@ kernel void vector_add(
     device const float* a [[buffer(0)]],
     device const float* b [[buffer(1)]],
     device float* result [[buffer(2)]],
+    constant uint& length [[buffer(3)]],
     uint index [[thread_position_in_grid]])
 {
+    if (index >= length) return;
     result[index] = a[index] + b[index];
 }
 
+kernel void vector_sub(
+    device const float* a [[buffer(0)]],
+    device const float* b [[buffer(1)]],
+    device float* result [[buffer(2)]],
+    constant uint& length [[buffer(3)]],
+    uint index [[thread_position_in_grid]])
+{
+    if (index >= length) return;
+    result[index] = a[index] - b[index];
+}
+
 kernel void vector_mul(
     device const float* a [[buffer(0)]],
     device const float* b [[buffer(1)]],
     device float* result [[buffer(2)]],
+    constant uint& length [[buffer(3)]],
     uint index [[thread_position_in_grid]])
 {
+    if (index >= length) return;
     result[index] = a[index] * b[index];
-} 
\ No newline at end of file
+}
+
+kernel void vector_div(
+    device const float* a [[buffer(0)]],
+    device const float* b [[buffer(1)]],
+    device float* result [[buffer(2)]],
+    constant uint& length [[buffer(3)]],
+    uint index [[thread_position_in_grid]])
+{
+    if (index >= length) return;
+    result[index] = a[index] / b[index];
+}
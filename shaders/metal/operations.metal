@@ -1,28 +1,53 @@
 #include <metal_stdlib>
 using namespace metal;
 
-kernel void vector_add(
-    device const float* a [[buffer(0)]],
-    device const float* b [[buffer(1)]],
-    device float* result [[buffer(2)]],
+kernel void vector_exp(
+    device const float* input [[buffer(0)]],
+    device float* output [[buffer(1)]],
+    constant uint& length [[buffer(2)]],
     uint index [[thread_position_in_grid]])
 {
-    result[index] = a[index] + b[index];
+    if (index >= length) return;
+    output[index] = exp(input[index]);
 }
 
-kernel void vector_multiply(
-    device const float* a [[buffer(0)]],
-    device const float* b [[buffer(1)]],
-    device float* result [[buffer(2)]],
+kernel void vector_log(
+    device const float* input [[buffer(0)]],
+    device float* output [[buffer(1)]],
+    constant uint& length [[buffer(2)]],
     uint index [[thread_position_in_grid]])
 {
-    result[index] = a[index] * b[index];
+    if (index >= length) return;
+    output[index] = log(input[index]);
+}
+
+kernel void vector_sqrt(
+    device const float* input [[buffer(0)]],
+    device float* output [[buffer(1)]],
+    constant uint& length [[buffer(2)]],
+    uint index [[thread_position_in_grid]])
+{
+    if (index >= length) return;
+    output[index] = sqrt(input[index]);
+}
+
+kernel void vector_pow(
+    device const float* input [[buffer(0)]],
+    device float* output [[buffer(1)]],
+    constant uint& length [[buffer(2)]],
+    constant float& power [[buffer(3)]],
+    uint index [[thread_position_in_grid]])
+{
+    if (index >= length) return;
+    output[index] = pow(input[index], power);
 }
 
 kernel void vector_relu(
     device const float* input [[buffer(0)]],
     device float* output [[buffer(1)]],
+    constant uint& length [[buffer(2)]],
     uint index [[thread_position_in_grid]])
 {
+    if (index >= length) return;
     output[index] = max(0.0f, input[index]);
-} 
\ No newline at end of file
+}
@@ -0,0 +1,103 @@
+// Element-wise binary operations
+// op: 0 = add, 1 = multiply, 2 = divide, 3 = subtract
+__kernel void binary_op(__global const float* a,
+                        __global const float* b,
+                        __global float* result,
+                        const uint op,
+                        const uint n) {
+    uint i = get_global_id(0);
+    if (i >= n) return;
+
+    switch (op) {
+        case 0: result[i] = a[i] + b[i]; break;
+        case 1: result[i] = a[i] * b[i]; break;
+        case 2: result[i] = a[i] / b[i]; break;
+        case 3: result[i] = a[i] - b[i]; break;
+    }
+}
+
+// Element-wise unary operations
+// op: 0 = exp, 1 = log, 2 = pow(scalar), 3 = sqrt
+__kernel void unary_op(__global const float* input,
+                       __global float* result,
+                       const uint op,
+                       const float scalar,
+                       const uint n) {
+    uint i = get_global_id(0);
+    if (i >= n) return;
+
+    switch (op) {
+        case 0: result[i] = exp(input[i]); break;
+        case 1: result[i] = log(input[i]); break;
+        case 2: result[i] = pow(input[i], scalar); break;
+        case 3: result[i] = sqrt(input[i]); break;
+    }
+}
+
+// Overridden through build options on devices with small work-groups
+#ifndef TILE_SIZE
+#define TILE_SIZE 16
+#endif
+
+// result[m, k] = a[m, n] * b[n, k]
+__kernel void matmul(__global const float* a,
+                     __global const float* b,
+                     __global float* result,
+                     const uint m,
+                     const uint n,
+                     const uint k) {
+    __local float tile_a[TILE_SIZE][TILE_SIZE];
+    __local float tile_b[TILE_SIZE][TILE_SIZE];
+
+    uint col = get_global_id(0);
+    uint row = get_global_id(1);
+    uint local_col = get_local_id(0);
+    uint local_row = get_local_id(1);
+
+    float sum = 0.0f;
+    uint num_tiles = (n + TILE_SIZE - 1) / TILE_SIZE;
+
+    for (uint t = 0; t < num_tiles; t++) {
+        uint a_col = t * TILE_SIZE + local_col;
+        uint b_row = t * TILE_SIZE + local_row;
+
+        tile_a[local_row][local_col] = (row < m && a_col < n) ? a[row * n + a_col] : 0.0f;
+        tile_b[local_row][local_col] = (b_row < n && col < k) ? b[b_row * k + col] : 0.0f;
+
+        barrier(CLK_LOCAL_MEM_FENCE);
+
+        for (uint i = 0; i < TILE_SIZE; i++) {
+            sum += tile_a[local_row][i] * tile_b[i][local_col];
+        }
+
+        barrier(CLK_LOCAL_MEM_FENCE);
+    }
+
+    if (row < m && col < k) {
+        result[row * k + col] = sum;
+    }
+}
+
+// Each work-group writes one partial sum; partials are combined on the host
+__kernel void reduce_sum(__global const float* input,
+                         __global float* partials,
+                         __local float* scratch,
+                         const uint n) {
+    uint gid = get_global_id(0);
+    uint lid = get_local_id(0);
+    uint group_size = get_local_size(0);
+
+    scratch[lid] = gid < n ? input[gid] : 0.0f;
+    barrier(CLK_LOCAL_MEM_FENCE);
+
+    for (uint stride = group_size / 2; stride > 0; stride /= 2) {
+        if (lid < stride) {
+            scratch[lid] += scratch[lid + stride];
+        }
+        barrier(CLK_LOCAL_MEM_FENCE);
+    }
+
+    if (lid == 0) {
+        partials[get_group_id(0)] = scratch[0];
+    }
+}
@@ -0,0 +1,47 @@
+struct Params {
+    op_type: u32,
+    input_length: u32,
+    scalar: f32,
+    _padding: u32,
+}
+
+@group(0) @binding(0) var<storage, read> input_a: array<f32>;
+@group(0) @binding(1) var<storage, read> input_b: array<f32>;
+@group(0) @binding(2) var<storage, read_write> output_data: array<f32>;
+@group(0) @binding(3) var<uniform> params: Params;
+
+@compute @workgroup_size(256)
+fn main(
+    @builtin(global_invocation_id) gid: vec3<u32>,
+    @builtin(num_workgroups) num_groups: vec3<u32>,
+) {
+    let index = gid.x + gid.y * num_groups.x * 256u;
+    if (index >= params.input_length) {
+        return;
+    }
+
+    let a = input_a[index];
+    let b = input_b[index];
+
+    switch params.op_type {
+        // Add
+        case 0u: {
+            output_data[index] = a + b;
+        }
+        // Multiply
+        case 1u: {
+            output_data[index] = a * b;
+        }
+        // Divide
+        case 2u: {
+            output_data[index] = a / b;
+        }
+        // Subtract
+        case 3u: {
+            output_data[index] = a - b;
+        }
+        default: {
+            output_data[index] = 0.0;
+        }
+    }
+}
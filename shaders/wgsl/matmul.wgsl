@@ -0,0 +1,58 @@
+// result[m, k] = a[m, n] * b[n, k]
+struct Dims {
+    m: u32,
+    n: u32,
+    k: u32,
+    _padding: u32,
+}
+
+@group(0) @binding(0) var<storage, read> a: array<f32>;
+@group(0) @binding(1) var<storage, read> b: array<f32>;
+@group(0) @binding(2) var<storage, read_write> result: array<f32>;
+@group(0) @binding(3) var<uniform> dims: Dims;
+
+var<workgroup> tile_a: array<array<f32, 16>, 16>;
+var<workgroup> tile_b: array<array<f32, 16>, 16>;
+
+@compute @workgroup_size(16, 16)
+fn main(
+    @builtin(global_invocation_id) gid: vec3<u32>,
+    @builtin(local_invocation_id) lid: vec3<u32>,
+) {
+    let row = gid.y;
+    let col = gid.x;
+    let local_row = lid.y;
+    let local_col = lid.x;
+
+    var sum = 0.0;
+    let num_tiles = (dims.n + 15u) / 16u;
+
+    for (var t = 0u; t < num_tiles; t = t + 1u) {
+        // Load tiles into workgroup memory
+        let a_col = t * 16u + local_col;
+        if (row < dims.m && a_col < dims.n) {
+            tile_a[local_row][local_col] = a[row * dims.n + a_col];
+        } else {
+            tile_a[local_row][local_col] = 0.0;
+        }
+
+        let b_row = t * 16u + local_row;
+        if (b_row < dims.n && col < dims.k) {
+            tile_b[local_row][local_col] = b[b_row * dims.k + col];
+        } else {
+            tile_b[local_row][local_col] = 0.0;
+        }
+
+        workgroupBarrier();
+
+        for (var i = 0u; i < 16u; i = i + 1u) {
+            sum = sum + tile_a[local_row][i] * tile_b[i][local_col];
+        }
+
+        workgroupBarrier();
+    }
+
+    if (row < dims.m && col < dims.k) {
+        result[row * dims.k + col] = sum;
+    }
+}
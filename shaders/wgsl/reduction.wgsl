@@ -0,0 +1,42 @@
+struct Params {
+    op_type: u32,
+    input_length: u32,
+    scalar: f32,
+    _padding: u32,
+}
+
+@group(0) @binding(0) var<storage, read> input_data: array<f32>;
+@group(0) @binding(1) var<storage, read_write> output_data: array<f32>;
+@group(0) @binding(2) var<uniform> params: Params;
+
+var<workgroup> shared_data: array<f32, 256>;
+
+// Each workgroup writes one partial sum; partials are combined on the host
+@compute @workgroup_size(256)
+fn main(
+    @builtin(global_invocation_id) gid: vec3<u32>,
+    @builtin(local_invocation_id) lid: vec3<u32>,
+    @builtin(workgroup_id) wid: vec3<u32>,
+    @builtin(num_workgroups) num_groups: vec3<u32>,
+) {
+    let index = gid.x + gid.y * num_groups.x * 256u;
+
+    var value = 0.0;
+    if (index < params.input_length) {
+        value = input_data[index];
+    }
+    shared_data[lid.x] = value;
+
+    workgroupBarrier();
+
+    for (var stride = 128u; stride > 0u; stride = stride / 2u) {
+        if (lid.x < stride) {
+            shared_data[lid.x] = shared_data[lid.x] + shared_data[lid.x + stride];
+        }
+        workgroupBarrier();
+    }
+
+    if (lid.x == 0u) {
+        output_data[wid.x + wid.y * num_groups.x] = shared_data[0];
+    }
+}
@@ -0,0 +1,517 @@
+//! Automatic mixed precision.
+//!
+//! Inside an [`autocast`] scope, matrix multiplies run in half precision: their operands and
+//! result are rounded to f16 or bf16 while products are still accumulated in f32, the way
+//! tensor cores do it. Everything else, including the weights layers keep and update, stays
+//! in f32, so the weights act as f32 master copies of the half precision values the multiplies
+//! see.
+//!
+//! Half precision gradients underflow easily, so training under autocast should go through a
+//! [`GradScaler`], which scales the loss gradient up before backpropagation and undoes the
+//! scaling in the weight update.
+//!
+//! ```ignore
+//! let mut scaler = GradScaler::default();
+//! let output = autocast(Precision::F16, || model.forward(&x))?;
+//! let grad = scaler.scale(&loss_grad(&output)?)?;
+//! autocast(Precision::F16, || scaler.backward(&mut model, &x, &grad, 0.01))?;
+//! scaler.update();
+//! ```
+//!
+//! For inference, [`ToPrecision::half`] and [`ToPrecision::to_precision`] turn a trained
+//! model into a [`HalfModel`], whose weights are rounded to half precision and whose forward
+//! pass runs under autocast.
+
+use crate::backend::{BackendCapabilities, DType, DeviceType};
+use crate::log::log_warn;
+use crate::nn::Layer;
+use crate::serialize::{StateDict, TrainingState};
+use crate::tensor::Tensor;
+use crate::MlResult;
+use std::cell::Cell;
+use std::fmt::{Display, Formatter};
+
+/// The reduced precision formats autocast can compute in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Precision {
+    /// IEEE half precision: 10 mantissa bits, largest finite value 65504.
+    F16,
+    /// bfloat16: the f32 exponent range with 7 mantissa bits.
+    BF16,
+}
+
+impl Precision {
+    /// Rounds `value` to the nearest value representable in this format, ties to even.
+    /// Values beyond the format's range become infinite.
+    pub fn round(self, value: f32) -> f32 {
+        if !value.is_finite() {
+            return value;
+        }
+        match self {
+            Precision::F16 => round_f16(value),
+            Precision::BF16 => round_mantissa(value, 16),
+        }
+    }
+
+    /// The matching storage type, when backends have one.
+    pub fn dtype(self) -> Option<DType> {
+        match self {
+            Precision::F16 => Some(DType::F16),
+            Precision::BF16 => None,
+        }
+    }
+
+    /// Whether a backend can compute in this format. The CPU backend emulates both by
+    /// rounding; device backends need native half arithmetic, which none offer for bf16.
+    pub fn supported_by(self, device: DeviceType, capabilities: &BackendCapabilities) -> bool {
+        device == DeviceType::Cpu
+            || self.dtype().is_some_and(|dtype| {
+                capabilities.supports_f16 || capabilities.supports_dtype(dtype)
+            })
+    }
+}
+
+impl Display for Precision {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Precision::F16 => write!(f, "f16"),
+            Precision::BF16 => write!(f, "bf16"),
+        }
+    }
+}
+
+// Drops the low `bits` mantissa bits, rounding to nearest even. A carry into the exponent
+// is what rounding up should do, including overflowing to infinity.
+fn round_mantissa(value: f32, bits: u32) -> f32 {
+    let raw = value.to_bits();
+    let half = (1 << (bits - 1)) - 1;
+    let odd = (raw >> bits) & 1;
+    f32::from_bits((raw + half + odd) & !((1 << bits) - 1))
+}
+
+fn round_f16(value: f32) -> f32 {
+    const MIN_NORMAL: f32 = 6.103_515_6e-5; // 2^-14
+    const SUBNORMAL_STEP: f32 = 5.960_464_5e-8; // 2^-24
+    const MAX: f32 = 65504.0;
+
+    let rounded = if value.abs() < MIN_NORMAL {
+        (value / SUBNORMAL_STEP).round_ties_even() * SUBNORMAL_STEP
+    } else {
+        round_mantissa(value, 13)
+    };
+
+    if rounded.abs() > MAX {
+        f32::INFINITY.copysign(value)
+    } else {
+        rounded
+    }
+}
+
+// The bit pattern of `value` rounded to f16, the inverse of `f16_to_f32`
+#[cfg_attr(not(feature = "distributed"), allow(dead_code))]
+pub(crate) fn f32_to_f16(value: f32) -> u16 {
+    const MIN_NORMAL: f32 = 6.103_515_6e-5; // 2^-14
+    const SUBNORMAL_STEP: f32 = 5.960_464_5e-8; // 2^-24
+
+    let sign = ((value.to_bits() >> 16) & 0x8000) as u16;
+    if value.is_nan() {
+        return sign | 0x7e00;
+    }
+    let magnitude = Precision::F16.round(value).abs();
+    if magnitude.is_infinite() {
+        sign | 0x7c00
+    } else if magnitude < MIN_NORMAL {
+        sign | (magnitude / SUBNORMAL_STEP) as u16
+    } else {
+        let bits = magnitude.to_bits();
+        let exponent = ((bits >> 23) & 0xff) as u16 - (127 - 15);
+        sign | exponent << 10 | ((bits >> 13) & 0x3ff) as u16
+    }
+}
+
+thread_local! {
+    static AUTOCAST: Cell<Option<Precision>> = const { Cell::new(None) };
+}
+
+/// Runs `f` with autocast enabled on this thread. Scopes nest; the previous setting is
+/// restored when `f` returns or panics.
+pub fn autocast<T>(precision: Precision, f: impl FnOnce() -> T) -> T {
+    with_autocast(Some(precision), f)
+}
+
+/// Runs `f` in full precision, even inside an autocast scope.
+pub fn full_precision<T>(f: impl FnOnce() -> T) -> T {
+    with_autocast(None, f)
+}
+
+/// The precision autocast ops use on this thread, if autocast is on.
+pub fn autocast_precision() -> Option<Precision> {
+    AUTOCAST.with(Cell::get)
+}
+
+fn with_autocast<T>(precision: Option<Precision>, f: impl FnOnce() -> T) -> T {
+    struct Restore(Option<Precision>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            AUTOCAST.with(|autocast| autocast.set(self.0));
+        }
+    }
+
+    let _restore = Restore(AUTOCAST.with(|autocast| autocast.replace(precision)));
+    f()
+}
+
+/// A model running inference in half precision; see [`ToPrecision`].
+///
+/// Its weights hold values representable in the precision and its forward pass rounds the
+/// input and output and runs under [`autocast`]. Ops that lose accuracy in half precision,
+/// such as the accumulations in softmax, stay in f32. On a backend that doesn't support
+/// the precision, the forward pass runs in f32 instead, with a warning the first time.
+pub struct HalfModel<L> {
+    model: L,
+    precision: Precision,
+    warned: Cell<bool>,
+}
+
+impl<L: Layer> HalfModel<L> {
+    /// Rounds every parameter of `model` to `precision`.
+    pub fn new(mut model: L, precision: Precision) -> Self {
+        for param in model.parameters_mut() {
+            *param = param.round_to(precision);
+        }
+        Self {
+            model,
+            precision,
+            warned: Cell::new(false),
+        }
+    }
+
+    pub fn precision(&self) -> Precision {
+        self.precision
+    }
+
+    pub fn model(&self) -> &L {
+        &self.model
+    }
+
+    /// The model, with its parameters still rounded.
+    pub fn into_inner(self) -> L {
+        self.model
+    }
+}
+
+impl<L: Layer> Layer for HalfModel<L> {
+    fn forward(&self, input: &Tensor) -> MlResult<Tensor> {
+        let device = input.device();
+        if !self.precision.supported_by(device, &input.capabilities()) {
+            if !self.warned.replace(true) {
+                log_warn!(
+                    "{} has no {} support; running the model in f32",
+                    device,
+                    self.precision
+                );
+            }
+            return full_precision(|| self.model.forward(input));
+        }
+
+        let output = autocast(self.precision, || {
+            self.model.forward(&input.round_to(self.precision))
+        })?;
+        Ok(output.round_to(self.precision))
+    }
+
+    fn backward(&mut self, _: &Tensor, _: &Tensor, _: f32) -> MlResult<Tensor> {
+        Err("Half precision models run inference only; train under autocast instead".into())
+    }
+
+    fn named_parameters(&self) -> Vec<(String, &Tensor)> {
+        self.model.named_parameters()
+    }
+
+    fn named_buffers(&self) -> Vec<(String, &Tensor)> {
+        self.model.named_buffers()
+    }
+}
+
+/// Conversions of a trained model to half precision inference.
+pub trait ToPrecision: Layer + Sized {
+    /// The model in f16.
+    fn half(self) -> HalfModel<Self> {
+        HalfModel::new(self, Precision::F16)
+    }
+
+    /// The model in `precision`, e.g. [`Precision::BF16`].
+    fn to_precision(self, precision: Precision) -> HalfModel<Self> {
+        HalfModel::new(self, precision)
+    }
+}
+
+impl<L: Layer> ToPrecision for L {}
+
+/// Dynamic loss scaling for mixed precision training.
+///
+/// The loss gradient is multiplied by a large scale so small gradients survive half precision
+/// rounding. Steps whose gradients overflowed are skipped and the scale is cut back; after
+/// `growth_interval` clean steps in a row it grows again.
+#[derive(Debug, Clone)]
+pub struct GradScaler {
+    scale: f32,
+    growth_factor: f32,
+    backoff_factor: f32,
+    growth_interval: usize,
+    clean_steps: usize,
+    found_inf: bool,
+}
+
+impl Default for GradScaler {
+    fn default() -> Self {
+        Self::new(65536.0, 2.0, 0.5, 2000)
+    }
+}
+
+impl GradScaler {
+    /// Creates a scaler starting at `init_scale`, multiplying it by `growth_factor` after
+    /// `growth_interval` clean steps and by `backoff_factor` after an overflow.
+    pub fn new(
+        init_scale: f32,
+        growth_factor: f32,
+        backoff_factor: f32,
+        growth_interval: usize,
+    ) -> Self {
+        Self {
+            scale: init_scale,
+            growth_factor,
+            backoff_factor,
+            growth_interval,
+            clean_steps: 0,
+            found_inf: false,
+        }
+    }
+
+    /// The current loss scale.
+    pub fn get_scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Whether an overflow has been seen since the last `update`, meaning this step is
+    /// being skipped.
+    pub fn found_inf(&self) -> bool {
+        self.found_inf
+    }
+
+    /// Multiplies the loss gradient by the current scale.
+    pub fn scale(&self, grad: &Tensor) -> MlResult<Tensor> {
+        full_precision(|| grad.mul_scalar(self.scale))
+    }
+
+    /// Divides a scaled gradient by the scale, for gradients applied by hand. Non-finite
+    /// values mark the step as overflowed.
+    pub fn unscale(&mut self, grad: &Tensor) -> MlResult<Tensor> {
+        if !is_finite(grad) {
+            self.found_inf = true;
+        }
+        full_precision(|| grad.mul_scalar(1.0 / self.scale))
+    }
+
+    /// Backpropagates a scaled gradient through `layer`, updating its weights as if the
+    /// gradient had not been scaled, and returns the still scaled input gradient.
+    ///
+    /// If the gradient or the updated weights aren't finite, the layer is restored and the
+    /// step is marked as overflowed. Once that has happened, later layers in the same step
+    /// are left untouched and get a zero gradient.
+    pub fn backward<L: Layer + ?Sized>(
+        &mut self,
+        layer: &mut L,
+        input: &Tensor,
+        grad_output: &Tensor,
+        learning_rate: f32,
+    ) -> MlResult<Tensor> {
+        if !self.found_inf && is_finite(grad_output) {
+            let snapshot: Vec<Tensor> = layer
+                .parameters_mut()
+                .into_iter()
+                .map(|param| param.clone())
+                .collect();
+
+            let grad_input = layer.backward(input, grad_output, learning_rate / self.scale)?;
+            if is_finite(&grad_input) && layer.parameters_mut().iter().all(|p| is_finite(p)) {
+                return Ok(grad_input);
+            }
+
+            for (param, saved) in layer.parameters_mut().into_iter().zip(snapshot) {
+                *param = saved;
+            }
+        }
+
+        self.found_inf = true;
+        Tensor::from_vec(vec![0.0; input.data().len()], input.shape())
+    }
+
+    /// Adjusts the scale at the end of a step and starts the next one.
+    pub fn update(&mut self) {
+        if self.found_inf {
+            self.scale *= self.backoff_factor;
+            self.clean_steps = 0;
+        } else {
+            self.clean_steps += 1;
+            if self.clean_steps == self.growth_interval {
+                self.scale *= self.growth_factor;
+                self.clean_steps = 0;
+            }
+        }
+        self.found_inf = false;
+    }
+}
+
+impl TrainingState for GradScaler {
+    fn state_dict(&self) -> MlResult<StateDict> {
+        let mut state = StateDict::new();
+        state.insert_scalar("scale", self.scale)?;
+        state.insert_scalar("growth_factor", self.growth_factor)?;
+        state.insert_scalar("backoff_factor", self.backoff_factor)?;
+        state.insert_scalar("growth_interval", self.growth_interval as f32)?;
+        state.insert_scalar("clean_steps", self.clean_steps as f32)?;
+        Ok(state)
+    }
+
+    fn load_state_dict(&mut self, state: &StateDict) -> MlResult<()> {
+        *self = Self {
+            scale: state.scalar("scale")?,
+            growth_factor: state.scalar("growth_factor")?,
+            backoff_factor: state.scalar("backoff_factor")?,
+            growth_interval: state.scalar("growth_interval")? as usize,
+            clean_steps: state.scalar("clean_steps")? as usize,
+            found_inf: false,
+        };
+        Ok(())
+    }
+}
+
+fn is_finite(tensor: &Tensor) -> bool {
+    tensor.data().iter().all(|value| value.is_finite())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::activation::Softmax;
+    use crate::nn::Linear;
+
+    #[test]
+    fn test_half_precision_rounding() {
+        assert_eq!(Precision::F16.round(1.0 + 1.0 / 4096.0), 1.0);
+        assert_eq!(Precision::F16.round(1.0 + 3.0 / 2048.0), 1.0 + 2.0 / 1024.0);
+        assert_eq!(Precision::F16.round(65504.0), 65504.0);
+        assert_eq!(Precision::F16.round(65520.0), f32::INFINITY);
+        assert_eq!(Precision::F16.round(-1e-8), 0.0);
+        assert_eq!(Precision::F16.round(1e-7), 2.0 * 5.960_464_5e-8);
+
+        assert_eq!(Precision::BF16.round(1.0 + 1.0 / 256.0), 1.0);
+        assert_eq!(Precision::BF16.round(1.0 + 3.0 / 256.0), 1.0 + 2.0 / 128.0);
+        assert_eq!(Precision::BF16.round(f32::MAX), f32::INFINITY);
+        assert!(Precision::BF16.round(f32::NAN).is_nan());
+
+        for value in [0.0, -1.5, 65504.0, 1e-7, -3.0e-5, 0.1, 1e6] {
+            let back = cetana_core::safetensors::f16_to_f32(f32_to_f16(value));
+            assert_eq!(back, Precision::F16.round(value));
+        }
+    }
+
+    #[test]
+    fn test_autocast_matmul() -> MlResult<()> {
+        let a = Tensor::from_vec(vec![1.0 + 1.0 / 4096.0, 1.0], &[1, 2])?;
+        let b = Tensor::from_vec(vec![1.0, 1.0], &[2, 1])?;
+
+        assert!(autocast_precision().is_none());
+        let half = autocast(Precision::F16, || {
+            assert_eq!(autocast_precision(), Some(Precision::F16));
+            assert!(full_precision(autocast_precision).is_none());
+            a.matmul(&b)
+        })?;
+        assert!(autocast_precision().is_none());
+
+        assert_eq!(half.data(), &[2.0]);
+        assert_eq!(a.matmul(&b)?.data(), &[2.0 + 1.0 / 4096.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_grad_scaler_skips_overflowing_steps() -> MlResult<()> {
+        let mut layer = Linear::new(2, 1, true)?;
+        let input = Tensor::from_vec(vec![1.0, 2.0], &[1, 2])?;
+        let grad = Tensor::from_vec(vec![0.5], &[1, 1])?;
+        let before: Vec<Vec<f32>> = layer
+            .parameters_mut()
+            .iter()
+            .map(|p| p.data().to_vec())
+            .collect();
+
+        // A clean step applies the unscaled update
+        let mut scaler = GradScaler::new(1024.0, 2.0, 0.5, 1);
+        let scaled = scaler.scale(&grad)?;
+        let grad_input = scaler.backward(&mut layer, &input, &scaled, 0.1)?;
+        assert!(!scaler.found_inf());
+        let weight = layer.parameters_mut()[0].data().to_vec();
+        assert!((weight[0] - (before[0][0] - 0.05)).abs() < 1e-5);
+        assert!((weight[1] - (before[0][1] - 0.1)).abs() < 1e-5);
+        assert!((grad_input.data()[0] - 512.0 * before[0][0]).abs() < 1e-3);
+        scaler.update();
+        assert_eq!(scaler.get_scale(), 2048.0);
+
+        // An update that overflows is rolled back and backs the scale off
+        let after_clean: Vec<Vec<f32>> = layer
+            .parameters_mut()
+            .iter()
+            .map(|p| p.data().to_vec())
+            .collect();
+        let large = Tensor::from_vec(vec![1e36, 1.0], &[1, 2])?;
+        let grad_input = scaler.backward(&mut layer, &large, &scaled, 0.1)?;
+        assert!(scaler.found_inf());
+        assert_eq!(grad_input.data(), &[0.0, 0.0]);
+        let after_overflow: Vec<Vec<f32>> = layer
+            .parameters_mut()
+            .iter()
+            .map(|p| p.data().to_vec())
+            .collect();
+        assert_eq!(after_overflow, after_clean);
+        scaler.update();
+        assert_eq!(scaler.get_scale(), 1024.0);
+        assert!(!scaler.found_inf());
+        Ok(())
+    }
+
+    #[test]
+    fn test_half_model() -> MlResult<()> {
+        crate::seed_all(5);
+        let layer = Linear::new(4, 3, true)?;
+        let input = Tensor::from_vec(vec![0.3, -1.2, 0.7, 2.1, 0.05, 0.9, -0.4, 1.5], &[2, 4])?;
+        let reference = layer.forward(&input)?;
+
+        let model = layer.half();
+        assert_eq!(model.precision(), Precision::F16);
+        for (_, param) in model.named_parameters() {
+            for &value in param.data() {
+                assert_eq!(Precision::F16.round(value), value);
+            }
+        }
+        let output = model.forward(&input)?;
+        for (&half, &full) in output.data().iter().zip(reference.data()) {
+            assert_eq!(Precision::F16.round(half), half);
+            assert!((half - full).abs() < 1e-2, "{} vs {}", half, full);
+        }
+        assert!(autocast_precision().is_none());
+
+        // Softmax keeps accumulating in f32, so its rows still sum to one
+        let softmax = Softmax::new().to_precision(Precision::BF16);
+        let logits = Tensor::from_vec((0..64).map(|i| i as f32 / 7.0).collect(), &[1, 64])?;
+        let probs = softmax.forward(&logits)?;
+        let sum: f32 = probs.data().iter().sum();
+        assert!((sum - 1.0).abs() < 1e-2, "{}", sum);
+
+        let mut model = model;
+        assert!(model.backward(&input, &output, 0.1).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+use super::Conv2dShape;
+use crate::MlResult;
+use std::any::Any;
+use std::fmt::Debug;
+
+/// Memory owned by a backend's device. Tensors keep these between ops so GPU backends can
+/// chain work without a host round trip.
+pub trait DeviceBuffer: Debug + Send + Sync {
+    /// Number of `f32` elements in the buffer.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Copies the buffer to host memory, waiting for any queued work that writes to it.
+    fn to_host(&self) -> MlResult<Vec<f32>>;
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// Operations a backend can run directly on its device buffers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeviceOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Exp,
+    Log,
+    Sqrt,
+    Pow(f32),
+    /// `[m, n] x [n, k] -> [m, k]`
+    MatMul {
+        m: usize,
+        n: usize,
+        k: usize,
+    },
+    /// Reduces the input to a single-element buffer.
+    Sum,
+    /// Reduces the middle dimension of the input viewed as `[outer, axis, inner]`, giving
+    /// `[outer, inner]`.
+    Reduce {
+        op: ReduceOp,
+        outer: usize,
+        axis: usize,
+        inner: usize,
+    },
+    /// Convolves an NCHW input (first buffer) with a weight (second buffer).
+    Conv2d(Conv2dShape),
+}
+
+impl DeviceOp {
+    /// Number of input buffers the op reads.
+    pub fn arity(&self) -> usize {
+        match self {
+            DeviceOp::Add | DeviceOp::Sub | DeviceOp::Mul | DeviceOp::Div => 2,
+            DeviceOp::MatMul { .. } | DeviceOp::Conv2d(_) => 2,
+            _ => 1,
+        }
+    }
+}
+
+/// How `Backend::reduce` combines the elements along an axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReduceOp {
+    Sum,
+    Mean,
+    Max,
+    Min,
+}
+
+impl ReduceOp {
+    /// Reduces the middle dimension of `data` viewed as `[outer, axis, inner]` on the host.
+    pub fn apply(self, data: &[f32], outer: usize, axis: usize, inner: usize) -> Vec<f32> {
+        let mut result = Vec::with_capacity(outer * inner);
+        for o in 0..outer {
+            for i in 0..inner {
+                let values = (0..axis).map(|a| data[(o * axis + a) * inner + i]);
+                result.push(match self {
+                    ReduceOp::Sum => values.sum(),
+                    ReduceOp::Mean => values.sum::<f32>() / axis as f32,
+                    ReduceOp::Max => values.fold(f32::NEG_INFINITY, f32::max),
+                    ReduceOp::Min => values.fold(f32::INFINITY, f32::min),
+                });
+            }
+        }
+        result
+    }
+}
+
+/// One step of a fused elementwise program. Programs start from the first input and apply
+/// each step to the running value; binary steps combine it with the same element of another
+/// input, given by its index.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FusedOp {
+    Add(usize),
+    Sub(usize),
+    Mul(usize),
+    Div(usize),
+    AddScalar(f32),
+    MulScalar(f32),
+    Pow(f32),
+    Neg,
+    Exp,
+    Log,
+    Sqrt,
+}
+
+impl FusedOp {
+    /// Index of the extra input the step reads, if any.
+    pub fn input(&self) -> Option<usize> {
+        match *self {
+            FusedOp::Add(i) | FusedOp::Sub(i) | FusedOp::Mul(i) | FusedOp::Div(i) => Some(i),
+            _ => None,
+        }
+    }
+
+    /// Applies the step to `value`, the running result for element `index`.
+    pub fn apply(&self, value: f32, inputs: &[&[f32]], index: usize) -> f32 {
+        match *self {
+            FusedOp::Add(i) => value + inputs[i][index],
+            FusedOp::Sub(i) => value - inputs[i][index],
+            FusedOp::Mul(i) => value * inputs[i][index],
+            FusedOp::Div(i) => value / inputs[i][index],
+            FusedOp::AddScalar(scalar) => value + scalar,
+            FusedOp::MulScalar(scalar) => value * scalar,
+            FusedOp::Pow(power) => value.powf(power),
+            FusedOp::Neg => -value,
+            FusedOp::Exp => value.exp(),
+            FusedOp::Log => value.ln(),
+            FusedOp::Sqrt => value.sqrt(),
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use std::fmt::{Display, Formatter};
+
+/// Element types a backend can compute with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DType {
+    F32,
+    F16,
+    F64,
+}
+
+impl DType {
+    pub fn size_in_bytes(&self) -> usize {
+        match self {
+            DType::F16 => 2,
+            DType::F32 => 4,
+            DType::F64 => 8,
+        }
+    }
+}
+
+impl Display for DType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DType::F32 => write!(f, "f32"),
+            DType::F16 => write!(f, "f16"),
+            DType::F64 => write!(f, "f64"),
+        }
+    }
+}
+
+/// What a backend can do on the device it runs on, so callers can pick a device up front
+/// rather than trying one and falling back on failure.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackendCapabilities {
+    pub device_name: String,
+    /// Element types the backend's kernels accept.
+    pub dtypes: Vec<DType>,
+    /// Largest single buffer the device can allocate, in bytes. `None` when only host
+    /// memory limits it.
+    pub max_buffer_size: Option<u64>,
+    /// Whether the hardware has native half-precision arithmetic.
+    pub supports_f16: bool,
+    /// Whether work can be queued without blocking the host until it completes.
+    pub supports_async: bool,
+    /// Whether every kernel gives bit-identical results from run to run, which
+    /// `set_deterministic` requires.
+    pub deterministic: bool,
+}
+
+impl BackendCapabilities {
+    /// A backend computing in `f32` with no buffer limit and synchronous, deterministic
+    /// execution.
+    pub fn new(device_name: impl Into<String>) -> Self {
+        Self {
+            device_name: device_name.into(),
+            dtypes: vec![DType::F32],
+            max_buffer_size: None,
+            supports_f16: false,
+            supports_async: false,
+            deterministic: true,
+        }
+    }
+
+    pub fn supports_dtype(&self, dtype: DType) -> bool {
+        self.dtypes.contains(&dtype)
+    }
+
+    /// Whether a buffer of `len` elements of `dtype` fits in a single allocation.
+    pub fn fits(&self, len: usize, dtype: DType) -> bool {
+        let bytes = (len as u64).saturating_mul(dtype.size_in_bytes() as u64);
+        match self.max_buffer_size {
+            Some(max) => bytes <= max,
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capabilities_limits() {
+        let mut capabilities = BackendCapabilities::new("test");
+        assert!(capabilities.supports_dtype(DType::F32));
+        assert!(!capabilities.supports_dtype(DType::F16));
+        assert!(capabilities.fits(usize::MAX, DType::F32));
+
+        capabilities.max_buffer_size = Some(1024);
+        assert!(capabilities.fits(256, DType::F32));
+        assert!(!capabilities.fits(257, DType::F32));
+        assert!(capabilities.fits(512, DType::F16));
+    }
+}
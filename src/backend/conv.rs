@@ -0,0 +1,67 @@
+use super::Backend;
+pub use cetana_core::conv::{im2col, Conv2dShape};
+
+/// Convolution as im2col followed by one `matmul` per image, using `backend`'s matmul.
+pub fn conv2d_im2col<B: Backend + ?Sized>(
+    backend: &B,
+    input: &[f32],
+    weight: &[f32],
+    shape: &Conv2dShape,
+) -> Vec<f32> {
+    let [batch, channels, _, _] = shape.input;
+    let [out_channels, _, kernel_h, kernel_w] = shape.weight;
+    let [_, _, out_h, out_w] = shape.output();
+
+    let patch_size = channels * kernel_h * kernel_w;
+    let mut output = Vec::with_capacity(shape.output_len());
+    for b in 0..batch {
+        let columns = im2col(input, shape, b);
+        output.extend(backend.matmul(weight, &columns, out_channels, patch_size, out_h * out_w));
+    }
+    output
+}
+
+/// Depthwise convolution over `channels` channels, each convolved on its own with
+/// `shape.weight[0]` filters of the `[channels * that, 1, kh, kw]` weight. `shape` is the
+/// geometry of one channel. Each output is accumulated directly, one kernel tap at a time,
+/// which for a single input channel is much cheaper than unfolding it with im2col.
+pub fn depthwise_conv2d(
+    input: &[f32],
+    weight: &[f32],
+    shape: &Conv2dShape,
+    channels: usize,
+) -> Vec<f32> {
+    let [batch, _, height, width] = shape.input;
+    let [multiplier, _, kernel_h, kernel_w] = shape.weight;
+    let [_, _, out_h, out_w] = shape.output();
+    let (stride_h, stride_w) = shape.stride;
+    let (pad_h, pad_w) = shape.padding;
+
+    let mut output = vec![0.0; batch * channels * multiplier * out_h * out_w];
+    let maps = output.chunks_mut(out_h * out_w).enumerate();
+    for (map, out) in maps {
+        // Output map `map` is filter `map % (channels * multiplier)` over the channel it reads
+        let filter = map % (channels * multiplier);
+        let image = &input[(map / multiplier) * height * width..][..height * width];
+        let kernel = &weight[filter * kernel_h * kernel_w..][..kernel_h * kernel_w];
+        for ky in 0..kernel_h {
+            for kx in 0..kernel_w {
+                let tap = kernel[ky * kernel_w + kx];
+                for oy in 0..out_h {
+                    let y = (oy * stride_h + ky).wrapping_sub(pad_h);
+                    if y >= height {
+                        continue;
+                    }
+                    let row = &image[y * width..][..width];
+                    for ox in 0..out_w {
+                        let x = (ox * stride_w + kx).wrapping_sub(pad_w);
+                        if x < width {
+                            out[oy * out_w + ox] += tap * row[x];
+                        }
+                    }
+                }
+            }
+        }
+    }
+    output
+}
@@ -0,0 +1,177 @@
+use std::os::raw::{c_long, c_ulong};
+
+type VDspStride = c_long;
+type VDspLength = c_ulong;
+
+// vDSP routines from Accelerate.framework. cblas_sgemm used by `blas::sgemm`
+// is also provided by this framework.
+#[link(name = "Accelerate", kind = "framework")]
+extern "C" {
+    fn vDSP_vadd(
+        a: *const f32,
+        ia: VDspStride,
+        b: *const f32,
+        ib: VDspStride,
+        c: *mut f32,
+        ic: VDspStride,
+        n: VDspLength,
+    );
+    fn vDSP_vsub(
+        b: *const f32,
+        ib: VDspStride,
+        a: *const f32,
+        ia: VDspStride,
+        c: *mut f32,
+        ic: VDspStride,
+        n: VDspLength,
+    );
+    fn vDSP_vmul(
+        a: *const f32,
+        ia: VDspStride,
+        b: *const f32,
+        ib: VDspStride,
+        c: *mut f32,
+        ic: VDspStride,
+        n: VDspLength,
+    );
+    fn vDSP_sve(a: *const f32, ia: VDspStride, c: *mut f32, n: VDspLength);
+    fn vDSP_maxv(a: *const f32, ia: VDspStride, c: *mut f32, n: VDspLength);
+    fn vDSP_minv(a: *const f32, ia: VDspStride, c: *mut f32, n: VDspLength);
+}
+
+pub fn vadd(a: &[f32], b: &[f32]) -> Vec<f32> {
+    let n = a.len().min(b.len());
+    let mut result = vec![0.0; n];
+    // SAFETY: `n` is no longer than either input, and `result` holds `n` elements
+    unsafe {
+        vDSP_vadd(
+            a.as_ptr(),
+            1,
+            b.as_ptr(),
+            1,
+            result.as_mut_ptr(),
+            1,
+            n as VDspLength,
+        );
+    }
+    result
+}
+
+pub fn vsub(a: &[f32], b: &[f32]) -> Vec<f32> {
+    let n = a.len().min(b.len());
+    let mut result = vec![0.0; n];
+    // vDSP_vsub computes A - B with the subtrahend passed first
+    // SAFETY: `n` is no longer than either input, and `result` holds `n` elements
+    unsafe {
+        vDSP_vsub(
+            b.as_ptr(),
+            1,
+            a.as_ptr(),
+            1,
+            result.as_mut_ptr(),
+            1,
+            n as VDspLength,
+        );
+    }
+    result
+}
+
+pub fn vmul(a: &[f32], b: &[f32]) -> Vec<f32> {
+    let n = a.len().min(b.len());
+    let mut result = vec![0.0; n];
+    // SAFETY: `n` is no longer than either input, and `result` holds `n` elements
+    unsafe {
+        vDSP_vmul(
+            a.as_ptr(),
+            1,
+            b.as_ptr(),
+            1,
+            result.as_mut_ptr(),
+            1,
+            n as VDspLength,
+        );
+    }
+    result
+}
+
+pub fn sum(a: &[f32]) -> f32 {
+    let mut result = 0.0;
+    // SAFETY: vDSP_sve reads exactly `a.len()` elements and writes one scalar
+    unsafe {
+        vDSP_sve(a.as_ptr(), 1, &mut result, a.len() as VDspLength);
+    }
+    result
+}
+
+/// The largest of the `n` elements of `a` that are `stride` apart, from the first; -inf
+/// when `n` is 0.
+pub fn maxv(a: &[f32], stride: usize, n: usize) -> f32 {
+    strided(vDSP_maxv, f32::NEG_INFINITY, a, stride, n)
+}
+
+/// The smallest of the `n` elements of `a` that are `stride` apart, from the first; inf
+/// when `n` is 0.
+pub fn minv(a: &[f32], stride: usize, n: usize) -> f32 {
+    strided(vDSP_minv, f32::INFINITY, a, stride, n)
+}
+
+// Runs a vDSP vector-to-scalar reduction over a strided run of `a`
+fn strided(
+    reduce: unsafe extern "C" fn(*const f32, VDspStride, *mut f32, VDspLength),
+    empty: f32,
+    a: &[f32],
+    stride: usize,
+    n: usize,
+) -> f32 {
+    if n == 0 {
+        return empty;
+    }
+    assert!(
+        (n - 1) * stride < a.len(),
+        "strided run past the end of the data"
+    );
+
+    let mut result = empty;
+    // SAFETY: the last element read, at `(n - 1) * stride`, is inside `a` as checked above
+    unsafe {
+        reduce(
+            a.as_ptr(),
+            stride as VDspStride,
+            &mut result,
+            n as VDspLength,
+        );
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_elementwise() {
+        let a = vec![1.0, 2.0, 3.0];
+        let b = vec![4.0, 5.0, 6.0];
+
+        assert_eq!(vadd(&a, &b), vec![5.0, 7.0, 9.0]);
+        assert_eq!(vsub(&a, &b), vec![-3.0, -3.0, -3.0]);
+        assert_eq!(vmul(&a, &b), vec![4.0, 10.0, 18.0]);
+    }
+
+    #[test]
+    fn test_sum() {
+        let a = vec![1.0, 2.0, 3.0, 4.0];
+        assert_eq!(sum(&a), 10.0);
+        assert_eq!(sum(&[]), 0.0);
+    }
+
+    #[test]
+    fn test_max_min() {
+        // Column 1 of a [3, 2] matrix is 2, -4, 6
+        let a = vec![1.0, 2.0, 3.0, -4.0, 5.0, 6.0];
+        assert_eq!(maxv(&a, 1, 6), 6.0);
+        assert_eq!(minv(&a[1..], 2, 3), -4.0);
+        assert_eq!(maxv(&a, 2, 0), f32::NEG_INFINITY);
+        assert_eq!(minv(&[], 1, 0), f32::INFINITY);
+    }
+}
@@ -0,0 +1,102 @@
+use std::os::raw::c_int;
+
+// CBLAS enum values (cblas.h)
+const CBLAS_ROW_MAJOR: c_int = 101;
+const CBLAS_NO_TRANS: c_int = 111;
+
+extern "C" {
+    #[allow(clippy::too_many_arguments)]
+    fn cblas_sgemm(
+        layout: c_int,
+        trans_a: c_int,
+        trans_b: c_int,
+        m: c_int,
+        n: c_int,
+        k: c_int,
+        alpha: f32,
+        a: *const f32,
+        lda: c_int,
+        b: *const f32,
+        ldb: c_int,
+        beta: f32,
+        c: *mut f32,
+        ldc: c_int,
+    );
+}
+
+/// Row-major GEMM through CBLAS.
+///
+/// Uses the same argument convention as `CpuCompute::matmul`:
+/// `a` is `[m, n]`, `b` is `[n, k]` and the result is `[m, k]`.
+///
+/// Panics if `a` or `b` doesn't hold exactly that many elements, or a dimension doesn't
+/// fit in a C `int`.
+pub fn sgemm(a: &[f32], b: &[f32], m: usize, n: usize, k: usize) -> Vec<f32> {
+    let mut result = vec![0.0; m * k];
+    if m == 0 || n == 0 || k == 0 {
+        return result;
+    }
+
+    assert_eq!(a.len(), m * n, "sgemm: `a` is not [{}, {}]", m, n);
+    assert_eq!(b.len(), n * k, "sgemm: `b` is not [{}, {}]", n, k);
+    assert!(
+        [m, n, k].iter().all(|&d| c_int::try_from(d).is_ok()),
+        "sgemm: [{}, {}] x [{}, {}] is too large for CBLAS",
+        m,
+        n,
+        n,
+        k
+    );
+
+    // SAFETY: `a` holds `m * n` elements and `b` holds `n * k`, as checked above, and
+    // `result` was allocated with `m * k`. With row-major, untransposed operands and
+    // leading dimensions `n`, `k` and `k`, CBLAS reads and writes exactly those ranges, and
+    // every dimension fits in a `c_int` so none of the casts wrap.
+    unsafe {
+        cblas_sgemm(
+            CBLAS_ROW_MAJOR,
+            CBLAS_NO_TRANS,
+            CBLAS_NO_TRANS,
+            m as c_int,
+            k as c_int,
+            n as c_int,
+            1.0,
+            a.as_ptr(),
+            n as c_int,
+            b.as_ptr(),
+            k as c_int,
+            0.0,
+            result.as_mut_ptr(),
+            k as c_int,
+        );
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sgemm() {
+        let a = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let b = vec![7.0, 8.0, 9.0, 10.0, 11.0, 12.0];
+
+        let result = sgemm(&a, &b, 2, 3, 2);
+        assert_eq!(result, vec![58.0, 64.0, 139.0, 154.0]);
+    }
+
+    #[test]
+    fn test_sgemm_empty() {
+        let result = sgemm(&[], &[], 0, 3, 2);
+        assert!(result.is_empty());
+    }
+
+    #[test]
+    fn test_sgemm_rejects_short_operands() {
+        // `a` should be [2, 3]; reading past it would be undefined behaviour
+        let result = std::panic::catch_unwind(|| sgemm(&[1.0, 2.0], &[1.0; 6], 2, 3, 2));
+        assert!(result.is_err());
+    }
+}
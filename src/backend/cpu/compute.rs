@@ -1,3 +1,4 @@
+use crate::backend::ReduceOp;
 use crate::MlResult;
 
 #[derive(Debug)]
@@ -22,6 +23,11 @@ impl CpuCompute {
 
     // Optimized binary operations using chunks
     pub fn add(&self, a: &[f32], b: &[f32]) -> Vec<f32> {
+        #[cfg(all(feature = "accelerate", target_os = "macos"))]
+        if self.check_dimensions(a, b).is_some() {
+            return super::accelerate::vadd(a, b);
+        }
+
         if let Some(len) = self.check_dimensions(a, b) {
             let mut result = Vec::with_capacity(len);
             for (x, y) in a.chunks(4).zip(b.chunks(4)) {
@@ -34,6 +40,11 @@ impl CpuCompute {
     }
 
     pub fn multiply(&self, a: &[f32], b: &[f32]) -> Vec<f32> {
+        #[cfg(all(feature = "accelerate", target_os = "macos"))]
+        if self.check_dimensions(a, b).is_some() {
+            return super::accelerate::vmul(a, b);
+        }
+
         if let Some(len) = self.check_dimensions(a, b) {
             let mut result = Vec::with_capacity(len);
             let chunks = len / 8;
@@ -64,37 +75,42 @@ impl CpuCompute {
         }
     }
 
-    // Optimized matrix multiplication with cache-friendly access
+    // Matrix multiplication, routed through CBLAS when "blas" or "accelerate" is enabled
     pub fn matmul(&self, a: &[f32], b: &[f32], m: usize, n: usize, k: usize) -> Vec<f32> {
-        let mut result = vec![0.0; m * k];
-        let block_size = 32;
+        #[cfg(any(feature = "blas", all(feature = "accelerate", target_os = "macos")))]
+        {
+            super::blas::sgemm(a, b, m, n, k)
+        }
+
+        #[cfg(not(any(feature = "blas", all(feature = "accelerate", target_os = "macos"))))]
+        {
+            cetana_core::kernels::matmul(a, b, m, n, k)
+        }
+    }
+
+    // Int8 matmul with i32 accumulation. B is transposed so each output is a dot product of
+    // two contiguous rows, which the compiler vectorizes into widening multiply-adds.
+    pub fn matmul_i8(&self, a: &[i8], b: &[i8], m: usize, n: usize, k: usize) -> Vec<i32> {
+        if a.len() != m * n || b.len() != n * k || n == 0 {
+            return vec![0; m * k];
+        }
 
-        // Pre-transpose matrix B and store in contiguous memory
-        let mut b_trans = vec![0.0; n * k];
+        let mut b_trans = vec![0; n * k];
         for j in 0..k {
             for i in 0..n {
                 b_trans[j * n + i] = b[i * k + j];
             }
         }
 
-        // Process blocks with better cache utilization
-        for i0 in (0..m).step_by(block_size) {
-            for l0 in (0..n).step_by(block_size) {
-                for j0 in (0..k).step_by(block_size) {
-                    let i_end = (i0 + block_size).min(m);
-                    let l_end = (l0 + block_size).min(n);
-                    let j_end = (j0 + block_size).min(k);
-
-                    for i in i0..i_end {
-                        for l in l0..l_end {
-                            let a_val = a[i * n + l];
-                            let row_idx = i * k;
-                            for j in j0..j_end {
-                                result[row_idx + j] += a_val * b_trans[j * n + l];
-                            }
-                        }
-                    }
-                }
+        let mut result = Vec::with_capacity(m * k);
+        for row in a.chunks_exact(n).take(m) {
+            for col in b_trans.chunks_exact(n).take(k) {
+                result.push(
+                    row.iter()
+                        .zip(col)
+                        .map(|(&x, &y)| x as i32 * y as i32)
+                        .sum(),
+                );
             }
         }
         result
@@ -119,6 +135,11 @@ impl CpuCompute {
     }
 
     pub fn sub(&self, a: &[f32], b: &[f32]) -> Vec<f32> {
+        #[cfg(all(feature = "accelerate", target_os = "macos"))]
+        if self.check_dimensions(a, b).is_some() {
+            return super::accelerate::vsub(a, b);
+        }
+
         if let Some(len) = self.check_dimensions(a, b) {
             let mut result = Vec::with_capacity(len);
             for (x, y) in a.chunks(4).zip(b.chunks(4)) {
@@ -174,6 +195,19 @@ impl CpuCompute {
 
     // Optimized reduction operations
     pub fn sum(&self, a: &[f32]) -> f32 {
+        #[cfg(all(feature = "accelerate", target_os = "macos"))]
+        {
+            super::accelerate::sum(a)
+        }
+
+        #[cfg(not(all(feature = "accelerate", target_os = "macos")))]
+        {
+            self.sum_unrolled(a)
+        }
+    }
+
+    #[cfg_attr(all(feature = "accelerate", target_os = "macos"), allow(dead_code))]
+    fn sum_unrolled(&self, a: &[f32]) -> f32 {
         let mut sum = 0.0;
         let chunks = a.len() / 8;
         let remainder = a.len() % 8;
@@ -206,6 +240,33 @@ impl CpuCompute {
         }
         self.sum(a) / a.len() as f32
     }
+
+    // Reduces the middle dimension of `a` viewed as `[outer, axis, inner]`, running max and
+    // min through vDSP when "accelerate" is enabled
+    pub fn reduce(
+        &self,
+        op: ReduceOp,
+        a: &[f32],
+        outer: usize,
+        axis: usize,
+        inner: usize,
+    ) -> Vec<f32> {
+        #[cfg(all(feature = "accelerate", target_os = "macos"))]
+        if axis > 0 {
+            let extreme: Option<fn(&[f32], usize, usize) -> f32> = match op {
+                ReduceOp::Max => Some(super::accelerate::maxv),
+                ReduceOp::Min => Some(super::accelerate::minv),
+                ReduceOp::Sum | ReduceOp::Mean => None,
+            };
+            if let Some(extreme) = extreme {
+                return (0..outer * inner)
+                    .map(|i| extreme(&a[(i / inner) * axis * inner + i % inner..], inner, axis))
+                    .collect();
+            }
+        }
+
+        op.apply(a, outer, axis, inner)
+    }
 }
 
 #[cfg(test)]
@@ -241,6 +302,32 @@ mod tests {
         assert_eq!(result, vec![19.0, 22.0, 43.0, 50.0]);
     }
 
+    #[test]
+    fn test_reduce() {
+        let compute = CpuCompute::new();
+        // [2, 3, 2], reduced along the middle
+        let a = vec![
+            1.0, -1.0, 5.0, 0.0, 3.0, 2.0, -6.0, 4.0, 0.0, 8.0, 2.0, -2.0,
+        ];
+
+        assert_eq!(
+            compute.reduce(ReduceOp::Max, &a, 2, 3, 2),
+            vec![5.0, 2.0, 2.0, 8.0]
+        );
+        assert_eq!(
+            compute.reduce(ReduceOp::Min, &a, 2, 3, 2),
+            vec![1.0, -1.0, -6.0, -2.0]
+        );
+        assert_eq!(
+            compute.reduce(ReduceOp::Sum, &a, 2, 3, 2),
+            vec![9.0, 1.0, -4.0, 10.0]
+        );
+        assert_eq!(
+            compute.reduce(ReduceOp::Max, &[], 2, 0, 1),
+            vec![f32::NEG_INFINITY; 2]
+        );
+    }
+
     #[test]
     fn test_exponential_operations() {
         let compute = CpuCompute::new();
@@ -330,4 +417,16 @@ mod tests {
         let result = compute.matmul(&a, &b, 2, 3, 2);
         assert_eq!(result, vec![58.0, 64.0, 139.0, 154.0]);
     }
+
+    #[test]
+    fn test_matmul_i8() {
+        let compute = CpuCompute::new();
+
+        let a: Vec<i8> = (0..35).map(|x| (x * 37 % 256 - 128) as i8).collect();
+        let b: Vec<i8> = (0..63).map(|x| (x * 53 % 256 - 128) as i8).collect();
+        assert_eq!(
+            compute.matmul_i8(&a, &b, 5, 7, 9),
+            crate::backend::matmul_i8(&a, &b, 5, 7, 9)
+        );
+    }
 }
@@ -1,4 +1,4 @@
-use crate::backend::{DeviceFeatures, DeviceType};
+use crate::backend::DeviceType;
 
 #[derive(Debug)]
 pub struct CpuCore;
@@ -11,8 +11,4 @@ impl CpuCore {
     pub fn device_type(&self) -> DeviceType {
         DeviceType::Cpu
     }
-
-    pub fn supports_feature(&self, _feature: &str) -> DeviceFeatures {
-        DeviceFeatures::new()
-    }
 }
@@ -1,9 +1,13 @@
 use crate::backend::feature::{
     DeviceFeatures, CPU_FEATURE_AVX, CPU_FEATURE_AVX2, CPU_FEATURE_AVX512F,
 };
-use crate::backend::{Backend, Device, DeviceType};
+use crate::backend::{split_axis, Backend, BackendCapabilities, Device, DeviceType, ReduceOp};
 use crate::MlResult;
 
+#[cfg(all(feature = "accelerate", target_os = "macos"))]
+mod accelerate;
+#[cfg(any(feature = "blas", all(feature = "accelerate", target_os = "macos")))]
+mod blas;
 mod compute;
 mod core;
 
@@ -61,6 +65,10 @@ impl Backend for CpuBackend {
         self.core.device_type()
     }
 
+    fn capabilities(&self) -> BackendCapabilities {
+        BackendCapabilities::new("CPU")
+    }
+
     fn execute_compute(&self, dimensions: [u32; 3]) -> MlResult<()> {
         self.compute.execute(dimensions)
     }
@@ -78,6 +86,10 @@ impl Backend for CpuBackend {
         self.compute.matmul(a, b, m, n, k)
     }
 
+    fn matmul_i8(&self, a: &[i8], b: &[i8], m: usize, n: usize, k: usize) -> Vec<i32> {
+        self.compute.matmul_i8(a, b, m, n, k)
+    }
+
     fn div(&self, a: &[f32], b: &[f32]) -> Vec<f32> {
         self.compute.div(a, b)
     }
@@ -109,6 +121,11 @@ impl Backend for CpuBackend {
     fn mean(&self, a: &[f32]) -> f32 {
         self.compute.mean(a)
     }
+
+    fn reduce(&self, op: ReduceOp, a: &[f32], shape: &[usize], axis: usize) -> Vec<f32> {
+        let (outer, len, inner) = split_axis(shape, axis);
+        self.compute.reduce(op, a, outer, len, inner)
+    }
 }
 
 #[cfg(test)]
@@ -1,27 +1,260 @@
-use super::{initialize_cuda, vector_add, vector_multiply, CudaBuffer, CudaDevice};
+use super::cublas::Cublas;
+use super::cudnn::Cudnn;
+use super::launch::LaunchConfig;
+use super::pool;
+use super::{initialize_cuda, CudaBuffer, CudaDevice, CudaError, CudaStream};
 use crate::backend::cuda::compute::*;
 use crate::backend::feature::{
     DeviceFeatures, GPU_FEATURE_FP16, GPU_FEATURE_FP64, GPU_FEATURE_TENSOR_CORES,
 };
-use crate::backend::{Backend, Device, DeviceType};
+use crate::backend::{
+    conv2d_im2col, is_deterministic, matmul_i8, split_axis, Backend, BackendCapabilities,
+    Conv2dShape, Device, DeviceBuffer, DeviceOp, DeviceType, FusedOp, MemoryStats, ReduceOp,
+};
 use crate::MlResult;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
 
 #[derive(Debug)]
 pub struct CudaBackend {
     device: CudaDevice,
+    stream: Arc<CudaStream>,
 }
 
-impl Device for CudaBackend {
-    fn new() -> MlResult<Self> {
+impl CudaBackend {
+    /// Creates a backend on the CUDA device at `index`.
+    pub fn with_device(index: usize) -> MlResult<Self> {
         initialize_cuda().map_err(|e| format!("CUDA initialization failed: {}", e))?;
-        let device =
-            CudaDevice::new(0).map_err(|e| format!("Failed to create CUDA device: {}", e))?;
+        let device = CudaDevice::new(index as i32)
+            .map_err(|e| format!("Failed to create CUDA device: {}", e))?;
+
+        // Streams belong to the device that is current when they are created
+        device
+            .set_current()
+            .map_err(|e| format!("Failed to select CUDA device: {}", e))?;
+        let stream =
+            CudaStream::new().map_err(|e| format!("Failed to create CUDA stream: {}", e))?;
+
+        Ok(CudaBackend {
+            device,
+            stream: Arc::new(stream),
+        })
+    }
+
+    /// Returns a process-wide backend instance for the device at `index`. Tensors share it
+    /// so that all of their device-resident work on that device is ordered on one stream.
+    pub fn shared(index: usize) -> MlResult<Arc<CudaBackend>> {
+        static SHARED: OnceLock<Mutex<HashMap<usize, Option<Arc<CudaBackend>>>>> = OnceLock::new();
+
+        let mut backends = SHARED
+            .get_or_init(|| Mutex::new(HashMap::new()))
+            .lock()
+            .map_err(|_| "CUDA backend registry poisoned")?;
+        backends
+            .entry(index)
+            .or_insert_with(|| CudaBackend::with_device(index).ok().map(Arc::new))
+            .clone()
+            .ok_or_else(|| format!("CUDA device {} is not available", index).into())
+    }
+
+    pub fn device_name(&self) -> &str {
+        self.device.get_device_name()
+    }
+
+    // Allocations and launches go to the thread's current device
+    fn activate(&self) -> Result<(), CudaError> {
+        self.device.set_current()
+    }
+
+    /// The stream this backend queues its work on. Each backend owns its own stream,
+    /// so work from different backends is not serialized on the default stream.
+    pub fn stream(&self) -> &CudaStream {
+        &self.stream
+    }
+
+    /// Blocks until all work queued on this backend's stream has completed.
+    pub fn synchronize(&self) -> MlResult<()> {
+        self.stream
+            .synchronize()
+            .map_err(|e| format!("CUDA stream synchronization failed: {}", e))?;
+        Ok(())
+    }
 
-        Ok(CudaBackend { device })
+    // Uploads `inputs`, queues `queue` and downloads `output_len` floats, all on the
+    // backend's stream, with a single synchronization at the end.
+    fn run_on_stream<F>(
+        &self,
+        inputs: &[&[f32]],
+        output_len: usize,
+        queue: F,
+    ) -> Result<Vec<f32>, CudaError>
+    where
+        F: FnOnce(&[CudaBuffer], &mut CudaBuffer, &CudaStream) -> Result<(), CudaError>,
+    {
+        self.activate()?;
+        let mut buffers = inputs
+            .iter()
+            .map(|data| CudaBuffer::from_pool(data.len(), &self.stream))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut result_buf = CudaBuffer::from_pool(output_len, &self.stream)?;
+        let mut result = vec![0.0; output_len];
+
+        let queued = (|| unsafe {
+            for (buffer, data) in buffers.iter_mut().zip(inputs) {
+                buffer.copy_from_host_async(data, &self.stream)?;
+            }
+            queue(&buffers, &mut result_buf, &self.stream)?;
+            result_buf.copy_to_host_async(&mut result, &self.stream)
+        })();
+
+        // Always wait, so no queued copy outlives the host memory it touches
+        let synced = self.stream.synchronize();
+        queued?;
+        synced?;
+
+        Ok(result)
+    }
+
+    // Queues `op` on the backend's stream without waiting for it
+    fn queue_on_device(
+        &self,
+        op: DeviceOp,
+        inputs: &[&CudaBuffer],
+    ) -> Result<CudaBuffer, CudaError> {
+        self.activate()?;
+        let stream = &self.stream;
+        let len = inputs[0].len();
+
+        match op {
+            DeviceOp::MatMul { m, n, k } => {
+                let mut result = CudaBuffer::from_pool(m * k, stream)?;
+                self.gemm(inputs[0], inputs[1], &mut result, m, n, k)?;
+                Ok(result)
+            }
+            DeviceOp::Sum => {
+                // Reduce block partials until a single value is left, never leaving the device
+                let mut partials =
+                    CudaBuffer::from_pool(LaunchConfig::linear(len).grid_dim.x as usize, stream)?;
+                vector_reduce_sum_async(inputs[0], &mut partials, stream)?;
+                while partials.len() > 1 {
+                    let mut next = CudaBuffer::from_pool(
+                        LaunchConfig::linear(partials.len()).grid_dim.x as usize,
+                        stream,
+                    )?;
+                    vector_reduce_sum_async(&partials, &mut next, stream)?;
+                    partials = next;
+                }
+                Ok(partials)
+            }
+            DeviceOp::Reduce {
+                op,
+                outer,
+                axis,
+                inner,
+            } => {
+                let mut result = CudaBuffer::from_pool(outer * inner, stream)?;
+                reduce_axis_async(inputs[0], &mut result, op, outer, axis, inner, stream)?;
+                Ok(result)
+            }
+            DeviceOp::Conv2d(shape) => {
+                let mut result = CudaBuffer::from_pool(shape.output_len(), stream)?;
+                self.queue_conv2d(inputs[0], inputs[1], &mut result, &shape)?;
+                Ok(result)
+            }
+            _ => {
+                let mut result = CudaBuffer::from_pool(len, stream)?;
+                match op {
+                    DeviceOp::Add => vector_add_async(inputs[0], inputs[1], &mut result, stream),
+                    DeviceOp::Sub => {
+                        vector_subtract_async(inputs[0], inputs[1], &mut result, stream)
+                    }
+                    DeviceOp::Mul => {
+                        vector_multiply_async(inputs[0], inputs[1], &mut result, stream)
+                    }
+                    DeviceOp::Div => vector_divide_async(inputs[0], inputs[1], &mut result, stream),
+                    DeviceOp::Exp => vector_exp_async(inputs[0], &mut result, stream),
+                    DeviceOp::Log => vector_log_async(inputs[0], &mut result, stream),
+                    DeviceOp::Sqrt => vector_sqrt_async(inputs[0], &mut result, stream),
+                    DeviceOp::Pow(power) => vector_pow_async(inputs[0], power, &mut result, stream),
+                    DeviceOp::MatMul { .. }
+                    | DeviceOp::Sum
+                    | DeviceOp::Reduce { .. }
+                    | DeviceOp::Conv2d(_) => unreachable!(),
+                }?;
+                Ok(result)
+            }
+        }
+    }
+
+    /// Whether matmul is dispatched to cuBLAS rather than the bundled kernel.
+    pub fn has_cublas(&self) -> bool {
+        Cublas::is_available()
+    }
+
+    /// Whether `conv2d` is dispatched to cuDNN rather than the bundled kernel.
+    pub fn has_cudnn(&self) -> bool {
+        Cudnn::is_available()
+    }
+
+    // cuBLAS when it loads and succeeds, the bundled kernel otherwise. The cuBLAS handle is
+    // shared by every backend's stream, which voids its reproducibility guarantee, so
+    // deterministic mode always uses the kernel: each output is one fixed-order dot product.
+    fn gemm(
+        &self,
+        a: &CudaBuffer,
+        b: &CudaBuffer,
+        result: &mut CudaBuffer,
+        m: usize,
+        n: usize,
+        k: usize,
+    ) -> Result<(), CudaError> {
+        if is_deterministic() {
+            return matrix_multiply_async(a, b, result, m, n, k, &self.stream);
+        }
+
+        if let Some(cublas) = Cublas::global() {
+            if let Ok(cublas) = cublas.lock() {
+                if cublas.set_stream(&self.stream).is_ok()
+                    && cublas.sgemm(a, b, result, m, n, k).is_ok()
+                {
+                    return Ok(());
+                }
+            }
+        }
+        matrix_multiply_async(a, b, result, m, n, k, &self.stream)
+    }
+
+    // cuDNN when it loads and succeeds, the bundled kernel otherwise. cuDNN picks its
+    // algorithm per call, so deterministic mode always uses the kernel.
+    fn queue_conv2d(
+        &self,
+        input: &CudaBuffer,
+        weight: &CudaBuffer,
+        result: &mut CudaBuffer,
+        shape: &Conv2dShape,
+    ) -> Result<(), CudaError> {
+        if !is_deterministic() {
+            if let Some(cudnn) = Cudnn::global() {
+                if let Ok(cudnn) = cudnn.lock() {
+                    if cudnn.set_stream(&self.stream).is_ok()
+                        && cudnn.conv2d_forward(input, weight, result, shape).is_ok()
+                    {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        conv2d_async(input, weight, result, shape, &self.stream)
+    }
+}
+
+impl Device for CudaBackend {
+    fn new() -> MlResult<Self> {
+        Self::with_device(0)
     }
 
     fn device_type(&self) -> DeviceType {
-        DeviceType::Cuda
+        DeviceType::Cuda(self.device.get_device_id() as usize)
     }
 
     fn get_features(&self) -> DeviceFeatures {
@@ -52,277 +285,120 @@ impl Device for CudaBackend {
 
 impl Backend for CudaBackend {
     fn device(&self) -> DeviceType {
-        DeviceType::Cuda
+        DeviceType::Cuda(self.device.get_device_id() as usize)
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        self.device.capabilities()
     }
 
     fn execute_compute(&self, _dimensions: [u32; 3]) -> MlResult<()> {
-        self.device
-            .synchronize()
-            .map_err(|e| format!("CUDA compute synchronization failed: {}", e))?;
-        Ok(())
+        self.synchronize()
     }
 
     fn add(&self, a: &[f32], b: &[f32]) -> Vec<f32> {
-        let size = a.len();
-        let mut result = vec![0.0; size];
-
-        let mut a_buf = match CudaBuffer::new(size) {
-            Ok(buf) => buf,
-            Err(_) => return vec![0.0; size],
-        };
-        let mut b_buf = match CudaBuffer::new(size) {
-            Ok(buf) => buf,
-            Err(_) => return vec![0.0; size],
-        };
-        let mut result_buf = match CudaBuffer::new(size) {
-            Ok(buf) => buf,
-            Err(_) => return vec![0.0; size],
-        };
-
-        if a_buf.copy_from_host(a).is_err()
-            || b_buf.copy_from_host(b).is_err()
-            || vector_add(&a_buf, &b_buf, &mut result_buf).is_err()
-            || result_buf.copy_to_host(&mut result).is_err()
-        {
-            return vec![0.0; size];
-        }
-
-        result
+        self.run_on_stream(&[a, b], a.len(), |inputs, result, stream| {
+            vector_add_async(&inputs[0], &inputs[1], result, stream)
+        })
+        .unwrap_or_else(|_| vec![0.0; a.len()])
     }
 
     fn multiply(&self, a: &[f32], b: &[f32]) -> Vec<f32> {
-        let size = a.len();
-        let mut result = vec![0.0; size];
-
-        let mut a_buf = match CudaBuffer::new(size) {
-            Ok(buf) => buf,
-            Err(_) => return vec![0.0; size],
-        };
-        let mut b_buf = match CudaBuffer::new(size) {
-            Ok(buf) => buf,
-            Err(_) => return vec![0.0; size],
-        };
-        let mut result_buf = match CudaBuffer::new(size) {
-            Ok(buf) => buf,
-            Err(_) => return vec![0.0; size],
-        };
-
-        if a_buf.copy_from_host(a).is_err()
-            || b_buf.copy_from_host(b).is_err()
-            || vector_multiply(&a_buf, &b_buf, &mut result_buf).is_err()
-            || result_buf.copy_to_host(&mut result).is_err()
-        {
-            return vec![0.0; size];
-        }
-
-        result
+        self.run_on_stream(&[a, b], a.len(), |inputs, result, stream| {
+            vector_multiply_async(&inputs[0], &inputs[1], result, stream)
+        })
+        .unwrap_or_else(|_| vec![0.0; a.len()])
     }
 
     fn matmul(&self, a: &[f32], b: &[f32], m: usize, n: usize, k: usize) -> Vec<f32> {
-        let size = m * n;
-        let mut result = vec![0.0; size];
-
-        let mut a_buf = match CudaBuffer::new(m * k) {
-            Ok(buf) => buf,
-            Err(_) => return vec![0.0; size],
-        };
-        let mut b_buf = match CudaBuffer::new(k * n) {
-            Ok(buf) => buf,
-            Err(_) => return vec![0.0; size],
-        };
-        let mut result_buf = match CudaBuffer::new(size) {
-            Ok(buf) => buf,
-            Err(_) => return vec![0.0; size],
-        };
-
-        if a_buf.copy_from_host(a).is_err()
-            || b_buf.copy_from_host(b).is_err()
-            || matrix_multiply(&a_buf, &b_buf, &mut result_buf, m, n, k).is_err()
-            || result_buf.copy_to_host(&mut result).is_err()
-        {
-            return vec![0.0; size];
+        if a.len() != m * n || b.len() != n * k {
+            return vec![0.0; m * k];
         }
 
-        result
+        self.run_on_stream(&[a, b], m * k, |inputs, result, _| {
+            self.gemm(&inputs[0], &inputs[1], result, m, n, k)
+        })
+        .unwrap_or_else(|_| vec![0.0; m * k])
     }
 
-    fn div(&self, a: &[f32], b: &[f32]) -> Vec<f32> {
-        if a.len() != b.len() {
-            return vec![0.0; a.len()];
+    fn matmul_i8(&self, a: &[i8], b: &[i8], m: usize, n: usize, k: usize) -> Vec<i32> {
+        if a.len() != m * n || b.len() != n * k {
+            return vec![0; m * k];
+        }
+        if m * n * k == 0 {
+            return matmul_i8(a, b, m, n, k);
         }
 
-        let size = a.len();
-        let mut result = vec![0.0; size];
-
-        let mut a_buf = match CudaBuffer::new(size) {
-            Ok(buf) => buf,
-            Err(_) => return vec![0.0; size],
-        };
-        let mut b_buf = match CudaBuffer::new(size) {
-            Ok(buf) => buf,
-            Err(_) => return vec![0.0; size],
-        };
-        let mut result_buf = match CudaBuffer::new(size) {
-            Ok(buf) => buf,
-            Err(_) => return vec![0.0; size],
-        };
-
-        if a_buf.copy_from_host(a).is_err()
-            || b_buf.copy_from_host(b).is_err()
-            || vector_divide(&a_buf, &b_buf, &mut result_buf).is_err()
-            || result_buf.copy_to_host(&mut result).is_err()
-        {
-            return vec![0.0; size];
+        let mut b_trans = vec![0; n * k];
+        for j in 0..k {
+            for i in 0..n {
+                b_trans[j * n + i] = b[i * k + j];
+            }
         }
+        let packed_a = pack_i8_rows(a, m, n);
+        let packed_bt = pack_i8_rows(&b_trans, k, n);
+
+        self.run_on_stream(&[&packed_a, &packed_bt], m * k, |inputs, result, stream| {
+            matrix_multiply_i8_async(&inputs[0], &inputs[1], result, m, n, k, stream)
+        })
+        .map(|bits| bits.iter().map(|value| value.to_bits() as i32).collect())
+        .unwrap_or_else(|_| matmul_i8(a, b, m, n, k))
+    }
 
-        result
+    fn div(&self, a: &[f32], b: &[f32]) -> Vec<f32> {
+        self.run_on_stream(&[a, b], a.len(), |inputs, result, stream| {
+            vector_divide_async(&inputs[0], &inputs[1], result, stream)
+        })
+        .unwrap_or_else(|_| vec![0.0; a.len()])
     }
 
     fn sub(&self, a: &[f32], b: &[f32]) -> Vec<f32> {
-        if a.len() != b.len() {
-            return vec![0.0; a.len()];
-        }
-
-        let size = a.len();
-        let mut result = vec![0.0; size];
-
-        let mut a_buf = match CudaBuffer::new(size) {
-            Ok(buf) => buf,
-            Err(_) => return vec![0.0; size],
-        };
-        let mut b_buf = match CudaBuffer::new(size) {
-            Ok(buf) => buf,
-            Err(_) => return vec![0.0; size],
-        };
-        let mut result_buf = match CudaBuffer::new(size) {
-            Ok(buf) => buf,
-            Err(_) => return vec![0.0; size],
-        };
-
-        if a_buf.copy_from_host(a).is_err()
-            || b_buf.copy_from_host(b).is_err()
-            || vector_subtract(&a_buf, &b_buf, &mut result_buf).is_err()
-            || result_buf.copy_to_host(&mut result).is_err()
-        {
-            return vec![0.0; size];
-        }
-
-        result
+        self.run_on_stream(&[a, b], a.len(), |inputs, result, stream| {
+            vector_subtract_async(&inputs[0], &inputs[1], result, stream)
+        })
+        .unwrap_or_else(|_| vec![0.0; a.len()])
     }
 
     fn exp(&self, a: &[f32]) -> Vec<f32> {
-        let size = a.len();
-        let mut result = vec![0.0; size];
-
-        let mut a_buf = match CudaBuffer::new(size) {
-            Ok(buf) => buf,
-            Err(_) => return vec![0.0; size],
-        };
-        let mut result_buf = match CudaBuffer::new(size) {
-            Ok(buf) => buf,
-            Err(_) => return vec![0.0; size],
-        };
-
-        if a_buf.copy_from_host(a).is_err()
-            || vector_exp(&a_buf, &mut result_buf).is_err()
-            || result_buf.copy_to_host(&mut result).is_err()
-        {
-            return vec![0.0; size];
-        }
-
-        result
+        self.run_on_stream(&[a], a.len(), |inputs, result, stream| {
+            vector_exp_async(&inputs[0], result, stream)
+        })
+        .unwrap_or_else(|_| vec![0.0; a.len()])
     }
 
     fn log(&self, a: &[f32]) -> Vec<f32> {
-        let size = a.len();
-        let mut result = vec![0.0; size];
-
-        let mut a_buf = match CudaBuffer::new(size) {
-            Ok(buf) => buf,
-            Err(_) => return vec![0.0; size],
-        };
-        let mut result_buf = match CudaBuffer::new(size) {
-            Ok(buf) => buf,
-            Err(_) => return vec![0.0; size],
-        };
-
-        if a_buf.copy_from_host(a).is_err()
-            || vector_log(&a_buf, &mut result_buf).is_err()
-            || result_buf.copy_to_host(&mut result).is_err()
-        {
-            return vec![0.0; size];
-        }
-
-        result
+        self.run_on_stream(&[a], a.len(), |inputs, result, stream| {
+            vector_log_async(&inputs[0], result, stream)
+        })
+        .unwrap_or_else(|_| vec![0.0; a.len()])
     }
 
     fn pow(&self, a: &[f32], power: f32) -> Vec<f32> {
-        let size = a.len();
-        let mut result = vec![0.0; size];
-
-        let mut a_buf = match CudaBuffer::new(size) {
-            Ok(buf) => buf,
-            Err(_) => return vec![0.0; size],
-        };
-        let mut result_buf = match CudaBuffer::new(size) {
-            Ok(buf) => buf,
-            Err(_) => return vec![0.0; size],
-        };
-
-        if a_buf.copy_from_host(a).is_err()
-            || vector_pow(&a_buf, power, &mut result_buf).is_err()
-            || result_buf.copy_to_host(&mut result).is_err()
-        {
-            return vec![0.0; size];
-        }
-
-        result
+        self.run_on_stream(&[a], a.len(), |inputs, result, stream| {
+            vector_pow_async(&inputs[0], power, result, stream)
+        })
+        .unwrap_or_else(|_| vec![0.0; a.len()])
     }
 
     fn sqrt(&self, a: &[f32]) -> Vec<f32> {
-        let size = a.len();
-        let mut result = vec![0.0; size];
-
-        let mut a_buf = match CudaBuffer::new(size) {
-            Ok(buf) => buf,
-            Err(_) => return vec![0.0; size],
-        };
-        let mut result_buf = match CudaBuffer::new(size) {
-            Ok(buf) => buf,
-            Err(_) => return vec![0.0; size],
-        };
-
-        if a_buf.copy_from_host(a).is_err()
-            || vector_sqrt(&a_buf, &mut result_buf).is_err()
-            || result_buf.copy_to_host(&mut result).is_err()
-        {
-            return vec![0.0; size];
-        }
-
-        result
+        self.run_on_stream(&[a], a.len(), |inputs, result, stream| {
+            vector_sqrt_async(&inputs[0], result, stream)
+        })
+        .unwrap_or_else(|_| vec![0.0; a.len()])
     }
 
     fn sum(&self, a: &[f32]) -> f32 {
-        let size = a.len();
-        let mut partial_sums = vec![0.0; 1];
-
-        let mut a_buf = match CudaBuffer::new(size) {
-            Ok(buf) => buf,
-            Err(_) => return 0.0,
-        };
-        let mut result_buf = match CudaBuffer::new(1) {
-            Ok(buf) => buf,
-            Err(_) => return 0.0,
-        };
-
-        if a_buf.copy_from_host(a).is_err()
-            || vector_reduce_sum(&a_buf, &mut result_buf).is_err()
-            || result_buf.copy_to_host(&mut partial_sums).is_err()
-        {
+        if a.is_empty() {
             return 0.0;
         }
 
-        partial_sums[0]
+        // One partial sum per block, combined on the host
+        let num_blocks = LaunchConfig::linear(a.len()).grid_dim.x as usize;
+        self.run_on_stream(&[a], num_blocks, |inputs, partials, stream| {
+            vector_reduce_sum_async(&inputs[0], partials, stream)
+        })
+        .map(|partials| partials.iter().sum())
+        .unwrap_or(0.0)
     }
 
     fn mean(&self, a: &[f32]) -> f32 {
@@ -332,11 +408,129 @@ impl Backend for CudaBackend {
         let sum = self.sum(a);
         sum / a.len() as f32
     }
+
+    fn reduce(&self, op: ReduceOp, a: &[f32], shape: &[usize], axis: usize) -> Vec<f32> {
+        let (outer, len, inner) = split_axis(shape, axis);
+        if a.is_empty() || outer * inner == 0 {
+            return op.apply(a, outer, len, inner);
+        }
+
+        self.run_on_stream(&[a], outer * inner, |inputs, result, stream| {
+            reduce_axis_async(&inputs[0], result, op, outer, len, inner, stream)
+        })
+        .unwrap_or_else(|_| op.apply(a, outer, len, inner))
+    }
+
+    fn conv2d(&self, input: &[f32], weight: &[f32], shape: &Conv2dShape) -> Vec<f32> {
+        if input.is_empty() || weight.is_empty() || shape.output_len() == 0 {
+            return conv2d_im2col(self, input, weight, shape);
+        }
+
+        self.run_on_stream(&[input, weight], shape.output_len(), |inputs, output, _| {
+            self.queue_conv2d(&inputs[0], &inputs[1], output, shape)
+        })
+        .unwrap_or_else(|_| conv2d_im2col(self, input, weight, shape))
+    }
+
+    fn upload(&self, data: &[f32]) -> Option<Arc<dyn DeviceBuffer>> {
+        if data.is_empty() {
+            return None;
+        }
+
+        self.activate().ok()?;
+        let mut buffer = CudaBuffer::from_pool(data.len(), &self.stream).ok()?;
+        // `data` is only borrowed, so the copy has to land before returning
+        let queued = unsafe { buffer.copy_from_host_async(data, &self.stream) };
+        let synced = self.stream.synchronize();
+        queued.and(synced).ok()?;
+
+        Some(Arc::new(buffer))
+    }
+
+    fn execute_on_device(
+        &self,
+        op: DeviceOp,
+        inputs: &[&dyn DeviceBuffer],
+    ) -> Option<Arc<dyn DeviceBuffer>> {
+        if inputs.len() != op.arity() {
+            return None;
+        }
+
+        let buffers = inputs
+            .iter()
+            .map(|buffer| buffer.as_any().downcast_ref::<CudaBuffer>())
+            .collect::<Option<Vec<_>>>()?;
+        let device_id = self.device.get_device_id();
+        if buffers[0].is_empty() || buffers.iter().any(|buffer| buffer.device() != device_id) {
+            return None;
+        }
+
+        match op {
+            DeviceOp::MatMul { m, n, k }
+                if buffers[0].len() != m * n || buffers[1].len() != n * k =>
+            {
+                return None
+            }
+            DeviceOp::Add | DeviceOp::Sub | DeviceOp::Mul | DeviceOp::Div
+                if buffers[0].len() != buffers[1].len() =>
+            {
+                return None
+            }
+            DeviceOp::Reduce {
+                outer, axis, inner, ..
+            } if buffers[0].len() != outer * axis * inner || outer * inner == 0 => return None,
+            DeviceOp::Conv2d(shape)
+                if buffers[0].len() != shape.input.iter().product::<usize>()
+                    || buffers[1].len() != shape.weight.iter().product::<usize>()
+                    || shape.output_len() == 0 =>
+            {
+                return None
+            }
+            _ => {}
+        }
+
+        let result = self.queue_on_device(op, &buffers).ok()?;
+        Some(Arc::new(result))
+    }
+
+    fn memory_stats(&self) -> Option<MemoryStats> {
+        Some(pool::memory_stats(self.device.get_device_id()))
+    }
+
+    fn empty_cache(&self) -> MlResult<()> {
+        pool::empty_cache(self.device.get_device_id())
+            .map_err(|e| format!("Failed to empty the CUDA cache: {}", e))?;
+        Ok(())
+    }
+
+    fn execute_fused(
+        &self,
+        ops: &[FusedOp],
+        inputs: &[&dyn DeviceBuffer],
+    ) -> Option<Arc<dyn DeviceBuffer>> {
+        let buffers = inputs
+            .iter()
+            .map(|buffer| buffer.as_any().downcast_ref::<CudaBuffer>())
+            .collect::<Option<Vec<_>>>()?;
+        let device_id = self.device.get_device_id();
+        if buffers.is_empty()
+            || buffers[0].is_empty()
+            || buffers.iter().any(|buffer| buffer.device() != device_id)
+        {
+            return None;
+        }
+
+        self.activate().ok()?;
+        let mut result = CudaBuffer::from_pool(buffers[0].len(), &self.stream).ok()?;
+        fused_elementwise_async(&buffers, ops, &mut result, &self.stream).ok()?;
+        Some(Arc::new(result))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::backend::cuda::PinnedBuffer;
 
     #[test]
     fn test_basic_operations() -> Result<(), Box<dyn std::error::Error>> {
@@ -383,4 +577,204 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_cuda_matmul_non_square() -> Result<(), Box<dyn std::error::Error>> {
+        let backend = CudaBackend::new()?;
+
+        // 2x3 * 3x2 matrices
+        let a = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let b = vec![7.0, 8.0, 9.0, 10.0, 11.0, 12.0];
+
+        let result = backend.matmul(&a, &b, 2, 3, 2);
+        assert_eq!(result, vec![58.0, 64.0, 139.0, 154.0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cuda_streams_run_independently() -> Result<(), Box<dyn std::error::Error>> {
+        let _backend = CudaBackend::new()?;
+        let first = CudaStream::new()?;
+        let second = CudaStream::new()?;
+
+        let a = vec![1.0f32, 2.0, 3.0];
+        let b = vec![4.0f32, 5.0, 6.0];
+        let mut a_buf = CudaBuffer::new(3)?;
+        let mut b_buf = CudaBuffer::new(3)?;
+        let mut sum_buf = CudaBuffer::new(3)?;
+        let mut product_buf = CudaBuffer::new(3)?;
+
+        a_buf.copy_from_host(&a)?;
+        b_buf.copy_from_host(&b)?;
+
+        // Independent ops queued on separate streams
+        vector_add_async(&a_buf, &b_buf, &mut sum_buf, &first)?;
+        vector_multiply_async(&a_buf, &b_buf, &mut product_buf, &second)?;
+
+        let mut sum = vec![0.0; 3];
+        let mut product = vec![0.0; 3];
+        unsafe {
+            sum_buf.copy_to_host_async(&mut sum, &first)?;
+            product_buf.copy_to_host_async(&mut product, &second)?;
+        }
+        first.synchronize()?;
+        second.synchronize()?;
+
+        assert!(first.is_idle()?);
+        assert_eq!(sum, vec![5.0, 7.0, 9.0]);
+        assert_eq!(product, vec![4.0, 10.0, 18.0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cuda_large_sum() -> Result<(), Box<dyn std::error::Error>> {
+        let backend = CudaBackend::new()?;
+
+        // Spans several blocks of the reduction kernel
+        let a = vec![1.0f32; 10_000];
+        assert_eq!(backend.sum(&a), 10_000.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cuda_reduce_along_axis() -> Result<(), Box<dyn std::error::Error>> {
+        let backend = CudaBackend::new()?;
+        let a: Vec<f32> = (0..12).map(|x| x as f32).collect();
+
+        for op in [ReduceOp::Sum, ReduceOp::Mean, ReduceOp::Max, ReduceOp::Min] {
+            for axis in 0..3 {
+                let (outer, len, inner) = split_axis(&[2, 3, 2], axis);
+                let expected = op.apply(&a, outer, len, inner);
+                assert_eq!(backend.reduce(op, &a, &[2, 3, 2], axis), expected);
+
+                let input = backend.upload(&a).ok_or("upload failed")?;
+                let op = DeviceOp::Reduce {
+                    op,
+                    outer,
+                    axis: len,
+                    inner,
+                };
+                let result = backend
+                    .execute_on_device(op, &[input.as_ref()])
+                    .ok_or("reduce failed")?;
+                assert_eq!(result.to_host()?, expected);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cuda_ops_stay_on_device() -> Result<(), Box<dyn std::error::Error>> {
+        let backend = CudaBackend::new()?;
+        let a = backend.upload(&[1.0, 2.0, 3.0]).ok_or("upload failed")?;
+        let b = backend.upload(&[4.0, 5.0, 6.0]).ok_or("upload failed")?;
+
+        let sum = backend
+            .execute_on_device(DeviceOp::Add, &[a.as_ref(), b.as_ref()])
+            .ok_or("add failed")?;
+        let product = backend
+            .execute_on_device(DeviceOp::Mul, &[sum.as_ref(), b.as_ref()])
+            .ok_or("mul failed")?;
+        let total = backend
+            .execute_on_device(DeviceOp::Sum, &[product.as_ref()])
+            .ok_or("sum failed")?;
+
+        assert_eq!(product.to_host()?, vec![20.0, 35.0, 54.0]);
+        assert_eq!(total.to_host()?, vec![109.0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cuda_fused_elementwise() -> Result<(), Box<dyn std::error::Error>> {
+        let backend = CudaBackend::new()?;
+        let a = backend.upload(&[1.0, 2.0, 3.0]).ok_or("upload failed")?;
+        let b = backend.upload(&[4.0, 5.0, 6.0]).ok_or("upload failed")?;
+
+        // (a + b) * b - 1
+        let ops = [FusedOp::Add(1), FusedOp::Mul(1), FusedOp::AddScalar(-1.0)];
+        let result = backend
+            .execute_fused(&ops, &[a.as_ref(), b.as_ref()])
+            .ok_or("fused kernel failed")?;
+
+        assert_eq!(result.to_host()?, vec![19.0, 34.0, 53.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cuda_pinned_transfers_overlap_compute() -> Result<(), Box<dyn std::error::Error>> {
+        let _backend = CudaBackend::new()?;
+        let copy_stream = CudaStream::new()?;
+        let compute_stream = CudaStream::new()?;
+
+        let host_a = PinnedBuffer::from_slice(&[1.0, 2.0, 3.0])?;
+        let host_b = PinnedBuffer::from_slice(&[4.0, 5.0, 6.0])?;
+        let mut a_buf = CudaBuffer::new(3)?;
+        let mut b_buf = CudaBuffer::new(3)?;
+        let mut sum_buf = CudaBuffer::new(3)?;
+
+        // Compute waits on the copies through events, not on the host
+        let upload_a = a_buf.upload_async(&host_a, &copy_stream)?;
+        let upload_b = b_buf.upload_async(&host_b, &copy_stream)?;
+        compute_stream.wait_event(upload_a.event())?;
+        compute_stream.wait_event(upload_b.event())?;
+        vector_add_async(&a_buf, &b_buf, &mut sum_buf, &compute_stream)?;
+
+        let mut result = PinnedBuffer::new(3)?;
+        sum_buf
+            .download_async(&mut result, &compute_stream)?
+            .wait()?;
+        upload_a.wait()?;
+        upload_b.wait()?;
+
+        assert_eq!(&result[..], &[5.0, 7.0, 9.0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cuda_matmul_i8() -> Result<(), Box<dyn std::error::Error>> {
+        let backend = CudaBackend::new()?;
+
+        // n = 7 leaves a partially filled word at the end of every packed row
+        let a: Vec<i8> = (0..35).map(|x| (x * 37 % 256 - 128) as i8).collect();
+        let b: Vec<i8> = (0..63).map(|x| (x * 53 % 256 - 128) as i8).collect();
+        assert_eq!(
+            backend.matmul_i8(&a, &b, 5, 7, 9),
+            matmul_i8(&a, &b, 5, 7, 9)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cuda_conv2d() -> Result<(), Box<dyn std::error::Error>> {
+        let backend = CudaBackend::new()?;
+
+        // 1x1x3x3 input, single 2x2 kernel
+        let input = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0];
+        let weight = vec![1.0, 0.0, 0.0, 1.0];
+        let shape = Conv2dShape::new([1, 1, 3, 3], [1, 1, 2, 2], (1, 1), (0, 0))?;
+
+        assert_eq!(shape.output(), [1, 1, 2, 2]);
+        assert_eq!(
+            backend.conv2d(&input, &weight, &shape),
+            vec![6.0, 8.0, 12.0, 14.0]
+        );
+
+        // The bundled kernel, with padding
+        let padded = Conv2dShape::new([1, 1, 3, 3], [1, 1, 2, 2], (2, 2), (1, 1))?;
+        let output = backend.run_on_stream(
+            &[&input, &weight],
+            padded.output_len(),
+            |inputs, output, stream| conv2d_async(&inputs[0], &inputs[1], output, &padded, stream),
+        )?;
+        assert_eq!(output, conv2d_im2col(&backend, &input, &weight, &padded));
+
+        Ok(())
+    }
 }
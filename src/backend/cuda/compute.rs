@@ -1,5 +1,13 @@
-use super::CudaError;
+use super::core::{current_device, synchronize_device};
+use super::launch::{launch, LaunchConfig};
+use super::pool;
+use super::{CudaError, CudaStream};
+use crate::backend::{Conv2dShape, DeviceBuffer, FusedOp, ReduceOp};
+use crate::MlResult;
+use std::any::Any;
+use std::ffi::c_void;
 use std::ptr::null_mut;
+use std::sync::Arc;
 
 #[link(name = "cuda")]
 extern "C" {
@@ -11,8 +19,58 @@ extern "C" {
         count: usize,
         kind: cudaMemcpyKind,
     ) -> i32;
-    fn cudaMemset(ptr: *mut std::ffi::c_void, value: i32, count: usize) -> i32;
-    fn cudaDeviceSynchronize() -> i32;
+    fn cudaMemcpyAsync(
+        dst: *mut std::ffi::c_void,
+        src: *const std::ffi::c_void,
+        count: usize,
+        kind: cudaMemcpyKind,
+        stream: *mut std::ffi::c_void,
+    ) -> i32;
+}
+
+// Kernels from cuda/kernels.cu. They are never called directly: their host-side symbols
+// are handed to cudaLaunchKernel together with a launch configuration and stream.
+extern "C" {
+    fn vector_add_kernel(result: *mut f32, a: *const f32, b: *const f32, n: i32);
+    fn vector_multiply_kernel(result: *mut f32, a: *const f32, b: *const f32, n: i32);
+    fn vector_sub_kernel(result: *mut f32, a: *const f32, b: *const f32, n: i32);
+    fn vector_div_kernel(result: *mut f32, a: *const f32, b: *const f32, n: i32);
+    fn vector_exp_kernel(result: *mut f32, input: *const f32, n: i32);
+    fn vector_log_kernel(result: *mut f32, input: *const f32, n: i32);
+    fn vector_sqrt_kernel(result: *mut f32, input: *const f32, n: i32);
+    fn vector_pow_kernel(result: *mut f32, input: *const f32, power: f32, n: i32);
+    fn vector_reduce_sum_kernel(result: *mut f32, input: *const f32, n: i32);
+    fn matrix_multiply_kernel(
+        result: *mut f32,
+        a: *const f32,
+        b: *const f32,
+        m: i32,
+        n: i32,
+        k: i32,
+    );
+    fn matrix_multiply_i8_kernel(
+        result: *mut i32,
+        a: *const i32,
+        bt: *const i32,
+        m: i32,
+        words: i32,
+        k: i32,
+    );
+    fn fused_elementwise_kernel(result: *mut f32, program: FusedProgram, n: i32);
+    fn reduce_axis_kernel(
+        result: *mut f32,
+        input: *const f32,
+        outer: i32,
+        axis: i32,
+        inner: i32,
+        op: i32,
+    );
+    fn conv2d_kernel(
+        result: *mut f32,
+        input: *const f32,
+        weight: *const f32,
+        geometry: Conv2dGeometry,
+    );
 }
 
 #[repr(C)]
@@ -28,10 +86,29 @@ const CUDA_SUCCESS: i32 = 0;
 pub struct CudaBuffer {
     ptr: *mut f32,
     size: usize,
+    device: i32,
+    pooled: Option<Pooled>,
+}
+
+// Where a pooled buffer's block goes back to when it is dropped
+struct Pooled {
+    bytes: usize,
+    stream: Arc<CudaStream>,
+}
+
+// Device pointers are valid from any host thread in the process
+unsafe impl Send for CudaBuffer {}
+unsafe impl Sync for CudaBuffer {}
+
+impl std::fmt::Debug for CudaBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CudaBuffer({} floats)", self.size)
+    }
 }
 
 impl CudaBuffer {
     pub fn new(size: usize) -> Result<Self, CudaError> {
+        let device = current_device()?;
         let mut ptr: *mut f32 = null_mut();
         unsafe {
             let result = cudaMalloc(
@@ -44,7 +121,54 @@ impl CudaBuffer {
                 ));
             }
         }
-        Ok(CudaBuffer { ptr, size })
+        Ok(CudaBuffer {
+            ptr,
+            size,
+            device,
+            pooled: None,
+        })
+    }
+
+    /// Allocates a buffer from the device's caching allocator. When dropped, its memory is
+    /// kept for reuse once the work queued on `stream` up to that point has completed, so
+    /// any work queued on other streams must be synchronized before the buffer is dropped.
+    pub fn from_pool(size: usize, stream: &Arc<CudaStream>) -> Result<Self, CudaError> {
+        if size == 0 {
+            return Self::new(0);
+        }
+
+        let device = current_device()?;
+        let (ptr, bytes) = pool::allocate(size * std::mem::size_of::<f32>())?;
+        Ok(CudaBuffer {
+            ptr,
+            size,
+            device,
+            pooled: Some(Pooled {
+                bytes,
+                stream: stream.clone(),
+            }),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    /// The device the buffer was allocated on.
+    pub fn device(&self) -> i32 {
+        self.device
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub fn as_ptr(&self) -> *const f32 {
+        self.ptr
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut f32 {
+        self.ptr
     }
 
     pub fn copy_from_host(&mut self, data: &[f32]) -> Result<(), CudaError> {
@@ -82,233 +206,547 @@ impl CudaBuffer {
         }
         Ok(())
     }
+
+    /// Queues a host-to-device copy on `stream`.
+    ///
+    /// # Safety
+    /// `data` must stay alive and unmodified until `stream` has been synchronized.
+    pub unsafe fn copy_from_host_async(
+        &mut self,
+        data: &[f32],
+        stream: &CudaStream,
+    ) -> Result<(), CudaError> {
+        if data.len() > self.size {
+            return Err(CudaError::InvalidValue);
+        }
+        let result = cudaMemcpyAsync(
+            self.ptr as *mut std::ffi::c_void,
+            data.as_ptr() as *const std::ffi::c_void,
+            std::mem::size_of_val(data),
+            cudaMemcpyKind::HostToDevice,
+            stream.as_raw(),
+        );
+        if result != CUDA_SUCCESS {
+            return Err(CudaError::Other("Failed to queue copy to device".into()));
+        }
+        Ok(())
+    }
+
+    /// Queues a device-to-host copy on `stream`.
+    ///
+    /// # Safety
+    /// `data` must stay alive and must not be read until `stream` has been synchronized.
+    pub unsafe fn copy_to_host_async(
+        &self,
+        data: &mut [f32],
+        stream: &CudaStream,
+    ) -> Result<(), CudaError> {
+        if data.len() > self.size {
+            return Err(CudaError::InvalidValue);
+        }
+        let result = cudaMemcpyAsync(
+            data.as_mut_ptr() as *mut std::ffi::c_void,
+            self.ptr as *const std::ffi::c_void,
+            std::mem::size_of_val(data),
+            cudaMemcpyKind::DeviceToHost,
+            stream.as_raw(),
+        );
+        if result != CUDA_SUCCESS {
+            return Err(CudaError::Other("Failed to queue copy from device".into()));
+        }
+        Ok(())
+    }
+}
+
+impl DeviceBuffer for CudaBuffer {
+    fn len(&self) -> usize {
+        self.size
+    }
+
+    fn to_host(&self) -> MlResult<Vec<f32>> {
+        // The buffer may still be written by work queued on any of its device's streams
+        synchronize_device(self.device).map_err(|e| e.to_string())?;
+
+        let mut data = vec![0.0; self.size];
+        self.copy_to_host(&mut data).map_err(|e| e.to_string())?;
+        Ok(data)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 
 impl Drop for CudaBuffer {
     fn drop(&mut self) {
-        unsafe {
-            cudaFree(self.ptr as *mut std::ffi::c_void);
+        match &self.pooled {
+            Some(pooled) => pool::release(self.device, self.ptr, pooled.bytes, &pooled.stream),
+            None => unsafe {
+                cudaFree(self.ptr as *mut std::ffi::c_void);
+            },
         }
     }
 }
 
-pub fn vector_add(
+fn arg<T>(value: &mut T) -> *mut c_void {
+    value as *mut T as *mut c_void
+}
+
+fn launch_binary(
+    kernel: *const c_void,
     a: &CudaBuffer,
     b: &CudaBuffer,
     result: &mut CudaBuffer,
+    stream: &CudaStream,
 ) -> Result<(), CudaError> {
     if a.size != b.size || a.size != result.size {
         return Err(CudaError::InvalidValue);
     }
 
+    let mut result_ptr = result.ptr;
+    let mut a_ptr = a.ptr as *const f32;
+    let mut b_ptr = b.ptr as *const f32;
+    let mut n = a.size as i32;
     unsafe {
-        extern "C" {
-            fn vector_add_kernel(result: *mut f32, a: *const f32, b: *const f32, n: i32);
-        }
-
-        vector_add_kernel(result.ptr, a.ptr, b.ptr, a.size as i32);
-        let sync_result = cudaDeviceSynchronize();
-        if sync_result != CUDA_SUCCESS {
-            return Err(CudaError::Synchronization(
-                "Failed to synchronize device".into(),
-            ));
-        }
+        launch(
+            kernel,
+            LaunchConfig::linear(a.size),
+            &mut [
+                arg(&mut result_ptr),
+                arg(&mut a_ptr),
+                arg(&mut b_ptr),
+                arg(&mut n),
+            ],
+            stream,
+        )
     }
-    Ok(())
 }
 
-pub fn vector_multiply(
-    a: &CudaBuffer,
-    b: &CudaBuffer,
+fn launch_unary(
+    kernel: *const c_void,
+    input: &CudaBuffer,
     result: &mut CudaBuffer,
+    stream: &CudaStream,
 ) -> Result<(), CudaError> {
-    if a.size != b.size || a.size != result.size {
+    if input.size != result.size {
         return Err(CudaError::InvalidValue);
     }
 
+    let mut result_ptr = result.ptr;
+    let mut input_ptr = input.ptr as *const f32;
+    let mut n = input.size as i32;
     unsafe {
-        extern "C" {
-            fn vector_multiply_kernel(result: *mut f32, a: *const f32, b: *const f32, n: i32);
-        }
-
-        vector_multiply_kernel(result.ptr, a.ptr, b.ptr, a.size as i32);
-        let sync_result = cudaDeviceSynchronize();
-        if sync_result != CUDA_SUCCESS {
-            return Err(CudaError::Synchronization(
-                "Failed to synchronize device".into(),
-            ));
-        }
+        launch(
+            kernel,
+            LaunchConfig::linear(input.size),
+            &mut [arg(&mut result_ptr), arg(&mut input_ptr), arg(&mut n)],
+            stream,
+        )
     }
-    Ok(())
 }
 
-pub fn vector_reduce_sum(input: &CudaBuffer, result: &mut CudaBuffer) -> Result<(), CudaError> {
-    unsafe {
-        extern "C" {
-            fn vector_reduce_sum_kernel(result: *mut f32, input: *const f32, n: i32);
-        }
-
-        vector_reduce_sum_kernel(result.ptr, input.ptr, input.size as i32);
-        let sync_result = cudaDeviceSynchronize();
-        if sync_result != CUDA_SUCCESS {
-            return Err(CudaError::Synchronization(
-                "Failed to synchronize device".into(),
-            ));
-        }
-    }
-    Ok(())
+pub fn vector_add_async(
+    a: &CudaBuffer,
+    b: &CudaBuffer,
+    result: &mut CudaBuffer,
+    stream: &CudaStream,
+) -> Result<(), CudaError> {
+    launch_binary(vector_add_kernel as *const c_void, a, b, result, stream)
 }
 
-pub fn vector_exp(input: &CudaBuffer, result: &mut CudaBuffer) -> Result<(), CudaError> {
-    unsafe {
-        extern "C" {
-            fn vector_exp_kernel(result: *mut f32, input: *const f32, n: i32);
-        }
+pub fn vector_multiply_async(
+    a: &CudaBuffer,
+    b: &CudaBuffer,
+    result: &mut CudaBuffer,
+    stream: &CudaStream,
+) -> Result<(), CudaError> {
+    launch_binary(
+        vector_multiply_kernel as *const c_void,
+        a,
+        b,
+        result,
+        stream,
+    )
+}
 
-        vector_exp_kernel(result.ptr, input.ptr, input.size as i32);
-        let sync_result = cudaDeviceSynchronize();
-        if sync_result != CUDA_SUCCESS {
-            return Err(CudaError::Synchronization(
-                "Failed to synchronize device".into(),
-            ));
-        }
-    }
-    Ok(())
+pub fn vector_subtract_async(
+    a: &CudaBuffer,
+    b: &CudaBuffer,
+    result: &mut CudaBuffer,
+    stream: &CudaStream,
+) -> Result<(), CudaError> {
+    launch_binary(vector_sub_kernel as *const c_void, a, b, result, stream)
 }
 
-pub fn vector_log(input: &CudaBuffer, result: &mut CudaBuffer) -> Result<(), CudaError> {
-    unsafe {
-        extern "C" {
-            fn vector_log_kernel(result: *mut f32, input: *const f32, n: i32);
-        }
+pub fn vector_divide_async(
+    a: &CudaBuffer,
+    b: &CudaBuffer,
+    result: &mut CudaBuffer,
+    stream: &CudaStream,
+) -> Result<(), CudaError> {
+    launch_binary(vector_div_kernel as *const c_void, a, b, result, stream)
+}
 
-        vector_log_kernel(result.ptr, input.ptr, input.size as i32);
-        let sync_result = cudaDeviceSynchronize();
-        if sync_result != CUDA_SUCCESS {
-            return Err(CudaError::Synchronization(
-                "Failed to synchronize device".into(),
-            ));
-        }
-    }
-    Ok(())
+pub fn vector_exp_async(
+    input: &CudaBuffer,
+    result: &mut CudaBuffer,
+    stream: &CudaStream,
+) -> Result<(), CudaError> {
+    launch_unary(vector_exp_kernel as *const c_void, input, result, stream)
 }
 
-pub fn vector_sqrt(input: &CudaBuffer, result: &mut CudaBuffer) -> Result<(), CudaError> {
-    unsafe {
-        extern "C" {
-            fn vector_sqrt_kernel(result: *mut f32, input: *const f32, n: i32);
-        }
+pub fn vector_log_async(
+    input: &CudaBuffer,
+    result: &mut CudaBuffer,
+    stream: &CudaStream,
+) -> Result<(), CudaError> {
+    launch_unary(vector_log_kernel as *const c_void, input, result, stream)
+}
 
-        vector_sqrt_kernel(result.ptr, input.ptr, input.size as i32);
-        let sync_result = cudaDeviceSynchronize();
-        if sync_result != CUDA_SUCCESS {
-            return Err(CudaError::Synchronization(
-                "Failed to synchronize device".into(),
-            ));
-        }
-    }
-    Ok(())
+pub fn vector_sqrt_async(
+    input: &CudaBuffer,
+    result: &mut CudaBuffer,
+    stream: &CudaStream,
+) -> Result<(), CudaError> {
+    launch_unary(vector_sqrt_kernel as *const c_void, input, result, stream)
 }
 
-pub fn vector_pow(
+pub fn vector_pow_async(
     input: &CudaBuffer,
     power: f32,
     result: &mut CudaBuffer,
+    stream: &CudaStream,
 ) -> Result<(), CudaError> {
+    if input.size != result.size {
+        return Err(CudaError::InvalidValue);
+    }
+
+    let mut result_ptr = result.ptr;
+    let mut input_ptr = input.ptr as *const f32;
+    let mut power = power;
+    let mut n = input.size as i32;
     unsafe {
-        extern "C" {
-            fn vector_pow_kernel(result: *mut f32, input: *const f32, power: f32, n: i32);
-        }
+        launch(
+            vector_pow_kernel as *const c_void,
+            LaunchConfig::linear(input.size),
+            &mut [
+                arg(&mut result_ptr),
+                arg(&mut input_ptr),
+                arg(&mut power),
+                arg(&mut n),
+            ],
+            stream,
+        )
+    }
+}
 
-        vector_pow_kernel(result.ptr, input.ptr, power, input.size as i32);
-        let sync_result = cudaDeviceSynchronize();
-        if sync_result != CUDA_SUCCESS {
-            return Err(CudaError::Synchronization(
-                "Failed to synchronize device".into(),
-            ));
-        }
+/// Queues a block-wise sum of `input`: `partials` receives one sum per block of
+/// `LaunchConfig::linear(input.len())`, to be added up after the stream is synchronized.
+pub fn vector_reduce_sum_async(
+    input: &CudaBuffer,
+    partials: &mut CudaBuffer,
+    stream: &CudaStream,
+) -> Result<(), CudaError> {
+    let mut config = LaunchConfig::linear(input.size);
+    if partials.size < config.grid_dim.x as usize {
+        return Err(CudaError::InvalidValue);
+    }
+    config.shared_mem_bytes = config.block_dim.x * std::mem::size_of::<f32>() as u32;
+
+    let mut result_ptr = partials.ptr;
+    let mut input_ptr = input.ptr as *const f32;
+    let mut n = input.size as i32;
+    unsafe {
+        launch(
+            vector_reduce_sum_kernel as *const c_void,
+            config,
+            &mut [arg(&mut result_ptr), arg(&mut input_ptr), arg(&mut n)],
+            stream,
+        )
     }
-    Ok(())
 }
 
-pub fn matrix_multiply(
+/// Queues `result[m, k] = a[m, n] * b[n, k]` on `stream`.
+pub fn matrix_multiply_async(
     a: &CudaBuffer,
     b: &CudaBuffer,
     result: &mut CudaBuffer,
     m: usize,
     n: usize,
     k: usize,
+    stream: &CudaStream,
 ) -> Result<(), CudaError> {
-    unsafe {
-        extern "C" {
-            fn matrix_multiply_kernel(
-                result: *mut f32,
-                a: *const f32,
-                b: *const f32,
-                m: i32,
-                n: i32,
-                k: i32,
-            );
-        }
+    if a.size < m * n || b.size < n * k || result.size < m * k {
+        return Err(CudaError::InvalidValue);
+    }
 
-        matrix_multiply_kernel(result.ptr, a.ptr, b.ptr, m as i32, n as i32, k as i32);
+    let mut result_ptr = result.ptr;
+    let mut a_ptr = a.ptr as *const f32;
+    let mut b_ptr = b.ptr as *const f32;
+    let (mut m_arg, mut n_arg, mut k_arg) = (m as i32, n as i32, k as i32);
+    unsafe {
+        launch(
+            matrix_multiply_kernel as *const c_void,
+            LaunchConfig::tiled(m, k),
+            &mut [
+                arg(&mut result_ptr),
+                arg(&mut a_ptr),
+                arg(&mut b_ptr),
+                arg(&mut m_arg),
+                arg(&mut n_arg),
+                arg(&mut k_arg),
+            ],
+            stream,
+        )
+    }
+}
 
-        let sync_result = cudaDeviceSynchronize();
-        if sync_result != CUDA_SUCCESS {
-            return Err(CudaError::Synchronization(
-                "Failed to synchronize device".into(),
-            ));
+/// Packs the rows of a `[rows, cols]` int8 matrix four values to a 32-bit word, zero padding
+/// each row to a whole number of words, in the layout `matrix_multiply_i8_async` reads.
+/// The words are stored as `f32` bit patterns since that is what `CudaBuffer` holds.
+pub fn pack_i8_rows(data: &[i8], rows: usize, cols: usize) -> Vec<f32> {
+    let mut packed = Vec::with_capacity(rows * cols.div_ceil(4));
+    for row in data.chunks(cols.max(1)).take(rows) {
+        for quad in row.chunks(4) {
+            let mut bytes = [0u8; 4];
+            for (byte, &value) in bytes.iter_mut().zip(quad) {
+                *byte = value as u8;
+            }
+            packed.push(f32::from_bits(u32::from_le_bytes(bytes)));
         }
     }
-    Ok(())
+    packed
 }
 
-pub fn vector_subtract(
+/// Queues an int8 matmul with i32 accumulation. `a` is `[m, n]` and `bt` is B transposed,
+/// `[k, n]`, both packed by `pack_i8_rows`; `result` receives `[m, k]` i32 values as raw
+/// bits. Uses `dp4a` on devices of compute capability 6.1 and newer.
+pub fn matrix_multiply_i8_async(
     a: &CudaBuffer,
-    b: &CudaBuffer,
+    bt: &CudaBuffer,
     result: &mut CudaBuffer,
+    m: usize,
+    n: usize,
+    k: usize,
+    stream: &CudaStream,
 ) -> Result<(), CudaError> {
-    if a.size != b.size || a.size != result.size {
+    let words = n.div_ceil(4);
+    if a.size < m * words || bt.size < k * words || result.size < m * k {
         return Err(CudaError::InvalidValue);
     }
 
+    let mut result_ptr = result.ptr as *mut i32;
+    let mut a_ptr = a.ptr as *const i32;
+    let mut bt_ptr = bt.ptr as *const i32;
+    let (mut m_arg, mut words_arg, mut k_arg) = (m as i32, words as i32, k as i32);
     unsafe {
-        extern "C" {
-            fn vector_subtract_kernel(result: *mut f32, a: *const f32, b: *const f32, n: i32);
+        launch(
+            matrix_multiply_i8_kernel as *const c_void,
+            LaunchConfig::tiled(m, k),
+            &mut [
+                arg(&mut result_ptr),
+                arg(&mut a_ptr),
+                arg(&mut bt_ptr),
+                arg(&mut m_arg),
+                arg(&mut words_arg),
+                arg(&mut k_arg),
+            ],
+            stream,
+        )
+    }
+}
+
+const FUSED_MAX_INPUTS: usize = 8;
+const FUSED_MAX_OPS: usize = 32;
+
+// Mirrors `FusedProgram` in cuda/kernels.cu
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct FusedProgram {
+    inputs: [*const f32; FUSED_MAX_INPUTS],
+    opcodes: [i32; FUSED_MAX_OPS],
+    operands: [i32; FUSED_MAX_OPS],
+    scalars: [f32; FUSED_MAX_OPS],
+    num_ops: i32,
+}
+
+impl FusedProgram {
+    fn new(inputs: &[&CudaBuffer], ops: &[FusedOp]) -> Result<Self, CudaError> {
+        if inputs.is_empty() || inputs.len() > FUSED_MAX_INPUTS || ops.len() > FUSED_MAX_OPS {
+            return Err(CudaError::InvalidValue);
+        }
+
+        let mut program = Self {
+            inputs: [std::ptr::null(); FUSED_MAX_INPUTS],
+            opcodes: [0; FUSED_MAX_OPS],
+            operands: [0; FUSED_MAX_OPS],
+            scalars: [0.0; FUSED_MAX_OPS],
+            num_ops: ops.len() as i32,
+        };
+        for (slot, input) in program.inputs.iter_mut().zip(inputs) {
+            *slot = input.ptr;
         }
 
-        vector_subtract_kernel(result.ptr, a.ptr, b.ptr, a.size as i32);
-        let sync_result = cudaDeviceSynchronize();
-        if sync_result != CUDA_SUCCESS {
-            return Err(CudaError::Synchronization(
-                "Failed to synchronize device".into(),
-            ));
+        for (i, op) in ops.iter().enumerate() {
+            let (opcode, operand, scalar) = match *op {
+                FusedOp::Add(input) => (0, input, 0.0),
+                FusedOp::Sub(input) => (1, input, 0.0),
+                FusedOp::Mul(input) => (2, input, 0.0),
+                FusedOp::Div(input) => (3, input, 0.0),
+                FusedOp::AddScalar(scalar) => (4, 0, scalar),
+                FusedOp::MulScalar(scalar) => (5, 0, scalar),
+                FusedOp::Pow(power) => (6, 0, power),
+                FusedOp::Neg => (7, 0, 0.0),
+                FusedOp::Exp => (8, 0, 0.0),
+                FusedOp::Log => (9, 0, 0.0),
+                FusedOp::Sqrt => (10, 0, 0.0),
+            };
+            if operand >= inputs.len() {
+                return Err(CudaError::InvalidValue);
+            }
+            program.opcodes[i] = opcode;
+            program.operands[i] = operand as i32;
+            program.scalars[i] = scalar;
         }
+
+        Ok(program)
     }
-    Ok(())
 }
 
-pub fn vector_divide(
-    a: &CudaBuffer,
-    b: &CudaBuffer,
+/// Queues a chain of elementwise `ops` over same-sized `inputs` as a single kernel.
+pub fn fused_elementwise_async(
+    inputs: &[&CudaBuffer],
+    ops: &[FusedOp],
     result: &mut CudaBuffer,
+    stream: &CudaStream,
 ) -> Result<(), CudaError> {
-    if a.size != b.size || a.size != result.size {
+    if inputs.iter().any(|input| input.size != result.size) {
         return Err(CudaError::InvalidValue);
     }
 
+    let mut program = FusedProgram::new(inputs, ops)?;
+    let mut result_ptr = result.ptr;
+    let mut n = result.size as i32;
     unsafe {
-        extern "C" {
-            fn vector_divide_kernel(result: *mut f32, a: *const f32, b: *const f32, n: i32);
-        }
+        launch(
+            fused_elementwise_kernel as *const c_void,
+            LaunchConfig::linear(result.size),
+            &mut [arg(&mut result_ptr), arg(&mut program), arg(&mut n)],
+            stream,
+        )
+    }
+}
 
-        vector_divide_kernel(result.ptr, a.ptr, b.ptr, a.size as i32);
-        let sync_result = cudaDeviceSynchronize();
-        if sync_result != CUDA_SUCCESS {
-            return Err(CudaError::Synchronization(
-                "Failed to synchronize device".into(),
-            ));
-        }
+/// Queues a reduction of the middle dimension of `input` viewed as `[outer, axis, inner]`,
+/// writing `[outer, inner]` to `result`.
+pub fn reduce_axis_async(
+    input: &CudaBuffer,
+    result: &mut CudaBuffer,
+    op: ReduceOp,
+    outer: usize,
+    axis: usize,
+    inner: usize,
+    stream: &CudaStream,
+) -> Result<(), CudaError> {
+    if input.size != outer * axis * inner || result.size < outer * inner {
+        return Err(CudaError::InvalidValue);
+    }
+
+    // Matches `ReduceOpcode` in kernels.cu
+    let mut opcode = match op {
+        ReduceOp::Sum => 0,
+        ReduceOp::Mean => 1,
+        ReduceOp::Max => 2,
+        ReduceOp::Min => 3,
+    };
+    let mut result_ptr = result.ptr;
+    let mut input_ptr = input.ptr as *const f32;
+    let (mut outer, mut axis, mut inner) = (outer as i32, axis as i32, inner as i32);
+    unsafe {
+        launch(
+            reduce_axis_kernel as *const c_void,
+            LaunchConfig::linear((outer * inner) as usize),
+            &mut [
+                arg(&mut result_ptr),
+                arg(&mut input_ptr),
+                arg(&mut outer),
+                arg(&mut axis),
+                arg(&mut inner),
+                arg(&mut opcode),
+            ],
+            stream,
+        )
+    }
+}
+
+// Mirrors `Conv2dGeometry` in cuda/kernels.cu
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Conv2dGeometry {
+    batch: i32,
+    channels: i32,
+    height: i32,
+    width: i32,
+    out_channels: i32,
+    kernel_h: i32,
+    kernel_w: i32,
+    stride_h: i32,
+    stride_w: i32,
+    pad_h: i32,
+    pad_w: i32,
+    out_h: i32,
+    out_w: i32,
+}
+
+/// Queues a direct convolution of an NCHW `input` with `weight`, writing the NCHW output
+/// described by `shape` to `result`.
+pub fn conv2d_async(
+    input: &CudaBuffer,
+    weight: &CudaBuffer,
+    result: &mut CudaBuffer,
+    shape: &Conv2dShape,
+    stream: &CudaStream,
+) -> Result<(), CudaError> {
+    let output_len = shape.output_len();
+    if input.size != shape.input.iter().product::<usize>()
+        || weight.size != shape.weight.iter().product::<usize>()
+        || result.size < output_len
+    {
+        return Err(CudaError::InvalidValue);
+    }
+
+    let [batch, channels, height, width] = shape.input.map(|d| d as i32);
+    let [out_channels, _, kernel_h, kernel_w] = shape.weight.map(|d| d as i32);
+    let [_, _, out_h, out_w] = shape.output().map(|d| d as i32);
+    let mut geometry = Conv2dGeometry {
+        batch,
+        channels,
+        height,
+        width,
+        out_channels,
+        kernel_h,
+        kernel_w,
+        stride_h: shape.stride.0 as i32,
+        stride_w: shape.stride.1 as i32,
+        pad_h: shape.padding.0 as i32,
+        pad_w: shape.padding.1 as i32,
+        out_h,
+        out_w,
+    };
+    let mut result_ptr = result.ptr;
+    let mut input_ptr = input.ptr as *const f32;
+    let mut weight_ptr = weight.ptr as *const f32;
+    unsafe {
+        launch(
+            conv2d_kernel as *const c_void,
+            LaunchConfig::linear(output_len),
+            &mut [
+                arg(&mut result_ptr),
+                arg(&mut input_ptr),
+                arg(&mut weight_ptr),
+                arg(&mut geometry),
+            ],
+            stream,
+        )
     }
-    Ok(())
 }
@@ -1,4 +1,5 @@
 use super::CudaError;
+use crate::backend::BackendCapabilities;
 use std::ffi::CStr;
 use std::sync::Once;
 
@@ -19,6 +20,7 @@ extern "C" {
     fn cudaDeviceGetAttribute(value: *mut i32, attr: i32, device: i32) -> i32;
     fn cudaMemGetInfo(free: *mut usize, total: *mut usize) -> i32;
     fn cudaSetDevice(device: i32) -> i32;
+    fn cudaGetDevice(device: *mut i32) -> i32;
     fn cudaDeviceSynchronize() -> i32;
     fn cudaGetDeviceCount(count: *mut i32) -> i32;
     fn cudaInit(flags: u32) -> i32;
@@ -38,6 +40,8 @@ struct cudaDeviceProp {
     memory_clock_rate: i32,
     memory_bus_width: i32,
     // Add other fields as needed
+    // Room for the rest of the runtime's struct, which cudaGetDeviceProperties fills in full
+    reserved: [u8; 2048],
 }
 
 const CUDA_SUCCESS: i32 = 0;
@@ -62,6 +66,7 @@ impl CudaDevice {
             clock_rate: 0,
             memory_clock_rate: 0,
             memory_bus_width: 0,
+            reserved: [0; 2048],
         };
 
         let mut major = 0;
@@ -92,11 +97,15 @@ impl CudaDevice {
             {
                 return Err(CudaError::InvalidDevice(device_id));
             }
+        }
 
-            // Get memory info
-            if cudaMemGetInfo(&mut free_memory, &mut total_memory) != CUDA_SUCCESS {
-                return Err(CudaError::Other("Failed to get memory info".into()));
-            }
+        // cudaMemGetInfo reports on the current device, so switch to this one while asking
+        let previous = current_device()?;
+        set_device(device_id)?;
+        let memory_info = unsafe { cudaMemGetInfo(&mut free_memory, &mut total_memory) };
+        set_device(previous)?;
+        if memory_info != CUDA_SUCCESS {
+            return Err(CudaError::Other("Failed to get memory info".into()));
         }
 
         let device_name = unsafe {
@@ -129,27 +138,57 @@ impl CudaDevice {
         self.total_memory
     }
 
+    pub fn capabilities(&self) -> BackendCapabilities {
+        let mut capabilities = BackendCapabilities::new(self.device_name.clone());
+        capabilities.max_buffer_size = Some(self.total_memory as u64);
+        // Native half-precision arithmetic arrived with compute capability 5.3
+        capabilities.supports_f16 = self.compute_capability >= (5, 3);
+        capabilities.supports_async = true;
+        capabilities
+    }
+
+    /// Makes this the device that allocations and launches on the calling thread target.
     pub fn set_current(&self) -> Result<(), CudaError> {
-        unsafe {
-            let result = cudaSetDevice(self.device_id);
-            if result != CUDA_SUCCESS {
-                return Err(CudaError::InvalidDevice(self.device_id));
-            }
-        }
-        Ok(())
+        set_device(self.device_id)
     }
 
     pub fn synchronize(&self) -> Result<(), CudaError> {
-        unsafe {
-            let result = cudaDeviceSynchronize();
-            if result != CUDA_SUCCESS {
-                return Err(CudaError::Synchronization(
-                    "Device synchronization failed".into(),
-                ));
-            }
+        synchronize_device(self.device_id)
+    }
+}
+
+pub fn set_device(device_id: i32) -> Result<(), CudaError> {
+    unsafe {
+        if cudaSetDevice(device_id) != CUDA_SUCCESS {
+            return Err(CudaError::InvalidDevice(device_id));
         }
-        Ok(())
     }
+    Ok(())
+}
+
+pub fn current_device() -> Result<i32, CudaError> {
+    let mut device_id = 0;
+    unsafe {
+        if cudaGetDevice(&mut device_id) != CUDA_SUCCESS {
+            return Err(CudaError::NotInitialized);
+        }
+    }
+    Ok(device_id)
+}
+
+/// Blocks until all work queued on `device_id`, on any stream, has completed.
+pub fn synchronize_device(device_id: i32) -> Result<(), CudaError> {
+    let previous = current_device()?;
+    set_device(device_id)?;
+    let result = unsafe { cudaDeviceSynchronize() };
+    set_device(previous)?;
+
+    if result != CUDA_SUCCESS {
+        return Err(CudaError::Synchronization(
+            "Device synchronization failed".into(),
+        ));
+    }
+    Ok(())
 }
 
 pub fn initialize_cuda() -> Result<(), CudaError> {
@@ -0,0 +1,149 @@
+use super::{load_library, CudaBuffer, CudaError, CudaStream};
+use std::ffi::c_void;
+use std::sync::{Mutex, OnceLock};
+
+type CublasHandle = *mut c_void;
+
+type CreateFn = unsafe extern "C" fn(*mut CublasHandle) -> i32;
+type DestroyFn = unsafe extern "C" fn(CublasHandle) -> i32;
+type SetStreamFn = unsafe extern "C" fn(CublasHandle, *mut c_void) -> i32;
+type SgemmFn = unsafe extern "C" fn(
+    CublasHandle,
+    i32,
+    i32,
+    i32,
+    i32,
+    i32,
+    *const f32,
+    *const f32,
+    i32,
+    *const f32,
+    i32,
+    *const f32,
+    *mut f32,
+    i32,
+) -> i32;
+
+const CUBLAS_STATUS_SUCCESS: i32 = 0;
+const CUBLAS_OP_N: i32 = 0;
+
+const LIBRARY_NAMES: &[&str] = &[
+    "libcublas.so",
+    "libcublas.so.12",
+    "libcublas.so.11",
+    "cublas64_12.dll",
+    "cublas64_11.dll",
+];
+
+pub struct Cublas {
+    handle: CublasHandle,
+    sgemm: SgemmFn,
+    set_stream: SetStreamFn,
+    destroy: DestroyFn,
+    // Keeps the function pointers above valid
+    _library: libloading::Library,
+}
+
+// The handle is only ever used behind the Mutex in `Cublas::global`
+unsafe impl Send for Cublas {}
+
+impl Cublas {
+    fn load() -> Option<Self> {
+        let library = load_library(LIBRARY_NAMES)?;
+
+        unsafe {
+            let create = *library.get::<CreateFn>(b"cublasCreate_v2\0").ok()?;
+            let destroy = *library.get::<DestroyFn>(b"cublasDestroy_v2\0").ok()?;
+            let sgemm = *library.get::<SgemmFn>(b"cublasSgemm_v2\0").ok()?;
+            let set_stream = *library.get::<SetStreamFn>(b"cublasSetStream_v2\0").ok()?;
+
+            let mut handle = std::ptr::null_mut();
+            if create(&mut handle) != CUBLAS_STATUS_SUCCESS {
+                return None;
+            }
+
+            Some(Self {
+                handle,
+                sgemm,
+                set_stream,
+                destroy,
+                _library: library,
+            })
+        }
+    }
+
+    /// Process-wide cuBLAS context, or `None` when the library can't be loaded.
+    pub fn global() -> Option<&'static Mutex<Cublas>> {
+        static CUBLAS: OnceLock<Option<Mutex<Cublas>>> = OnceLock::new();
+        CUBLAS
+            .get_or_init(|| Cublas::load().map(Mutex::new))
+            .as_ref()
+    }
+
+    pub fn is_available() -> bool {
+        Self::global().is_some()
+    }
+
+    /// Queues subsequent calls on `stream`.
+    pub fn set_stream(&self, stream: &CudaStream) -> Result<(), CudaError> {
+        let status = unsafe { (self.set_stream)(self.handle, stream.as_raw()) };
+        if status != CUBLAS_STATUS_SUCCESS {
+            return Err(CudaError::Other(format!(
+                "cublasSetStream failed with status {}",
+                status
+            )));
+        }
+        Ok(())
+    }
+
+    /// Row-major GEMM on device buffers: `a` is `[m, n]`, `b` is `[n, k]` and
+    /// `result` is `[m, k]`, matching `Backend::matmul`.
+    pub fn sgemm(
+        &self,
+        a: &CudaBuffer,
+        b: &CudaBuffer,
+        result: &mut CudaBuffer,
+        m: usize,
+        n: usize,
+        k: usize,
+    ) -> Result<(), CudaError> {
+        let alpha = 1.0f32;
+        let beta = 0.0f32;
+
+        // cuBLAS is column-major; a row-major C = A * B is the column-major C^T = B^T * A^T
+        let status = unsafe {
+            (self.sgemm)(
+                self.handle,
+                CUBLAS_OP_N,
+                CUBLAS_OP_N,
+                k as i32,
+                m as i32,
+                n as i32,
+                &alpha,
+                b.as_ptr(),
+                k as i32,
+                a.as_ptr(),
+                n as i32,
+                &beta,
+                result.as_mut_ptr(),
+                k as i32,
+            )
+        };
+
+        if status != CUBLAS_STATUS_SUCCESS {
+            return Err(CudaError::KernelExecutionFailed(format!(
+                "cublasSgemm failed with status {}",
+                status
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Cublas {
+    fn drop(&mut self) {
+        unsafe {
+            (self.destroy)(self.handle);
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use super::{load_library, CudaBuffer, CudaError, CudaStream};
+use crate::backend::Conv2dShape;
+use std::ffi::c_void;
+use std::sync::{Mutex, OnceLock};
+
+type CudnnHandle = *mut c_void;
+type Descriptor = *mut c_void;
+
+type CreateFn = unsafe extern "C" fn(*mut CudnnHandle) -> i32;
+type DestroyFn = unsafe extern "C" fn(CudnnHandle) -> i32;
+type SetStreamFn = unsafe extern "C" fn(CudnnHandle, *mut c_void) -> i32;
+type CreateDescriptorFn = unsafe extern "C" fn(*mut Descriptor) -> i32;
+type DestroyDescriptorFn = unsafe extern "C" fn(Descriptor) -> i32;
+type SetTensor4dFn = unsafe extern "C" fn(Descriptor, i32, i32, i32, i32, i32, i32) -> i32;
+type SetFilter4dFn = unsafe extern "C" fn(Descriptor, i32, i32, i32, i32, i32, i32) -> i32;
+type SetConvolution2dFn =
+    unsafe extern "C" fn(Descriptor, i32, i32, i32, i32, i32, i32, i32, i32) -> i32;
+type WorkspaceSizeFn = unsafe extern "C" fn(
+    CudnnHandle,
+    Descriptor,
+    Descriptor,
+    Descriptor,
+    Descriptor,
+    i32,
+    *mut usize,
+) -> i32;
+type ConvolutionForwardFn = unsafe extern "C" fn(
+    CudnnHandle,
+    *const f32,
+    Descriptor,
+    *const f32,
+    Descriptor,
+    *const f32,
+    Descriptor,
+    i32,
+    *mut c_void,
+    usize,
+    *const f32,
+    Descriptor,
+    *mut f32,
+) -> i32;
+
+const CUDNN_STATUS_SUCCESS: i32 = 0;
+const CUDNN_TENSOR_NCHW: i32 = 0;
+const CUDNN_DATA_FLOAT: i32 = 0;
+const CUDNN_CROSS_CORRELATION: i32 = 1;
+const CUDNN_CONVOLUTION_FWD_ALGO_IMPLICIT_GEMM: i32 = 0;
+
+const LIBRARY_NAMES: &[&str] = &[
+    "libcudnn.so",
+    "libcudnn.so.9",
+    "libcudnn.so.8",
+    "cudnn64_9.dll",
+    "cudnn64_8.dll",
+];
+
+struct Api {
+    set_stream: SetStreamFn,
+    create_tensor: CreateDescriptorFn,
+    destroy_tensor: DestroyDescriptorFn,
+    set_tensor: SetTensor4dFn,
+    create_filter: CreateDescriptorFn,
+    destroy_filter: DestroyDescriptorFn,
+    set_filter: SetFilter4dFn,
+    create_conv: CreateDescriptorFn,
+    destroy_conv: DestroyDescriptorFn,
+    set_conv: SetConvolution2dFn,
+    workspace_size: WorkspaceSizeFn,
+    forward: ConvolutionForwardFn,
+}
+
+pub struct Cudnn {
+    handle: CudnnHandle,
+    api: Api,
+    destroy: DestroyFn,
+    // Keeps the function pointers above valid
+    _library: libloading::Library,
+}
+
+// The handle is only ever used behind the Mutex in `Cudnn::global`
+unsafe impl Send for Cudnn {}
+
+fn check(call: &str, status: i32) -> Result<(), CudaError> {
+    if status == CUDNN_STATUS_SUCCESS {
+        Ok(())
+    } else {
+        Err(CudaError::KernelExecutionFailed(format!(
+            "{} failed with status {}",
+            call, status
+        )))
+    }
+}
+
+impl Cudnn {
+    fn load() -> Option<Self> {
+        let library = load_library(LIBRARY_NAMES)?;
+
+        unsafe {
+            let create = *library.get::<CreateFn>(b"cudnnCreate\0").ok()?;
+            let destroy = *library.get::<DestroyFn>(b"cudnnDestroy\0").ok()?;
+            let api = Api {
+                set_stream: *library.get(b"cudnnSetStream\0").ok()?,
+                create_tensor: *library.get(b"cudnnCreateTensorDescriptor\0").ok()?,
+                destroy_tensor: *library.get(b"cudnnDestroyTensorDescriptor\0").ok()?,
+                set_tensor: *library.get(b"cudnnSetTensor4dDescriptor\0").ok()?,
+                create_filter: *library.get(b"cudnnCreateFilterDescriptor\0").ok()?,
+                destroy_filter: *library.get(b"cudnnDestroyFilterDescriptor\0").ok()?,
+                set_filter: *library.get(b"cudnnSetFilter4dDescriptor\0").ok()?,
+                create_conv: *library.get(b"cudnnCreateConvolutionDescriptor\0").ok()?,
+                destroy_conv: *library.get(b"cudnnDestroyConvolutionDescriptor\0").ok()?,
+                set_conv: *library.get(b"cudnnSetConvolution2dDescriptor\0").ok()?,
+                workspace_size: *library
+                    .get(b"cudnnGetConvolutionForwardWorkspaceSize\0")
+                    .ok()?,
+                forward: *library.get(b"cudnnConvolutionForward\0").ok()?,
+            };
+
+            let mut handle = std::ptr::null_mut();
+            if create(&mut handle) != CUDNN_STATUS_SUCCESS {
+                return None;
+            }
+
+            Some(Self {
+                handle,
+                api,
+                destroy,
+                _library: library,
+            })
+        }
+    }
+
+    /// Process-wide cuDNN context, or `None` when the library can't be loaded.
+    pub fn global() -> Option<&'static Mutex<Cudnn>> {
+        static CUDNN: OnceLock<Option<Mutex<Cudnn>>> = OnceLock::new();
+        CUDNN.get_or_init(|| Cudnn::load().map(Mutex::new)).as_ref()
+    }
+
+    pub fn is_available() -> bool {
+        Self::global().is_some()
+    }
+
+    /// Queues subsequent calls on `stream`.
+    pub fn set_stream(&self, stream: &CudaStream) -> Result<(), CudaError> {
+        unsafe {
+            check(
+                "cudnnSetStream",
+                (self.api.set_stream)(self.handle, stream.as_raw()),
+            )
+        }
+    }
+
+    /// Forward cross-correlation (the usual deep-learning "convolution") on device buffers.
+    pub fn conv2d_forward(
+        &self,
+        input: &CudaBuffer,
+        weight: &CudaBuffer,
+        output: &mut CudaBuffer,
+        shape: &Conv2dShape,
+    ) -> Result<(), CudaError> {
+        let [n, c, h, w] = shape.input.map(|d| d as i32);
+        let [oc, ic, kh, kw] = shape.weight.map(|d| d as i32);
+        let [on, occ, oh, ow] = shape.output().map(|d| d as i32);
+
+        let api = &self.api;
+        let mut x_desc = std::ptr::null_mut();
+        let mut y_desc = std::ptr::null_mut();
+        let mut w_desc = std::ptr::null_mut();
+        let mut conv_desc = std::ptr::null_mut();
+
+        unsafe {
+            let result = (|| {
+                check(
+                    "cudnnCreateTensorDescriptor",
+                    (api.create_tensor)(&mut x_desc),
+                )?;
+                check(
+                    "cudnnCreateTensorDescriptor",
+                    (api.create_tensor)(&mut y_desc),
+                )?;
+                check(
+                    "cudnnCreateFilterDescriptor",
+                    (api.create_filter)(&mut w_desc),
+                )?;
+                check(
+                    "cudnnCreateConvolutionDescriptor",
+                    (api.create_conv)(&mut conv_desc),
+                )?;
+
+                check(
+                    "cudnnSetTensor4dDescriptor",
+                    (api.set_tensor)(x_desc, CUDNN_TENSOR_NCHW, CUDNN_DATA_FLOAT, n, c, h, w),
+                )?;
+                check(
+                    "cudnnSetTensor4dDescriptor",
+                    (api.set_tensor)(y_desc, CUDNN_TENSOR_NCHW, CUDNN_DATA_FLOAT, on, occ, oh, ow),
+                )?;
+                check(
+                    "cudnnSetFilter4dDescriptor",
+                    (api.set_filter)(w_desc, CUDNN_DATA_FLOAT, CUDNN_TENSOR_NCHW, oc, ic, kh, kw),
+                )?;
+                check(
+                    "cudnnSetConvolution2dDescriptor",
+                    (api.set_conv)(
+                        conv_desc,
+                        shape.padding.0 as i32,
+                        shape.padding.1 as i32,
+                        shape.stride.0 as i32,
+                        shape.stride.1 as i32,
+                        1,
+                        1,
+                        CUDNN_CROSS_CORRELATION,
+                        CUDNN_DATA_FLOAT,
+                    ),
+                )?;
+
+                // Deterministic, so it is also safe under `set_deterministic`
+                let algo = CUDNN_CONVOLUTION_FWD_ALGO_IMPLICIT_GEMM;
+                let mut workspace_bytes = 0usize;
+                check(
+                    "cudnnGetConvolutionForwardWorkspaceSize",
+                    (api.workspace_size)(
+                        self.handle,
+                        x_desc,
+                        w_desc,
+                        conv_desc,
+                        y_desc,
+                        algo,
+                        &mut workspace_bytes,
+                    ),
+                )?;
+                let mut workspace =
+                    CudaBuffer::new(workspace_bytes.div_ceil(std::mem::size_of::<f32>()))?;
+
+                let alpha = 1.0f32;
+                let beta = 0.0f32;
+                check(
+                    "cudnnConvolutionForward",
+                    (api.forward)(
+                        self.handle,
+                        &alpha,
+                        x_desc,
+                        input.as_ptr(),
+                        w_desc,
+                        weight.as_ptr(),
+                        conv_desc,
+                        algo,
+                        workspace.as_mut_ptr() as *mut c_void,
+                        workspace_bytes,
+                        &beta,
+                        y_desc,
+                        output.as_mut_ptr(),
+                    ),
+                )
+            })();
+
+            if !conv_desc.is_null() {
+                (api.destroy_conv)(conv_desc);
+            }
+            if !w_desc.is_null() {
+                (api.destroy_filter)(w_desc);
+            }
+            if !y_desc.is_null() {
+                (api.destroy_tensor)(y_desc);
+            }
+            if !x_desc.is_null() {
+                (api.destroy_tensor)(x_desc);
+            }
+
+            result
+        }
+    }
+}
+
+impl Drop for Cudnn {
+    fn drop(&mut self) {
+        unsafe {
+            (self.destroy)(self.handle);
+        }
+    }
+}
@@ -1,3 +1,21 @@
+use super::{CudaError, CudaStream};
+use std::ffi::c_void;
+
+#[link(name = "cuda")]
+extern "C" {
+    fn cudaLaunchKernel(
+        func: *const c_void,
+        grid_dim: Dim3,
+        block_dim: Dim3,
+        args: *mut *mut c_void,
+        shared_mem: usize,
+        stream: *mut c_void,
+    ) -> i32;
+}
+
+const THREADS_PER_BLOCK: u32 = 256;
+const TILE_SIZE: u32 = 16;
+
 #[derive(Debug, Clone, Copy)]
 pub struct LaunchConfig {
     pub grid_dim: Dim3,
@@ -5,6 +23,7 @@ pub struct LaunchConfig {
     pub shared_mem_bytes: u32,
 }
 
+#[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct Dim3 {
     pub x: u32,
@@ -26,4 +45,54 @@ impl LaunchConfig {
             shared_mem_bytes,
         }
     }
+
+    /// One thread per element.
+    pub fn linear(n: usize) -> Self {
+        Self::new(
+            Dim3::new((n as u32).div_ceil(THREADS_PER_BLOCK).max(1), 1, 1),
+            Dim3::new(THREADS_PER_BLOCK, 1, 1),
+            0,
+        )
+    }
+
+    /// One thread per element of a `rows x cols` output, in square tiles.
+    pub fn tiled(rows: usize, cols: usize) -> Self {
+        Self::new(
+            Dim3::new(
+                (cols as u32).div_ceil(TILE_SIZE).max(1),
+                (rows as u32).div_ceil(TILE_SIZE).max(1),
+                1,
+            ),
+            Dim3::new(TILE_SIZE, TILE_SIZE, 1),
+            0,
+        )
+    }
+}
+
+/// Queues `kernel` on `stream` without waiting for it to run.
+///
+/// # Safety
+/// `kernel` must be a `__global__` function symbol and `args` must point to values
+/// matching its parameter list, kept alive until this call returns.
+pub unsafe fn launch(
+    kernel: *const c_void,
+    config: LaunchConfig,
+    args: &mut [*mut c_void],
+    stream: &CudaStream,
+) -> Result<(), CudaError> {
+    let result = cudaLaunchKernel(
+        kernel,
+        config.grid_dim,
+        config.block_dim,
+        args.as_mut_ptr(),
+        config.shared_mem_bytes as usize,
+        stream.as_raw(),
+    );
+    if result != 0 {
+        return Err(CudaError::KernelLaunchFailed(format!(
+            "cudaLaunchKernel failed with error {}",
+            result
+        )));
+    }
+    Ok(())
 }
@@ -1,11 +1,24 @@
 mod backend;
 mod compute;
 mod core;
+mod cublas;
+mod cudnn;
 mod launch;
+mod pinned;
+mod pool;
+mod stream;
 
 pub use backend::CudaBackend;
-pub use compute::{vector_add, vector_multiply, CudaBuffer};
-pub use core::{initialize_cuda, CudaDevice};
+pub use compute::{
+    conv2d_async, fused_elementwise_async, matrix_multiply_async, matrix_multiply_i8_async,
+    pack_i8_rows, reduce_axis_async, vector_add_async, vector_divide_async, vector_exp_async,
+    vector_log_async, vector_multiply_async, vector_pow_async, vector_reduce_sum_async,
+    vector_sqrt_async, vector_subtract_async, CudaBuffer,
+};
+pub use core::{get_device_count, initialize_cuda, CudaDevice};
+pub use pinned::{PendingTransfer, PinnedBuffer};
+pub use pool::{empty_cache, memory_stats};
+pub use stream::{CudaEvent, CudaStream};
 
 #[derive(Debug)]
 pub enum CudaError {
@@ -80,3 +93,12 @@ impl From<CudaError> for CudaBackendError {
         CudaBackendError::CudaError(error)
     }
 }
+
+// Opens the first library in `names` that the dynamic loader can find. cuBLAS and cuDNN
+// are loaded at runtime so that machines without them still get the custom kernels; so is
+// NCCL, for distributed training.
+pub(crate) fn load_library(names: &[&str]) -> Option<libloading::Library> {
+    names
+        .iter()
+        .find_map(|name| unsafe { libloading::Library::new(name) }.ok())
+}
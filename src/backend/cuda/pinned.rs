@@ -0,0 +1,158 @@
+use super::{CudaBuffer, CudaError, CudaEvent, CudaStream};
+use std::ffi::c_void;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::ptr::{null_mut, NonNull};
+
+#[link(name = "cuda")]
+extern "C" {
+    fn cudaMallocHost(ptr: *mut *mut c_void, size: usize) -> i32;
+    fn cudaFreeHost(ptr: *mut c_void) -> i32;
+}
+
+const CUDA_SUCCESS: i32 = 0;
+
+/// Page-locked host memory. Copies between pinned memory and the device run truly
+/// asynchronously, so a loader can fill the next batch while the current one is computed on.
+pub struct PinnedBuffer {
+    ptr: NonNull<f32>,
+    len: usize,
+}
+
+// Pinned allocations are ordinary host memory once allocated
+unsafe impl Send for PinnedBuffer {}
+unsafe impl Sync for PinnedBuffer {}
+
+impl PinnedBuffer {
+    /// Allocates `len` zeroed floats of page-locked memory.
+    pub fn new(len: usize) -> Result<Self, CudaError> {
+        if len == 0 {
+            return Ok(Self {
+                ptr: NonNull::dangling(),
+                len,
+            });
+        }
+
+        let mut raw: *mut c_void = null_mut();
+        let size = len * std::mem::size_of::<f32>();
+        unsafe {
+            if cudaMallocHost(&mut raw, size) != CUDA_SUCCESS {
+                return Err(CudaError::MemoryAllocationFailed(
+                    "Failed to allocate pinned host memory".into(),
+                ));
+            }
+            std::ptr::write_bytes(raw as *mut u8, 0, size);
+        }
+
+        let ptr = NonNull::new(raw as *mut f32).ok_or(CudaError::OutOfMemory)?;
+        Ok(Self { ptr, len })
+    }
+
+    pub fn from_slice(data: &[f32]) -> Result<Self, CudaError> {
+        let mut buffer = Self::new(data.len())?;
+        buffer.copy_from_slice(data);
+        Ok(buffer)
+    }
+}
+
+impl Deref for PinnedBuffer {
+    type Target = [f32];
+
+    fn deref(&self) -> &[f32] {
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for PinnedBuffer {
+    fn deref_mut(&mut self) -> &mut [f32] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl std::fmt::Debug for PinnedBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PinnedBuffer({} floats)", self.len)
+    }
+}
+
+impl Drop for PinnedBuffer {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            unsafe {
+                cudaFreeHost(self.ptr.as_ptr() as *mut c_void);
+            }
+        }
+    }
+}
+
+/// A copy queued between pinned host memory and the device. The host buffer stays borrowed
+/// until the copy has completed; dropping the transfer waits for it.
+#[must_use = "dropping a transfer blocks until the copy completes"]
+#[derive(Debug)]
+pub struct PendingTransfer<'a> {
+    event: CudaEvent,
+    _host: PhantomData<&'a mut [f32]>,
+}
+
+impl PendingTransfer<'_> {
+    fn record(stream: &CudaStream) -> Result<Self, CudaError> {
+        let event = CudaEvent::new().and_then(|event| event.record(stream).map(|_| event));
+
+        match event {
+            Ok(event) => Ok(Self {
+                event,
+                _host: PhantomData,
+            }),
+            Err(e) => {
+                // Without an event to wait on, the copy has to finish before the borrow ends
+                stream.synchronize()?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Marks the completion of the copy, so other streams can `wait_event` on it without
+    /// blocking the host.
+    pub fn event(&self) -> &CudaEvent {
+        &self.event
+    }
+
+    pub fn is_complete(&self) -> Result<bool, CudaError> {
+        self.event.is_complete()
+    }
+
+    /// Blocks until the copy has completed.
+    pub fn wait(self) -> Result<(), CudaError> {
+        self.event.synchronize()
+    }
+}
+
+impl Drop for PendingTransfer<'_> {
+    fn drop(&mut self) {
+        let _ = self.event.synchronize();
+    }
+}
+
+impl CudaBuffer {
+    /// Queues a copy of `src` into this buffer on `stream` and returns without waiting.
+    pub fn upload_async<'a>(
+        &mut self,
+        src: &'a PinnedBuffer,
+        stream: &CudaStream,
+    ) -> Result<PendingTransfer<'a>, CudaError> {
+        // The returned transfer borrows `src` until the copy has completed
+        unsafe { self.copy_from_host_async(src, stream)? };
+        PendingTransfer::record(stream)
+    }
+
+    /// Queues a copy of this buffer into `dst` on `stream` and returns without waiting.
+    pub fn download_async<'a>(
+        &self,
+        dst: &'a mut PinnedBuffer,
+        stream: &CudaStream,
+    ) -> Result<PendingTransfer<'a>, CudaError> {
+        // The returned transfer borrows `dst` until the copy has completed
+        unsafe { self.copy_to_host_async(dst, stream)? };
+        PendingTransfer::record(stream)
+    }
+}
@@ -0,0 +1,136 @@
+use super::core::{current_device, set_device, synchronize_device};
+use super::{CudaError, CudaEvent, CudaStream};
+use crate::backend::pool::{block_size, BlockCache};
+use crate::backend::MemoryStats;
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::ptr::null_mut;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+#[link(name = "cuda")]
+extern "C" {
+    fn cudaMalloc(ptr: *mut *mut c_void, size: usize) -> i32;
+    fn cudaFree(ptr: *mut c_void) -> i32;
+}
+
+const CUDA_SUCCESS: i32 = 0;
+
+// A freed block becomes reusable once the work its stream had queued at the time has run
+#[derive(Debug)]
+struct CachedBlock {
+    ptr: usize,
+    event: CudaEvent,
+}
+
+type DevicePools = HashMap<i32, BlockCache<usize, CachedBlock>>;
+
+fn pools() -> MutexGuard<'static, DevicePools> {
+    static POOLS: OnceLock<Mutex<DevicePools>> = OnceLock::new();
+    POOLS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// Runs `f` with `device` current, restoring the previous device afterwards
+fn on_device<T>(device: i32, f: impl FnOnce() -> T) -> Result<T, CudaError> {
+    let previous = current_device()?;
+    if previous != device {
+        set_device(device)?;
+    }
+    let result = f();
+    if previous != device {
+        set_device(previous)?;
+    }
+    Ok(result)
+}
+
+fn malloc(bytes: usize) -> Option<usize> {
+    let mut ptr = null_mut();
+    let status = unsafe { cudaMalloc(&mut ptr, bytes) };
+    (status == CUDA_SUCCESS).then_some(ptr as usize)
+}
+
+/// Allocates at least `bytes` on the current device, reusing a cached block when one of the
+/// right size is free. Returns the pointer and the block size it was carved from.
+pub(crate) fn allocate(bytes: usize) -> Result<(*mut f32, usize), CudaError> {
+    let device = current_device()?;
+    let bytes = block_size(bytes);
+
+    if let Some(block) = pools()
+        .entry(device)
+        .or_default()
+        .take(&bytes, |block| block.event.is_complete().unwrap_or(false))
+    {
+        return Ok((block.ptr as *mut f32, bytes));
+    }
+
+    // On failure, give the cache back to the driver and try once more
+    let ptr = match malloc(bytes) {
+        Some(ptr) => ptr,
+        None => {
+            empty_cache(device)?;
+            malloc(bytes).ok_or_else(|| {
+                CudaError::MemoryAllocationFailed("Failed to allocate CUDA memory".into())
+            })?
+        }
+    };
+
+    pools().entry(device).or_default().allocated(bytes);
+    Ok((ptr as *mut f32, bytes))
+}
+
+/// Caches a block whose buffer was last used on `stream`. It is handed out again only
+/// after everything queued on `stream` so far has completed.
+pub(crate) fn release(device: i32, ptr: *mut f32, bytes: usize, stream: &CudaStream) {
+    let event = on_device(device, || {
+        let event = CudaEvent::new()?;
+        event.record(stream)?;
+        Ok::<_, CudaError>(event)
+    })
+    .and_then(|event| event);
+
+    let mut pools = pools();
+    let cache = pools.entry(device).or_default();
+    match event {
+        Ok(event) => cache.release(
+            bytes,
+            bytes,
+            CachedBlock {
+                ptr: ptr as usize,
+                event,
+            },
+        ),
+        Err(_) => {
+            // Without an event there is no telling when the block is idle
+            let _ = stream.synchronize();
+            unsafe { cudaFree(ptr as *mut c_void) };
+            cache.freed(bytes);
+        }
+    }
+}
+
+pub fn memory_stats(device: i32) -> MemoryStats {
+    pools()
+        .get(&device)
+        .map(|cache| cache.stats())
+        .unwrap_or_default()
+}
+
+/// Frees every cached block on `device`, waiting for any work that may still use them.
+pub fn empty_cache(device: i32) -> Result<(), CudaError> {
+    let blocks = match pools().get_mut(&device) {
+        Some(cache) => cache.drain(),
+        None => return Ok(()),
+    };
+    if blocks.is_empty() {
+        return Ok(());
+    }
+
+    synchronize_device(device)?;
+    on_device(device, || {
+        for block in blocks {
+            unsafe { cudaFree(block.ptr as *mut c_void) };
+        }
+    })
+}
@@ -0,0 +1,166 @@
+use super::CudaError;
+use std::ffi::c_void;
+use std::ptr::null_mut;
+
+#[link(name = "cuda")]
+extern "C" {
+    fn cudaStreamCreateWithFlags(stream: *mut *mut c_void, flags: u32) -> i32;
+    fn cudaStreamDestroy(stream: *mut c_void) -> i32;
+    fn cudaStreamSynchronize(stream: *mut c_void) -> i32;
+    fn cudaStreamQuery(stream: *mut c_void) -> i32;
+    fn cudaStreamWaitEvent(stream: *mut c_void, event: *mut c_void, flags: u32) -> i32;
+    fn cudaEventCreateWithFlags(event: *mut *mut c_void, flags: u32) -> i32;
+    fn cudaEventDestroy(event: *mut c_void) -> i32;
+    fn cudaEventRecord(event: *mut c_void, stream: *mut c_void) -> i32;
+    fn cudaEventSynchronize(event: *mut c_void) -> i32;
+    fn cudaEventQuery(event: *mut c_void) -> i32;
+}
+
+const CUDA_SUCCESS: i32 = 0;
+const CUDA_ERROR_NOT_READY: i32 = 600;
+const CUDA_STREAM_NON_BLOCKING: u32 = 0x01;
+const CUDA_EVENT_DISABLE_TIMING: u32 = 0x02;
+
+/// An ordered queue of device work. Work queued on different streams may run concurrently;
+/// nothing queued on a stream is guaranteed to have finished until `synchronize` returns.
+#[derive(Debug)]
+pub struct CudaStream {
+    raw: *mut c_void,
+    owned: bool,
+}
+
+// CUDA streams may be used from any host thread
+unsafe impl Send for CudaStream {}
+unsafe impl Sync for CudaStream {}
+
+impl CudaStream {
+    /// Creates a stream that does not implicitly synchronize with the default stream.
+    pub fn new() -> Result<Self, CudaError> {
+        let mut raw = null_mut();
+        unsafe {
+            if cudaStreamCreateWithFlags(&mut raw, CUDA_STREAM_NON_BLOCKING) != CUDA_SUCCESS {
+                return Err(CudaError::Other("Failed to create CUDA stream".into()));
+            }
+        }
+        Ok(Self { raw, owned: true })
+    }
+
+    /// The legacy default stream, which serializes with every other blocking stream.
+    pub fn default_stream() -> Self {
+        Self {
+            raw: null_mut(),
+            owned: false,
+        }
+    }
+
+    pub fn as_raw(&self) -> *mut c_void {
+        self.raw
+    }
+
+    /// Blocks the host until all work queued on this stream has completed.
+    pub fn synchronize(&self) -> Result<(), CudaError> {
+        unsafe {
+            if cudaStreamSynchronize(self.raw) != CUDA_SUCCESS {
+                return Err(CudaError::Synchronization(
+                    "Stream synchronization failed".into(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns true if all work queued on this stream has completed, without blocking.
+    pub fn is_idle(&self) -> Result<bool, CudaError> {
+        match unsafe { cudaStreamQuery(self.raw) } {
+            CUDA_SUCCESS => Ok(true),
+            CUDA_ERROR_NOT_READY => Ok(false),
+            code => Err(CudaError::Other(format!(
+                "Stream query failed with error {}",
+                code
+            ))),
+        }
+    }
+
+    /// Makes all future work on this stream wait until `event` has completed.
+    pub fn wait_event(&self, event: &CudaEvent) -> Result<(), CudaError> {
+        unsafe {
+            if cudaStreamWaitEvent(self.raw, event.raw, 0) != CUDA_SUCCESS {
+                return Err(CudaError::Synchronization(
+                    "Failed to make stream wait on event".into(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for CudaStream {
+    fn drop(&mut self) {
+        if self.owned {
+            unsafe {
+                cudaStreamDestroy(self.raw);
+            }
+        }
+    }
+}
+
+/// A marker in a stream, used to order work across streams.
+#[derive(Debug)]
+pub struct CudaEvent {
+    raw: *mut c_void,
+}
+
+unsafe impl Send for CudaEvent {}
+unsafe impl Sync for CudaEvent {}
+
+impl CudaEvent {
+    pub fn new() -> Result<Self, CudaError> {
+        let mut raw = null_mut();
+        unsafe {
+            if cudaEventCreateWithFlags(&mut raw, CUDA_EVENT_DISABLE_TIMING) != CUDA_SUCCESS {
+                return Err(CudaError::Other("Failed to create CUDA event".into()));
+            }
+        }
+        Ok(Self { raw })
+    }
+
+    /// Captures the work queued on `stream` so far.
+    pub fn record(&self, stream: &CudaStream) -> Result<(), CudaError> {
+        unsafe {
+            if cudaEventRecord(self.raw, stream.raw) != CUDA_SUCCESS {
+                return Err(CudaError::Other("Failed to record CUDA event".into()));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn synchronize(&self) -> Result<(), CudaError> {
+        unsafe {
+            if cudaEventSynchronize(self.raw) != CUDA_SUCCESS {
+                return Err(CudaError::Synchronization(
+                    "Event synchronization failed".into(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_complete(&self) -> Result<bool, CudaError> {
+        match unsafe { cudaEventQuery(self.raw) } {
+            CUDA_SUCCESS => Ok(true),
+            CUDA_ERROR_NOT_READY => Ok(false),
+            code => Err(CudaError::Other(format!(
+                "Event query failed with error {}",
+                code
+            ))),
+        }
+    }
+}
+
+impl Drop for CudaEvent {
+    fn drop(&mut self) {
+        unsafe {
+            cudaEventDestroy(self.raw);
+        }
+    }
+}
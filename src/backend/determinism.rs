@@ -0,0 +1,29 @@
+use super::{Backend, BackendError};
+use crate::MlResult;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static DETERMINISTIC: AtomicBool = AtomicBool::new(false);
+
+/// Restricts every backend to kernels that give bit-identical results from run to run.
+///
+/// Backends switch to fixed-order implementations where their fast path isn't reproducible,
+/// and placing tensors on a backend that can't guarantee it becomes an error.
+pub fn set_deterministic(enabled: bool) {
+    DETERMINISTIC.store(enabled, Ordering::SeqCst);
+}
+
+pub fn is_deterministic() -> bool {
+    DETERMINISTIC.load(Ordering::SeqCst)
+}
+
+/// Fails if deterministic mode is on and `backend` has no reproducible implementation.
+pub(crate) fn check_deterministic(backend: &dyn Backend) -> MlResult<()> {
+    if is_deterministic() && !backend.capabilities().deterministic {
+        return Err(BackendError::Other(format!(
+            "Deterministic mode is enabled but device {} has no deterministic kernels",
+            backend.device()
+        ))
+        .into());
+    }
+    Ok(())
+}
@@ -1,27 +1,51 @@
 use std::collections::HashSet;
 use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 use std::sync::Mutex;
 use std::sync::Once;
 
 #[cfg(feature = "cuda")]
-use crate::backend::cuda::CudaDevice;
+use crate::backend::cuda::{get_device_count, CudaDevice};
 use crate::backend::feature::*;
-use crate::backend::BackendError;
-use crate::MlResult;
+#[cfg(any(feature = "mps", feature = "wgpu", feature = "opencl"))]
+use crate::backend::Backend;
+use crate::backend::{registered_backend, registered_backends, BackendCapabilities, BackendError};
+#[cfg(any(
+    feature = "cuda",
+    feature = "vulkan",
+    feature = "mps",
+    feature = "wgpu",
+    feature = "opencl"
+))]
+use crate::log::log_debug;
+use crate::log::{log_info, log_warn, record_fallback};
+use crate::{MlError, MlResult};
+
+/// Environment variable naming the default device, e.g. `CETANA_DEVICE=vulkan:1`.
+pub const DEVICE_ENV_VAR: &str = "CETANA_DEVICE";
 
 static INIT: Once = Once::new();
 static mut GLOBAL_DEVICE_MANAGER: Option<DeviceManager> = None;
 static mut DEFAULT_DEVICE: Option<Mutex<DeviceType>> = None;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DeviceType {
     Cpu,
+    /// Vulkan adapter, by index in the physical device list
     #[cfg(feature = "vulkan")]
-    Vulkan,
+    Vulkan(usize),
+    /// CUDA device ordinal
     #[cfg(feature = "cuda")]
-    Cuda,
+    Cuda(usize),
     #[cfg(feature = "mps")]
     Mps,
+    #[cfg(feature = "wgpu")]
+    Wgpu,
+    #[cfg(feature = "opencl")]
+    OpenCl,
+    /// Backend registered at runtime with `register_backend`
+    Custom(&'static str),
 }
 
 impl Display for DeviceType {
@@ -30,8 +54,79 @@ impl Display for DeviceType {
     }
 }
 
+// Backends that can be compiled in, whether or not this build has them
+const BACKEND_NAMES: [&str; 6] = ["cpu", "cuda", "vulkan", "mps", "wgpu", "opencl"];
+
+/// Parses device names as `CETANA_DEVICE` takes them: `cpu`, `cuda`, `vulkan`, `mps`, `wgpu`
+/// or `opencl`, with an optional index for CUDA and Vulkan (`cuda:1`), or the name of a
+/// registered custom backend. Backends this build was compiled without are rejected.
+impl FromStr for DeviceType {
+    type Err = MlError;
+
+    fn from_str(s: &str) -> MlResult<Self> {
+        let name = s.trim();
+        let lower = name.to_ascii_lowercase();
+        let (kind, index) = match lower.split_once(':') {
+            Some((kind, index)) if BACKEND_NAMES.contains(&kind) => (kind, Some(index)),
+            _ => (lower.as_str(), None),
+        };
+
+        if !BACKEND_NAMES.contains(&kind) {
+            return registered_backends()
+                .into_iter()
+                .find(|registered| *registered == name)
+                .map(DeviceType::Custom)
+                .ok_or_else(|| BackendError::Other(format!("Unknown device {:?}", name)).into());
+        }
+        if index.is_some() && !matches!(kind, "cuda" | "vulkan") {
+            return Err(
+                BackendError::Other(format!("Device {:?} does not take an index", name)).into(),
+            );
+        }
+
+        match kind {
+            "cpu" => Ok(DeviceType::Cpu),
+            #[cfg(feature = "cuda")]
+            "cuda" => Ok(DeviceType::Cuda(parse_index(name, index)?)),
+            #[cfg(feature = "vulkan")]
+            "vulkan" => Ok(DeviceType::Vulkan(parse_index(name, index)?)),
+            #[cfg(feature = "mps")]
+            "mps" => Ok(DeviceType::Mps),
+            #[cfg(feature = "wgpu")]
+            "wgpu" => Ok(DeviceType::Wgpu),
+            #[cfg(feature = "opencl")]
+            "opencl" => Ok(DeviceType::OpenCl),
+            _ => Err(BackendError::Other(format!(
+                "Device {:?} needs cetana to be built with the `{}` feature",
+                name, kind
+            ))
+            .into()),
+        }
+    }
+}
+
+#[cfg(any(feature = "cuda", feature = "vulkan"))]
+fn parse_index(name: &str, index: Option<&str>) -> MlResult<usize> {
+    index.map_or(Ok(0), |index| {
+        index
+            .parse()
+            .map_err(|_| BackendError::Other(format!("Invalid device index in {:?}", name)).into())
+    })
+}
+
+/// A device found while probing, with what is known about it.
+#[derive(Debug, Clone)]
+pub struct DeviceInfo {
+    pub device_type: DeviceType,
+    pub name: String,
+    /// Total device memory in bytes, when the backend reports it.
+    pub memory: Option<u64>,
+    pub capabilities: BackendCapabilities,
+}
+
 pub struct DeviceManager {
     available_devices: HashSet<DeviceType>,
+    devices: Vec<DeviceInfo>,
 }
 
 impl Default for DeviceManager {
@@ -42,58 +137,163 @@ impl Default for DeviceManager {
 
 impl DeviceManager {
     pub fn new() -> Self {
-        let mut available_devices = HashSet::new();
-
         // CPU is always available
-        available_devices.insert(DeviceType::Cpu);
+        #[allow(unused_mut)]
+        let mut devices = vec![DeviceInfo {
+            device_type: DeviceType::Cpu,
+            name: "CPU".to_string(),
+            memory: None,
+            capabilities: BackendCapabilities::new("CPU"),
+        }];
 
         // Check for CUDA support
         #[cfg(feature = "cuda")]
         {
-            println!("Checking CUDA support...");
-            match CudaDevice::new(0) {
-                Ok(_) => {
-                    println!("CUDA GPU support confirmed");
-                    available_devices.insert(DeviceType::Cuda);
+            log_debug!("Checking CUDA support...");
+            let count = get_device_count().unwrap_or(0);
+            for index in 0..count {
+                match CudaDevice::new(index) {
+                    Ok(device) => {
+                        log_info!(
+                            "CUDA GPU support confirmed ({}: {})",
+                            index,
+                            device.get_device_name()
+                        );
+                        devices.push(DeviceInfo {
+                            device_type: DeviceType::Cuda(index as usize),
+                            name: device.get_device_name().to_string(),
+                            memory: Some(device.get_total_memory() as u64),
+                            capabilities: device.capabilities(),
+                        });
+                    }
+                    Err(e) => log_info!("CUDA initialization failed for device {}: {}", index, e),
                 }
-                Err(e) => println!("CUDA initialization failed: {}", e),
             }
         }
 
         // Check for Vulkan support
         #[cfg(feature = "vulkan")]
         {
-            println!("Checking Vulkan support...");
+            log_debug!("Checking Vulkan support...");
             if let Ok(entry) = unsafe { ash::Entry::load() } {
                 match unsafe { entry.enumerate_instance_extension_properties(None) } {
-                    Ok(_) => match crate::backend::VulkanBackend::new() {
-                        Ok(_) => {
-                            println!("Vulkan GPU support confirmed");
-                            available_devices.insert(DeviceType::Vulkan);
-                            // cleanup backend
-                            // backend.cleanup();
+                    Ok(_) => match crate::backend::vulkan::VulkanCore::enumerate_adapters() {
+                        Ok(adapters) => {
+                            for adapter in adapters {
+                                log_info!(
+                                    "Vulkan GPU support confirmed ({}: {})",
+                                    adapter.index,
+                                    adapter.name
+                                );
+                                devices.push(DeviceInfo {
+                                    device_type: DeviceType::Vulkan(adapter.index),
+                                    name: adapter.name,
+                                    memory: Some(adapter.memory),
+                                    capabilities: adapter.capabilities,
+                                });
+                            }
                         }
-                        Err(e) => println!("Vulkan backend creation failed: {:?}", e),
+                        Err(e) => log_info!("Vulkan adapter enumeration failed: {:?}", e),
                     },
-                    Err(e) => println!("Vulkan extension enumeration failed: {:?}", e),
+                    Err(e) => log_info!("Vulkan extension enumeration failed: {:?}", e),
                 }
             } else {
-                println!("Failed to load Vulkan entry points");
+                log_info!("Failed to load Vulkan entry points");
             }
         }
 
-        println!("Available devices: {:?}", available_devices);
-        Self { available_devices }
+        // Check for Metal support
+        #[cfg(feature = "mps")]
+        {
+            log_debug!("Checking MPS support...");
+            match crate::backend::MpsBackend::shared() {
+                Ok(backend) => {
+                    log_info!("MPS GPU support confirmed ({})", backend.device_name());
+                    devices.push(DeviceInfo {
+                        device_type: DeviceType::Mps,
+                        name: backend.device_name().to_string(),
+                        memory: None,
+                        capabilities: backend.capabilities(),
+                    });
+                }
+                Err(e) => log_info!("MPS backend creation failed: {}", e),
+            }
+        }
+
+        // Check for wgpu support
+        #[cfg(feature = "wgpu")]
+        {
+            log_debug!("Checking wgpu support...");
+            match crate::backend::WgpuBackend::shared() {
+                Ok(backend) => {
+                    log_info!("wgpu GPU support confirmed ({})", backend.adapter_name());
+                    devices.push(DeviceInfo {
+                        device_type: DeviceType::Wgpu,
+                        name: backend.adapter_name().to_string(),
+                        memory: None,
+                        capabilities: backend.capabilities(),
+                    });
+                }
+                Err(e) => log_info!("wgpu backend creation failed: {}", e),
+            }
+        }
+
+        // Check for OpenCL support
+        #[cfg(feature = "opencl")]
+        {
+            log_debug!("Checking OpenCL support...");
+            match crate::backend::OpenClBackend::shared() {
+                Ok(backend) => {
+                    log_info!("OpenCL GPU support confirmed ({})", backend.device_name());
+                    devices.push(DeviceInfo {
+                        device_type: DeviceType::OpenCl,
+                        name: backend.device_name().to_string(),
+                        memory: None,
+                        capabilities: backend.capabilities(),
+                    });
+                }
+                Err(e) => log_info!("OpenCL backend creation failed: {}", e),
+            }
+        }
+
+        // Backends registered by downstream crates
+        for name in registered_backends() {
+            if let Some(backend) = registered_backend(name) {
+                log_info!("Custom backend registered ({})", name);
+                devices.push(DeviceInfo {
+                    device_type: DeviceType::Custom(name),
+                    name: name.to_string(),
+                    memory: None,
+                    capabilities: backend.capabilities(),
+                });
+            }
+        }
+
+        let available_devices = devices.iter().map(|info| info.device_type).collect();
+        log_info!("Available devices: {:?}", available_devices);
+        Self {
+            available_devices,
+            devices,
+        }
     }
 
     pub fn available_devices(&self) -> &HashSet<DeviceType> {
         &self.available_devices
     }
 
+    /// Every available device with its name and memory, in probe order.
+    pub fn devices(&self) -> &[DeviceInfo] {
+        &self.devices
+    }
+
+    pub fn device_info(&self, device: DeviceType) -> Option<&DeviceInfo> {
+        self.devices.iter().find(|info| info.device_type == device)
+    }
+
     pub fn select_device(&self, preferred: Option<DeviceType>) -> MlResult<DeviceType> {
         match preferred {
             Some(device_type) => {
-                if self.available_devices.contains(&device_type) {
+                if self.is_available(device_type) {
                     Ok(device_type)
                 } else {
                     Err(BackendError::Other(format!(
@@ -103,24 +303,54 @@ impl DeviceManager {
                     .into())
                 }
             }
-            None => {
-                #[cfg(feature = "cuda")]
-                if self.available_devices.contains(&DeviceType::Cuda) {
-                    return Ok(DeviceType::Cuda);
-                }
+            None => self.select_device_where(|_| true),
+        }
+    }
 
-                #[cfg(feature = "vulkan")]
-                if self.available_devices.contains(&DeviceType::Vulkan) {
-                    return Ok(DeviceType::Vulkan);
-                }
+    /// Picks the highest-priority device whose capabilities satisfy `predicate`, e.g. one
+    /// that can hold a buffer of a given size.
+    pub fn select_device_where<F>(&self, predicate: F) -> MlResult<DeviceType>
+    where
+        F: Fn(&BackendCapabilities) -> bool,
+    {
+        // The first device of each kind is its lowest index, since devices are probed in order
+        self.devices
+            .iter()
+            .filter(|info| predicate(&info.capabilities))
+            .min_by_key(|info| Self::priority(info.device_type))
+            .map(|info| info.device_type)
+            .ok_or_else(|| {
+                BackendError::Other(
+                    "No available device has the requested capabilities".to_string(),
+                )
+                .into()
+            })
+    }
 
-                #[cfg(feature = "mps")]
-                if self.available_devices.contains(&DeviceType::Mps) {
-                    return Ok(DeviceType::Mps);
-                }
+    /// Whether `device` was found while probing. Custom backends also count once they have
+    /// been registered after the probe.
+    pub fn is_available(&self, device: DeviceType) -> bool {
+        match device {
+            DeviceType::Custom(name) => registered_backend(name).is_some(),
+            _ => self.available_devices.contains(&device),
+        }
+    }
 
-                Ok(DeviceType::Cpu)
-            }
+    fn priority(device_type: DeviceType) -> u8 {
+        match device_type {
+            #[cfg(feature = "cuda")]
+            DeviceType::Cuda(_) => 0,
+            #[cfg(feature = "vulkan")]
+            DeviceType::Vulkan(_) => 1,
+            #[cfg(feature = "mps")]
+            DeviceType::Mps => 2,
+            #[cfg(feature = "wgpu")]
+            DeviceType::Wgpu => 3,
+            #[cfg(feature = "opencl")]
+            DeviceType::OpenCl => 4,
+            DeviceType::Cpu => 5,
+            // Never picked over the CPU unless asked for
+            DeviceType::Custom(_) => 6,
         }
     }
 
@@ -130,42 +360,54 @@ impl DeviceManager {
                 GLOBAL_DEVICE_MANAGER = Some(DeviceManager::new());
 
                 // Initialize default device
-                let manager = GLOBAL_DEVICE_MANAGER.as_ref().unwrap();
-
-                // Select default device based on priority and availability
-                let device_type = {
-                    #[cfg(feature = "cuda")]
-                    {
-                        if manager.available_devices.contains(&DeviceType::Cuda) {
-                            DeviceType::Cuda
-                        } else {
-                            DeviceType::Cpu
-                        }
-                    }
-                    #[cfg(all(feature = "vulkan", not(feature = "cuda")))]
-                    {
-                        if manager.available_devices.contains(&DeviceType::Vulkan) {
-                            DeviceType::Vulkan
-                        } else {
-                            DeviceType::Cpu
-                        }
-                    }
-                    #[cfg(not(any(feature = "cuda", feature = "vulkan")))]
-                    {
-                        DeviceType::Cpu
-                    }
-                };
+                let manager = (*std::ptr::addr_of!(GLOBAL_DEVICE_MANAGER))
+                    .as_ref()
+                    .unwrap();
+
+                // Select default device based on priority and availability, unless the
+                // environment asks for another one
+                let device_type =
+                    manager.resolve_default_device(std::env::var(DEVICE_ENV_VAR).ok().as_deref());
 
                 DEFAULT_DEVICE = Some(Mutex::new(device_type));
-                println!("Default device set to: {:?}", device_type);
+                log_info!("Default device set to: {:?}", device_type);
             });
-            GLOBAL_DEVICE_MANAGER.as_ref().unwrap()
+            (*std::ptr::addr_of!(GLOBAL_DEVICE_MANAGER))
+                .as_ref()
+                .unwrap()
+        }
+    }
+
+    // A device named in the environment wins if it's available. Otherwise the fallback is
+    // recorded and the best available device is used.
+    fn resolve_default_device(&self, requested: Option<&str>) -> DeviceType {
+        let selected = self.select_device(None).unwrap_or(DeviceType::Cpu);
+        let Some(requested) = requested else {
+            return selected;
+        };
+
+        match requested.parse::<DeviceType>() {
+            Ok(device) if self.is_available(device) => device,
+            Ok(device) => {
+                record_fallback(
+                    device,
+                    selected,
+                    format!("{} names a device that is not available", DEVICE_ENV_VAR),
+                );
+                selected
+            }
+            Err(e) => {
+                log_warn!("Ignoring {}={:?}: {}", DEVICE_ENV_VAR, requested, e);
+                selected
+            }
         }
     }
 
+    /// Makes `device` the device every tensor created from now on is placed on. It must be
+    /// one the manager found, or a registered custom backend.
     pub fn set_default_device(device: DeviceType) -> MlResult<()> {
         let manager = Self::global();
-        if manager.available_devices.contains(&device) {
+        if manager.is_available(device) {
             unsafe {
                 if let Some(ref mutex) = DEFAULT_DEVICE {
                     *mutex.lock().unwrap() = device;
@@ -181,7 +423,11 @@ impl DeviceManager {
         }
     }
 
+    /// The device new tensors are placed on. The first call probes for devices and picks
+    /// the default: the device named by the `CETANA_DEVICE` environment variable if set and
+    /// available, otherwise the highest-priority one found.
     pub fn get_default_device() -> DeviceType {
+        Self::global();
         unsafe {
             if let Some(ref mutex) = DEFAULT_DEVICE {
                 *mutex.lock().unwrap()
@@ -218,7 +464,7 @@ impl DeviceManager {
 
         // Add GPU features if available
         #[cfg(feature = "cuda")]
-        if self.available_devices.contains(&DeviceType::Cuda) {
+        if self.available_devices.contains(&DeviceType::Cuda(0)) {
             features.add_feature(
                 GPU_FEATURE_FP16,
                 true,
@@ -242,12 +488,6 @@ fn cuda_is_available() -> bool {
     false
 }
 
-#[cfg(feature = "mps")]
-fn mps_is_available() -> bool {
-    // Implement MPS availability check
-    false
-}
-
 pub trait Device {
     fn new() -> MlResult<Self>
     where
@@ -284,8 +524,70 @@ mod tests {
 
         // Requesting unavailable device should return error
         #[cfg(feature = "cuda")]
-        assert!(manager.select_device(Some(DeviceType::Cuda)).is_err());
+        assert!(manager.select_device(Some(DeviceType::Cuda(0))).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_device_enumeration() {
+        let manager = DeviceManager::new();
+
+        let cpu = manager.device_info(DeviceType::Cpu).unwrap();
+        assert_eq!(cpu.name, "CPU");
+        assert_eq!(manager.devices().len(), manager.available_devices().len());
+        assert!(manager
+            .devices()
+            .iter()
+            .all(|info| manager.available_devices().contains(&info.device_type)));
+    }
+
+    #[test]
+    fn test_select_device_by_capabilities() -> MlResult<()> {
+        let manager = DeviceManager::new();
+
+        let cpu = manager.device_info(DeviceType::Cpu).unwrap();
+        assert_eq!(cpu.capabilities.device_name, "CPU");
+
+        let device = manager.select_device_where(|caps| caps.max_buffer_size.is_none())?;
+        let info = manager.device_info(device).unwrap();
+        assert!(info.capabilities.max_buffer_size.is_none());
+
+        let device = manager.select_device_where(|_| true)?;
+        assert_eq!(device, manager.select_device(None)?);
+
+        assert!(manager.select_device_where(|_| false).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_device_names() -> MlResult<()> {
+        assert_eq!(" CPU ".parse::<DeviceType>()?, DeviceType::Cpu);
+        assert!("cpu:1".parse::<DeviceType>().is_err());
+        assert!("tpu".parse::<DeviceType>().is_err());
 
+        #[cfg(feature = "cuda")]
+        assert_eq!("cuda:1".parse::<DeviceType>()?, DeviceType::Cuda(1));
+        #[cfg(not(feature = "cuda"))]
+        assert!("cuda"
+            .parse::<DeviceType>()
+            .unwrap_err()
+            .to_string()
+            .contains("`cuda` feature"));
+        #[cfg(feature = "vulkan")]
+        assert_eq!("vulkan".parse::<DeviceType>()?, DeviceType::Vulkan(0));
+        #[cfg(feature = "vulkan")]
+        assert!("vulkan:x".parse::<DeviceType>().is_err());
         Ok(())
     }
+
+    #[test]
+    fn test_default_device_from_environment() {
+        let manager = DeviceManager::new();
+        let selected = manager.select_device(None).unwrap();
+
+        assert_eq!(manager.resolve_default_device(None), selected);
+        assert_eq!(manager.resolve_default_device(Some("cpu")), DeviceType::Cpu);
+        assert_eq!(manager.resolve_default_device(Some("bogus")), selected);
+    }
 }
@@ -0,0 +1,255 @@
+//! Running whole ONNX graphs on an external runtime.
+//!
+//! A [`Delegate`] is a layer that hands its input to a model loaded by onnxruntime
+//! (`onnxruntime` feature) or tract (`tract` feature), so a network can use parts written
+//! with operators cetana doesn't implement yet. Runtimes implemented outside this crate are
+//! added with [`register_runtime`]. Delegated layers run inference only.
+//!
+//! ```ignore
+//! let backbone = Delegate::open("backbone.onnx")?;
+//! let head = Linear::new(512, 10, true)?;
+//! let logits = head.forward(&backbone.forward(&images)?)?;
+//! ```
+
+#[cfg(feature = "onnxruntime")]
+mod onnxruntime;
+#[cfg(feature = "tract")]
+mod tract;
+
+#[cfg(feature = "fs")]
+use std::path::Path;
+use std::sync::{Arc, OnceLock, RwLock};
+
+use super::BackendError;
+use crate::log::log_warn;
+use crate::nn::Layer;
+use crate::tensor::Tensor;
+use crate::MlResult;
+
+#[cfg(feature = "onnxruntime")]
+pub use self::onnxruntime::OnnxRuntime;
+#[cfg(feature = "tract")]
+pub use self::tract::TractRuntime;
+
+/// A runtime that loads and runs ONNX models.
+pub trait ExternalRuntime: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Whether the runtime can be used in this process, e.g. its shared library was found.
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    /// Loads the ONNX model in `model`.
+    fn load(&self, model: &[u8]) -> MlResult<Box<dyn ExternalSession>>;
+}
+
+/// A model loaded by an [`ExternalRuntime`].
+pub trait ExternalSession: Send + Sync {
+    fn input_names(&self) -> Vec<String>;
+
+    fn output_names(&self) -> Vec<String>;
+
+    /// Runs the model on one tensor per input, in the order of
+    /// [`ExternalSession::input_names`], and returns one tensor per output.
+    fn run(&self, inputs: &[&Tensor]) -> MlResult<Vec<Tensor>>;
+}
+
+fn registry() -> &'static RwLock<Vec<Arc<dyn ExternalRuntime>>> {
+    static REGISTRY: OnceLock<RwLock<Vec<Arc<dyn ExternalRuntime>>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| RwLock::new(Vec::new()))
+}
+
+/// Makes a runtime implemented outside this crate available to [`Delegate::load`], after
+/// the built-in ones.
+pub fn register_runtime(runtime: Arc<dyn ExternalRuntime>) -> MlResult<()> {
+    let mut runtimes = registry()
+        .write()
+        .map_err(|_| BackendError::Other("Runtime registry poisoned".to_string()))?;
+    if runtimes.iter().any(|r| r.name() == runtime.name()) {
+        return Err(BackendError::Other(format!(
+            "A runtime named {:?} is already registered",
+            runtime.name()
+        ))
+        .into());
+    }
+    runtimes.push(runtime);
+    Ok(())
+}
+
+/// Removes a registered runtime. Delegates already loaded by it keep working.
+pub fn unregister_runtime(name: &str) -> Option<Arc<dyn ExternalRuntime>> {
+    let mut runtimes = registry().write().ok()?;
+    let index = runtimes.iter().position(|r| r.name() == name)?;
+    Some(runtimes.remove(index))
+}
+
+/// Every runtime that can be used now, in the order [`Delegate::load`] tries them:
+/// onnxruntime, then tract, then registered runtimes.
+pub fn available_runtimes() -> Vec<Arc<dyn ExternalRuntime>> {
+    #[allow(unused_mut)]
+    let mut runtimes: Vec<Arc<dyn ExternalRuntime>> = Vec::new();
+    #[cfg(feature = "onnxruntime")]
+    runtimes.push(Arc::new(OnnxRuntime));
+    #[cfg(feature = "tract")]
+    runtimes.push(Arc::new(TractRuntime));
+    if let Ok(registered) = registry().read() {
+        runtimes.extend(registered.iter().cloned());
+    }
+    runtimes.retain(|runtime| runtime.is_available());
+    runtimes
+}
+
+/// A layer whose forward pass runs an ONNX model on an external runtime.
+pub struct Delegate {
+    runtime: &'static str,
+    session: Box<dyn ExternalSession>,
+}
+
+impl Delegate {
+    /// Loads `model` on the first available runtime that accepts it.
+    pub fn load(model: &[u8]) -> MlResult<Self> {
+        let runtimes = available_runtimes();
+        if runtimes.is_empty() {
+            return Err(BackendError::Other(
+                "No external runtime is available; enable the `onnxruntime` or `tract` feature \
+                 or register one"
+                    .to_string(),
+            )
+            .into());
+        }
+
+        let mut errors = Vec::new();
+        for runtime in runtimes {
+            match Self::with_runtime(runtime.as_ref(), model) {
+                Ok(delegate) => return Ok(delegate),
+                Err(e) => {
+                    log_warn!("{} could not load the model: {}", runtime.name(), e);
+                    errors.push(format!("{}: {}", runtime.name(), e));
+                }
+            }
+        }
+        Err(BackendError::Other(format!(
+            "No runtime could load the model ({})",
+            errors.join("; ")
+        ))
+        .into())
+    }
+
+    /// Loads the ONNX model at `path` on the first available runtime that accepts it.
+    #[cfg(feature = "fs")]
+    pub fn open<P: AsRef<Path>>(path: P) -> MlResult<Self> {
+        let bytes = std::fs::read(path).map_err(|e| format!("Failed to read file: {}", e))?;
+        Self::load(&bytes)
+    }
+
+    /// Loads `model` on `runtime`.
+    pub fn with_runtime(runtime: &dyn ExternalRuntime, model: &[u8]) -> MlResult<Self> {
+        Ok(Self {
+            runtime: runtime.name(),
+            session: runtime.load(model)?,
+        })
+    }
+
+    /// The name of the runtime running the model.
+    pub fn runtime(&self) -> &'static str {
+        self.runtime
+    }
+
+    pub fn input_names(&self) -> Vec<String> {
+        self.session.input_names()
+    }
+
+    pub fn output_names(&self) -> Vec<String> {
+        self.session.output_names()
+    }
+
+    /// Runs a model with several inputs or outputs; see [`ExternalSession::run`].
+    pub fn run(&self, inputs: &[&Tensor]) -> MlResult<Vec<Tensor>> {
+        let expected = self.session.input_names().len();
+        if inputs.len() != expected {
+            return Err(format!(
+                "The delegated model takes {} inputs, got {}",
+                expected,
+                inputs.len()
+            )
+            .into());
+        }
+        self.session.run(inputs)
+    }
+}
+
+impl Layer for Delegate {
+    /// Runs a model with one input, returning its first output.
+    fn forward(&self, input: &Tensor) -> MlResult<Tensor> {
+        self.run(&[input])?
+            .into_iter()
+            .next()
+            .ok_or_else(|| "The delegated model has no outputs".into())
+    }
+
+    fn backward(
+        &mut self,
+        _input: &Tensor,
+        _grad_output: &Tensor,
+        _learning_rate: f32,
+    ) -> MlResult<Tensor> {
+        Err(format!("Layers delegated to {} run inference only", self.runtime).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // "Runs" models whose bytes are a scale factor
+    struct Scaler;
+
+    struct ScalerSession(f32);
+
+    impl ExternalRuntime for Scaler {
+        fn name(&self) -> &'static str {
+            "scaler"
+        }
+
+        fn load(&self, model: &[u8]) -> MlResult<Box<dyn ExternalSession>> {
+            let factor = std::str::from_utf8(model)
+                .ok()
+                .and_then(|text| text.parse().ok())
+                .ok_or("not a scale factor")?;
+            Ok(Box::new(ScalerSession(factor)))
+        }
+    }
+
+    impl ExternalSession for ScalerSession {
+        fn input_names(&self) -> Vec<String> {
+            vec!["x".to_string()]
+        }
+
+        fn output_names(&self) -> Vec<String> {
+            vec!["y".to_string()]
+        }
+
+        fn run(&self, inputs: &[&Tensor]) -> MlResult<Vec<Tensor>> {
+            Ok(vec![inputs[0].mul_scalar(self.0)?])
+        }
+    }
+
+    #[test]
+    fn test_delegate() -> MlResult<()> {
+        register_runtime(Arc::new(Scaler))?;
+        assert!(register_runtime(Arc::new(Scaler)).is_err());
+        assert!(available_runtimes().iter().any(|r| r.name() == "scaler"));
+
+        let mut delegate = Delegate::load(b"2.5")?;
+        assert_eq!(delegate.runtime(), "scaler");
+        let input = Tensor::from_vec(vec![1.0, -2.0], &[1, 2])?;
+        assert_eq!(delegate.forward(&input)?.data(), &[2.5, -5.0]);
+        assert!(delegate.run(&[&input, &input]).is_err());
+        assert!(delegate.backward(&input, &input, 0.1).is_err());
+        assert!(Delegate::load(b"not a model").is_err());
+
+        assert!(unregister_runtime("scaler").is_some());
+        Ok(())
+    }
+}
@@ -0,0 +1,86 @@
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::OnceLock;
+
+use ort::session::{Session, SessionInputValue};
+use ort::value::Tensor as OrtTensor;
+
+use super::{ExternalRuntime, ExternalSession};
+use crate::tensor::Tensor;
+use crate::MlResult;
+
+/// onnxruntime, loaded from its shared library at run time. It is available when the
+/// library is found, e.g. through `ORT_DYLIB_PATH`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OnnxRuntime;
+
+struct OnnxSession {
+    session: Session,
+}
+
+fn ort_error(e: impl std::fmt::Display) -> String {
+    format!("onnxruntime: {}", e)
+}
+
+impl ExternalRuntime for OnnxRuntime {
+    fn name(&self) -> &'static str {
+        "onnxruntime"
+    }
+
+    fn is_available(&self) -> bool {
+        // Loading a missing library panics inside ort, so probe it once
+        static AVAILABLE: OnceLock<bool> = OnceLock::new();
+        *AVAILABLE.get_or_init(|| {
+            catch_unwind(AssertUnwindSafe(|| Session::builder().is_ok())).unwrap_or(false)
+        })
+    }
+
+    fn load(&self, model: &[u8]) -> MlResult<Box<dyn ExternalSession>> {
+        let session = Session::builder()
+            .and_then(|builder| builder.commit_from_memory(model))
+            .map_err(ort_error)?;
+        Ok(Box::new(OnnxSession { session }))
+    }
+}
+
+impl ExternalSession for OnnxSession {
+    fn input_names(&self) -> Vec<String> {
+        self.session.inputs.iter().map(|i| i.name.clone()).collect()
+    }
+
+    fn output_names(&self) -> Vec<String> {
+        self.session
+            .outputs
+            .iter()
+            .map(|o| o.name.clone())
+            .collect()
+    }
+
+    fn run(&self, inputs: &[&Tensor]) -> MlResult<Vec<Tensor>> {
+        let values = self
+            .session
+            .inputs
+            .iter()
+            .zip(inputs)
+            .map(|(input, tensor)| {
+                let value = OrtTensor::<f32>::from_array((
+                    tensor.shape().to_vec(),
+                    tensor.data().to_vec(),
+                ))?;
+                Ok((input.name.clone(), SessionInputValue::from(value)))
+            })
+            .collect::<ort::Result<Vec<_>>>()
+            .map_err(ort_error)?;
+        let outputs = self.session.run(values).map_err(ort_error)?;
+
+        self.output_names()
+            .iter()
+            .map(|name| {
+                let (shape, data) = outputs[name.as_str()]
+                    .try_extract_raw_tensor::<f32>()
+                    .map_err(ort_error)?;
+                let shape: Vec<usize> = shape.iter().map(|&d| d as usize).collect();
+                Tensor::from_vec(data.to_vec(), &shape)
+            })
+            .collect()
+    }
+}
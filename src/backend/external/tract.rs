@@ -0,0 +1,81 @@
+use std::io::Cursor;
+
+use tract_onnx::prelude::{
+    Framework, TValue, TVec, Tensor as TractTensor, TypedModel, TypedRunnableModel,
+};
+
+use super::{ExternalRuntime, ExternalSession};
+use crate::tensor::Tensor;
+use crate::MlResult;
+
+/// tract, a pure-Rust ONNX runtime. Models are optimized for the shapes declared in the
+/// file when they are loaded.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TractRuntime;
+
+struct TractSession {
+    plan: TypedRunnableModel<TypedModel>,
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+}
+
+fn tract_error(e: impl std::fmt::Display) -> String {
+    format!("tract: {}", e)
+}
+
+impl ExternalRuntime for TractRuntime {
+    fn name(&self) -> &'static str {
+        "tract"
+    }
+
+    fn load(&self, model: &[u8]) -> MlResult<Box<dyn ExternalSession>> {
+        let plan = tract_onnx::onnx()
+            .model_for_read(&mut Cursor::new(model))
+            .and_then(|model| model.into_optimized())
+            .and_then(|model| model.into_runnable())
+            .map_err(tract_error)?;
+
+        let graph = plan.model();
+        let names = |outlets: &[tract_onnx::prelude::OutletId]| {
+            outlets
+                .iter()
+                .map(|outlet| graph.node(outlet.node).name.clone())
+                .collect()
+        };
+        let inputs = names(graph.input_outlets().map_err(tract_error)?);
+        let outputs = names(graph.output_outlets().map_err(tract_error)?);
+        Ok(Box::new(TractSession {
+            plan,
+            inputs,
+            outputs,
+        }))
+    }
+}
+
+impl ExternalSession for TractSession {
+    fn input_names(&self) -> Vec<String> {
+        self.inputs.clone()
+    }
+
+    fn output_names(&self) -> Vec<String> {
+        self.outputs.clone()
+    }
+
+    fn run(&self, inputs: &[&Tensor]) -> MlResult<Vec<Tensor>> {
+        let inputs = inputs
+            .iter()
+            .map(|tensor| TractTensor::from_shape(tensor.shape(), tensor.data()).map(TValue::from))
+            .collect::<Result<TVec<_>, _>>()
+            .map_err(tract_error)?;
+        let outputs = self.plan.run(inputs).map_err(tract_error)?;
+
+        outputs
+            .iter()
+            .map(|value| {
+                let value = value.cast_to::<f32>().map_err(tract_error)?;
+                let data = value.as_slice::<f32>().map_err(tract_error)?;
+                Tensor::from_vec(data.to_vec(), value.shape())
+            })
+            .collect()
+    }
+}
@@ -29,7 +29,7 @@ impl DeviceFeatures {
         self.features
             .iter()
             .find(|f| f.name == feature_name)
-            .map_or(false, |f| f.supported)
+            .is_some_and(|f| f.supported)
     }
 }
 
@@ -37,10 +37,15 @@ impl DeviceFeatures {
 pub const CPU_FEATURE_AVX: &str = "avx";
 pub const CPU_FEATURE_AVX2: &str = "avx2";
 pub const CPU_FEATURE_AVX512F: &str = "avx512f";
+#[allow(dead_code)]
 pub const CPU_FEATURE_SSE4_1: &str = "sse4.1";
+#[allow(dead_code)]
 pub const CPU_FEATURE_SSE4_2: &str = "sse4.2";
 
-// GPU Features
+// GPU Features, only used by the GPU backends enabled with their cargo features
+#[allow(dead_code)]
 pub const GPU_FEATURE_FP16: &str = "fp16";
+#[allow(dead_code)]
 pub const GPU_FEATURE_FP64: &str = "fp64";
+#[allow(dead_code)]
 pub const GPU_FEATURE_TENSOR_CORES: &str = "tensor_cores";
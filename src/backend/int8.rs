@@ -0,0 +1,48 @@
+/// `[m, n] x [n, k]` product of int8 matrices, with every dot product accumulated in i32 so
+/// it can't overflow for `n` up to 2^17. Mismatched inputs give zeros.
+pub fn matmul_i8(a: &[i8], b: &[i8], m: usize, n: usize, k: usize) -> Vec<i32> {
+    let mut result = vec![0; m * k];
+    if a.len() != m * n || b.len() != n * k {
+        return result;
+    }
+
+    for i in 0..m {
+        for l in 0..n {
+            let a_val = a[i * n + l] as i32;
+            for j in 0..k {
+                result[i * k + j] += a_val * b[l * k + j] as i32;
+            }
+        }
+    }
+    result
+}
+
+/// Maps i32 accumulators back to int8: each value is multiplied by `scale` (usually
+/// `a_scale * b_scale / output_scale`), rounded to nearest even, offset by `zero_point` and
+/// saturated to the int8 range.
+pub fn requantize(acc: &[i32], scale: f32, zero_point: i32) -> Vec<i8> {
+    acc.iter()
+        .map(|&value| {
+            let scaled = (value as f32 * scale).round_ties_even() as i32;
+            scaled.saturating_add(zero_point).clamp(-128, 127) as i8
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matmul_i8_and_requantize() {
+        // [2, 3] x [3, 2], at the edges of the int8 range
+        let a = [127, -128, 1, 2, 3, 4];
+        let b = [127, 1, -128, 2, 1, 3];
+        let acc = matmul_i8(&a, &b, 2, 3, 2);
+        assert_eq!(acc, vec![32514, -126, -126, 20]);
+
+        assert_eq!(requantize(&acc, 1.0 / 256.0, 0), vec![127, 0, 0, 0]);
+        assert_eq!(requantize(&[-126, 20, 6, 10], 0.25, 1), vec![-31, 6, 3, 3]);
+        assert_eq!(requantize(&[i32::MIN, i32::MAX], 1.0, 0), vec![-128, 127]);
+    }
+}
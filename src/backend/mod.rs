@@ -1,27 +1,62 @@
 use std::fmt::{Debug, Display, Formatter};
+use std::sync::Arc;
 
+mod buffer;
+mod capabilities;
+mod conv;
+mod determinism;
 mod device;
+pub mod external;
 mod feature;
-pub use device::{Device, DeviceManager, DeviceType};
+mod int8;
+mod pool;
+mod registry;
+mod transfer;
+pub use buffer::{DeviceBuffer, DeviceOp, FusedOp, ReduceOp};
+pub use capabilities::{BackendCapabilities, DType};
+pub use conv::{conv2d_im2col, depthwise_conv2d, im2col, Conv2dShape};
+pub(crate) use determinism::check_deterministic;
+pub use determinism::{is_deterministic, set_deterministic};
+pub use device::{Device, DeviceInfo, DeviceManager, DeviceType, DEVICE_ENV_VAR};
+pub use external::{
+    available_runtimes, register_runtime, unregister_runtime, Delegate, ExternalRuntime,
+    ExternalSession,
+};
 pub use feature::DeviceFeatures;
+pub use int8::{matmul_i8, requantize};
+pub use pool::MemoryStats;
+#[cfg(test)]
+pub(crate) use registry::tests::HostBackend;
+pub use registry::{
+    register_backend, registered_backend, registered_backends, unregister_backend, SharedBackend,
+};
+pub use transfer::{set_transfer_policy, transfer_policy, with_transfer_policy, TransferPolicy};
 
 #[cfg(feature = "cpu")]
 mod cpu;
 #[cfg(feature = "cuda")]
-mod cuda;
+pub mod cuda;
 #[cfg(feature = "mps")]
 mod mps;
+#[cfg(feature = "opencl")]
+mod opencl;
 #[cfg(feature = "vulkan")]
-mod vulkan;
+pub mod vulkan;
+#[cfg(feature = "wgpu")]
+mod wgpu;
 
+#[cfg(feature = "wgpu")]
+pub use self::wgpu::{WgpuBackend, WgpuError};
 #[cfg(feature = "cpu")]
 pub use cpu::CpuBackend;
 #[cfg(feature = "cuda")]
-pub use cuda::{CudaBackend, CudaBackendError};
+pub use cuda::{CudaBackend, CudaBackendError, CudaBuffer, CudaEvent, CudaStream, PinnedBuffer};
 #[cfg(feature = "mps")]
-pub use mps::MpsError;
+pub use mps::{MpsBackend, MpsError};
+#[cfg(feature = "opencl")]
+pub use opencl::{OpenClBackend, OpenClError};
 #[cfg(feature = "vulkan")]
-pub use vulkan::{VulkanBackend, VulkanError};
+pub use vulkan::{StagingBuffer, VulkanBackend, VulkanError, VulkanTransfer};
 
 use crate::MlResult;
 
@@ -29,9 +64,21 @@ pub trait Backend: Debug {
     fn execute_compute(&self, dimensions: [u32; 3]) -> MlResult<()>;
 
     fn device(&self) -> DeviceType;
+
+    /// Reports what the backend supports on its device.
+    fn capabilities(&self) -> BackendCapabilities {
+        BackendCapabilities::new(self.device().to_string())
+    }
+
     fn add(&self, a: &[f32], b: &[f32]) -> Vec<f32>;
     fn multiply(&self, a: &[f32], b: &[f32]) -> Vec<f32>;
     fn matmul(&self, a: &[f32], b: &[f32], m: usize, n: usize, k: usize) -> Vec<f32>;
+
+    /// `[m, n] x [n, k]` product of int8 matrices, accumulated in i32. Pair it with
+    /// [`requantize`] to get int8 activations back.
+    fn matmul_i8(&self, a: &[i8], b: &[i8], m: usize, n: usize, k: usize) -> Vec<i32> {
+        matmul_i8(a, b, m, n, k)
+    }
     fn div(&self, a: &[f32], b: &[f32]) -> Vec<f32>;
     fn sub(&self, a: &[f32], b: &[f32]) -> Vec<f32>;
     fn exp(&self, a: &[f32]) -> Vec<f32>;
@@ -40,6 +87,66 @@ pub trait Backend: Debug {
     fn sqrt(&self, a: &[f32]) -> Vec<f32>;
     fn sum(&self, a: &[f32]) -> f32;
     fn mean(&self, a: &[f32]) -> f32;
+
+    /// Reduces `a`, laid out row-major as `shape`, along `axis`. The result holds the
+    /// remaining dimensions in the same order.
+    fn reduce(&self, op: ReduceOp, a: &[f32], shape: &[usize], axis: usize) -> Vec<f32> {
+        let (outer, len, inner) = split_axis(shape, axis);
+        op.apply(a, outer, len, inner)
+    }
+
+    /// 2D convolution (cross-correlation) of an NCHW `input` with `weight`, giving an NCHW
+    /// output of `shape.output()`. The default unfolds the input with im2col and runs this
+    /// backend's `matmul`.
+    fn conv2d(&self, input: &[f32], weight: &[f32], shape: &Conv2dShape) -> Vec<f32> {
+        conv2d_im2col(self, input, weight, shape)
+    }
+
+    /// Copies `data` into device memory. Backends that compute on host memory return
+    /// `None`, which keeps tensors on the host.
+    fn upload(&self, _data: &[f32]) -> Option<Arc<dyn DeviceBuffer>> {
+        None
+    }
+
+    /// Runs `op` on buffers returned by `upload` or by earlier calls, leaving the result on
+    /// the device. `None` means the op can't run on the device and the caller should fall
+    /// back to the slice-based methods.
+    fn execute_on_device(
+        &self,
+        _op: DeviceOp,
+        _inputs: &[&dyn DeviceBuffer],
+    ) -> Option<Arc<dyn DeviceBuffer>> {
+        None
+    }
+
+    /// Device memory held by the backend's caching allocator. `None` for backends that
+    /// don't cache allocations.
+    fn memory_stats(&self) -> Option<MemoryStats> {
+        None
+    }
+
+    /// Hands cached device memory that no buffer is using back to the driver.
+    fn empty_cache(&self) -> MlResult<()> {
+        Ok(())
+    }
+
+    /// Runs a chain of elementwise `ops` over `inputs` in a single kernel, without
+    /// materializing the intermediate results. `None` means the backend can't fuse them and
+    /// the caller should evaluate the chain on the host.
+    fn execute_fused(
+        &self,
+        _ops: &[FusedOp],
+        _inputs: &[&dyn DeviceBuffer],
+    ) -> Option<Arc<dyn DeviceBuffer>> {
+        None
+    }
+}
+
+/// Views `shape` as `[outer, shape[axis], inner]`, the layout axis reductions work on.
+pub fn split_axis(shape: &[usize], axis: usize) -> (usize, usize, usize) {
+    let outer = shape[..axis].iter().product();
+    let inner = shape[axis + 1..].iter().product();
+    (outer, shape[axis], inner)
 }
 
 #[derive(Debug)]
@@ -52,6 +159,10 @@ pub enum BackendError {
     CudaError(CudaBackendError),
     #[cfg(feature = "mps")]
     MpsError(MpsError),
+    #[cfg(feature = "wgpu")]
+    WgpuError(WgpuError),
+    #[cfg(feature = "opencl")]
+    OpenClError(OpenClError),
     Other(String),
 }
 
@@ -66,6 +177,10 @@ impl Display for BackendError {
             BackendError::CudaError(e) => write!(f, "{}", e),
             #[cfg(feature = "mps")]
             BackendError::MpsError(e) => write!(f, "{}", e),
+            #[cfg(feature = "wgpu")]
+            BackendError::WgpuError(e) => write!(f, "{}", e),
+            #[cfg(feature = "opencl")]
+            BackendError::OpenClError(e) => write!(f, "{}", e),
             BackendError::Other(s) => write!(f, "{}", s),
         }
     }
@@ -98,3 +213,17 @@ impl From<MpsError> for BackendError {
         BackendError::MpsError(err)
     }
 }
+
+#[cfg(feature = "wgpu")]
+impl From<WgpuError> for BackendError {
+    fn from(err: WgpuError) -> Self {
+        BackendError::WgpuError(err)
+    }
+}
+
+#[cfg(feature = "opencl")]
+impl From<OpenClError> for BackendError {
+    fn from(err: OpenClError) -> Self {
+        BackendError::OpenClError(err)
+    }
+}
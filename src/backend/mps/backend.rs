@@ -1,238 +1,320 @@
-use super::{MpsCompute, MpsDevice, MpsError};
+use super::{MpsBuffer, MpsCompute, MpsDevice, MpsError};
 use crate::backend::feature::{DeviceFeatures, GPU_FEATURE_FP16, GPU_FEATURE_FP64};
-use crate::backend::{Backend, DeviceType};
-use metal::{Buffer, MTLDataType, MTLResourceOptions, MTLSize};
-use std::sync::Arc;
+use crate::backend::{Backend, BackendCapabilities, Device, DeviceBuffer, DeviceOp, DeviceType};
+use crate::MlResult;
+use std::fmt::Debug;
+use std::sync::{Arc, OnceLock};
 
 pub struct MpsBackend {
     device: Arc<MpsDevice>,
     compute: MpsCompute,
 }
 
-impl MpsBackend {
-    pub fn new() -> Result<Self, MpsError> {
-        let device = Arc::new(MpsDevice::new()?);
-        let compute = MpsCompute::new(Arc::clone(&device))?;
+impl Debug for MpsBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "MpsBackend({})", self.device.name())
+    }
+}
 
-        Ok(Self { device, compute })
+impl MpsBackend {
+    /// Returns a process-wide backend instance so tensors don't each load the shader library.
+    pub fn shared() -> MlResult<Arc<MpsBackend>> {
+        static SHARED: OnceLock<Option<Arc<MpsBackend>>> = OnceLock::new();
+
+        SHARED
+            .get_or_init(|| MpsBackend::new().ok().map(Arc::new))
+            .clone()
+            .ok_or_else(|| MpsError::DeviceNotFound.into())
     }
 
-    pub fn create_buffer<T: Copy>(&self, data: &[T]) -> Result<Buffer, MpsError> {
-        let buffer = self.device.device().new_buffer_with_data(
-            data.as_ptr() as *const _,
-            (data.len() * std::mem::size_of::<T>()) as u64,
-            MTLResourceOptions::StorageModeShared,
-        );
+    pub fn device_name(&self) -> &str {
+        self.device.name()
+    }
 
-        buffer.ok_or(MpsError::BufferCreationError)
+    pub fn compute(&self) -> &MpsCompute {
+        &self.compute
     }
 
-    pub fn matmul(
-        &self,
-        a: &Buffer,
-        b: &Buffer,
-        m: usize,
-        n: usize,
-        k: usize,
-    ) -> Result<Buffer, MpsError> {
-        if m == 0 || n == 0 || k == 0 {
-            return Err(MpsError::InvalidDimensions);
+    fn binary(&self, kernel: &str, a: &[f32], b: &[f32]) -> Vec<f32> {
+        if a.len() != b.len() || a.is_empty() {
+            return vec![0.0; a.len()];
         }
 
-        let result_size = m * n * std::mem::size_of::<f32>();
-        let result_buffer = self
-            .device
-            .device()
-            .new_buffer(result_size as u64, MTLResourceOptions::StorageModeShared)
-            .ok_or(MpsError::BufferCreationError)?;
-
-        let library = self
-            .device
-            .device()
-            .new_library_with_source(
-                include_str!("../../../shaders/metal/matrix_ops.metal"),
-                &metal::CompileOptions::new(),
-            )
-            .map_err(|_| MpsError::ShaderCompilationError)?;
+        let a_buffer = self.compute.create_buffer(a);
+        let b_buffer = self.compute.create_buffer(b);
+        match self
+            .compute
+            .binary_op(kernel, &a_buffer, &b_buffer, a.len())
+        {
+            Ok(result) => self.compute.read_buffer(&result, a.len()),
+            Err(_) => vec![0.0; a.len()],
+        }
+    }
 
-        let kernel = library
-            .get_function("matrix_multiply", None)
-            .map_err(|_| MpsError::ShaderCompilationError)?;
+    fn unary(&self, kernel: &str, a: &[f32], power: f32) -> Vec<f32> {
+        if a.is_empty() {
+            return Vec::new();
+        }
 
-        let pipeline = self
-            .device
-            .device()
-            .new_compute_pipeline_state_with_function(&kernel)
-            .map_err(|_| MpsError::ShaderCompilationError)?;
+        let input = self.compute.create_buffer(a);
+        match self.compute.unary_op(kernel, &input, a.len(), power) {
+            Ok(result) => self.compute.read_buffer(&result, a.len()),
+            Err(_) => vec![0.0; a.len()],
+        }
+    }
+}
 
-        // Create dimension buffers
-        let m_buffer = self.create_buffer(&[m as u32])?;
-        let n_buffer = self.create_buffer(&[n as u32])?;
-        let k_buffer = self.create_buffer(&[k as u32])?;
+impl Device for MpsBackend {
+    fn new() -> MlResult<Self> {
+        let device = Arc::new(MpsDevice::new()?);
+        let compute = MpsCompute::new(Arc::clone(&device))?;
 
-        let thread_group_size = MTLSize::new(16, 16, 1);
-        let grid_size = MTLSize::new(((n + 15) / 16) as u64, ((m + 15) / 16) as u64, 1);
+        Ok(Self { device, compute })
+    }
 
-        let command_buffer = self.compute.command_queue.new_command_buffer();
-        let compute_encoder = command_buffer.new_compute_command_encoder();
+    fn device_type(&self) -> DeviceType {
+        DeviceType::Mps
+    }
 
-        compute_encoder.set_compute_pipeline_state(&pipeline);
-        compute_encoder.set_buffer(0, Some(a), 0);
-        compute_encoder.set_buffer(1, Some(b), 0);
-        compute_encoder.set_buffer(2, Some(&result_buffer), 0);
-        compute_encoder.set_buffer(3, Some(&m_buffer), 0);
-        compute_encoder.set_buffer(4, Some(&n_buffer), 0);
-        compute_encoder.set_buffer(5, Some(&k_buffer), 0);
+    fn get_features(&self) -> DeviceFeatures {
+        let mut features = DeviceFeatures::new();
 
-        compute_encoder.dispatch_thread_groups(grid_size, thread_group_size);
-        compute_encoder.end_encoding();
+        // Check MPS-specific features
+        features.add_feature(
+            GPU_FEATURE_FP16,
+            true, // MPS supports FP16
+            Some("Half-precision floating point support".to_string()),
+        );
 
-        command_buffer.commit();
-        command_buffer.wait_until_completed();
+        features.add_feature(
+            GPU_FEATURE_FP64,
+            false, // MPS typically doesn't support FP64
+            Some("Double-precision floating point support".to_string()),
+        );
 
-        Ok(result_buffer)
+        features
     }
+}
 
-    pub fn add(&self, a: &Buffer, b: &Buffer, size: usize) -> Result<Buffer, MpsError> {
-        let result_buffer = self
-            .device
-            .device()
-            .new_buffer(
-                (size * std::mem::size_of::<f32>()) as u64,
-                MTLResourceOptions::StorageModeShared,
-            )
-            .ok_or(MpsError::BufferCreationError)?;
-
-        // Create and compile the addition kernel
-        let library = self
-            .device
-            .device()
-            .new_library_with_source(
-                include_str!("../../../shaders/metal/binary_ops.metal"),
-                &metal::CompileOptions::new(),
-            )
-            .map_err(|_| MpsError::ShaderCompilationError)?;
+impl Backend for MpsBackend {
+    fn device(&self) -> DeviceType {
+        DeviceType::Mps
+    }
 
-        let kernel = library
-            .get_function("vector_add", None)
-            .map_err(|_| MpsError::ShaderCompilationError)?;
+    fn capabilities(&self) -> BackendCapabilities {
+        let mut capabilities = BackendCapabilities::new(self.device.name());
+        capabilities.max_buffer_size = Some(self.device.device().max_buffer_length());
+        capabilities.supports_f16 = true;
+        capabilities
+    }
 
-        let pipeline = self
-            .device
-            .device()
-            .new_compute_pipeline_state_with_function(&kernel)
-            .map_err(|_| MpsError::ShaderCompilationError)?;
+    fn execute_compute(&self, _dimensions: [u32; 3]) -> MlResult<()> {
+        // Every dispatch waits for its command buffer, so there is nothing left to flush
+        Ok(())
+    }
 
-        // Configure thread groups
-        let thread_group_size = MTLSize::new(256, 1, 1);
-        let grid_size = MTLSize::new(((size + 255) / 256) as u64, 1, 1);
+    fn add(&self, a: &[f32], b: &[f32]) -> Vec<f32> {
+        self.binary("vector_add", a, b)
+    }
 
-        let command_buffer = self.compute.command_queue.new_command_buffer();
-        let compute_encoder = command_buffer.new_compute_command_encoder();
+    fn multiply(&self, a: &[f32], b: &[f32]) -> Vec<f32> {
+        self.binary("vector_mul", a, b)
+    }
 
-        compute_encoder.set_compute_pipeline_state(&pipeline);
-        compute_encoder.set_buffer(0, Some(a), 0);
-        compute_encoder.set_buffer(1, Some(b), 0);
-        compute_encoder.set_buffer(2, Some(&result_buffer), 0);
+    fn div(&self, a: &[f32], b: &[f32]) -> Vec<f32> {
+        self.binary("vector_div", a, b)
+    }
 
-        compute_encoder.dispatch_thread_groups(grid_size, thread_group_size);
-        compute_encoder.end_encoding();
+    fn sub(&self, a: &[f32], b: &[f32]) -> Vec<f32> {
+        self.binary("vector_sub", a, b)
+    }
 
-        command_buffer.commit();
-        command_buffer.wait_until_completed();
+    fn matmul(&self, a: &[f32], b: &[f32], m: usize, n: usize, k: usize) -> Vec<f32> {
+        if a.len() != m * n || b.len() != n * k || m * n * k == 0 {
+            return vec![0.0; m * k];
+        }
 
-        Ok(result_buffer)
+        let a_buffer = self.compute.create_buffer(a);
+        let b_buffer = self.compute.create_buffer(b);
+        match self.compute.matmul(&a_buffer, &b_buffer, m, n, k) {
+            Ok(result) => self.compute.read_buffer(&result, m * k),
+            Err(_) => vec![0.0; m * k],
+        }
     }
 
-    pub fn multiply(&self, a: &Buffer, b: &Buffer, size: usize) -> Result<Buffer, MpsError> {
-        let result_buffer = self
-            .device
-            .device()
-            .new_buffer(
-                (size * std::mem::size_of::<f32>()) as u64,
-                MTLResourceOptions::StorageModeShared,
-            )
-            .ok_or(MpsError::BufferCreationError)?;
-
-        let library = self
-            .device
-            .device()
-            .new_library_with_source(
-                include_str!("../../../shaders/metal/binary_ops.metal"),
-                &metal::CompileOptions::new(),
-            )
-            .map_err(|_| MpsError::ShaderCompilationError)?;
+    fn exp(&self, a: &[f32]) -> Vec<f32> {
+        self.unary("vector_exp", a, 0.0)
+    }
 
-        let kernel = library
-            .get_function("vector_mul", None)
-            .map_err(|_| MpsError::ShaderCompilationError)?;
+    fn log(&self, a: &[f32]) -> Vec<f32> {
+        self.unary("vector_log", a, 0.0)
+    }
 
-        let pipeline = self
-            .device
-            .device()
-            .new_compute_pipeline_state_with_function(&kernel)
-            .map_err(|_| MpsError::ShaderCompilationError)?;
+    fn pow(&self, a: &[f32], power: f32) -> Vec<f32> {
+        self.unary("vector_pow", a, power)
+    }
 
-        let thread_group_size = MTLSize::new(256, 1, 1);
-        let grid_size = MTLSize::new(((size + 255) / 256) as u64, 1, 1);
+    fn sqrt(&self, a: &[f32]) -> Vec<f32> {
+        self.unary("vector_sqrt", a, 0.0)
+    }
 
-        let command_buffer = self.compute.command_queue.new_command_buffer();
-        let compute_encoder = command_buffer.new_compute_command_encoder();
+    fn sum(&self, a: &[f32]) -> f32 {
+        if a.is_empty() {
+            return 0.0;
+        }
 
-        compute_encoder.set_compute_pipeline_state(&pipeline);
-        compute_encoder.set_buffer(0, Some(a), 0);
-        compute_encoder.set_buffer(1, Some(b), 0);
-        compute_encoder.set_buffer(2, Some(&result_buffer), 0);
+        let input = self.compute.create_buffer(a);
+        self.compute.reduce_sum(&input, a.len()).unwrap_or(0.0)
+    }
 
-        compute_encoder.dispatch_thread_groups(grid_size, thread_group_size);
-        compute_encoder.end_encoding();
+    fn mean(&self, a: &[f32]) -> f32 {
+        if a.is_empty() {
+            return 0.0;
+        }
+        self.sum(a) / a.len() as f32
+    }
 
-        command_buffer.commit();
-        command_buffer.wait_until_completed();
+    fn upload(&self, data: &[f32]) -> Option<Arc<dyn DeviceBuffer>> {
+        if data.is_empty() {
+            return None;
+        }
 
-        Ok(result_buffer)
+        let buffer = self.compute.create_buffer(data);
+        Some(Arc::new(MpsBuffer::new(buffer, data.len())))
     }
 
-    pub fn get_supported_features(&self) -> DeviceFeatures {
-        let mut features = DeviceFeatures::new();
-
-        // Check MPS-specific features
-        features.add_feature(
-            GPU_FEATURE_FP16,
-            true, // MPS supports FP16
-            Some("Half-precision floating point support".to_string()),
-        );
+    fn execute_on_device(
+        &self,
+        op: DeviceOp,
+        inputs: &[&dyn DeviceBuffer],
+    ) -> Option<Arc<dyn DeviceBuffer>> {
+        if inputs.len() != op.arity() {
+            return None;
+        }
 
-        features.add_feature(
-            GPU_FEATURE_FP64,
-            false, // MPS typically doesn't support FP64
-            Some("Double-precision floating point support".to_string()),
-        );
+        let buffers = inputs
+            .iter()
+            .map(|buffer| buffer.as_any().downcast_ref::<MpsBuffer>())
+            .collect::<Option<Vec<_>>>()?;
+        let len = buffers[0].len();
+        if len == 0 {
+            return None;
+        }
 
-        features
+        let binary = |kernel| {
+            if buffers[1].len() != len {
+                return None;
+            }
+            self.compute
+                .binary_op(kernel, buffers[0].buffer(), buffers[1].buffer(), len)
+                .ok()
+        };
+        let unary = |kernel, power| {
+            self.compute
+                .unary_op(kernel, buffers[0].buffer(), len, power)
+                .ok()
+        };
+
+        let (result, result_len) = match op {
+            DeviceOp::Add => (binary("vector_add")?, len),
+            DeviceOp::Sub => (binary("vector_sub")?, len),
+            DeviceOp::Mul => (binary("vector_mul")?, len),
+            DeviceOp::Div => (binary("vector_div")?, len),
+            DeviceOp::Exp => (unary("vector_exp", 0.0)?, len),
+            DeviceOp::Log => (unary("vector_log", 0.0)?, len),
+            DeviceOp::Sqrt => (unary("vector_sqrt", 0.0)?, len),
+            DeviceOp::Pow(power) => (unary("vector_pow", power)?, len),
+            DeviceOp::MatMul { m, n, k } => {
+                if len != m * n || buffers[1].len() != n * k {
+                    return None;
+                }
+                let result = self
+                    .compute
+                    .matmul(buffers[0].buffer(), buffers[1].buffer(), m, n, k)
+                    .ok()?;
+                (result, m * k)
+            }
+            DeviceOp::Sum => {
+                let total = self.compute.reduce_sum(buffers[0].buffer(), len).ok()?;
+                (self.compute.create_buffer(&[total]), 1)
+            }
+            DeviceOp::Reduce { .. } | DeviceOp::Conv2d(_) => return None,
+        };
+
+        Some(Arc::new(MpsBuffer::new(result, result_len)))
     }
 }
 
-impl Default for MpsBackend {
-    fn default() -> Self {
-        Self::new().expect("Failed to create MPS backend")
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_basic_operations() -> MlResult<()> {
+        let backend = MpsBackend::new()?;
+        let a = vec![1.0f32, 2.0, 3.0];
+        let b = vec![4.0f32, 5.0, 6.0];
+
+        assert_eq!(backend.add(&a, &b), vec![5.0, 7.0, 9.0]);
+        assert_eq!(backend.sub(&a, &b), vec![-3.0, -3.0, -3.0]);
+        assert_eq!(backend.multiply(&a, &b), vec![4.0, 10.0, 18.0]);
+        assert_eq!(backend.div(&a, &b), vec![0.25, 0.4, 0.5]);
+        assert_eq!(backend.sum(&a), 6.0);
+        assert_eq!(backend.mean(&a), 2.0);
+
+        Ok(())
     }
-}
 
-impl crate::Backend for MpsBackend {
-    type Error = MpsError;
+    #[test]
+    fn test_mps_matmul() -> MlResult<()> {
+        let backend = MpsBackend::new()?;
+
+        // 2x3 * 3x2 matrices
+        let a = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let b = vec![7.0, 8.0, 9.0, 10.0, 11.0, 12.0];
 
-    fn name(&self) -> &str {
-        "MPS"
+        let result = backend.matmul(&a, &b, 2, 3, 2);
+        assert_eq!(result, vec![58.0, 64.0, 139.0, 154.0]);
+
+        Ok(())
     }
 
-    fn device_type(&self) -> crate::DeviceType {
-        crate::DeviceType::Mps
+    #[test]
+    fn test_mps_execute_on_device() -> MlResult<()> {
+        let backend = MpsBackend::new()?;
+        let a = backend.upload(&[1.0, 2.0, 3.0, 4.0]).unwrap();
+        let b = backend.upload(&[5.0, 6.0, 7.0, 8.0]).unwrap();
+
+        let product = backend
+            .execute_on_device(
+                DeviceOp::MatMul { m: 2, n: 2, k: 2 },
+                &[a.as_ref(), b.as_ref()],
+            )
+            .unwrap();
+        let total = backend
+            .execute_on_device(DeviceOp::Sum, &[product.as_ref()])
+            .unwrap();
+
+        assert_eq!(product.to_host()?, vec![19.0, 22.0, 43.0, 50.0]);
+        assert_eq!(total.to_host()?, vec![134.0]);
+
+        Ok(())
     }
-}
 
-impl Device for MpsBackend {
-    fn get_features(&self) -> DeviceFeatures {
-        self.get_supported_features()
+    #[test]
+    fn test_mps_ops_on_device_buffers() -> MlResult<()> {
+        let backend = MpsBackend::new()?;
+        let compute = backend.compute();
+
+        // Chain two ops without leaving the GPU
+        let a = compute.create_buffer(&[1.0f32, 2.0, 3.0]);
+        let b = compute.create_buffer(&[4.0f32, 5.0, 6.0]);
+        let sum = compute.binary_op("vector_add", &a, &b, 3)?;
+        let product = compute.binary_op("vector_mul", &sum, &b, 3)?;
+
+        assert_eq!(compute.read_buffer(&product, 3), vec![20.0, 35.0, 54.0]);
+        assert_eq!(compute.reduce_sum(&product, 3)?, 109.0);
+
+        Ok(())
     }
 }
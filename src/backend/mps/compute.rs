@@ -1,47 +1,288 @@
-use super::core::MpsDevice;
-use metal::{CommandQueue, ComputePipelineState, MTLSize};
+use super::{MpsDevice, MpsError};
+use crate::backend::DeviceBuffer;
+use crate::MlResult;
+use metal::{
+    Buffer, CommandQueue, ComputeCommandEncoderRef, ComputePipelineState, MTLResourceOptions,
+    MTLSize,
+};
+use std::any::Any;
+use std::collections::HashMap;
+use std::ffi::c_void;
 use std::sync::Arc;
 
+// Compiled by build.rs from shaders/metal/*.metal
+const SHADER_LIBRARY: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/shaders.metallib"));
+
+const KERNELS: &[&str] = &[
+    "vector_add",
+    "vector_sub",
+    "vector_mul",
+    "vector_div",
+    "vector_exp",
+    "vector_log",
+    "vector_sqrt",
+    "vector_pow",
+    "matrix_multiply",
+    "reduce_sum",
+];
+
+const THREADS_PER_GROUP: u64 = 256;
+const MATMUL_TILE: u64 = 16;
+
+/// A shared-storage Metal buffer holding `len` floats. Every dispatch waits for its
+/// command buffer, so the contents are always up to date when read.
+pub struct MpsBuffer {
+    buffer: Buffer,
+    len: usize,
+}
+
+// Metal buffers are reference counted and safe to share between threads
+unsafe impl Send for MpsBuffer {}
+unsafe impl Sync for MpsBuffer {}
+
+impl std::fmt::Debug for MpsBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "MpsBuffer({} floats)", self.len)
+    }
+}
+
+impl MpsBuffer {
+    pub fn new(buffer: Buffer, len: usize) -> Self {
+        Self { buffer, len }
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+}
+
+impl DeviceBuffer for MpsBuffer {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn to_host(&self) -> MlResult<Vec<f32>> {
+        let ptr = self.buffer.contents() as *const f32;
+        Ok(unsafe { std::slice::from_raw_parts(ptr, self.len).to_vec() })
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
 pub struct MpsCompute {
     device: Arc<MpsDevice>,
     command_queue: CommandQueue,
+    pipelines: HashMap<&'static str, ComputePipelineState>,
 }
 
 impl MpsCompute {
-    pub fn new(device: Arc<MpsDevice>) -> Result<Self, crate::MpsError> {
-        let command_queue = device
+    pub fn new(device: Arc<MpsDevice>) -> Result<Self, MpsError> {
+        let library = device
             .device()
-            .new_command_queue()
-            .ok_or(crate::MpsError::InitializationError)?;
+            .new_library_with_data(SHADER_LIBRARY)
+            .map_err(|_| MpsError::ShaderCompilationError)?;
+
+        let mut pipelines = HashMap::new();
+        for &name in KERNELS {
+            let function = library
+                .get_function(name, None)
+                .map_err(|_| MpsError::ShaderCompilationError)?;
+            let pipeline = device
+                .device()
+                .new_compute_pipeline_state_with_function(&function)
+                .map_err(|_| MpsError::ShaderCompilationError)?;
+            pipelines.insert(name, pipeline);
+        }
+
+        let command_queue = device.device().new_command_queue();
 
         Ok(Self {
             device,
             command_queue,
+            pipelines,
         })
     }
 
-    pub fn dispatch_compute(
+    pub fn create_buffer<T: Copy>(&self, data: &[T]) -> Buffer {
+        self.device.device().new_buffer_with_data(
+            data.as_ptr() as *const c_void,
+            std::mem::size_of_val(data) as u64,
+            MTLResourceOptions::StorageModeShared,
+        )
+    }
+
+    pub fn new_buffer(&self, len: usize) -> Buffer {
+        self.device.device().new_buffer(
+            (len * std::mem::size_of::<f32>()) as u64,
+            MTLResourceOptions::StorageModeShared,
+        )
+    }
+
+    /// Copies the first `len` floats of a shared-storage buffer back to the host.
+    pub fn read_buffer(&self, buffer: &Buffer, len: usize) -> Vec<f32> {
+        let ptr = buffer.contents() as *const f32;
+        unsafe { std::slice::from_raw_parts(ptr, len).to_vec() }
+    }
+
+    // Encodes a single dispatch of `kernel`, lets `bind` attach its arguments and waits for completion
+    fn dispatch<F>(
         &self,
-        pipeline: &ComputePipelineState,
-        grid_size: MTLSize,
-        thread_group_size: MTLSize,
-    ) -> Result<(), crate::MpsError> {
+        kernel: &str,
+        thread_groups: MTLSize,
+        threads_per_group: MTLSize,
+        bind: F,
+    ) -> Result<(), MpsError>
+    where
+        F: FnOnce(&ComputeCommandEncoderRef),
+    {
+        let pipeline = self
+            .pipelines
+            .get(kernel)
+            .ok_or_else(|| MpsError::Other(format!("Unknown kernel: {}", kernel)))?;
+
         let command_buffer = self.command_queue.new_command_buffer();
-        let compute_encoder = command_buffer.new_compute_command_encoder();
+        let encoder = command_buffer.new_compute_command_encoder();
 
-        compute_encoder.set_compute_pipeline_state(pipeline);
-        compute_encoder.dispatch_thread_groups(grid_size, thread_group_size);
-        compute_encoder.end_encoding();
+        encoder.set_compute_pipeline_state(pipeline);
+        bind(encoder);
+        encoder.dispatch_thread_groups(thread_groups, threads_per_group);
+        encoder.end_encoding();
 
         command_buffer.commit();
         command_buffer.wait_until_completed();
 
         Ok(())
     }
-}
 
-impl Drop for MpsCompute {
-    fn drop(&mut self) {
-        // Metal handles cleanup automatically through reference counting
+    fn set_u32(encoder: &ComputeCommandEncoderRef, index: u64, value: u32) {
+        encoder.set_bytes(
+            index,
+            std::mem::size_of::<u32>() as u64,
+            &value as *const u32 as *const c_void,
+        );
+    }
+
+    fn groups_for(len: usize) -> MTLSize {
+        MTLSize::new((len as u64).div_ceil(THREADS_PER_GROUP), 1, 1)
+    }
+
+    /// Runs one of the `vector_{add,sub,mul,div}` kernels on device buffers.
+    pub fn binary_op(
+        &self,
+        kernel: &str,
+        a: &Buffer,
+        b: &Buffer,
+        len: usize,
+    ) -> Result<Buffer, MpsError> {
+        let result = self.new_buffer(len);
+
+        self.dispatch(
+            kernel,
+            Self::groups_for(len),
+            MTLSize::new(THREADS_PER_GROUP, 1, 1),
+            |encoder| {
+                encoder.set_buffer(0, Some(a), 0);
+                encoder.set_buffer(1, Some(b), 0);
+                encoder.set_buffer(2, Some(&result), 0);
+                Self::set_u32(encoder, 3, len as u32);
+            },
+        )?;
+
+        Ok(result)
+    }
+
+    /// Runs one of the `vector_{exp,log,sqrt,pow}` kernels on a device buffer.
+    /// `power` is only read by `vector_pow`.
+    pub fn unary_op(
+        &self,
+        kernel: &str,
+        input: &Buffer,
+        len: usize,
+        power: f32,
+    ) -> Result<Buffer, MpsError> {
+        let result = self.new_buffer(len);
+
+        self.dispatch(
+            kernel,
+            Self::groups_for(len),
+            MTLSize::new(THREADS_PER_GROUP, 1, 1),
+            |encoder| {
+                encoder.set_buffer(0, Some(input), 0);
+                encoder.set_buffer(1, Some(&result), 0);
+                Self::set_u32(encoder, 2, len as u32);
+                encoder.set_bytes(
+                    3,
+                    std::mem::size_of::<f32>() as u64,
+                    &power as *const f32 as *const c_void,
+                );
+            },
+        )?;
+
+        Ok(result)
+    }
+
+    /// Multiplies an `[m, n]` buffer by an `[n, k]` buffer into a new `[m, k]` buffer.
+    pub fn matmul(
+        &self,
+        a: &Buffer,
+        b: &Buffer,
+        m: usize,
+        n: usize,
+        k: usize,
+    ) -> Result<Buffer, MpsError> {
+        if m == 0 || n == 0 || k == 0 {
+            return Err(MpsError::InvalidDimensions);
+        }
+
+        let result = self.new_buffer(m * k);
+
+        self.dispatch(
+            "matrix_multiply",
+            MTLSize::new(
+                (k as u64).div_ceil(MATMUL_TILE),
+                (m as u64).div_ceil(MATMUL_TILE),
+                1,
+            ),
+            MTLSize::new(MATMUL_TILE, MATMUL_TILE, 1),
+            |encoder| {
+                encoder.set_buffer(0, Some(a), 0);
+                encoder.set_buffer(1, Some(b), 0);
+                encoder.set_buffer(2, Some(&result), 0);
+                Self::set_u32(encoder, 3, m as u32);
+                Self::set_u32(encoder, 4, n as u32);
+                Self::set_u32(encoder, 5, k as u32);
+            },
+        )?;
+
+        Ok(result)
+    }
+
+    /// Sums `len` floats of a device buffer. Each threadgroup reduces its slice on the
+    /// GPU and the per-group partials are added on the host.
+    pub fn reduce_sum(&self, input: &Buffer, len: usize) -> Result<f32, MpsError> {
+        let groups = Self::groups_for(len);
+        let partials = self.new_buffer(groups.width as usize);
+
+        self.dispatch(
+            "reduce_sum",
+            groups,
+            MTLSize::new(THREADS_PER_GROUP, 1, 1),
+            |encoder| {
+                encoder.set_buffer(0, Some(input), 0);
+                encoder.set_buffer(1, Some(&partials), 0);
+                Self::set_u32(encoder, 2, len as u32);
+                encoder.set_threadgroup_memory_length(
+                    0,
+                    THREADS_PER_GROUP * std::mem::size_of::<f32>() as u64,
+                );
+            },
+        )?;
+
+        Ok(self
+            .read_buffer(&partials, groups.width as usize)
+            .iter()
+            .sum())
     }
 }
@@ -1,20 +1,22 @@
-use metal::{Device, MTLDevice};
-use std::sync::Arc;
+use super::MpsError;
+use metal::Device;
 
 pub struct MpsDevice {
-    device: Arc<Device>,
+    device: Device,
 }
 
 impl MpsDevice {
-    pub fn new() -> Result<Self, crate::MpsError> {
-        let device = Device::system_default().ok_or(crate::MpsError::DeviceNotFound)?;
+    pub fn new() -> Result<Self, MpsError> {
+        let device = Device::system_default().ok_or(MpsError::DeviceNotFound)?;
 
-        Ok(Self {
-            device: Arc::new(device),
-        })
+        Ok(Self { device })
     }
 
     pub fn device(&self) -> &Device {
         &self.device
     }
+
+    pub fn name(&self) -> &str {
+        self.device.name()
+    }
 }
@@ -5,7 +5,7 @@ mod compute;
 mod core;
 
 pub use backend::MpsBackend;
-pub use compute::MpsCompute;
+pub use compute::{MpsBuffer, MpsCompute};
 pub use core::MpsDevice;
 
 #[derive(Debug)]
@@ -32,3 +32,11 @@ impl fmt::Display for MpsError {
         }
     }
 }
+
+impl std::error::Error for MpsError {}
+
+impl From<MpsError> for crate::MlError {
+    fn from(err: MpsError) -> Self {
+        crate::MlError::BackendError(crate::backend::BackendError::MpsError(err))
+    }
+}
@@ -0,0 +1,200 @@
+use super::{OpenClCompute, OpenClCore, OpenClError};
+use crate::backend::feature::{DeviceFeatures, GPU_FEATURE_FP16, GPU_FEATURE_FP64};
+use crate::backend::{Backend, BackendCapabilities, Device, DeviceType};
+use crate::MlResult;
+use std::fmt::Debug;
+use std::sync::{Arc, OnceLock};
+
+pub struct OpenClBackend {
+    compute: OpenClCompute,
+    core: OpenClCore,
+}
+
+// OpenCL handles are reference-counted by the driver and every API call used here is
+// thread-safe; kernels, the only objects that aren't, are created per launch.
+unsafe impl Send for OpenClBackend {}
+unsafe impl Sync for OpenClBackend {}
+
+impl Debug for OpenClBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "OpenClBackend({})", self.core.device_name)
+    }
+}
+
+impl OpenClBackend {
+    /// Returns a process-wide backend instance so tensors don't each build the kernel program.
+    pub fn shared() -> MlResult<Arc<OpenClBackend>> {
+        static SHARED: OnceLock<Option<Arc<OpenClBackend>>> = OnceLock::new();
+
+        SHARED
+            .get_or_init(|| OpenClBackend::new().ok().map(Arc::new))
+            .clone()
+            .ok_or_else(|| OpenClError::DeviceNotFound.into())
+    }
+
+    pub fn device_name(&self) -> &str {
+        &self.core.device_name
+    }
+}
+
+impl Device for OpenClBackend {
+    fn new() -> MlResult<Self> {
+        let core = OpenClCore::new()?;
+        let compute = OpenClCompute::new(&core)?;
+
+        Ok(Self { compute, core })
+    }
+
+    fn device_type(&self) -> DeviceType {
+        DeviceType::OpenCl
+    }
+
+    fn get_features(&self) -> DeviceFeatures {
+        let mut features = DeviceFeatures::new();
+
+        features.add_feature(
+            GPU_FEATURE_FP16,
+            self.core.has_extension("cl_khr_fp16"),
+            Some("Half-precision floating point support".to_string()),
+        );
+
+        features.add_feature(
+            GPU_FEATURE_FP64,
+            self.core.has_extension("cl_khr_fp64"),
+            Some("Double-precision floating point support".to_string()),
+        );
+
+        features
+    }
+}
+
+impl Backend for OpenClBackend {
+    fn device(&self) -> DeviceType {
+        DeviceType::OpenCl
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        let mut capabilities = BackendCapabilities::new(self.core.device_name.clone());
+        // Drivers that fail the query leave the size at zero
+        if self.core.max_mem_alloc_size > 0 {
+            capabilities.max_buffer_size = Some(self.core.max_mem_alloc_size);
+        }
+        capabilities.supports_f16 = self.core.has_extension("cl_khr_fp16");
+        capabilities
+    }
+
+    fn execute_compute(&self, _dimensions: [u32; 3]) -> MlResult<()> {
+        self.core.finish()?;
+        Ok(())
+    }
+
+    fn add(&self, a: &[f32], b: &[f32]) -> Vec<f32> {
+        self.compute
+            .execute_binary_op(&self.core, a, b, 0)
+            .unwrap_or_else(|_| vec![0.0; a.len()])
+    }
+
+    fn multiply(&self, a: &[f32], b: &[f32]) -> Vec<f32> {
+        self.compute
+            .execute_binary_op(&self.core, a, b, 1)
+            .unwrap_or_else(|_| vec![0.0; a.len()])
+    }
+
+    fn div(&self, a: &[f32], b: &[f32]) -> Vec<f32> {
+        self.compute
+            .execute_binary_op(&self.core, a, b, 2)
+            .unwrap_or_else(|_| vec![0.0; a.len()])
+    }
+
+    fn sub(&self, a: &[f32], b: &[f32]) -> Vec<f32> {
+        self.compute
+            .execute_binary_op(&self.core, a, b, 3)
+            .unwrap_or_else(|_| vec![0.0; a.len()])
+    }
+
+    fn matmul(&self, a: &[f32], b: &[f32], m: usize, n: usize, k: usize) -> Vec<f32> {
+        self.compute
+            .matmul(&self.core, a, b, m, n, k)
+            .unwrap_or_else(|_| vec![0.0; m * k])
+    }
+
+    fn exp(&self, a: &[f32]) -> Vec<f32> {
+        self.compute
+            .execute_unary_op(&self.core, a, 0, 0.0)
+            .unwrap_or_else(|_| vec![0.0; a.len()])
+    }
+
+    fn log(&self, a: &[f32]) -> Vec<f32> {
+        self.compute
+            .execute_unary_op(&self.core, a, 1, 0.0)
+            .unwrap_or_else(|_| vec![0.0; a.len()])
+    }
+
+    fn pow(&self, a: &[f32], power: f32) -> Vec<f32> {
+        self.compute
+            .execute_unary_op(&self.core, a, 2, power)
+            .unwrap_or_else(|_| vec![0.0; a.len()])
+    }
+
+    fn sqrt(&self, a: &[f32]) -> Vec<f32> {
+        self.compute
+            .execute_unary_op(&self.core, a, 3, 0.0)
+            .unwrap_or_else(|_| vec![0.0; a.len()])
+    }
+
+    fn sum(&self, a: &[f32]) -> f32 {
+        self.compute.execute_reduction(&self.core, a).unwrap_or(0.0)
+    }
+
+    fn mean(&self, a: &[f32]) -> f32 {
+        if a.is_empty() {
+            return 0.0;
+        }
+        self.sum(a) / a.len() as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_basic_operations() -> MlResult<()> {
+        let backend = OpenClBackend::new()?;
+        let a = vec![1.0f32, 2.0, 3.0];
+        let b = vec![4.0f32, 5.0, 6.0];
+
+        assert_eq!(backend.add(&a, &b), vec![5.0, 7.0, 9.0]);
+        assert_eq!(backend.sub(&a, &b), vec![-3.0, -3.0, -3.0]);
+        assert_eq!(backend.multiply(&a, &b), vec![4.0, 10.0, 18.0]);
+        assert_eq!(backend.div(&a, &b), vec![0.25, 0.4, 0.5]);
+        assert_eq!(backend.sum(&a), 6.0);
+        assert_eq!(backend.mean(&a), 2.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_opencl_matmul() -> MlResult<()> {
+        let backend = OpenClBackend::new()?;
+
+        // 2x3 * 3x2 matrices
+        let a = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let b = vec![7.0, 8.0, 9.0, 10.0, 11.0, 12.0];
+
+        let result = backend.matmul(&a, &b, 2, 3, 2);
+        assert_eq!(result, vec![58.0, 64.0, 139.0, 154.0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_opencl_reduction_spans_work_groups() -> MlResult<()> {
+        let backend = OpenClBackend::new()?;
+        let a = vec![1.0f32; 1000];
+
+        assert_eq!(backend.sum(&a), 1000.0);
+
+        Ok(())
+    }
+}
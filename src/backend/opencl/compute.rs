@@ -0,0 +1,353 @@
+use super::ffi::*;
+use super::{check, OpenClCore, OpenClError};
+use std::ffi::{c_void, CStr, CString};
+use std::ptr::{null, null_mut};
+
+const KERNEL_SOURCE: &str = include_str!("../../../shaders/opencl/kernels.cl");
+const MAX_REDUCE_LOCAL_SIZE: usize = 256;
+
+struct ClBuffer {
+    mem: cl_mem,
+    len: usize,
+}
+
+impl ClBuffer {
+    fn from_slice(core: &OpenClCore, data: &[f32]) -> Result<Self, OpenClError> {
+        let mut err = CL_SUCCESS;
+        let mem = unsafe {
+            clCreateBuffer(
+                core.context,
+                CL_MEM_READ_ONLY | CL_MEM_COPY_HOST_PTR,
+                std::mem::size_of_val(data),
+                data.as_ptr() as *mut c_void,
+                &mut err,
+            )
+        };
+        check("clCreateBuffer", err)?;
+        Ok(Self {
+            mem,
+            len: data.len(),
+        })
+    }
+
+    fn output(core: &OpenClCore, len: usize) -> Result<Self, OpenClError> {
+        let mut err = CL_SUCCESS;
+        let mem = unsafe {
+            clCreateBuffer(
+                core.context,
+                CL_MEM_WRITE_ONLY,
+                len * std::mem::size_of::<f32>(),
+                null_mut(),
+                &mut err,
+            )
+        };
+        check("clCreateBuffer", err)?;
+        Ok(Self { mem, len })
+    }
+
+    fn read(&self, core: &OpenClCore) -> Result<Vec<f32>, OpenClError> {
+        let mut result = vec![0.0f32; self.len];
+        unsafe {
+            check(
+                "clEnqueueReadBuffer",
+                clEnqueueReadBuffer(
+                    core.queue,
+                    self.mem,
+                    CL_TRUE,
+                    0,
+                    std::mem::size_of_val(result.as_slice()),
+                    result.as_mut_ptr() as *mut c_void,
+                    0,
+                    null(),
+                    null_mut(),
+                ),
+            )?;
+        }
+        Ok(result)
+    }
+}
+
+impl Drop for ClBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            clReleaseMemObject(self.mem);
+        }
+    }
+}
+
+// Kernels are created per launch: clSetKernelArg is not thread-safe on a shared kernel object
+struct Kernel(cl_kernel);
+
+impl Kernel {
+    fn arg<T>(&self, index: u32, value: &T) -> Result<&Self, OpenClError> {
+        unsafe {
+            check(
+                "clSetKernelArg",
+                clSetKernelArg(
+                    self.0,
+                    index,
+                    std::mem::size_of::<T>(),
+                    value as *const T as *const c_void,
+                ),
+            )?;
+        }
+        Ok(self)
+    }
+
+    fn buffer(&self, index: u32, buffer: &ClBuffer) -> Result<&Self, OpenClError> {
+        self.arg(index, &buffer.mem)
+    }
+
+    fn local(&self, index: u32, bytes: usize) -> Result<&Self, OpenClError> {
+        unsafe {
+            check(
+                "clSetKernelArg",
+                clSetKernelArg(self.0, index, bytes, null()),
+            )?;
+        }
+        Ok(self)
+    }
+
+    fn enqueue(
+        &self,
+        core: &OpenClCore,
+        global: &[usize],
+        local: Option<&[usize]>,
+    ) -> Result<(), OpenClError> {
+        unsafe {
+            check(
+                "clEnqueueNDRangeKernel",
+                clEnqueueNDRangeKernel(
+                    core.queue,
+                    self.0,
+                    global.len() as cl_uint,
+                    null(),
+                    global.as_ptr(),
+                    local.map_or(null(), |l| l.as_ptr()),
+                    0,
+                    null(),
+                    null_mut(),
+                ),
+            )
+        }
+    }
+}
+
+impl Drop for Kernel {
+    fn drop(&mut self) {
+        unsafe {
+            clReleaseKernel(self.0);
+        }
+    }
+}
+
+pub struct OpenClCompute {
+    program: cl_program,
+    tile_size: usize,
+    reduce_local_size: usize,
+}
+
+impl OpenClCompute {
+    pub fn new(core: &OpenClCore) -> Result<Self, OpenClError> {
+        // The matmul tile is square, so older devices with small work-groups get a smaller tile
+        let mut tile_size = 16;
+        while tile_size > 1 && tile_size * tile_size > core.max_work_group_size {
+            tile_size /= 2;
+        }
+        let mut reduce_local_size = MAX_REDUCE_LOCAL_SIZE;
+        while reduce_local_size > 1 && reduce_local_size > core.max_work_group_size {
+            reduce_local_size /= 2;
+        }
+
+        let program = Self::build_program(core, tile_size)?;
+
+        Ok(Self {
+            program,
+            tile_size,
+            reduce_local_size,
+        })
+    }
+
+    fn build_program(core: &OpenClCore, tile_size: usize) -> Result<cl_program, OpenClError> {
+        let source = CString::new(KERNEL_SOURCE)
+            .map_err(|e| OpenClError::Other(format!("Invalid kernel source: {}", e)))?;
+        let options = CString::new(format!("-D TILE_SIZE={}", tile_size))
+            .map_err(|e| OpenClError::Other(e.to_string()))?;
+
+        unsafe {
+            let mut err = CL_SUCCESS;
+            let source_ptr = source.as_ptr();
+            let program = clCreateProgramWithSource(core.context, 1, &source_ptr, null(), &mut err);
+            check("clCreateProgramWithSource", err)?;
+
+            let status =
+                clBuildProgram(program, 1, &core.device, options.as_ptr(), None, null_mut());
+            if status != CL_SUCCESS {
+                let log = Self::build_log(core, program);
+                clReleaseProgram(program);
+                return Err(OpenClError::BuildFailed(log));
+            }
+
+            Ok(program)
+        }
+    }
+
+    fn build_log(core: &OpenClCore, program: cl_program) -> String {
+        unsafe {
+            let mut size = 0;
+            clGetProgramBuildInfo(
+                program,
+                core.device,
+                CL_PROGRAM_BUILD_LOG,
+                0,
+                null_mut(),
+                &mut size,
+            );
+            let mut log = vec![0u8; size];
+            clGetProgramBuildInfo(
+                program,
+                core.device,
+                CL_PROGRAM_BUILD_LOG,
+                size,
+                log.as_mut_ptr() as *mut c_void,
+                null_mut(),
+            );
+            String::from_utf8_lossy(&log)
+                .trim_end_matches('\0')
+                .to_string()
+        }
+    }
+
+    fn kernel(&self, name: &CStr) -> Result<Kernel, OpenClError> {
+        let mut err = CL_SUCCESS;
+        let kernel = unsafe { clCreateKernel(self.program, name.as_ptr(), &mut err) };
+        check("clCreateKernel", err)?;
+        Ok(Kernel(kernel))
+    }
+
+    pub fn execute_binary_op(
+        &self,
+        core: &OpenClCore,
+        a: &[f32],
+        b: &[f32],
+        op_type: u32,
+    ) -> Result<Vec<f32>, OpenClError> {
+        if a.len() != b.len() {
+            return Err(OpenClError::InvalidDimensions(format!(
+                "operand lengths differ: {} vs {}",
+                a.len(),
+                b.len()
+            )));
+        }
+        if a.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let input_a = ClBuffer::from_slice(core, a)?;
+        let input_b = ClBuffer::from_slice(core, b)?;
+        let output = ClBuffer::output(core, a.len())?;
+
+        self.kernel(c"binary_op")?
+            .buffer(0, &input_a)?
+            .buffer(1, &input_b)?
+            .buffer(2, &output)?
+            .arg(3, &op_type)?
+            .arg(4, &(a.len() as u32))?
+            .enqueue(core, &[a.len()], None)?;
+
+        output.read(core)
+    }
+
+    pub fn execute_unary_op(
+        &self,
+        core: &OpenClCore,
+        a: &[f32],
+        op_type: u32,
+        scalar: f32,
+    ) -> Result<Vec<f32>, OpenClError> {
+        if a.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let input = ClBuffer::from_slice(core, a)?;
+        let output = ClBuffer::output(core, a.len())?;
+
+        self.kernel(c"unary_op")?
+            .buffer(0, &input)?
+            .buffer(1, &output)?
+            .arg(2, &op_type)?
+            .arg(3, &scalar)?
+            .arg(4, &(a.len() as u32))?
+            .enqueue(core, &[a.len()], None)?;
+
+        output.read(core)
+    }
+
+    pub fn matmul(
+        &self,
+        core: &OpenClCore,
+        a: &[f32],
+        b: &[f32],
+        m: usize,
+        n: usize,
+        k: usize,
+    ) -> Result<Vec<f32>, OpenClError> {
+        if a.len() != m * n || b.len() != n * k {
+            return Err(OpenClError::InvalidDimensions(format!(
+                "matmul expects {}x{} and {}x{} operands",
+                m, n, n, k
+            )));
+        }
+        if m == 0 || n == 0 || k == 0 {
+            return Ok(vec![0.0; m * k]);
+        }
+
+        let input_a = ClBuffer::from_slice(core, a)?;
+        let input_b = ClBuffer::from_slice(core, b)?;
+        let output = ClBuffer::output(core, m * k)?;
+
+        // OpenCL 1.x requires the global size to be a multiple of the work-group size
+        let tile = self.tile_size;
+        let global = [k.div_ceil(tile) * tile, m.div_ceil(tile) * tile];
+
+        self.kernel(c"matmul")?
+            .buffer(0, &input_a)?
+            .buffer(1, &input_b)?
+            .buffer(2, &output)?
+            .arg(3, &(m as u32))?
+            .arg(4, &(n as u32))?
+            .arg(5, &(k as u32))?
+            .enqueue(core, &global, Some(&[tile, tile]))?;
+
+        output.read(core)
+    }
+
+    pub fn execute_reduction(&self, core: &OpenClCore, input: &[f32]) -> Result<f32, OpenClError> {
+        if input.is_empty() {
+            return Ok(0.0);
+        }
+
+        let local = self.reduce_local_size;
+        let num_groups = input.len().div_ceil(local);
+
+        let input_buffer = ClBuffer::from_slice(core, input)?;
+        let partials = ClBuffer::output(core, num_groups)?;
+
+        self.kernel(c"reduce_sum")?
+            .buffer(0, &input_buffer)?
+            .buffer(1, &partials)?
+            .local(2, local * std::mem::size_of::<f32>())?
+            .arg(3, &(input.len() as u32))?
+            .enqueue(core, &[num_groups * local], Some(&[local]))?;
+
+        Ok(partials.read(core)?.iter().sum())
+    }
+}
+
+impl Drop for OpenClCompute {
+    fn drop(&mut self) {
+        unsafe {
+            clReleaseProgram(self.program);
+        }
+    }
+}
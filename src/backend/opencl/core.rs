@@ -0,0 +1,129 @@
+use super::ffi::*;
+use super::{check, OpenClError};
+use std::ffi::c_void;
+use std::ptr::{null, null_mut};
+
+pub struct OpenClCore {
+    pub device: cl_device_id,
+    pub context: cl_context,
+    pub queue: cl_command_queue,
+    pub device_name: String,
+    pub extensions: String,
+    pub max_work_group_size: usize,
+    pub max_mem_alloc_size: u64,
+}
+
+impl OpenClCore {
+    pub fn new() -> Result<Self, OpenClError> {
+        let device = Self::select_device()?;
+
+        unsafe {
+            let mut err = CL_SUCCESS;
+            let context = clCreateContext(null(), 1, &device, None, null_mut(), &mut err);
+            check("clCreateContext", err)?;
+
+            let queue = clCreateCommandQueue(context, device, 0, &mut err);
+            if let Err(e) = check("clCreateCommandQueue", err) {
+                clReleaseContext(context);
+                return Err(e);
+            }
+
+            let mut max_work_group_size = 0usize;
+            clGetDeviceInfo(
+                device,
+                CL_DEVICE_MAX_WORK_GROUP_SIZE,
+                std::mem::size_of::<usize>(),
+                &mut max_work_group_size as *mut usize as *mut c_void,
+                null_mut(),
+            );
+
+            let mut max_mem_alloc_size = 0u64;
+            clGetDeviceInfo(
+                device,
+                CL_DEVICE_MAX_MEM_ALLOC_SIZE,
+                std::mem::size_of::<u64>(),
+                &mut max_mem_alloc_size as *mut u64 as *mut c_void,
+                null_mut(),
+            );
+
+            Ok(Self {
+                device,
+                context,
+                queue,
+                device_name: Self::device_info_string(device, CL_DEVICE_NAME),
+                extensions: Self::device_info_string(device, CL_DEVICE_EXTENSIONS),
+                max_work_group_size,
+                max_mem_alloc_size,
+            })
+        }
+    }
+
+    // Prefers a GPU on any platform and falls back to whatever device the first platform exposes
+    fn select_device() -> Result<cl_device_id, OpenClError> {
+        unsafe {
+            let mut num_platforms = 0;
+            clGetPlatformIDs(0, null_mut(), &mut num_platforms);
+            if num_platforms == 0 {
+                return Err(OpenClError::PlatformNotFound);
+            }
+
+            let mut platforms = vec![null_mut(); num_platforms as usize];
+            check(
+                "clGetPlatformIDs",
+                clGetPlatformIDs(num_platforms, platforms.as_mut_ptr(), null_mut()),
+            )?;
+
+            for device_type in [CL_DEVICE_TYPE_GPU, CL_DEVICE_TYPE_ALL] {
+                for &platform in &platforms {
+                    let mut device = null_mut();
+                    let mut num_devices = 0;
+                    let status =
+                        clGetDeviceIDs(platform, device_type, 1, &mut device, &mut num_devices);
+                    if status == CL_SUCCESS && num_devices > 0 {
+                        return Ok(device);
+                    }
+                }
+            }
+
+            Err(OpenClError::DeviceNotFound)
+        }
+    }
+
+    fn device_info_string(device: cl_device_id, param: cl_device_info) -> String {
+        unsafe {
+            let mut size = 0;
+            if clGetDeviceInfo(device, param, 0, null_mut(), &mut size) != CL_SUCCESS || size == 0 {
+                return String::new();
+            }
+
+            let mut buffer = vec![0u8; size];
+            clGetDeviceInfo(
+                device,
+                param,
+                size,
+                buffer.as_mut_ptr() as *mut c_void,
+                null_mut(),
+            );
+            String::from_utf8_lossy(&buffer)
+                .trim_end_matches('\0')
+                .to_string()
+        }
+    }
+
+    pub fn has_extension(&self, name: &str) -> bool {
+        self.extensions.split_whitespace().any(|ext| ext == name)
+    }
+
+    pub fn finish(&self) -> Result<(), OpenClError> {
+        unsafe { check("clFinish", clFinish(self.queue)) }
+    }
+}
+
+impl Drop for OpenClCore {
+    fn drop(&mut self) {
+        unsafe {
+            clReleaseCommandQueue(self.queue);
+            clReleaseContext(self.context);
+        }
+    }
+}
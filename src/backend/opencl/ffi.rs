@@ -0,0 +1,166 @@
+#![allow(non_camel_case_types)]
+
+use std::ffi::{c_char, c_void};
+
+pub type cl_int = i32;
+pub type cl_uint = u32;
+pub type cl_bool = cl_uint;
+pub type cl_bitfield = u64;
+pub type cl_device_type = cl_bitfield;
+pub type cl_mem_flags = cl_bitfield;
+pub type cl_command_queue_properties = cl_bitfield;
+pub type cl_device_info = cl_uint;
+pub type cl_program_build_info = cl_uint;
+pub type cl_context_properties = isize;
+
+pub type cl_platform_id = *mut c_void;
+pub type cl_device_id = *mut c_void;
+pub type cl_context = *mut c_void;
+pub type cl_command_queue = *mut c_void;
+pub type cl_mem = *mut c_void;
+pub type cl_program = *mut c_void;
+pub type cl_kernel = *mut c_void;
+pub type cl_event = *mut c_void;
+
+pub const CL_SUCCESS: cl_int = 0;
+pub const CL_TRUE: cl_bool = 1;
+
+pub const CL_DEVICE_TYPE_GPU: cl_device_type = 1 << 2;
+pub const CL_DEVICE_TYPE_ALL: cl_device_type = 0xFFFF_FFFF;
+
+pub const CL_DEVICE_MAX_WORK_GROUP_SIZE: cl_device_info = 0x1004;
+pub const CL_DEVICE_MAX_MEM_ALLOC_SIZE: cl_device_info = 0x1010;
+pub const CL_DEVICE_NAME: cl_device_info = 0x102B;
+pub const CL_DEVICE_EXTENSIONS: cl_device_info = 0x1030;
+
+pub const CL_MEM_WRITE_ONLY: cl_mem_flags = 1 << 1;
+pub const CL_MEM_READ_ONLY: cl_mem_flags = 1 << 2;
+pub const CL_MEM_COPY_HOST_PTR: cl_mem_flags = 1 << 5;
+
+pub const CL_PROGRAM_BUILD_LOG: cl_program_build_info = 0x1183;
+
+type BuildCallback = Option<extern "C" fn(cl_program, *mut c_void)>;
+type ContextCallback = Option<extern "C" fn(*const c_char, *const c_void, usize, *mut c_void)>;
+
+#[cfg_attr(target_os = "macos", link(name = "OpenCL", kind = "framework"))]
+#[cfg_attr(not(target_os = "macos"), link(name = "OpenCL"))]
+extern "C" {
+    pub fn clGetPlatformIDs(
+        num_entries: cl_uint,
+        platforms: *mut cl_platform_id,
+        num_platforms: *mut cl_uint,
+    ) -> cl_int;
+
+    pub fn clGetDeviceIDs(
+        platform: cl_platform_id,
+        device_type: cl_device_type,
+        num_entries: cl_uint,
+        devices: *mut cl_device_id,
+        num_devices: *mut cl_uint,
+    ) -> cl_int;
+
+    pub fn clGetDeviceInfo(
+        device: cl_device_id,
+        param_name: cl_device_info,
+        param_value_size: usize,
+        param_value: *mut c_void,
+        param_value_size_ret: *mut usize,
+    ) -> cl_int;
+
+    pub fn clCreateContext(
+        properties: *const cl_context_properties,
+        num_devices: cl_uint,
+        devices: *const cl_device_id,
+        pfn_notify: ContextCallback,
+        user_data: *mut c_void,
+        errcode_ret: *mut cl_int,
+    ) -> cl_context;
+
+    pub fn clCreateCommandQueue(
+        context: cl_context,
+        device: cl_device_id,
+        properties: cl_command_queue_properties,
+        errcode_ret: *mut cl_int,
+    ) -> cl_command_queue;
+
+    pub fn clCreateBuffer(
+        context: cl_context,
+        flags: cl_mem_flags,
+        size: usize,
+        host_ptr: *mut c_void,
+        errcode_ret: *mut cl_int,
+    ) -> cl_mem;
+
+    pub fn clCreateProgramWithSource(
+        context: cl_context,
+        count: cl_uint,
+        strings: *const *const c_char,
+        lengths: *const usize,
+        errcode_ret: *mut cl_int,
+    ) -> cl_program;
+
+    pub fn clBuildProgram(
+        program: cl_program,
+        num_devices: cl_uint,
+        device_list: *const cl_device_id,
+        options: *const c_char,
+        pfn_notify: BuildCallback,
+        user_data: *mut c_void,
+    ) -> cl_int;
+
+    pub fn clGetProgramBuildInfo(
+        program: cl_program,
+        device: cl_device_id,
+        param_name: cl_program_build_info,
+        param_value_size: usize,
+        param_value: *mut c_void,
+        param_value_size_ret: *mut usize,
+    ) -> cl_int;
+
+    pub fn clCreateKernel(
+        program: cl_program,
+        kernel_name: *const c_char,
+        errcode_ret: *mut cl_int,
+    ) -> cl_kernel;
+
+    pub fn clSetKernelArg(
+        kernel: cl_kernel,
+        arg_index: cl_uint,
+        arg_size: usize,
+        arg_value: *const c_void,
+    ) -> cl_int;
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn clEnqueueNDRangeKernel(
+        queue: cl_command_queue,
+        kernel: cl_kernel,
+        work_dim: cl_uint,
+        global_work_offset: *const usize,
+        global_work_size: *const usize,
+        local_work_size: *const usize,
+        num_events_in_wait_list: cl_uint,
+        event_wait_list: *const cl_event,
+        event: *mut cl_event,
+    ) -> cl_int;
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn clEnqueueReadBuffer(
+        queue: cl_command_queue,
+        buffer: cl_mem,
+        blocking_read: cl_bool,
+        offset: usize,
+        size: usize,
+        ptr: *mut c_void,
+        num_events_in_wait_list: cl_uint,
+        event_wait_list: *const cl_event,
+        event: *mut cl_event,
+    ) -> cl_int;
+
+    pub fn clFinish(queue: cl_command_queue) -> cl_int;
+
+    pub fn clReleaseMemObject(memobj: cl_mem) -> cl_int;
+    pub fn clReleaseKernel(kernel: cl_kernel) -> cl_int;
+    pub fn clReleaseProgram(program: cl_program) -> cl_int;
+    pub fn clReleaseCommandQueue(queue: cl_command_queue) -> cl_int;
+    pub fn clReleaseContext(context: cl_context) -> cl_int;
+}
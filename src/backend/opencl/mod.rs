@@ -0,0 +1,52 @@
+use std::error::Error;
+
+mod backend;
+mod compute;
+mod core;
+mod ffi;
+
+pub use backend::OpenClBackend;
+pub use compute::OpenClCompute;
+pub use core::OpenClCore;
+
+#[derive(Debug)]
+pub enum OpenClError {
+    PlatformNotFound,
+    DeviceNotFound,
+    ApiError { call: &'static str, code: i32 },
+    BuildFailed(String),
+    InvalidDimensions(String),
+    Other(String),
+}
+
+impl std::fmt::Display for OpenClError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OpenClError::PlatformNotFound => write!(f, "No OpenCL platform found"),
+            OpenClError::DeviceNotFound => write!(f, "No OpenCL device found"),
+            OpenClError::ApiError { call, code } => {
+                write!(f, "{} failed with error code {}", call, code)
+            }
+            OpenClError::BuildFailed(log) => write!(f, "Kernel build failed: {}", log),
+            OpenClError::InvalidDimensions(s) => write!(f, "Invalid dimensions: {}", s),
+            OpenClError::Other(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+impl Error for OpenClError {}
+
+impl From<OpenClError> for crate::MlError {
+    fn from(err: OpenClError) -> Self {
+        crate::MlError::BackendError(crate::backend::BackendError::OpenClError(err))
+    }
+}
+
+// Maps an OpenCL status code to a Result, naming the call that produced it
+fn check(call: &'static str, code: ffi::cl_int) -> Result<(), OpenClError> {
+    if code == ffi::CL_SUCCESS {
+        Ok(())
+    } else {
+        Err(OpenClError::ApiError { call, code })
+    }
+}
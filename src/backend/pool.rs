@@ -0,0 +1,141 @@
+// Only the CUDA and Vulkan backends cache their allocations
+#![cfg_attr(not(any(feature = "cuda", feature = "vulkan")), allow(dead_code))]
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Device memory held by a backend's caching allocator.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// Bytes in blocks currently handed out to live buffers.
+    pub allocated_bytes: u64,
+    /// Bytes obtained from the driver, including cached blocks waiting to be reused.
+    pub reserved_bytes: u64,
+    /// The most `allocated_bytes` has been.
+    pub peak_allocated_bytes: u64,
+}
+
+const SMALL_BLOCK: usize = 512;
+const LARGE_BLOCK: usize = 2 << 20;
+
+/// Rounds an allocation up to the size class it is cached under, so nearby sizes share
+/// blocks: multiples of 512 bytes up to 1 MiB, and of 2 MiB beyond.
+pub(crate) fn block_size(bytes: usize) -> usize {
+    if bytes <= 1 << 20 {
+        bytes.max(1).next_multiple_of(SMALL_BLOCK)
+    } else {
+        bytes.next_multiple_of(LARGE_BLOCK)
+    }
+}
+
+/// Freed device blocks, grouped by key, waiting to be handed out again.
+///
+/// The cache only does the bookkeeping; backends allocate and free the blocks themselves and
+/// decide when a freed block is safe to reuse.
+#[derive(Debug)]
+pub(crate) struct BlockCache<K, T> {
+    free: HashMap<K, Vec<(usize, T)>>,
+    stats: MemoryStats,
+}
+
+impl<K: Hash + Eq, T> Default for BlockCache<K, T> {
+    fn default() -> Self {
+        Self {
+            free: HashMap::new(),
+            stats: MemoryStats::default(),
+        }
+    }
+}
+
+impl<K: Hash + Eq, T> BlockCache<K, T> {
+    /// Takes a cached block for `key` that `ready` accepts, counting it as allocated.
+    pub fn take(&mut self, key: &K, ready: impl Fn(&T) -> bool) -> Option<T> {
+        let blocks = self.free.get_mut(key)?;
+        let position = blocks.iter().position(|(_, block)| ready(block))?;
+        let (bytes, block) = blocks.swap_remove(position);
+        self.add_allocated(bytes);
+        Some(block)
+    }
+
+    /// Counts a block freshly obtained from the driver.
+    pub fn allocated(&mut self, bytes: usize) {
+        self.add_allocated(bytes);
+        self.stats.reserved_bytes += bytes as u64;
+    }
+
+    fn add_allocated(&mut self, bytes: usize) {
+        self.stats.allocated_bytes += bytes as u64;
+        self.stats.peak_allocated_bytes = self
+            .stats
+            .peak_allocated_bytes
+            .max(self.stats.allocated_bytes);
+    }
+
+    /// Returns a block to the cache once its buffer is dropped.
+    pub fn release(&mut self, key: K, bytes: usize, block: T) {
+        self.stats.allocated_bytes -= bytes as u64;
+        self.free.entry(key).or_default().push((bytes, block));
+    }
+
+    /// Counts a block that was handed back to the driver instead of cached.
+    #[cfg_attr(not(feature = "cuda"), allow(dead_code))]
+    pub fn freed(&mut self, bytes: usize) {
+        self.stats.allocated_bytes -= bytes as u64;
+        self.stats.reserved_bytes -= bytes as u64;
+    }
+
+    /// Removes every cached block so the caller can free it.
+    pub fn drain(&mut self) -> Vec<T> {
+        let blocks: Vec<_> = self.free.drain().flat_map(|(_, blocks)| blocks).collect();
+        for (bytes, _) in &blocks {
+            self.stats.reserved_bytes -= *bytes as u64;
+        }
+        blocks.into_iter().map(|(_, block)| block).collect()
+    }
+
+    pub fn stats(&self) -> MemoryStats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_cache_reuse() {
+        assert_eq!(block_size(0), 512);
+        assert_eq!(block_size(513), 1024);
+        assert_eq!(block_size((1 << 20) + 1), 2 << 20);
+
+        let mut cache: BlockCache<usize, u32> = BlockCache::default();
+        cache.allocated(512);
+        cache.allocated(1024);
+        cache.release(512, 512, 1);
+        assert_eq!(
+            cache.stats(),
+            MemoryStats {
+                allocated_bytes: 1024,
+                reserved_bytes: 1536,
+                peak_allocated_bytes: 1536,
+            }
+        );
+
+        // Blocks that aren't ready yet are skipped
+        assert_eq!(cache.take(&512, |_| false), None);
+        assert_eq!(cache.take(&1024, |_| true), None);
+        assert_eq!(cache.take(&512, |_| true), Some(1));
+        assert_eq!(cache.stats().allocated_bytes, 1536);
+
+        cache.release(1024, 1024, 2);
+        assert_eq!(cache.drain(), vec![2]);
+        assert_eq!(
+            cache.stats(),
+            MemoryStats {
+                allocated_bytes: 512,
+                reserved_bytes: 512,
+                peak_allocated_bytes: 1536,
+            }
+        );
+    }
+}
@@ -0,0 +1,227 @@
+use super::{Backend, BackendError, DeviceType};
+use crate::MlResult;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, RwLock};
+
+/// A backend that can be handed out to tensors on any thread.
+pub type SharedBackend = Arc<dyn Backend + Send + Sync>;
+
+fn registry() -> &'static RwLock<HashMap<&'static str, SharedBackend>> {
+    static REGISTRY: OnceLock<RwLock<HashMap<&'static str, SharedBackend>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// Makes a backend implemented outside this crate available as `DeviceType::Custom(name)`.
+///
+/// The backend must report that device type from `Backend::device`, so tensors created on it
+/// can tell its buffers apart from those of the built-in backends. Register before the first
+/// tensor is created to have `DeviceManager::global` list it among the probed devices.
+pub fn register_backend(name: &'static str, backend: SharedBackend) -> MlResult<()> {
+    if backend.device() != DeviceType::Custom(name) {
+        return Err(BackendError::Other(format!(
+            "Backend registered as {:?} reports device {}",
+            name,
+            backend.device()
+        ))
+        .into());
+    }
+
+    let mut backends = registry()
+        .write()
+        .map_err(|_| BackendError::Other("Backend registry poisoned".to_string()))?;
+    if backends.contains_key(name) {
+        return Err(BackendError::Other(format!(
+            "A backend named {:?} is already registered",
+            name
+        ))
+        .into());
+    }
+    backends.insert(name, backend);
+    Ok(())
+}
+
+/// Removes a registered backend. Tensors already created on it keep it alive.
+pub fn unregister_backend(name: &str) -> Option<SharedBackend> {
+    registry().write().ok()?.remove(name)
+}
+
+pub fn registered_backend(name: &str) -> Option<SharedBackend> {
+    registry().read().ok()?.get(name).cloned()
+}
+
+/// Names of all registered backends, sorted.
+pub fn registered_backends() -> Vec<&'static str> {
+    let mut names: Vec<_> = registry()
+        .read()
+        .map(|backends| backends.keys().copied().collect())
+        .unwrap_or_default();
+    names.sort_unstable();
+    names
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::backend::{
+        is_deterministic, set_deterministic, transfer::lock_transfer_policy, transfer_policy,
+        with_transfer_policy, BackendCapabilities, CpuBackend, Device, DeviceManager,
+        TransferPolicy,
+    };
+    use crate::tensor::Tensor;
+
+    // A backend on a custom device that computes everything with the CPU backend, for
+    // tests of tensors off the default device
+    #[derive(Debug)]
+    pub(crate) struct HostBackend {
+        name: &'static str,
+        cpu: CpuBackend,
+        deterministic: bool,
+    }
+
+    impl HostBackend {
+        pub(crate) fn new(name: &'static str) -> Self {
+            Self {
+                name,
+                cpu: CpuBackend::new().unwrap(),
+                deterministic: true,
+            }
+        }
+    }
+
+    impl Backend for HostBackend {
+        fn execute_compute(&self, dimensions: [u32; 3]) -> MlResult<()> {
+            self.cpu.execute_compute(dimensions)
+        }
+
+        fn device(&self) -> DeviceType {
+            DeviceType::Custom(self.name)
+        }
+
+        fn capabilities(&self) -> BackendCapabilities {
+            let mut capabilities = BackendCapabilities::new(self.name);
+            capabilities.deterministic = self.deterministic;
+            capabilities
+        }
+
+        fn add(&self, a: &[f32], b: &[f32]) -> Vec<f32> {
+            self.cpu.add(a, b)
+        }
+
+        fn multiply(&self, a: &[f32], b: &[f32]) -> Vec<f32> {
+            self.cpu.multiply(a, b)
+        }
+
+        fn matmul(&self, a: &[f32], b: &[f32], m: usize, n: usize, k: usize) -> Vec<f32> {
+            self.cpu.matmul(a, b, m, n, k)
+        }
+
+        fn div(&self, a: &[f32], b: &[f32]) -> Vec<f32> {
+            self.cpu.div(a, b)
+        }
+
+        fn sub(&self, a: &[f32], b: &[f32]) -> Vec<f32> {
+            self.cpu.sub(a, b)
+        }
+
+        fn exp(&self, a: &[f32]) -> Vec<f32> {
+            self.cpu.exp(a)
+        }
+
+        fn log(&self, a: &[f32]) -> Vec<f32> {
+            self.cpu.log(a)
+        }
+
+        fn pow(&self, a: &[f32], power: f32) -> Vec<f32> {
+            self.cpu.pow(a, power)
+        }
+
+        fn sqrt(&self, a: &[f32]) -> Vec<f32> {
+            self.cpu.sqrt(a)
+        }
+
+        fn sum(&self, a: &[f32]) -> f32 {
+            self.cpu.sum(a)
+        }
+
+        fn mean(&self, a: &[f32]) -> f32 {
+            self.cpu.mean(a)
+        }
+    }
+
+    #[test]
+    fn test_register_custom_backend() -> MlResult<()> {
+        let device = DeviceType::Custom("test-host");
+        register_backend("test-host", Arc::new(HostBackend::new("test-host")))?;
+
+        assert!(registered_backends().contains(&"test-host"));
+        assert!(register_backend("test-host", Arc::new(HostBackend::new("test-host"))).is_err());
+        assert!(register_backend("test-other", Arc::new(HostBackend::new("test-host"))).is_err());
+
+        let manager = DeviceManager::new();
+        assert_eq!(manager.select_device(Some(device))?, device);
+
+        let a = Tensor::from_vec(vec![1.0, 2.0], &[2])?.to_device(device)?;
+        let b = Tensor::from_vec(vec![3.0, 4.0], &[2])?.to_device(device)?;
+        assert_eq!(a.device(), device);
+        assert_eq!(a.add(&b)?.data(), &[4.0, 6.0]);
+
+        assert!(unregister_backend("test-host").is_some());
+        assert!(Tensor::from_vec(vec![1.0], &[1])?
+            .to_device(device)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_deterministic_mode_rejects_backend() -> MlResult<()> {
+        let device = DeviceType::Custom("test-nondeterministic");
+        let mut backend = HostBackend::new("test-nondeterministic");
+        backend.deterministic = false;
+        register_backend("test-nondeterministic", Arc::new(backend))?;
+
+        let tensor = Tensor::from_vec(vec![1.0, 2.0], &[2])?;
+        assert!(tensor.to_device(device).is_ok());
+
+        set_deterministic(true);
+        assert!(is_deterministic());
+        let result = tensor.to_device(device);
+        set_deterministic(false);
+
+        assert!(result.is_err());
+        unregister_backend("test-nondeterministic");
+        Ok(())
+    }
+
+    #[test]
+    fn test_mixed_devices() -> MlResult<()> {
+        let _policy = lock_transfer_policy();
+        let device = DeviceType::Custom("test-mixed");
+        register_backend("test-mixed", Arc::new(HostBackend::new("test-mixed")))?;
+
+        let a = Tensor::from_vec(vec![1.0, 2.0], &[2])?.to_device(device)?;
+        let b = Tensor::from_vec(vec![3.0, 4.0], &[2])?.to_device(DeviceType::Cpu)?;
+
+        // Mixing devices fails by default, naming both of them
+        assert_eq!(transfer_policy(), TransferPolicy::Error);
+        let message = a.add(&b).unwrap_err().to_string();
+        assert!(
+            message.contains("test-mixed") && message.contains("Cpu"),
+            "{}",
+            message
+        );
+        assert!(b.matmul(&a.reshape(&[2, 1])?).is_err());
+        assert!(a.fuse().mul(&b).eval().is_err());
+
+        // With automatic transfers the result is on the first operand's device
+        let (sum, product) = with_transfer_policy(TransferPolicy::Auto, || {
+            (a.add(&b), b.fuse().mul(&a).eval())
+        });
+        assert_eq!(transfer_policy(), TransferPolicy::Error);
+
+        let sum = sum?;
+        assert_eq!((sum.device(), sum.data()), (device, &[4.0, 6.0][..]));
+        assert_eq!(product?.device(), DeviceType::Cpu);
+        unregister_backend("test-mixed");
+        Ok(())
+    }
+}
@@ -0,0 +1,56 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(test)]
+use std::sync::{Mutex, MutexGuard};
+
+static AUTO_TRANSFER: AtomicBool = AtomicBool::new(false);
+
+/// What an op does when its operands are on different devices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TransferPolicy {
+    /// Fail with [`TensorError::DeviceMismatch`](crate::tensor::TensorError::DeviceMismatch)
+    /// naming both devices.
+    #[default]
+    Error,
+    /// Copy the other operands to the device of the tensor the op is called on, through
+    /// host memory, and run the op there.
+    Auto,
+}
+
+/// Sets how every thread handles ops on tensors from different devices. Mixing devices is
+/// an error until this is set to [`TransferPolicy::Auto`].
+pub fn set_transfer_policy(policy: TransferPolicy) {
+    AUTO_TRANSFER.store(policy == TransferPolicy::Auto, Ordering::SeqCst);
+}
+
+pub fn transfer_policy() -> TransferPolicy {
+    if AUTO_TRANSFER.load(Ordering::SeqCst) {
+        TransferPolicy::Auto
+    } else {
+        TransferPolicy::Error
+    }
+}
+
+/// Runs `f` with `policy` in place of the current transfer policy. The previous policy is
+/// restored when `f` returns or panics; like [`set_transfer_policy`], it applies to every
+/// thread in the meantime.
+pub fn with_transfer_policy<T>(policy: TransferPolicy, f: impl FnOnce() -> T) -> T {
+    struct Restore(TransferPolicy);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            set_transfer_policy(self.0);
+        }
+    }
+
+    let _restore = Restore(transfer_policy());
+    set_transfer_policy(policy);
+    f()
+}
+
+// Held by tests that change the transfer policy, so tests relying on it don't run
+// while another has it changed
+#[cfg(test)]
+pub(crate) fn lock_transfer_policy() -> MutexGuard<'static, ()> {
+    static LOCK: Mutex<()> = Mutex::new(());
+    LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
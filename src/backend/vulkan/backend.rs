@@ -1,11 +1,15 @@
-use super::{VulkanCompute, VulkanCore};
-use crate::backend::{Backend, DeviceType};
+use super::{VulkanCompute, VulkanCore, VulkanTransfer};
+use crate::backend::{
+    conv2d_im2col, split_axis, Backend, BackendCapabilities, Conv2dShape, DeviceType, MemoryStats,
+    ReduceOp,
+};
 use crate::MlResult;
 use std::fmt::Debug;
 
 pub struct VulkanBackend {
     core: VulkanCore,
     compute: VulkanCompute,
+    transfer: VulkanTransfer,
 }
 
 impl Debug for VulkanBackend {
@@ -16,21 +20,43 @@ impl Debug for VulkanBackend {
 
 impl VulkanBackend {
     pub fn new() -> MlResult<Self> {
-        let core = VulkanCore::new()?;
+        Self::from_core(VulkanCore::new()?)
+    }
+
+    /// Creates a backend on the adapter at `index`, as listed by `VulkanCore::enumerate_adapters`.
+    pub fn with_adapter(index: usize) -> MlResult<Self> {
+        Self::from_core(VulkanCore::with_adapter(index)?)
+    }
+
+    fn from_core(core: VulkanCore) -> MlResult<Self> {
         let compute = VulkanCompute::new(
             core.device.clone(),
             core.instance.clone(),
             core.physical_device,
             core.queue_family_index,
         )?;
+        let transfer = VulkanTransfer::new(&core)?;
+
+        Ok(Self {
+            core,
+            compute,
+            transfer,
+        })
+    }
 
-        Ok(Self { core, compute })
+    /// Staging buffers and asynchronous copies for overlapping uploads with compute.
+    pub fn transfer(&self) -> &VulkanTransfer {
+        &self.transfer
     }
 }
 
 impl Backend for VulkanBackend {
     fn device(&self) -> DeviceType {
-        DeviceType::Vulkan
+        DeviceType::Vulkan(self.core.adapter_index)
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        self.core.capabilities()
     }
 
     fn add(&self, a: &[f32], b: &[f32]) -> Vec<f32> {
@@ -72,6 +98,27 @@ impl Backend for VulkanBackend {
         sum / a.len() as f32
     }
 
+    fn reduce(&self, op: ReduceOp, a: &[f32], shape: &[usize], axis: usize) -> Vec<f32> {
+        let (outer, len, inner) = split_axis(shape, axis);
+        if a.is_empty() || outer * inner == 0 {
+            return op.apply(a, outer, len, inner);
+        }
+
+        self.compute
+            .reduce_axis(a, op, outer, len, inner)
+            .unwrap_or_else(|_| op.apply(a, outer, len, inner))
+    }
+
+    fn conv2d(&self, input: &[f32], weight: &[f32], shape: &Conv2dShape) -> Vec<f32> {
+        if input.is_empty() || weight.is_empty() || shape.output_len() == 0 {
+            return conv2d_im2col(self, input, weight, shape);
+        }
+
+        self.compute
+            .conv2d(input, weight, shape)
+            .unwrap_or_else(|_| conv2d_im2col(self, input, weight, shape))
+    }
+
     fn execute_compute(&self, dimensions: [u32; 3]) -> MlResult<()> {
         self.compute.execute_compute(dimensions)
     }
@@ -91,4 +138,13 @@ impl Backend for VulkanBackend {
     fn sqrt(&self, a: &[f32]) -> Vec<f32> {
         self.compute.sqrt(a)
     }
+
+    fn memory_stats(&self) -> Option<MemoryStats> {
+        Some(self.compute.memory_stats())
+    }
+
+    fn empty_cache(&self) -> MlResult<()> {
+        self.compute.empty_cache();
+        Ok(())
+    }
 }
@@ -110,4 +110,13 @@ impl Buffer {
     pub fn handle(&self) -> vk::Buffer {
         self.buffer
     }
+
+    pub fn memory(&self) -> vk::DeviceMemory {
+        self.memory
+    }
+
+    /// Size in bytes.
+    pub fn size(&self) -> vk::DeviceSize {
+        self.size
+    }
 }
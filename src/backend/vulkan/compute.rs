@@ -1,4 +1,6 @@
-use super::{Buffer, VulkanError};
+use super::pool::BufferPool;
+use super::VulkanError;
+use crate::backend::{Conv2dShape, MemoryStats, ReduceOp};
 use crate::MlResult;
 use ash::{vk, Device, Instance};
 use std::fs::read;
@@ -6,8 +8,6 @@ use std::sync::Arc;
 
 pub struct VulkanCompute {
     device: Arc<Device>,
-    instance: Arc<Instance>,
-    physical_device: vk::PhysicalDevice,
     compute_queue: vk::Queue,
     command_pool: vk::CommandPool,
     command_buffer: vk::CommandBuffer,
@@ -18,6 +18,9 @@ pub struct VulkanCompute {
     reduction_pipeline: vk::Pipeline,
     binary_ops_pipeline: vk::Pipeline,
     matmul_pipeline: vk::Pipeline,
+    reduce_axis_pipeline: vk::Pipeline,
+    conv2d_pipeline: vk::Pipeline,
+    pool: BufferPool,
 }
 
 impl VulkanCompute {
@@ -62,7 +65,7 @@ impl VulkanCompute {
         let push_constant_range = [vk::PushConstantRange {
             stage_flags: vk::ShaderStageFlags::COMPUTE,
             offset: 0,
-            size: 12, // 3 * sizeof(u32)
+            size: 64, // 16 * sizeof(u32), room for conv2d's geometry
             ..Default::default()
         }];
 
@@ -96,6 +99,15 @@ impl VulkanCompute {
         let matmul_pipeline =
             Self::create_compute_pipeline(&device, pipeline_layout, "shaders/vulkan/matmul.spv")?;
 
+        let reduce_axis_pipeline = Self::create_compute_pipeline(
+            &device,
+            pipeline_layout,
+            "shaders/vulkan/reduce_axis.spv",
+        )?;
+
+        let conv2d_pipeline =
+            Self::create_compute_pipeline(&device, pipeline_layout, "shaders/vulkan/conv2d.spv")?;
+
         let fence_info = vk::FenceCreateInfo {
             s_type: vk::StructureType::FENCE_CREATE_INFO,
             ..Default::default()
@@ -107,10 +119,10 @@ impl VulkanCompute {
                 .map_err(VulkanError::from)?
         };
 
+        let pool = BufferPool::new(device.clone(), instance, physical_device);
+
         Ok(Self {
             device,
-            instance,
-            physical_device,
             compute_queue,
             command_pool,
             command_buffer,
@@ -120,7 +132,10 @@ impl VulkanCompute {
             binary_ops_pipeline,
             reduction_pipeline,
             matmul_pipeline,
+            reduce_axis_pipeline,
+            conv2d_pipeline,
             fence,
+            pool,
         })
     }
 
@@ -132,31 +147,22 @@ impl VulkanCompute {
     ) -> MlResult<Vec<f32>> {
         let size = input_a.len();
 
-        let input_buffer_a = Buffer::new(
-            self.device.clone(),
-            self.instance.clone(),
-            self.physical_device,
-            std::mem::size_of_val(input_a) as u64,
+        let input_buffer_a = self.pool.acquire(
+            std::mem::size_of_val(input_a),
             vk::BufferUsageFlags::STORAGE_BUFFER,
             vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
         )?;
         input_buffer_a.map_memory(input_a)?;
 
-        let input_buffer_b = Buffer::new(
-            self.device.clone(),
-            self.instance.clone(),
-            self.physical_device,
-            std::mem::size_of_val(input_a) as u64,
+        let input_buffer_b = self.pool.acquire(
+            std::mem::size_of_val(input_a),
             vk::BufferUsageFlags::STORAGE_BUFFER,
             vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
         )?;
         input_buffer_b.map_memory(input_b)?;
 
-        let output_buffer = Buffer::new(
-            self.device.clone(),
-            self.instance.clone(),
-            self.physical_device,
-            std::mem::size_of_val(input_a) as u64,
+        let output_buffer = self.pool.acquire(
+            std::mem::size_of_val(input_a),
             vk::BufferUsageFlags::STORAGE_BUFFER,
             vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
         )?;
@@ -249,21 +255,15 @@ impl VulkanCompute {
     }
 
     pub fn execute_reduction(&self, input: &[f32]) -> MlResult<f32> {
-        let input_buffer = Buffer::new(
-            self.device.clone(),
-            self.instance.clone(),
-            self.physical_device,
-            std::mem::size_of_val(input) as u64,
+        let input_buffer = self.pool.acquire(
+            std::mem::size_of_val(input),
             vk::BufferUsageFlags::STORAGE_BUFFER,
             vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
         )?;
         input_buffer.map_memory(input)?;
 
-        let output_buffer = Buffer::new(
-            self.device.clone(),
-            self.instance.clone(),
-            self.physical_device,
-            std::mem::size_of::<f32>() as u64,
+        let output_buffer = self.pool.acquire(
+            std::mem::size_of::<f32>(),
             vk::BufferUsageFlags::STORAGE_BUFFER,
             vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
         )?;
@@ -356,32 +356,263 @@ impl VulkanCompute {
         Ok(result[0])
     }
 
+    /// Reduces the middle dimension of `input` viewed as `[outer, axis, inner]`.
+    pub fn reduce_axis(
+        &self,
+        input: &[f32],
+        op: ReduceOp,
+        outer: usize,
+        axis: usize,
+        inner: usize,
+    ) -> MlResult<Vec<f32>> {
+        let output_len = outer * inner;
+
+        let input_buffer = self.pool.acquire(
+            std::mem::size_of_val(input),
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        )?;
+        input_buffer.map_memory(input)?;
+
+        let output_buffer = self.pool.acquire(
+            std::mem::size_of::<f32>() * output_len,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        )?;
+
+        let descriptor_set = self.allocate_descriptor_set()?;
+        super::descriptor::update_descriptor_set(
+            &self.device,
+            descriptor_set,
+            &[&input_buffer, &output_buffer],
+        )?;
+
+        // The opcodes match reduce_axis.comp
+        let opcode = match op {
+            ReduceOp::Sum => 0,
+            ReduceOp::Mean => 1,
+            ReduceOp::Max => 2,
+            ReduceOp::Min => 3,
+        };
+        let push_constant_data = [outer as u32, axis as u32, inner as u32, opcode];
+
+        unsafe {
+            self.device
+                .reset_command_buffer(self.command_buffer, vk::CommandBufferResetFlags::empty())
+                .map_err(VulkanError::from)?;
+
+            let begin_info = vk::CommandBufferBeginInfo {
+                s_type: vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
+                flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+                ..Default::default()
+            };
+
+            self.device
+                .begin_command_buffer(self.command_buffer, &begin_info)
+                .map_err(VulkanError::from)?;
+
+            self.device.cmd_push_constants(
+                self.command_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                std::slice::from_raw_parts(
+                    push_constant_data.as_ptr() as *const u8,
+                    std::mem::size_of_val(&push_constant_data),
+                ),
+            );
+
+            self.device.cmd_bind_pipeline(
+                self.command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.reduce_axis_pipeline,
+            );
+
+            self.device.cmd_bind_descriptor_sets(
+                self.command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline_layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+
+            self.device
+                .cmd_dispatch(self.command_buffer, output_len.div_ceil(256) as u32, 1, 1);
+
+            self.device
+                .end_command_buffer(self.command_buffer)
+                .map_err(VulkanError::from)?;
+
+            self.device
+                .reset_fences(&[self.fence])
+                .map_err(VulkanError::from)?;
+
+            let submit_info = vk::SubmitInfo {
+                s_type: vk::StructureType::SUBMIT_INFO,
+                command_buffer_count: 1,
+                p_command_buffers: &self.command_buffer,
+                ..Default::default()
+            };
+
+            self.device
+                .queue_submit(self.compute_queue, &[submit_info], self.fence)
+                .map_err(VulkanError::from)?;
+
+            self.device
+                .wait_for_fences(&[self.fence], true, u64::MAX)
+                .map_err(VulkanError::from)?;
+            self.device
+                .reset_fences(&[self.fence])
+                .map_err(VulkanError::from)?;
+        }
+
+        output_buffer.read_memory(output_len)
+    }
+
+    /// Convolves an NCHW `input` with `weight`, one invocation per output element.
+    pub fn conv2d(&self, input: &[f32], weight: &[f32], shape: &Conv2dShape) -> MlResult<Vec<f32>> {
+        let output_len = shape.output_len();
+
+        let input_buffer = self.pool.acquire(
+            std::mem::size_of_val(input),
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        )?;
+        input_buffer.map_memory(input)?;
+
+        let weight_buffer = self.pool.acquire(
+            std::mem::size_of_val(weight),
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        )?;
+        weight_buffer.map_memory(weight)?;
+
+        let output_buffer = self.pool.acquire(
+            std::mem::size_of::<f32>() * output_len,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        )?;
+
+        let descriptor_set = self.allocate_descriptor_set()?;
+        super::descriptor::update_descriptor_set(
+            &self.device,
+            descriptor_set,
+            &[&input_buffer, &weight_buffer, &output_buffer],
+        )?;
+
+        // Laid out like the push constant block in conv2d.comp
+        let [batch, channels, height, width] = shape.input;
+        let [out_channels, _, kernel_h, kernel_w] = shape.weight;
+        let [_, _, out_h, out_w] = shape.output();
+        let push_constant_data = [
+            batch,
+            channels,
+            height,
+            width,
+            out_channels,
+            kernel_h,
+            kernel_w,
+            shape.stride.0,
+            shape.stride.1,
+            shape.padding.0,
+            shape.padding.1,
+            out_h,
+            out_w,
+        ]
+        .map(|value| value as u32);
+
+        unsafe {
+            self.device
+                .reset_command_buffer(self.command_buffer, vk::CommandBufferResetFlags::empty())
+                .map_err(VulkanError::from)?;
+
+            let begin_info = vk::CommandBufferBeginInfo {
+                s_type: vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
+                flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+                ..Default::default()
+            };
+
+            self.device
+                .begin_command_buffer(self.command_buffer, &begin_info)
+                .map_err(VulkanError::from)?;
+
+            self.device.cmd_push_constants(
+                self.command_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                std::slice::from_raw_parts(
+                    push_constant_data.as_ptr() as *const u8,
+                    std::mem::size_of_val(&push_constant_data),
+                ),
+            );
+
+            self.device.cmd_bind_pipeline(
+                self.command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.conv2d_pipeline,
+            );
+
+            self.device.cmd_bind_descriptor_sets(
+                self.command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline_layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+
+            self.device
+                .cmd_dispatch(self.command_buffer, output_len.div_ceil(256) as u32, 1, 1);
+
+            self.device
+                .end_command_buffer(self.command_buffer)
+                .map_err(VulkanError::from)?;
+
+            self.device
+                .reset_fences(&[self.fence])
+                .map_err(VulkanError::from)?;
+
+            let submit_info = vk::SubmitInfo {
+                s_type: vk::StructureType::SUBMIT_INFO,
+                command_buffer_count: 1,
+                p_command_buffers: &self.command_buffer,
+                ..Default::default()
+            };
+
+            self.device
+                .queue_submit(self.compute_queue, &[submit_info], self.fence)
+                .map_err(VulkanError::from)?;
+
+            self.device
+                .wait_for_fences(&[self.fence], true, u64::MAX)
+                .map_err(VulkanError::from)?;
+            self.device
+                .reset_fences(&[self.fence])
+                .map_err(VulkanError::from)?;
+        }
+
+        output_buffer.read_memory(output_len)
+    }
+
     pub fn matmul(&self, a: &[f32], b: &[f32], m: usize, n: usize, k: usize) -> MlResult<Vec<f32>> {
-        let input_a = Buffer::new(
-            self.device.clone(),
-            self.instance.clone(),
-            self.physical_device,
-            std::mem::size_of_val(a) as u64,
+        let input_a = self.pool.acquire(
+            std::mem::size_of_val(a),
             vk::BufferUsageFlags::STORAGE_BUFFER,
             vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
         )?;
         input_a.map_memory(a)?;
 
-        let input_b = Buffer::new(
-            self.device.clone(),
-            self.instance.clone(),
-            self.physical_device,
-            std::mem::size_of_val(b) as u64,
+        let input_b = self.pool.acquire(
+            std::mem::size_of_val(b),
             vk::BufferUsageFlags::STORAGE_BUFFER,
             vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
         )?;
         input_b.map_memory(b)?;
 
-        let output = Buffer::new(
-            self.device.clone(),
-            self.instance.clone(),
-            self.physical_device,
-            (std::mem::size_of::<f32>() * m * k) as u64,
+        let output = self.pool.acquire(
+            std::mem::size_of::<f32>() * m * k,
             vk::BufferUsageFlags::STORAGE_BUFFER,
             vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
         )?;
@@ -562,6 +793,16 @@ impl VulkanCompute {
             .unwrap_or_else(|_| vec![0.0; a.len()])
     }
 
+    /// Bytes held by the buffer pool shared by this device's dispatches.
+    pub fn memory_stats(&self) -> MemoryStats {
+        self.pool.memory_stats()
+    }
+
+    /// Frees the buffers cached between dispatches.
+    pub fn empty_cache(&self) {
+        self.pool.empty_cache();
+    }
+
     pub fn execute_compute(&self, _dimensions: [u32; 3]) -> MlResult<()> {
         unsafe {
             self.device
@@ -587,6 +828,9 @@ impl Drop for VulkanCompute {
             self.device.destroy_pipeline(self.reduction_pipeline, None);
             self.device.destroy_pipeline(self.binary_ops_pipeline, None);
             self.device.destroy_pipeline(self.matmul_pipeline, None);
+            self.device
+                .destroy_pipeline(self.reduce_axis_pipeline, None);
+            self.device.destroy_pipeline(self.conv2d_pipeline, None);
             self.device
                 .destroy_pipeline_layout(self.pipeline_layout, None);
             self.device
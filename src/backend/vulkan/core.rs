@@ -1,4 +1,5 @@
 use super::VulkanError;
+use crate::backend::BackendCapabilities;
 use ash::{vk, Device, Entry, Instance};
 use std::ffi::CStr;
 use std::sync::Arc;
@@ -9,12 +10,103 @@ pub struct VulkanCore {
     pub device: Arc<Device>,
     pub physical_device: vk::PhysicalDevice,
     pub queue_family_index: u32,
+    pub adapter_index: usize,
+}
+
+/// A physical device that exposes a compute queue.
+#[derive(Debug, Clone)]
+pub struct VulkanAdapter {
+    /// Position in the instance's physical device list, as accepted by `with_adapter`.
+    pub index: usize,
+    pub name: String,
+    /// Total size of the device-local memory heaps, in bytes.
+    pub memory: u64,
+    pub capabilities: BackendCapabilities,
 }
 
 impl VulkanCore {
+    /// Opens the first adapter with a compute queue.
     pub fn new() -> Result<Self, VulkanError> {
+        Self::create(None)
+    }
+
+    /// Opens the adapter at `index` in the physical device list.
+    pub fn with_adapter(index: usize) -> Result<Self, VulkanError> {
+        Self::create(Some(index))
+    }
+
+    /// Lists every adapter that can run compute work.
+    pub fn enumerate_adapters() -> Result<Vec<VulkanAdapter>, VulkanError> {
         unsafe {
             let entry = Entry::linked();
+            let instance = Self::create_instance(&entry)?;
+
+            let adapters = instance
+                .enumerate_physical_devices()
+                .map(|pdevices| {
+                    pdevices
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, pdevice)| {
+                            Self::compute_queue_family(&instance, **pdevice).is_some()
+                        })
+                        .map(|(index, pdevice)| {
+                            let properties = instance.get_physical_device_properties(*pdevice);
+                            let memory = instance.get_physical_device_memory_properties(*pdevice);
+                            VulkanAdapter {
+                                index,
+                                name: CStr::from_ptr(properties.device_name.as_ptr())
+                                    .to_string_lossy()
+                                    .into_owned(),
+                                memory: memory.memory_heaps[..memory.memory_heap_count as usize]
+                                    .iter()
+                                    .filter(|heap| {
+                                        heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL)
+                                    })
+                                    .map(|heap| heap.size)
+                                    .sum(),
+                                capabilities: Self::query_capabilities(&instance, *pdevice),
+                            }
+                        })
+                        .collect()
+                })
+                .map_err(VulkanError::from);
+
+            instance.destroy_instance(None);
+            adapters
+        }
+    }
+
+    /// Reports what the opened adapter supports.
+    pub fn capabilities(&self) -> BackendCapabilities {
+        Self::query_capabilities(&self.instance, self.physical_device)
+    }
+
+    fn query_capabilities(instance: &Instance, pdevice: vk::PhysicalDevice) -> BackendCapabilities {
+        unsafe {
+            let properties = instance.get_physical_device_properties(pdevice);
+
+            // Shader float16 is only reported through the 1.2 feature chain
+            let supports_f16 = properties.api_version >= vk::API_VERSION_1_2 && {
+                let mut features12 = vk::PhysicalDeviceVulkan12Features::default();
+                let mut features =
+                    vk::PhysicalDeviceFeatures2::default().push_next(&mut features12);
+                instance.get_physical_device_features2(pdevice, &mut features);
+                features12.shader_float16 == vk::TRUE
+            };
+
+            let mut capabilities = BackendCapabilities::new(
+                CStr::from_ptr(properties.device_name.as_ptr()).to_string_lossy(),
+            );
+            capabilities.max_buffer_size = Some(properties.limits.max_storage_buffer_range as u64);
+            capabilities.supports_f16 = supports_f16;
+            capabilities.supports_async = true;
+            capabilities
+        }
+    }
+
+    fn create_instance(entry: &Entry) -> Result<Instance, VulkanError> {
+        unsafe {
             let app_name = CStr::from_bytes_with_nul(b"Cetana ML\0")
                 .map_err(|_| VulkanError::InitializationFailed("Invalid app name"))?;
 
@@ -52,29 +144,45 @@ impl VulkanCore {
                 ..Default::default()
             };
 
-            let instance = entry
+            entry
                 .create_instance(&create_info, None)
-                .map_err(VulkanError::from)?;
-            let instance = Arc::new(instance);
+                .map_err(VulkanError::from)
+        }
+    }
+
+    fn compute_queue_family(instance: &Instance, pdevice: vk::PhysicalDevice) -> Option<usize> {
+        let queue_families =
+            unsafe { instance.get_physical_device_queue_family_properties(pdevice) };
+        queue_families
+            .iter()
+            .position(|info| info.queue_flags.contains(vk::QueueFlags::COMPUTE))
+    }
+
+    fn create(adapter: Option<usize>) -> Result<Self, VulkanError> {
+        unsafe {
+            let entry = Entry::linked();
+            let instance = Arc::new(Self::create_instance(&entry)?);
 
             let pdevices = instance
                 .enumerate_physical_devices()
                 .map_err(VulkanError::from)?;
 
-            let (physical_device, queue_family_index) = pdevices
-                .iter()
-                .find_map(|pdevice| {
-                    let queue_families =
-                        instance.get_physical_device_queue_family_properties(*pdevice);
-                    queue_families.iter().enumerate().find_map(|(index, info)| {
-                        if info.queue_flags.contains(vk::QueueFlags::COMPUTE) {
-                            Some((*pdevice, index))
-                        } else {
-                            None
-                        }
+            let (adapter_index, physical_device, queue_family_index) = match adapter {
+                Some(index) => {
+                    let pdevice = *pdevices.get(index).ok_or(VulkanError::DeviceNotSuitable)?;
+                    let family = Self::compute_queue_family(&instance, pdevice)
+                        .ok_or(VulkanError::NoComputeQueue)?;
+                    (index, pdevice, family)
+                }
+                None => pdevices
+                    .iter()
+                    .enumerate()
+                    .find_map(|(index, pdevice)| {
+                        Self::compute_queue_family(&instance, *pdevice)
+                            .map(|family| (index, *pdevice, family))
                     })
-                })
-                .ok_or(VulkanError::NoComputeQueue)?;
+                    .ok_or(VulkanError::NoComputeQueue)?,
+            };
 
             let queue_priorities = [1.0];
             let queue_info = vk::DeviceQueueCreateInfo {
@@ -103,6 +211,7 @@ impl VulkanCore {
                 device,
                 physical_device,
                 queue_family_index: queue_family_index as u32,
+                adapter_index,
             })
         }
     }
@@ -7,11 +7,14 @@ mod compute;
 mod core;
 mod descriptor;
 mod memory;
+mod pool;
+mod transfer;
 
 pub use backend::VulkanBackend;
 pub use buffer::Buffer;
 pub use compute::VulkanCompute;
-pub use core::VulkanCore;
+pub use core::{VulkanAdapter, VulkanCore};
+pub use transfer::{PendingTransfer, StagingBuffer, VulkanTransfer};
 
 #[derive(Debug)]
 pub enum VulkanError {
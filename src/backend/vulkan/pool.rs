@@ -0,0 +1,119 @@
+use super::Buffer;
+use crate::backend::pool::{block_size, BlockCache};
+use crate::backend::MemoryStats;
+use crate::MlResult;
+use ash::{vk, Device, Instance};
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+type BufferKey = (usize, vk::BufferUsageFlags, vk::MemoryPropertyFlags);
+
+/// Caches buffers between dispatches instead of allocating and freeing device memory for
+/// every op.
+pub struct BufferPool {
+    device: Arc<Device>,
+    instance: Arc<Instance>,
+    physical_device: vk::PhysicalDevice,
+    cache: Mutex<BlockCache<BufferKey, Buffer>>,
+}
+
+impl BufferPool {
+    pub fn new(
+        device: Arc<Device>,
+        instance: Arc<Instance>,
+        physical_device: vk::PhysicalDevice,
+    ) -> Self {
+        Self {
+            device,
+            instance,
+            physical_device,
+            cache: Mutex::new(BlockCache::default()),
+        }
+    }
+
+    fn cache(&self) -> MutexGuard<'_, BlockCache<BufferKey, Buffer>> {
+        self.cache
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Hands out a buffer of at least `bytes`, reusing a cached one when possible. The
+    /// buffer returns to the pool when dropped, so it must not be dropped while queued work
+    /// still uses it.
+    pub fn acquire(
+        &self,
+        bytes: usize,
+        usage: vk::BufferUsageFlags,
+        properties: vk::MemoryPropertyFlags,
+    ) -> MlResult<PooledBuffer<'_>> {
+        let key = (block_size(bytes), usage, properties);
+
+        let cached = self.cache().take(&key, |_| true);
+        let buffer = match cached {
+            Some(buffer) => buffer,
+            None => {
+                let buffer = Buffer::new(
+                    self.device.clone(),
+                    self.instance.clone(),
+                    self.physical_device,
+                    key.0 as vk::DeviceSize,
+                    usage,
+                    properties,
+                )?;
+                self.cache().allocated(key.0);
+                buffer
+            }
+        };
+
+        Ok(PooledBuffer {
+            pool: self,
+            key,
+            buffer: Some(buffer),
+        })
+    }
+
+    pub fn memory_stats(&self) -> MemoryStats {
+        self.cache().stats()
+    }
+
+    /// Frees every cached buffer.
+    pub fn empty_cache(&self) {
+        for buffer in self.cache().drain() {
+            unsafe {
+                self.device.destroy_buffer(buffer.handle(), None);
+                self.device.free_memory(buffer.memory(), None);
+            }
+        }
+    }
+}
+
+impl Drop for BufferPool {
+    fn drop(&mut self) {
+        self.empty_cache();
+    }
+}
+
+/// A buffer on loan from a `BufferPool`.
+pub struct PooledBuffer<'a> {
+    pool: &'a BufferPool,
+    key: BufferKey,
+    buffer: Option<Buffer>,
+}
+
+impl Deref for PooledBuffer<'_> {
+    type Target = Buffer;
+
+    fn deref(&self) -> &Buffer {
+        self.buffer
+            .as_ref()
+            .expect("pooled buffer already released")
+    }
+}
+
+impl Drop for PooledBuffer<'_> {
+    fn drop(&mut self) {
+        if let Some(buffer) = self.buffer.take() {
+            self.pool.cache().release(self.key, self.key.0, buffer);
+        }
+    }
+}
@@ -0,0 +1,306 @@
+use super::{Buffer, VulkanCore, VulkanError};
+use crate::MlResult;
+use ash::{vk, Device, Instance};
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+use std::sync::Arc;
+
+/// Host-visible staging memory that stays mapped for its whole lifetime. The driver can DMA
+/// straight out of it, so filling one staging buffer can overlap with copying another.
+pub struct StagingBuffer {
+    buffer: Buffer,
+    ptr: NonNull<f32>,
+    len: usize,
+    device: Arc<Device>,
+}
+
+impl StagingBuffer {
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+}
+
+impl Deref for StagingBuffer {
+    type Target = [f32];
+
+    fn deref(&self) -> &[f32] {
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for StagingBuffer {
+    fn deref_mut(&mut self) -> &mut [f32] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for StagingBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.unmap_memory(self.buffer.memory());
+            self.device.destroy_buffer(self.buffer.handle(), None);
+            self.device.free_memory(self.buffer.memory(), None);
+        }
+    }
+}
+
+/// Queues buffer copies on the compute queue without waiting for them.
+pub struct VulkanTransfer {
+    device: Arc<Device>,
+    instance: Arc<Instance>,
+    physical_device: vk::PhysicalDevice,
+    queue: vk::Queue,
+    command_pool: vk::CommandPool,
+}
+
+impl VulkanTransfer {
+    pub fn new(core: &VulkanCore) -> MlResult<Self> {
+        let device = core.device.clone();
+        let queue = unsafe { device.get_device_queue(core.queue_family_index, 0) };
+
+        let command_pool_info = vk::CommandPoolCreateInfo {
+            s_type: vk::StructureType::COMMAND_POOL_CREATE_INFO,
+            flags: vk::CommandPoolCreateFlags::TRANSIENT,
+            queue_family_index: core.queue_family_index,
+            ..Default::default()
+        };
+
+        let command_pool = unsafe {
+            device
+                .create_command_pool(&command_pool_info, None)
+                .map_err(VulkanError::from)?
+        };
+
+        Ok(Self {
+            device,
+            instance: core.instance.clone(),
+            physical_device: core.physical_device,
+            queue,
+            command_pool,
+        })
+    }
+
+    /// Allocates `len` floats of mapped, host-coherent memory.
+    pub fn staging_buffer(&self, len: usize) -> MlResult<StagingBuffer> {
+        let buffer = Buffer::new(
+            self.device.clone(),
+            self.instance.clone(),
+            self.physical_device,
+            (len.max(1) * std::mem::size_of::<f32>()) as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        )?;
+
+        let ptr = unsafe {
+            self.device
+                .map_memory(
+                    buffer.memory(),
+                    0,
+                    vk::WHOLE_SIZE,
+                    vk::MemoryMapFlags::empty(),
+                )
+                .map_err(VulkanError::from)?
+        };
+
+        Ok(StagingBuffer {
+            buffer,
+            ptr: NonNull::new(ptr as *mut f32)
+                .ok_or(VulkanError::Other("Failed to map staging buffer".into()))?,
+            len,
+            device: self.device.clone(),
+        })
+    }
+
+    /// Allocates `len` floats of device-local memory usable by the compute shaders.
+    pub fn device_buffer(&self, len: usize) -> MlResult<Buffer> {
+        Buffer::new(
+            self.device.clone(),
+            self.instance.clone(),
+            self.physical_device,
+            (len.max(1) * std::mem::size_of::<f32>()) as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::TRANSFER_SRC
+                | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        )
+    }
+
+    /// Queues a copy of `src` into `dst` and returns without waiting.
+    pub fn upload_async<'a>(
+        &'a self,
+        src: &'a StagingBuffer,
+        dst: &Buffer,
+    ) -> MlResult<PendingTransfer<'a>> {
+        if dst.size() < std::mem::size_of_val(&src[..]) as vk::DeviceSize {
+            return Err(VulkanError::Other("Upload destination is too small".into()).into());
+        }
+        self.submit_copy(src.buffer(), dst, std::mem::size_of_val(&src[..]))
+    }
+
+    /// Queues a copy of `src` into `dst` and returns without waiting. `dst` can be read once
+    /// the transfer has completed.
+    pub fn download_async<'a>(
+        &'a self,
+        src: &Buffer,
+        dst: &'a mut StagingBuffer,
+    ) -> MlResult<PendingTransfer<'a>> {
+        let size = std::mem::size_of_val(&dst[..]);
+        if src.size() < size as vk::DeviceSize {
+            return Err(VulkanError::Other("Download source is too small".into()).into());
+        }
+        self.submit_copy(src, dst.buffer(), size)
+    }
+
+    fn submit_copy<'a>(
+        &'a self,
+        src: &Buffer,
+        dst: &Buffer,
+        size: usize,
+    ) -> MlResult<PendingTransfer<'a>> {
+        let allocate_info = vk::CommandBufferAllocateInfo {
+            s_type: vk::StructureType::COMMAND_BUFFER_ALLOCATE_INFO,
+            command_pool: self.command_pool,
+            level: vk::CommandBufferLevel::PRIMARY,
+            command_buffer_count: 1,
+            ..Default::default()
+        };
+
+        unsafe {
+            let command_buffer = self
+                .device
+                .allocate_command_buffers(&allocate_info)
+                .map_err(VulkanError::from)?[0];
+
+            let fence_info = vk::FenceCreateInfo {
+                s_type: vk::StructureType::FENCE_CREATE_INFO,
+                ..Default::default()
+            };
+            let fence = match self.device.create_fence(&fence_info, None) {
+                Ok(fence) => fence,
+                Err(e) => {
+                    self.device
+                        .free_command_buffers(self.command_pool, &[command_buffer]);
+                    return Err(VulkanError::from(e).into());
+                }
+            };
+
+            // From here on the transfer cleans up after itself, even if submission fails
+            let mut transfer = PendingTransfer {
+                device: self.device.clone(),
+                command_pool: self.command_pool,
+                command_buffer,
+                fence,
+                submitted: false,
+                _borrow: PhantomData,
+            };
+
+            let begin_info = vk::CommandBufferBeginInfo {
+                s_type: vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
+                flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+                ..Default::default()
+            };
+            self.device
+                .begin_command_buffer(command_buffer, &begin_info)
+                .map_err(VulkanError::from)?;
+
+            if size > 0 {
+                let region = vk::BufferCopy {
+                    src_offset: 0,
+                    dst_offset: 0,
+                    size: size as vk::DeviceSize,
+                };
+                self.device
+                    .cmd_copy_buffer(command_buffer, src.handle(), dst.handle(), &[region]);
+            }
+
+            // Make the copy visible to later compute dispatches and to host reads
+            let barrier = vk::MemoryBarrier {
+                s_type: vk::StructureType::MEMORY_BARRIER,
+                src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+                dst_access_mask: vk::AccessFlags::SHADER_READ | vk::AccessFlags::HOST_READ,
+                ..Default::default()
+            };
+            self.device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &[barrier],
+                &[],
+                &[],
+            );
+
+            self.device
+                .end_command_buffer(command_buffer)
+                .map_err(VulkanError::from)?;
+
+            let submit_info = vk::SubmitInfo {
+                s_type: vk::StructureType::SUBMIT_INFO,
+                command_buffer_count: 1,
+                p_command_buffers: &command_buffer,
+                ..Default::default()
+            };
+            self.device
+                .queue_submit(self.queue, &[submit_info], fence)
+                .map_err(VulkanError::from)?;
+
+            transfer.submitted = true;
+            Ok(transfer)
+        }
+    }
+}
+
+impl Drop for VulkanTransfer {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_command_pool(self.command_pool, None);
+        }
+    }
+}
+
+/// A copy submitted by `VulkanTransfer`. The staging buffer stays borrowed until the copy
+/// has completed; dropping the transfer waits for it.
+#[must_use = "dropping a transfer blocks until the copy completes"]
+pub struct PendingTransfer<'a> {
+    device: Arc<Device>,
+    command_pool: vk::CommandPool,
+    command_buffer: vk::CommandBuffer,
+    fence: vk::Fence,
+    submitted: bool,
+    _borrow: PhantomData<&'a mut [f32]>,
+}
+
+impl PendingTransfer<'_> {
+    pub fn is_complete(&self) -> MlResult<bool> {
+        unsafe {
+            Ok(self
+                .device
+                .get_fence_status(self.fence)
+                .map_err(VulkanError::from)?)
+        }
+    }
+
+    /// Blocks until the copy has completed.
+    pub fn wait(self) -> MlResult<()> {
+        unsafe {
+            self.device
+                .wait_for_fences(&[self.fence], true, u64::MAX)
+                .map_err(VulkanError::from)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for PendingTransfer<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            if self.submitted {
+                let _ = self.device.wait_for_fences(&[self.fence], true, u64::MAX);
+            }
+            self.device.destroy_fence(self.fence, None);
+            self.device
+                .free_command_buffers(self.command_pool, &[self.command_buffer]);
+        }
+    }
+}
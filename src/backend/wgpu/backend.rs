@@ -0,0 +1,274 @@
+use super::{WgpuCompute, WgpuCore, WgpuError};
+use crate::backend::feature::{DeviceFeatures, GPU_FEATURE_FP16, GPU_FEATURE_FP64};
+use crate::backend::{Backend, BackendCapabilities, Device, DeviceOp, DeviceType};
+use crate::MlResult;
+use std::fmt::Debug;
+use std::future::Future;
+use std::sync::{Arc, OnceLock};
+
+static SHARED: OnceLock<Option<Arc<WgpuBackend>>> = OnceLock::new();
+
+pub struct WgpuBackend {
+    core: WgpuCore,
+    compute: WgpuCompute,
+}
+
+impl Debug for WgpuBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "WgpuBackend({})", self.core.adapter_info.name)
+    }
+}
+
+impl WgpuBackend {
+    /// Returns a process-wide backend instance so tensors don't each request their own device.
+    ///
+    /// On wasm32 the instance has to be created by [`WgpuBackend::init_shared`] first.
+    pub fn shared() -> MlResult<Arc<WgpuBackend>> {
+        #[cfg(not(target_arch = "wasm32"))]
+        let shared = SHARED.get_or_init(|| WgpuBackend::new().ok().map(Arc::new));
+        #[cfg(target_arch = "wasm32")]
+        let shared = SHARED.get().ok_or_else(|| {
+            WgpuError::Other("await WgpuBackend::init_shared before using wgpu on wasm32".into())
+        })?;
+
+        shared
+            .clone()
+            .ok_or_else(|| WgpuError::AdapterNotFound.into())
+    }
+
+    /// Requests an adapter and device without blocking, as the browser requires.
+    pub async fn new_async() -> MlResult<Self> {
+        let core = WgpuCore::new_async().await?;
+        let compute = WgpuCompute::new(&core)?;
+
+        Ok(Self { core, compute })
+    }
+
+    /// Creates the instance [`WgpuBackend::shared`] returns, if there isn't one yet. In the
+    /// browser, await this once before creating any wgpu tensors.
+    pub async fn init_shared() -> MlResult<Arc<WgpuBackend>> {
+        if let Some(shared) = SHARED.get() {
+            return shared
+                .clone()
+                .ok_or_else(|| WgpuError::AdapterNotFound.into());
+        }
+        let backend = Self::new_async().await.ok().map(Arc::new);
+        SHARED
+            .get_or_init(|| backend)
+            .clone()
+            .ok_or_else(|| WgpuError::AdapterNotFound.into())
+    }
+
+    /// Runs `op` on `inputs` and waits for the result without blocking, which is the only
+    /// way to read results back in the browser. The synchronous [`Backend`] methods fail
+    /// there instead.
+    pub async fn execute_async(&self, op: DeviceOp, inputs: &[&[f32]]) -> MlResult<Vec<f32>> {
+        if inputs.len() != op.arity() {
+            return Err(WgpuError::InvalidDimensions(format!(
+                "{:?} takes {} inputs, got {}",
+                op,
+                op.arity(),
+                inputs.len()
+            ))
+            .into());
+        }
+        let (core, compute) = (&self.core, &self.compute);
+        match op {
+            DeviceOp::Add => {
+                compute
+                    .execute_binary_op(core, inputs[0], inputs[1], 0)
+                    .await
+            }
+            DeviceOp::Mul => {
+                compute
+                    .execute_binary_op(core, inputs[0], inputs[1], 1)
+                    .await
+            }
+            DeviceOp::Div => {
+                compute
+                    .execute_binary_op(core, inputs[0], inputs[1], 2)
+                    .await
+            }
+            DeviceOp::Sub => {
+                compute
+                    .execute_binary_op(core, inputs[0], inputs[1], 3)
+                    .await
+            }
+            DeviceOp::Exp => compute.execute_unary_op(core, inputs[0], 0, 0.0).await,
+            DeviceOp::Log => compute.execute_unary_op(core, inputs[0], 1, 0.0).await,
+            DeviceOp::Pow(power) => compute.execute_unary_op(core, inputs[0], 2, power).await,
+            DeviceOp::Sqrt => compute.execute_unary_op(core, inputs[0], 3, 0.0).await,
+            DeviceOp::MatMul { m, n, k } => {
+                compute.matmul(core, inputs[0], inputs[1], m, n, k).await
+            }
+            DeviceOp::Sum => Ok(vec![compute.execute_reduction(core, inputs[0]).await?]),
+            _ => Err(WgpuError::Other(format!("{:?} is not supported by wgpu", op)).into()),
+        }
+    }
+
+    pub fn adapter_name(&self) -> &str {
+        &self.core.adapter_info.name
+    }
+}
+
+impl Device for WgpuBackend {
+    fn new() -> MlResult<Self> {
+        let core = WgpuCore::new()?;
+        let compute = WgpuCompute::new(&core)?;
+
+        Ok(Self { core, compute })
+    }
+
+    fn device_type(&self) -> DeviceType {
+        DeviceType::Wgpu
+    }
+
+    fn get_features(&self) -> DeviceFeatures {
+        let mut features = DeviceFeatures::new();
+
+        features.add_feature(
+            GPU_FEATURE_FP16,
+            self.core.features.contains(wgpu::Features::SHADER_F16),
+            Some("Half-precision floating point support".to_string()),
+        );
+
+        features.add_feature(
+            GPU_FEATURE_FP64,
+            self.core.features.contains(wgpu::Features::SHADER_F64),
+            Some("Double-precision floating point support".to_string()),
+        );
+
+        features
+    }
+}
+
+impl Backend for WgpuBackend {
+    fn device(&self) -> DeviceType {
+        DeviceType::Wgpu
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        let limits = self.core.device.limits();
+
+        let mut capabilities = BackendCapabilities::new(self.core.adapter_info.name.clone());
+        // Kernels bind their inputs as storage buffers, which have their own, smaller limit
+        capabilities.max_buffer_size = Some(
+            limits
+                .max_buffer_size
+                .min(limits.max_storage_buffer_binding_size as u64),
+        );
+        capabilities.supports_f16 = self.core.features.contains(wgpu::Features::SHADER_F16);
+        capabilities
+    }
+
+    fn execute_compute(&self, _dimensions: [u32; 3]) -> MlResult<()> {
+        let _ = self.core.device.poll(wgpu::Maintain::Wait);
+        Ok(())
+    }
+
+    fn add(&self, a: &[f32], b: &[f32]) -> Vec<f32> {
+        wait(self.compute.execute_binary_op(&self.core, a, b, 0))
+            .unwrap_or_else(|_| vec![0.0; a.len()])
+    }
+
+    fn multiply(&self, a: &[f32], b: &[f32]) -> Vec<f32> {
+        wait(self.compute.execute_binary_op(&self.core, a, b, 1))
+            .unwrap_or_else(|_| vec![0.0; a.len()])
+    }
+
+    fn div(&self, a: &[f32], b: &[f32]) -> Vec<f32> {
+        wait(self.compute.execute_binary_op(&self.core, a, b, 2))
+            .unwrap_or_else(|_| vec![0.0; a.len()])
+    }
+
+    fn sub(&self, a: &[f32], b: &[f32]) -> Vec<f32> {
+        wait(self.compute.execute_binary_op(&self.core, a, b, 3))
+            .unwrap_or_else(|_| vec![0.0; a.len()])
+    }
+
+    fn matmul(&self, a: &[f32], b: &[f32], m: usize, n: usize, k: usize) -> Vec<f32> {
+        wait(self.compute.matmul(&self.core, a, b, m, n, k)).unwrap_or_else(|_| vec![0.0; m * k])
+    }
+
+    fn exp(&self, a: &[f32]) -> Vec<f32> {
+        wait(self.compute.execute_unary_op(&self.core, a, 0, 0.0))
+            .unwrap_or_else(|_| vec![0.0; a.len()])
+    }
+
+    fn log(&self, a: &[f32]) -> Vec<f32> {
+        wait(self.compute.execute_unary_op(&self.core, a, 1, 0.0))
+            .unwrap_or_else(|_| vec![0.0; a.len()])
+    }
+
+    fn pow(&self, a: &[f32], power: f32) -> Vec<f32> {
+        wait(self.compute.execute_unary_op(&self.core, a, 2, power))
+            .unwrap_or_else(|_| vec![0.0; a.len()])
+    }
+
+    fn sqrt(&self, a: &[f32]) -> Vec<f32> {
+        wait(self.compute.execute_unary_op(&self.core, a, 3, 0.0))
+            .unwrap_or_else(|_| vec![0.0; a.len()])
+    }
+
+    fn sum(&self, a: &[f32]) -> f32 {
+        wait(self.compute.execute_reduction(&self.core, a)).unwrap_or(0.0)
+    }
+
+    fn mean(&self, a: &[f32]) -> f32 {
+        if a.is_empty() {
+            return 0.0;
+        }
+        self.sum(a) / a.len() as f32
+    }
+}
+
+// Blocks on an op's readback, which only native targets can do
+#[cfg(not(target_arch = "wasm32"))]
+fn wait<T>(op: impl Future<Output = MlResult<T>>) -> MlResult<T> {
+    pollster::block_on(op)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn wait<T>(_op: impl Future<Output = MlResult<T>>) -> MlResult<T> {
+    Err(WgpuError::Other(
+        "wgpu results can't be waited for synchronously on wasm32; use \
+         WgpuBackend::execute_async"
+            .into(),
+    )
+    .into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_basic_operations() -> MlResult<()> {
+        let backend = WgpuBackend::new()?;
+        let a = vec![1.0f32, 2.0, 3.0];
+        let b = vec![4.0f32, 5.0, 6.0];
+
+        assert_eq!(backend.add(&a, &b), vec![5.0, 7.0, 9.0]);
+        assert_eq!(backend.sub(&a, &b), vec![-3.0, -3.0, -3.0]);
+        assert_eq!(backend.multiply(&a, &b), vec![4.0, 10.0, 18.0]);
+        assert_eq!(backend.div(&a, &b), vec![0.25, 0.4, 0.5]);
+        assert_eq!(backend.sum(&a), 6.0);
+        assert_eq!(backend.mean(&a), 2.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_wgpu_matmul() -> MlResult<()> {
+        let backend = WgpuBackend::new()?;
+
+        // 2x3 * 3x2 matrices
+        let a = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let b = vec![7.0, 8.0, 9.0, 10.0, 11.0, 12.0];
+
+        let result = backend.matmul(&a, &b, 2, 3, 2);
+        assert_eq!(result, vec![58.0, 64.0, 139.0, 154.0]);
+
+        Ok(())
+    }
+}
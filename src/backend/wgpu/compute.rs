@@ -0,0 +1,345 @@
+use super::{WgpuCore, WgpuError};
+use crate::MlResult;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use wgpu::util::DeviceExt;
+
+const WORKGROUP_SIZE: u32 = 256;
+const MAX_WORKGROUPS_PER_DIM: u32 = 65535;
+
+pub struct WgpuCompute {
+    binary_ops_pipeline: wgpu::ComputePipeline,
+    unary_ops_pipeline: wgpu::ComputePipeline,
+    matmul_pipeline: wgpu::ComputePipeline,
+    reduction_pipeline: wgpu::ComputePipeline,
+}
+
+fn as_bytes<T: Copy>(data: &[T]) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, std::mem::size_of_val(data)) }
+}
+
+// Resolves once a buffer mapping requested with `map_async` completes. Natively that happens
+// inside `Device::poll`; in the browser, only after control returns to the event loop.
+#[derive(Default)]
+struct Readback {
+    state: Arc<Mutex<ReadbackState>>,
+}
+
+#[derive(Default)]
+struct ReadbackState {
+    result: Option<Result<(), wgpu::BufferAsyncError>>,
+    waker: Option<Waker>,
+}
+
+impl Readback {
+    fn request(&self, slice: &wgpu::BufferSlice) {
+        let state = Arc::clone(&self.state);
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+            state.result = Some(result);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        });
+    }
+}
+
+impl Future for Readback {
+    type Output = Result<(), wgpu::BufferAsyncError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+// Splits a 1D dispatch over two dimensions so large inputs stay within the per-dimension limit
+fn dispatch_size(len: usize) -> (u32, u32) {
+    let groups = (len as u32).div_ceil(WORKGROUP_SIZE).max(1);
+    if groups <= MAX_WORKGROUPS_PER_DIM {
+        (groups, 1)
+    } else {
+        (
+            MAX_WORKGROUPS_PER_DIM,
+            groups.div_ceil(MAX_WORKGROUPS_PER_DIM),
+        )
+    }
+}
+
+impl WgpuCompute {
+    pub fn new(core: &WgpuCore) -> MlResult<Self> {
+        let binary_ops_pipeline = Self::create_compute_pipeline(
+            &core.device,
+            "binary_ops",
+            include_str!("../../../shaders/wgsl/binary_ops.wgsl"),
+        );
+        let unary_ops_pipeline = Self::create_compute_pipeline(
+            &core.device,
+            "unary_ops",
+            include_str!("../../../shaders/wgsl/unary_ops.wgsl"),
+        );
+        let matmul_pipeline = Self::create_compute_pipeline(
+            &core.device,
+            "matmul",
+            include_str!("../../../shaders/wgsl/matmul.wgsl"),
+        );
+        let reduction_pipeline = Self::create_compute_pipeline(
+            &core.device,
+            "reduction",
+            include_str!("../../../shaders/wgsl/reduction.wgsl"),
+        );
+
+        Ok(Self {
+            binary_ops_pipeline,
+            unary_ops_pipeline,
+            matmul_pipeline,
+            reduction_pipeline,
+        })
+    }
+
+    fn create_compute_pipeline(
+        device: &wgpu::Device,
+        label: &str,
+        source: &str,
+    ) -> wgpu::ComputePipeline {
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(label),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(label),
+            layout: None,
+            module: &module,
+            entry_point: "main",
+            compilation_options: Default::default(),
+            cache: None,
+        })
+    }
+
+    fn storage_buffer(core: &WgpuCore, data: &[f32]) -> wgpu::Buffer {
+        core.device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: as_bytes(data),
+                usage: wgpu::BufferUsages::STORAGE,
+            })
+    }
+
+    fn output_buffer(core: &WgpuCore, len: usize) -> wgpu::Buffer {
+        core.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (len * std::mem::size_of::<f32>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn uniform_buffer(core: &WgpuCore, params: &[u32; 4]) -> wgpu::Buffer {
+        core.device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: as_bytes(params),
+                usage: wgpu::BufferUsages::UNIFORM,
+            })
+    }
+
+    // Records a single dispatch, copies `output` into a staging buffer and waits for the readback
+    async fn run(
+        &self,
+        core: &WgpuCore,
+        pipeline: &wgpu::ComputePipeline,
+        buffers: &[&wgpu::Buffer],
+        workgroups: (u32, u32),
+        output: &wgpu::Buffer,
+        output_len: usize,
+    ) -> MlResult<Vec<f32>> {
+        let layout = pipeline.get_bind_group_layout(0);
+        let entries: Vec<wgpu::BindGroupEntry> = buffers
+            .iter()
+            .enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect();
+
+        let bind_group = core.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &layout,
+            entries: &entries,
+        });
+
+        let size = (output_len * std::mem::size_of::<f32>()) as u64;
+        let staging = core.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = core
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: None,
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(workgroups.0, workgroups.1, 1);
+        }
+        encoder.copy_buffer_to_buffer(output, 0, &staging, 0, size);
+        core.queue.submit(Some(encoder.finish()));
+
+        let slice = staging.slice(..);
+        let readback = Readback::default();
+        readback.request(&slice);
+        // Runs the mapping callback natively; a no-op in the browser
+        let _ = core.device.poll(wgpu::Maintain::Wait);
+        readback.await.map_err(WgpuError::from)?;
+
+        let result = {
+            let mapped = slice.get_mapped_range();
+            mapped
+                .chunks_exact(4)
+                .map(|bytes| f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                .collect()
+        };
+        staging.unmap();
+
+        Ok(result)
+    }
+
+    pub async fn execute_binary_op(
+        &self,
+        core: &WgpuCore,
+        a: &[f32],
+        b: &[f32],
+        op_type: u32,
+    ) -> MlResult<Vec<f32>> {
+        if a.len() != b.len() {
+            return Err(WgpuError::InvalidDimensions(format!(
+                "operand lengths differ: {} vs {}",
+                a.len(),
+                b.len()
+            ))
+            .into());
+        }
+        if a.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let input_a = Self::storage_buffer(core, a);
+        let input_b = Self::storage_buffer(core, b);
+        let output = Self::output_buffer(core, a.len());
+        let params = Self::uniform_buffer(core, &[op_type, a.len() as u32, 0, 0]);
+
+        self.run(
+            core,
+            &self.binary_ops_pipeline,
+            &[&input_a, &input_b, &output, &params],
+            dispatch_size(a.len()),
+            &output,
+            a.len(),
+        )
+        .await
+    }
+
+    pub async fn execute_unary_op(
+        &self,
+        core: &WgpuCore,
+        a: &[f32],
+        op_type: u32,
+        scalar: f32,
+    ) -> MlResult<Vec<f32>> {
+        if a.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let input = Self::storage_buffer(core, a);
+        let output = Self::output_buffer(core, a.len());
+        let params = Self::uniform_buffer(core, &[op_type, a.len() as u32, scalar.to_bits(), 0]);
+
+        self.run(
+            core,
+            &self.unary_ops_pipeline,
+            &[&input, &output, &params],
+            dispatch_size(a.len()),
+            &output,
+            a.len(),
+        )
+        .await
+    }
+
+    pub async fn matmul(
+        &self,
+        core: &WgpuCore,
+        a: &[f32],
+        b: &[f32],
+        m: usize,
+        n: usize,
+        k: usize,
+    ) -> MlResult<Vec<f32>> {
+        if a.len() != m * n || b.len() != n * k {
+            return Err(WgpuError::InvalidDimensions(format!(
+                "matmul expects {}x{} and {}x{} operands",
+                m, n, n, k
+            ))
+            .into());
+        }
+        if m == 0 || n == 0 || k == 0 {
+            return Ok(vec![0.0; m * k]);
+        }
+
+        let input_a = Self::storage_buffer(core, a);
+        let input_b = Self::storage_buffer(core, b);
+        let output = Self::output_buffer(core, m * k);
+        let dims = Self::uniform_buffer(core, &[m as u32, n as u32, k as u32, 0]);
+
+        self.run(
+            core,
+            &self.matmul_pipeline,
+            &[&input_a, &input_b, &output, &dims],
+            ((k as u32).div_ceil(16), (m as u32).div_ceil(16)),
+            &output,
+            m * k,
+        )
+        .await
+    }
+
+    pub async fn execute_reduction(&self, core: &WgpuCore, input: &[f32]) -> MlResult<f32> {
+        if input.is_empty() {
+            return Ok(0.0);
+        }
+
+        let workgroups = dispatch_size(input.len());
+        let num_partials = (workgroups.0 * workgroups.1) as usize;
+
+        let input_buffer = Self::storage_buffer(core, input);
+        let output = Self::output_buffer(core, num_partials);
+        let params = Self::uniform_buffer(core, &[0, input.len() as u32, 0, 0]);
+
+        let partials = self
+            .run(
+                core,
+                &self.reduction_pipeline,
+                &[&input_buffer, &output, &params],
+                workgroups,
+                &output,
+                num_partials,
+            )
+            .await?;
+
+        Ok(partials.iter().sum())
+    }
+}
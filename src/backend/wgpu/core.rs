@@ -0,0 +1,57 @@
+use super::WgpuError;
+
+pub struct WgpuCore {
+    pub adapter_info: wgpu::AdapterInfo,
+    pub features: wgpu::Features,
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+}
+
+impl WgpuCore {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new() -> Result<Self, WgpuError> {
+        pollster::block_on(Self::new_async())
+    }
+
+    // The browser hands out adapters and devices asynchronously, and nothing may block on them
+    #[cfg(target_arch = "wasm32")]
+    pub fn new() -> Result<Self, WgpuError> {
+        Err(WgpuError::Other(
+            "wgpu devices can't be requested synchronously on wasm32; await \
+             WgpuBackend::init_shared instead"
+                .into(),
+        ))
+    }
+
+    pub async fn new_async() -> Result<Self, WgpuError> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                compatible_surface: None,
+                force_fallback_adapter: false,
+            })
+            .await
+            .ok_or(WgpuError::AdapterNotFound)?;
+
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: Some("Cetana ML"),
+                    required_features: wgpu::Features::empty(),
+                    required_limits: adapter.limits(),
+                    ..Default::default()
+                },
+                None,
+            )
+            .await?;
+
+        Ok(Self {
+            adapter_info: adapter.get_info(),
+            features: adapter.features(),
+            device,
+            queue,
+        })
+    }
+}
@@ -0,0 +1,50 @@
+use std::error::Error;
+
+mod backend;
+mod compute;
+mod core;
+
+pub use backend::WgpuBackend;
+pub use compute::WgpuCompute;
+pub use core::WgpuCore;
+
+#[derive(Debug)]
+pub enum WgpuError {
+    AdapterNotFound,
+    DeviceRequestFailed(String),
+    BufferMapFailed(String),
+    InvalidDimensions(String),
+    Other(String),
+}
+
+impl std::fmt::Display for WgpuError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WgpuError::AdapterNotFound => write!(f, "No suitable wgpu adapter found"),
+            WgpuError::DeviceRequestFailed(s) => write!(f, "Device request failed: {}", s),
+            WgpuError::BufferMapFailed(s) => write!(f, "Buffer map failed: {}", s),
+            WgpuError::InvalidDimensions(s) => write!(f, "Invalid dimensions: {}", s),
+            WgpuError::Other(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+impl Error for WgpuError {}
+
+impl From<wgpu::RequestDeviceError> for WgpuError {
+    fn from(err: wgpu::RequestDeviceError) -> Self {
+        WgpuError::DeviceRequestFailed(err.to_string())
+    }
+}
+
+impl From<wgpu::BufferAsyncError> for WgpuError {
+    fn from(err: wgpu::BufferAsyncError) -> Self {
+        WgpuError::BufferMapFailed(err.to_string())
+    }
+}
+
+impl From<WgpuError> for crate::MlError {
+    fn from(err: WgpuError) -> Self {
+        crate::MlError::BackendError(crate::backend::BackendError::WgpuError(err))
+    }
+}
@@ -0,0 +1,284 @@
+//! Per-op timings for each backend, to check whether a GPU backend actually beats the CPU
+//! on this machine before committing to it.
+//!
+//! ```ignore
+//! let report = cetana::bench::run_available(&BenchConfig::default())?;
+//! println!("{}", report);
+//! ```
+//!
+//! Ops are timed through the slice-based `Backend` methods, the path tensors take when their
+//! data is on the host, so GPU timings include copying inputs to the device and results back.
+
+use crate::backend::{Backend, DeviceManager, DeviceType};
+use crate::tensor::backend_for;
+use crate::MlResult;
+use std::fmt::{Display, Formatter};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+/// A backend op that can be timed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BenchOp {
+    Add,
+    Sub,
+    Multiply,
+    Div,
+    MatMul,
+    Exp,
+    Log,
+    Pow,
+    Sqrt,
+    Sum,
+    Mean,
+}
+
+impl BenchOp {
+    pub const ALL: [BenchOp; 11] = [
+        BenchOp::Add,
+        BenchOp::Sub,
+        BenchOp::Multiply,
+        BenchOp::Div,
+        BenchOp::MatMul,
+        BenchOp::Exp,
+        BenchOp::Log,
+        BenchOp::Pow,
+        BenchOp::Sqrt,
+        BenchOp::Sum,
+        BenchOp::Mean,
+    ];
+
+    // Matmul sizes are the side of a square matrix; everything else takes element counts
+    fn input_len(self, size: usize) -> usize {
+        match self {
+            BenchOp::MatMul => size * size,
+            _ => size,
+        }
+    }
+
+    fn run(self, backend: &dyn Backend, a: &[f32], b: &[f32], size: usize) {
+        match self {
+            BenchOp::Add => drop(black_box(backend.add(a, b))),
+            BenchOp::Sub => drop(black_box(backend.sub(a, b))),
+            BenchOp::Multiply => drop(black_box(backend.multiply(a, b))),
+            BenchOp::Div => drop(black_box(backend.div(a, b))),
+            BenchOp::MatMul => drop(black_box(backend.matmul(a, b, size, size, size))),
+            BenchOp::Exp => drop(black_box(backend.exp(a))),
+            BenchOp::Log => drop(black_box(backend.log(a))),
+            BenchOp::Pow => drop(black_box(backend.pow(a, 2.0))),
+            BenchOp::Sqrt => drop(black_box(backend.sqrt(a))),
+            BenchOp::Sum => drop(black_box(backend.sum(a))),
+            BenchOp::Mean => drop(black_box(backend.mean(a))),
+        }
+    }
+}
+
+impl Display for BenchOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            BenchOp::Add => "add",
+            BenchOp::Sub => "sub",
+            BenchOp::Multiply => "multiply",
+            BenchOp::Div => "div",
+            BenchOp::MatMul => "matmul",
+            BenchOp::Exp => "exp",
+            BenchOp::Log => "log",
+            BenchOp::Pow => "pow",
+            BenchOp::Sqrt => "sqrt",
+            BenchOp::Sum => "sum",
+            BenchOp::Mean => "mean",
+        };
+        f.pad(name)
+    }
+}
+
+/// What to time and how often.
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    pub ops: Vec<BenchOp>,
+    /// Element counts for elementwise ops and reductions.
+    pub sizes: Vec<usize>,
+    /// Matrix sides for matmul, which multiplies two square matrices.
+    pub matmul_sizes: Vec<usize>,
+    /// Untimed runs before measuring, to let lazy initialization and caches settle.
+    pub warmup: usize,
+    pub iterations: usize,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            ops: BenchOp::ALL.to_vec(),
+            sizes: vec![1 << 10, 1 << 16, 1 << 20],
+            matmul_sizes: vec![64, 256, 512],
+            warmup: 2,
+            iterations: 10,
+        }
+    }
+}
+
+/// Timings for one op at one size on one device.
+#[derive(Debug, Clone)]
+pub struct BenchResult {
+    pub op: BenchOp,
+    pub size: usize,
+    pub device: DeviceType,
+    pub mean: Duration,
+    pub min: Duration,
+}
+
+/// Every timing from a run. Its `Display` prints one row per op and size with a column per
+/// device, along with each device's speedup over the first one.
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub devices: Vec<DeviceType>,
+    pub results: Vec<BenchResult>,
+}
+
+impl BenchReport {
+    pub fn get(&self, op: BenchOp, size: usize, device: DeviceType) -> Option<&BenchResult> {
+        self.results
+            .iter()
+            .find(|r| r.op == op && r.size == size && r.device == device)
+    }
+
+    /// The device with the lowest mean time for `op` at `size`.
+    pub fn fastest(&self, op: BenchOp, size: usize) -> Option<DeviceType> {
+        self.results
+            .iter()
+            .filter(|r| r.op == op && r.size == size)
+            .min_by_key(|r| r.mean)
+            .map(|r| r.device)
+    }
+}
+
+impl Display for BenchReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:<10}{:>10}", "op", "size")?;
+        for device in &self.devices {
+            write!(f, "{:>24}", device.to_string())?;
+        }
+        writeln!(f)?;
+
+        let mut rows: Vec<(BenchOp, usize)> = Vec::new();
+        for result in &self.results {
+            if !rows.contains(&(result.op, result.size)) {
+                rows.push((result.op, result.size));
+            }
+        }
+
+        let baseline = self.devices.first().copied();
+        for (op, size) in rows {
+            write!(f, "{:<10}{:>10}", op, size)?;
+            let base = baseline.and_then(|device| self.get(op, size, device));
+            for device in &self.devices {
+                let cell = match (self.get(op, size, *device), base) {
+                    (Some(result), Some(base)) if result.device != base.device => format!(
+                        "{:.1?} ({:.2}x)",
+                        result.mean,
+                        base.mean.as_secs_f64() / result.mean.as_secs_f64().max(1e-12)
+                    ),
+                    (Some(result), _) => format!("{:.1?}", result.mean),
+                    (None, _) => "-".to_string(),
+                };
+                write!(f, "{:>24}", cell)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Times every op in `config` on each of `devices`.
+pub fn run(config: &BenchConfig, devices: &[DeviceType]) -> MlResult<BenchReport> {
+    let mut results = Vec::new();
+
+    for &device in devices {
+        let backend = backend_for(device)?;
+        for &op in &config.ops {
+            let sizes = match op {
+                BenchOp::MatMul => &config.matmul_sizes,
+                _ => &config.sizes,
+            };
+            for &size in sizes {
+                results.push(time_op(backend.as_ref(), op, size, config));
+            }
+        }
+    }
+
+    Ok(BenchReport {
+        devices: devices.to_vec(),
+        results,
+    })
+}
+
+/// Times every op in `config` on each device the `DeviceManager` found, in the order it
+/// lists them.
+pub fn run_available(config: &BenchConfig) -> MlResult<BenchReport> {
+    let mut devices: Vec<DeviceType> = DeviceManager::global()
+        .devices()
+        .iter()
+        .map(|info| info.device_type)
+        .collect();
+
+    // Compare against the CPU
+    if let Some(position) = devices.iter().position(|&d| d == DeviceType::Cpu) {
+        devices[..=position].rotate_right(1);
+    }
+    run(config, &devices)
+}
+
+fn time_op(backend: &dyn Backend, op: BenchOp, size: usize, config: &BenchConfig) -> BenchResult {
+    // Positive inputs keep log and sqrt finite
+    let len = op.input_len(size);
+    let a: Vec<f32> = (0..len).map(|i| (i % 97) as f32 / 97.0 + 0.5).collect();
+    let b: Vec<f32> = (0..len).map(|i| (i % 89) as f32 / 89.0 + 0.5).collect();
+
+    for _ in 0..config.warmup {
+        op.run(backend, &a, &b, size);
+    }
+
+    let mut total = Duration::ZERO;
+    let mut min = Duration::MAX;
+    for _ in 0..config.iterations.max(1) {
+        let start = Instant::now();
+        op.run(backend, &a, &b, size);
+        let elapsed = start.elapsed();
+        total += elapsed;
+        min = min.min(elapsed);
+    }
+
+    BenchResult {
+        op,
+        size,
+        device: backend.device(),
+        mean: total / config.iterations.max(1) as u32,
+        min,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bench_report() -> MlResult<()> {
+        let config = BenchConfig {
+            ops: vec![BenchOp::Add, BenchOp::MatMul],
+            sizes: vec![16, 64],
+            matmul_sizes: vec![8],
+            warmup: 0,
+            iterations: 2,
+        };
+        let report = run(&config, &[DeviceType::Cpu])?;
+
+        assert_eq!(report.results.len(), 3);
+        assert!(report.get(BenchOp::Add, 64, DeviceType::Cpu).is_some());
+        assert!(report.get(BenchOp::MatMul, 16, DeviceType::Cpu).is_none());
+        assert_eq!(report.fastest(BenchOp::MatMul, 8), Some(DeviceType::Cpu));
+
+        let table = report.to_string();
+        assert_eq!(table.lines().count(), 4);
+        assert!(table.lines().nth(3).unwrap().starts_with("matmul"));
+        Ok(())
+    }
+}
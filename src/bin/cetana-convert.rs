@@ -0,0 +1,128 @@
+//! Converts checkpoints between tensor formats and lists what they hold.
+//!
+//! ```text
+//! cetana-convert [--from FORMAT] [--to FORMAT] INPUT OUTPUT
+//! cetana-convert --list [--from FORMAT] INPUT
+//! ```
+
+use std::process::ExitCode;
+
+use cetana::serialize::TensorFormat;
+use cetana::MlResult;
+
+const USAGE: &str = "\
+Usage:
+  cetana-convert [--from FORMAT] [--to FORMAT] INPUT OUTPUT
+  cetana-convert --list [--from FORMAT] INPUT
+
+Converts INPUT to OUTPUT, or with --list prints the name and shape of each tensor in INPUT.
+Formats are picked from file extensions unless given with --from and --to.
+
+Formats: native (.spn), safetensors, npy, npz, onnx, pt (.pt/.pth, read only),
+gguf (read only)";
+
+struct Args {
+    list: bool,
+    from: Option<TensorFormat>,
+    to: Option<TensorFormat>,
+    paths: Vec<String>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> MlResult<Args> {
+    let mut parsed = Args {
+        list: false,
+        from: None,
+        to: None,
+        paths: Vec::new(),
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--list" | "-l" => parsed.list = true,
+            "--from" | "--to" => {
+                let format = args
+                    .next()
+                    .ok_or_else(|| format!("{} needs a format", arg))?
+                    .parse()?;
+                if arg == "--from" {
+                    parsed.from = Some(format);
+                } else {
+                    parsed.to = Some(format);
+                }
+            }
+            flag if flag.starts_with('-') && flag.len() > 1 => {
+                return Err(format!("Unknown option {}", flag).into())
+            }
+            _ => parsed.paths.push(arg),
+        }
+    }
+    Ok(parsed)
+}
+
+fn list(format: TensorFormat, path: &str) -> MlResult<()> {
+    let inventory = format.inventory(path)?;
+    let width = inventory
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    let mut total = 0;
+    for (name, shape) in &inventory {
+        let count: usize = shape.iter().product();
+        total += count;
+        println!(
+            "{:<width$}  {:<20}  {}",
+            name,
+            format!("{:?}", shape),
+            count
+        );
+    }
+    println!(
+        "{} tensors, {} parameters ({} format)",
+        inventory.len(),
+        total,
+        format
+    );
+    Ok(())
+}
+
+fn run() -> MlResult<()> {
+    let args = parse_args(std::env::args().skip(1))?;
+    match (args.list, &args.paths[..]) {
+        (true, [input]) => {
+            let from = args
+                .from
+                .map_or_else(|| TensorFormat::from_path(input), Ok)?;
+            list(from, input)
+        }
+        (false, [input, output]) => {
+            let from = args
+                .from
+                .map_or_else(|| TensorFormat::from_path(input), Ok)?;
+            let to = args
+                .to
+                .map_or_else(|| TensorFormat::from_path(output), Ok)?;
+            let state = from.load(input)?;
+            to.save(output, &state)?;
+            println!(
+                "Wrote {} tensors from {} ({}) to {} ({})",
+                state.len(),
+                input,
+                from,
+                output,
+                to
+            );
+            Ok(())
+        }
+        _ => Err(USAGE.into()),
+    }
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
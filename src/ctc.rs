@@ -0,0 +1,330 @@
+//! Decoding the outputs of models trained with connectionist temporal classification (CTC),
+//! as speech recognizers and OCR models are.
+//!
+//! Such a model scores every label and an extra blank for each frame of its input, giving
+//! `[batch, frames, classes]` scores. A label sequence is read off a path of one class per
+//! frame by merging repeated labels and then dropping the blanks, so `a a _ a b _` reads
+//! `a a b`. [`greedy_decode`] reads the single most likely path; [`beam_search`] keeps the
+//! most likely label prefixes, summing over every path that reads each one, and can weigh
+//! them with a language model through a [`PrefixScorer`].
+//!
+//! Scores may be logits or log-probabilities: each frame is normalized with a log-softmax
+//! first. Padded batches pass each sequence's number of frames as `lengths`.
+//!
+//! ```ignore
+//! let log_probs = model.forward(&spectrogram)?;
+//! let config = BeamSearchConfig { beam_width: 16, lm_weight: 0.5, ..Default::default() };
+//! let transcripts = beam_search(&log_probs, Some(&frames), &config, Some(&bigram))?;
+//! ```
+
+use std::collections::HashMap;
+
+use crate::tensor::{Tensor, TensorError};
+use crate::MlResult;
+
+/// A language model over label sequences, for [`beam_search`] to weigh its prefixes with.
+pub trait PrefixScorer {
+    /// The log-probability of `label` following `prefix`.
+    fn score(&self, prefix: &[usize], label: usize) -> f32;
+}
+
+impl<F: Fn(&[usize], usize) -> f32> PrefixScorer for F {
+    fn score(&self, prefix: &[usize], label: usize) -> f32 {
+        self(prefix, label)
+    }
+}
+
+/// Settings for [`beam_search`].
+#[derive(Debug, Clone)]
+pub struct BeamSearchConfig {
+    /// How many prefixes are kept after each frame.
+    pub beam_width: usize,
+    /// The class of the blank.
+    pub blank: usize,
+    /// Multiplies the language model's log-probability of each label added to a prefix.
+    pub lm_weight: f32,
+    /// Added for each label in a prefix, countering the language model's preference for
+    /// short ones.
+    pub insertion_bonus: f32,
+}
+
+impl Default for BeamSearchConfig {
+    fn default() -> Self {
+        Self {
+            beam_width: 8,
+            blank: 0,
+            lm_weight: 1.0,
+            insertion_bonus: 0.0,
+        }
+    }
+}
+
+/// The labels of each sequence's most likely path: the best class of every frame, with
+/// repeats merged and blanks dropped.
+pub fn greedy_decode(
+    scores: &Tensor,
+    lengths: Option<&[usize]>,
+    blank: usize,
+) -> MlResult<Vec<Vec<usize>>> {
+    let (frames, classes) = dims(scores, lengths, blank)?;
+    Ok(sequences(scores, frames, classes, lengths)
+        .map(|frames| {
+            let path = frames.chunks(classes).map(|frame| {
+                (frame.iter().enumerate())
+                    .max_by(|a, b| a.1.total_cmp(b.1))
+                    .map_or(blank, |(class, _)| class)
+            });
+            collapse(path, blank)
+        })
+        .collect())
+}
+
+/// The most likely label sequence of each sequence, by CTC prefix beam search.
+///
+/// Each prefix keeps the probability of the paths so far that read it and end in a blank,
+/// and of those that end in its last label, since only the latter merge with another copy
+/// of that label. With a `scorer`, a prefix is ranked by its log-probability plus
+/// `lm_weight` times the scorer's log-probability of each of its labels, and
+/// `insertion_bonus` per label.
+pub fn beam_search(
+    scores: &Tensor,
+    lengths: Option<&[usize]>,
+    config: &BeamSearchConfig,
+    scorer: Option<&dyn PrefixScorer>,
+) -> MlResult<Vec<Vec<usize>>> {
+    let (frames, classes) = dims(scores, lengths, config.blank)?;
+    if config.beam_width == 0 {
+        return Err("Beam search needs a beam width of at least 1".into());
+    }
+
+    let mut results = Vec::new();
+    for frames in sequences(scores, frames, classes, lengths) {
+        let mut beams = vec![(Vec::new(), Beam::start())];
+        for frame in frames.chunks(classes) {
+            let frame = log_softmax(frame);
+            let mut next: HashMap<Vec<usize>, Beam> = HashMap::new();
+            for (prefix, beam) in &beams {
+                let total = log_add(beam.blank, beam.label);
+                for (class, &p) in frame.iter().enumerate() {
+                    if class == config.blank {
+                        let entry = next.entry(prefix.clone()).or_insert(beam.emptied());
+                        entry.blank = log_add(entry.blank, total + p);
+                        continue;
+                    }
+
+                    // Repeating the last label extends the prefix only after a blank
+                    let repeat = prefix.last() == Some(&class);
+                    if repeat {
+                        let entry = next.entry(prefix.clone()).or_insert(beam.emptied());
+                        entry.label = log_add(entry.label, beam.label + p);
+                    }
+                    let mut extended = prefix.clone();
+                    extended.push(class);
+                    let entry = next.entry(extended).or_insert_with(|| {
+                        let lm = scorer.map_or(0.0, |scorer| scorer.score(prefix, class));
+                        Beam {
+                            lm: beam.lm + config.lm_weight * lm + config.insertion_bonus,
+                            ..Beam::empty()
+                        }
+                    });
+                    let paths = if repeat { beam.blank } else { total };
+                    entry.label = log_add(entry.label, paths + p);
+                }
+            }
+
+            beams = next.into_iter().collect();
+            // Ties go to the shorter prefix, then the smaller labels, for a stable result
+            beams.sort_by(|(a, x), (b, y)| {
+                (y.rank().total_cmp(&x.rank()))
+                    .then(a.len().cmp(&b.len()))
+                    .then(a.cmp(b))
+            });
+            beams.truncate(config.beam_width);
+        }
+        results.push(beams.swap_remove(0).0);
+    }
+    Ok(results)
+}
+
+// The log-probabilities of the paths reading a prefix, split by whether they end in a
+// blank, and the prefix's language model score
+#[derive(Clone, Copy)]
+struct Beam {
+    blank: f32,
+    label: f32,
+    lm: f32,
+}
+
+impl Beam {
+    // The empty prefix before the first frame
+    fn start() -> Self {
+        Self {
+            blank: 0.0,
+            ..Self::empty()
+        }
+    }
+
+    fn empty() -> Self {
+        Self {
+            blank: f32::NEG_INFINITY,
+            label: f32::NEG_INFINITY,
+            lm: 0.0,
+        }
+    }
+
+    // The same prefix with no paths yet
+    fn emptied(&self) -> Self {
+        Self {
+            lm: self.lm,
+            ..Self::empty()
+        }
+    }
+
+    fn rank(&self) -> f32 {
+        log_add(self.blank, self.label) + self.lm
+    }
+}
+
+// The number of frames and classes of `[batch, frames, classes]` scores, checked against
+// `lengths` and the blank
+fn dims(scores: &Tensor, lengths: Option<&[usize]>, blank: usize) -> MlResult<(usize, usize)> {
+    let [batch, frames, classes] = *scores.shape() else {
+        return Err(TensorError::InvalidOperation {
+            op: "ctc_decode",
+            reason: format!(
+                "expected [batch, frames, classes] scores, got {:?}",
+                scores.shape()
+            ),
+        }
+        .into());
+    };
+    if blank >= classes {
+        return Err(format!("Blank {} is not one of the {} classes", blank, classes).into());
+    }
+    if let Some(lengths) = lengths {
+        if lengths.len() != batch || lengths.iter().any(|&len| len > frames) {
+            return Err(format!(
+                "Lengths {:?} don't fit a batch of {} sequences of {} frames",
+                lengths, batch, frames
+            )
+            .into());
+        }
+    }
+    Ok((frames, classes))
+}
+
+// Each sequence's scores, up to its length
+fn sequences<'a>(
+    scores: &'a Tensor,
+    frames: usize,
+    classes: usize,
+    lengths: Option<&'a [usize]>,
+) -> impl Iterator<Item = &'a [f32]> {
+    let data = scores.data();
+    let batch = scores.shape()[0];
+    (0..batch).map(move |b| {
+        let len = lengths.map_or(frames, |lengths| lengths[b]);
+        &data[b * frames * classes..][..len * classes]
+    })
+}
+
+// The labels a path reads
+fn collapse(path: impl Iterator<Item = usize>, blank: usize) -> Vec<usize> {
+    let mut labels = Vec::new();
+    let mut previous = None;
+    for class in path {
+        if class != blank && previous != Some(class) {
+            labels.push(class);
+        }
+        previous = Some(class);
+    }
+    labels
+}
+
+fn log_softmax(frame: &[f32]) -> Vec<f32> {
+    let max = frame.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let log_sum = frame.iter().map(|s| (s - max).exp()).sum::<f32>().ln() + max;
+    frame.iter().map(|s| s - log_sum).collect()
+}
+
+// `ln(e^a + e^b)`
+fn log_add(a: f32, b: f32) -> f32 {
+    let (high, low) = if a > b { (a, b) } else { (b, a) };
+    if low == f32::NEG_INFINITY {
+        return high;
+    }
+    high + (low - high).exp().ln_1p()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_greedy_decode() -> MlResult<()> {
+        // Paths 1 1 _ 1 2 2 _ and, cut to four frames, 2 _ 2 2
+        fn path(classes: &[usize]) -> Vec<f32> {
+            (classes.iter())
+                .flat_map(|&c| (0..3).map(move |k| if k == c { 1.0 } else { 0.0 }))
+                .collect()
+        }
+        let data = [path(&[1, 1, 0, 1, 2, 2, 0]), path(&[2, 0, 2, 2, 1, 1, 1])].concat();
+        let scores = Tensor::from_vec(data, &[2, 7, 3])?;
+        assert_eq!(
+            greedy_decode(&scores, None, 0)?,
+            [vec![1, 1, 2], vec![2, 2, 1]]
+        );
+        assert_eq!(
+            greedy_decode(&scores, Some(&[7, 4]), 0)?,
+            [vec![1, 1, 2], vec![2, 2]]
+        );
+
+        assert!(greedy_decode(&scores, None, 3).is_err());
+        assert!(greedy_decode(&scores, Some(&[8, 1]), 0).is_err());
+        assert!(greedy_decode(&scores.reshape(&[14, 3])?, None, 0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_beam_search() -> MlResult<()> {
+        // Blank is likelier than `a` in each frame, but the paths reading `a` (a a, a _ and
+        // _ a) add up to 0.64 against 0.36 for reading nothing
+        let frame = [0.6f32.ln(), 0.4f32.ln()];
+        let scores = Tensor::from_vec(frame.repeat(4), &[2, 2, 2])?;
+        let config = BeamSearchConfig::default();
+        assert_eq!(
+            greedy_decode(&scores, None, 0)?,
+            [Vec::<usize>::new(), vec![]]
+        );
+        assert_eq!(
+            beam_search(&scores, None, &config, None)?,
+            [vec![1], vec![1]]
+        );
+
+        // A single frame can't gather enough paths
+        let decoded = beam_search(&scores, Some(&[2, 1]), &config, None)?;
+        assert_eq!(decoded, [vec![1], vec![]]);
+
+        // A language model that finds `a` unlikely outweighs the acoustic scores
+        let unlikely = |_: &[usize], _: usize| -5.0f32;
+        let decoded = beam_search(&scores, None, &config, Some(&unlikely))?;
+        assert_eq!(decoded, [Vec::<usize>::new(), vec![]]);
+        let bonus = BeamSearchConfig {
+            insertion_bonus: 5.0,
+            ..config.clone()
+        };
+        let decoded = beam_search(&scores, None, &bonus, Some(&unlikely))?;
+        assert_eq!(decoded, [vec![1], vec![1]]);
+
+        // Repeats need a blank between them: a _ a reads `a a`
+        let frames = [[0.1f32, 0.9], [0.9, 0.1], [0.1, 0.9]].concat();
+        let scores = Tensor::from_vec(frames.iter().map(|p| p.ln()).collect(), &[1, 3, 2])?;
+        assert_eq!(beam_search(&scores, None, &config, None)?, [vec![1, 1]]);
+        let narrow = BeamSearchConfig {
+            beam_width: 0,
+            ..config
+        };
+        assert!(beam_search(&scores, None, &narrow, None).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,539 @@
+//! Tabular datasets read from Arrow record batches, behind the `arrow` feature, and from
+//! Parquet files, behind the `parquet` feature.
+//!
+//! Columns are encoded like [`CsvDataset`](super::CsvDataset)'s: numeric columns of any
+//! width are read as f32 and optionally normalized, and string and dictionary columns are
+//! categorical.
+//!
+//! ```ignore
+//! let train = ArrowDataset::builder()
+//!     .features(["age", "income", "city"])
+//!     .target("churned")
+//!     .nulls(Nulls::Fill(0.0))
+//!     .normalize(Normalization::Standard)
+//!     .build_parquet("train.parquet")?;
+//!
+//! // Batches from elsewhere, encoded exactly like the training split
+//! let test = ArrowDataset::builder().like(&train).build(&batches)?;
+//! ```
+
+use std::collections::BTreeSet;
+#[cfg(feature = "parquet")]
+use std::path::Path;
+
+use arrow::array::{Array, AsArray};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Float32Type};
+use arrow::record_batch::RecordBatch;
+
+use super::tabular::{feature_names, statistics, Column, ColumnEncoding, Normalization};
+use super::{Dataset, HostTensor};
+use crate::tensor::Tensor;
+use crate::MlResult;
+
+/// What to do with null values in the selected columns.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Nulls {
+    /// Fail to build the dataset.
+    #[default]
+    Error,
+    /// Read a null in a numeric column as this value, before normalizing. A null in a
+    /// categorical feature has no category, so its one-hot vector is all zeros; in a
+    /// categorical target it is still an error.
+    Fill(f32),
+    /// Leave out every row with a null in any selected column.
+    DropRow,
+}
+
+/// Builds an [`ArrowDataset`]; see [`ArrowDataset::builder`].
+#[derive(Debug, Clone, Default)]
+pub struct ArrowBuilder {
+    features: Option<Vec<String>>,
+    targets: Vec<String>,
+    categorical: Vec<String>,
+    categories: Vec<(String, Vec<String>)>,
+    normalization: Normalization,
+    nulls: Nulls,
+    fitted: Option<(Vec<Column>, Vec<Column>)>,
+}
+
+// A selected column's values across every batch
+enum Values {
+    Numbers(Vec<Option<f32>>),
+    Strings(Vec<Option<String>>),
+}
+
+impl Values {
+    fn is_null(&self, row: usize) -> bool {
+        match self {
+            Values::Numbers(values) => values[row].is_none(),
+            Values::Strings(values) => values[row].is_none(),
+        }
+    }
+}
+
+impl ArrowBuilder {
+    /// The columns that make up the features, in order. Defaults to every column that isn't
+    /// a target.
+    pub fn features<I, S>(mut self, columns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.features = Some(columns.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Adds a target column. Several targets are batched side by side.
+    pub fn target(mut self, column: impl Into<String>) -> Self {
+        self.targets.push(column.into());
+        self
+    }
+
+    /// Treats `column` as categorical even though it holds numbers, such as integer codes.
+    /// String and dictionary columns always are.
+    pub fn categorical(mut self, column: impl Into<String>) -> Self {
+        self.categorical.push(column.into());
+        self
+    }
+
+    /// Treats `column` as categorical with exactly these categories, in this order. Any
+    /// other value in the column is an error.
+    pub fn categories<I, S>(mut self, column: impl Into<String>, categories: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let categories = categories.into_iter().map(Into::into).collect();
+        self.categories.push((column.into(), categories));
+        self
+    }
+
+    pub fn normalize(mut self, normalization: Normalization) -> Self {
+        self.normalization = normalization;
+        self
+    }
+
+    /// How nulls in the selected columns are handled. Defaults to [`Nulls::Error`].
+    pub fn nulls(mut self, nulls: Nulls) -> Self {
+        self.nulls = nulls;
+        self
+    }
+
+    /// Uses `other`'s columns, categories and normalization statistics instead of working
+    /// them out from these batches, so a validation or test split is encoded like the
+    /// training split.
+    pub fn like(mut self, other: &ArrowDataset) -> Self {
+        self.fitted = Some((other.features.clone(), other.targets.clone()));
+        self
+    }
+
+    /// Reads the rows of `batches`, in order. Every batch must have the selected columns.
+    pub fn build(self, batches: &[RecordBatch]) -> MlResult<ArrowDataset> {
+        let feature_names = match (&self.fitted, &self.features) {
+            (Some((features, _)), _) => features.iter().map(|c| c.name.clone()).collect(),
+            (None, Some(names)) => names.clone(),
+            (None, None) => batches
+                .first()
+                .map(|batch| {
+                    batch
+                        .schema()
+                        .fields()
+                        .iter()
+                        .map(|field| field.name().clone())
+                        .filter(|name| !self.targets.contains(name))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        };
+        let target_names: Vec<String> = match &self.fitted {
+            Some((_, targets)) => targets.iter().map(|c| c.name.clone()).collect(),
+            None => self.targets.clone(),
+        };
+
+        let feature_values = feature_names
+            .iter()
+            .map(|name| self.read_column(batches, name))
+            .collect::<MlResult<Vec<_>>>()?;
+        let target_values = target_names
+            .iter()
+            .map(|name| self.read_column(batches, name))
+            .collect::<MlResult<Vec<_>>>()?;
+
+        let total: usize = batches.iter().map(RecordBatch::num_rows).sum();
+        let selected = feature_names
+            .iter()
+            .zip(&feature_values)
+            .chain(target_names.iter().zip(&target_values));
+        let mut kept = vec![true; total];
+        for (name, values) in selected {
+            for (row, keep) in kept.iter_mut().enumerate() {
+                if values.is_null(row) {
+                    match self.nulls {
+                        Nulls::Error => {
+                            return Err(format!("Row {}, column {} is null", row, name).into())
+                        }
+                        Nulls::DropRow => *keep = false,
+                        Nulls::Fill(_) => {}
+                    }
+                }
+            }
+        }
+        let rows: Vec<usize> = (0..total).filter(|&row| kept[row]).collect();
+
+        let (features, targets) = match self.fitted.clone() {
+            Some(fitted) => fitted,
+            None => (
+                self.fit(&feature_names, &feature_values, &rows, false)?,
+                self.fit(&target_names, &target_values, &rows, true)?,
+            ),
+        };
+
+        let feature_width = features.iter().map(|c| c.width(false)).sum();
+        let target_width = targets.len();
+        let mut feature_data = Vec::with_capacity(rows.len() * feature_width);
+        let mut target_data = Vec::with_capacity(rows.len() * target_width);
+        for &row in &rows {
+            for (columns, values, out, as_target) in [
+                (&features, &feature_values, &mut feature_data, false),
+                (&targets, &target_values, &mut target_data, true),
+            ] {
+                for (column, values) in columns.iter().zip(values) {
+                    self.encode(values, row, column, as_target, out)
+                        .map_err(|reason| {
+                            format!("Row {}, column {}: {}", row, column.name, reason)
+                        })?;
+                }
+            }
+        }
+
+        Ok(ArrowDataset {
+            rows: rows.len(),
+            feature_width,
+            target_width,
+            feature_data,
+            target_data,
+            features,
+            targets,
+        })
+    }
+
+    /// Reads every row group of the Parquet file at `path`.
+    #[cfg(feature = "parquet")]
+    pub fn build_parquet<P: AsRef<Path>>(self, path: P) -> MlResult<ArrowDataset> {
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file)
+            .and_then(|builder| builder.build())
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let batches = reader
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        self.build(&batches)
+    }
+
+    fn is_categorical(&self, name: &str, data_type: &DataType) -> bool {
+        let fitted = self.fitted.iter().flat_map(|(f, t)| f.iter().chain(t));
+        match fitted.into_iter().find(|column| column.name == name) {
+            Some(column) => matches!(column.encoding, ColumnEncoding::Categorical { .. }),
+            None => {
+                self.categorical.iter().any(|c| c == name)
+                    || self.categories.iter().any(|(c, _)| c == name)
+                    || matches!(
+                        data_type,
+                        DataType::Utf8 | DataType::LargeUtf8 | DataType::Dictionary(..)
+                    )
+            }
+        }
+    }
+
+    fn read_column(&self, batches: &[RecordBatch], name: &str) -> MlResult<Values> {
+        let mut numbers = Vec::new();
+        let mut strings = Vec::new();
+        let mut categorical = None;
+        for batch in batches {
+            let array = batch
+                .column_by_name(name)
+                .ok_or_else(|| format!("No column named {}", name))?;
+            let as_strings =
+                *categorical.get_or_insert_with(|| self.is_categorical(name, array.data_type()));
+            let to = if as_strings {
+                DataType::Utf8
+            } else {
+                DataType::Float32
+            };
+            let array = cast(array.as_ref(), &to)
+                .map_err(|e| format!("Failed to read column {}: {}", name, e))?;
+            if as_strings {
+                let array = array.as_string::<i32>();
+                strings.extend(
+                    (0..array.len())
+                        .map(|i| (!array.is_null(i)).then(|| array.value(i).trim().to_string())),
+                );
+            } else {
+                let array = array.as_primitive::<Float32Type>();
+                numbers
+                    .extend((0..array.len()).map(|i| (!array.is_null(i)).then(|| array.value(i))));
+            }
+        }
+        Ok(match categorical {
+            Some(true) => Values::Strings(strings),
+            _ => Values::Numbers(numbers),
+        })
+    }
+
+    // Works out each column's encoding from the values in the rows that are kept
+    fn fit(
+        &self,
+        names: &[String],
+        values: &[Values],
+        rows: &[usize],
+        as_target: bool,
+    ) -> MlResult<Vec<Column>> {
+        names
+            .iter()
+            .zip(values)
+            .map(|(name, values)| {
+                let encoding = match values {
+                    Values::Strings(strings) => {
+                        let categories = match self.categories.iter().find(|(c, _)| c == name) {
+                            Some((_, categories)) => categories.clone(),
+                            None => {
+                                let present: BTreeSet<&String> = rows
+                                    .iter()
+                                    .filter_map(|&row| strings[row].as_ref())
+                                    .collect();
+                                present.into_iter().cloned().collect()
+                            }
+                        };
+                        ColumnEncoding::Categorical { categories }
+                    }
+                    Values::Numbers(numbers) => {
+                        let (offset, scale) = if as_target {
+                            (0.0, 1.0)
+                        } else {
+                            let present: Vec<f32> =
+                                rows.iter().filter_map(|&row| numbers[row]).collect();
+                            statistics(&present, self.normalization)
+                        };
+                        ColumnEncoding::Numeric { offset, scale }
+                    }
+                };
+                Ok(Column {
+                    name: name.clone(),
+                    encoding,
+                })
+            })
+            .collect()
+    }
+
+    fn encode(
+        &self,
+        values: &Values,
+        row: usize,
+        column: &Column,
+        as_target: bool,
+        out: &mut Vec<f32>,
+    ) -> Result<(), String> {
+        let fill = match self.nulls {
+            Nulls::Fill(value) => Some(value),
+            _ => None,
+        };
+        match (&column.encoding, values) {
+            (ColumnEncoding::Numeric { offset, scale }, Values::Numbers(numbers)) => {
+                let value = numbers[row].or(fill).ok_or("null value")?;
+                out.push((value - offset) / scale);
+            }
+            (ColumnEncoding::Categorical { categories }, Values::Strings(strings)) => {
+                let index = match &strings[row] {
+                    Some(value) => Some(
+                        categories
+                            .iter()
+                            .position(|category| category == value)
+                            .ok_or_else(|| format!("unknown category {:?}", value))?,
+                    ),
+                    None => None,
+                };
+                match (index, as_target) {
+                    (Some(index), true) => out.push(index as f32),
+                    (None, true) => return Err("null category in a target".into()),
+                    (index, false) => {
+                        out.extend((0..categories.len()).map(|i| (Some(i) == index) as u8 as f32))
+                    }
+                }
+            }
+            _ => return Err("column type doesn't match its encoding".into()),
+        }
+        Ok(())
+    }
+}
+
+/// The rows of Arrow record batches or a Parquet file as feature and target vectors.
+///
+/// Numeric columns are read as f32 and optionally normalized; categorical feature columns
+/// are one-hot encoded, and a categorical target becomes its category's index, as the
+/// classification losses expect. Every batch is encoded when the dataset is built.
+#[derive(Debug, Clone)]
+pub struct ArrowDataset {
+    rows: usize,
+    feature_width: usize,
+    target_width: usize,
+    feature_data: Vec<f32>,
+    target_data: Vec<f32>,
+    features: Vec<Column>,
+    targets: Vec<Column>,
+}
+
+impl ArrowDataset {
+    pub fn builder() -> ArrowBuilder {
+        ArrowBuilder::default()
+    }
+
+    /// Reads every column of `batches` as a feature.
+    pub fn from_batches(batches: &[RecordBatch]) -> MlResult<Self> {
+        Self::builder().build(batches)
+    }
+
+    /// Every row's features, as a `[rows, features]` tensor.
+    pub fn features(&self) -> MlResult<Tensor> {
+        Tensor::from_vec(self.feature_data.clone(), &[self.rows, self.feature_width])
+    }
+
+    /// Every row's targets, as a `[rows, targets]` tensor.
+    pub fn targets(&self) -> MlResult<Tensor> {
+        Tensor::from_vec(self.target_data.clone(), &[self.rows, self.target_width])
+    }
+
+    /// The name of each feature: the column name for numeric columns, and `column=category`
+    /// for each one-hot position of a categorical column.
+    pub fn feature_names(&self) -> Vec<String> {
+        feature_names(&self.features)
+    }
+
+    pub fn target_names(&self) -> Vec<&str> {
+        self.targets.iter().map(|c| c.name.as_str()).collect()
+    }
+
+    /// How column `name` was encoded, whether it is a feature or a target.
+    pub fn encoding(&self, name: &str) -> Option<&ColumnEncoding> {
+        self.features
+            .iter()
+            .chain(&self.targets)
+            .find(|column| column.name == name)
+            .map(|column| &column.encoding)
+    }
+}
+
+impl Dataset for ArrowDataset {
+    type Item = (HostTensor, HostTensor);
+
+    fn len(&self) -> usize {
+        self.rows
+    }
+
+    fn get(&self, index: usize) -> MlResult<Self::Item> {
+        if index >= self.rows {
+            return Err(format!("Row {} is out of range for {} rows", index, self.rows).into());
+        }
+        let (f, t) = (self.feature_width, self.target_width);
+        Ok((
+            HostTensor::new(self.feature_data[index * f..(index + 1) * f].to_vec(), &[f])?,
+            HostTensor::new(self.target_data[index * t..(index + 1) * t].to_vec(), &[t])?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{ArrayRef, DictionaryArray, Float64Array, Int64Array, StringArray};
+    use arrow::datatypes::{Field, Int8Type, Schema};
+    use std::sync::Arc;
+
+    fn batch(columns: Vec<(&str, ArrayRef)>) -> RecordBatch {
+        let schema = Schema::new(
+            columns
+                .iter()
+                .map(|(name, array)| Field::new(*name, array.data_type().clone(), true))
+                .collect::<Vec<_>>(),
+        );
+        RecordBatch::try_new(
+            Arc::new(schema),
+            columns.into_iter().map(|(_, a)| a).collect(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_arrow_dataset() -> MlResult<()> {
+        let first = batch(vec![
+            (
+                "age",
+                Arc::new(Int64Array::from(vec![Some(20), None])) as ArrayRef,
+            ),
+            (
+                "city",
+                Arc::new(StringArray::from(vec![Some("b"), Some("a")])),
+            ),
+            ("label", Arc::new(Float64Array::from(vec![1.0, 0.0]))),
+        ]);
+        let second = batch(vec![
+            ("age", Arc::new(Int64Array::from(vec![40])) as ArrayRef),
+            ("city", Arc::new(StringArray::from(vec![None::<&str>]))),
+            ("label", Arc::new(Float64Array::from(vec![1.0]))),
+        ]);
+        let batches = [first, second];
+
+        assert!(ArrowDataset::builder()
+            .target("label")
+            .build(&batches)
+            .is_err());
+
+        let filled = ArrowDataset::builder()
+            .target("label")
+            .nulls(Nulls::Fill(30.0))
+            .normalize(Normalization::MinMax)
+            .build(&batches)?;
+        assert_eq!(filled.len(), 3);
+        assert_eq!(filled.feature_names(), ["age", "city=a", "city=b"]);
+        assert_eq!(
+            filled.features()?.data(),
+            [0.0, 0.0, 1.0, 0.5, 1.0, 0.0, 1.0, 0.0, 0.0]
+        );
+        assert_eq!(filled.targets()?.data(), [1.0, 0.0, 1.0]);
+
+        let dropped = ArrowDataset::builder()
+            .features(["city"])
+            .target("label")
+            .nulls(Nulls::DropRow)
+            .build(&batches)?;
+        assert_eq!(dropped.len(), 2);
+        assert_eq!(dropped.features()?.data(), [0.0, 1.0, 1.0, 0.0]);
+
+        // Dictionary targets become class indices, with categories from the training split
+        let codes: DictionaryArray<Int8Type> = vec!["no", "yes", "no"].into_iter().collect();
+        let train = batch(vec![
+            (
+                "x",
+                Arc::new(Float64Array::from(vec![1.0, 2.0, 3.0])) as ArrayRef,
+            ),
+            ("y", Arc::new(codes)),
+        ]);
+        let train = ArrowDataset::builder()
+            .target("y")
+            .normalize(Normalization::Standard)
+            .build(&[train])?;
+        assert_eq!(train.targets()?.data(), [0.0, 1.0, 0.0]);
+        let test = batch(vec![
+            ("x", Arc::new(Float64Array::from(vec![2.0])) as ArrayRef),
+            ("y", Arc::new(StringArray::from(vec!["yes"]))),
+        ]);
+        let test = ArrowDataset::builder().like(&train).build(&[test])?;
+        assert_eq!(test.get(0)?.0.data(), [0.0]);
+        assert_eq!(test.get(0)?.1.data(), [1.0]);
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+//! Datasets built from other datasets; see [`Dataset::concat`], [`Dataset::zip`] and
+//! [`Dataset::map`].
+
+use super::Dataset;
+use crate::MlResult;
+
+/// The samples of one dataset followed by those of another.
+#[derive(Debug, Clone)]
+pub struct Concat<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A, B> Concat<A, B> {
+    pub(crate) fn new(first: A, second: B) -> Self {
+        Self { first, second }
+    }
+}
+
+impl<A, B> Dataset for Concat<A, B>
+where
+    A: Dataset,
+    B: Dataset<Item = A::Item>,
+{
+    type Item = A::Item;
+
+    fn len(&self) -> usize {
+        self.first.len() + self.second.len()
+    }
+
+    fn get(&self, index: usize) -> MlResult<Self::Item> {
+        match index.checked_sub(self.first.len()) {
+            None => self.first.get(index),
+            Some(index) => self.second.get(index),
+        }
+    }
+
+    fn set_epoch(&self, epoch: u64) {
+        self.first.set_epoch(epoch);
+        self.second.set_epoch(epoch);
+    }
+}
+
+/// Pairs of samples at the same index of two datasets, as long as the shorter one.
+#[derive(Debug, Clone)]
+pub struct Zip<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A, B> Zip<A, B> {
+    pub(crate) fn new(first: A, second: B) -> Self {
+        Self { first, second }
+    }
+}
+
+impl<A: Dataset, B: Dataset> Dataset for Zip<A, B> {
+    type Item = (A::Item, B::Item);
+
+    fn len(&self) -> usize {
+        self.first.len().min(self.second.len())
+    }
+
+    fn get(&self, index: usize) -> MlResult<Self::Item> {
+        Ok((self.first.get(index)?, self.second.get(index)?))
+    }
+
+    fn set_epoch(&self, epoch: u64) {
+        self.first.set_epoch(epoch);
+        self.second.set_epoch(epoch);
+    }
+}
+
+/// A dataset whose samples pass through a function as they are read.
+#[derive(Debug, Clone)]
+pub struct Mapped<D, F> {
+    dataset: D,
+    f: F,
+}
+
+impl<D, F> Mapped<D, F> {
+    pub(crate) fn new(dataset: D, f: F) -> Self {
+        Self { dataset, f }
+    }
+}
+
+impl<D, F, T> Dataset for Mapped<D, F>
+where
+    D: Dataset,
+    F: Fn(D::Item) -> MlResult<T>,
+{
+    type Item = T;
+
+    fn len(&self) -> usize {
+        self.dataset.len()
+    }
+
+    fn get(&self, index: usize) -> MlResult<T> {
+        (self.f)(self.dataset.get(index)?)
+    }
+
+    fn set_epoch(&self, epoch: u64) {
+        self.dataset.set_epoch(epoch);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{DataLoader, HostTensor, TensorDataset};
+
+    #[test]
+    fn test_dataset_combinators() -> MlResult<()> {
+        let inputs = HostTensor::new((0..6).map(|i| i as f32).collect(), &[3, 2])?;
+        let targets = HostTensor::new(vec![0.0, 1.0, 0.0], &[3])?;
+        let first = TensorDataset::from_host(inputs, targets)?;
+        assert_eq!(first.len(), 3);
+        let (x, y) = first.get(1)?;
+        assert_eq!((x.data(), x.shape()), (&[2.0, 3.0][..], &[2][..]));
+        assert_eq!((y.data(), y.shape()), (&[1.0][..], &[][..]));
+        assert!(first.get(3).is_err());
+
+        let second = TensorDataset::from_host(
+            HostTensor::new(vec![10.0, 11.0], &[1, 2])?,
+            HostTensor::new(vec![1.0], &[1])?,
+        )?;
+        let both = first.clone().concat(second);
+        assert_eq!(both.len(), 4);
+        assert_eq!(both.get(3)?.0.data(), &[10.0, 11.0]);
+
+        let doubled = first.clone().map(|(x, y): (HostTensor, HostTensor)| {
+            let shape = x.shape().to_vec();
+            let data = x.into_data().into_iter().map(|v| v * 2.0).collect();
+            Ok((HostTensor::new(data, &shape)?, y))
+        });
+        let paired = doubled.zip(both);
+        assert_eq!(paired.len(), 3);
+        let ((scaled, _), (original, _)) = paired.get(2)?;
+        assert_eq!(scaled.data(), &[8.0, 10.0]);
+        assert_eq!(original.data(), &[4.0, 5.0]);
+
+        let loader = DataLoader::new(first.map(|(x, _)| Ok(x)), 3);
+        let batch = loader.iter().next().unwrap()?;
+        assert_eq!(batch.shape(), &[3, 2]);
+        Ok(())
+    }
+}
@@ -0,0 +1,472 @@
+//! Tabular datasets read from CSV and TSV files.
+//!
+//! ```ignore
+//! let train = CsvDataset::builder("train.csv")
+//!     .features(["age", "income", "city"])
+//!     .categorical("city")
+//!     .target("churned")
+//!     .normalize(Normalization::Standard)
+//!     .build()?;
+//!
+//! // The test split is encoded and scaled exactly like the training split
+//! let test = CsvDataset::builder("test.csv").like(&train).build()?;
+//! ```
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+pub use super::tabular::{ColumnEncoding, Normalization};
+
+use super::tabular::{statistics, Column};
+use super::{Dataset, HostTensor};
+use crate::tensor::Tensor;
+use crate::MlResult;
+
+/// Builds a [`CsvDataset`]; see [`CsvDataset::builder`].
+#[derive(Debug, Clone)]
+pub struct CsvBuilder {
+    path: PathBuf,
+    delimiter: Option<u8>,
+    has_header: bool,
+    features: Option<Vec<String>>,
+    targets: Vec<String>,
+    categorical: Vec<String>,
+    categories: Vec<(String, Vec<String>)>,
+    normalization: Normalization,
+    fitted: Option<(Vec<Column>, Vec<Column>)>,
+}
+
+impl CsvBuilder {
+    /// The field separator. Defaults to a tab for `.tsv` files and a comma otherwise.
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = Some(delimiter);
+        self
+    }
+
+    /// Whether the first row names the columns, as it does by default. Without a header,
+    /// columns are named by their position: `"0"`, `"1"` and so on.
+    pub fn has_header(mut self, has_header: bool) -> Self {
+        self.has_header = has_header;
+        self
+    }
+
+    /// The columns that make up the features, in order. Defaults to every column that isn't
+    /// a target.
+    pub fn features<I, S>(mut self, columns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.features = Some(columns.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Adds a target column. Several targets are batched side by side.
+    pub fn target(mut self, column: impl Into<String>) -> Self {
+        self.targets.push(column.into());
+        self
+    }
+
+    /// Treats `column` as categorical: its distinct values, sorted, are the categories.
+    pub fn categorical(mut self, column: impl Into<String>) -> Self {
+        self.categorical.push(column.into());
+        self
+    }
+
+    /// Treats `column` as categorical with exactly these categories, in this order. Any
+    /// other value in the column is an error.
+    pub fn categories<I, S>(mut self, column: impl Into<String>, categories: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let categories = categories.into_iter().map(Into::into).collect();
+        self.categories.push((column.into(), categories));
+        self
+    }
+
+    pub fn normalize(mut self, normalization: Normalization) -> Self {
+        self.normalization = normalization;
+        self
+    }
+
+    /// Uses `other`'s columns, categories and normalization statistics instead of working
+    /// them out from this file, so a validation or test split is encoded like the training
+    /// split.
+    pub fn like(mut self, other: &CsvDataset) -> Self {
+        self.fitted = Some((other.features.clone(), other.targets.clone()));
+        self
+    }
+
+    pub fn build(self) -> MlResult<CsvDataset> {
+        let text = std::fs::read_to_string(&self.path)
+            .map_err(|e| format!("Failed to read {}: {}", self.path.display(), e))?;
+        let delimiter = self.delimiter.unwrap_or_else(|| {
+            let tsv = self.path.extension().is_some_and(|e| e == "tsv");
+            if tsv {
+                b'\t'
+            } else {
+                b','
+            }
+        });
+
+        let mut records = parse_records(&text, delimiter as char)?;
+        let header = if self.has_header {
+            if records.is_empty() {
+                return Err(format!("{} has no header row", self.path.display()).into());
+            }
+            records.remove(0)
+        } else {
+            let width = records.first().map_or(0, Vec::len);
+            (0..width).map(|i| i.to_string()).collect()
+        };
+        if let Some((row, record)) = records
+            .iter()
+            .enumerate()
+            .find(|(_, record)| record.len() != header.len())
+        {
+            return Err(format!(
+                "Row {} of {} has {} fields, expected {}",
+                row + 1 + self.has_header as usize,
+                self.path.display(),
+                record.len(),
+                header.len()
+            )
+            .into());
+        }
+
+        let (features, targets) = match self.fitted.clone() {
+            Some(fitted) => fitted,
+            None => self.fit(&header, &records)?,
+        };
+
+        let feature_width = features.iter().map(|c| c.width(false)).sum();
+        let target_width = targets.len();
+        let mut feature_data = Vec::with_capacity(records.len() * feature_width);
+        let mut target_data = Vec::with_capacity(records.len() * target_width);
+        for (row, record) in records.iter().enumerate() {
+            for (columns, out, as_target) in [
+                (&features, &mut feature_data, false),
+                (&targets, &mut target_data, true),
+            ] {
+                for column in columns {
+                    let index = column_index(&header, &column.name)?;
+                    encode(&record[index], column, as_target, out).map_err(|reason| {
+                        format!(
+                            "Row {}, column {} of {}: {}",
+                            row + 1 + self.has_header as usize,
+                            column.name,
+                            self.path.display(),
+                            reason
+                        )
+                    })?;
+                }
+            }
+        }
+
+        Ok(CsvDataset {
+            rows: records.len(),
+            feature_width,
+            target_width,
+            feature_data,
+            target_data,
+            features,
+            targets,
+        })
+    }
+
+    // Works out each selected column's encoding from the file's contents
+    fn fit(
+        &self,
+        header: &[String],
+        records: &[Vec<String>],
+    ) -> MlResult<(Vec<Column>, Vec<Column>)> {
+        let feature_names = match &self.features {
+            Some(names) => names.clone(),
+            None => header
+                .iter()
+                .filter(|name| !self.targets.contains(name))
+                .cloned()
+                .collect(),
+        };
+
+        let fit_column = |name: &String, as_target: bool| -> MlResult<Column> {
+            let index = column_index(header, name)?;
+            let values = records.iter().map(|record| record[index].trim());
+
+            if let Some((_, categories)) = self.categories.iter().find(|(c, _)| c == name) {
+                let encoding = ColumnEncoding::Categorical {
+                    categories: categories.clone(),
+                };
+                return Ok(Column {
+                    name: name.clone(),
+                    encoding,
+                });
+            }
+            if self.categorical.contains(name) {
+                let categories: BTreeSet<_> = values.map(str::to_string).collect();
+                let encoding = ColumnEncoding::Categorical {
+                    categories: categories.into_iter().collect(),
+                };
+                return Ok(Column {
+                    name: name.clone(),
+                    encoding,
+                });
+            }
+
+            let values = values
+                .enumerate()
+                .map(|(row, value)| {
+                    parse_number(value).map_err(|reason| {
+                        format!("Row {}, column {}: {}", row + 1, name, reason).into()
+                    })
+                })
+                .collect::<MlResult<Vec<f32>>>()?;
+            let (offset, scale) = if as_target {
+                (0.0, 1.0)
+            } else {
+                statistics(&values, self.normalization)
+            };
+            Ok(Column {
+                name: name.clone(),
+                encoding: ColumnEncoding::Numeric { offset, scale },
+            })
+        };
+
+        let features = feature_names
+            .iter()
+            .map(|name| fit_column(name, false))
+            .collect::<MlResult<Vec<_>>>()?;
+        let targets = self
+            .targets
+            .iter()
+            .map(|name| fit_column(name, true))
+            .collect::<MlResult<Vec<_>>>()?;
+        Ok((features, targets))
+    }
+}
+
+/// The rows of a CSV or TSV file as feature and target vectors.
+///
+/// Numeric columns are parsed and optionally normalized; categorical feature columns are
+/// one-hot encoded, and a categorical target becomes its category's index, as the
+/// classification losses expect. The whole file is encoded when the dataset is built.
+#[derive(Debug, Clone)]
+pub struct CsvDataset {
+    rows: usize,
+    feature_width: usize,
+    target_width: usize,
+    feature_data: Vec<f32>,
+    target_data: Vec<f32>,
+    features: Vec<Column>,
+    targets: Vec<Column>,
+}
+
+impl CsvDataset {
+    pub fn builder<P: AsRef<Path>>(path: P) -> CsvBuilder {
+        CsvBuilder {
+            path: path.as_ref().to_path_buf(),
+            delimiter: None,
+            has_header: true,
+            features: None,
+            targets: Vec::new(),
+            categorical: Vec::new(),
+            categories: Vec::new(),
+            normalization: Normalization::None,
+            fitted: None,
+        }
+    }
+
+    /// Reads every column of a file with a header row as a numeric feature.
+    pub fn open<P: AsRef<Path>>(path: P) -> MlResult<Self> {
+        Self::builder(path).build()
+    }
+
+    /// Every row's features, as a `[rows, features]` tensor.
+    pub fn features(&self) -> MlResult<Tensor> {
+        Tensor::from_vec(self.feature_data.clone(), &[self.rows, self.feature_width])
+    }
+
+    /// Every row's targets, as a `[rows, targets]` tensor.
+    pub fn targets(&self) -> MlResult<Tensor> {
+        Tensor::from_vec(self.target_data.clone(), &[self.rows, self.target_width])
+    }
+
+    /// The name of each feature: the column name for numeric columns, and `column=category`
+    /// for each one-hot position of a categorical column.
+    pub fn feature_names(&self) -> Vec<String> {
+        super::tabular::feature_names(&self.features)
+    }
+
+    pub fn target_names(&self) -> Vec<&str> {
+        self.targets.iter().map(|c| c.name.as_str()).collect()
+    }
+
+    /// How column `name` was encoded, whether it is a feature or a target.
+    pub fn encoding(&self, name: &str) -> Option<&ColumnEncoding> {
+        self.features
+            .iter()
+            .chain(&self.targets)
+            .find(|column| column.name == name)
+            .map(|column| &column.encoding)
+    }
+}
+
+impl Dataset for CsvDataset {
+    type Item = (HostTensor, HostTensor);
+
+    fn len(&self) -> usize {
+        self.rows
+    }
+
+    fn get(&self, index: usize) -> MlResult<Self::Item> {
+        if index >= self.rows {
+            return Err(format!("Row {} is out of range for {} rows", index, self.rows).into());
+        }
+        let (f, t) = (self.feature_width, self.target_width);
+        Ok((
+            HostTensor::new(self.feature_data[index * f..(index + 1) * f].to_vec(), &[f])?,
+            HostTensor::new(self.target_data[index * t..(index + 1) * t].to_vec(), &[t])?,
+        ))
+    }
+}
+
+fn column_index(header: &[String], name: &str) -> MlResult<usize> {
+    header
+        .iter()
+        .position(|column| column == name)
+        .ok_or_else(|| format!("No column named {}", name).into())
+}
+
+fn parse_number(value: &str) -> Result<f32, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("{:?} is not a number", value))
+}
+
+fn encode(value: &str, column: &Column, as_target: bool, out: &mut Vec<f32>) -> Result<(), String> {
+    match &column.encoding {
+        ColumnEncoding::Numeric { offset, scale } => {
+            out.push((parse_number(value)? - offset) / scale);
+        }
+        ColumnEncoding::Categorical { categories } => {
+            let value = value.trim();
+            let index = categories
+                .iter()
+                .position(|category| category == value)
+                .ok_or_else(|| format!("unknown category {:?}", value))?;
+            if as_target {
+                out.push(index as f32);
+            } else {
+                out.extend((0..categories.len()).map(|i| (i == index) as u8 as f32));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Splits CSV text into records, honouring double-quoted fields, which may contain the
+// delimiter, newlines and doubled quotes. Blank lines are skipped.
+fn parse_records(text: &str, delimiter: char) -> MlResult<Vec<Vec<String>>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => quoted = false,
+                c => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() => quoted = true,
+            c if c == delimiter => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                if !record.is_empty() || !field.is_empty() {
+                    record.push(std::mem::take(&mut field));
+                    records.push(std::mem::take(&mut record));
+                }
+            }
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("Unterminated quoted field in CSV data".into());
+    }
+    if !record.is_empty() || !field.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_dataset() -> MlResult<()> {
+        let path = "test_dataset.csv";
+        std::fs::write(
+            path,
+            "height,weight,\"city, country\",label\n\
+             150,50,\"Seoul, KR\",small\r\n\
+             170,70,Paris,large\n\
+             \n\
+             190,90,\"Seoul, KR\",large\n",
+        )
+        .expect("Failed to write test file");
+
+        let train = CsvDataset::builder(path)
+            .categorical("city, country")
+            .categorical("label")
+            .target("label")
+            .normalize(Normalization::MinMax)
+            .build()?;
+        assert_eq!(train.len(), 3);
+        assert_eq!(
+            train.feature_names(),
+            [
+                "height",
+                "weight",
+                "city, country=Paris",
+                "city, country=Seoul, KR"
+            ]
+        );
+        let (features, target) = train.get(1)?;
+        assert_eq!(features.data(), &[0.5, 0.5, 1.0, 0.0]);
+        assert_eq!(target.data(), &[0.0]);
+        assert_eq!(train.targets()?.data(), &[1.0, 0.0, 0.0]);
+
+        // A second file reuses the first one's categories and scaling
+        std::fs::write(
+            path,
+            "height\tweight\tcity, country\tlabel\n210\t70\tParis\tsmall\n",
+        )
+        .expect("Failed to write test file");
+        let test = CsvDataset::builder(path)
+            .delimiter(b'\t')
+            .like(&train)
+            .build()?;
+        assert_eq!(test.features()?.data(), &[1.5, 0.5, 1.0, 0.0]);
+        assert_eq!(test.targets()?.data(), &[1.0]);
+
+        std::fs::write(path, "height,weight,city, country,label\n1,2,3,4\n")
+            .expect("Failed to write test file");
+        assert!(CsvDataset::builder(path).like(&train).build().is_err());
+        assert!(CsvDataset::builder(path).features(["age"]).build().is_err());
+
+        std::fs::remove_file(path).expect("Failed to remove test file");
+        Ok(())
+    }
+}
@@ -0,0 +1,233 @@
+//! Image classification datasets laid out as one directory per class.
+//!
+//! ```text
+//! root/cat/001.jpg
+//! root/cat/002.png
+//! root/dog/001.jpg
+//! ```
+//!
+//! Class names are the subdirectory names, sorted, and labels are their positions. Images are
+//! decoded with the `image` crate when a sample is read, so the work spreads across
+//! [`DataLoader`](super::DataLoader) workers.
+
+use std::path::{Path, PathBuf};
+
+use super::{Dataset, HostTensor};
+use crate::MlResult;
+
+/// The extensions of the files an [`ImageFolder`] picks up, compared case-insensitively.
+pub const IMAGE_EXTENSIONS: [&str; 8] = ["png", "jpg", "jpeg", "bmp", "gif", "tif", "tiff", "webp"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Resize {
+    Exact(usize, usize),
+    Shorter(usize),
+}
+
+/// A dataset of the images under a directory, labelled by the subdirectory they are in.
+///
+/// Each sample is a `[channels, height, width]` tensor scaled to `[0, 1]`, resized and then
+/// center cropped if asked to, and the class index.
+#[derive(Debug, Clone)]
+pub struct ImageFolder {
+    classes: Vec<String>,
+    samples: Vec<(PathBuf, usize)>,
+    resize: Option<Resize>,
+    crop: Option<(usize, usize)>,
+    grayscale: bool,
+}
+
+impl ImageFolder {
+    /// Indexes the images under `root`. Files directly under `root`, and files without an
+    /// image extension, are ignored.
+    pub fn new<P: AsRef<Path>>(root: P) -> MlResult<Self> {
+        let root = root.as_ref();
+        let mut classes: Vec<(String, PathBuf)> = read_dir(root)?
+            .into_iter()
+            .filter(|path| path.is_dir())
+            .filter_map(|path| {
+                let name = path.file_name()?.to_str()?.to_string();
+                Some((name, path))
+            })
+            .collect();
+        classes.sort();
+        if classes.is_empty() {
+            return Err(format!("No class directories in {}", root.display()).into());
+        }
+
+        let mut samples = Vec::new();
+        for (label, (_, dir)) in classes.iter().enumerate() {
+            let mut images: Vec<PathBuf> = read_dir(dir)?
+                .into_iter()
+                .filter(|path| path.is_file() && is_image(path))
+                .collect();
+            images.sort();
+            samples.extend(images.into_iter().map(|path| (path, label)));
+        }
+
+        Ok(Self {
+            classes: classes.into_iter().map(|(name, _)| name).collect(),
+            samples,
+            resize: None,
+            crop: None,
+            grayscale: false,
+        })
+    }
+
+    /// Resizes every image to exactly `height` by `width`.
+    pub fn resize(mut self, height: usize, width: usize) -> Self {
+        self.resize = Some(Resize::Exact(height, width));
+        self
+    }
+
+    /// Resizes every image so its shorter side is `size`, keeping its aspect ratio.
+    pub fn resize_shorter(mut self, size: usize) -> Self {
+        self.resize = Some(Resize::Shorter(size));
+        self
+    }
+
+    /// Crops the central `height` by `width` of every image, after any resize.
+    pub fn center_crop(mut self, height: usize, width: usize) -> Self {
+        self.crop = Some((height, width));
+        self
+    }
+
+    /// Decodes images to one channel instead of three.
+    pub fn grayscale(mut self, grayscale: bool) -> Self {
+        self.grayscale = grayscale;
+        self
+    }
+
+    /// The class names, by label.
+    pub fn classes(&self) -> &[String] {
+        &self.classes
+    }
+
+    /// The label of class `name`.
+    pub fn class_index(&self, name: &str) -> Option<usize> {
+        self.classes.iter().position(|class| class == name)
+    }
+
+    /// Every image's path and label, in the order the dataset indexes them.
+    pub fn samples(&self) -> &[(PathBuf, usize)] {
+        &self.samples
+    }
+
+    fn decode(&self, path: &Path) -> MlResult<HostTensor> {
+        let image =
+            image::open(path).map_err(|e| format!("Failed to decode {}: {}", path.display(), e))?;
+        let (channels, width, height, pixels) = if self.grayscale {
+            let image = image.into_luma8();
+            (1, image.width(), image.height(), image.into_raw())
+        } else {
+            let image = image.into_rgb8();
+            (3, image.width(), image.height(), image.into_raw())
+        };
+        let (height, width) = (height as usize, width as usize);
+
+        // Interleaved pixels to channel planes
+        let mut data = vec![0.0; pixels.len()];
+        for (i, &value) in pixels.iter().enumerate() {
+            let (pixel, channel) = (i / channels, i % channels);
+            data[channel * height * width + pixel] = value as f32 / 255.0;
+        }
+        HostTensor::new(data, &[channels, height, width])
+    }
+}
+
+impl Dataset for ImageFolder {
+    type Item = (HostTensor, usize);
+
+    fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    fn get(&self, index: usize) -> MlResult<Self::Item> {
+        let (path, label) = self.samples.get(index).ok_or_else(|| {
+            format!(
+                "Image {} is out of range for {} images",
+                index,
+                self.samples.len()
+            )
+        })?;
+
+        let mut image = self.decode(path)?;
+        let [_, height, width] = image.image_dims()?;
+        match self.resize {
+            Some(Resize::Exact(h, w)) => image = image.resize(h, w)?,
+            Some(Resize::Shorter(size)) => {
+                let shorter = height.min(width).max(1);
+                let h = (height * size).div_ceil(shorter).max(size);
+                let w = (width * size).div_ceil(shorter).max(size);
+                image = image.resize(h, w)?;
+            }
+            None => {}
+        }
+        if let Some((h, w)) = self.crop {
+            image = image.center_crop(h, w)?;
+        }
+        Ok((image, *label))
+    }
+}
+
+fn read_dir(dir: &Path) -> MlResult<Vec<PathBuf>> {
+    let entries =
+        std::fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+    entries
+        .map(|entry| {
+            entry
+                .map(|entry| entry.path())
+                .map_err(|e| format!("Failed to read {}: {}", dir.display(), e).into())
+        })
+        .collect()
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension().and_then(|e| e.to_str()).is_some_and(|e| {
+        IMAGE_EXTENSIONS
+            .iter()
+            .any(|ext| e.eq_ignore_ascii_case(ext))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_folder_layout() -> MlResult<()> {
+        let root = std::env::temp_dir().join("cetana_test_image_folder");
+        for file in [
+            "dog/b.PNG",
+            "dog/a.jpg",
+            "cat/x.png",
+            "cat/notes.txt",
+            "readme.md",
+        ] {
+            let path = root.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).expect("Failed to create test dir");
+            std::fs::write(&path, b"").expect("Failed to write test file");
+        }
+
+        let folder = ImageFolder::new(&root)?.resize(4, 4).center_crop(2, 2);
+        assert_eq!(folder.classes(), ["cat", "dog"]);
+        assert_eq!(folder.class_index("dog"), Some(1));
+        let labels: Vec<_> = folder
+            .samples()
+            .iter()
+            .map(|(path, label)| (path.file_name().unwrap().to_str().unwrap(), *label))
+            .collect();
+        assert_eq!(labels, [("x.png", 0), ("a.jpg", 1), ("b.PNG", 1)]);
+        // Empty files aren't images
+        assert!(folder.get(0).is_err());
+
+        // The resize and crop applied to decoded images
+        let image = HostTensor::new((0..16).map(|i| i as f32).collect(), &[1, 4, 4])?;
+        assert_eq!(image.resize(2, 2)?.data(), &[2.5, 4.5, 10.5, 12.5]);
+        assert_eq!(image.center_crop(2, 2)?.data(), &[5.0, 6.0, 9.0, 10.0]);
+        assert_eq!(image.resize(4, 4)?, image);
+
+        std::fs::remove_dir_all(&root).expect("Failed to remove test dir");
+        Ok(())
+    }
+}
@@ -0,0 +1,429 @@
+#[cfg(feature = "threads")]
+use std::collections::BTreeMap;
+#[cfg(feature = "threads")]
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "threads")]
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::Arc;
+#[cfg(feature = "threads")]
+use std::sync::Mutex;
+#[cfg(feature = "threads")]
+use std::thread::JoinHandle;
+
+use super::{Collate, Dataset, RandomSampler, Sampler, SequentialSampler};
+use crate::nn::random::SimpleRng;
+use crate::MlResult;
+
+type CollateFn<T, B> = dyn Fn(Vec<T>) -> MlResult<B> + Send + Sync;
+
+/// Reads a [`Dataset`] in batches.
+///
+/// Each call to [`DataLoader::iter`] is one epoch. With [`DataLoader::shuffle`] every epoch
+/// visits the samples in a new order, derived from the seed and the epoch number so a run
+/// can be repeated, and a [`DataLoader::sampler`] chooses the samples some other way. With [`DataLoader::num_workers`] above zero, batches are read and collated
+/// on that many threads, up to [`DataLoader::prefetch`] batches ahead of the training loop,
+/// and still come out in order.
+pub struct DataLoader<D: Dataset, B> {
+    dataset: Arc<D>,
+    collate: Arc<CollateFn<D::Item, B>>,
+    batch_size: usize,
+    sampler: Arc<dyn Sampler>,
+    drop_last: bool,
+    seed: u64,
+    epoch: AtomicU64,
+    // Only read when there are threads to hand work to
+    #[cfg_attr(not(feature = "threads"), allow(dead_code))]
+    num_workers: usize,
+    #[cfg_attr(not(feature = "threads"), allow(dead_code))]
+    prefetch: usize,
+}
+
+impl<D> DataLoader<D, <D::Item as Collate>::Batch>
+where
+    D: Dataset,
+    D::Item: Collate + 'static,
+{
+    /// Batches of `batch_size` samples, collated by the samples' [`Collate`] impl, read in
+    /// order on the calling thread.
+    pub fn new(dataset: D, batch_size: usize) -> Self {
+        Self::with_collate(dataset, batch_size, <D::Item as Collate>::collate)
+    }
+}
+
+impl<D: Dataset, B> DataLoader<D, B> {
+    /// Like [`DataLoader::new`], with batches put together by `collate`.
+    pub fn with_collate(
+        dataset: D,
+        batch_size: usize,
+        collate: impl Fn(Vec<D::Item>) -> MlResult<B> + Send + Sync + 'static,
+    ) -> Self {
+        let seed = crate::nn::random::next_seed();
+        Self {
+            dataset: Arc::new(dataset),
+            collate: Arc::new(collate),
+            batch_size: batch_size.max(1),
+            sampler: Arc::new(SequentialSampler),
+            drop_last: false,
+            seed,
+            epoch: AtomicU64::new(0),
+            num_workers: 0,
+            prefetch: 2,
+        }
+    }
+
+    /// Visits the samples in a new random order every epoch.
+    pub fn shuffle(mut self, shuffle: bool) -> Self {
+        self.sampler = if shuffle {
+            Arc::new(RandomSampler)
+        } else {
+            Arc::new(SequentialSampler)
+        };
+        self
+    }
+
+    /// Reads the samples `sampler` picks each epoch, in its order, instead of every sample
+    /// in index order.
+    pub fn sampler(mut self, sampler: impl Sampler + 'static) -> Self {
+        self.sampler = Arc::new(sampler);
+        self
+    }
+
+    /// Leaves out the last batch of an epoch when it would be smaller than the rest.
+    pub fn drop_last(mut self, drop_last: bool) -> Self {
+        self.drop_last = drop_last;
+        self
+    }
+
+    /// Seeds the shuffling or sampling, which otherwise draws its seed from the global
+    /// generator [`seed_all`](crate::seed_all) seeds.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Reads and collates batches on `workers` background threads; zero, the default, does
+    /// it on the calling thread as each batch is asked for. Without the `threads` feature
+    /// batches are always read on the calling thread.
+    pub fn num_workers(mut self, workers: usize) -> Self {
+        self.num_workers = workers;
+        self
+    }
+
+    /// How many batches workers may have ready or in progress ahead of the one being used,
+    /// per worker. Defaults to 2.
+    pub fn prefetch(mut self, batches: usize) -> Self {
+        self.prefetch = batches.max(1);
+        self
+    }
+
+    /// Sets the epoch the next [`DataLoader::iter`] reads, as when resuming a run; each call
+    /// to `iter` moves on to the following epoch.
+    pub fn set_epoch(&self, epoch: u64) {
+        self.epoch.store(epoch, Ordering::Relaxed);
+    }
+
+    pub fn dataset(&self) -> &D {
+        &self.dataset
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    /// The number of batches in an epoch.
+    pub fn len(&self) -> usize {
+        let samples = self.sampler.len(self.dataset.len());
+        if self.drop_last {
+            samples / self.batch_size
+        } else {
+            samples.div_ceil(self.batch_size)
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // The sample indices of the next epoch, in the order they are visited
+    fn epoch_order(&self) -> Vec<usize> {
+        let epoch = self.epoch.fetch_add(1, Ordering::Relaxed);
+        self.dataset.set_epoch(epoch);
+        let mut rng = SimpleRng::new(self.seed ^ epoch.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        let mut order = self.sampler.sample(self.dataset.len(), &mut rng);
+        order.truncate(self.len() * self.batch_size);
+        order
+    }
+}
+
+impl<D, B> DataLoader<D, B>
+where
+    D: Dataset + Send + Sync + 'static,
+    B: Send + 'static,
+{
+    /// One epoch of batches.
+    pub fn iter(&self) -> Batches<B> {
+        let job = Arc::new(Job {
+            dataset: Arc::clone(&self.dataset),
+            collate: Arc::clone(&self.collate),
+            order: self.epoch_order(),
+            batch_size: self.batch_size,
+        });
+        let total = self.len();
+
+        #[cfg(feature = "threads")]
+        if self.num_workers > 0 {
+            return self.spawn_workers(job, total);
+        }
+        let produce = move |batch| job.batch(batch);
+        Batches {
+            next: 0,
+            total,
+            source: Source::Inline(Box::new(produce)),
+        }
+    }
+
+    #[cfg(feature = "threads")]
+    fn spawn_workers(&self, job: Arc<Job<D, B>>, total: usize) -> Batches<B> {
+        let capacity = self.num_workers * self.prefetch;
+        let (task_sender, tasks) = sync_channel::<usize>(capacity);
+        let (result_sender, results) = sync_channel(capacity);
+        let tasks = Arc::new(Mutex::new(tasks));
+        let workers = (0..self.num_workers)
+            .map(|_| {
+                let job = Arc::clone(&job);
+                let tasks = Arc::clone(&tasks);
+                let results = result_sender.clone();
+                std::thread::spawn(move || loop {
+                    let task = tasks.lock().map(|tasks| tasks.recv());
+                    let Ok(Ok(batch)) = task else { break };
+                    let result = catch_unwind(AssertUnwindSafe(|| job.batch(batch)))
+                        .unwrap_or_else(|_| Err("Data loader worker panicked".into()));
+                    if results.send((batch, result)).is_err() {
+                        break;
+                    }
+                })
+            })
+            .collect();
+
+        let mut pool = WorkerPool {
+            tasks: Some(task_sender),
+            results: Some(results),
+            ready: BTreeMap::new(),
+            queued: 0,
+            workers,
+        };
+        pool.queue_up_to(capacity.min(total));
+        Batches {
+            next: 0,
+            total,
+            source: Source::Workers(pool),
+        }
+    }
+}
+
+// What a worker needs to produce any batch of one epoch
+struct Job<D: Dataset, B> {
+    dataset: Arc<D>,
+    collate: Arc<CollateFn<D::Item, B>>,
+    order: Vec<usize>,
+    batch_size: usize,
+}
+
+impl<D: Dataset, B> Job<D, B> {
+    fn batch(&self, batch: usize) -> MlResult<B> {
+        let start = batch * self.batch_size;
+        let end = (start + self.batch_size).min(self.order.len());
+        let len = self.dataset.len();
+        let items = self.order[start..end]
+            .iter()
+            .map(|&index| {
+                if index >= len {
+                    return Err(format!(
+                        "Sampled index {} is out of range for {} samples",
+                        index, len
+                    )
+                    .into());
+                }
+                self.dataset.get(index)
+            })
+            .collect::<MlResult<Vec<_>>>()?;
+        (self.collate)(items)
+    }
+}
+
+/// The batches of one epoch of a [`DataLoader`], in order. Dropping it early stops the
+/// loader's workers.
+pub struct Batches<B> {
+    next: usize,
+    total: usize,
+    source: Source<B>,
+}
+
+enum Source<B> {
+    Inline(Box<dyn FnMut(usize) -> MlResult<B>>),
+    #[cfg(feature = "threads")]
+    Workers(WorkerPool<B>),
+}
+
+#[cfg(feature = "threads")]
+struct WorkerPool<B> {
+    tasks: Option<SyncSender<usize>>,
+    results: Option<Receiver<(usize, MlResult<B>)>>,
+    // Batches that finished ahead of the one being waited for
+    ready: BTreeMap<usize, MlResult<B>>,
+    queued: usize,
+    workers: Vec<JoinHandle<()>>,
+}
+
+#[cfg(feature = "threads")]
+impl<B> WorkerPool<B> {
+    fn queue_up_to(&mut self, end: usize) {
+        while self.queued < end {
+            let sent = self.tasks.as_ref().map(|tasks| tasks.send(self.queued));
+            if !matches!(sent, Some(Ok(()))) {
+                return;
+            }
+            self.queued += 1;
+        }
+    }
+
+    fn take(&mut self, batch: usize) -> MlResult<B> {
+        loop {
+            if let Some(result) = self.ready.remove(&batch) {
+                return result;
+            }
+            match self.results.as_ref().map(Receiver::recv) {
+                Some(Ok((index, result))) => {
+                    self.ready.insert(index, result);
+                }
+                _ => return Err("Data loader workers stopped".into()),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "threads")]
+impl<B> Drop for WorkerPool<B> {
+    fn drop(&mut self) {
+        // Closing both channels wakes any worker waiting on either
+        self.tasks.take();
+        self.results.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl<B> Iterator for Batches<B> {
+    type Item = MlResult<B>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.total {
+            return None;
+        }
+        let batch = self.next;
+        self.next += 1;
+        Some(match &mut self.source {
+            Source::Inline(produce) => produce(batch),
+            #[cfg(feature = "threads")]
+            Source::Workers(pool) => {
+                let result = pool.take(batch);
+                // A batch was used up, so another may be started
+                pool.queue_up_to((pool.queued + 1).min(self.total));
+                result
+            }
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.total - self.next;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<B> ExactSizeIterator for Batches<B> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::HostTensor;
+
+    struct Squares(usize);
+
+    impl Dataset for Squares {
+        type Item = (HostTensor, usize);
+
+        fn len(&self) -> usize {
+            self.0
+        }
+
+        fn get(&self, index: usize) -> MlResult<Self::Item> {
+            if index == 13 {
+                return Err("unlucky sample".into());
+            }
+            // Make later samples slower, so workers finish out of order
+            std::thread::sleep(std::time::Duration::from_micros(200 * (index % 3) as u64));
+            let x = index as f32;
+            Ok((HostTensor::new(vec![x, x * x], &[2])?, index % 2))
+        }
+    }
+
+    fn epoch<B>(loader: &DataLoader<Squares, B>) -> Vec<MlResult<B>>
+    where
+        B: Send + 'static,
+    {
+        loader.iter().collect()
+    }
+
+    #[test]
+    fn test_data_loader_workers() -> MlResult<()> {
+        let sequential = DataLoader::new(Squares(10), 4);
+        let batches = epoch(&sequential)
+            .into_iter()
+            .collect::<MlResult<Vec<_>>>()?;
+        assert_eq!(batches.len(), 3);
+        let (inputs, labels) = &batches[2];
+        assert_eq!(inputs.shape(), &[2, 2]);
+        assert_eq!(inputs.data(), &[8.0, 64.0, 9.0, 81.0]);
+        assert_eq!(labels.data(), &[0.0, 1.0]);
+
+        // Background workers give the same batches in the same order
+        let parallel = DataLoader::new(Squares(10), 4).num_workers(3).prefetch(1);
+        let prefetched = epoch(&parallel).into_iter().collect::<MlResult<Vec<_>>>()?;
+        assert_eq!(prefetched, batches);
+
+        // Shuffled epochs differ from each other but repeat for the same seed
+        let shuffled = || {
+            DataLoader::new(Squares(12), 3)
+                .shuffle(true)
+                .seed(5)
+                .num_workers(2)
+                .drop_last(true)
+        };
+        let loader = shuffled();
+        let first = epoch(&loader).into_iter().collect::<MlResult<Vec<_>>>()?;
+        let second = epoch(&loader).into_iter().collect::<MlResult<Vec<_>>>()?;
+        assert_ne!(first, second);
+        assert_eq!(
+            epoch(&shuffled())
+                .into_iter()
+                .collect::<MlResult<Vec<_>>>()?,
+            first
+        );
+        let mut seen: Vec<f32> = first
+            .iter()
+            .flat_map(|(x, _)| x.data().iter().step_by(2).copied())
+            .collect();
+        seen.sort_by(f32::total_cmp);
+        assert_eq!(seen, (0..12).map(|i| i as f32).collect::<Vec<_>>());
+
+        // A failing sample fails its batch only, and stopping early doesn't hang
+        let loader = DataLoader::new(Squares(20), 4).num_workers(2);
+        let results = epoch(&loader);
+        assert!(results[3].is_err());
+        assert!(results[4].is_ok());
+        assert_eq!(loader.iter().take(1).count(), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,373 @@
+//! Datasets and batched loading for training.
+//!
+//! A [`Dataset`] hands out samples by index, and a [`DataLoader`] groups them into batches,
+//! optionally shuffled and prepared ahead of time on worker threads. Samples are made of
+//! [`HostTensor`]s rather than [`Tensor`]s, since tensors stay on the thread that created
+//! them; a batch becomes a tensor once it reaches the training loop.
+//!
+//! ```ignore
+//! let loader = DataLoader::new(dataset, 64).shuffle(true).num_workers(4);
+//! for batch in loader.iter() {
+//!     let (inputs, targets) = batch?;
+//!     let (inputs, targets) = (inputs.to_tensor()?, targets.to_tensor()?);
+//!     // ...
+//! }
+//! ```
+
+use std::sync::Arc;
+
+use crate::tensor::{Tensor, TensorError};
+use crate::MlResult;
+
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod combinators;
+#[cfg(feature = "fs")]
+pub mod csv;
+#[cfg(all(feature = "image", feature = "fs"))]
+pub mod folder;
+pub mod loader;
+#[cfg(feature = "polars")]
+pub mod polars;
+pub mod sampler;
+pub mod split;
+pub mod stream;
+pub mod tabular;
+pub mod tensor;
+pub mod text;
+pub mod transforms;
+#[cfg(feature = "fs")]
+pub mod vision;
+
+#[cfg(feature = "arrow")]
+pub use self::arrow::{ArrowBuilder, ArrowDataset, Nulls};
+#[cfg(feature = "polars")]
+pub use self::polars::{FrameEncoder, FrameEncoderBuilder, StringEncoding};
+pub use combinators::{Concat, Mapped, Zip};
+#[cfg(feature = "fs")]
+pub use csv::{CsvBuilder, CsvDataset};
+#[cfg(all(feature = "image", feature = "fs"))]
+pub use folder::ImageFolder;
+pub use loader::{Batches, DataLoader};
+pub use sampler::{
+    DistributedSampler, RandomSampler, Sampler, SequentialSampler, StratifiedSampler,
+    WeightedRandomSampler,
+};
+pub use split::{Fold, KFold, Subset};
+#[cfg(feature = "fs")]
+pub use stream::Lines;
+pub use stream::{IterableDataset, StreamBatches, StreamLoader, WorkerInfo};
+pub use tabular::{ColumnEncoding, Normalization};
+pub use tensor::TensorDataset;
+pub use text::{
+    BpeTokenizer, Encoding, TextDataset, TextEncoder, Tokenizer, Vocab, WhitespaceTokenizer,
+};
+pub use transforms::{Transform, Transformed};
+#[cfg(feature = "fs")]
+pub use vision::{Cifar, Mnist, Split, CIFAR10_CLASSES};
+
+/// A collection of samples that can be read in any order.
+pub trait Dataset {
+    type Item;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The sample at `index`, which is below [`Dataset::len`].
+    fn get(&self, index: usize) -> MlResult<Self::Item>;
+
+    /// Told by a [`DataLoader`] which epoch it is about to read, for datasets whose samples
+    /// vary from epoch to epoch, such as randomly augmented ones.
+    fn set_epoch(&self, _epoch: u64) {}
+
+    /// Applies `transform` to the input of each `(input, target)` sample.
+    fn transform<X, Y, T>(self, transform: T) -> Transformed<Self, T>
+    where
+        Self: Dataset<Item = (X, Y)> + Sized,
+        T: Transform<X>,
+    {
+        Transformed::new(self, transform)
+    }
+
+    /// This dataset's samples followed by `other`'s.
+    fn concat<D>(self, other: D) -> Concat<Self, D>
+    where
+        Self: Sized,
+        D: Dataset<Item = Self::Item>,
+    {
+        Concat::new(self, other)
+    }
+
+    /// Pairs of this dataset's and `other`'s samples at each index, as many as the shorter
+    /// one has.
+    fn zip<D: Dataset>(self, other: D) -> Zip<Self, D>
+    where
+        Self: Sized,
+    {
+        Zip::new(self, other)
+    }
+
+    /// Passes each sample through `f` as it is read.
+    fn map<F, T>(self, f: F) -> Mapped<Self, F>
+    where
+        Self: Sized,
+        F: Fn(Self::Item) -> MlResult<T>,
+    {
+        Mapped::new(self, f)
+    }
+}
+
+impl<D: Dataset + ?Sized> Dataset for Arc<D> {
+    type Item = D::Item;
+
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn get(&self, index: usize) -> MlResult<Self::Item> {
+        (**self).get(index)
+    }
+
+    fn set_epoch(&self, epoch: u64) {
+        (**self).set_epoch(epoch)
+    }
+}
+
+impl<D: Dataset + ?Sized> Dataset for Box<D> {
+    type Item = D::Item;
+
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn get(&self, index: usize) -> MlResult<Self::Item> {
+        (**self).get(index)
+    }
+
+    fn set_epoch(&self, epoch: u64) {
+        (**self).set_epoch(epoch)
+    }
+}
+
+/// An f32 array in host memory. Unlike a [`Tensor`] it can be sent between threads, so it is
+/// what datasets produce and data loader workers batch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HostTensor {
+    data: Vec<f32>,
+    shape: Vec<usize>,
+}
+
+impl HostTensor {
+    pub fn new(data: Vec<f32>, shape: &[usize]) -> MlResult<Self> {
+        let expected: usize = shape.iter().product();
+        if data.len() != expected {
+            return Err(TensorError::InvalidDataLength {
+                expected,
+                got: data.len(),
+            }
+            .into());
+        }
+        Ok(Self {
+            data,
+            shape: shape.to_vec(),
+        })
+    }
+
+    /// A tensor of the given shape copied from `tensor`'s data.
+    pub fn from_tensor(tensor: &Tensor) -> Self {
+        Self {
+            data: tensor.data().to_vec(),
+            shape: tensor.shape().to_vec(),
+        }
+    }
+
+    /// A one element tensor of shape `[1]`.
+    pub fn scalar(value: f32) -> Self {
+        Self {
+            data: vec![value],
+            shape: vec![1],
+        }
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    pub fn data(&self) -> &[f32] {
+        &self.data
+    }
+
+    pub fn into_data(self) -> Vec<f32> {
+        self.data
+    }
+
+    /// Makes a tensor on the default device from the data.
+    pub fn to_tensor(&self) -> MlResult<Tensor> {
+        Tensor::from_vec(self.data.clone(), &self.shape)
+    }
+
+    pub fn into_tensor(self) -> MlResult<Tensor> {
+        Tensor::from_vec(self.data, &self.shape)
+    }
+
+    /// Stacks tensors of one shape along a new leading axis.
+    pub fn stack(items: &[HostTensor]) -> MlResult<Self> {
+        let shape = items.first().map_or(&[][..], |first| first.shape());
+        if let Some(other) = items.iter().find(|item| item.shape() != shape) {
+            return Err(TensorError::InvalidShape {
+                expected: shape.to_vec(),
+                got: other.shape().to_vec(),
+            }
+            .into());
+        }
+
+        let mut data = Vec::with_capacity(items.len() * shape.iter().product::<usize>());
+        for item in items {
+            data.extend_from_slice(&item.data);
+        }
+        let mut stacked = vec![items.len()];
+        stacked.extend_from_slice(shape);
+        Ok(Self {
+            data,
+            shape: stacked,
+        })
+    }
+
+    // The `[channels, height, width]` of an image, treating a 2-D tensor as one channel
+    fn image_dims(&self) -> MlResult<[usize; 3]> {
+        match self.shape[..] {
+            [c, h, w] => Ok([c, h, w]),
+            [h, w] => Ok([1, h, w]),
+            _ => Err(TensorError::InvalidOperation {
+                op: "image",
+                reason: format!("expected a [C, H, W] or [H, W] image, got {:?}", self.shape),
+            }
+            .into()),
+        }
+    }
+
+    /// The `height` by `width` window of an image whose top left corner is at `top`, `left`.
+    /// Images are `[channels, height, width]` or `[height, width]`.
+    pub fn crop(&self, top: usize, left: usize, height: usize, width: usize) -> MlResult<Self> {
+        let [channels, h, w] = self.image_dims()?;
+        if top + height > h || left + width > w {
+            return Err(TensorError::InvalidOperation {
+                op: "crop",
+                reason: format!(
+                    "{}x{} window at ({}, {}) exceeds {}x{} image",
+                    height, width, top, left, h, w
+                ),
+            }
+            .into());
+        }
+
+        let mut data = Vec::with_capacity(channels * height * width);
+        for plane in self.data.chunks_exact(h * w) {
+            for row in plane.chunks_exact(w).skip(top).take(height) {
+                data.extend_from_slice(&row[left..left + width]);
+            }
+        }
+        let mut shape = self.shape.clone();
+        let rank = shape.len();
+        shape[rank - 2..].copy_from_slice(&[height, width]);
+        Self::new(data, &shape)
+    }
+
+    /// The central `height` by `width` window of an image.
+    pub fn center_crop(&self, height: usize, width: usize) -> MlResult<Self> {
+        let [_, h, w] = self.image_dims()?;
+        self.crop(
+            h.saturating_sub(height) / 2,
+            w.saturating_sub(width) / 2,
+            height,
+            width,
+        )
+    }
+
+    /// Resizes an image to `height` by `width` with bilinear interpolation, sampling at pixel
+    /// centers.
+    pub fn resize(&self, height: usize, width: usize) -> MlResult<Self> {
+        let [channels, h, w] = self.image_dims()?;
+        if h == 0 || w == 0 {
+            return Err(TensorError::InvalidOperation {
+                op: "resize",
+                reason: "can't resize an empty image".to_string(),
+            }
+            .into());
+        }
+        // Source position, the pixel before it and the weight of the pixel after it
+        let taps = |out: usize, len: usize| -> Vec<(usize, usize, f32)> {
+            let scale = len as f32 / out as f32;
+            (0..out)
+                .map(|i| {
+                    let x = ((i as f32 + 0.5) * scale - 0.5).clamp(0.0, (len - 1) as f32);
+                    let before = x.floor() as usize;
+                    (before, (before + 1).min(len - 1), x - before as f32)
+                })
+                .collect()
+        };
+        let (rows, cols) = (taps(height, h), taps(width, w));
+
+        let mut data = Vec::with_capacity(channels * height * width);
+        for plane in self.data.chunks_exact(h * w) {
+            for &(y0, y1, fy) in &rows {
+                for &(x0, x1, fx) in &cols {
+                    let top = plane[y0 * w + x0] * (1.0 - fx) + plane[y0 * w + x1] * fx;
+                    let bottom = plane[y1 * w + x0] * (1.0 - fx) + plane[y1 * w + x1] * fx;
+                    data.push(top * (1.0 - fy) + bottom * fy);
+                }
+            }
+        }
+        let mut shape = self.shape.clone();
+        let rank = shape.len();
+        shape[rank - 2..].copy_from_slice(&[height, width]);
+        Self::new(data, &shape)
+    }
+}
+
+/// Samples that a [`DataLoader`] knows how to batch by default.
+///
+/// Tensors are stacked along a new leading axis, scalars and class indices become a 1-D
+/// tensor, and tuples are batched field by field.
+pub trait Collate: Sized {
+    type Batch;
+
+    fn collate(items: Vec<Self>) -> MlResult<Self::Batch>;
+}
+
+impl Collate for HostTensor {
+    type Batch = HostTensor;
+
+    fn collate(items: Vec<Self>) -> MlResult<HostTensor> {
+        HostTensor::stack(&items)
+    }
+}
+
+impl Collate for f32 {
+    type Batch = HostTensor;
+
+    fn collate(items: Vec<Self>) -> MlResult<HostTensor> {
+        let len = items.len();
+        HostTensor::new(items, &[len])
+    }
+}
+
+impl Collate for usize {
+    type Batch = HostTensor;
+
+    fn collate(items: Vec<Self>) -> MlResult<HostTensor> {
+        f32::collate(items.into_iter().map(|index| index as f32).collect())
+    }
+}
+
+impl<A: Collate, B: Collate> Collate for (A, B) {
+    type Batch = (A::Batch, B::Batch);
+
+    fn collate(items: Vec<Self>) -> MlResult<Self::Batch> {
+        let (a, b): (Vec<A>, Vec<B>) = items.into_iter().unzip();
+        Ok((A::collate(a)?, B::collate(b)?))
+    }
+}
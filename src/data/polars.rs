@@ -0,0 +1,368 @@
+//! Conversions between Polars data frames and feature tensors, behind the `polars` feature.
+//!
+//! A [`FrameEncoder`] is fitted on the selected columns of a frame: numeric columns are read
+//! as f32 and optionally normalized, and string and categorical columns become one-hot
+//! vectors or category indices. The same encoder turns other frames into tensors the same
+//! way, and turns tensors back into frames, undoing the normalization and picking the
+//! category of each one-hot block.
+//!
+//! ```ignore
+//! let encoder = FrameEncoder::builder()
+//!     .columns(["age", "income", "city"])
+//!     .normalize(Normalization::Standard)
+//!     .fit(&train)?;
+//! let x = encoder.encode(&train)?;
+//! let frame = encoder.decode(&x)?;
+//! ```
+
+use std::collections::BTreeSet;
+
+use polars::prelude::{DataFrame, DataType, NamedFrom, Series};
+
+use super::tabular::{feature_names, statistics, Column, ColumnEncoding, Normalization};
+use crate::tensor::Tensor;
+use crate::MlResult;
+
+/// How string and categorical columns are encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StringEncoding {
+    /// One position per category, set to 1 for the row's category.
+    #[default]
+    OneHot,
+    /// The category's index, as a single f32.
+    Ordinal,
+}
+
+/// Builds a [`FrameEncoder`]; see [`FrameEncoder::builder`].
+#[derive(Debug, Clone, Default)]
+pub struct FrameEncoderBuilder {
+    columns: Option<Vec<String>>,
+    strings: StringEncoding,
+    ordinal: Vec<String>,
+    categories: Vec<(String, Vec<String>)>,
+    normalization: Normalization,
+}
+
+impl FrameEncoderBuilder {
+    /// The columns to encode, in order. Defaults to every column of the frame.
+    pub fn columns<I, S>(mut self, columns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.columns = Some(columns.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// How string and categorical columns are encoded. Defaults to one-hot.
+    pub fn strings(mut self, encoding: StringEncoding) -> Self {
+        self.strings = encoding;
+        self
+    }
+
+    /// Encodes `column` as category indices whatever [`FrameEncoderBuilder::strings`] says,
+    /// e.g. for ordered categories such as sizes.
+    pub fn ordinal(mut self, column: impl Into<String>) -> Self {
+        self.ordinal.push(column.into());
+        self
+    }
+
+    /// Treats `column` as categorical with exactly these categories, in this order, even if
+    /// it holds numbers. Any other value in the column is an error.
+    pub fn categories<I, S>(mut self, column: impl Into<String>, categories: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let categories = categories.into_iter().map(Into::into).collect();
+        self.categories.push((column.into(), categories));
+        self
+    }
+
+    pub fn normalize(mut self, normalization: Normalization) -> Self {
+        self.normalization = normalization;
+        self
+    }
+
+    /// Works out each column's encoding from `frame`: the normalization statistics of
+    /// numeric columns, and the sorted distinct values of the others.
+    pub fn fit(self, frame: &DataFrame) -> MlResult<FrameEncoder> {
+        let names = match &self.columns {
+            Some(names) => names.clone(),
+            None => frame
+                .get_columns()
+                .iter()
+                .map(|series| series.name().to_string())
+                .collect(),
+        };
+
+        let mut columns = Vec::with_capacity(names.len());
+        for name in names {
+            let series = series(frame, &name)?;
+            let explicit = self.categories.iter().find(|(c, _)| *c == name);
+            let encoding = match explicit {
+                Some((_, categories)) => ColumnEncoding::Categorical {
+                    categories: categories.clone(),
+                },
+                None if is_categorical(series.dtype()) => {
+                    let values = strings(series)?;
+                    let distinct: BTreeSet<&str> = values.iter().map(String::as_str).collect();
+                    ColumnEncoding::Categorical {
+                        categories: distinct.into_iter().map(str::to_string).collect(),
+                    }
+                }
+                None => {
+                    let (offset, scale) = statistics(&numbers(series)?, self.normalization);
+                    ColumnEncoding::Numeric { offset, scale }
+                }
+            };
+            let ordinal = self.strings == StringEncoding::Ordinal || self.ordinal.contains(&name);
+            columns.push((Column { name, encoding }, ordinal));
+        }
+        Ok(FrameEncoder { columns })
+    }
+}
+
+/// Turns the selected columns of data frames into `[rows, features]` tensors and back.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameEncoder {
+    // Each column with whether a categorical one is encoded as its index
+    columns: Vec<(Column, bool)>,
+}
+
+impl FrameEncoder {
+    pub fn builder() -> FrameEncoderBuilder {
+        FrameEncoderBuilder::default()
+    }
+
+    /// Fits an encoder on every column of `frame` with the default settings.
+    pub fn fit(frame: &DataFrame) -> MlResult<Self> {
+        Self::builder().fit(frame)
+    }
+
+    /// The number of features each row becomes.
+    pub fn width(&self) -> usize {
+        self.columns
+            .iter()
+            .map(|(column, ordinal)| column.width(*ordinal))
+            .sum()
+    }
+
+    /// The name of each feature: the column name for numeric and ordinal columns, and
+    /// `column=category` for each one-hot position.
+    pub fn feature_names(&self) -> Vec<String> {
+        self.columns
+            .iter()
+            .flat_map(|(column, ordinal)| {
+                if *ordinal {
+                    vec![column.name.clone()]
+                } else {
+                    feature_names(std::slice::from_ref(column))
+                }
+            })
+            .collect()
+    }
+
+    /// How column `name` is encoded.
+    pub fn encoding(&self, name: &str) -> Option<&ColumnEncoding> {
+        self.columns
+            .iter()
+            .find(|(column, _)| column.name == name)
+            .map(|(column, _)| &column.encoding)
+    }
+
+    /// Encodes the encoder's columns of `frame` as a `[rows, features]` tensor. Nulls are
+    /// an error; fill or drop them in Polars first.
+    pub fn encode(&self, frame: &DataFrame) -> MlResult<Tensor> {
+        let rows = frame.height();
+        let width = self.width();
+        let mut data = vec![0.0; rows * width];
+
+        let mut start = 0;
+        for (column, ordinal) in &self.columns {
+            let series = series(frame, &column.name)?;
+            match &column.encoding {
+                ColumnEncoding::Numeric { offset, scale } => {
+                    for (row, value) in numbers(series)?.into_iter().enumerate() {
+                        data[row * width + start] = (value - offset) / scale;
+                    }
+                }
+                ColumnEncoding::Categorical { categories } => {
+                    for (row, value) in strings(series)?.iter().enumerate() {
+                        let index = categories
+                            .iter()
+                            .position(|category| category == value)
+                            .ok_or_else(|| {
+                                format!(
+                                    "Row {}, column {}: unknown category {:?}",
+                                    row, column.name, value
+                                )
+                            })?;
+                        if *ordinal {
+                            data[row * width + start] = index as f32;
+                        } else {
+                            data[row * width + start + index] = 1.0;
+                        }
+                    }
+                }
+            }
+            start += column.width(*ordinal);
+        }
+        Tensor::from_vec(data, &[rows, width])
+    }
+
+    /// Decodes a `[rows, features]` tensor, such as a model's reconstruction, into a frame
+    /// with one column per encoded column. Numeric columns come back as `Float32` in their
+    /// original units; categorical ones as strings, taking the largest position of a
+    /// one-hot block and rounding an index, and null when it is out of range.
+    pub fn decode(&self, tensor: &Tensor) -> MlResult<DataFrame> {
+        let width = self.width();
+        let rows = match tensor.shape() {
+            &[rows, w] if w == width => rows,
+            shape => {
+                return Err(format!(
+                    "Expected a [rows, {}] tensor to decode, got {:?}",
+                    width, shape
+                )
+                .into())
+            }
+        };
+        let data = tensor.data();
+
+        let mut series = Vec::with_capacity(self.columns.len());
+        let mut start = 0;
+        for (column, ordinal) in &self.columns {
+            let name = column.name.as_str();
+            let cells = (0..rows).map(|row| &data[row * width + start..][..column.width(*ordinal)]);
+            series.push(match &column.encoding {
+                ColumnEncoding::Numeric { offset, scale } => {
+                    let values: Vec<f32> = cells.map(|cell| cell[0] * scale + offset).collect();
+                    Series::new(name.into(), values)
+                }
+                ColumnEncoding::Categorical { categories } => {
+                    let values: Vec<Option<&str>> = cells
+                        .map(|cell| {
+                            let index = if *ordinal {
+                                let index = cell[0].round();
+                                (index >= 0.0).then_some(index as usize)
+                            } else {
+                                cell.iter()
+                                    .enumerate()
+                                    .max_by(|a, b| a.1.total_cmp(b.1))
+                                    .map(|(index, _)| index)
+                            };
+                            index.and_then(|i| categories.get(i)).map(String::as_str)
+                        })
+                        .collect();
+                    Series::new(name.into(), values)
+                }
+            });
+            start += column.width(*ordinal);
+        }
+        DataFrame::new(series).map_err(|e| format!("Failed to build the frame: {}", e).into())
+    }
+}
+
+fn is_categorical(dtype: &DataType) -> bool {
+    matches!(dtype, DataType::String | DataType::Categorical(..))
+}
+
+fn series<'a>(frame: &'a DataFrame, name: &str) -> MlResult<&'a Series> {
+    frame
+        .column(name)
+        .map_err(|_| format!("No column named {}", name).into())
+}
+
+fn check_nulls(series: &Series) -> MlResult<()> {
+    match series.null_count() {
+        0 => Ok(()),
+        n => Err(format!("Column {} has {} nulls", series.name(), n).into()),
+    }
+}
+
+fn numbers(series: &Series) -> MlResult<Vec<f32>> {
+    check_nulls(series)?;
+    let cast = series
+        .cast(&DataType::Float32)
+        .and_then(|cast| Ok(cast.f32()?.into_iter().flatten().collect()))
+        .map_err(|e| format!("Failed to read column {}: {}", series.name(), e))?;
+    Ok(cast)
+}
+
+fn strings(series: &Series) -> MlResult<Vec<String>> {
+    check_nulls(series)?;
+    let cast = series
+        .cast(&DataType::String)
+        .and_then(|cast| {
+            Ok(cast
+                .str()?
+                .into_iter()
+                .flatten()
+                .map(str::to_string)
+                .collect())
+        })
+        .map_err(|e| format!("Failed to read column {}: {}", series.name(), e))?;
+    Ok(cast)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_encoder() -> MlResult<()> {
+        let frame = DataFrame::new(vec![
+            Series::new("age".into(), vec![20.0f64, 40.0, 30.0]),
+            Series::new("city".into(), vec!["b", "a", "b"]),
+            Series::new("size".into(), vec!["s", "m", "l"]),
+            Series::new("id".into(), vec![7i64, 8, 9]),
+        ])
+        .unwrap();
+
+        let encoder = FrameEncoder::builder()
+            .columns(["age", "city", "size"])
+            .categories("size", ["s", "m", "l"])
+            .ordinal("size")
+            .normalize(Normalization::MinMax)
+            .fit(&frame)?;
+        assert_eq!(encoder.feature_names(), ["age", "city=a", "city=b", "size"]);
+
+        let x = encoder.encode(&frame)?;
+        assert_eq!(x.shape(), &[3, 4]);
+        assert_eq!(
+            x.data(),
+            &[0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 1.0, 0.5, 0.0, 1.0, 2.0]
+        );
+
+        let back = encoder.decode(&x)?;
+        assert_eq!(back.width(), 3);
+        let ages: Vec<_> = back
+            .column("age")
+            .unwrap()
+            .f32()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(ages, [Some(20.0), Some(40.0), Some(30.0)]);
+        let sizes: Vec<_> = back
+            .column("size")
+            .unwrap()
+            .str()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(sizes, [Some("s"), Some("m"), Some("l")]);
+
+        // Unknown categories and nulls are errors
+        let other = DataFrame::new(vec![
+            Series::new("age".into(), vec![Some(1.0f64)]),
+            Series::new("city".into(), vec!["c"]),
+            Series::new("size".into(), vec!["s"]),
+        ])
+        .unwrap();
+        assert!(encoder.encode(&other).is_err());
+        let nulls = DataFrame::new(vec![Series::new("age".into(), vec![None::<f64>])]).unwrap();
+        assert!(FrameEncoder::fit(&nulls).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,367 @@
+//! The order, and choice, of the samples a [`DataLoader`](super::DataLoader) reads each epoch.
+
+use crate::nn::random::SimpleRng;
+use crate::MlResult;
+
+/// Chooses the dataset indices of an epoch.
+///
+/// A [`DataLoader`](super::DataLoader) hands the sampler a generator seeded from its seed
+/// and the epoch number, so epochs differ from each other but repeat across runs.
+pub trait Sampler: Send + Sync {
+    /// The number of indices [`Sampler::sample`] gives for a dataset of `dataset_len`
+    /// samples.
+    fn len(&self, dataset_len: usize) -> usize;
+
+    /// One epoch's indices, in the order they are read.
+    fn sample(&self, dataset_len: usize, rng: &mut SimpleRng) -> Vec<usize>;
+}
+
+/// Every sample once, in index order.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SequentialSampler;
+
+impl Sampler for SequentialSampler {
+    fn len(&self, dataset_len: usize) -> usize {
+        dataset_len
+    }
+
+    fn sample(&self, dataset_len: usize, _rng: &mut SimpleRng) -> Vec<usize> {
+        (0..dataset_len).collect()
+    }
+}
+
+/// Every sample once, in a random order.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RandomSampler;
+
+impl Sampler for RandomSampler {
+    fn len(&self, dataset_len: usize) -> usize {
+        dataset_len
+    }
+
+    fn sample(&self, dataset_len: usize, rng: &mut SimpleRng) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..dataset_len).collect();
+        rng.shuffle(&mut order);
+        order
+    }
+}
+
+/// Draws samples with probability proportional to their weights, one weight per sample of
+/// the dataset.
+///
+/// With replacement a sample can be read several times in an epoch, which is how a rare
+/// class is oversampled; [`WeightedRandomSampler::balanced`] weights every class equally.
+#[derive(Debug, Clone)]
+pub struct WeightedRandomSampler {
+    weights: Vec<f64>,
+    num_samples: usize,
+    replacement: bool,
+}
+
+impl WeightedRandomSampler {
+    /// `num_samples` draws per epoch from samples weighted by `weights`. Without
+    /// `replacement` every draw is a different sample, so there must be at least
+    /// `num_samples` positive weights.
+    pub fn new(weights: &[f32], num_samples: usize, replacement: bool) -> MlResult<Self> {
+        if let Some(weight) = weights.iter().find(|w| !w.is_finite() || **w < 0.0) {
+            return Err(format!(
+                "Sample weights must be finite and non-negative, got {}",
+                weight
+            )
+            .into());
+        }
+        let positive = weights.iter().filter(|&&w| w > 0.0).count();
+        if num_samples > 0 && positive == 0 {
+            return Err("Can't draw samples when every weight is zero".into());
+        }
+        if !replacement && num_samples > positive {
+            return Err(format!(
+                "Can't draw {} samples without replacement from {} with positive weight",
+                num_samples, positive
+            )
+            .into());
+        }
+        Ok(Self {
+            weights: weights.iter().map(|&w| w as f64).collect(),
+            num_samples,
+            replacement,
+        })
+    }
+
+    /// Weights each sample by the inverse of its class's frequency in `labels`, so every
+    /// class is drawn equally often, with as many draws per epoch as there are samples.
+    pub fn balanced(labels: &[usize]) -> MlResult<Self> {
+        let counts = class_counts(labels);
+        let weights: Vec<f32> = labels.iter().map(|&l| 1.0 / counts[l] as f32).collect();
+        Self::new(&weights, labels.len(), true)
+    }
+
+    pub fn weights(&self) -> &[f64] {
+        &self.weights
+    }
+}
+
+impl Sampler for WeightedRandomSampler {
+    fn len(&self, _dataset_len: usize) -> usize {
+        self.num_samples
+    }
+
+    fn sample(&self, _dataset_len: usize, rng: &mut SimpleRng) -> Vec<usize> {
+        if self.replacement {
+            let cumulative: Vec<f64> = self
+                .weights
+                .iter()
+                .scan(0.0, |total, &w| {
+                    *total += w;
+                    Some(*total)
+                })
+                .collect();
+            let total = cumulative.last().copied().unwrap_or_default();
+            let last = self.weights.len().saturating_sub(1);
+            (0..self.num_samples)
+                .map(|_| {
+                    let target = rng.next_f32() as f64 * total;
+                    cumulative.partition_point(|&c| c <= target).min(last)
+                })
+                .collect()
+        } else {
+            // Each sample gets an exponentially distributed key with rate equal to its
+            // weight; the smallest keys are a weighted draw without replacement
+            let mut keys: Vec<(f64, usize)> = self
+                .weights
+                .iter()
+                .enumerate()
+                .map(|(index, &w)| {
+                    let u = 1.0 - rng.next_f32() as f64;
+                    let key = if w > 0.0 { -u.ln() / w } else { f64::INFINITY };
+                    (key, index)
+                })
+                .collect();
+            keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+            keys.truncate(self.num_samples);
+            keys.into_iter().map(|(_, index)| index).collect()
+        }
+    }
+}
+
+/// Every sample once, in a random order that spreads each class evenly over the epoch, so
+/// every batch holds the classes in about the ratio of the whole dataset: any run of
+/// consecutive samples has within one of its share of each class.
+#[derive(Debug, Clone)]
+pub struct StratifiedSampler {
+    labels: Vec<usize>,
+}
+
+impl StratifiedSampler {
+    /// A sampler over a dataset whose sample `i` is of class `labels[i]`.
+    pub fn new(labels: &[usize]) -> Self {
+        Self {
+            labels: labels.to_vec(),
+        }
+    }
+
+    pub fn labels(&self) -> &[usize] {
+        &self.labels
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn len(&self, _dataset_len: usize) -> usize {
+        self.labels.len()
+    }
+
+    fn sample(&self, _dataset_len: usize, rng: &mut SimpleRng) -> Vec<usize> {
+        let mut classes = vec![Vec::new(); class_counts(&self.labels).len()];
+        for (index, &label) in self.labels.iter().enumerate() {
+            classes[label].push(index);
+        }
+
+        // A class of n samples takes positions (k + offset) / n of the epoch, a stride of
+        // 1 / n apart, starting at a random offset
+        let mut keyed = Vec::with_capacity(self.labels.len());
+        for members in &mut classes {
+            rng.shuffle(members);
+            let offset = rng.next_f32() as f64;
+            let n = members.len() as f64;
+            keyed.extend(
+                members
+                    .iter()
+                    .enumerate()
+                    .map(|(k, &index)| ((k as f64 + offset) / n, index)),
+            );
+        }
+        keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+        keyed.into_iter().map(|(_, index)| index).collect()
+    }
+}
+
+/// The share of each epoch one rank of a data-parallel job reads: the epoch's indices are
+/// dealt out in turn, so the ranks see disjoint samples that together cover the dataset.
+///
+/// Every rank gets the same number of samples, since each step of a
+/// `DistributedDataParallel` model waits for all of them: the epoch is padded by repeating
+/// its first indices, or with [`DistributedSampler::drop_last`], cut to a multiple of the
+/// world size. Shuffled, the ranks must agree on the order, so give every rank's loader the
+/// same [`DataLoader::seed`](super::DataLoader::seed).
+#[derive(Debug, Clone, Copy)]
+pub struct DistributedSampler {
+    rank: usize,
+    world_size: usize,
+    shuffle: bool,
+    drop_last: bool,
+}
+
+impl DistributedSampler {
+    /// The sampler of rank `rank` out of `world_size`, shuffling every epoch.
+    pub fn new(rank: usize, world_size: usize) -> MlResult<Self> {
+        if rank >= world_size {
+            return Err(format!("Rank {} is outside a world of {}", rank, world_size).into());
+        }
+        Ok(Self {
+            rank,
+            world_size,
+            shuffle: true,
+            drop_last: false,
+        })
+    }
+
+    /// Deals the indices out in index order instead of a random one.
+    pub fn shuffle(mut self, shuffle: bool) -> Self {
+        self.shuffle = shuffle;
+        self
+    }
+
+    /// Drops the samples left over after an equal share for each rank instead of padding.
+    pub fn drop_last(mut self, drop_last: bool) -> Self {
+        self.drop_last = drop_last;
+        self
+    }
+
+    pub fn rank(&self) -> usize {
+        self.rank
+    }
+
+    pub fn world_size(&self) -> usize {
+        self.world_size
+    }
+}
+
+impl Sampler for DistributedSampler {
+    fn len(&self, dataset_len: usize) -> usize {
+        if self.drop_last {
+            dataset_len / self.world_size
+        } else {
+            dataset_len.div_ceil(self.world_size)
+        }
+    }
+
+    fn sample(&self, dataset_len: usize, rng: &mut SimpleRng) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..dataset_len).collect();
+        if self.shuffle {
+            rng.shuffle(&mut order);
+        }
+        let total = self.len(dataset_len) * self.world_size;
+        let padding: Vec<usize> = order
+            .iter()
+            .copied()
+            .cycle()
+            .take(total.saturating_sub(dataset_len))
+            .collect();
+        order.extend(padding);
+        order
+            .into_iter()
+            .take(total)
+            .skip(self.rank)
+            .step_by(self.world_size)
+            .collect()
+    }
+}
+
+// The number of samples of each class, up to the largest label
+fn class_counts(labels: &[usize]) -> Vec<usize> {
+    let classes = labels.iter().max().map_or(0, |&max| max + 1);
+    let mut counts = vec![0; classes];
+    for &label in labels {
+        counts[label] += 1;
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{DataLoader, Dataset};
+
+    struct Labelled(Vec<usize>);
+
+    impl Dataset for Labelled {
+        type Item = usize;
+
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        fn get(&self, index: usize) -> MlResult<usize> {
+            Ok(self.0[index])
+        }
+    }
+
+    #[test]
+    fn test_weighted_and_stratified_samplers() -> MlResult<()> {
+        // Nine samples of class 0 for each of class 1
+        let labels: Vec<usize> = (0..100).map(|i| usize::from(i % 10 == 0)).collect();
+        let epoch = |sampler: &dyn Sampler, seed| sampler.sample(100, &mut SimpleRng::new(seed));
+
+        let balanced = WeightedRandomSampler::balanced(&labels)?;
+        let drawn = epoch(&balanced, 1);
+        assert_eq!(drawn.len(), 100);
+        let rare = drawn.iter().filter(|&&i| labels[i] == 1).count();
+        assert!((30..=70).contains(&rare), "{} of class 1", rare);
+
+        let mut weights = vec![1.0; 10];
+        weights[3] = 0.0;
+        let without = WeightedRandomSampler::new(&weights, 9, false)?;
+        let mut drawn = epoch(&without, 2);
+        drawn.sort_unstable();
+        assert_eq!(drawn, [0, 1, 2, 4, 5, 6, 7, 8, 9]);
+        assert!(WeightedRandomSampler::new(&weights, 10, false).is_err());
+        assert!(WeightedRandomSampler::new(&[1.0, -1.0], 1, true).is_err());
+
+        // Every batch of ten has exactly one sample of class 1
+        let stratified = StratifiedSampler::new(&labels);
+        let loader = DataLoader::new(Labelled(labels.clone()), 10)
+            .sampler(stratified)
+            .seed(3);
+        let batches = loader.iter().collect::<MlResult<Vec<_>>>()?;
+        assert_eq!(batches.len(), 10);
+        for batch in &batches {
+            assert_eq!(batch.data().iter().sum::<f32>(), 1.0);
+        }
+        let mut order = epoch(&StratifiedSampler::new(&labels), 4);
+        assert_ne!(order, epoch(&StratifiedSampler::new(&labels), 5));
+        order.sort_unstable();
+        assert_eq!(order, (0..100).collect::<Vec<_>>());
+
+        // Ten samples over three ranks: disjoint shares, padded to four each or cut to three
+        let shares = |drop_last| -> MlResult<Vec<Vec<usize>>> {
+            (0..3)
+                .map(|rank| {
+                    let sampler = DistributedSampler::new(rank, 3)?.drop_last(drop_last);
+                    assert_eq!(sampler.len(10), if drop_last { 3 } else { 4 });
+                    Ok(sampler.sample(10, &mut SimpleRng::new(6)))
+                })
+                .collect()
+        };
+        let mut all: Vec<usize> = shares(false)?.concat();
+        assert_eq!(all.len(), 12);
+        all.sort_unstable();
+        all.dedup();
+        assert_eq!(all, (0..10).collect::<Vec<_>>());
+        let mut all: Vec<usize> = shares(true)?.concat();
+        all.sort_unstable();
+        all.dedup();
+        assert_eq!(all.len(), 9);
+        assert!(DistributedSampler::new(3, 3).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,162 @@
+//! Splitting a dataset into parts, such as the folds of cross-validation.
+
+use super::Dataset;
+use crate::nn::random::SimpleRng;
+use crate::MlResult;
+
+/// The samples of a dataset at the given indices, in that order.
+///
+/// Subsets of one dataset can share it by wrapping it in an [`Arc`](std::sync::Arc).
+#[derive(Debug, Clone)]
+pub struct Subset<D> {
+    dataset: D,
+    indices: Vec<usize>,
+}
+
+impl<D: Dataset> Subset<D> {
+    pub fn new(dataset: D, indices: Vec<usize>) -> MlResult<Self> {
+        if let Some(&index) = indices.iter().find(|&&i| i >= dataset.len()) {
+            return Err(format!(
+                "Subset index {} is out of range for a dataset of {} samples",
+                index,
+                dataset.len()
+            )
+            .into());
+        }
+        Ok(Self { dataset, indices })
+    }
+
+    pub fn indices(&self) -> &[usize] {
+        &self.indices
+    }
+
+    pub fn inner(&self) -> &D {
+        &self.dataset
+    }
+}
+
+impl<D: Dataset> Dataset for Subset<D> {
+    type Item = D::Item;
+
+    fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    fn get(&self, index: usize) -> MlResult<Self::Item> {
+        let &inner = self.indices.get(index).ok_or_else(|| {
+            format!(
+                "Index {} is out of range for a subset of {} samples",
+                index,
+                self.indices.len()
+            )
+        })?;
+        self.dataset.get(inner)
+    }
+
+    fn set_epoch(&self, epoch: u64) {
+        self.dataset.set_epoch(epoch);
+    }
+}
+
+/// The training and validation indices of one fold.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fold {
+    pub train: Vec<usize>,
+    pub validation: Vec<usize>,
+}
+
+/// Splits samples into `k` folds, each validating on one part and training on the rest.
+///
+/// Every sample is validated on exactly once. When the samples don't divide evenly, the
+/// first folds validate on one sample more than the others.
+#[derive(Debug, Clone)]
+pub struct KFold {
+    k: usize,
+    seed: Option<u64>,
+}
+
+impl KFold {
+    pub fn new(k: usize) -> Self {
+        Self { k, seed: None }
+    }
+
+    /// Shuffles the samples with `seed` before dividing them, rather than taking the parts
+    /// in index order.
+    pub fn shuffle(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// The folds of `len` samples, in order.
+    pub fn split(&self, len: usize) -> MlResult<Vec<Fold>> {
+        if self.k < 2 || self.k > len {
+            return Err(format!(
+                "Can't split {} samples into {} folds; k must be between 2 and the number of samples",
+                len, self.k
+            )
+            .into());
+        }
+        let mut order: Vec<usize> = (0..len).collect();
+        if let Some(seed) = self.seed {
+            SimpleRng::new(seed).shuffle(&mut order);
+        }
+
+        let mut folds = Vec::with_capacity(self.k);
+        let mut start = 0;
+        for fold in 0..self.k {
+            let size = len / self.k + usize::from(fold < len % self.k);
+            let end = start + size;
+            folds.push(Fold {
+                train: order[..start]
+                    .iter()
+                    .chain(&order[end..])
+                    .copied()
+                    .collect(),
+                validation: order[start..end].to_vec(),
+            });
+            start = end;
+        }
+        Ok(folds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{HostTensor, TensorDataset};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_kfold_and_subset() -> MlResult<()> {
+        let folds = KFold::new(3).split(7)?;
+        let sizes: Vec<usize> = folds.iter().map(|f| f.validation.len()).collect();
+        assert_eq!(sizes, [3, 2, 2]);
+        assert_eq!(folds[1].validation, [3, 4]);
+        assert_eq!(folds[1].train, [0, 1, 2, 5, 6]);
+
+        let shuffled = KFold::new(3).shuffle(5).split(7)?;
+        let mut validated: Vec<usize> =
+            shuffled.iter().flat_map(|f| f.validation.clone()).collect();
+        assert_ne!(validated, (0..7).collect::<Vec<_>>());
+        validated.sort_unstable();
+        assert_eq!(validated, (0..7).collect::<Vec<_>>());
+        assert_eq!(KFold::new(3).shuffle(5).split(7)?, shuffled);
+        assert!(KFold::new(1).split(7).is_err());
+        assert!(KFold::new(8).split(7).is_err());
+
+        let data = Arc::new(TensorDataset::from_host(
+            HostTensor::new((0..7).map(|i| i as f32).collect(), &[7, 1])?,
+            HostTensor::new(vec![0.0; 7], &[7])?,
+        )?);
+        let subset = Subset::new(data.clone(), folds[1].validation.clone())?;
+        assert_eq!(subset.len(), 2);
+        assert_eq!(subset.get(1)?.0.data(), [4.0]);
+        assert!(subset.get(2).is_err());
+        assert!(Subset::new(data, vec![7]).is_err());
+        Ok(())
+    }
+}
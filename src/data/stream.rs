@@ -0,0 +1,509 @@
+//! Datasets read as streams rather than by index.
+//!
+//! An [`IterableDataset`] suits data that can only be read in order: generated samples,
+//! records arriving over a network, or files too big to index. A [`StreamLoader`] batches
+//! one, and with worker threads each worker reads its own shard of the stream, as told by
+//! the [`WorkerInfo`] it is handed.
+//!
+//! ```ignore
+//! let lines = Lines::new("corpus.txt", |line| Ok(line.len() as f32));
+//! let loader = StreamLoader::new(lines, 256).num_workers(4).shuffle_buffer(10_000);
+//! for batch in loader.iter() {
+//!     let lengths = batch?;
+//!     // ...
+//! }
+//! ```
+
+#[cfg(feature = "fs")]
+use std::io::{BufRead, BufReader};
+#[cfg(feature = "threads")]
+use std::panic::{catch_unwind, AssertUnwindSafe};
+#[cfg(feature = "fs")]
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "threads")]
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::Arc;
+#[cfg(feature = "threads")]
+use std::thread::JoinHandle;
+
+use super::Collate;
+use crate::nn::random::SimpleRng;
+use crate::MlResult;
+
+type CollateFn<T, B> = dyn Fn(Vec<T>) -> MlResult<B> + Send + Sync;
+
+/// Which part of a stream one reader is to produce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorkerInfo {
+    /// The reader's position among the readers, below `num_workers`.
+    pub id: usize,
+    /// How many readers share the stream; 1 when there are no worker threads.
+    pub num_workers: usize,
+    pub epoch: u64,
+    /// A seed for this reader in this epoch, for datasets that generate random samples.
+    pub seed: u64,
+}
+
+impl WorkerInfo {
+    /// Every `num_workers`-th item of `items`, starting from the `id`-th, which divides a
+    /// stream that every reader can see into disjoint shards.
+    pub fn shard<I: Iterator>(&self, items: I) -> impl Iterator<Item = I::Item> {
+        items.skip(self.id).step_by(self.num_workers.max(1))
+    }
+}
+
+/// A dataset read from start to end.
+pub trait IterableDataset {
+    type Item;
+
+    /// The samples of one reader for one epoch. With several readers, each must produce a
+    /// different part of the data, usually by passing the whole stream through
+    /// [`WorkerInfo::shard`], or by opening only the files that belong to its shard.
+    fn iter(&self, worker: WorkerInfo) -> Box<dyn Iterator<Item = MlResult<Self::Item>> + '_>;
+}
+
+impl<D: IterableDataset + ?Sized> IterableDataset for Arc<D> {
+    type Item = D::Item;
+
+    fn iter(&self, worker: WorkerInfo) -> Box<dyn Iterator<Item = MlResult<Self::Item>> + '_> {
+        (**self).iter(worker)
+    }
+}
+
+/// The lines of a text file, each turned into a sample by `parse`. The file is read
+/// sequentially, never held in memory as a whole, and sharded by line number.
+#[cfg(feature = "fs")]
+pub struct Lines<F> {
+    path: PathBuf,
+    parse: F,
+    skip: usize,
+}
+
+#[cfg(feature = "fs")]
+impl<F> Lines<F> {
+    pub fn new<P, T>(path: P, parse: F) -> Self
+    where
+        P: AsRef<Path>,
+        F: Fn(&str) -> MlResult<T>,
+    {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            parse,
+            skip: 0,
+        }
+    }
+
+    /// Leaves out the first `lines` lines, such as a header.
+    pub fn skip(mut self, lines: usize) -> Self {
+        self.skip = lines;
+        self
+    }
+}
+
+#[cfg(feature = "fs")]
+impl<F, T> IterableDataset for Lines<F>
+where
+    F: Fn(&str) -> MlResult<T>,
+    T: 'static,
+{
+    type Item = T;
+
+    fn iter(&self, worker: WorkerInfo) -> Box<dyn Iterator<Item = MlResult<T>> + '_> {
+        let path = &self.path;
+        let file = match std::fs::File::open(path) {
+            Ok(file) => file,
+            Err(e) => {
+                let error = format!("Failed to open {}: {}", path.display(), e);
+                return Box::new(std::iter::once(Err(error.into())));
+            }
+        };
+        let lines = BufReader::new(file).lines().skip(self.skip);
+        Box::new(worker.shard(lines).map(move |line| match line {
+            Ok(line) => (self.parse)(&line),
+            Err(e) => Err(format!("Failed to read {}: {}", path.display(), e).into()),
+        }))
+    }
+}
+
+/// Reads an [`IterableDataset`] in batches.
+///
+/// Each call to [`StreamLoader::iter`] is one epoch. With [`StreamLoader::num_workers`]
+/// above zero, each worker reads and batches its own shard, up to
+/// [`StreamLoader::prefetch`] batches ahead, and batches are taken from the workers in turn
+/// so an epoch comes out in the same order every time. Every worker's last batch may be
+/// smaller than the rest unless [`StreamLoader::drop_last`] is set.
+pub struct StreamLoader<D: IterableDataset, B> {
+    dataset: Arc<D>,
+    collate: Arc<CollateFn<D::Item, B>>,
+    batch_size: usize,
+    drop_last: bool,
+    shuffle_buffer: usize,
+    seed: u64,
+    epoch: AtomicU64,
+    // Only read when there are threads to hand work to
+    #[cfg_attr(not(feature = "threads"), allow(dead_code))]
+    num_workers: usize,
+    #[cfg_attr(not(feature = "threads"), allow(dead_code))]
+    prefetch: usize,
+}
+
+impl<D> StreamLoader<D, <D::Item as Collate>::Batch>
+where
+    D: IterableDataset,
+    D::Item: Collate + 'static,
+{
+    /// Batches of `batch_size` samples, collated by the samples' [`Collate`] impl, read on
+    /// the calling thread.
+    pub fn new(dataset: D, batch_size: usize) -> Self {
+        Self::with_collate(dataset, batch_size, <D::Item as Collate>::collate)
+    }
+}
+
+impl<D: IterableDataset, B> StreamLoader<D, B> {
+    /// Like [`StreamLoader::new`], with batches put together by `collate`.
+    pub fn with_collate(
+        dataset: D,
+        batch_size: usize,
+        collate: impl Fn(Vec<D::Item>) -> MlResult<B> + Send + Sync + 'static,
+    ) -> Self {
+        let seed = crate::nn::random::next_seed();
+        Self {
+            dataset: Arc::new(dataset),
+            collate: Arc::new(collate),
+            batch_size: batch_size.max(1),
+            drop_last: false,
+            shuffle_buffer: 0,
+            seed,
+            epoch: AtomicU64::new(0),
+            num_workers: 0,
+            prefetch: 2,
+        }
+    }
+
+    /// Leaves out batches smaller than the rest.
+    pub fn drop_last(mut self, drop_last: bool) -> Self {
+        self.drop_last = drop_last;
+        self
+    }
+
+    /// Shuffles samples through a buffer of `samples` on each reader: every sample taken is
+    /// a random one of the buffer, whose place the next sample of the stream fills. The
+    /// bigger the buffer the closer to a full shuffle; zero, the default, keeps stream order.
+    pub fn shuffle_buffer(mut self, samples: usize) -> Self {
+        self.shuffle_buffer = samples;
+        self
+    }
+
+    /// Seeds the shuffle buffer and [`WorkerInfo::seed`], which otherwise draw their seed
+    /// from the global generator [`seed_all`](crate::seed_all) seeds.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Reads the stream on `workers` background threads, each producing one shard; zero,
+    /// the default, reads it on the calling thread as each batch is asked for. Without the
+    /// `threads` feature the stream is always read on the calling thread.
+    pub fn num_workers(mut self, workers: usize) -> Self {
+        self.num_workers = workers;
+        self
+    }
+
+    /// How many batches each worker may have ready ahead of the one being used. Defaults
+    /// to 2.
+    pub fn prefetch(mut self, batches: usize) -> Self {
+        self.prefetch = batches.max(1);
+        self
+    }
+
+    /// Sets the epoch the next [`StreamLoader::iter`] reads; each call to `iter` moves on to
+    /// the following epoch.
+    pub fn set_epoch(&self, epoch: u64) {
+        self.epoch.store(epoch, Ordering::Relaxed);
+    }
+
+    pub fn dataset(&self) -> &D {
+        &self.dataset
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    fn reader(&self, id: usize, num_workers: usize, epoch: u64) -> Reader<D, B> {
+        let seed = (self.seed ^ epoch.wrapping_mul(0x9e37_79b9_7f4a_7c15))
+            .wrapping_add((id as u64).wrapping_mul(0xd6e8_feb8_6659_fd93));
+        Reader {
+            collate: Arc::clone(&self.collate),
+            batch_size: self.batch_size,
+            drop_last: self.drop_last,
+            shuffle_buffer: self.shuffle_buffer,
+            worker: WorkerInfo {
+                id,
+                num_workers,
+                epoch,
+                seed,
+            },
+        }
+    }
+}
+
+impl<D, B> StreamLoader<D, B>
+where
+    D: IterableDataset + Send + Sync + 'static,
+    B: Send + 'static,
+{
+    /// One epoch of batches.
+    pub fn iter(&self) -> StreamBatches<'_, B> {
+        let epoch = self.epoch.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "threads")]
+        if self.num_workers > 0 {
+            return self.spawn_workers(epoch);
+        }
+        let reader = self.reader(0, 1, epoch);
+        StreamBatches {
+            source: StreamSource::Inline(reader.batches(&*self.dataset)),
+        }
+    }
+
+    #[cfg(feature = "threads")]
+    fn spawn_workers(&self, epoch: u64) -> StreamBatches<'_, B> {
+        let mut receivers = Vec::with_capacity(self.num_workers);
+        let mut workers = Vec::with_capacity(self.num_workers);
+        for id in 0..self.num_workers {
+            let (sender, receiver) = sync_channel(self.prefetch);
+            let reader = self.reader(id, self.num_workers, epoch);
+            let dataset = Arc::clone(&self.dataset);
+            workers.push(std::thread::spawn(move || reader.run(&*dataset, sender)));
+            receivers.push(receiver);
+        }
+        StreamBatches {
+            source: StreamSource::Workers {
+                receivers,
+                next: 0,
+                workers,
+            },
+        }
+    }
+}
+
+// How one reader of one epoch turns samples into batches
+struct Reader<D: IterableDataset, B> {
+    collate: Arc<CollateFn<D::Item, B>>,
+    batch_size: usize,
+    drop_last: bool,
+    shuffle_buffer: usize,
+    worker: WorkerInfo,
+}
+
+impl<D: IterableDataset, B> Reader<D, B> {
+    fn batches<'a>(self, dataset: &'a D) -> Box<dyn Iterator<Item = MlResult<B>> + 'a>
+    where
+        B: 'a,
+    {
+        let mut samples = dataset.iter(self.worker);
+        if self.shuffle_buffer > 1 {
+            samples = Box::new(shuffled(samples, self.shuffle_buffer, self.worker.seed));
+        }
+        Box::new(std::iter::from_fn(move || {
+            let mut items = Vec::with_capacity(self.batch_size);
+            for sample in samples.by_ref().take(self.batch_size) {
+                match sample {
+                    Ok(item) => items.push(item),
+                    // The samples read so far go with the bad one
+                    Err(e) => return Some(Err(e)),
+                }
+            }
+            if items.is_empty() || (self.drop_last && items.len() < self.batch_size) {
+                return None;
+            }
+            Some((self.collate)(items))
+        }))
+    }
+
+    // Sends the reader's batches until they run out or the loader stops listening
+    #[cfg(feature = "threads")]
+    fn run(self, dataset: &D, sender: SyncSender<MlResult<B>>) {
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            for batch in self.batches(dataset) {
+                if sender.send(batch).is_err() {
+                    return;
+                }
+            }
+        }));
+        if result.is_err() {
+            let _ = sender.send(Err("Stream loader worker panicked".into()));
+        }
+    }
+}
+
+// Yields a random sample of a buffer of `capacity`, refilling it from `samples`
+fn shuffled<'a, T: 'a>(
+    mut samples: Box<dyn Iterator<Item = T> + 'a>,
+    capacity: usize,
+    seed: u64,
+) -> impl Iterator<Item = T> + 'a {
+    let mut rng = SimpleRng::new(seed);
+    let mut buffer: Vec<T> = samples.by_ref().take(capacity).collect();
+    std::iter::from_fn(move || {
+        if buffer.is_empty() {
+            return None;
+        }
+        let index = rng.gen_index(buffer.len());
+        match samples.next() {
+            Some(sample) => Some(std::mem::replace(&mut buffer[index], sample)),
+            None => Some(buffer.swap_remove(index)),
+        }
+    })
+}
+
+/// The batches of one epoch of a [`StreamLoader`]. Dropping it early stops the loader's
+/// workers.
+pub struct StreamBatches<'a, B> {
+    source: StreamSource<'a, B>,
+}
+
+enum StreamSource<'a, B> {
+    Inline(Box<dyn Iterator<Item = MlResult<B>> + 'a>),
+    #[cfg(feature = "threads")]
+    Workers {
+        // A worker's receiver is removed once it has sent its last batch
+        receivers: Vec<Receiver<MlResult<B>>>,
+        next: usize,
+        workers: Vec<JoinHandle<()>>,
+    },
+}
+
+impl<B> Iterator for StreamBatches<'_, B> {
+    type Item = MlResult<B>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.source {
+            StreamSource::Inline(batches) => batches.next(),
+            #[cfg(feature = "threads")]
+            StreamSource::Workers {
+                receivers, next, ..
+            } => {
+                while !receivers.is_empty() {
+                    let worker = *next % receivers.len();
+                    match receivers[worker].recv() {
+                        Ok(batch) => {
+                            *next = worker + 1;
+                            return Some(batch);
+                        }
+                        Err(_) => {
+                            receivers.remove(worker);
+                            *next = worker;
+                        }
+                    }
+                }
+                None
+            }
+        }
+    }
+}
+
+#[cfg(feature = "threads")]
+impl<B> Drop for StreamBatches<'_, B> {
+    fn drop(&mut self) {
+        if let StreamSource::Workers {
+            receivers, workers, ..
+        } = &mut self.source
+        {
+            // Workers waiting to send give up once their receiver is gone
+            receivers.clear();
+            for worker in workers.drain(..) {
+                let _ = worker.join();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The numbers below `len`, failing at `bad`
+    struct Counter {
+        len: usize,
+        bad: Option<usize>,
+    }
+
+    impl IterableDataset for Counter {
+        type Item = f32;
+
+        fn iter(&self, worker: WorkerInfo) -> Box<dyn Iterator<Item = MlResult<f32>> + '_> {
+            Box::new(worker.shard(0..self.len).map(move |i| {
+                if Some(i) == self.bad {
+                    return Err("bad record".into());
+                }
+                Ok(i as f32)
+            }))
+        }
+    }
+
+    fn collect<D>(loader: &StreamLoader<D, crate::data::HostTensor>) -> MlResult<Vec<Vec<f32>>>
+    where
+        D: IterableDataset + Send + Sync + 'static,
+    {
+        loader.iter().map(|batch| Ok(batch?.into_data())).collect()
+    }
+
+    #[test]
+    fn test_stream_loader_shards() -> MlResult<()> {
+        let inline = StreamLoader::new(Counter { len: 7, bad: None }, 3);
+        assert_eq!(
+            collect(&inline)?,
+            [vec![0.0, 1.0, 2.0], vec![3.0, 4.0, 5.0], vec![6.0]]
+        );
+
+        // Two workers take alternate samples and their batches alternate
+        let sharded = StreamLoader::new(Counter { len: 11, bad: None }, 2).num_workers(2);
+        let batches = collect(&sharded)?;
+        assert_eq!(
+            batches[..3],
+            [vec![0.0, 2.0], vec![1.0, 3.0], vec![4.0, 6.0]]
+        );
+        let mut seen: Vec<f32> = batches.concat();
+        seen.sort_by(f32::total_cmp);
+        assert_eq!(seen, (0..11).map(|i| i as f32).collect::<Vec<_>>());
+        let dropped = StreamLoader::new(Counter { len: 11, bad: None }, 2)
+            .num_workers(2)
+            .drop_last(true);
+        assert_eq!(collect(&dropped)?.len(), 5);
+
+        // Buffered shuffling is a permutation that repeats for a seed
+        let shuffling = || {
+            StreamLoader::new(Counter { len: 20, bad: None }, 5)
+                .shuffle_buffer(8)
+                .num_workers(2)
+                .seed(9)
+        };
+        let shuffled = collect(&shuffling())?.concat();
+        assert_eq!(collect(&shuffling())?.concat(), shuffled);
+        let unshuffled = StreamLoader::new(Counter { len: 20, bad: None }, 5).num_workers(2);
+        assert_ne!(shuffled, collect(&unshuffled)?.concat());
+
+        // A bad record fails its batch only, and stopping early doesn't hang
+        let failing = StreamLoader::new(
+            Counter {
+                len: 9,
+                bad: Some(4),
+            },
+            3,
+        )
+        .num_workers(3);
+        let results: Vec<_> = failing.iter().collect();
+        assert_eq!(results.iter().filter(|r| r.is_err()).count(), 1);
+        assert_eq!(failing.iter().take(1).count(), 1);
+
+        let path = std::env::temp_dir().join("cetana_test_stream_lines.txt");
+        std::fs::write(&path, "length\n1\n22\n333\n").expect("Failed to write test file");
+        let lines = Lines::new(&path, |line| Ok(line.len() as f32)).skip(1);
+        let loader = StreamLoader::new(lines, 2).num_workers(2);
+        assert_eq!(collect(&loader)?, [vec![1.0, 3.0], vec![2.0]]);
+        std::fs::remove_file(&path).expect("Failed to remove test file");
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+//! Column encodings shared by the tabular datasets.
+
+// Only the datasets behind the `fs`, `arrow` and `polars` features use the helpers
+#![cfg_attr(
+    not(any(feature = "fs", feature = "arrow", feature = "polars")),
+    allow(dead_code)
+)]
+
+/// How numeric feature columns are rescaled. Categorical columns are never rescaled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Normalization {
+    #[default]
+    None,
+    /// Zero mean and unit variance.
+    Standard,
+    /// Into `[0, 1]`.
+    MinMax,
+}
+
+/// How a selected column's values become numbers.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnEncoding {
+    /// Read as an f32, then rescaled by `offset` and `scale` as `(x - offset) / scale`.
+    Numeric { offset: f32, scale: f32 },
+    /// One of `categories`: a one-hot vector as a feature, the category's index as a target.
+    Categorical { categories: Vec<String> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Column {
+    pub(crate) name: String,
+    pub(crate) encoding: ColumnEncoding,
+}
+
+impl Column {
+    pub(crate) fn width(&self, as_target: bool) -> usize {
+        match &self.encoding {
+            ColumnEncoding::Categorical { categories } if !as_target => categories.len(),
+            _ => 1,
+        }
+    }
+}
+
+// The name of each feature: the column name for numeric columns, and `column=category` for
+// each one-hot position of a categorical column
+pub(crate) fn feature_names(columns: &[Column]) -> Vec<String> {
+    columns
+        .iter()
+        .flat_map(|column| match &column.encoding {
+            ColumnEncoding::Numeric { .. } => vec![column.name.clone()],
+            ColumnEncoding::Categorical { categories } => categories
+                .iter()
+                .map(|category| format!("{}={}", column.name, category))
+                .collect(),
+        })
+        .collect()
+}
+
+// The offset and scale that give `values` the requested normalization
+pub(crate) fn statistics(values: &[f32], normalization: Normalization) -> (f32, f32) {
+    if values.is_empty() {
+        return (0.0, 1.0);
+    }
+    let (offset, scale) = match normalization {
+        Normalization::None => return (0.0, 1.0),
+        Normalization::Standard => {
+            let n = values.len() as f32;
+            let mean = values.iter().sum::<f32>() / n;
+            let variance = values.iter().map(|x| (x - mean).powi(2)).sum::<f32>() / n;
+            (mean, variance.sqrt())
+        }
+        Normalization::MinMax => {
+            let min = values.iter().copied().fold(f32::INFINITY, f32::min);
+            let max = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+            (min, max - min)
+        }
+    };
+    // A constant column stays put rather than dividing by zero
+    (offset, if scale > 0.0 { scale } else { 1.0 })
+}
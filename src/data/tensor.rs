@@ -0,0 +1,77 @@
+//! Datasets of data already in memory.
+
+use super::{Dataset, HostTensor};
+use crate::tensor::{Tensor, TensorError};
+use crate::MlResult;
+
+/// Inputs and targets held as two tensors whose first axis runs over the samples. Sample
+/// `i` is row `i` of each, without the leading axis: a `[samples, features]` input gives
+/// `[features]` samples and a `[samples]` target gives scalars of shape `[]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TensorDataset {
+    inputs: HostTensor,
+    targets: HostTensor,
+}
+
+impl TensorDataset {
+    /// A dataset of copies of `inputs` and `targets`, which must have the same number of
+    /// rows.
+    pub fn new(inputs: &Tensor, targets: &Tensor) -> MlResult<Self> {
+        Self::from_host(
+            HostTensor::from_tensor(inputs),
+            HostTensor::from_tensor(targets),
+        )
+    }
+
+    pub fn from_host(inputs: HostTensor, targets: HostTensor) -> MlResult<Self> {
+        let rows = |tensor: &HostTensor| tensor.shape().first().copied();
+        if rows(&inputs).is_none() || rows(&inputs) != rows(&targets) {
+            return Err(TensorError::InvalidOperation {
+                op: "tensor_dataset",
+                reason: format!(
+                    "inputs of shape {:?} and targets of shape {:?} need the same number of rows",
+                    inputs.shape(),
+                    targets.shape()
+                ),
+            }
+            .into());
+        }
+        Ok(Self { inputs, targets })
+    }
+
+    pub fn inputs(&self) -> &HostTensor {
+        &self.inputs
+    }
+
+    pub fn targets(&self) -> &HostTensor {
+        &self.targets
+    }
+}
+
+// Row `index` of `tensor`, without the leading axis
+fn row(tensor: &HostTensor, index: usize) -> MlResult<HostTensor> {
+    let shape = &tensor.shape()[1..];
+    let width: usize = shape.iter().product();
+    let start = index * width;
+    HostTensor::new(tensor.data()[start..start + width].to_vec(), shape)
+}
+
+impl Dataset for TensorDataset {
+    type Item = (HostTensor, HostTensor);
+
+    fn len(&self) -> usize {
+        self.inputs.shape()[0]
+    }
+
+    fn get(&self, index: usize) -> MlResult<Self::Item> {
+        if index >= self.len() {
+            return Err(format!(
+                "Sample {} is out of range for {} samples",
+                index,
+                self.len()
+            )
+            .into());
+        }
+        Ok((row(&self.inputs, index)?, row(&self.targets, index)?))
+    }
+}
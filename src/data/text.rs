@@ -0,0 +1,513 @@
+//! Text classification datasets: tokenization, vocabularies and fixed-length encoding.
+//!
+//! ```ignore
+//! let tokenizer = BpeTokenizer::train(&texts, 8000);
+//! let vocab = Vocab::build(&tokenizer, &texts, 2, None);
+//! let encoder = TextEncoder::new(tokenizer, vocab, 128);
+//! let train = TextDataset::new(&encoder, &texts, &labels)?;
+//! // Batches of ((token ids, attention mask), labels), each [batch, 128]
+//! let loader = DataLoader::new(train, 32).shuffle(true);
+//! ```
+//!
+//! Token ids are stored in f32 [`HostTensor`]s like class indices are, holding whole
+//! numbers that index an embedding table.
+
+use std::collections::{BTreeMap, HashMap};
+
+use super::{Dataset, HostTensor};
+use crate::MlResult;
+
+/// Splits text into tokens.
+pub trait Tokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String>;
+}
+
+/// Splits text at whitespace, optionally lowercasing it and making each punctuation
+/// character a token of its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WhitespaceTokenizer {
+    lowercase: bool,
+    split_punctuation: bool,
+}
+
+impl WhitespaceTokenizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn lowercase(mut self, lowercase: bool) -> Self {
+        self.lowercase = lowercase;
+        self
+    }
+
+    /// Splits `"end."` into `"end"` and `"."`.
+    pub fn split_punctuation(mut self, split: bool) -> Self {
+        self.split_punctuation = split;
+        self
+    }
+}
+
+impl Tokenizer for WhitespaceTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        let text = if self.lowercase {
+            text.to_lowercase()
+        } else {
+            text.to_string()
+        };
+        if !self.split_punctuation {
+            return text.split_whitespace().map(str::to_string).collect();
+        }
+
+        let mut tokens = Vec::new();
+        for word in text.split_whitespace() {
+            let mut current = String::new();
+            for c in word.chars() {
+                if c.is_ascii_punctuation() {
+                    if !current.is_empty() {
+                        tokens.push(std::mem::take(&mut current));
+                    }
+                    tokens.push(c.to_string());
+                } else {
+                    current.push(c);
+                }
+            }
+            if !current.is_empty() {
+                tokens.push(current);
+            }
+        }
+        tokens
+    }
+}
+
+/// Byte-pair encoding: words are split into characters, then adjacent symbols are merged in
+/// the order the merges were learned, so frequent words stay whole and rare ones break into
+/// known pieces. The last symbol of a word carries an [`BpeTokenizer::END_OF_WORD`] marker,
+/// so `"low"` alone and `"low"` inside `"lower"` are different tokens.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BpeTokenizer {
+    merges: Vec<(String, String)>,
+    ranks: HashMap<(String, String), usize>,
+    lowercase: bool,
+}
+
+impl BpeTokenizer {
+    pub const END_OF_WORD: &'static str = "</w>";
+
+    /// A tokenizer applying `merges`, earliest first.
+    pub fn from_merges(merges: Vec<(String, String)>) -> Self {
+        let ranks = merges
+            .iter()
+            .cloned()
+            .enumerate()
+            .map(|(rank, pair)| (pair, rank))
+            .collect();
+        Self {
+            merges,
+            ranks,
+            lowercase: false,
+        }
+    }
+
+    /// Learns merges from `texts` until there are `vocab_size` distinct symbols, counting the
+    /// single characters, or no pair of symbols occurs more than once. Each step merges the
+    /// most frequent adjacent pair, the alphabetically first on ties.
+    pub fn train<S: AsRef<str>>(texts: &[S], vocab_size: usize) -> Self {
+        let mut words: BTreeMap<Vec<String>, usize> = BTreeMap::new();
+        for text in texts {
+            for word in text.as_ref().split_whitespace() {
+                *words.entry(symbols(word)).or_default() += 1;
+            }
+        }
+        let mut alphabet: Vec<&String> = words.keys().flatten().collect();
+        alphabet.sort();
+        alphabet.dedup();
+        let mut size = alphabet.len();
+
+        let mut merges = Vec::new();
+        while size < vocab_size {
+            let mut pairs: BTreeMap<(&str, &str), usize> = BTreeMap::new();
+            for (word, count) in &words {
+                for pair in word.windows(2) {
+                    *pairs.entry((&pair[0], &pair[1])).or_default() += count;
+                }
+            }
+            // The first of the most frequent pairs in alphabetical order
+            let best = pairs
+                .into_iter()
+                .rev()
+                .max_by_key(|&(_, count)| count)
+                .filter(|&(_, count)| count > 1);
+            let Some(((left, right), _)) = best else {
+                break;
+            };
+            let pair = (left.to_string(), right.to_string());
+            words = words
+                .into_iter()
+                .map(|(word, count)| (merge(word, &pair), count))
+                .fold(BTreeMap::new(), |mut words, (word, count)| {
+                    *words.entry(word).or_default() += count;
+                    words
+                });
+            merges.push(pair);
+            size += 1;
+        }
+        Self::from_merges(merges)
+    }
+
+    /// Lowercases text before splitting it.
+    pub fn lowercase(mut self, lowercase: bool) -> Self {
+        self.lowercase = lowercase;
+        self
+    }
+
+    /// The learned merges, earliest first.
+    pub fn merges(&self) -> &[(String, String)] {
+        &self.merges
+    }
+
+    fn encode_word(&self, word: &str) -> Vec<String> {
+        let mut word = symbols(word);
+        loop {
+            let best = word
+                .windows(2)
+                .filter_map(|pair| self.ranks.get(&(pair[0].clone(), pair[1].clone())))
+                .min();
+            let Some(&rank) = best else {
+                return word;
+            };
+            word = merge(word, &self.merges[rank]);
+        }
+    }
+}
+
+impl Tokenizer for BpeTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        let text = if self.lowercase {
+            text.to_lowercase()
+        } else {
+            text.to_string()
+        };
+        text.split_whitespace()
+            .flat_map(|word| self.encode_word(word))
+            .collect()
+    }
+}
+
+// A word's characters, the last one marked as ending it
+fn symbols(word: &str) -> Vec<String> {
+    let mut symbols: Vec<String> = word.chars().map(String::from).collect();
+    if let Some(last) = symbols.last_mut() {
+        last.push_str(BpeTokenizer::END_OF_WORD);
+    }
+    symbols
+}
+
+// Joins every non-overlapping occurrence of `pair`, left to right
+fn merge(word: Vec<String>, pair: &(String, String)) -> Vec<String> {
+    let mut merged = Vec::with_capacity(word.len());
+    let mut symbols = word.into_iter().peekable();
+    while let Some(symbol) = symbols.next() {
+        if symbol == pair.0 && symbols.peek() == Some(&pair.1) {
+            symbols.next();
+            merged.push(symbol + &pair.1);
+        } else {
+            merged.push(symbol);
+        }
+    }
+    merged
+}
+
+/// A mapping between tokens and ids. Id 0 is the padding token [`Vocab::PAD`] and id 1 the
+/// [`Vocab::UNK`] token that stands in for tokens outside the vocabulary.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vocab {
+    tokens: Vec<String>,
+    ids: HashMap<String, usize>,
+}
+
+impl Vocab {
+    pub const PAD: &'static str = "<pad>";
+    pub const UNK: &'static str = "<unk>";
+
+    /// A vocabulary of the special tokens followed by `tokens`, in order. Repeated tokens
+    /// keep their first id.
+    pub fn from_tokens<I, S>(tokens: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut vocab = Self {
+            tokens: Vec::new(),
+            ids: HashMap::new(),
+        };
+        let specials = [Self::PAD, Self::UNK].map(String::from);
+        for token in specials
+            .into_iter()
+            .chain(tokens.into_iter().map(Into::into))
+        {
+            if !vocab.ids.contains_key(&token) {
+                vocab.ids.insert(token.clone(), vocab.tokens.len());
+                vocab.tokens.push(token);
+            }
+        }
+        vocab
+    }
+
+    /// The tokens `tokenizer` finds in `texts` at least `min_frequency` times, most frequent
+    /// first, keeping at most `max_size` of them besides the special tokens.
+    pub fn build<T, S>(
+        tokenizer: &T,
+        texts: &[S],
+        min_frequency: usize,
+        max_size: Option<usize>,
+    ) -> Self
+    where
+        T: Tokenizer + ?Sized,
+        S: AsRef<str>,
+    {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for text in texts {
+            for token in tokenizer.tokenize(text.as_ref()) {
+                *counts.entry(token).or_default() += 1;
+            }
+        }
+        let mut counts: Vec<(String, usize)> = counts
+            .into_iter()
+            .filter(|&(_, count)| count >= min_frequency)
+            .collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        counts.truncate(max_size.unwrap_or(usize::MAX));
+        Self::from_tokens(counts.into_iter().map(|(token, _)| token))
+    }
+
+    /// The number of tokens, including the special ones.
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    pub fn pad_id(&self) -> usize {
+        self.ids[Self::PAD]
+    }
+
+    pub fn unk_id(&self) -> usize {
+        self.ids[Self::UNK]
+    }
+
+    /// The id of `token`, or of [`Vocab::UNK`] when it isn't in the vocabulary.
+    pub fn id(&self, token: &str) -> usize {
+        self.ids
+            .get(token)
+            .copied()
+            .unwrap_or_else(|| self.unk_id())
+    }
+
+    pub fn token(&self, id: usize) -> Option<&str> {
+        self.tokens.get(id).map(String::as_str)
+    }
+
+    pub fn tokens(&self) -> &[String] {
+        &self.tokens
+    }
+
+    pub fn encode<S: AsRef<str>>(&self, tokens: &[S]) -> Vec<usize> {
+        tokens.iter().map(|token| self.id(token.as_ref())).collect()
+    }
+
+    /// The tokens of `ids`, leaving out padding.
+    pub fn decode(&self, ids: &[usize]) -> Vec<&str> {
+        let pad = self.pad_id();
+        ids.iter()
+            .filter(|&&id| id != pad)
+            .map(|&id| self.token(id).unwrap_or(Self::UNK))
+            .collect()
+    }
+}
+
+/// A text's token ids, cut or padded to the encoder's length, and its attention mask: 1 for
+/// a real token and 0 for padding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Encoding {
+    pub ids: Vec<usize>,
+    pub attention_mask: Vec<u8>,
+}
+
+impl Encoding {
+    /// The ids and the mask as `[length]` tensors.
+    pub fn to_host_tensors(&self) -> MlResult<(HostTensor, HostTensor)> {
+        let len = self.ids.len();
+        Ok((
+            HostTensor::new(self.ids.iter().map(|&id| id as f32).collect(), &[len])?,
+            HostTensor::new(
+                self.attention_mask.iter().map(|&m| m as f32).collect(),
+                &[len],
+            )?,
+        ))
+    }
+}
+
+/// Turns text into fixed-length token ids with a tokenizer and a vocabulary. Longer texts
+/// keep their first `max_len` tokens and shorter ones are padded at the end.
+#[derive(Debug, Clone)]
+pub struct TextEncoder<T> {
+    tokenizer: T,
+    vocab: Vocab,
+    max_len: usize,
+}
+
+impl<T: Tokenizer> TextEncoder<T> {
+    pub fn new(tokenizer: T, vocab: Vocab, max_len: usize) -> Self {
+        Self {
+            tokenizer,
+            vocab,
+            max_len: max_len.max(1),
+        }
+    }
+
+    pub fn tokenizer(&self) -> &T {
+        &self.tokenizer
+    }
+
+    pub fn vocab(&self) -> &Vocab {
+        &self.vocab
+    }
+
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    pub fn encode(&self, text: &str) -> Encoding {
+        let mut ids = self.vocab.encode(&self.tokenizer.tokenize(text));
+        ids.truncate(self.max_len);
+        let mut attention_mask = vec![1; ids.len()];
+        attention_mask.resize(self.max_len, 0);
+        ids.resize(self.max_len, self.vocab.pad_id());
+        Encoding {
+            ids,
+            attention_mask,
+        }
+    }
+
+    /// The ids and attention masks of `texts` as two `[texts, max_len]` tensors.
+    pub fn encode_batch<S: AsRef<str>>(&self, texts: &[S]) -> MlResult<(HostTensor, HostTensor)> {
+        let (ids, masks): (Vec<_>, Vec<_>) = texts
+            .iter()
+            .map(|text| self.encode(text.as_ref()).to_host_tensors())
+            .collect::<MlResult<Vec<_>>>()?
+            .into_iter()
+            .unzip();
+        Ok((HostTensor::stack(&ids)?, HostTensor::stack(&masks)?))
+    }
+}
+
+/// Labelled texts, encoded up front.
+///
+/// Each sample is `((ids, attention_mask), label)` with `[max_len]` ids and mask, so the
+/// default collation batches them into `[batch, max_len]` tensors and a `[batch]` label
+/// tensor.
+#[derive(Debug, Clone)]
+pub struct TextDataset {
+    encodings: Vec<Encoding>,
+    labels: Vec<usize>,
+}
+
+impl TextDataset {
+    pub fn new<T: Tokenizer, S: AsRef<str>>(
+        encoder: &TextEncoder<T>,
+        texts: &[S],
+        labels: &[usize],
+    ) -> MlResult<Self> {
+        if texts.len() != labels.len() {
+            return Err(format!("{} texts but {} labels", texts.len(), labels.len()).into());
+        }
+        Ok(Self {
+            encodings: texts
+                .iter()
+                .map(|text| encoder.encode(text.as_ref()))
+                .collect(),
+            labels: labels.to_vec(),
+        })
+    }
+
+    pub fn encodings(&self) -> &[Encoding] {
+        &self.encodings
+    }
+
+    pub fn labels(&self) -> &[usize] {
+        &self.labels
+    }
+}
+
+impl Dataset for TextDataset {
+    type Item = ((HostTensor, HostTensor), usize);
+
+    fn len(&self) -> usize {
+        self.encodings.len()
+    }
+
+    fn get(&self, index: usize) -> MlResult<Self::Item> {
+        let encoding = self.encodings.get(index).ok_or_else(|| {
+            format!(
+                "Text {} is out of range for {} texts",
+                index,
+                self.encodings.len()
+            )
+        })?;
+        Ok((encoding.to_host_tensors()?, self.labels[index]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::DataLoader;
+
+    #[test]
+    fn test_text_pipeline() -> MlResult<()> {
+        let words = WhitespaceTokenizer::new()
+            .lowercase(true)
+            .split_punctuation(true);
+        assert_eq!(
+            words.tokenize("Hello, world!"),
+            ["hello", ",", "world", "!"]
+        );
+
+        let texts = ["the cat sat", "the dog sat down", "a cat"];
+        let vocab = Vocab::build(&words, &texts, 2, None);
+        assert_eq!(vocab.tokens(), ["<pad>", "<unk>", "cat", "sat", "the"]);
+        assert_eq!(vocab.encode(&["the", "bird"]), [4, 1]);
+
+        let encoder = TextEncoder::new(words, vocab, 3);
+        let encoding = encoder.encode("A cat");
+        assert_eq!(encoding.ids, [1, 2, 0]);
+        assert_eq!(encoding.attention_mask, [1, 1, 0]);
+        assert_eq!(encoder.encode("the cat sat down").ids, [4, 2, 3]);
+        assert_eq!(encoder.vocab().decode(&encoding.ids), ["<unk>", "cat"]);
+
+        let dataset = TextDataset::new(&encoder, &texts, &[0, 1, 0])?;
+        let loader = DataLoader::new(dataset, 2);
+        let ((ids, mask), labels) = loader.iter().next().unwrap()?;
+        assert_eq!(ids.shape(), &[2, 3]);
+        assert_eq!(ids.data(), &[4.0, 2.0, 3.0, 4.0, 1.0, 3.0]);
+        assert_eq!(mask.data(), &[1.0; 6]);
+        assert_eq!(labels.data(), &[0.0, 1.0]);
+
+        // Frequent words become single tokens, rare ones break into learned pieces
+        let corpus = ["low low low lower lower newest newest widest"];
+        let bpe = BpeTokenizer::train(&corpus, 16);
+        assert_eq!(bpe.merges()[0], ("l".to_string(), "o".to_string()));
+        assert_eq!(bpe.tokenize("low"), ["low</w>"]);
+        let pieces = bpe.tokenize("lowest");
+        assert!(pieces.len() > 1 && pieces.len() < 6);
+        assert_eq!(pieces.concat(), "lowest</w>");
+        let restored = BpeTokenizer::from_merges(bpe.merges().to_vec());
+        assert_eq!(
+            restored.tokenize("lower newest"),
+            bpe.tokenize("lower newest")
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,444 @@
+//! Preprocessing and random augmentation of samples.
+//!
+//! Transforms chain with [`Transform::then`] and attach to a dataset with
+//! [`Dataset::transform`], which applies them to each sample's input as it is read, on
+//! whichever [`DataLoader`](super::DataLoader) worker reads it.
+//!
+//! ```ignore
+//! let augment = RandomCrop::new(32, 32).padding(4)
+//!     .then(RandomHorizontalFlip::new(0.5))
+//!     .then(Normalize::new(&[0.49, 0.48, 0.45], &[0.25, 0.24, 0.26]));
+//! let train = Cifar::cifar10("data", Split::Train)?.transform(augment).seed(7);
+//! ```
+//!
+//! Random transforms draw from a [`SimpleRng`] seeded from the dataset's seed, the epoch and
+//! the sample's index, so a run augments identically however many workers read it, while
+//! each epoch sees new variations.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::{Dataset, HostTensor};
+use crate::nn::random::SimpleRng;
+use crate::tensor::{Tensor, TensorError};
+use crate::MlResult;
+
+/// A step of a sample preprocessing pipeline taking `In` to [`Transform::Output`].
+pub trait Transform<In> {
+    type Output;
+
+    /// Transforms `input`, drawing any randomness from `rng`.
+    fn apply(&self, input: In, rng: &mut SimpleRng) -> MlResult<Self::Output>;
+
+    /// This transform followed by `next`.
+    fn then<T: Transform<Self::Output>>(self, next: T) -> Compose<Self, T>
+    where
+        Self: Sized,
+    {
+        Compose(self, next)
+    }
+}
+
+/// Two transforms applied one after the other; see [`Transform::then`].
+#[derive(Debug, Clone)]
+pub struct Compose<A, B>(A, B);
+
+impl<In, A: Transform<In>, B: Transform<A::Output>> Transform<In> for Compose<A, B> {
+    type Output = B::Output;
+
+    fn apply(&self, input: In, rng: &mut SimpleRng) -> MlResult<B::Output> {
+        let middle = self.0.apply(input, rng)?;
+        self.1.apply(middle, rng)
+    }
+}
+
+/// Subtracts a per-channel mean and divides by a per-channel standard deviation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Normalize {
+    mean: Vec<f32>,
+    std: Vec<f32>,
+}
+
+impl Normalize {
+    pub fn new(mean: &[f32], std: &[f32]) -> Self {
+        Self {
+            mean: mean.to_vec(),
+            std: std.to_vec(),
+        }
+    }
+}
+
+impl Transform<HostTensor> for Normalize {
+    type Output = HostTensor;
+
+    fn apply(&self, input: HostTensor, _rng: &mut SimpleRng) -> MlResult<HostTensor> {
+        let [channels, h, w] = input.image_dims()?;
+        if self.mean.len() != channels || self.std.len() != channels {
+            return Err(TensorError::InvalidOperation {
+                op: "normalize",
+                reason: format!(
+                    "{} means and {} standard deviations for {} channels",
+                    self.mean.len(),
+                    self.std.len(),
+                    channels
+                ),
+            }
+            .into());
+        }
+        let shape = input.shape().to_vec();
+        let mut data = input.into_data();
+        for (c, plane) in data.chunks_exact_mut(h * w).enumerate() {
+            plane
+                .iter_mut()
+                .for_each(|x| *x = (*x - self.mean[c]) / self.std[c]);
+        }
+        HostTensor::new(data, &shape)
+    }
+}
+
+/// Resizes images to a fixed size; see [`HostTensor::resize`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Resize {
+    pub height: usize,
+    pub width: usize,
+}
+
+impl Transform<HostTensor> for Resize {
+    type Output = HostTensor;
+
+    fn apply(&self, input: HostTensor, _rng: &mut SimpleRng) -> MlResult<HostTensor> {
+        input.resize(self.height, self.width)
+    }
+}
+
+/// Crops the center of images; see [`HostTensor::center_crop`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CenterCrop {
+    pub height: usize,
+    pub width: usize,
+}
+
+impl Transform<HostTensor> for CenterCrop {
+    type Output = HostTensor;
+
+    fn apply(&self, input: HostTensor, _rng: &mut SimpleRng) -> MlResult<HostTensor> {
+        input.center_crop(self.height, self.width)
+    }
+}
+
+/// Crops a window at a random position, after optionally zero-padding every side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RandomCrop {
+    height: usize,
+    width: usize,
+    padding: usize,
+}
+
+impl RandomCrop {
+    pub fn new(height: usize, width: usize) -> Self {
+        Self {
+            height,
+            width,
+            padding: 0,
+        }
+    }
+
+    /// Pads each side of the image with `padding` zeros before cropping.
+    pub fn padding(mut self, padding: usize) -> Self {
+        self.padding = padding;
+        self
+    }
+}
+
+impl Transform<HostTensor> for RandomCrop {
+    type Output = HostTensor;
+
+    fn apply(&self, input: HostTensor, rng: &mut SimpleRng) -> MlResult<HostTensor> {
+        let input = if self.padding > 0 {
+            pad(&input, self.padding)?
+        } else {
+            input
+        };
+        let [_, h, w] = input.image_dims()?;
+        let top = rng.gen_index(h.saturating_sub(self.height) + 1);
+        let left = rng.gen_index(w.saturating_sub(self.width) + 1);
+        input.crop(top, left, self.height, self.width)
+    }
+}
+
+// Surrounds every channel of an image with `padding` zeros
+fn pad(image: &HostTensor, padding: usize) -> MlResult<HostTensor> {
+    let [channels, h, w] = image.image_dims()?;
+    let (ph, pw) = (h + 2 * padding, w + 2 * padding);
+    let mut data = vec![0.0; channels * ph * pw];
+    for (c, plane) in image.data().chunks_exact(h * w).enumerate() {
+        for (y, row) in plane.chunks_exact(w).enumerate() {
+            let start = c * ph * pw + (y + padding) * pw + padding;
+            data[start..start + w].copy_from_slice(row);
+        }
+    }
+    let mut shape = image.shape().to_vec();
+    let rank = shape.len();
+    shape[rank - 2..].copy_from_slice(&[ph, pw]);
+    HostTensor::new(data, &shape)
+}
+
+/// Mirrors images left to right with probability `p`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RandomHorizontalFlip {
+    p: f32,
+}
+
+impl RandomHorizontalFlip {
+    pub fn new(p: f32) -> Self {
+        Self { p }
+    }
+}
+
+impl Transform<HostTensor> for RandomHorizontalFlip {
+    type Output = HostTensor;
+
+    fn apply(&self, input: HostTensor, rng: &mut SimpleRng) -> MlResult<HostTensor> {
+        let [_, _, w] = input.image_dims()?;
+        if rng.next_f32() >= self.p {
+            return Ok(input);
+        }
+        let shape = input.shape().to_vec();
+        let mut data = input.into_data();
+        data.chunks_exact_mut(w.max(1)).for_each(<[f32]>::reverse);
+        HostTensor::new(data, &shape)
+    }
+}
+
+/// Randomly changes the brightness, contrast and saturation of images with values in
+/// `[0, 1]`. Each factor is drawn uniformly from `[1 - amount, 1 + amount]`; an amount of
+/// zero leaves that property alone. Saturation needs three channels and is skipped for
+/// others.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ColorJitter {
+    brightness: f32,
+    contrast: f32,
+    saturation: f32,
+}
+
+impl ColorJitter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn brightness(mut self, amount: f32) -> Self {
+        self.brightness = amount;
+        self
+    }
+
+    pub fn contrast(mut self, amount: f32) -> Self {
+        self.contrast = amount;
+        self
+    }
+
+    pub fn saturation(mut self, amount: f32) -> Self {
+        self.saturation = amount;
+        self
+    }
+}
+
+impl Transform<HostTensor> for ColorJitter {
+    type Output = HostTensor;
+
+    fn apply(&self, input: HostTensor, rng: &mut SimpleRng) -> MlResult<HostTensor> {
+        let [channels, h, w] = input.image_dims()?;
+        let mut factor = |amount: f32| {
+            (amount > 0.0).then(|| rng.gen_range((1.0 - amount).max(0.0), 1.0 + amount))
+        };
+        let (brightness, contrast, saturation) = (
+            factor(self.brightness),
+            factor(self.contrast),
+            factor(self.saturation),
+        );
+
+        let shape = input.shape().to_vec();
+        let mut data = input.into_data();
+        // Each adjustment blends the image with a reference: black, its mean gray level, and
+        // its own grayscale
+        if let Some(f) = brightness {
+            data.iter_mut().for_each(|x| *x *= f);
+        }
+        if let Some(f) = contrast {
+            let mean = grayscale(&data, channels, h * w).iter().sum::<f32>() / (h * w) as f32;
+            data.iter_mut().for_each(|x| *x = mean + f * (*x - mean));
+        }
+        if let (Some(f), 3) = (saturation, channels) {
+            let gray = grayscale(&data, channels, h * w);
+            for plane in data.chunks_exact_mut(h * w) {
+                for (x, g) in plane.iter_mut().zip(&gray) {
+                    *x = g + f * (*x - g);
+                }
+            }
+        }
+        data.iter_mut().for_each(|x| *x = x.clamp(0.0, 1.0));
+        HostTensor::new(data, &shape)
+    }
+}
+
+// The luma of each pixel, or the only channel of a grayscale image
+fn grayscale(data: &[f32], channels: usize, pixels: usize) -> Vec<f32> {
+    if channels != 3 {
+        return data[..pixels].to_vec();
+    }
+    (0..pixels)
+        .map(|i| 0.299 * data[i] + 0.587 * data[pixels + i] + 0.114 * data[2 * pixels + i])
+        .collect()
+}
+
+/// Turns a sample into a [`Tensor`] on the default device. Tensors stay on the thread that
+/// made them, so this ends a pipeline run on the training thread, such as one applied to
+/// whole batches, rather than one inside data loader workers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ToTensor;
+
+impl Transform<HostTensor> for ToTensor {
+    type Output = Tensor;
+
+    fn apply(&self, input: HostTensor, _rng: &mut SimpleRng) -> MlResult<Tensor> {
+        input.into_tensor()
+    }
+}
+
+/// A dataset with a transform applied to the input of each sample; see
+/// [`Dataset::transform`].
+#[derive(Debug)]
+pub struct Transformed<D, T> {
+    dataset: D,
+    transform: T,
+    seed: u64,
+    epoch: AtomicU64,
+}
+
+impl<D, T> Transformed<D, T> {
+    pub(crate) fn new(dataset: D, transform: T) -> Self {
+        let seed = crate::nn::random::next_seed();
+        Self {
+            dataset,
+            transform,
+            seed,
+            epoch: AtomicU64::new(0),
+        }
+    }
+
+    /// Seeds the random transforms, which otherwise draw their seed from the global
+    /// generator [`seed_all`](crate::seed_all) seeds.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn inner(&self) -> &D {
+        &self.dataset
+    }
+}
+
+impl<D, T, X, Y> Dataset for Transformed<D, T>
+where
+    D: Dataset<Item = (X, Y)>,
+    T: Transform<X>,
+{
+    type Item = (T::Output, Y);
+
+    fn len(&self) -> usize {
+        self.dataset.len()
+    }
+
+    fn get(&self, index: usize) -> MlResult<Self::Item> {
+        let (input, target) = self.dataset.get(index)?;
+        let epoch = self.epoch.load(Ordering::Relaxed);
+        let mut rng = SimpleRng::new(mix(self.seed, epoch, index as u64));
+        Ok((self.transform.apply(input, &mut rng)?, target))
+    }
+
+    fn set_epoch(&self, epoch: u64) {
+        self.epoch.store(epoch, Ordering::Relaxed);
+        self.dataset.set_epoch(epoch);
+    }
+}
+
+// Scrambles the three numbers into one seed, so nearby indices and epochs give unrelated
+// random streams (the splitmix64 finalizer)
+fn mix(seed: u64, epoch: u64, index: u64) -> u64 {
+    let mut z = seed
+        ^ epoch.wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        ^ index.wrapping_mul(0xd6e8_feb8_6659_fd93);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::DataLoader;
+
+    struct Ramps;
+
+    impl Dataset for Ramps {
+        type Item = (HostTensor, usize);
+
+        fn len(&self) -> usize {
+            8
+        }
+
+        fn get(&self, index: usize) -> MlResult<Self::Item> {
+            let data = (0..12).map(|i| (i + index) as f32 / 20.0).collect();
+            Ok((HostTensor::new(data, &[3, 2, 2])?, index))
+        }
+    }
+
+    #[test]
+    fn test_transform_pipeline() -> MlResult<()> {
+        let mut rng = SimpleRng::new(0);
+        let (image, _) = Ramps.get(0)?;
+
+        let flipped = RandomHorizontalFlip::new(1.0).apply(image.clone(), &mut rng)?;
+        assert_eq!(&flipped.data()[..4], &[0.05, 0.0, 0.15, 0.1]);
+        let normalized =
+            Normalize::new(&[0.1, 0.3, 0.5], &[0.5, 0.5, 0.5]).apply(image.clone(), &mut rng)?;
+        assert!((normalized.data()[0] - -0.2).abs() < 1e-6);
+        assert!(Normalize::new(&[0.0], &[1.0])
+            .apply(image.clone(), &mut rng)
+            .is_err());
+
+        let padded = RandomCrop::new(2, 2)
+            .padding(1)
+            .apply(image.clone(), &mut rng)?;
+        assert_eq!(padded.shape(), &[3, 2, 2]);
+        let jittered = ColorJitter::new()
+            .brightness(0.5)
+            .contrast(0.5)
+            .saturation(0.5)
+            .apply(image.clone(), &mut rng)?;
+        assert!(jittered.data().iter().all(|x| (0.0..=1.0).contains(x)));
+        assert_ne!(jittered, image);
+
+        // Augmentation repeats for a seed whatever the number of workers, and changes with
+        // the epoch
+        let augment = || {
+            RandomCrop::new(2, 2)
+                .padding(1)
+                .then(RandomHorizontalFlip::new(0.5))
+                .then(ColorJitter::new().brightness(0.3))
+        };
+        let epoch = |workers: usize| -> MlResult<Vec<HostTensor>> {
+            let dataset = Ramps.transform(augment()).seed(3);
+            let loader = DataLoader::new(dataset, 4).num_workers(workers).seed(1);
+            loader.set_epoch(5);
+            loader.iter().map(|batch| Ok(batch?.0)).collect()
+        };
+        assert_eq!(epoch(0)?, epoch(3)?);
+        let dataset = Ramps.transform(augment()).seed(3);
+        let first = dataset.get(2)?;
+        dataset.set_epoch(1);
+        assert_ne!(dataset.get(2)?, first);
+
+        let tensor = ToTensor.apply(image, &mut rng)?;
+        assert_eq!(tensor.shape(), &[3, 2, 2]);
+        Ok(())
+    }
+}
@@ -0,0 +1,507 @@
+//! The standard image classification datasets, read from their original binary files.
+//!
+//! [`Mnist`] reads the IDX files of MNIST and of look-alikes such as Fashion-MNIST, gzipped or
+//! not; [`Cifar`] reads the binary versions of CIFAR-10 and CIFAR-100. Images come out as
+//! `[channels, height, width]` tensors scaled to `[0, 1]`, with the class index as the target.
+//! With the `download` feature, missing files are fetched from the usual mirrors.
+//!
+//! ```ignore
+//! let train = Mnist::load("data/mnist", Split::Train)?;
+//! let loader = DataLoader::new(train, 64).shuffle(true);
+//! ```
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use super::{Dataset, HostTensor};
+use crate::serialize::FormatError;
+use crate::tensor::Tensor;
+use crate::MlResult;
+
+/// Which half of a dataset to read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Split {
+    Train,
+    Test,
+}
+
+/// The names of the CIFAR-10 classes, by label.
+pub const CIFAR10_CLASSES: [&str; 10] = [
+    "airplane",
+    "automobile",
+    "bird",
+    "cat",
+    "deer",
+    "dog",
+    "frog",
+    "horse",
+    "ship",
+    "truck",
+];
+
+/// Images and labels as stored on disk, one byte per pixel and per label.
+#[derive(Debug, Clone)]
+struct Images {
+    pixels: Vec<u8>,
+    labels: Vec<u8>,
+    shape: [usize; 3],
+}
+
+impl Images {
+    fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    fn image_len(&self) -> usize {
+        self.shape.iter().product()
+    }
+
+    fn get(&self, index: usize) -> MlResult<(HostTensor, usize)> {
+        if index >= self.len() {
+            return Err(
+                format!("Image {} is out of range for {} images", index, self.len()).into(),
+            );
+        }
+        let size = self.image_len();
+        let pixels = &self.pixels[index * size..(index + 1) * size];
+        let image = HostTensor::new(
+            pixels.iter().map(|&p| p as f32 / 255.0).collect(),
+            &self.shape,
+        )?;
+        Ok((image, self.labels[index] as usize))
+    }
+
+    fn images(&self) -> MlResult<Tensor> {
+        let data = self.pixels.iter().map(|&p| p as f32 / 255.0).collect();
+        let [c, h, w] = self.shape;
+        Tensor::from_vec(data, &[self.len(), c, h, w])
+    }
+
+    fn labels(&self) -> MlResult<Tensor> {
+        let data = self.labels.iter().map(|&label| label as f32).collect();
+        Tensor::from_vec(data, &[self.len()])
+    }
+}
+
+/// MNIST, or any dataset in its IDX layout: 28x28 grayscale images of ten classes.
+#[derive(Debug, Clone)]
+pub struct Mnist {
+    images: Images,
+}
+
+impl Mnist {
+    const BASE_URL: &'static str = "https://ossci-datasets.s3.amazonaws.com/mnist";
+
+    fn file_names(split: Split) -> [&'static str; 2] {
+        match split {
+            Split::Train => ["train-images-idx3-ubyte", "train-labels-idx1-ubyte"],
+            Split::Test => ["t10k-images-idx3-ubyte", "t10k-labels-idx1-ubyte"],
+        }
+    }
+
+    /// Reads a split from `dir`, which holds the four IDX files under their usual names,
+    /// optionally with a `.gz` suffix. With the `download` feature, missing files are
+    /// downloaded into `dir` first.
+    pub fn load<P: AsRef<Path>>(dir: P, split: Split) -> MlResult<Self> {
+        let dir = dir.as_ref();
+        let [images, labels] = Self::file_names(split).map(|name| find_file(dir, name));
+        match (images, labels) {
+            (Some(images), Some(labels)) => Self::from_idx(images, labels),
+            _ => Self::fetch(dir, split),
+        }
+    }
+
+    #[cfg(feature = "download")]
+    fn fetch(dir: &Path, split: Split) -> MlResult<Self> {
+        let mut paths = Vec::new();
+        for name in Self::file_names(split) {
+            let path = dir.join(format!("{}.gz", name));
+            download(&format!("{}/{}.gz", Self::BASE_URL, name), &path)?;
+            paths.push(path);
+        }
+        Self::from_idx(&paths[0], &paths[1])
+    }
+
+    #[cfg(not(feature = "download"))]
+    fn fetch(dir: &Path, split: Split) -> MlResult<Self> {
+        Err(format!(
+            "MNIST {:?} files not found in {}; download them from {} or enable the download \
+             feature",
+            split,
+            dir.display(),
+            Self::BASE_URL
+        )
+        .into())
+    }
+
+    /// Reads an IDX image file and the matching IDX label file.
+    pub fn from_idx<P: AsRef<Path>, Q: AsRef<Path>>(images: P, labels: Q) -> MlResult<Self> {
+        let (image_dims, pixels) = read_idx(images)?;
+        let (label_dims, labels) = read_idx(labels)?;
+        let (&[count, height, width], &[label_count]) = (&image_dims[..], &label_dims[..]) else {
+            return Err(FormatError::Invalid(format!(
+                "expected 3-D images and 1-D labels, got dimensions {:?} and {:?}",
+                image_dims, label_dims
+            ))
+            .into());
+        };
+        if count != label_count {
+            return Err(FormatError::Invalid(format!(
+                "{} images but {} labels",
+                count, label_count
+            ))
+            .into());
+        }
+        Ok(Self {
+            images: Images {
+                pixels,
+                labels,
+                shape: [1, height, width],
+            },
+        })
+    }
+
+    /// Every image, as a `[count, 1, height, width]` tensor.
+    pub fn images(&self) -> MlResult<Tensor> {
+        self.images.images()
+    }
+
+    /// Every label, as a `[count]` tensor of class indices.
+    pub fn labels(&self) -> MlResult<Tensor> {
+        self.images.labels()
+    }
+}
+
+impl Dataset for Mnist {
+    type Item = (HostTensor, usize);
+
+    fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    fn get(&self, index: usize) -> MlResult<Self::Item> {
+        self.images.get(index)
+    }
+}
+
+/// CIFAR-10 or CIFAR-100: 32x32 color images of 10 or 100 classes.
+#[derive(Debug, Clone)]
+pub struct Cifar {
+    images: Images,
+    classes: usize,
+}
+
+impl Cifar {
+    const IMAGE_LEN: usize = 3 * 32 * 32;
+
+    /// Reads a split of CIFAR-10 from `dir`, either the extracted `cifar-10-batches-bin`
+    /// directory or the directory holding it. With the `download` feature, a missing dataset
+    /// is downloaded and extracted into `dir` first.
+    pub fn cifar10<P: AsRef<Path>>(dir: P, split: Split) -> MlResult<Self> {
+        let files: Vec<String> = match split {
+            Split::Train => (1..=5).map(|i| format!("data_batch_{}.bin", i)).collect(),
+            Split::Test => vec!["test_batch.bin".to_string()],
+        };
+        let dir = Self::locate(
+            dir.as_ref(),
+            "cifar-10-batches-bin",
+            &files[0],
+            "cifar-10-binary",
+        )?;
+        Self::from_batches(files.iter().map(|file| dir.join(file)), 10)
+    }
+
+    /// Reads a split of CIFAR-100 from `dir`, either the extracted `cifar-100-binary`
+    /// directory or the directory holding it, labelled with the 100 fine classes.
+    pub fn cifar100<P: AsRef<Path>>(dir: P, split: Split) -> MlResult<Self> {
+        let file = match split {
+            Split::Train => "train.bin",
+            Split::Test => "test.bin",
+        };
+        let dir = Self::locate(dir.as_ref(), "cifar-100-binary", file, "cifar-100-binary")?;
+        Self::from_batches([dir.join(file)], 100)
+    }
+
+    /// Reads CIFAR binary batch files: records of a label byte, for CIFAR-100 a coarse label
+    /// byte before it, and 3072 bytes of channel-major pixels.
+    pub fn from_batches<I, P>(paths: I, classes: usize) -> MlResult<Self>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let label_bytes = if classes == 100 { 2 } else { 1 };
+        let record_len = label_bytes + Self::IMAGE_LEN;
+
+        let mut pixels = Vec::new();
+        let mut labels = Vec::new();
+        for path in paths {
+            let bytes = read_bytes(path.as_ref())?;
+            if bytes.len() % record_len != 0 {
+                return Err(FormatError::Invalid(format!(
+                    "{} is not a whole number of {} byte CIFAR records",
+                    path.as_ref().display(),
+                    record_len
+                ))
+                .into());
+            }
+            for record in bytes.chunks_exact(record_len) {
+                let label = record[label_bytes - 1];
+                if label as usize >= classes {
+                    return Err(FormatError::Invalid(format!(
+                        "label {} of {} classes",
+                        label, classes
+                    ))
+                    .into());
+                }
+                labels.push(label);
+                pixels.extend_from_slice(&record[label_bytes..]);
+            }
+        }
+        Ok(Self {
+            images: Images {
+                pixels,
+                labels,
+                shape: [3, 32, 32],
+            },
+            classes,
+        })
+    }
+
+    // The directory holding `file`: `dir` itself or its `extracted` subdirectory, where
+    // `archive` is downloaded to if neither has it
+    fn locate(dir: &Path, extracted: &str, file: &str, archive: &str) -> MlResult<PathBuf> {
+        for candidate in [dir.to_path_buf(), dir.join(extracted)] {
+            if candidate.join(file).is_file() {
+                return Ok(candidate);
+            }
+        }
+        Self::fetch(dir, file, archive)?;
+        Ok(dir.join(extracted))
+    }
+
+    #[cfg(feature = "download")]
+    fn fetch(dir: &Path, _file: &str, archive: &str) -> MlResult<()> {
+        let path = dir.join(format!("{}.tar.gz", archive));
+        download(&Self::url(archive), &path)?;
+        extract_tar_gz(&path, dir)
+    }
+
+    #[cfg(not(feature = "download"))]
+    fn fetch(dir: &Path, file: &str, archive: &str) -> MlResult<()> {
+        Err(format!(
+            "{} not found in {}; download and extract {} or enable the download feature",
+            file,
+            dir.display(),
+            Self::url(archive)
+        )
+        .into())
+    }
+
+    fn url(archive: &str) -> String {
+        format!("https://www.cs.toronto.edu/~kriz/{}.tar.gz", archive)
+    }
+
+    pub fn classes(&self) -> usize {
+        self.classes
+    }
+
+    /// Every image, as a `[count, 3, 32, 32]` tensor.
+    pub fn images(&self) -> MlResult<Tensor> {
+        self.images.images()
+    }
+
+    /// Every label, as a `[count]` tensor of class indices.
+    pub fn labels(&self) -> MlResult<Tensor> {
+        self.images.labels()
+    }
+}
+
+impl Dataset for Cifar {
+    type Item = (HostTensor, usize);
+
+    fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    fn get(&self, index: usize) -> MlResult<Self::Item> {
+        self.images.get(index)
+    }
+}
+
+/// Reads an IDX file of unsigned bytes, gzipped or not, into its dimensions and data.
+pub fn read_idx<P: AsRef<Path>>(path: P) -> MlResult<(Vec<usize>, Vec<u8>)> {
+    let bytes = read_bytes(path.as_ref())?;
+    if bytes.len() < 4 || bytes[..2] != [0, 0] {
+        return Err(FormatError::BadMagic.into());
+    }
+    if bytes[2] != 0x08 {
+        return Err(FormatError::UnsupportedDtype(bytes[2]).into());
+    }
+
+    let ndim = bytes[3] as usize;
+    let header = 4 + 4 * ndim;
+    if bytes.len() < header {
+        return Err(FormatError::Truncated {
+            needed: header,
+            available: bytes.len(),
+        }
+        .into());
+    }
+    let dims: Vec<usize> = bytes[4..header]
+        .chunks_exact(4)
+        .map(|dim| u32::from_be_bytes(dim.try_into().unwrap()) as usize)
+        .collect();
+
+    let len: usize = dims.iter().product();
+    let data = &bytes[header..];
+    if data.len() < len {
+        return Err(FormatError::Truncated {
+            needed: len,
+            available: data.len(),
+        }
+        .into());
+    }
+    if data.len() > len {
+        return Err(FormatError::TrailingBytes(data.len() - len).into());
+    }
+    Ok((dims, data.to_vec()))
+}
+
+// `dir/name`, or `dir/name.gz`, if either exists
+fn find_file(dir: &Path, name: &str) -> Option<PathBuf> {
+    [dir.join(name), dir.join(format!("{}.gz", name))]
+        .into_iter()
+        .find(|path| path.is_file())
+}
+
+// Reads a whole file, decompressing it if it is gzipped
+fn read_bytes(path: &Path) -> MlResult<Vec<u8>> {
+    let bytes =
+        std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    if !bytes.starts_with(&[0x1f, 0x8b]) {
+        return Ok(bytes);
+    }
+    let mut data = Vec::new();
+    flate2::read::GzDecoder::new(&bytes[..])
+        .read_to_end(&mut data)
+        .map_err(|e| format!("Failed to decompress {}: {}", path.display(), e))?;
+    Ok(data)
+}
+
+#[cfg(feature = "download")]
+fn download(url: &str, path: &Path) -> MlResult<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    crate::log::log_info!("Downloading {}", url);
+    let bytes = reqwest::blocking::get(url)
+        .and_then(|response| response.error_for_status())
+        .and_then(|response| response.bytes())
+        .map_err(|e| format!("Failed to download {}: {}", url, e))?;
+    std::fs::write(path, &bytes)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(())
+}
+
+// Unpacks the regular files of a gzipped tar archive under `dir`
+#[cfg(feature = "download")]
+fn extract_tar_gz(archive: &Path, dir: &Path) -> MlResult<()> {
+    let bytes = read_bytes(archive)?;
+    let mut offset = 0;
+    while offset + 512 <= bytes.len() {
+        let header = &bytes[offset..offset + 512];
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        let field = |range: std::ops::Range<usize>| {
+            let raw = &header[range];
+            let end = raw.iter().position(|&b| b == 0).unwrap_or(raw.len());
+            String::from_utf8_lossy(&raw[..end]).trim().to_string()
+        };
+        let name = match field(345..500) {
+            prefix if !prefix.is_empty() => format!("{}/{}", prefix, field(0..100)),
+            _ => field(0..100),
+        };
+        let size = usize::from_str_radix(&field(124..136), 8)
+            .map_err(|_| FormatError::Invalid(format!("bad size for {} in tar archive", name)))?;
+        let start = offset + 512;
+        let contents = bytes
+            .get(start..start + size)
+            .ok_or(FormatError::Truncated {
+                needed: size,
+                available: bytes.len().saturating_sub(start),
+            })?;
+
+        let regular = matches!(header[156], b'0' | 0);
+        if regular && !name.split('/').any(|part| part == "..") {
+            let path = dir.join(&name);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)
+                    .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+            }
+            std::fs::write(&path, contents)
+                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        }
+        offset = start + size.div_ceil(512) * 512;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn idx(dims: &[u32], data: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0, 0, 0x08, dims.len() as u8];
+        for dim in dims {
+            bytes.extend_from_slice(&dim.to_be_bytes());
+        }
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn test_mnist_and_cifar_files() -> MlResult<()> {
+        let dir = std::env::temp_dir().join("cetana_test_vision");
+        std::fs::create_dir_all(&dir).expect("Failed to create test dir");
+
+        // Two 2x2 images, the labels gzipped as downloads are
+        let pixels = [0, 255, 51, 102, 255, 0, 0, 255];
+        std::fs::write(dir.join("t10k-images-idx3-ubyte"), idx(&[2, 2, 2], &pixels))
+            .expect("Failed to write test file");
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gz.write_all(&idx(&[2], &[7, 3]))
+            .expect("Failed to compress");
+        std::fs::write(dir.join("t10k-labels-idx1-ubyte.gz"), gz.finish().unwrap())
+            .expect("Failed to write test file");
+
+        let mnist = Mnist::load(&dir, Split::Test)?;
+        assert_eq!(mnist.len(), 2);
+        let (image, label) = mnist.get(0)?;
+        assert_eq!(image.shape(), &[1, 2, 2]);
+        assert_eq!(image.data(), &[0.0, 1.0, 0.2, 0.4]);
+        assert_eq!(label, 7);
+        assert_eq!(mnist.images()?.shape(), &[2, 1, 2, 2]);
+        assert_eq!(mnist.labels()?.data(), &[7.0, 3.0]);
+        assert!(Mnist::load(&dir, Split::Train).is_err());
+
+        // A CIFAR-100 test file: coarse label, fine label, pixels
+        let nested = dir.join("cifar-100-binary");
+        std::fs::create_dir_all(&nested).expect("Failed to create test dir");
+        let mut record = vec![4, 42];
+        record.extend((0..Cifar::IMAGE_LEN).map(|i| (i % 256) as u8));
+        std::fs::write(nested.join("test.bin"), &record).expect("Failed to write test file");
+        let cifar = Cifar::cifar100(&dir, Split::Test)?;
+        let (image, label) = cifar.get(0)?;
+        assert_eq!((image.shape(), label), (&[3, 32, 32][..], 42));
+        assert_eq!(image.data()[255], 1.0);
+
+        // As CIFAR-10, the same bytes are no longer whole records
+        assert!(Cifar::from_batches([nested.join("test.bin")], 10).is_err());
+
+        std::fs::remove_dir_all(&dir).expect("Failed to remove test dir");
+        Ok(())
+    }
+}
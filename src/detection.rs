@@ -0,0 +1,178 @@
+//! Post-processing for object detection.
+//!
+//! Boxes are `[N, 4]` tensors of corners `(x1, y1, x2, y2)`, with `x1 <= x2` and
+//! `y1 <= y2`. [`box_iou`] compares two sets of them, and [`nms`] keeps the best-scoring
+//! box of each cluster of overlapping ones; [`batched_nms`] does so separately for each
+//! class. Both return the indices of the boxes they keep, which pick them out with
+//! [`Tensor::index_select`].
+//!
+//! ```ignore
+//! let keep = batched_nms(&boxes, &scores, &classes, 0.5)?;
+//! let boxes = boxes.index_select(0, &keep)?;
+//! ```
+
+use crate::tensor::{Tensor, TensorError};
+use crate::MlResult;
+
+/// The area of each of `[N, 4]` `boxes`, as `[N]`.
+pub fn box_area(boxes: &Tensor) -> MlResult<Tensor> {
+    let areas: Vec<_> = corners(boxes)?.map(area).collect();
+    let len = areas.len();
+    Tensor::from_vec(areas, &[len])
+}
+
+/// The intersection over union of every box in `a`, `[N, 4]`, with every box in `b`,
+/// `[M, 4]`, as `[N, M]`. Boxes that don't overlap, or that have no area, score 0.
+pub fn box_iou(a: &Tensor, b: &Tensor) -> MlResult<Tensor> {
+    let left: Vec<_> = corners(a)?.collect();
+    let right: Vec<_> = corners(b)?.collect();
+    let ious = left
+        .iter()
+        .flat_map(|x| right.iter().map(move |y| iou(x, y)))
+        .collect();
+    Tensor::from_vec(ious, &[left.len(), right.len()])
+}
+
+/// Non-maximum suppression: from the highest score down, keeps each box that overlaps
+/// none already kept by more than `iou_threshold`. Returns the indices of the kept boxes,
+/// best first, as a 1D tensor. Ties in score keep the earlier box first.
+pub fn nms(boxes: &Tensor, scores: &Tensor, iou_threshold: f32) -> MlResult<Tensor> {
+    let keep = suppress(boxes, scores, None, iou_threshold)?;
+    Tensor::from_vec(keep.iter().map(|&i| i as f32).collect(), &[keep.len()])
+}
+
+/// [`nms`] run separately for each class, so boxes of different `classes`, `[N]` whole
+/// numbers, never suppress each other. The indices of every class's kept boxes are
+/// returned together, best first.
+pub fn batched_nms(
+    boxes: &Tensor,
+    scores: &Tensor,
+    classes: &Tensor,
+    iou_threshold: f32,
+) -> MlResult<Tensor> {
+    let keep = suppress(boxes, scores, Some(classes), iou_threshold)?;
+    Tensor::from_vec(keep.iter().map(|&i| i as f32).collect(), &[keep.len()])
+}
+
+type Corners = [f32; 4];
+
+// Each box of an `[N, 4]` tensor
+fn corners(boxes: &Tensor) -> MlResult<impl Iterator<Item = Corners> + '_> {
+    if !matches!(boxes.shape(), [_, 4]) {
+        return Err(TensorError::InvalidOperation {
+            op: "box_iou",
+            reason: format!("expected [N, 4] boxes, got {:?}", boxes.shape()),
+        }
+        .into());
+    }
+    Ok(boxes
+        .data()
+        .chunks_exact(4)
+        .map(|b| [b[0], b[1], b[2], b[3]]))
+}
+
+fn area(b: Corners) -> f32 {
+    (b[2] - b[0]).max(0.0) * (b[3] - b[1]).max(0.0)
+}
+
+fn iou(a: &Corners, b: &Corners) -> f32 {
+    let overlap = [
+        a[0].max(b[0]),
+        a[1].max(b[1]),
+        a[2].min(b[2]),
+        a[3].min(b[3]),
+    ];
+    let intersection = area(overlap);
+    let union = area(*a) + area(*b) - intersection;
+    if union > 0.0 {
+        intersection / union
+    } else {
+        0.0
+    }
+}
+
+// The indices of the boxes NMS keeps, best first; with `classes`, boxes only suppress
+// those of their own class
+fn suppress(
+    boxes: &Tensor,
+    scores: &Tensor,
+    classes: Option<&Tensor>,
+    iou_threshold: f32,
+) -> MlResult<Vec<usize>> {
+    let boxes: Vec<_> = corners(boxes)?.collect();
+    for labels in std::iter::once(scores).chain(classes) {
+        if labels.shape() != [boxes.len()] {
+            return Err(TensorError::InvalidShape {
+                expected: vec![boxes.len()],
+                got: labels.shape().to_vec(),
+            }
+            .into());
+        }
+    }
+    let scores = scores.data();
+    let class = |i: usize| classes.map(|classes| classes.data()[i]);
+
+    let mut order: Vec<usize> = (0..boxes.len()).collect();
+    order.sort_by(|&i, &j| scores[j].total_cmp(&scores[i]).then(i.cmp(&j)));
+    let mut keep: Vec<usize> = Vec::new();
+    for i in order {
+        let suppressed = keep
+            .iter()
+            .any(|&k| class(k) == class(i) && iou(&boxes[k], &boxes[i]) > iou_threshold);
+        if !suppressed {
+            keep.push(i);
+        }
+    }
+    Ok(keep)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_box_iou_and_nms() -> MlResult<()> {
+        // Two overlapping boxes, one far away and one inside the first
+        let boxes = Tensor::from_vec(
+            vec![
+                0.0, 0.0, 2.0, 2.0, //
+                1.0, 0.0, 3.0, 2.0, //
+                10.0, 10.0, 11.0, 11.0, //
+                0.0, 0.0, 1.0, 1.0,
+            ],
+            &[4, 4],
+        )?;
+        assert_eq!(box_area(&boxes)?.data(), &[4.0, 4.0, 1.0, 1.0]);
+        let ious = box_iou(&boxes, &boxes.narrow(0, 0, 2)?)?;
+        assert_eq!(ious.shape(), &[4, 2]);
+        // Overlap 2 over a union of 6, and the box inside covers a quarter of the first
+        assert_eq!(
+            ious.data(),
+            &[1.0, 1.0 / 3.0, 1.0 / 3.0, 1.0, 0.0, 0.0, 0.25, 0.0]
+        );
+
+        let scores = Tensor::from_vec(vec![0.6, 0.9, 0.5, 0.8], &[4])?;
+        // The first box overlaps the better-scoring second by a third
+        assert_eq!(nms(&boxes, &scores, 0.3)?.data(), &[1.0, 3.0, 2.0]);
+        assert_eq!(nms(&boxes, &scores, 0.4)?.data(), &[1.0, 3.0, 0.0, 2.0]);
+
+        // Boxes of different classes don't suppress each other
+        let classes = Tensor::from_vec(vec![0.0, 1.0, 0.0, 0.0], &[4])?;
+        let keep = batched_nms(&boxes, &scores, &classes, 0.3)?;
+        assert_eq!(keep.data(), &[1.0, 3.0, 0.0, 2.0]);
+        assert_eq!(boxes.index_select(0, &keep)?.shape(), &[4, 4]);
+        assert_eq!(
+            batched_nms(&boxes, &scores, &classes, 0.2)?.data(),
+            &[1.0, 3.0, 2.0]
+        );
+
+        let empty = Tensor::from_vec(vec![], &[0, 4])?;
+        assert_eq!(
+            nms(&empty, &Tensor::from_vec(vec![], &[0])?, 0.5)?.shape(),
+            &[0]
+        );
+        assert!(nms(&boxes, &scores.narrow(0, 0, 3)?, 0.5).is_err());
+        assert!(box_iou(&boxes.reshape(&[2, 8])?, &boxes).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,329 @@
+use super::{ProcessGroup, ReduceOp, TcpGroup};
+use crate::amp::f32_to_f16;
+use crate::nn::Layer;
+use crate::tensor::Tensor;
+use crate::MlResult;
+
+/// A model replica kept in step with the other ranks of a [`ProcessGroup`].
+///
+/// Layers apply their update inside [`Layer::backward`], so after each backward pass the
+/// wrapper averages the change in every parameter across the ranks, in one all-reduce, and
+/// applies the average instead. For SGD that is exactly the update of the averaged gradient,
+/// i.e. of one step on the union of every rank's batch.
+///
+/// Every rank must run the same number of backward passes; give each its own shard of the
+/// data with a [`DistributedSampler`](crate::data::DistributedSampler), which evens out the
+/// shares. On a slow network, [`DistributedDataParallel::compression`] shrinks what each step
+/// sends.
+pub struct DistributedDataParallel<L, G = TcpGroup> {
+    model: L,
+    group: G,
+    compression: Compression,
+    // The part of the update top-k compression hasn't sent yet
+    residual: Vec<f32>,
+}
+
+/// How [`DistributedDataParallel`] shrinks the updates the ranks exchange, for clusters
+/// whose network is the bottleneck.
+///
+/// The compressed forms are exchanged with an all-gather and averaged by each rank, so they
+/// pay off when the compressed update is well under `1 / world_size` of the full one, or
+/// the process group's all-reduce passes through one rank anyway, as [`TcpGroup`]'s does.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Compression {
+    /// Full f32 updates, averaged with one all-reduce.
+    #[default]
+    None,
+    /// Updates rounded to f16, halving the traffic.
+    Fp16,
+    /// Only this fraction of each rank's update, the entries of largest magnitude, sent as
+    /// index and value pairs. What isn't sent is added to the next step's update (error
+    /// feedback), so small updates are delayed rather than lost.
+    TopK(f32),
+}
+
+impl<L: Layer, G: ProcessGroup> DistributedDataParallel<L, G> {
+    /// Wraps `model`, overwriting its parameters with rank 0's so every replica starts from
+    /// the same weights.
+    pub fn new(mut model: L, mut group: G) -> MlResult<Self> {
+        for param in model.parameters_mut() {
+            let mut data = param.data().to_vec();
+            group.broadcast(&mut data, 0)?;
+            *param = Tensor::from_vec(data, param.shape())?.to_device(param.device())?;
+        }
+        Ok(Self {
+            model,
+            group,
+            compression: Compression::None,
+            residual: Vec::new(),
+        })
+    }
+
+    /// Compresses the updates exchanged after each backward pass. Every rank must use the
+    /// same compression.
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self.residual.clear();
+        self
+    }
+
+    pub fn model(&self) -> &L {
+        &self.model
+    }
+
+    pub fn group(&self) -> &G {
+        &self.group
+    }
+
+    pub fn group_mut(&mut self) -> &mut G {
+        &mut self.group
+    }
+
+    pub fn into_inner(self) -> L {
+        self.model
+    }
+}
+
+impl<L, G: ProcessGroup> DistributedDataParallel<L, G> {
+    // Replaces this rank's update with the average of every rank's
+    fn average(&mut self, deltas: &mut [f32]) -> MlResult<()> {
+        let payload = match self.compression {
+            Compression::None => return self.group.all_reduce(deltas, ReduceOp::Mean),
+            Compression::Fp16 => pack_f16(deltas),
+            Compression::TopK(ratio) => {
+                if self.residual.len() != deltas.len() {
+                    self.residual = vec![0.0; deltas.len()];
+                }
+                for (residual, &delta) in self.residual.iter_mut().zip(deltas.iter()) {
+                    *residual += delta;
+                }
+                let k =
+                    ((ratio * deltas.len() as f32).ceil() as usize).clamp(1, deltas.len().max(1));
+                top_k(&mut self.residual, k)
+            }
+        };
+
+        deltas.fill(0.0);
+        let world_size = self.group.world_size() as f32;
+        for part in self.group.all_gather(&payload)? {
+            match self.compression {
+                Compression::TopK(_) => {
+                    let (indices, values) = part.split_at(part.len() / 2);
+                    for (&index, &value) in indices.iter().zip(values) {
+                        let slot = deltas
+                            .get_mut(index.to_bits() as usize)
+                            .ok_or("A rank sent an update outside the parameters")?;
+                        *slot += value / world_size;
+                    }
+                }
+                _ => {
+                    for (delta, value) in deltas.iter_mut().zip(unpack_f16(&part)) {
+                        *delta += value / world_size;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Two f16 values to each f32 slot, carried as raw bits; all-gathers copy them untouched
+fn pack_f16(values: &[f32]) -> Vec<f32> {
+    values
+        .chunks(2)
+        .map(|pair| {
+            let low = f32_to_f16(pair[0]) as u32;
+            let high = pair.get(1).map_or(0, |&x| f32_to_f16(x) as u32);
+            f32::from_bits(low | high << 16)
+        })
+        .collect()
+}
+
+fn unpack_f16(packed: &[f32]) -> impl Iterator<Item = f32> + '_ {
+    packed.iter().flat_map(|x| {
+        let bits = x.to_bits();
+        [bits as u16, (bits >> 16) as u16].map(cetana_core::safetensors::f16_to_f32)
+    })
+}
+
+// Takes the `k` entries of largest magnitude out of `residual`, as their indices, stored as
+// f32 bits, followed by their values
+fn top_k(residual: &mut [f32], k: usize) -> Vec<f32> {
+    let mut order: Vec<usize> = (0..residual.len()).collect();
+    if k < order.len() {
+        order.select_nth_unstable_by(k, |&a, &b| residual[b].abs().total_cmp(&residual[a].abs()));
+        order.truncate(k);
+    }
+    let mut payload: Vec<f32> = order.iter().map(|&i| f32::from_bits(i as u32)).collect();
+    for &i in &order {
+        payload.push(std::mem::take(&mut residual[i]));
+    }
+    payload
+}
+
+impl<L: Layer, G: ProcessGroup> Layer for DistributedDataParallel<L, G> {
+    fn forward(&self, input: &Tensor) -> MlResult<Tensor> {
+        self.model.forward(input)
+    }
+
+    fn backward(
+        &mut self,
+        input: &Tensor,
+        grad_output: &Tensor,
+        learning_rate: f32,
+    ) -> MlResult<Tensor> {
+        let before: Vec<Vec<f32>> = self
+            .model
+            .parameters_mut()
+            .into_iter()
+            .map(|param| param.data().to_vec())
+            .collect();
+        let grad_input = self.model.backward(input, grad_output, learning_rate)?;
+
+        let mut deltas: Vec<f32> = Vec::with_capacity(before.iter().map(Vec::len).sum());
+        for ((_, param), before) in self.model.named_parameters().into_iter().zip(&before) {
+            deltas.extend(param.data().iter().zip(before).map(|(a, b)| a - b));
+        }
+        self.average(&mut deltas)?;
+
+        let mut deltas = deltas.as_slice();
+        for (param, before) in self.model.parameters_mut().into_iter().zip(before) {
+            let (delta, rest) = deltas.split_at(before.len());
+            deltas = rest;
+            let data = before.iter().zip(delta).map(|(b, d)| b + d).collect();
+            *param = Tensor::from_vec(data, param.shape())?.to_device(param.device())?;
+        }
+        Ok(grad_input)
+    }
+
+    fn named_parameters(&self) -> Vec<(String, &Tensor)> {
+        self.model.named_parameters()
+    }
+
+    fn named_parameters_mut(&mut self) -> Vec<(String, &mut Tensor)> {
+        self.model.named_parameters_mut()
+    }
+
+    fn named_buffers(&self) -> Vec<(String, &Tensor)> {
+        self.model.named_buffers()
+    }
+
+    fn named_buffers_mut(&mut self) -> Vec<(String, &mut Tensor)> {
+        self.model.named_buffers_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::Linear;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_distributed_data_parallel() -> MlResult<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let batch = |rank: usize| -> MlResult<(Tensor, Tensor)> {
+            let x = rank as f32 + 1.0;
+            Ok((
+                Tensor::from_vec(vec![x, -x, 0.5, x], &[2, 2])?,
+                Tensor::from_vec(vec![1.0, x], &[2, 1])?,
+            ))
+        };
+
+        // Each rank starts from different weights and sees different data
+        let train = move |rank: usize, group: TcpGroup| -> MlResult<Vec<f32>> {
+            crate::seed_all(rank as u64);
+            let mut model = DistributedDataParallel::new(Linear::new(2, 1, true)?, group)?;
+            let (x, grad) = batch(rank)?;
+            model.backward(&x, &grad, 0.1)?;
+            let params = model.named_parameters();
+            Ok(params.iter().flat_map(|(_, p)| p.data().to_vec()).collect())
+        };
+        let worker = std::thread::spawn(move || train(1, TcpGroup::connect(addr, 1, 2)?));
+        let rank0 = train(0, TcpGroup::host(listener, 2)?)?;
+        let rank1 = worker.join().unwrap()?;
+        assert_eq!(rank0, rank1);
+
+        // The same as averaging the two updates from rank 0's weights
+        crate::seed_all(0);
+        let initial = Linear::new(2, 1, true)?;
+        let mut expected: Vec<f32> = vec![0.0; rank0.len()];
+        for rank in 0..2 {
+            let mut model = Linear::new(2, 1, true)?;
+            for (param, init) in model
+                .parameters_mut()
+                .into_iter()
+                .zip(initial.named_parameters())
+            {
+                *param = init.1.clone();
+            }
+            let (x, grad) = batch(rank)?;
+            model.backward(&x, &grad, 0.1)?;
+            let params = model.named_parameters();
+            let values = params.iter().flat_map(|(_, p)| p.data().to_vec());
+            for (e, v) in expected.iter_mut().zip(values) {
+                *e += v / 2.0;
+            }
+        }
+        for (actual, expected) in rank0.iter().zip(&expected) {
+            assert!(
+                (actual - expected).abs() < 1e-6,
+                "{:?} vs {:?}",
+                rank0,
+                expected
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_compression() -> MlResult<()> {
+        let train = |compression: Compression| -> MlResult<[Vec<f32>; 2]> {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            let run = move |rank: usize, group: TcpGroup| -> MlResult<Vec<f32>> {
+                crate::seed_all(rank as u64);
+                let model = Linear::new(4, 2, true)?;
+                let mut model =
+                    DistributedDataParallel::new(model, group)?.compression(compression);
+                let x = rank as f32 + 1.0;
+                let input = Tensor::from_vec(vec![x, -x, 0.5, 2.0], &[1, 4])?;
+                let grad = Tensor::from_vec(vec![1.0, -x], &[1, 2])?;
+                for _ in 0..3 {
+                    model.backward(&input, &grad, 0.1)?;
+                }
+                let params = model.named_parameters();
+                Ok(params.iter().flat_map(|(_, p)| p.data().to_vec()).collect())
+            };
+            let worker = std::thread::spawn(move || run(1, TcpGroup::connect(addr, 1, 2)?));
+            let rank0 = run(0, TcpGroup::host(listener, 2)?)?;
+            Ok([rank0, worker.join().unwrap()?])
+        };
+
+        let [exact, _] = train(Compression::None)?;
+        let [rank0, rank1] = train(Compression::Fp16)?;
+        assert_eq!(rank0, rank1);
+        for (a, b) in rank0.iter().zip(&exact) {
+            assert!((a - b).abs() < 1e-2, "{:?} vs {:?}", rank0, exact);
+        }
+
+        // Only a quarter of each update is sent, but the replicas still agree
+        let [rank0, rank1] = train(Compression::TopK(0.25))?;
+        assert_eq!(rank0, rank1);
+        assert_ne!(rank0, exact);
+
+        let mut residual = vec![0.5, -3.0, 0.1, 2.0];
+        let mut payload = top_k(&mut residual, 2);
+        payload[2..].sort_by(f32::total_cmp);
+        assert_eq!(payload[2..], [-3.0, 2.0]);
+        assert_eq!(residual, [0.5, 0.0, 0.1, 0.0]);
+        let packed = pack_f16(&[1.0, -0.5, 3.0]);
+        assert_eq!(packed.len(), 2);
+        assert_eq!(
+            unpack_f16(&packed).collect::<Vec<_>>(),
+            [1.0, -0.5, 3.0, 0.0]
+        );
+        Ok(())
+    }
+}
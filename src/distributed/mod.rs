@@ -0,0 +1,137 @@
+//! Data- and model-parallel training across processes.
+//!
+//! Each process, or rank, trains a replica of the model on its own share of the data. A
+//! [`ProcessGroup`] connects the ranks and runs the collectives they synchronize with:
+//! [`TcpGroup`] over TCP on any machine, and, with the `nccl` feature, [`NcclGroup`]
+//! through NVIDIA's collective library between CUDA devices. [`DistributedDataParallel`]
+//! wraps a model so every backward pass averages the update across the ranks, keeping the
+//! replicas identical.
+//!
+//! A model too large for one device can instead be split across the ranks, layer by layer:
+//! [`ColumnParallelLinear`] divides a layer's outputs between them and [`RowParallelLinear`]
+//! its inputs, with [`scatter`] and [`gather`] moving activations between the two layouts.
+//!
+//! [`SyncBatchNorm`] normalizes with the statistics of every rank's batch, for when each
+//! device only fits a few samples.
+//!
+//! Launch one process per rank with `MASTER_ADDR`, `MASTER_PORT`, `RANK` and `WORLD_SIZE`
+//! set, as `torchrun` does:
+//!
+//! ```ignore
+//! let group = TcpGroup::from_env()?;
+//! let model = DistributedDataParallel::new(build_model()?, group)?;
+//! let mut trainer = Trainer::new(model, CrossEntropyLoss::new());
+//! trainer.fit(&loader_for_this_rank, 10)?;
+//! ```
+
+mod ddp;
+#[cfg(feature = "nccl")]
+mod nccl;
+mod norm;
+mod parallel;
+mod tcp;
+
+pub use crate::backend::ReduceOp;
+pub use ddp::{Compression, DistributedDataParallel};
+#[cfg(feature = "nccl")]
+pub use nccl::NcclGroup;
+pub use norm::SyncBatchNorm;
+pub use parallel::{gather, scatter, ColumnParallelLinear, RowParallelLinear};
+pub use tcp::TcpGroup;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::MlResult;
+
+/// A set of processes that run collectives together.
+///
+/// Every rank must call the same collectives in the same order with buffers of the same
+/// length; a rank that skips one leaves the others waiting.
+pub trait ProcessGroup {
+    /// This process's index, in `0..world_size()`.
+    fn rank(&self) -> usize;
+
+    /// The number of processes in the group.
+    fn world_size(&self) -> usize;
+
+    /// Combines `data` elementwise across the ranks with `op`, leaving the result on every
+    /// rank.
+    fn all_reduce(&mut self, data: &mut [f32], op: ReduceOp) -> MlResult<()>;
+
+    /// Overwrites `data` on every rank with its value on `root`.
+    fn broadcast(&mut self, data: &mut [f32], root: usize) -> MlResult<()>;
+
+    /// Every rank's `data`, in rank order.
+    fn all_gather(&mut self, data: &[f32]) -> MlResult<Vec<Vec<f32>>>;
+
+    /// Returns once every rank has called it.
+    fn barrier(&mut self) -> MlResult<()> {
+        self.all_reduce(&mut [], ReduceOp::Sum)
+    }
+}
+
+impl<G: ProcessGroup + ?Sized> ProcessGroup for Box<G> {
+    fn rank(&self) -> usize {
+        (**self).rank()
+    }
+
+    fn world_size(&self) -> usize {
+        (**self).world_size()
+    }
+
+    fn all_reduce(&mut self, data: &mut [f32], op: ReduceOp) -> MlResult<()> {
+        (**self).all_reduce(data, op)
+    }
+
+    fn broadcast(&mut self, data: &mut [f32], root: usize) -> MlResult<()> {
+        (**self).broadcast(data, root)
+    }
+
+    fn all_gather(&mut self, data: &[f32]) -> MlResult<Vec<Vec<f32>>> {
+        (**self).all_gather(data)
+    }
+
+    fn barrier(&mut self) -> MlResult<()> {
+        (**self).barrier()
+    }
+}
+
+// Lets several layers of one model, like a stack of tensor-parallel ones, share a group
+impl<G: ProcessGroup + ?Sized> ProcessGroup for Rc<RefCell<G>> {
+    fn rank(&self) -> usize {
+        self.borrow().rank()
+    }
+
+    fn world_size(&self) -> usize {
+        self.borrow().world_size()
+    }
+
+    fn all_reduce(&mut self, data: &mut [f32], op: ReduceOp) -> MlResult<()> {
+        self.borrow_mut().all_reduce(data, op)
+    }
+
+    fn broadcast(&mut self, data: &mut [f32], root: usize) -> MlResult<()> {
+        self.borrow_mut().broadcast(data, root)
+    }
+
+    fn all_gather(&mut self, data: &[f32]) -> MlResult<Vec<Vec<f32>>> {
+        self.borrow_mut().all_gather(data)
+    }
+
+    fn barrier(&mut self) -> MlResult<()> {
+        self.borrow_mut().barrier()
+    }
+}
+
+// Folds `other` into `acc` for the reductions that combine pairwise; `Mean` sums here and
+// divides once every rank is in
+fn combine(acc: &mut [f32], other: &[f32], op: ReduceOp) {
+    for (a, &b) in acc.iter_mut().zip(other) {
+        *a = match op {
+            ReduceOp::Sum | ReduceOp::Mean => *a + b,
+            ReduceOp::Max => a.max(b),
+            ReduceOp::Min => a.min(b),
+        };
+    }
+}
@@ -0,0 +1,252 @@
+use std::ffi::c_void;
+
+use super::{ProcessGroup, ReduceOp, TcpGroup};
+use crate::backend::cuda::{load_library, CudaBuffer, CudaDevice, CudaStream};
+use crate::MlResult;
+
+type NcclComm = *mut c_void;
+
+// ncclUniqueId, an opaque 128 byte blob
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct UniqueId {
+    internal: [u8; 128],
+}
+
+type GetUniqueIdFn = unsafe extern "C" fn(*mut UniqueId) -> i32;
+type CommInitRankFn = unsafe extern "C" fn(*mut NcclComm, i32, UniqueId, i32) -> i32;
+type CommDestroyFn = unsafe extern "C" fn(NcclComm) -> i32;
+type AllReduceFn =
+    unsafe extern "C" fn(*const c_void, *mut c_void, usize, i32, i32, NcclComm, *mut c_void) -> i32;
+type BroadcastFn =
+    unsafe extern "C" fn(*const c_void, *mut c_void, usize, i32, i32, NcclComm, *mut c_void) -> i32;
+type AllGatherFn =
+    unsafe extern "C" fn(*const c_void, *mut c_void, usize, i32, NcclComm, *mut c_void) -> i32;
+
+const NCCL_SUCCESS: i32 = 0;
+const NCCL_FLOAT32: i32 = 7;
+
+const LIBRARY_NAMES: &[&str] = &["libnccl.so", "libnccl.so.2"];
+
+/// A [`ProcessGroup`] running collectives through NCCL between CUDA devices, over NVLink
+/// or InfiniBand where the cluster has them.
+///
+/// NCCL is loaded at runtime, so this needs `libnccl.so` installed but not to build. Ranks
+/// first meet over a [`TcpGroup`], which hands NCCL's communicator id around and serves as
+/// the barrier. Buffers are staged through the rank's device, since collectives take host
+/// slices.
+pub struct NcclGroup {
+    bootstrap: TcpGroup,
+    comm: NcclComm,
+    stream: CudaStream,
+    all_reduce: AllReduceFn,
+    broadcast: BroadcastFn,
+    all_gather: AllGatherFn,
+    destroy: CommDestroyFn,
+    // Keeps the function pointers above valid
+    _library: libloading::Library,
+}
+
+// The communicator is only used through `&mut self`
+unsafe impl Send for NcclGroup {}
+
+impl NcclGroup {
+    /// Sets up NCCL between the ranks of `bootstrap`, with this rank on CUDA device `device`.
+    pub fn new(mut bootstrap: TcpGroup, device: i32) -> MlResult<Self> {
+        let library = load_library(LIBRARY_NAMES).ok_or("NCCL is not installed")?;
+        CudaDevice::new(device)
+            .and_then(|device| device.set_current())
+            .map_err(|e| format!("Failed to select CUDA device {}: {}", device, e))?;
+        let stream =
+            CudaStream::new().map_err(|e| format!("Failed to create CUDA stream: {}", e))?;
+
+        unsafe {
+            let symbol = |name: &str| format!("NCCL has no {}", name);
+            let get_unique_id = *library
+                .get::<GetUniqueIdFn>(b"ncclGetUniqueId\0")
+                .map_err(|_| symbol("ncclGetUniqueId"))?;
+            let init_rank = *library
+                .get::<CommInitRankFn>(b"ncclCommInitRank\0")
+                .map_err(|_| symbol("ncclCommInitRank"))?;
+            let destroy = *library
+                .get::<CommDestroyFn>(b"ncclCommDestroy\0")
+                .map_err(|_| symbol("ncclCommDestroy"))?;
+            let all_reduce = *library
+                .get::<AllReduceFn>(b"ncclAllReduce\0")
+                .map_err(|_| symbol("ncclAllReduce"))?;
+            let broadcast = *library
+                .get::<BroadcastFn>(b"ncclBroadcast\0")
+                .map_err(|_| symbol("ncclBroadcast"))?;
+            let all_gather = *library
+                .get::<AllGatherFn>(b"ncclAllGather\0")
+                .map_err(|_| symbol("ncclAllGather"))?;
+
+            let mut id = UniqueId { internal: [0; 128] };
+            if bootstrap.rank() == 0 {
+                check(get_unique_id(&mut id), "ncclGetUniqueId")?;
+            }
+            let mut bytes = id.internal.to_vec();
+            bootstrap.broadcast_bytes(&mut bytes, 0)?;
+            id.internal.copy_from_slice(&bytes);
+
+            let mut comm = std::ptr::null_mut();
+            check(
+                init_rank(
+                    &mut comm,
+                    bootstrap.world_size() as i32,
+                    id,
+                    bootstrap.rank() as i32,
+                ),
+                "ncclCommInitRank",
+            )?;
+
+            Ok(Self {
+                bootstrap,
+                comm,
+                stream,
+                all_reduce,
+                broadcast,
+                all_gather,
+                destroy,
+                _library: library,
+            })
+        }
+    }
+
+    fn upload(data: &[f32], len: usize) -> MlResult<CudaBuffer> {
+        let mut buffer =
+            CudaBuffer::new(len).map_err(|e| format!("Failed to allocate CUDA memory: {}", e))?;
+        buffer
+            .copy_from_host(data)
+            .map_err(|e| format!("Failed to copy to the device: {}", e))?;
+        Ok(buffer)
+    }
+
+    fn download(&self, buffer: &CudaBuffer, data: &mut [f32]) -> MlResult<()> {
+        self.stream
+            .synchronize()
+            .map_err(|e| format!("CUDA stream synchronization failed: {}", e))?;
+        buffer
+            .copy_to_host(data)
+            .map_err(|e| format!("Failed to copy from the device: {}", e).into())
+    }
+}
+
+impl ProcessGroup for NcclGroup {
+    fn rank(&self) -> usize {
+        self.bootstrap.rank()
+    }
+
+    fn world_size(&self) -> usize {
+        self.bootstrap.world_size()
+    }
+
+    fn all_reduce(&mut self, data: &mut [f32], op: ReduceOp) -> MlResult<()> {
+        if data.is_empty() {
+            return self.bootstrap.barrier();
+        }
+        // ncclSum, ncclMax, ncclMin and ncclAvg
+        let op = match op {
+            ReduceOp::Sum => 0,
+            ReduceOp::Max => 2,
+            ReduceOp::Min => 3,
+            ReduceOp::Mean => 4,
+        };
+        let mut buffer = Self::upload(data, data.len())?;
+        let status = unsafe {
+            (self.all_reduce)(
+                buffer.as_ptr() as *const c_void,
+                buffer.as_mut_ptr() as *mut c_void,
+                data.len(),
+                NCCL_FLOAT32,
+                op,
+                self.comm,
+                self.stream.as_raw(),
+            )
+        };
+        check(status, "ncclAllReduce")?;
+        self.download(&buffer, data)
+    }
+
+    fn broadcast(&mut self, data: &mut [f32], root: usize) -> MlResult<()> {
+        if root >= self.world_size() {
+            return Err(
+                format!("Root {} is outside a world of {}", root, self.world_size()).into(),
+            );
+        }
+        if data.is_empty() {
+            return self.bootstrap.barrier();
+        }
+        let mut buffer = Self::upload(data, data.len())?;
+        let status = unsafe {
+            (self.broadcast)(
+                buffer.as_ptr() as *const c_void,
+                buffer.as_mut_ptr() as *mut c_void,
+                data.len(),
+                NCCL_FLOAT32,
+                root as i32,
+                self.comm,
+                self.stream.as_raw(),
+            )
+        };
+        check(status, "ncclBroadcast")?;
+        self.download(&buffer, data)
+    }
+
+    // NCCL gathers equal parts, so every part is padded to the longest
+    fn all_gather(&mut self, data: &[f32]) -> MlResult<Vec<Vec<f32>>> {
+        let lengths: Vec<usize> = self
+            .bootstrap
+            .all_gather(&[data.len() as f32])?
+            .into_iter()
+            .map(|len| len[0] as usize)
+            .collect();
+        let part = lengths.iter().copied().max().unwrap_or(0);
+        if part == 0 {
+            return Ok(vec![Vec::new(); lengths.len()]);
+        }
+
+        let mut padded = data.to_vec();
+        padded.resize(part, 0.0);
+        let send = Self::upload(&padded, part)?;
+        let mut receive = CudaBuffer::new(part * lengths.len())
+            .map_err(|e| format!("Failed to allocate CUDA memory: {}", e))?;
+        let status = unsafe {
+            (self.all_gather)(
+                send.as_ptr() as *const c_void,
+                receive.as_mut_ptr() as *mut c_void,
+                part,
+                NCCL_FLOAT32,
+                self.comm,
+                self.stream.as_raw(),
+            )
+        };
+        check(status, "ncclAllGather")?;
+        let mut gathered = vec![0.0; part * lengths.len()];
+        self.download(&receive, &mut gathered)?;
+        Ok(gathered
+            .chunks(part)
+            .zip(lengths)
+            .map(|(chunk, len)| chunk[..len].to_vec())
+            .collect())
+    }
+
+    fn barrier(&mut self) -> MlResult<()> {
+        self.bootstrap.barrier()
+    }
+}
+
+impl Drop for NcclGroup {
+    fn drop(&mut self) {
+        unsafe {
+            (self.destroy)(self.comm);
+        }
+    }
+}
+
+fn check(status: i32, call: &str) -> MlResult<()> {
+    if status != NCCL_SUCCESS {
+        return Err(format!("{} failed with status {}", call, status).into());
+    }
+    Ok(())
+}
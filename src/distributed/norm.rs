@@ -0,0 +1,334 @@
+use std::cell::RefCell;
+
+use super::{ProcessGroup, ReduceOp, TcpGroup};
+use crate::nn::{Buffer, Layer, Parameter};
+use crate::tensor::Tensor;
+use crate::MlResult;
+
+/// Batch normalization whose statistics cover the batches of every rank, not just this
+/// one's. With a few samples per device, per-rank batch statistics are too noisy to
+/// normalize with; all-reducing them makes the layer behave as it would on the whole batch.
+///
+/// The input is `[batch, channels, ...]`, normalized per channel over every other
+/// dimension. While training, each forward and backward pass all-reduces a few numbers per
+/// channel, so every rank must run them together; ranks may have batches of different
+/// sizes. The running statistics, kept as buffers for evaluation, advance with each
+/// backward pass, since that is where the layer can change. In evaluation mode
+/// ([`SyncBatchNorm::train`]) the layer uses them and doesn't communicate at all.
+///
+/// The scale and shift get this rank's gradient; wrap the model in a
+/// [`DistributedDataParallel`](super::DistributedDataParallel) to keep them in step.
+pub struct SyncBatchNorm<G = TcpGroup> {
+    weight: Parameter,
+    bias: Parameter,
+    running_mean: Buffer,
+    running_var: Buffer,
+    momentum: f32,
+    epsilon: f32,
+    training: bool,
+    group: RefCell<G>,
+}
+
+// Per-channel statistics of the batch across every rank
+struct Statistics {
+    mean: Vec<f32>,
+    var: Vec<f32>,
+    count: f32,
+}
+
+impl<G: ProcessGroup> SyncBatchNorm<G> {
+    /// A layer normalizing `num_features` channels, starting from unit scale, zero shift and
+    /// the statistics of a standard normal, in training mode.
+    pub fn new(num_features: usize, group: G) -> MlResult<Self> {
+        if num_features == 0 {
+            return Err("SyncBatchNorm needs at least one channel".into());
+        }
+        let filled = |value| Tensor::from_vec(vec![value; num_features], &[num_features]);
+        Ok(Self {
+            weight: filled(1.0)?.into(),
+            bias: filled(0.0)?.into(),
+            running_mean: filled(0.0)?.into(),
+            running_var: filled(1.0)?.into(),
+            momentum: 0.1,
+            epsilon: 1e-5,
+            training: true,
+            group: RefCell::new(group),
+        })
+    }
+
+    /// How far each step moves the running statistics toward the batch's. Defaults to 0.1.
+    pub fn momentum(mut self, momentum: f32) -> Self {
+        self.momentum = momentum;
+        self
+    }
+
+    /// Added to the variance before taking its square root. Defaults to 1e-5.
+    pub fn epsilon(mut self, epsilon: f32) -> Self {
+        self.epsilon = epsilon;
+        self
+    }
+
+    /// Switches between normalizing with the batch statistics (training) and with the
+    /// running ones (evaluation).
+    pub fn train(&mut self, training: bool) {
+        self.training = training;
+    }
+
+    pub fn is_training(&self) -> bool {
+        self.training
+    }
+
+    // The input's channel count and number of values per channel in each sample, checked
+    // against the layer's
+    fn layout(&self, input: &Tensor) -> MlResult<(usize, usize)> {
+        let channels = self.weight.shape()[0];
+        match input.shape() {
+            [_, c, rest @ ..] if *c == channels => Ok((channels, rest.iter().product())),
+            shape => Err(format!(
+                "SyncBatchNorm over {} channels expects [batch, {}, ...] input, got {:?}",
+                channels, channels, shape
+            )
+            .into()),
+        }
+    }
+
+    // The mean and biased variance of each channel over every rank's batch, from one
+    // all-reduce of the sums, the sums of squares and the count
+    fn statistics(&self, data: &[f32], channels: usize, inner: usize) -> MlResult<Statistics> {
+        let mut sums = vec![0.0; 2 * channels + 1];
+        for (c, value) in channel_values(data, channels, inner) {
+            sums[c] += value;
+            sums[channels + c] += value * value;
+        }
+        sums[2 * channels] = (data.len() / channels) as f32;
+        self.group
+            .borrow_mut()
+            .all_reduce(&mut sums, ReduceOp::Sum)?;
+
+        let count = sums[2 * channels];
+        if count == 0.0 {
+            return Err("SyncBatchNorm got an empty batch on every rank".into());
+        }
+        let mean: Vec<f32> = sums[..channels].iter().map(|s| s / count).collect();
+        let var = (sums[channels..2 * channels].iter().zip(&mean))
+            .map(|(sq, m)| (sq / count - m * m).max(0.0))
+            .collect();
+        Ok(Statistics { mean, var, count })
+    }
+
+    // The statistics to normalize with: the batch's while training, the running ones
+    // otherwise
+    fn current(&self, data: &[f32], channels: usize, inner: usize) -> MlResult<Statistics> {
+        if self.training {
+            self.statistics(data, channels, inner)
+        } else {
+            Ok(Statistics {
+                mean: self.running_mean.data().to_vec(),
+                var: self.running_var.data().to_vec(),
+                count: 0.0,
+            })
+        }
+    }
+}
+
+impl<G: ProcessGroup> Layer for SyncBatchNorm<G> {
+    fn forward(&self, input: &Tensor) -> MlResult<Tensor> {
+        let (channels, inner) = self.layout(input)?;
+        let data = input.data();
+        let stats = self.current(data, channels, inner)?;
+        let (weight, bias) = (self.weight.data(), self.bias.data());
+
+        let output = channel_values(data, channels, inner)
+            .map(|(c, x)| {
+                let normalized = (x - stats.mean[c]) / (stats.var[c] + self.epsilon).sqrt();
+                normalized * weight[c] + bias[c]
+            })
+            .collect();
+        Tensor::from_vec(output, input.shape())?.to_device(input.device())
+    }
+
+    fn backward(
+        &mut self,
+        input: &Tensor,
+        grad_output: &Tensor,
+        learning_rate: f32,
+    ) -> MlResult<Tensor> {
+        let (channels, inner) = self.layout(input)?;
+        if grad_output.shape() != input.shape() {
+            return Err(format!(
+                "SyncBatchNorm expects a gradient of the input's shape {:?}, got {:?}",
+                input.shape(),
+                grad_output.shape()
+            )
+            .into());
+        }
+        let data = input.data();
+        let grad = grad_output.data();
+        let stats = self.current(data, channels, inner)?;
+        let inv_std: Vec<f32> = (stats.var.iter())
+            .map(|v| 1.0 / (v + self.epsilon).sqrt())
+            .collect();
+        let normalized = |c: usize, x: f32| (x - stats.mean[c]) * inv_std[c];
+
+        // This rank's gradient of the shift, then of the scale
+        let mut sums = vec![0.0; 2 * channels];
+        for ((c, x), &g) in channel_values(data, channels, inner).zip(grad) {
+            sums[c] += g;
+            sums[channels + c] += g * normalized(c, x);
+        }
+        let (grad_bias, grad_weight) = (sums[..channels].to_vec(), sums[channels..].to_vec());
+
+        let weight = self.weight.data();
+        let grad_input = if self.training {
+            // The batch statistics depend on every rank's input, so the input gradient needs
+            // the gradient sums of the whole batch
+            self.group
+                .borrow_mut()
+                .all_reduce(&mut sums, ReduceOp::Sum)?;
+            channel_values(data, channels, inner)
+                .zip(grad)
+                .map(|((c, x), &g)| {
+                    let mean_grad = sums[c] / stats.count;
+                    let mean_projection = sums[channels + c] / stats.count;
+                    weight[c] * inv_std[c] * (g - mean_grad - normalized(c, x) * mean_projection)
+                })
+                .collect()
+        } else {
+            channel_values(data, channels, inner)
+                .zip(grad)
+                .map(|((c, _), &g)| g * weight[c] * inv_std[c])
+                .collect()
+        };
+
+        let step = |param: &Tensor, grad: &[f32]| {
+            let data = (param.data().iter().zip(grad))
+                .map(|(p, g)| p - learning_rate * g)
+                .collect();
+            Tensor::from_vec(data, param.shape())?.to_device(param.device())
+        };
+        *self.weight = step(&self.weight, &grad_weight)?;
+        *self.bias = step(&self.bias, &grad_bias)?;
+
+        if self.training {
+            // The running variance is the unbiased estimate, as for inference on new data
+            let correction = stats.count / (stats.count - 1.0).max(1.0);
+            let blend = |running: &Tensor, batch: &[f32], scale: f32| {
+                let data = (running.data().iter().zip(batch))
+                    .map(|(r, b)| (1.0 - self.momentum) * r + self.momentum * b * scale)
+                    .collect();
+                Tensor::from_vec(data, running.shape())?.to_device(running.device())
+            };
+            let mean = blend(&self.running_mean, &stats.mean, 1.0)?;
+            let var = blend(&self.running_var, &stats.var, correction)?;
+            *self.running_mean = mean;
+            *self.running_var = var;
+        }
+        Tensor::from_vec(grad_input, input.shape())?.to_device(input.device())
+    }
+
+    fn named_parameters(&self) -> Vec<(String, &Tensor)> {
+        vec![
+            ("weight".to_string(), &*self.weight),
+            ("bias".to_string(), &*self.bias),
+        ]
+    }
+
+    fn named_parameters_mut(&mut self) -> Vec<(String, &mut Tensor)> {
+        vec![
+            ("weight".to_string(), &mut *self.weight),
+            ("bias".to_string(), &mut *self.bias),
+        ]
+    }
+
+    fn named_buffers(&self) -> Vec<(String, &Tensor)> {
+        vec![
+            ("running_mean".to_string(), &*self.running_mean),
+            ("running_var".to_string(), &*self.running_var),
+        ]
+    }
+
+    fn named_buffers_mut(&mut self) -> Vec<(String, &mut Tensor)> {
+        vec![
+            ("running_mean".to_string(), &mut *self.running_mean),
+            ("running_var".to_string(), &mut *self.running_var),
+        ]
+    }
+}
+
+// Each value of a `[batch, channels, inner...]` tensor's data with its channel
+fn channel_values(
+    data: &[f32],
+    channels: usize,
+    inner: usize,
+) -> impl Iterator<Item = (usize, f32)> + '_ {
+    (data.iter().enumerate()).map(move |(i, &x)| ((i / inner.max(1)) % channels, x))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::assert_close_within;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_sync_batch_norm() -> MlResult<()> {
+        // A [3, 2, 2] batch, split unevenly: one sample on rank 0 and two on rank 1
+        let values: Vec<f32> = (0..12).map(|i| (i * i % 7) as f32 - 2.0).collect();
+        let grads: Vec<f32> = (0..12).map(|i| (i % 5) as f32 * 0.5 - 1.0).collect();
+        let inputs = values.clone();
+        let run = move |samples: std::ops::Range<usize>, group| -> MlResult<Vec<Vec<f32>>> {
+            let (start, end) = (samples.start * 4, samples.end * 4);
+            let shape = [samples.len(), 2, 2];
+            let input = Tensor::from_vec(inputs[start..end].to_vec(), &shape)?;
+            let grad = Tensor::from_vec(grads[start..end].to_vec(), &shape)?;
+            let mut layer = SyncBatchNorm::<TcpGroup>::new(2, group)?.momentum(0.5);
+            let output = layer.forward(&input)?;
+            let grad_input = layer.backward(&input, &grad, 0.0)?;
+            let mut results = vec![output.into_data(), grad_input.into_data()];
+            results.extend(layer.named_buffers().iter().map(|(_, t)| t.data().to_vec()));
+            Ok(results)
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let remote = run.clone();
+        let worker = std::thread::spawn(move || remote(1..3, TcpGroup::connect(addr, 1, 2)?));
+        let rank0 = run(0..1, TcpGroup::host(listener, 2)?)?;
+        let rank1 = worker.join().unwrap()?;
+
+        // The same as one rank normalizing the whole batch
+        let whole = run(
+            0..3,
+            TcpGroup::host(TcpListener::bind("127.0.0.1:0").unwrap(), 1)?,
+        )?;
+        assert_close_within(&[&rank0[0][..], &rank1[0]].concat(), &whole[0], 1e-4);
+        assert_close_within(&[&rank0[1][..], &rank1[1]].concat(), &whole[1], 1e-4);
+        for stats in 2..4 {
+            assert_close_within(&rank0[stats], &whole[stats], 1e-4);
+            assert_close_within(&rank1[stats], &whole[stats], 1e-4);
+        }
+
+        // Each channel of the output has zero mean and unit variance
+        let channel: Vec<f32> = (0..3)
+            .flat_map(|b| whole[0][b * 4..b * 4 + 2].to_vec())
+            .collect();
+        let mean = channel.iter().sum::<f32>() / 6.0;
+        let var = channel.iter().map(|x| (x - mean).powi(2)).sum::<f32>() / 6.0;
+        assert!(mean.abs() < 1e-5 && (var - 1.0).abs() < 1e-3);
+        let batch_mean: f32 = (0..3)
+            .map(|b| values[b * 4] + values[b * 4 + 1])
+            .sum::<f32>()
+            / 6.0;
+        assert_close_within(&whole[2][..1], &[0.5 * batch_mean], 1e-4);
+
+        // Evaluation uses the running statistics, without communicating
+        let group = TcpGroup::host(TcpListener::bind("127.0.0.1:0").unwrap(), 1)?;
+        let mut layer = SyncBatchNorm::new(2, group)?;
+        layer.train(false);
+        let input = Tensor::from_vec(vec![1.0, 2.0, 3.0, 4.0], &[2, 2])?;
+        assert_close_within(layer.forward(&input)?.data(), &[1.0, 2.0, 3.0, 4.0], 1e-4);
+        assert!(layer
+            .forward(&Tensor::from_vec(vec![0.0; 3], &[1, 3])?)
+            .is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,418 @@
+use std::cell::RefCell;
+
+use super::{ProcessGroup, ReduceOp, TcpGroup};
+use crate::nn::{Layer, Linear};
+use crate::serialize::{LoadReport, StateDict};
+use crate::tensor::Tensor;
+use crate::MlResult;
+
+/// This rank's share of `tensor`, split evenly along its last dimension. The inverse of
+/// [`gather`]; nothing is sent, since every rank already holds the whole tensor.
+pub fn scatter<G: ProcessGroup + ?Sized>(tensor: &Tensor, group: &G) -> MlResult<Tensor> {
+    let last = *tensor.shape().last().ok_or("Can't scatter a scalar")?;
+    let width = shard_size(last, group.world_size())?;
+    let start = group.rank() * width;
+    let data = tensor
+        .data()
+        .chunks(last.max(1))
+        .flat_map(|row| &row[start..start + width])
+        .copied()
+        .collect();
+    let mut shape = tensor.shape().to_vec();
+    let dims = shape.len();
+    shape[dims - 1] = width;
+    Tensor::from_vec(data, &shape)?.to_device(tensor.device())
+}
+
+/// Every rank's `tensor` joined along the last dimension in rank order, on every rank.
+pub fn gather<G: ProcessGroup + ?Sized>(tensor: &Tensor, group: &mut G) -> MlResult<Tensor> {
+    let width = *tensor.shape().last().ok_or("Can't gather a scalar")?;
+    let parts = group.all_gather(tensor.data())?;
+    if parts.iter().any(|part| part.len() != tensor.data().len()) {
+        return Err("Ranks gathered tensors of different shapes".into());
+    }
+
+    let rows: usize = tensor.shape()[..tensor.shape().len() - 1].iter().product();
+    let mut data = Vec::with_capacity(tensor.data().len() * parts.len());
+    for row in 0..rows {
+        for part in &parts {
+            data.extend_from_slice(&part[row * width..(row + 1) * width]);
+        }
+    }
+    let mut shape = tensor.shape().to_vec();
+    let dims = shape.len();
+    shape[dims - 1] = width * parts.len();
+    Tensor::from_vec(data, &shape)?.to_device(tensor.device())
+}
+
+/// A linear layer whose output features are split across the ranks: each holds the rows of
+/// the weight, and the bias entries, for its share of the outputs.
+///
+/// Every rank gets the whole input. The output is gathered so each rank sees all of it,
+/// unless [`ColumnParallelLinear::gather_output`] leaves it split to feed a
+/// [`RowParallelLinear`] directly, which saves a round of communication per layer pair.
+///
+/// [`Layer::state_dict`] holds this rank's shard, to save a checkpoint per rank;
+/// [`ColumnParallelLinear::gather_state_dict`] assembles the whole layer as a [`Linear`]
+/// would save it. [`Layer::load_state_dict`] takes either, cutting a whole layer's
+/// parameters down to this rank's share.
+pub struct ColumnParallelLinear<G = TcpGroup> {
+    shard: Linear,
+    group: RefCell<G>,
+    gather_output: bool,
+}
+
+impl<G: ProcessGroup> ColumnParallelLinear<G> {
+    /// This rank's share of a layer from `in_features` to `out_features`, which must split
+    /// evenly over the ranks.
+    pub fn new(in_features: usize, out_features: usize, bias: bool, group: G) -> MlResult<Self> {
+        let width = shard_size(out_features, group.world_size())?;
+        Ok(Self {
+            shard: Linear::new(in_features, width, bias)?,
+            group: RefCell::new(group),
+            gather_output: true,
+        })
+    }
+
+    /// This rank's share of `linear`, which every rank holds whole.
+    pub fn from_linear(linear: &Linear, group: G) -> MlResult<Self> {
+        let (in_features, out_features, bias) = dimensions(linear);
+        let mut layer = Self::new(in_features, out_features, bias, group)?;
+        layer.load_state_dict(&linear.state_dict(), true)?;
+        Ok(layer)
+    }
+
+    /// Whether to gather the output from every rank; otherwise each rank's output holds only
+    /// its own features.
+    pub fn gather_output(mut self, gather_output: bool) -> Self {
+        self.gather_output = gather_output;
+        self
+    }
+
+    /// The whole layer's parameters, named as a [`Linear`] names them. Every rank must call
+    /// this together.
+    pub fn gather_state_dict(&self) -> MlResult<StateDict> {
+        let mut group = self.group.borrow_mut();
+        let mut state = StateDict::new();
+        for (name, param) in self.shard.named_parameters() {
+            let data = group.all_gather(param.data())?.concat();
+            let mut shape = param.shape().to_vec();
+            shape[0] *= group.world_size();
+            state.insert(name, Tensor::from_vec(data, &shape)?);
+        }
+        Ok(state)
+    }
+}
+
+impl<G: ProcessGroup> Layer for ColumnParallelLinear<G> {
+    fn forward(&self, input: &Tensor) -> MlResult<Tensor> {
+        let output = self.shard.forward(input)?;
+        if self.gather_output {
+            gather(&output, &mut *self.group.borrow_mut())
+        } else {
+            Ok(output)
+        }
+    }
+
+    fn backward(
+        &mut self,
+        input: &Tensor,
+        grad_output: &Tensor,
+        learning_rate: f32,
+    ) -> MlResult<Tensor> {
+        let group = self.group.get_mut();
+        let scattered;
+        let grad_output = if self.gather_output {
+            scattered = scatter(grad_output, group)?;
+            &scattered
+        } else {
+            grad_output
+        };
+        // Each rank's input gradient only covers its own outputs; the whole one is the sum
+        let grad_input = self.shard.backward(input, grad_output, learning_rate)?;
+        all_reduce(grad_input, group, ReduceOp::Sum)
+    }
+
+    fn named_parameters(&self) -> Vec<(String, &Tensor)> {
+        self.shard.named_parameters()
+    }
+
+    fn named_parameters_mut(&mut self) -> Vec<(String, &mut Tensor)> {
+        self.shard.named_parameters_mut()
+    }
+
+    fn load_state_dict(&mut self, state: &StateDict, strict: bool) -> MlResult<LoadReport> {
+        let group = self.group.get_mut();
+        let (rank, world_size) = (group.rank(), group.world_size());
+        let local = local_state(state, &self.shard, |tensor| {
+            let first = *tensor.shape().first().ok_or("Can't split a scalar")?;
+            let rows = shard_size(first, world_size)?;
+            let len = tensor.data().len() / world_size;
+            let data = tensor.data()[rank * len..(rank + 1) * len].to_vec();
+            let mut shape = tensor.shape().to_vec();
+            shape[0] = rows;
+            Tensor::from_vec(data, &shape)
+        })?;
+        self.shard.load_state_dict(&local, strict)
+    }
+}
+
+/// A linear layer whose input features are split across the ranks: each holds the columns
+/// of the weight for its share of the inputs, and multiplies them by that share of the
+/// input. The partial outputs are summed across the ranks, and the bias, which every rank
+/// holds whole, added once.
+///
+/// The input is cut down to this rank's share, unless
+/// [`RowParallelLinear::input_is_parallel`] says it arrives already split, as the output of
+/// a [`ColumnParallelLinear`] that doesn't gather it does. Checkpoints work as they do for
+/// [`ColumnParallelLinear`].
+pub struct RowParallelLinear<G = TcpGroup> {
+    // Holds the whole bias, identical on every rank
+    shard: Linear,
+    group: RefCell<G>,
+    input_is_parallel: bool,
+}
+
+impl<G: ProcessGroup> RowParallelLinear<G> {
+    /// This rank's share of a layer from `in_features`, which must split evenly over the
+    /// ranks, to `out_features`. The bias starts from rank 0's.
+    pub fn new(
+        in_features: usize,
+        out_features: usize,
+        bias: bool,
+        mut group: G,
+    ) -> MlResult<Self> {
+        let width = shard_size(in_features, group.world_size())?;
+        // A layer of `width` inputs draws from a wider range than one of `in_features`
+        let scale = (width as f32 / in_features as f32).sqrt();
+        let (weight, bias) = Linear::new(width, out_features, bias)?.into_parts();
+        let bias = match bias {
+            Some(bias) => {
+                let mut data = bias.mul_scalar(scale)?.into_data();
+                group.broadcast(&mut data, 0)?;
+                Some(Tensor::from_vec(data, &[out_features])?)
+            }
+            None => None,
+        };
+        Ok(Self {
+            shard: Linear::from_parts(weight.mul_scalar(scale)?, bias),
+            group: RefCell::new(group),
+            input_is_parallel: false,
+        })
+    }
+
+    /// This rank's share of `linear`, which every rank holds whole.
+    pub fn from_linear(linear: &Linear, group: G) -> MlResult<Self> {
+        let (in_features, out_features, bias) = dimensions(linear);
+        let mut layer = Self::new(in_features, out_features, bias, group)?;
+        layer.load_state_dict(&linear.state_dict(), true)?;
+        Ok(layer)
+    }
+
+    /// Whether the input arrives already split across the ranks.
+    pub fn input_is_parallel(mut self, input_is_parallel: bool) -> Self {
+        self.input_is_parallel = input_is_parallel;
+        self
+    }
+
+    /// The whole layer's parameters, named as a [`Linear`] names them. Every rank must call
+    /// this together.
+    pub fn gather_state_dict(&self) -> MlResult<StateDict> {
+        let mut group = self.group.borrow_mut();
+        let mut state = StateDict::new();
+        for (name, param) in self.shard.named_parameters() {
+            let param = match name.as_str() {
+                "weight" => gather(param, &mut *group)?,
+                _ => param.clone(),
+            };
+            state.insert(name, param);
+        }
+        Ok(state)
+    }
+}
+
+impl<G: ProcessGroup> Layer for RowParallelLinear<G> {
+    fn forward(&self, input: &Tensor) -> MlResult<Tensor> {
+        let mut group = self.group.borrow_mut();
+        let scattered;
+        let input = if self.input_is_parallel {
+            input
+        } else {
+            scattered = scatter(input, &*group)?;
+            &scattered
+        };
+
+        let params = self.shard.named_parameters();
+        let partial = input.matmul(&params[0].1.transpose(0, 1)?)?;
+        let output = all_reduce(partial, &mut *group, ReduceOp::Sum)?;
+        match params.get(1) {
+            Some((_, bias)) => output.add(bias),
+            None => Ok(output),
+        }
+    }
+
+    fn backward(
+        &mut self,
+        input: &Tensor,
+        grad_output: &Tensor,
+        learning_rate: f32,
+    ) -> MlResult<Tensor> {
+        let group = self.group.get_mut();
+        let scattered;
+        let input = if self.input_is_parallel {
+            input
+        } else {
+            scattered = scatter(input, group)?;
+            &scattered
+        };
+        // Every rank has the whole output gradient, so the bias copies stay identical
+        let grad_input = self.shard.backward(input, grad_output, learning_rate)?;
+        if self.input_is_parallel {
+            Ok(grad_input)
+        } else {
+            gather(&grad_input, group)
+        }
+    }
+
+    fn named_parameters(&self) -> Vec<(String, &Tensor)> {
+        self.shard.named_parameters()
+    }
+
+    fn named_parameters_mut(&mut self) -> Vec<(String, &mut Tensor)> {
+        self.shard.named_parameters_mut()
+    }
+
+    fn load_state_dict(&mut self, state: &StateDict, strict: bool) -> MlResult<LoadReport> {
+        let group = self.group.get_mut();
+        let local = local_state(state, &self.shard, |tensor| scatter(tensor, group))?;
+        self.shard.load_state_dict(&local, strict)
+    }
+}
+
+// The size of each rank's share of `size` entries
+fn shard_size(size: usize, world_size: usize) -> MlResult<usize> {
+    if !size.is_multiple_of(world_size) {
+        return Err(format!(
+            "{} features don't split evenly over {} ranks",
+            size, world_size
+        )
+        .into());
+    }
+    Ok(size / world_size)
+}
+
+// The input and output features of `linear`, and whether it has a bias
+fn dimensions(linear: &Linear) -> (usize, usize, bool) {
+    let params = linear.named_parameters();
+    let shape = params[0].1.shape();
+    (shape[1], shape[0], params.len() > 1)
+}
+
+// `state` with each of `shard`'s parameters that has another shape, as a whole layer's
+// would, cut down to this rank's share by `split`. Quantized entries are dequantized first.
+fn local_state(
+    state: &StateDict,
+    shard: &Linear,
+    mut split: impl FnMut(&Tensor) -> MlResult<Tensor>,
+) -> MlResult<StateDict> {
+    let shapes = shard.named_parameters();
+    let mut local = StateDict::new();
+    let dequantized = state
+        .iter_quantized()
+        .map(|(name, q)| Ok((name, q.dequantize()?)))
+        .collect::<MlResult<Vec<_>>>()?;
+    let entries = state
+        .iter()
+        .chain(dequantized.iter().map(|(name, tensor)| (*name, tensor)));
+    for (name, tensor) in entries {
+        let shard_shape = shapes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, p)| p.shape());
+        let tensor = match shard_shape {
+            Some(shape) if shape != tensor.shape() => split(tensor)?,
+            _ => tensor.clone(),
+        };
+        local.insert(name, tensor);
+    }
+    Ok(local)
+}
+
+fn all_reduce<G: ProcessGroup + ?Sized>(
+    tensor: Tensor,
+    group: &mut G,
+    op: ReduceOp,
+) -> MlResult<Tensor> {
+    let device = tensor.device();
+    let shape = tensor.shape().to_vec();
+    let mut data = tensor.into_data();
+    group.all_reduce(&mut data, op)?;
+    Tensor::from_vec(data, &shape)?.to_device(device)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::assert_close;
+    use std::net::TcpListener;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_tensor_parallel() -> MlResult<()> {
+        let input = || Tensor::from_vec((0..12).map(|i| i as f32 / 6.0 - 1.0).collect(), &[3, 4]);
+        let grad = || Tensor::from_vec(vec![0.5, -1.0, 0.25, 1.0, -0.5, 0.75], &[3, 2]);
+        let full = || -> MlResult<(Linear, Linear)> {
+            crate::seed_all(7);
+            Ok((Linear::new(4, 6, true)?, Linear::new(6, 2, true)?))
+        };
+
+        // A column-parallel layer feeding a row-parallel one, split over two ranks
+        let run = move |group: TcpGroup| -> MlResult<Vec<Vec<f32>>> {
+            let group = Rc::new(RefCell::new(group));
+            let (first, second) = full()?;
+            let mut first =
+                ColumnParallelLinear::from_linear(&first, group.clone())?.gather_output(false);
+            let mut second =
+                RowParallelLinear::from_linear(&second, group.clone())?.input_is_parallel(true);
+            assert_eq!(first.named_parameters()[0].1.shape(), &[3, 4]);
+            assert_eq!(second.named_parameters()[0].1.shape(), &[2, 3]);
+
+            let (x, grad) = (input()?, grad()?);
+            let hidden = first.forward(&x)?;
+            let output = second.forward(&hidden)?;
+            let grad_hidden = second.backward(&hidden, &grad, 0.1)?;
+            let grad_input = first.backward(&x, &grad_hidden, 0.1)?;
+
+            let mut results = vec![output.into_data(), grad_input.into_data()];
+            for state in [first.gather_state_dict()?, second.gather_state_dict()?] {
+                results.extend(state.into_iter().map(|(_, t)| t.into_data()));
+            }
+            Ok(results)
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let worker = std::thread::spawn(move || run(TcpGroup::connect(addr, 1, 2)?));
+        let rank0 = run(TcpGroup::host(listener, 2)?)?;
+        let rank1 = worker.join().unwrap()?;
+        assert_eq!(rank0, rank1);
+
+        // The same step on the whole layers
+        let (mut first, mut second) = full()?;
+        let (x, grad) = (input()?, grad()?);
+        let hidden = first.forward(&x)?;
+        assert_close(&rank0[0], second.forward(&hidden)?.data());
+        let grad_hidden = second.backward(&hidden, &grad, 0.1)?;
+        assert_close(&rank0[1], first.backward(&x, &grad_hidden, 0.1)?.data());
+        let params = first.state_dict().into_iter().chain(second.state_dict());
+        for (actual, (_, expected)) in rank0[2..].iter().zip(params) {
+            assert_close(actual, expected.data());
+        }
+
+        // Gathering a split tensor back, in the single-rank case
+        let mut group = TcpGroup::host(TcpListener::bind("127.0.0.1:0").unwrap(), 1)?;
+        let x = input()?;
+        assert_eq!(gather(&scatter(&x, &group)?, &mut group)?.data(), x.data());
+        assert!(ColumnParallelLinear::new(4, 3, true, group).is_ok());
+        Ok(())
+    }
+}
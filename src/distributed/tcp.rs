@@ -0,0 +1,311 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use super::{combine, ProcessGroup, ReduceOp};
+use crate::MlResult;
+
+// How long a rank keeps retrying to reach rank 0, which may start after it
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A [`ProcessGroup`] over TCP, for CPU training or clusters without NCCL.
+///
+/// Rank 0 listens and every other rank connects to it, so collectives pass through rank 0:
+/// it reduces what the others send and sends the result back. That keeps the protocol
+/// simple and suits the few ranks of a small cluster; rank 0's link carries
+/// `2 * (world_size - 1)` copies of each buffer.
+pub struct TcpGroup {
+    rank: usize,
+    world_size: usize,
+    // On rank 0 a stream to each other rank, in rank order; elsewhere the one to rank 0
+    peers: Vec<TcpStream>,
+}
+
+impl TcpGroup {
+    /// Joins the group whose rank 0 listens at `addr`. Rank 0 binds `addr` and waits for
+    /// the other ranks; they retry for up to a minute while it starts.
+    pub fn connect(addr: impl ToSocketAddrs, rank: usize, world_size: usize) -> MlResult<Self> {
+        if rank >= world_size {
+            return Err(format!("Rank {} is outside a world of {}", rank, world_size).into());
+        }
+        let addr = addr
+            .to_socket_addrs()
+            .map_err(|e| format!("Invalid address: {}", e))?
+            .next()
+            .ok_or("The address resolved to nothing")?;
+
+        if rank == 0 {
+            let listener =
+                TcpListener::bind(addr).map_err(|e| format!("Failed to bind {}: {}", addr, e))?;
+            return Self::host(listener, world_size);
+        }
+        let mut stream = connect_with_retry(addr)?;
+        write_u64(&mut stream, rank as u64)?;
+        write_u64(&mut stream, world_size as u64)?;
+        Ok(Self {
+            rank,
+            world_size,
+            peers: vec![stream],
+        })
+    }
+
+    /// Rank 0 of a group, accepting the other ranks on a listener already bound, e.g. to
+    /// port 0 with the chosen port passed on to them.
+    pub fn host(listener: TcpListener, world_size: usize) -> MlResult<Self> {
+        let mut peers: Vec<Option<TcpStream>> = (1..world_size).map(|_| None).collect();
+        for _ in 1..world_size {
+            let (mut stream, _) = listener
+                .accept()
+                .map_err(|e| format!("Failed to accept a rank: {}", e))?;
+            stream.set_nodelay(true).ok();
+            let rank = read_u64(&mut stream)? as usize;
+            let theirs = read_u64(&mut stream)? as usize;
+            if theirs != world_size {
+                return Err(format!(
+                    "Rank {} expects a world of {}, not {}",
+                    rank, theirs, world_size
+                )
+                .into());
+            }
+            match peers.get_mut(rank.wrapping_sub(1)) {
+                Some(slot @ None) => *slot = Some(stream),
+                _ => return Err(format!("Unexpected or duplicate rank {}", rank).into()),
+            }
+        }
+        Ok(Self {
+            rank: 0,
+            world_size,
+            peers: peers.into_iter().flatten().collect(),
+        })
+    }
+
+    /// Joins the group described by the `MASTER_ADDR`, `MASTER_PORT`, `RANK` and
+    /// `WORLD_SIZE` environment variables.
+    pub fn from_env() -> MlResult<Self> {
+        let var = |name: &str| std::env::var(name).map_err(|_| format!("{} is not set", name));
+        let number = |name: &str| -> MlResult<usize> {
+            var(name)?
+                .parse()
+                .map_err(|e| format!("Invalid {}: {}", name, e).into())
+        };
+        let addr = format!("{}:{}", var("MASTER_ADDR")?, var("MASTER_PORT")?);
+        Self::connect(addr, number("RANK")?, number("WORLD_SIZE")?)
+    }
+}
+
+impl ProcessGroup for TcpGroup {
+    fn rank(&self) -> usize {
+        self.rank
+    }
+
+    fn world_size(&self) -> usize {
+        self.world_size
+    }
+
+    fn all_reduce(&mut self, data: &mut [f32], op: ReduceOp) -> MlResult<()> {
+        if self.rank != 0 {
+            write_f32s(&mut self.peers[0], data)?;
+            return read_f32s_into(&mut self.peers[0], data);
+        }
+
+        for peer in &mut self.peers {
+            let other = read_f32s(peer)?;
+            if other.len() != data.len() {
+                return Err(format!(
+                    "all_reduce of {} values met one of {}",
+                    data.len(),
+                    other.len()
+                )
+                .into());
+            }
+            combine(data, &other, op);
+        }
+        if op == ReduceOp::Mean {
+            data.iter_mut().for_each(|x| *x /= self.world_size as f32);
+        }
+        for peer in &mut self.peers {
+            write_f32s(peer, data)?;
+        }
+        Ok(())
+    }
+
+    fn broadcast(&mut self, data: &mut [f32], root: usize) -> MlResult<()> {
+        if root >= self.world_size {
+            return Err(format!("Root {} is outside a world of {}", root, self.world_size).into());
+        }
+        if self.rank != 0 {
+            if self.rank == root {
+                write_f32s(&mut self.peers[0], data)?;
+            }
+            return read_f32s_into(&mut self.peers[0], data);
+        }
+
+        if root != 0 {
+            read_f32s_into(&mut self.peers[root - 1], data)?;
+        }
+        for peer in &mut self.peers {
+            write_f32s(peer, data)?;
+        }
+        Ok(())
+    }
+
+    fn all_gather(&mut self, data: &[f32]) -> MlResult<Vec<Vec<f32>>> {
+        if self.rank != 0 {
+            write_f32s(&mut self.peers[0], data)?;
+            return (0..self.world_size)
+                .map(|_| read_f32s(&mut self.peers[0]))
+                .collect();
+        }
+
+        let mut parts = vec![data.to_vec()];
+        for peer in &mut self.peers {
+            parts.push(read_f32s(peer)?);
+        }
+        for peer in &mut self.peers {
+            for part in &parts {
+                write_f32s(peer, part)?;
+            }
+        }
+        Ok(parts)
+    }
+}
+
+#[cfg(any(feature = "nccl", test))]
+impl TcpGroup {
+    /// Sends `bytes` from `root` to every rank, for metadata that isn't f32.
+    pub(crate) fn broadcast_bytes(&mut self, bytes: &mut Vec<u8>, root: usize) -> MlResult<()> {
+        if self.rank != 0 {
+            if self.rank == root {
+                write_bytes(&mut self.peers[0], bytes)?;
+            }
+            *bytes = read_bytes(&mut self.peers[0])?;
+            return Ok(());
+        }
+        if root != 0 {
+            *bytes = read_bytes(&mut self.peers[root - 1])?;
+        }
+        for peer in &mut self.peers {
+            write_bytes(peer, bytes)?;
+        }
+        Ok(())
+    }
+}
+
+fn connect_with_retry(addr: SocketAddr) -> MlResult<TcpStream> {
+    let deadline = Instant::now() + CONNECT_TIMEOUT;
+    loop {
+        match TcpStream::connect(addr) {
+            Ok(stream) => {
+                stream.set_nodelay(true).ok();
+                return Ok(stream);
+            }
+            Err(e) if Instant::now() >= deadline => {
+                return Err(format!("Failed to reach rank 0 at {}: {}", addr, e).into())
+            }
+            Err(_) => std::thread::sleep(Duration::from_millis(50)),
+        }
+    }
+}
+
+// Messages are a u64 byte count then the bytes, little-endian throughout
+
+fn write_u64(stream: &mut TcpStream, value: u64) -> MlResult<()> {
+    stream
+        .write_all(&value.to_le_bytes())
+        .map_err(|e| format!("Failed to send to a rank: {}", e).into())
+}
+
+fn read_u64(stream: &mut TcpStream) -> MlResult<u64> {
+    let mut bytes = [0; 8];
+    stream
+        .read_exact(&mut bytes)
+        .map_err(|e| format!("Failed to receive from a rank: {}", e))?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn write_bytes(stream: &mut TcpStream, bytes: &[u8]) -> MlResult<()> {
+    write_u64(stream, bytes.len() as u64)?;
+    stream
+        .write_all(bytes)
+        .map_err(|e| format!("Failed to send to a rank: {}", e).into())
+}
+
+fn read_bytes(stream: &mut TcpStream) -> MlResult<Vec<u8>> {
+    let len = read_u64(stream)? as usize;
+    let mut bytes = vec![0; len];
+    stream
+        .read_exact(&mut bytes)
+        .map_err(|e| format!("Failed to receive from a rank: {}", e))?;
+    Ok(bytes)
+}
+
+fn write_f32s(stream: &mut TcpStream, data: &[f32]) -> MlResult<()> {
+    let bytes: Vec<u8> = data.iter().flat_map(|x| x.to_le_bytes()).collect();
+    write_bytes(stream, &bytes)
+}
+
+fn read_f32s(stream: &mut TcpStream) -> MlResult<Vec<f32>> {
+    let bytes = read_bytes(stream)?;
+    if bytes.len() % 4 != 0 {
+        return Err(format!("Received {} bytes, not a whole number of f32s", bytes.len()).into());
+    }
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect())
+}
+
+fn read_f32s_into(stream: &mut TcpStream, data: &mut [f32]) -> MlResult<()> {
+    let values = read_f32s(stream)?;
+    if values.len() != data.len() {
+        return Err(format!("Expected {} values, received {}", data.len(), values.len()).into());
+    }
+    data.copy_from_slice(&values);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tcp_collectives() -> MlResult<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let world_size = 3;
+
+        let run = |mut group: TcpGroup| -> MlResult<()> {
+            let rank = group.rank() as f32;
+            let mut data = [rank, 10.0 * rank];
+            group.all_reduce(&mut data, ReduceOp::Sum)?;
+            assert_eq!(data, [3.0, 30.0]);
+            let mut data = [rank];
+            group.all_reduce(&mut data, ReduceOp::Max)?;
+            assert_eq!(data, [2.0]);
+            let mut data = [rank];
+            group.all_reduce(&mut data, ReduceOp::Mean)?;
+            assert_eq!(data, [1.0]);
+
+            let mut data = [rank; 2];
+            group.broadcast(&mut data, 2)?;
+            assert_eq!(data, [2.0; 2]);
+            let gathered = group.all_gather(&vec![rank; group.rank() + 1])?;
+            assert_eq!(gathered, [vec![0.0], vec![1.0; 2], vec![2.0; 3]]);
+            group.barrier()?;
+
+            let mut bytes = vec![group.rank() as u8; group.rank()];
+            group.broadcast_bytes(&mut bytes, 1)?;
+            assert_eq!(bytes, [1]);
+            Ok(())
+        };
+
+        let workers: Vec<_> = (1..world_size)
+            .map(|rank| std::thread::spawn(move || run(TcpGroup::connect(addr, rank, world_size)?)))
+            .collect();
+        run(TcpGroup::host(listener, world_size)?)?;
+        for worker in workers {
+            worker.join().unwrap()?;
+        }
+        Ok(())
+    }
+}
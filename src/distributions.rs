@@ -0,0 +1,520 @@
+//! Probability distributions over tensors.
+//!
+//! Every [`Distribution`] can be sampled and score values with [`Distribution::log_prob`].
+//! Those that also implement [`Reparameterize`] draw samples as a differentiable function of
+//! their parameters and independent noise, the reparameterization trick, so a loss on the
+//! sample can be backpropagated to the parameters: [`Reparameterize::rsample`] keeps the
+//! noise and [`Reparameterize::rsample_backward`] turns the sample's gradient into the
+//! parameters' ones.
+//!
+//! Categorical samples aren't differentiable, but the Gumbel-softmax (or Concrete)
+//! relaxation in [`RelaxedOneHotCategorical`] is, and the [`GumbelSoftmax`] layer puts it
+//! in a model.
+//!
+//! ```ignore
+//! let mut rng = SimpleRng::new(0);
+//! let posterior = Normal::new(mean, std)?;
+//! let z = posterior.rsample(&mut rng)?;
+//! let grad_z = decoder.backward(&z.value, &grad_output, lr)?;
+//! let (grad_mean, grad_std) = posterior.rsample_backward(&z, &grad_z)?;
+//! ```
+
+use std::cell::RefCell;
+use std::f32::consts::PI;
+
+use crate::nn::random::{next_seed, SimpleRng};
+use crate::nn::Layer;
+use crate::tensor::{Tensor, TensorError};
+use crate::MlResult;
+
+/// A distribution over tensors of a fixed shape.
+pub trait Distribution {
+    /// Draws a sample.
+    fn sample(&self, rng: &mut SimpleRng) -> MlResult<Tensor>;
+
+    /// The log density, or log probability for discrete distributions, of each value.
+    fn log_prob(&self, value: &Tensor) -> MlResult<Tensor>;
+}
+
+/// A sample drawn with the reparameterization trick: `value` is a deterministic function
+/// of the distribution's parameters and of `noise`, drawn from a fixed distribution.
+#[derive(Debug, Clone)]
+pub struct RSample {
+    pub value: Tensor,
+    pub noise: Tensor,
+}
+
+/// A distribution whose samples can be differentiated with respect to its parameters.
+pub trait Reparameterize: Distribution {
+    /// The gradients of the distribution's parameters.
+    type Grad;
+
+    /// Draws a sample, keeping the noise it was computed from.
+    fn rsample(&self, rng: &mut SimpleRng) -> MlResult<RSample>;
+
+    /// Backpropagates `grad`, the gradient of a loss with respect to `sample.value`, to
+    /// the parameters `sample` was drawn with.
+    fn rsample_backward(&self, sample: &RSample, grad: &Tensor) -> MlResult<Self::Grad>;
+}
+
+/// The normal distribution with mean `loc` and standard deviation `scale`, elementwise.
+#[derive(Debug, Clone)]
+pub struct Normal {
+    loc: Tensor,
+    scale: Tensor,
+}
+
+impl Normal {
+    pub fn new(loc: Tensor, scale: Tensor) -> MlResult<Self> {
+        check_shape(&loc, &scale)?;
+        if scale.data().iter().any(|&s| s <= 0.0) {
+            return Err("Normal needs a positive scale".into());
+        }
+        Ok(Self { loc, scale })
+    }
+
+    pub fn loc(&self) -> &Tensor {
+        &self.loc
+    }
+
+    pub fn scale(&self) -> &Tensor {
+        &self.scale
+    }
+}
+
+impl Distribution for Normal {
+    fn sample(&self, rng: &mut SimpleRng) -> MlResult<Tensor> {
+        Ok(self.rsample(rng)?.value)
+    }
+
+    fn log_prob(&self, value: &Tensor) -> MlResult<Tensor> {
+        check_shape(&self.loc, value)?;
+        let data = value
+            .data()
+            .iter()
+            .zip(self.loc.data().iter().zip(self.scale.data()))
+            .map(|(&x, (&mu, &sigma))| {
+                let z = (x - mu) / sigma;
+                -0.5 * z * z - sigma.ln() - 0.5 * (2.0 * PI).ln()
+            })
+            .collect();
+        Tensor::from_vec(data, value.shape())
+    }
+}
+
+impl Reparameterize for Normal {
+    /// The gradients of `loc` and `scale`.
+    type Grad = (Tensor, Tensor);
+
+    // value = loc + scale * noise, noise ~ N(0, 1)
+    fn rsample(&self, rng: &mut SimpleRng) -> MlResult<RSample> {
+        let noise = Tensor::from_vec(
+            (0..self.loc.data().len())
+                .map(|_| standard_normal(rng))
+                .collect(),
+            self.loc.shape(),
+        )?;
+        let value = self.loc.add(&self.scale.mul(&noise)?)?;
+        Ok(RSample { value, noise })
+    }
+
+    fn rsample_backward(&self, sample: &RSample, grad: &Tensor) -> MlResult<(Tensor, Tensor)> {
+        check_shape(&self.loc, grad)?;
+        Ok((grad.clone(), grad.mul(&sample.noise)?))
+    }
+}
+
+/// The categorical distribution over the last axis of `logits`, which are unnormalized
+/// log probabilities. Samples and values are class indices stored as f32, one per row.
+#[derive(Debug, Clone)]
+pub struct Categorical {
+    logits: Tensor,
+}
+
+impl Categorical {
+    pub fn new(logits: Tensor) -> MlResult<Self> {
+        classes(&logits)?;
+        Ok(Self { logits })
+    }
+
+    pub fn logits(&self) -> &Tensor {
+        &self.logits
+    }
+
+    /// The probability of each class.
+    pub fn probs(&self) -> MlResult<Tensor> {
+        let k = classes(&self.logits)?;
+        let data = self.logits.data().chunks(k).flat_map(softmax).collect();
+        Tensor::from_vec(data, self.logits.shape())
+    }
+
+    // The shape of a sample: the logits' without the class axis
+    fn batch_shape(&self) -> &[usize] {
+        &self.logits.shape()[..self.logits.shape().len() - 1]
+    }
+}
+
+impl Distribution for Categorical {
+    // The Gumbel-max trick: the argmax of the logits plus Gumbel noise
+    fn sample(&self, rng: &mut SimpleRng) -> MlResult<Tensor> {
+        let k = classes(&self.logits)?;
+        let data = self
+            .logits
+            .data()
+            .chunks(k)
+            .map(|row| {
+                let perturbed = row.iter().map(|&l| l + gumbel(rng));
+                argmax(perturbed) as f32
+            })
+            .collect();
+        Tensor::from_vec(data, self.batch_shape())
+    }
+
+    fn log_prob(&self, value: &Tensor) -> MlResult<Tensor> {
+        let k = classes(&self.logits)?;
+        let rows = self.logits.data().chunks(k);
+        if value.data().len() != rows.len() {
+            return Err(TensorError::InvalidShape {
+                expected: self.batch_shape().to_vec(),
+                got: value.shape().to_vec(),
+            }
+            .into());
+        }
+        let data = rows
+            .zip(value.data())
+            .map(|(row, &class)| match row.get(class as usize) {
+                Some(&logit) if class >= 0.0 && class.fract() == 0.0 => {
+                    Ok(logit - log_sum_exp(row.iter().copied()))
+                }
+                _ => Err(format!("{} is not a class index below {}", class, k).into()),
+            })
+            .collect::<MlResult<_>>()?;
+        Tensor::from_vec(data, value.shape())
+    }
+}
+
+/// The Gumbel-softmax, or Concrete, relaxation of a one-hot [`Categorical`]: samples are
+/// `softmax((logits + g) / temperature)` for Gumbel noise `g`, points on the simplex that
+/// approach one-hot vectors as the temperature goes to 0.
+#[derive(Debug, Clone)]
+pub struct RelaxedOneHotCategorical {
+    logits: Tensor,
+    temperature: f32,
+}
+
+impl RelaxedOneHotCategorical {
+    pub fn new(logits: Tensor, temperature: f32) -> MlResult<Self> {
+        classes(&logits)?;
+        if temperature <= 0.0 {
+            return Err("The temperature must be positive".into());
+        }
+        Ok(Self {
+            logits,
+            temperature,
+        })
+    }
+
+    pub fn logits(&self) -> &Tensor {
+        &self.logits
+    }
+
+    pub fn temperature(&self) -> f32 {
+        self.temperature
+    }
+}
+
+impl Distribution for RelaxedOneHotCategorical {
+    fn sample(&self, rng: &mut SimpleRng) -> MlResult<Tensor> {
+        Ok(self.rsample(rng)?.value)
+    }
+
+    // The Concrete density of Maddison et al.:
+    // log((k-1)!) + (k-1) log(t) + sum(l - (t+1) log(y)) - k logsumexp(l - t log(y))
+    fn log_prob(&self, value: &Tensor) -> MlResult<Tensor> {
+        check_shape(&self.logits, value)?;
+        let k = classes(&self.logits)?;
+        let t = self.temperature;
+        let log_norm = (1..k).map(|i| (i as f32).ln()).sum::<f32>() + (k - 1) as f32 * t.ln();
+        let data = self
+            .logits
+            .data()
+            .chunks(k)
+            .zip(value.data().chunks(k))
+            .map(|(logits, y)| {
+                let terms = logits.iter().zip(y);
+                let sum: f32 = terms.clone().map(|(&l, &y)| l - (t + 1.0) * y.ln()).sum();
+                log_norm + sum - k as f32 * log_sum_exp(terms.map(|(&l, &y)| l - t * y.ln()))
+            })
+            .collect();
+        Tensor::from_vec(data, &self.logits.shape()[..self.logits.shape().len() - 1])
+    }
+}
+
+impl Reparameterize for RelaxedOneHotCategorical {
+    /// The gradient of the logits.
+    type Grad = Tensor;
+
+    fn rsample(&self, rng: &mut SimpleRng) -> MlResult<RSample> {
+        let noise = Tensor::from_vec(
+            (0..self.logits.data().len()).map(|_| gumbel(rng)).collect(),
+            self.logits.shape(),
+        )?;
+        let value = relaxed_softmax(&self.logits, &noise, self.temperature)?;
+        Ok(RSample { value, noise })
+    }
+
+    fn rsample_backward(&self, sample: &RSample, grad: &Tensor) -> MlResult<Tensor> {
+        check_shape(&self.logits, grad)?;
+        softmax_backward(&sample.value, grad, self.temperature)
+    }
+}
+
+/// A layer drawing a Gumbel-softmax sample from its input logits, which lets a model make
+/// discrete choices and still be trained by backpropagation.
+///
+/// With `hard`, outputs are one-hot, but gradients are those of the relaxed sample (the
+/// straight-through estimator). The noise of the latest forward pass is kept for the
+/// backward pass, so call them in pairs.
+pub struct GumbelSoftmax {
+    temperature: f32,
+    hard: bool,
+    rng: RefCell<SimpleRng>,
+    noise: RefCell<Option<Tensor>>,
+}
+
+impl GumbelSoftmax {
+    pub fn new(temperature: f32, hard: bool) -> MlResult<Self> {
+        if temperature <= 0.0 {
+            return Err("The temperature must be positive".into());
+        }
+        Ok(Self {
+            temperature,
+            hard,
+            rng: RefCell::new(SimpleRng::new(next_seed())),
+            noise: RefCell::new(None),
+        })
+    }
+
+    /// Sets the temperature, e.g. to anneal it during training.
+    pub fn set_temperature(&mut self, temperature: f32) {
+        self.temperature = temperature;
+    }
+
+    pub fn temperature(&self) -> f32 {
+        self.temperature
+    }
+}
+
+impl Layer for GumbelSoftmax {
+    fn forward(&self, input: &Tensor) -> MlResult<Tensor> {
+        let distribution = RelaxedOneHotCategorical::new(input.clone(), self.temperature)?;
+        let sample = distribution.rsample(&mut self.rng.borrow_mut())?;
+        *self.noise.borrow_mut() = Some(sample.noise);
+        if !self.hard {
+            return Ok(sample.value);
+        }
+
+        let k = classes(input)?;
+        let data = sample
+            .value
+            .data()
+            .chunks(k)
+            .flat_map(|row| {
+                let hot = argmax(row.iter().copied());
+                (0..k).map(move |i| if i == hot { 1.0 } else { 0.0 })
+            })
+            .collect();
+        Tensor::from_vec(data, input.shape())
+    }
+
+    fn backward(&mut self, input: &Tensor, grad_output: &Tensor, _: f32) -> MlResult<Tensor> {
+        let noise = self
+            .noise
+            .borrow()
+            .clone()
+            .ok_or("GumbelSoftmax::backward needs a forward pass first")?;
+        check_shape(input, &noise)?;
+        check_shape(input, grad_output)?;
+        let soft = relaxed_softmax(input, &noise, self.temperature)?;
+        softmax_backward(&soft, grad_output, self.temperature)
+    }
+}
+
+fn check_shape(expected: &Tensor, got: &Tensor) -> MlResult<()> {
+    if expected.shape() != got.shape() {
+        return Err(TensorError::InvalidShape {
+            expected: expected.shape().to_vec(),
+            got: got.shape().to_vec(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+// The size of the last axis, which holds the classes
+fn classes(logits: &Tensor) -> MlResult<usize> {
+    match logits.shape().last() {
+        Some(&k) if k > 0 => Ok(k),
+        _ => Err(format!(
+            "Expected logits with a class axis, got shape {:?}",
+            logits.shape()
+        )
+        .into()),
+    }
+}
+
+// softmax((logits + noise) / temperature) along the last axis
+fn relaxed_softmax(logits: &Tensor, noise: &Tensor, temperature: f32) -> MlResult<Tensor> {
+    let k = classes(logits)?;
+    let perturbed: Vec<f32> = logits
+        .data()
+        .iter()
+        .zip(noise.data())
+        .map(|(&l, &g)| (l + g) / temperature)
+        .collect();
+    let data = perturbed.chunks(k).flat_map(softmax).collect();
+    Tensor::from_vec(data, logits.shape())
+}
+
+// The gradient of the logits of y = softmax((logits + g) / t):
+// y * (grad - sum(grad * y)) / t along the last axis
+fn softmax_backward(y: &Tensor, grad: &Tensor, temperature: f32) -> MlResult<Tensor> {
+    let k = classes(y)?;
+    let data = y
+        .data()
+        .chunks(k)
+        .zip(grad.data().chunks(k))
+        .flat_map(|(y, g)| {
+            let dot: f32 = y.iter().zip(g).map(|(y, g)| y * g).sum();
+            y.iter()
+                .zip(g)
+                .map(move |(y, g)| y * (g - dot) / temperature)
+        })
+        .collect();
+    Tensor::from_vec(data, y.shape())
+}
+
+fn softmax(row: &[f32]) -> Vec<f32> {
+    let max = row.iter().fold(f32::NEG_INFINITY, |m, &x| m.max(x));
+    let exps: Vec<f32> = row.iter().map(|&x| (x - max).exp()).collect();
+    let sum: f32 = exps.iter().sum();
+    exps.into_iter().map(|e| e / sum).collect()
+}
+
+fn log_sum_exp(values: impl Iterator<Item = f32> + Clone) -> f32 {
+    let max = values.clone().fold(f32::NEG_INFINITY, f32::max);
+    max + values.map(|x| (x - max).exp()).sum::<f32>().ln()
+}
+
+fn argmax(values: impl Iterator<Item = f32>) -> usize {
+    values
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map_or(0, |(i, _)| i)
+}
+
+// Box-Muller, with 1 - u in (0, 1] so the logarithm stays finite
+fn standard_normal(rng: &mut SimpleRng) -> f32 {
+    let u = 1.0 - rng.next_f32();
+    let v = rng.next_f32();
+    (-2.0 * u.ln()).sqrt() * (2.0 * PI * v).cos()
+}
+
+// -log(-log(u)) for u in (0, 1)
+fn gumbel(rng: &mut SimpleRng) -> f32 {
+    let u = rng.next_f32().max(f32::MIN_POSITIVE);
+    -(-u.ln()).ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reparameterized_sampling() -> MlResult<()> {
+        let mut rng = SimpleRng::new(17);
+
+        // Normal samples have the right moments, and d value / d scale is the noise
+        let n = 4000;
+        let normal = Normal::new(
+            Tensor::from_vec(vec![2.0; n], &[n])?,
+            Tensor::from_vec(vec![0.5; n], &[n])?,
+        )?;
+        let sample = normal.rsample(&mut rng)?;
+        let mean = sample.value.data().iter().sum::<f32>() / n as f32;
+        let var = sample
+            .value
+            .data()
+            .iter()
+            .map(|x| (x - mean).powi(2))
+            .sum::<f32>()
+            / n as f32;
+        assert!((mean - 2.0).abs() < 0.05, "{}", mean);
+        assert!((var.sqrt() - 0.5).abs() < 0.05, "{}", var);
+        let (grad_loc, grad_scale) =
+            normal.rsample_backward(&sample, &Tensor::from_vec(vec![1.0; n], &[n])?)?;
+        assert_eq!(grad_loc.data()[0], 1.0);
+        assert_eq!(grad_scale.data(), sample.noise.data());
+        let log_prob = normal.log_prob(&Tensor::from_vec(vec![2.0; n], &[n])?)?;
+        assert!((log_prob.data()[0] - -(0.5 * (2.0 * PI).ln() + 0.5f32.ln())).abs() < 1e-6);
+
+        // The Gumbel-max trick samples classes in proportion to their probabilities
+        let logits = Tensor::from_vec(vec![0.0, 1.0f32.ln(), 2.0f32.ln()], &[1, 3])?;
+        let categorical = Categorical::new(logits.clone())?;
+        let mut counts = [0; 3];
+        for _ in 0..3000 {
+            counts[categorical.sample(&mut rng)?.data()[0] as usize] += 1;
+        }
+        assert!(
+            (counts[2] as f32 / 3000.0 - 0.5).abs() < 0.05,
+            "{:?}",
+            counts
+        );
+        let log_prob = categorical.log_prob(&Tensor::from_vec(vec![2.0], &[1])?)?;
+        assert!((log_prob.data()[0] - 0.5f32.ln()).abs() < 1e-6);
+        assert!(categorical
+            .log_prob(&Tensor::from_vec(vec![3.0], &[1])?)
+            .is_err());
+
+        // Relaxed samples lie on the simplex and sharpen as the temperature drops
+        let relaxed = RelaxedOneHotCategorical::new(logits.clone(), 0.1)?;
+        let sample = relaxed.rsample(&mut rng)?;
+        assert!((sample.value.data().iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        assert!(sample.value.data().iter().any(|&p| p > 0.9));
+        assert!(relaxed.log_prob(&sample.value)?.data()[0].is_finite());
+
+        // Its gradient matches finite differences
+        let grad = Tensor::from_vec(vec![1.0, -2.0, 0.5], &[1, 3])?;
+        let relaxed = RelaxedOneHotCategorical::new(logits.clone(), 0.7)?;
+        let sample = relaxed.rsample(&mut rng)?;
+        let analytic = relaxed.rsample_backward(&sample, &grad)?;
+        let loss = |logits: Vec<f32>| -> MlResult<f32> {
+            let y = relaxed_softmax(&Tensor::from_vec(logits, &[1, 3])?, &sample.noise, 0.7)?;
+            Ok(y.data().iter().zip(grad.data()).map(|(y, g)| y * g).sum())
+        };
+        for i in 0..3 {
+            let (mut up, mut down) = (logits.data().to_vec(), logits.data().to_vec());
+            up[i] += 1e-3;
+            down[i] -= 1e-3;
+            let numeric = (loss(up)? - loss(down)?) / 2e-3;
+            assert!(
+                (numeric - analytic.data()[i]).abs() < 1e-2,
+                "{} vs {}",
+                numeric,
+                analytic.data()[i]
+            );
+        }
+
+        // The hard layer emits one-hot rows and passes the relaxed gradient
+        let mut layer = GumbelSoftmax::new(0.5, true)?;
+        let input = Tensor::from_vec(vec![0.1, 0.2, 0.3, 3.0, -1.0, 0.0], &[2, 3])?;
+        let output = layer.forward(&input)?;
+        for row in output.data().chunks(3) {
+            assert_eq!(row.iter().sum::<f32>(), 1.0);
+        }
+        let grad = layer.backward(&input, &Tensor::from_vec(vec![1.0; 6], &[2, 3])?, 0.1)?;
+        // A constant upstream gradient doesn't change a softmax
+        assert!(grad.data().iter().all(|g| g.abs() < 1e-6));
+        Ok(())
+    }
+}
@@ -0,0 +1,451 @@
+//! Autoregressive text generation.
+//!
+//! [`generate`] extends each prompt one token at a time with a [`LanguageModel`], which
+//! keeps the keys and values of the tokens it has seen in a [`KVCache`] so each step only
+//! runs on the newest token. [`GenerationConfig`] picks how the next token is chosen:
+//!
+//! - [`Strategy::Greedy`] takes the most likely token
+//! - [`Strategy::Sample`] draws it from the model's distribution, reshaped by the
+//!   temperature and cut down to the `top_k` most likely tokens or the smallest set whose
+//!   probability reaches `top_p` (nucleus sampling)
+//! - [`Strategy::BeamSearch`] keeps the most likely continuations so far and returns the
+//!   best one that finished
+//!
+//! A repetition penalty above 1 makes tokens already in the sequence less likely, for every
+//! strategy.
+//!
+//! ```ignore
+//! let config = GenerationConfig {
+//!     strategy: Strategy::Sample,
+//!     temperature: 0.8,
+//!     top_p: Some(0.9),
+//!     eos_token: Some(tokenizer.eos()),
+//!     ..GenerationConfig::default()
+//! };
+//! let completions = generate(&model, &[prompt], &config, &mut SimpleRng::new(0))?;
+//! ```
+
+use crate::nn::random::SimpleRng;
+use crate::nn::KVCache;
+use crate::tensor::Tensor;
+use crate::MlResult;
+
+/// A model that scores the token following each sequence of a batch.
+pub trait LanguageModel {
+    /// The `[batch, vocab]` logits of the token after each row of `tokens`, the `[batch,
+    /// len]` ids (stored as f32) of the tokens following those whose keys and values
+    /// `cache` holds. The model appends the new tokens' keys and values to `cache`.
+    fn forward_cached(&self, tokens: &Tensor, cache: &mut KVCache) -> MlResult<Tensor>;
+
+    /// How many layers the model keeps in its cache.
+    fn num_layers(&self) -> usize;
+}
+
+/// How [`generate`] picks each token.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strategy {
+    Greedy,
+    Sample,
+    /// Keeps the `beams` most likely sequences. Finished ones are ranked by their log
+    /// probability divided by `length ^ length_penalty`, so a penalty above 0 favours longer
+    /// sequences.
+    BeamSearch {
+        beams: usize,
+        length_penalty: f32,
+    },
+}
+
+/// Settings for [`generate`].
+#[derive(Debug, Clone)]
+pub struct GenerationConfig {
+    pub strategy: Strategy,
+    pub max_new_tokens: usize,
+    /// Divides the logits before sampling: below 1 sharpens the distribution, above 1
+    /// flattens it.
+    pub temperature: f32,
+    /// Samples only among the `k` most likely tokens.
+    pub top_k: Option<usize>,
+    /// Samples only among the most likely tokens whose probabilities add up to `p`.
+    pub top_p: Option<f32>,
+    /// Divides the positive logits of tokens already in the sequence, and multiplies the
+    /// negative ones; 1 leaves them as they are.
+    pub repetition_penalty: f32,
+    /// The token that ends a sequence. It is included in the output.
+    pub eos_token: Option<usize>,
+}
+
+impl Default for GenerationConfig {
+    fn default() -> Self {
+        Self {
+            strategy: Strategy::Greedy,
+            max_new_tokens: 32,
+            temperature: 1.0,
+            top_k: None,
+            top_p: None,
+            repetition_penalty: 1.0,
+            eos_token: None,
+        }
+    }
+}
+
+/// The tokens `model` generates after each of `prompts`, which must have the same length.
+/// Each continuation stops after the end-of-sequence token or `max_new_tokens` tokens.
+pub fn generate<M: LanguageModel + ?Sized>(
+    model: &M,
+    prompts: &[Vec<usize>],
+    config: &GenerationConfig,
+    rng: &mut SimpleRng,
+) -> MlResult<Vec<Vec<usize>>> {
+    let len = prompts.first().map_or(0, Vec::len);
+    if len == 0 || prompts.iter().any(|prompt| prompt.len() != len) {
+        return Err("Prompts must be non-empty and of the same length".into());
+    }
+    if config.temperature <= 0.0 || config.repetition_penalty <= 0.0 {
+        return Err("The temperature and repetition penalty must be positive".into());
+    }
+    match config.strategy {
+        Strategy::BeamSearch {
+            beams,
+            length_penalty,
+        } => {
+            if beams == 0 {
+                return Err("Beam search needs at least one beam".into());
+            }
+            beam_search(model, prompts, config, beams, length_penalty)
+        }
+        _ => decode(model, prompts, config, rng),
+    }
+}
+
+// Greedy decoding or sampling, one token per sequence per step
+fn decode<M: LanguageModel + ?Sized>(
+    model: &M,
+    prompts: &[Vec<usize>],
+    config: &GenerationConfig,
+    rng: &mut SimpleRng,
+) -> MlResult<Vec<Vec<usize>>> {
+    let mut cache = KVCache::new(model.num_layers());
+    let mut sequences = prompts.to_vec();
+    let mut outputs = vec![Vec::new(); prompts.len()];
+    let mut done = vec![false; prompts.len()];
+
+    let mut input = tokens(prompts)?;
+    for _ in 0..config.max_new_tokens {
+        let logits = model.forward_cached(&input, &mut cache)?;
+        let vocab = vocab_size(&logits, prompts.len())?;
+        let mut next = Vec::with_capacity(prompts.len());
+        for (i, row) in logits.data().chunks(vocab).enumerate() {
+            let mut row = row.to_vec();
+            penalize_repetitions(&mut row, &sequences[i], config.repetition_penalty);
+            let token = match config.strategy {
+                Strategy::Sample => sample(&mut row, config, rng),
+                _ => argmax(&row),
+            };
+            if !done[i] {
+                outputs[i].push(token);
+                done[i] = Some(token) == config.eos_token;
+            }
+            sequences[i].push(token);
+            next.push(vec![token]);
+        }
+        if done.iter().all(|&d| d) {
+            break;
+        }
+        input = tokens(&next)?;
+    }
+    Ok(outputs)
+}
+
+// A sequence beam search is extending, and its log probability
+#[derive(Debug, Clone)]
+struct Beam {
+    tokens: Vec<usize>,
+    score: f32,
+}
+
+fn beam_search<M: LanguageModel + ?Sized>(
+    model: &M,
+    prompts: &[Vec<usize>],
+    config: &GenerationConfig,
+    beams: usize,
+    length_penalty: f32,
+) -> MlResult<Vec<Vec<usize>>> {
+    // Each prompt runs as `beams` rows; only the first is live at the start, so the first
+    // step picks distinct tokens
+    let mut rows = Vec::new();
+    let mut active = Vec::new();
+    for prompt in prompts {
+        for beam in 0..beams {
+            rows.push(prompt.clone());
+            active.push(Beam {
+                tokens: Vec::new(),
+                score: if beam == 0 { 0.0 } else { f32::NEG_INFINITY },
+            });
+        }
+    }
+    let mut finished: Vec<Vec<Beam>> = vec![Vec::new(); prompts.len()];
+    let normalized = |beam: &Beam| beam.score / (beam.tokens.len() as f32).powf(length_penalty);
+
+    let mut cache = KVCache::new(model.num_layers());
+    let mut input = tokens(&rows)?;
+    for _ in 0..config.max_new_tokens {
+        let logits = model.forward_cached(&input, &mut cache)?;
+        let vocab = vocab_size(&logits, rows.len())?;
+        let mut log_probs = logits.data().to_vec();
+        for (row, (chunk, prompt)) in log_probs.chunks_mut(vocab).zip(&rows).enumerate() {
+            let mut seen = prompt.clone();
+            seen.extend(&active[row].tokens);
+            penalize_repetitions(chunk, &seen, config.repetition_penalty);
+            log_softmax(chunk);
+        }
+
+        let mut parents = Vec::with_capacity(rows.len());
+        let mut next = Vec::with_capacity(rows.len());
+        for (p, finished) in finished.iter_mut().enumerate() {
+            let first = p * beams;
+            if finished.len() >= beams {
+                // Done: the rows keep running on their last tokens, and are ignored
+                parents.extend(first..first + beams);
+                next.extend(active[first..first + beams].iter().cloned());
+                continue;
+            }
+
+            let mut candidates: Vec<(f32, usize, usize)> = (first..first + beams)
+                .flat_map(|row| {
+                    let scores = &log_probs[row * vocab..(row + 1) * vocab];
+                    let score = active[row].score;
+                    scores
+                        .iter()
+                        .enumerate()
+                        .map(move |(t, lp)| (score + lp, row, t))
+                })
+                .filter(|(score, ..)| score.is_finite())
+                .collect();
+            candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+            let mut extended = 0;
+            for (score, row, token) in candidates {
+                let mut tokens = active[row].tokens.clone();
+                tokens.push(token);
+                let beam = Beam { tokens, score };
+                if Some(token) == config.eos_token {
+                    finished.push(beam);
+                } else {
+                    parents.push(row);
+                    next.push(beam);
+                    extended += 1;
+                }
+                if extended == beams {
+                    break;
+                }
+            }
+            // Fewer candidates than beams: fill up with rows that are never extended
+            for _ in extended..beams {
+                parents.push(first);
+                next.push(Beam {
+                    score: f32::NEG_INFINITY,
+                    ..active[first].clone()
+                });
+            }
+        }
+
+        active = next;
+        if finished.iter().all(|f| f.len() >= beams) {
+            break;
+        }
+        cache.reorder(&parents)?;
+        let last: Vec<Vec<usize>> = active
+            .iter()
+            .zip(&rows)
+            .map(|(beam, prompt)| vec![*beam.tokens.last().or(prompt.last()).unwrap_or(&0)])
+            .collect();
+        input = tokens(&last)?;
+    }
+
+    // Beams still going at the end count as finished
+    let mut outputs = Vec::with_capacity(prompts.len());
+    for (p, mut finished) in finished.into_iter().enumerate() {
+        let beams = &active[p * beams..(p + 1) * beams];
+        finished.extend(beams.iter().filter(|b| b.score.is_finite()).cloned());
+        let best = finished
+            .into_iter()
+            .max_by(|a, b| normalized(a).total_cmp(&normalized(b)));
+        outputs.push(best.map(|beam| beam.tokens).unwrap_or_default());
+    }
+    Ok(outputs)
+}
+
+// The `[batch, len]` tensor of `rows` of ids
+fn tokens(rows: &[Vec<usize>]) -> MlResult<Tensor> {
+    let len = rows.first().map_or(0, Vec::len);
+    let data = rows.iter().flatten().map(|&t| t as f32).collect();
+    Tensor::from_vec(data, &[rows.len(), len])
+}
+
+fn vocab_size(logits: &Tensor, batch: usize) -> MlResult<usize> {
+    match *logits.shape() {
+        [rows, vocab] if rows == batch && vocab > 0 => Ok(vocab),
+        _ => Err(format!(
+            "Expected [{}, vocab] logits from the model, got {:?}",
+            batch,
+            logits.shape()
+        )
+        .into()),
+    }
+}
+
+fn penalize_repetitions(logits: &mut [f32], seen: &[usize], penalty: f32) {
+    if penalty == 1.0 {
+        return;
+    }
+    let vocab = logits.len();
+    let mut penalized = vec![false; vocab];
+    for &token in seen.iter().filter(|&&t| t < vocab) {
+        if !std::mem::replace(&mut penalized[token], true) {
+            let logit = &mut logits[token];
+            *logit = if *logit > 0.0 {
+                *logit / penalty
+            } else {
+                *logit * penalty
+            };
+        }
+    }
+}
+
+// Draws a token from `logits` after the temperature, top-k and top-p filters
+fn sample(logits: &mut [f32], config: &GenerationConfig, rng: &mut SimpleRng) -> usize {
+    logits.iter_mut().for_each(|l| *l /= config.temperature);
+    let mut order: Vec<usize> = (0..logits.len()).collect();
+    order.sort_by(|&a, &b| logits[b].total_cmp(&logits[a]));
+    let k = config.top_k.unwrap_or(logits.len()).clamp(1, logits.len());
+    order.truncate(k);
+
+    let max = logits[order[0]];
+    let mut probs: Vec<f32> = order.iter().map(|&t| (logits[t] - max).exp()).collect();
+    let total: f32 = probs.iter().sum();
+    probs.iter_mut().for_each(|p| *p /= total);
+    if let Some(top_p) = config.top_p {
+        // The smallest prefix whose probability reaches top_p, always at least one token
+        let mut cumulative = 0.0;
+        let keep = probs
+            .iter()
+            .position(|p| {
+                cumulative += p;
+                cumulative >= top_p
+            })
+            .map_or(probs.len(), |i| i + 1);
+        probs.truncate(keep);
+    }
+
+    let mut u = rng.next_f32() * probs.iter().sum::<f32>();
+    for (&token, p) in order.iter().zip(&probs) {
+        if u < *p {
+            return token;
+        }
+        u -= p;
+    }
+    order[probs.len() - 1]
+}
+
+fn log_softmax(row: &mut [f32]) {
+    let max = row.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let log_sum = max + row.iter().map(|x| (x - max).exp()).sum::<f32>().ln();
+    row.iter_mut().for_each(|x| *x -= log_sum);
+}
+
+fn argmax(values: &[f32]) -> usize {
+    values
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map_or(0, |(i, _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Scores the next token from the last one alone, with one row of log probabilities
+    // per token
+    struct Bigram(Vec<[f32; 4]>);
+
+    impl LanguageModel for Bigram {
+        fn forward_cached(&self, tokens: &Tensor, _: &mut KVCache) -> MlResult<Tensor> {
+            let len = tokens.shape()[1];
+            let data = (tokens.data().chunks(len))
+                .flat_map(|row| self.0[row[len - 1] as usize].map(f32::ln))
+                .collect();
+            Tensor::from_vec(data, &[tokens.shape()[0], 4])
+        }
+
+        fn num_layers(&self) -> usize {
+            0
+        }
+    }
+
+    #[test]
+    fn test_generate() -> MlResult<()> {
+        let model = Bigram(vec![
+            [0.0, 0.6, 0.4, 0.0],
+            [0.3, 0.5, 0.15, 0.05],
+            [0.0, 0.05, 0.05, 0.9],
+            [1.0, 0.0, 0.0, 0.0],
+        ]);
+        let mut rng = SimpleRng::new(0);
+        let config = GenerationConfig {
+            max_new_tokens: 3,
+            eos_token: Some(3),
+            ..GenerationConfig::default()
+        };
+        let greedy = generate(&model, &[vec![0], vec![2]], &config, &mut rng)?;
+        assert_eq!(greedy, [vec![1, 1, 1], vec![3]]);
+
+        // Penalizing repeats moves off tokens already generated
+        let penalized = GenerationConfig {
+            repetition_penalty: 10.0,
+            ..config.clone()
+        };
+        assert_eq!(
+            generate(&model, &[vec![0]], &penalized, &mut rng)?,
+            [vec![1, 2, 3]]
+        );
+
+        // Sampling from the single most likely token is greedy, and from the top two
+        // never picks a third
+        let top_1 = GenerationConfig {
+            strategy: Strategy::Sample,
+            top_k: Some(1),
+            ..config.clone()
+        };
+        assert_eq!(
+            generate(&model, &[vec![0]], &top_1, &mut rng)?,
+            [vec![1, 1, 1]]
+        );
+        let nucleus = GenerationConfig {
+            strategy: Strategy::Sample,
+            top_p: Some(0.5),
+            temperature: 0.5,
+            ..config.clone()
+        };
+        for _ in 0..20 {
+            let sampled = generate(&model, &[vec![1]], &nucleus, &mut rng)?;
+            assert!(sampled[0].iter().all(|&t| t == 1), "{:?}", sampled);
+        }
+
+        // 0 -> 2 -> eos is more likely than any continuation through 1, though 1 is the
+        // most likely first step
+        let beam = GenerationConfig {
+            strategy: Strategy::BeamSearch {
+                beams: 2,
+                length_penalty: 0.0,
+            },
+            ..config.clone()
+        };
+        let beams = generate(&model, &[vec![0], vec![1]], &beam, &mut rng)?;
+        assert_eq!(beams[0], [2, 3]);
+        assert_eq!(beams[1], [1, 1, 1]);
+
+        assert!(generate(&model, &[vec![0], vec![0, 1]], &config, &mut rng).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,636 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use super::{Graph, Node, NodeId, Op};
+use crate::backend::FusedOp;
+use crate::log::log_warn;
+use crate::memory;
+use crate::nn::Layer;
+use crate::tensor::{Fused, Tensor, TensorError};
+use crate::MlResult;
+
+thread_local! {
+    static TRACER: RefCell<Option<Tracer>> = const { RefCell::new(None) };
+}
+
+// The ops recorded so far as a graph, and the node that computed each tensor they made
+struct Tracer {
+    graph: Graph,
+    nodes: HashMap<u64, NodeId>,
+    // Tensors with ids from this one on were created during the trace
+    first_id: u64,
+    error: Option<String>,
+}
+
+impl Tracer {
+    // The node of `tensor`. Tensors from before the trace, such as weights, become
+    // constants; one created during it by an op that isn't recorded can't be replayed.
+    fn node(&mut self, tensor: &Tensor) -> Option<NodeId> {
+        let id = tensor.tensor_id();
+        if let Some(&node) = self.nodes.get(&id) {
+            return Some(node);
+        }
+        if id >= self.first_id {
+            self.error.get_or_insert_with(|| {
+                format!(
+                    "a {:?} tensor was computed by an op tracing doesn't record",
+                    tensor.shape()
+                )
+            });
+            return None;
+        }
+        let node = self.graph.constant(tensor.clone());
+        self.nodes.insert(id, node);
+        Some(node)
+    }
+
+    fn finish(mut self, example: &Tensor, output: &Tensor) -> MlResult<Trace> {
+        if let Some(error) = self.error {
+            return Err(format!("The forward pass can't be traced: {}", error).into());
+        }
+        let node =
+            self.nodes.get(&output.tensor_id()).copied().ok_or(
+                "The forward pass can't be traced: its output isn't computed by tensor ops",
+            )?;
+        self.graph.set_outputs(&[node])?;
+        self.graph.optimize()?;
+        Trace::compile(self.graph, example, output)
+    }
+}
+
+// Runs `f`, the op `op` applied to `input` and `others`, and records it if a trace is being
+// captured on this thread. The tracer is put aside while `f` runs, so the ops it is built
+// from aren't recorded too.
+pub(crate) fn record(
+    op: Op,
+    input: &Tensor,
+    others: &[&Tensor],
+    f: impl FnOnce() -> MlResult<Tensor>,
+) -> MlResult<Tensor> {
+    let Some(mut tracer) = TRACER.with(|tracer| tracer.borrow_mut().take()) else {
+        return f();
+    };
+
+    let result = f();
+    if let Ok(output) = &result {
+        let inputs = std::iter::once(input)
+            .chain(others.iter().copied())
+            .map(|tensor| tracer.node(tensor))
+            .collect::<Option<Vec<_>>>();
+        if let Some(inputs) = inputs {
+            let node = tracer
+                .graph
+                .push(op, &inputs)
+                .expect("tensor ops take as many inputs as their graph ops");
+            tracer.nodes.insert(output.tensor_id(), node);
+        }
+    }
+    TRACER.with(|slot| *slot.borrow_mut() = Some(tracer));
+    result
+}
+
+fn is_tracing() -> bool {
+    TRACER.with(|tracer| tracer.borrow().is_some())
+}
+
+/// Runs `layer` on `example` and captures the ops of its forward pass as a [`Trace`], which
+/// replays them for other inputs of the same shape.
+///
+/// Like any tracing JIT, this records the ops one pass happened to run: branches taken on
+/// the values of tensors are fixed, and the layer's weights are captured as they are now.
+/// Passes that compute a tensor outside the tensor ops, for instance from [`Tensor::data`],
+/// can't be traced and give an error.
+pub fn trace<L: Layer + ?Sized>(layer: &L, example: &Tensor) -> MlResult<Trace> {
+    capture(layer, example)?.1
+}
+
+// The output of `layer` on `example`, and its trace or why it couldn't be traced
+fn capture<L: Layer + ?Sized>(layer: &L, example: &Tensor) -> MlResult<(Tensor, MlResult<Trace>)> {
+    let mut tracer = Tracer {
+        graph: Graph::new(),
+        nodes: HashMap::new(),
+        first_id: memory::next_tensor_id(),
+        error: None,
+    };
+    let input = tracer.graph.input();
+    tracer.nodes.insert(example.tensor_id(), input);
+
+    let previous = TRACER.with(|slot| slot.borrow_mut().replace(tracer));
+    let output = layer.forward(example);
+    let tracer = TRACER.with(|slot| std::mem::replace(&mut *slot.borrow_mut(), previous));
+
+    let output = output?;
+    let trace = match tracer {
+        Some(tracer) => tracer.finish(example, &output),
+        None => Err("The trace was lost while the forward pass ran".into()),
+    };
+    Ok((output, trace))
+}
+
+/// A forward pass captured by [`trace`], for replaying on inputs of the shape it was traced
+/// with.
+///
+/// The traced graph is optimized first, so weights that only go through ops such as a
+/// transpose are prepared once. Runs of elementwise ops where each result only feeds the
+/// next are fused into one pass, and the host buffers of results no longer needed are kept
+/// for later fused steps to write into, so a replay mostly reuses the memory allocated
+/// when the trace was compiled.
+pub struct Trace {
+    graph: Graph,
+    input_shape: Vec<usize>,
+    steps: Vec<Step>,
+    // Host buffers free for fused steps to write into, by length
+    buffers: RefCell<HashMap<usize, Vec<Vec<f32>>>>,
+}
+
+// A node computed during a replay, and the values no later step reads once it is done
+struct Step {
+    node: NodeId,
+    compute: Compute,
+    len: usize,
+    free: Vec<NodeId>,
+}
+
+enum Compute {
+    // The node's own op
+    Op,
+    // Elementwise ops chained over `inputs`, ending with the node's
+    Fused {
+        inputs: Vec<NodeId>,
+        ops: Vec<FusedOp>,
+    },
+}
+
+impl Trace {
+    // Plans the replay of `graph`, and checks running it on `example` gives `expected`
+    fn compile(graph: Graph, example: &Tensor, expected: &Tensor) -> MlResult<Self> {
+        let inputs = std::slice::from_ref(example);
+        let live = graph.live();
+        let mut values: Vec<Option<Tensor>> = vec![None; graph.len()];
+        let mut shapes = vec![Vec::new(); graph.len()];
+        for (i, node) in graph.nodes.iter().enumerate() {
+            if !live[i] {
+                continue;
+            }
+            if !matches!(node.op, Op::Input(_) | Op::Constant(_)) {
+                let args: Vec<&Tensor> = node
+                    .inputs
+                    .iter()
+                    .map(|&id| graph.value(id, inputs, &values))
+                    .collect();
+                values[i] = Some(node.op.eval(&args)?);
+            }
+            shapes[i] = graph.value(NodeId(i), inputs, &values).shape().to_vec();
+        }
+
+        let output = graph.value(graph.outputs[0], inputs, &values);
+        let matches = output.shape() == expected.shape()
+            && (output.data().iter().zip(expected.data()))
+                .all(|(a, e)| (a - e).abs() <= 1e-4 * (1.0 + e.abs()));
+        if !matches {
+            let reason = "the recorded ops don't reproduce its output";
+            return Err(format!("The forward pass can't be traced: {}", reason).into());
+        }
+
+        let steps = plan(&graph, &live, &shapes);
+        let trace = Self {
+            graph,
+            input_shape: example.shape().to_vec(),
+            buffers: RefCell::new(HashMap::new()),
+            steps,
+        };
+        trace.allocate_buffers();
+        Ok(trace)
+    }
+
+    // Allocates as many buffers as one replay takes from the pool before it gets any back
+    fn allocate_buffers(&self) {
+        let mut free: HashMap<usize, usize> = HashMap::new();
+        let mut buffers = self.buffers.borrow_mut();
+        for step in &self.steps {
+            if let Compute::Fused { .. } = step.compute {
+                match free.get_mut(&step.len).filter(|count| **count > 0) {
+                    Some(count) => *count -= 1,
+                    None => buffers
+                        .entry(step.len)
+                        .or_default()
+                        .push(Vec::with_capacity(step.len)),
+                }
+            }
+            for id in &step.free {
+                let len = self.steps.iter().find(|s| s.node == *id).map(|s| s.len);
+                *free.entry(len.unwrap_or(0)).or_default() += 1;
+            }
+        }
+    }
+
+    /// Runs the traced ops on `input`, which must have the shape of the example.
+    pub fn run(&self, input: &Tensor) -> MlResult<Tensor> {
+        if input.shape() != self.input_shape {
+            return Err(TensorError::InvalidShape {
+                expected: self.input_shape.clone(),
+                got: input.shape().to_vec(),
+            }
+            .into());
+        }
+
+        let inputs = std::slice::from_ref(input);
+        let mut values: Vec<Option<Tensor>> = vec![None; self.graph.len()];
+        let mut buffers = self.buffers.borrow_mut();
+        for step in &self.steps {
+            let value = match &step.compute {
+                Compute::Op => {
+                    let node = self.graph.node(step.node);
+                    let args: Vec<&Tensor> = node
+                        .inputs
+                        .iter()
+                        .map(|&id| self.graph.value(id, inputs, &values))
+                        .collect();
+                    node.op.eval(&args)?
+                }
+                Compute::Fused { inputs: ids, ops } => {
+                    let args = ids
+                        .iter()
+                        .map(|&id| self.graph.value(id, inputs, &values))
+                        .collect();
+                    let buffer = buffers.get_mut(&step.len).and_then(Vec::pop);
+                    Fused::from_ops(args, ops).eval_into(buffer.unwrap_or_default())?
+                }
+            };
+            values[step.node.0] = Some(value);
+
+            for id in &step.free {
+                match values[id.0].take() {
+                    Some(value) if !value.is_on_device() => {
+                        let data = value.into_data();
+                        buffers.entry(data.len()).or_default().push(data);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let output = self.graph.outputs[0];
+        match values[output.0].take() {
+            Some(value) => Ok(value),
+            None => Ok(self.graph.value(output, inputs, &values).clone()),
+        }
+    }
+
+    /// The optimized graph of the traced ops.
+    pub fn graph(&self) -> &Graph {
+        &self.graph
+    }
+
+    /// The shape of the inputs the trace runs on.
+    pub fn input_shape(&self) -> &[usize] {
+        &self.input_shape
+    }
+
+    /// How many steps a replay runs, each a single op or a fused chain of elementwise ops.
+    pub fn num_steps(&self) -> usize {
+        self.steps.len()
+    }
+}
+
+impl Layer for Trace {
+    fn forward(&self, input: &Tensor) -> MlResult<Tensor> {
+        self.run(input)
+    }
+
+    fn backward(&mut self, _: &Tensor, _: &Tensor, _: f32) -> MlResult<Tensor> {
+        Err("Traces only run inference".into())
+    }
+}
+
+// The steps computing the live nodes of `graph` whose values have `shapes`
+fn plan(graph: &Graph, live: &[bool], shapes: &[Vec<usize>]) -> Vec<Step> {
+    let mut uses = vec![0; graph.len()];
+    for (node, _) in graph.nodes.iter().zip(live).filter(|(_, live)| **live) {
+        for id in &node.inputs {
+            uses[id.0] += 1;
+        }
+    }
+    for output in &graph.outputs {
+        uses[output.0] += 1;
+    }
+
+    // A fused step is moved to the end when it takes in another op, whose inputs may be
+    // computed after the step's first op
+    let mut steps: Vec<Option<Step>> = Vec::new();
+    let mut chain_ends: HashMap<NodeId, usize> = HashMap::new();
+    for (i, node) in graph.nodes.iter().enumerate() {
+        if !live[i] || matches!(node.op, Op::Input(_) | Op::Constant(_)) {
+            continue;
+        }
+        let id = NodeId(i);
+        let len = shapes[i].iter().product();
+        let Some(op) = fused_op(node, shapes) else {
+            steps.push(Some(Step {
+                node: id,
+                compute: Compute::Op,
+                len,
+                free: Vec::new(),
+            }));
+            continue;
+        };
+
+        // Continue the chain ending at an operand only this node uses. The chain's value
+        // comes first in the op, so it can only be the second operand of Add and Mul.
+        let commutes = matches!(node.op, Op::Add | Op::Mul);
+        let chain = node
+            .inputs
+            .iter()
+            .enumerate()
+            .filter(|&(position, _)| position == 0 || commutes)
+            .find(|(_, id)| uses[id.0] == 1 && chain_ends.contains_key(*id));
+        let (operand, mut step) = match chain {
+            Some((position, end)) => {
+                let index = chain_ends.remove(end).expect("the chain was found");
+                let step = steps[index]
+                    .take()
+                    .expect("a chain's step is only moved once");
+                (1 - position, step)
+            }
+            None => {
+                let step = Step {
+                    node: id,
+                    compute: Compute::Fused {
+                        inputs: vec![node.inputs[0]],
+                        ops: Vec::new(),
+                    },
+                    len,
+                    free: Vec::new(),
+                };
+                (1, step)
+            }
+        };
+
+        if let Compute::Fused { inputs, ops } = &mut step.compute {
+            let other = node.inputs.get(operand).map(|&other| {
+                inputs.iter().position(|&i| i == other).unwrap_or_else(|| {
+                    inputs.push(other);
+                    inputs.len() - 1
+                })
+            });
+            ops.push(op(other.unwrap_or(0)));
+        }
+        step.node = id;
+        chain_ends.insert(id, steps.len());
+        steps.push(Some(step));
+    }
+    let mut steps: Vec<Step> = steps.into_iter().flatten().collect();
+
+    // Each computed value is freed after the last step reading it, unless it is an output
+    let mut last_use = HashMap::new();
+    for (index, step) in steps.iter().enumerate() {
+        let reads = match &step.compute {
+            Compute::Op => &graph.node(step.node).inputs,
+            Compute::Fused { inputs, .. } => inputs,
+        };
+        for &id in reads {
+            last_use.insert(id, index);
+        }
+    }
+    let computed: Vec<NodeId> = steps.iter().map(|step| step.node).collect();
+    for id in computed {
+        if let Some(&index) = last_use.get(&id) {
+            if !graph.outputs.contains(&id) {
+                steps[index].free.push(id);
+            }
+        }
+    }
+    steps
+}
+
+// The fused form of `node`'s op, taking the index of its other operand, if it is an
+// elementwise op on tensors of the same shape
+fn fused_op(node: &Node, shapes: &[Vec<usize>]) -> Option<impl Fn(usize) -> FusedOp> {
+    let same_shapes = node
+        .inputs
+        .windows(2)
+        .all(|pair| shapes[pair[0].0] == shapes[pair[1].0]);
+    if !same_shapes {
+        return None;
+    }
+    let op = node.op.clone();
+    let fusable = matches!(
+        op,
+        Op::Add
+            | Op::Sub
+            | Op::Mul
+            | Op::Div
+            | Op::AddScalar(_)
+            | Op::MulScalar(_)
+            | Op::Pow(_)
+            | Op::Neg
+            | Op::Exp
+            | Op::Log
+            | Op::Sqrt
+    );
+    fusable.then_some(move |other| match op {
+        Op::Add => FusedOp::Add(other),
+        Op::Sub => FusedOp::Sub(other),
+        Op::Mul => FusedOp::Mul(other),
+        Op::Div => FusedOp::Div(other),
+        Op::AddScalar(scalar) => FusedOp::AddScalar(scalar),
+        Op::MulScalar(scalar) => FusedOp::MulScalar(scalar),
+        Op::Pow(power) => FusedOp::Pow(power),
+        Op::Neg => FusedOp::Neg,
+        Op::Exp => FusedOp::Exp,
+        Op::Log => FusedOp::Log,
+        _ => FusedOp::Sqrt,
+    })
+}
+
+/// A layer that traces the layer it wraps the first time it sees an input shape, and
+/// replays that [`Trace`] for later inputs of the same shape.
+///
+/// Shapes whose passes can't be traced keep running the layer, with a warning the first
+/// time. Training the layer through [`Layer::backward`] or changing its parameters drops
+/// the traces, since they hold the weights as they were.
+///
+/// ```ignore
+/// let model = Jit::new(model);
+/// for batch in batches {
+///     let logits = model.forward(&batch)?; // traced on the first batch, replayed after
+/// }
+/// ```
+pub struct Jit<L> {
+    layer: L,
+    traces: RefCell<HashMap<Vec<usize>, Option<Trace>>>,
+}
+
+impl<L: Layer> Jit<L> {
+    pub fn new(layer: L) -> Self {
+        Self {
+            layer,
+            traces: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn layer(&self) -> &L {
+        &self.layer
+    }
+
+    pub fn into_inner(self) -> L {
+        self.layer
+    }
+
+    /// Whether inputs of `shape` replay a trace.
+    pub fn is_traced(&self, shape: &[usize]) -> bool {
+        matches!(self.traces.borrow().get(shape), Some(Some(_)))
+    }
+
+    /// Drops the traces, so each shape is traced again the next time it is seen.
+    pub fn clear(&mut self) {
+        self.traces.get_mut().clear();
+    }
+}
+
+impl<L: Layer> Layer for Jit<L> {
+    fn forward(&self, input: &Tensor) -> MlResult<Tensor> {
+        // Inside another trace, the ops are recorded into that one
+        if is_tracing() {
+            return self.layer.forward(input);
+        }
+        if let Some(trace) = self.traces.borrow().get(input.shape()) {
+            return match trace {
+                Some(trace) => trace.run(input),
+                None => self.layer.forward(input),
+            };
+        }
+
+        let (output, trace) = capture(&self.layer, input)?;
+        let trace = match trace {
+            Ok(trace) => Some(trace),
+            Err(e) => {
+                log_warn!("Not tracing inputs of shape {:?}: {}", input.shape(), e);
+                None
+            }
+        };
+        self.traces
+            .borrow_mut()
+            .insert(input.shape().to_vec(), trace);
+        Ok(output)
+    }
+
+    fn backward(
+        &mut self,
+        input: &Tensor,
+        grad_output: &Tensor,
+        learning_rate: f32,
+    ) -> MlResult<Tensor> {
+        self.clear();
+        self.layer.backward(input, grad_output, learning_rate)
+    }
+
+    fn named_parameters(&self) -> Vec<(String, &Tensor)> {
+        self.layer.named_parameters()
+    }
+
+    fn named_parameters_mut(&mut self) -> Vec<(String, &mut Tensor)> {
+        self.clear();
+        self.layer.named_parameters_mut()
+    }
+
+    fn named_buffers(&self) -> Vec<(String, &Tensor)> {
+        self.layer.named_buffers()
+    }
+
+    fn named_buffers_mut(&mut self) -> Vec<(String, &mut Tensor)> {
+        self.clear();
+        self.layer.named_buffers_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::{Linear, Sigmoid, Tanh};
+    use crate::test_util::assert_close;
+
+    struct Mlp {
+        linear: Linear,
+        hidden: Tanh,
+        output: Sigmoid,
+    }
+
+    impl Layer for Mlp {
+        fn forward(&self, input: &Tensor) -> MlResult<Tensor> {
+            let x = self.hidden.forward(&self.linear.forward(input)?)?;
+            self.output.forward(&x.mul(&x)?.add(&x)?)
+        }
+
+        fn backward(&mut self, input: &Tensor, grad: &Tensor, lr: f32) -> MlResult<Tensor> {
+            self.linear.backward(input, grad, lr)
+        }
+
+        fn named_parameters_mut(&mut self) -> Vec<(String, &mut Tensor)> {
+            self.linear.named_parameters_mut()
+        }
+    }
+
+    // Sums the rows, which isn't recorded
+    struct RowSums;
+
+    impl Layer for RowSums {
+        fn forward(&self, input: &Tensor) -> MlResult<Tensor> {
+            input.sum(&[1], true)?.mul_scalar(2.0)
+        }
+
+        fn backward(&mut self, _: &Tensor, grad: &Tensor, _: f32) -> MlResult<Tensor> {
+            Ok(grad.clone())
+        }
+    }
+
+    #[test]
+    fn test_trace_replay() -> MlResult<()> {
+        let mlp = Mlp {
+            linear: Linear::new(3, 4, true)?,
+            hidden: Tanh::new(),
+            output: Sigmoid::new(),
+        };
+        let example = Tensor::from_vec(vec![0.5, -1.0, 2.0, 0.0, 1.0, -0.5], &[2, 3])?;
+        let trace = trace(&mlp, &example)?;
+        assert_eq!(trace.input_shape(), &[2, 3]);
+
+        // The weight's transpose is folded, and the elementwise ops are fused into fewer
+        // steps than there are ops
+        assert!(!trace.graph().nodes().iter().any(|n| n.op == Op::Transpose));
+        let computed = trace.graph().nodes().iter();
+        let computed = computed.filter(|n| !n.inputs.is_empty()).count();
+        assert!(trace.num_steps() < computed);
+
+        let input = Tensor::from_vec(vec![-2.0, 0.25, 1.5, 3.0, -0.75, 0.0], &[2, 3])?;
+        for _ in 0..2 {
+            let (traced, eager) = (trace.run(&input)?, mlp.forward(&input)?);
+            assert_eq!(traced.shape(), eager.shape());
+            assert_close(traced.data(), eager.data());
+        }
+        assert!(trace
+            .run(&Tensor::from_vec(vec![0.0; 3], &[1, 3])?)
+            .is_err());
+        assert!(super::trace(&RowSums, &example).is_err());
+
+        // Jit traces each shape once, and runs what it can't trace as it is
+        let mut jit = Jit::new(mlp);
+        let expected = jit.layer().forward(&input)?;
+        let output = jit.forward(&input)?;
+        assert_eq!(output.shape(), expected.shape());
+        assert_close(output.data(), expected.data());
+        assert!(jit.is_traced(&[2, 3]));
+        let output = jit.forward(&input)?;
+        assert_eq!(output.shape(), expected.shape());
+        assert_close(output.data(), expected.data());
+        jit.backward(&input, &Tensor::from_vec(vec![0.1; 8], &[2, 4])?, 0.1)?;
+        assert!(!jit.is_traced(&[2, 3]));
+
+        let sums = Jit::new(RowSums);
+        for _ in 0..2 {
+            assert_eq!(sums.forward(&example)?.data(), &[3.0, 1.0]);
+        }
+        assert!(!sums.is_traced(&[2, 3]));
+        Ok(())
+    }
+}
@@ -0,0 +1,385 @@
+//! A graph intermediate representation of models, for optimizing them before inference.
+//!
+//! A [`Graph`] is a list of [`Node`]s, each an [`Op`] applied to nodes before it, so the
+//! list is always in an order it can run in. Graphs are built with [`Graph::push`] or
+//! imported from ONNX with [`read_onnx_graph`](crate::serialize::read_onnx_graph),
+//! then rewritten by the passes [`Graph::optimize`] runs:
+//!
+//! - constant folding evaluates the nodes whose inputs are all constants ahead of time
+//! - common-subexpression elimination merges nodes applying the same op to the same inputs
+//! - conv+batchnorm folding scales a convolution's weight and bias by the inference-mode
+//!   batch norm after it, leaving one op where there were two
+//! - dead-node elimination drops the nodes no output depends on
+//!
+//! A graph with one input and one output is a [`Layer`], for inference only.
+//!
+//! Graphs also come from tracing: [`trace`] runs a layer once, recording the tensor ops of
+//! its forward pass, and gives a [`Trace`] that replays them on later inputs of the same
+//! shape, with elementwise ops fused and buffers reused between runs. [`Jit`] wraps a
+//! layer to do this for each input shape it sees.
+//!
+//! ```ignore
+//! let mut graph = load_onnx_graph("resnet18.onnx")?;
+//! graph.optimize()?;
+//! let logits = graph.forward(&images)?;
+//! ```
+
+pub(crate) mod jit;
+mod passes;
+
+pub use jit::{trace, Jit, Trace};
+
+use crate::nn::Layer;
+use crate::tensor::{Tensor, TensorError};
+use crate::MlResult;
+
+/// The position of a node in its [`Graph`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+impl NodeId {
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+/// What a node computes from its inputs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    /// The graph input of this index.
+    Input(usize),
+    /// The graph constant of this index.
+    Constant(usize),
+    MatMul,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    AddScalar(f32),
+    MulScalar(f32),
+    Neg,
+    Exp,
+    Log,
+    Sqrt,
+    Pow(f32),
+    Clip {
+        min: f32,
+        max: f32,
+    },
+    Relu,
+    Sigmoid,
+    Tanh,
+    /// Swaps the axes of a 2D tensor.
+    Transpose,
+    /// Reorders the axes, as [`Tensor::permute`] does.
+    Permute(Vec<usize>),
+    Reshape(Vec<usize>),
+    /// Collapses the axes before this one and the axes from it on, giving a 2D tensor.
+    Flatten(usize),
+    /// The 2D convolution of `[input, weight]` or `[input, weight, bias]`, as
+    /// [`Tensor::conv2d_grouped`] computes it, with the bias added to each output channel.
+    Conv2d {
+        stride: (usize, usize),
+        padding: (usize, usize),
+        groups: usize,
+    },
+    /// Inference-mode batch normalization over axis 1 of `[input, scale, bias, mean,
+    /// variance]`: `scale * (x - mean) / sqrt(variance + epsilon) + bias`.
+    BatchNorm {
+        epsilon: f32,
+    },
+}
+
+impl Op {
+    // The numbers of inputs the op takes
+    fn arity(&self) -> std::ops::RangeInclusive<usize> {
+        match self {
+            Op::Input(_) | Op::Constant(_) => 0..=0,
+            Op::MatMul | Op::Add | Op::Sub | Op::Mul | Op::Div => 2..=2,
+            Op::Conv2d { .. } => 2..=3,
+            Op::BatchNorm { .. } => 5..=5,
+            _ => 1..=1,
+        }
+    }
+
+    pub(crate) fn eval(&self, args: &[&Tensor]) -> MlResult<Tensor> {
+        let x = args[0];
+        match self {
+            Op::Input(_) | Op::Constant(_) => Err(format!("{:?} has no inputs", self).into()),
+            Op::MatMul => x.matmul(args[1]),
+            Op::Add => x.add(args[1]),
+            Op::Sub => x.sub(args[1]),
+            Op::Mul => x.mul(args[1]),
+            Op::Div => x.div(args[1]),
+            Op::AddScalar(scalar) => x.add_scalar(*scalar),
+            Op::MulScalar(scalar) => x.mul_scalar(*scalar),
+            Op::Neg => x.neg(),
+            Op::Exp => x.exp(),
+            Op::Log => x.log(),
+            Op::Sqrt => x.sqrt(),
+            Op::Pow(power) => x.pow(*power),
+            Op::Clip { min, max } => x.clip(*min, *max),
+            Op::Relu => map(x, |v| v.max(0.0)),
+            Op::Sigmoid => map(x, |v| 1.0 / (1.0 + (-v).exp())),
+            Op::Tanh => map(x, f32::tanh),
+            Op::Transpose => x.transpose(0, 1),
+            Op::Permute(axes) => x.permute(axes),
+            Op::Reshape(shape) => x.reshape(shape),
+            Op::Flatten(axis) => {
+                let axis = (*axis).min(x.shape().len());
+                let rows = x.shape()[..axis].iter().product();
+                x.reshape(&[rows, x.shape()[axis..].iter().product()])
+            }
+            Op::Conv2d {
+                stride,
+                padding,
+                groups,
+            } => {
+                let output = x.conv2d_grouped(args[1], *stride, *padding, *groups)?;
+                match args.get(2) {
+                    Some(bias) => {
+                        let bias = channel_params(&output, bias)?;
+                        per_channel(&output, |c, v| v + bias[c])
+                    }
+                    None => Ok(output),
+                }
+            }
+            Op::BatchNorm { epsilon } => {
+                let [scale, bias, mean, variance] =
+                    [1, 2, 3, 4].map(|i| channel_params(x, args[i]));
+                let (scale, bias, mean, variance) = (scale?, bias?, mean?, variance?);
+                per_channel(x, |c, v| {
+                    scale[c] * (v - mean[c]) / (variance[c] + epsilon).sqrt() + bias[c]
+                })
+            }
+        }
+    }
+}
+
+/// One step of a [`Graph`]: `op` applied to the values of `inputs`.
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub op: Op,
+    pub inputs: Vec<NodeId>,
+}
+
+/// A model as a graph of tensor ops, with its weights as constants.
+#[derive(Debug, Clone, Default)]
+pub struct Graph {
+    nodes: Vec<Node>,
+    constants: Vec<Tensor>,
+    num_inputs: usize,
+    outputs: Vec<NodeId>,
+}
+
+impl Graph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the next input, the one [`Graph::run`] takes after the inputs added before it.
+    pub fn input(&mut self) -> NodeId {
+        self.num_inputs += 1;
+        self.add(Op::Input(self.num_inputs - 1), Vec::new())
+    }
+
+    pub fn constant(&mut self, tensor: Tensor) -> NodeId {
+        self.constants.push(tensor);
+        self.add(Op::Constant(self.constants.len() - 1), Vec::new())
+    }
+
+    /// Adds a node applying `op` to `inputs`, which must already be in the graph.
+    pub fn push(&mut self, op: Op, inputs: &[NodeId]) -> MlResult<NodeId> {
+        if matches!(op, Op::Input(_) | Op::Constant(_)) {
+            return Err("Add inputs and constants with Graph::input and Graph::constant".into());
+        }
+        if !op.arity().contains(&inputs.len()) {
+            return Err(format!("{:?} can't take {} inputs", op, inputs.len()).into());
+        }
+        self.check(inputs)?;
+        Ok(self.add(op, inputs.to_vec()))
+    }
+
+    /// Sets the nodes whose values [`Graph::run`] returns.
+    pub fn set_outputs(&mut self, outputs: &[NodeId]) -> MlResult<()> {
+        self.check(outputs)?;
+        self.outputs = outputs.to_vec();
+        Ok(())
+    }
+
+    pub fn outputs(&self) -> &[NodeId] {
+        &self.outputs
+    }
+
+    pub fn num_inputs(&self) -> usize {
+        self.num_inputs
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0]
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// The value of `id`, if it is a constant node.
+    pub fn constant_value(&self, id: NodeId) -> Option<&Tensor> {
+        match self.nodes.get(id.0)?.op {
+            Op::Constant(index) => self.constants.get(index),
+            _ => None,
+        }
+    }
+
+    /// Computes the outputs from `inputs`, one for each [`Graph::input`]. Nodes no output
+    /// depends on are skipped.
+    pub fn run(&self, inputs: &[Tensor]) -> MlResult<Vec<Tensor>> {
+        if inputs.len() != self.num_inputs {
+            return Err(format!(
+                "The graph takes {} inputs, got {}",
+                self.num_inputs,
+                inputs.len()
+            )
+            .into());
+        }
+
+        let live = self.live();
+        let mut values: Vec<Option<Tensor>> = vec![None; self.nodes.len()];
+        for (i, node) in self.nodes.iter().enumerate() {
+            if !live[i] || matches!(node.op, Op::Input(_) | Op::Constant(_)) {
+                continue;
+            }
+            let args: Vec<&Tensor> = node
+                .inputs
+                .iter()
+                .map(|&id| self.value(id, inputs, &values))
+                .collect();
+            values[i] = Some(node.op.eval(&args)?);
+        }
+        Ok(self
+            .outputs
+            .iter()
+            .map(|&id| self.value(id, inputs, &values).clone())
+            .collect())
+    }
+
+    // Inputs and constants aren't copied into `values`
+    fn value<'a>(
+        &'a self,
+        id: NodeId,
+        inputs: &'a [Tensor],
+        values: &'a [Option<Tensor>],
+    ) -> &'a Tensor {
+        match self.nodes[id.0].op {
+            Op::Input(index) => &inputs[index],
+            Op::Constant(index) => &self.constants[index],
+            _ => values[id.0].as_ref().expect("nodes run after their inputs"),
+        }
+    }
+
+    // Which nodes some output depends on
+    fn live(&self) -> Vec<bool> {
+        let mut live = vec![false; self.nodes.len()];
+        for output in &self.outputs {
+            live[output.0] = true;
+        }
+        for i in (0..self.nodes.len()).rev() {
+            if live[i] {
+                for input in &self.nodes[i].inputs {
+                    live[input.0] = true;
+                }
+            }
+        }
+        live
+    }
+
+    fn check(&self, ids: &[NodeId]) -> MlResult<()> {
+        match ids.iter().find(|id| id.0 >= self.nodes.len()) {
+            Some(id) => Err(format!("Node {} is not in the graph", id.0).into()),
+            None => Ok(()),
+        }
+    }
+
+    fn add(&mut self, op: Op, inputs: Vec<NodeId>) -> NodeId {
+        self.nodes.push(Node { op, inputs });
+        NodeId(self.nodes.len() - 1)
+    }
+}
+
+impl Layer for Graph {
+    fn forward(&self, input: &Tensor) -> MlResult<Tensor> {
+        if self.outputs.len() != 1 {
+            return Err("Only a graph with one output can run as a layer".into());
+        }
+        Ok(self.run(std::slice::from_ref(input))?.remove(0))
+    }
+
+    fn backward(&mut self, _: &Tensor, _: &Tensor, _: f32) -> MlResult<Tensor> {
+        Err("Graphs only run inference".into())
+    }
+}
+
+fn map(x: &Tensor, f: impl Fn(f32) -> f32) -> MlResult<Tensor> {
+    Tensor::from_vec(x.data().iter().map(|&v| f(v)).collect(), x.shape())
+}
+
+// `f(channel, value)` for each value of `x`, whose channels are along axis 1
+fn per_channel(x: &Tensor, f: impl Fn(usize, f32) -> f32) -> MlResult<Tensor> {
+    let channels = x.shape().get(1).copied().unwrap_or(1);
+    let spatial: usize = x.shape().iter().skip(2).product();
+    let data = x
+        .data()
+        .iter()
+        .enumerate()
+        .map(|(i, &v)| f((i / spatial) % channels, v))
+        .collect();
+    Tensor::from_vec(data, x.shape())
+}
+
+// The values of `params`, which must hold one per channel of `x`
+fn channel_params<'a>(x: &Tensor, params: &'a Tensor) -> MlResult<&'a [f32]> {
+    let channels = x.shape().get(1).copied().unwrap_or(1);
+    if params.shape() != [channels] {
+        return Err(TensorError::InvalidShape {
+            expected: vec![channels],
+            got: params.shape().to_vec(),
+        }
+        .into());
+    }
+    Ok(params.data())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_graph_run() -> MlResult<()> {
+        // relu(x @ w + b) and a node no output needs
+        let mut graph = Graph::new();
+        let x = graph.input();
+        let w = graph.constant(Tensor::from_vec(vec![1.0, -1.0, 2.0, 0.5], &[2, 2])?);
+        let b = graph.constant(Tensor::from_vec(vec![0.5, -3.0], &[2])?);
+        let xw = graph.push(Op::MatMul, &[x, w])?;
+        let y = graph.push(Op::Add, &[xw, b])?;
+        let y = graph.push(Op::Relu, &[y])?;
+        graph.push(Op::Log, &[x])?;
+        graph.set_outputs(&[y])?;
+
+        let input = Tensor::from_vec(vec![1.0, 2.0, -1.0, 0.0], &[2, 2])?;
+        assert_eq!(graph.forward(&input)?.data(), &[5.5, 0.0, 0.0, 0.0]);
+        assert!(graph.run(&[]).is_err());
+        assert!(graph.push(Op::Add, &[x]).is_err());
+        assert!(graph.push(Op::Relu, &[NodeId(99)]).is_err());
+        assert!(graph.backward(&input, &input, 0.1).is_err());
+        Ok(())
+    }
+}
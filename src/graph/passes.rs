@@ -0,0 +1,315 @@
+use std::collections::HashMap;
+
+use super::{Graph, Node, NodeId, Op};
+use crate::tensor::Tensor;
+use crate::MlResult;
+
+impl Graph {
+    /// Runs every pass until none finds anything more to do.
+    pub fn optimize(&mut self) -> MlResult<()> {
+        loop {
+            let changed = self.fold_constants()?
+                + self.fold_conv_batchnorm()?
+                + self.eliminate_common_subexpressions();
+            self.eliminate_dead_nodes();
+            if changed == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Evaluates each node whose inputs are all constants and makes it a constant holding
+    /// the result. Returns how many nodes were folded.
+    pub fn fold_constants(&mut self) -> MlResult<usize> {
+        let mut folded = 0;
+        for i in 0..self.nodes.len() {
+            let node = &self.nodes[i];
+            if node.inputs.is_empty() {
+                continue;
+            }
+            let Some(args) = node
+                .inputs
+                .iter()
+                .map(|&id| self.constant_value(id))
+                .collect::<Option<Vec<_>>>()
+            else {
+                continue;
+            };
+
+            let value = node.op.eval(&args)?;
+            self.constants.push(value);
+            self.nodes[i] = Node {
+                op: Op::Constant(self.constants.len() - 1),
+                inputs: Vec::new(),
+            };
+            folded += 1;
+        }
+        Ok(folded)
+    }
+
+    /// Points the users of each node that repeats an earlier node's op on the same inputs
+    /// at the earlier one. Returns how many nodes were merged away; they are left for
+    /// [`Graph::eliminate_dead_nodes`] to remove.
+    pub fn eliminate_common_subexpressions(&mut self) -> usize {
+        let mut replacements: Vec<NodeId> = (0..self.nodes.len()).map(NodeId).collect();
+        let mut seen: HashMap<Vec<NodeId>, Vec<NodeId>> = HashMap::new();
+        let mut merged = 0;
+        for i in 0..self.nodes.len() {
+            let inputs: Vec<NodeId> = self.nodes[i]
+                .inputs
+                .iter()
+                .map(|id| replacements[id.0])
+                .collect();
+            self.nodes[i].inputs.clone_from(&inputs);
+            // Inputs and distinct constants never compute the same thing
+            if inputs.is_empty() {
+                continue;
+            }
+
+            let candidates = seen.entry(inputs).or_default();
+            match candidates
+                .iter()
+                .find(|id| self.nodes[id.0].op == self.nodes[i].op)
+            {
+                Some(&earlier) => {
+                    replacements[i] = earlier;
+                    merged += 1;
+                }
+                None => candidates.push(NodeId(i)),
+            }
+        }
+        for output in &mut self.outputs {
+            *output = replacements[output.0];
+        }
+        merged
+    }
+
+    /// Folds each batch norm applied to a convolution into the convolution's weight and
+    /// bias, when both have constant parameters and nothing else uses the convolution's
+    /// output. Returns how many batch norms were folded.
+    pub fn fold_conv_batchnorm(&mut self) -> MlResult<usize> {
+        let mut uses = vec![0; self.nodes.len()];
+        for id in self
+            .nodes
+            .iter()
+            .flat_map(|n| &n.inputs)
+            .chain(&self.outputs)
+        {
+            uses[id.0] += 1;
+        }
+
+        let mut folds = HashMap::new();
+        for (i, node) in self.nodes.iter().enumerate() {
+            let Op::BatchNorm { epsilon } = node.op else {
+                continue;
+            };
+            let conv = &self.nodes[node.inputs[0].0];
+            if !matches!(conv.op, Op::Conv2d { .. }) || uses[node.inputs[0].0] != 1 {
+                continue;
+            }
+            let params = conv.inputs[1..].iter().chain(&node.inputs[1..]);
+            let Some(params) = params
+                .map(|&id| self.constant_value(id))
+                .collect::<Option<Vec<_>>>()
+            else {
+                continue;
+            };
+
+            // The convolution's bias, when it has one, comes before the batch norm's
+            let (weight, bias, norm) = match params.len() {
+                6 => (params[0], Some(params[1]), &params[2..]),
+                _ => (params[0], None, &params[1..]),
+            };
+            let (weight, bias) = fold(weight, bias, norm, epsilon)?;
+            folds.insert(i, (conv.op.clone(), conv.inputs[0], weight, bias));
+        }
+        if folds.is_empty() {
+            return Ok(0);
+        }
+
+        let folded = folds.len();
+        self.rebuild(|graph, i, map| match folds.remove(&i) {
+            Some((op, input, weight, bias)) => {
+                let weight = graph.constant(weight);
+                let bias = graph.constant(bias);
+                Rewrite::Replace(graph.add(op, vec![map[input.0], weight, bias]))
+            }
+            None => Rewrite::Keep,
+        });
+        Ok(folded)
+    }
+
+    /// Removes the nodes no output depends on, and the constants only they held. Inputs are
+    /// kept, so [`Graph::run`] takes the same inputs as before. Returns how many nodes were
+    /// removed.
+    pub fn eliminate_dead_nodes(&mut self) -> usize {
+        let live = self.live();
+        let keep: Vec<bool> = self
+            .nodes
+            .iter()
+            .zip(live)
+            .map(|(node, live)| live || matches!(node.op, Op::Input(_)))
+            .collect();
+        let before = self.nodes.len();
+        self.rebuild(|_, i, _| {
+            if keep[i] {
+                Rewrite::Keep
+            } else {
+                Rewrite::Drop
+            }
+        });
+        before - self.nodes.len()
+    }
+
+    // Copies the nodes one by one into the graph emptied out, renumbering them. `rewrite`
+    // sees the graph built so far, the old index of the next node and the new ids of the
+    // ones before it, and may emit nodes standing in for it.
+    fn rebuild(&mut self, mut rewrite: impl FnMut(&mut Graph, usize, &[NodeId]) -> Rewrite) {
+        let nodes = std::mem::take(&mut self.nodes);
+        let mut constants: Vec<Option<Tensor>> = std::mem::take(&mut self.constants)
+            .into_iter()
+            .map(Some)
+            .collect();
+
+        let mut map = Vec::with_capacity(nodes.len());
+        for (i, node) in nodes.into_iter().enumerate() {
+            let id = match rewrite(self, i, &map) {
+                Rewrite::Replace(id) => id,
+                // Nothing kept refers to a dropped node
+                Rewrite::Drop => NodeId(usize::MAX),
+                Rewrite::Keep => match node.op {
+                    Op::Constant(index) => {
+                        let tensor = constants[index].take();
+                        self.constant(tensor.expect("each constant belongs to one node"))
+                    }
+                    op => {
+                        let inputs = node.inputs.iter().map(|id| map[id.0]).collect();
+                        self.add(op, inputs)
+                    }
+                },
+            };
+            map.push(id);
+        }
+        for output in &mut self.outputs {
+            *output = map[output.0];
+        }
+    }
+}
+
+enum Rewrite {
+    Keep,
+    Drop,
+    Replace(NodeId),
+}
+
+// The weight and bias of a convolution followed by a batch norm with parameters `norm`:
+// each output channel is scaled by scale / sqrt(variance + epsilon)
+fn fold(
+    weight: &Tensor,
+    bias: Option<&Tensor>,
+    norm: &[&Tensor],
+    epsilon: f32,
+) -> MlResult<(Tensor, Tensor)> {
+    let channels = weight.shape()[0];
+    let lengths = norm.iter().chain(&bias).map(|p| p.data().len());
+    if lengths.clone().any(|len| len != channels) {
+        return Err(format!(
+            "Batch norm parameters don't match the {} channels of the convolution",
+            channels
+        )
+        .into());
+    }
+
+    let [scale, shift, mean, variance] = [0, 1, 2, 3].map(|i| norm[i].data());
+    let factor: Vec<f32> = (0..channels)
+        .map(|c| scale[c] / (variance[c] + epsilon).sqrt())
+        .collect();
+    let per_channel = weight.data().len() / channels.max(1);
+    let weight_data = weight
+        .data()
+        .iter()
+        .enumerate()
+        .map(|(i, w)| w * factor[i / per_channel])
+        .collect();
+    let bias_data = (0..channels)
+        .map(|c| {
+            let b = bias.map_or(0.0, |b| b.data()[c]);
+            (b - mean[c]) * factor[c] + shift[c]
+        })
+        .collect();
+    Ok((
+        Tensor::from_vec(weight_data, weight.shape())?,
+        Tensor::from_vec(bias_data, &[channels])?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(graph: &Graph, f: impl Fn(&Op) -> bool) -> usize {
+        graph.nodes().iter().filter(|node| f(&node.op)).count()
+    }
+
+    #[test]
+    fn test_optimize() -> MlResult<()> {
+        // x @ w^T computed twice and summed, with the transpose of a constant and a dead node
+        let mut graph = Graph::new();
+        let x = graph.input();
+        let w = graph.constant(Tensor::from_vec(vec![1.0, 2.0, 3.0, 4.0], &[2, 2])?);
+        let wt = graph.push(Op::Transpose, &[w])?;
+        let a = graph.push(Op::MatMul, &[x, wt])?;
+        let b = graph.push(Op::MatMul, &[x, wt])?;
+        let sum = graph.push(Op::Add, &[a, b])?;
+        graph.push(Op::Exp, &[x])?;
+        graph.set_outputs(&[sum])?;
+
+        let input = Tensor::from_vec(vec![1.0, -1.0], &[1, 2])?;
+        let expected = graph.run(std::slice::from_ref(&input))?;
+        assert_eq!(graph.fold_constants()?, 1);
+        assert!(graph.constant_value(wt).is_some());
+        assert_eq!(graph.eliminate_common_subexpressions(), 1);
+        assert_eq!(graph.eliminate_dead_nodes(), 3);
+        assert_eq!(graph.len(), 4);
+        assert_eq!(graph.run(&[input])?[0].data(), expected[0].data());
+
+        // A batch norm after a convolution becomes part of it
+        let mut graph = Graph::new();
+        let x = graph.input();
+        let weight = (0..8).map(|i| i as f32 * 0.25 - 1.0).collect();
+        let weight = graph.constant(Tensor::from_vec(weight, &[2, 1, 2, 2])?);
+        let bias = graph.constant(Tensor::from_vec(vec![0.5, -0.5], &[2])?);
+        let conv = Op::Conv2d {
+            stride: (1, 1),
+            padding: (1, 1),
+            groups: 1,
+        };
+        let y = graph.push(conv, &[x, weight, bias])?;
+        let norm = [
+            vec![2.0, 0.5],
+            vec![0.1, -0.2],
+            vec![1.0, -1.0],
+            vec![4.0, 0.25],
+        ]
+        .map(|values| Tensor::from_vec(values, &[2]).map(|t| graph.constant(t)));
+        let [scale, shift, mean, variance] = norm;
+        let y = graph.push(
+            Op::BatchNorm { epsilon: 1e-5 },
+            &[y, scale?, shift?, mean?, variance?],
+        )?;
+        let y = graph.push(Op::Relu, &[y])?;
+        graph.set_outputs(&[y])?;
+
+        let input = Tensor::from_vec((0..9).map(|i| i as f32 - 4.0).collect(), &[1, 1, 3, 3])?;
+        let expected = graph.run(std::slice::from_ref(&input))?;
+        graph.optimize()?;
+        assert_eq!(count(&graph, |op| matches!(op, Op::BatchNorm { .. })), 0);
+        assert_eq!(count(&graph, |op| matches!(op, Op::Conv2d { .. })), 1);
+        assert_eq!(graph.len(), 5);
+        let actual = graph.run(&[input])?;
+        for (a, e) in actual[0].data().iter().zip(expected[0].data()) {
+            assert!((a - e).abs() < 1e-4, "{:?} vs {:?}", actual, expected);
+        }
+        Ok(())
+    }
+}
@@ -1,14 +1,38 @@
 use std::fmt::{Display, Formatter};
 
+pub mod amp;
 pub mod backend;
+pub mod bench;
+pub mod ctc;
+pub mod data;
+pub mod detection;
+#[cfg(feature = "distributed")]
+pub mod distributed;
+pub mod distributions;
+pub mod generate;
+pub mod graph;
+pub mod log;
 pub mod loss;
+pub mod memory;
+pub mod metrics;
 pub mod nn;
 pub mod prelude;
+pub mod prune;
+pub mod quantize;
+pub mod rl;
 pub mod serialize;
+#[cfg(feature = "threads")]
+pub mod serve;
 pub mod tensor;
+#[cfg(test)]
+mod test_util;
+pub mod train;
+
+pub use nn::random::{rng_state, seed_all, set_rng_state};
 
 use backend::BackendError;
 use loss::LossError;
+use serialize::FormatError;
 use tensor::TensorError;
 
 #[derive(Debug)]
@@ -17,6 +41,7 @@ pub enum MlError {
     LossError(LossError),
     StringError(String),
     BackendError(BackendError),
+    FormatError(FormatError),
 }
 
 impl Display for MlError {
@@ -26,6 +51,7 @@ impl Display for MlError {
             MlError::LossError(e) => write!(f, "Loss error: {}", e),
             MlError::StringError(s) => write!(f, "{}", s),
             MlError::BackendError(e) => write!(f, "Backend error: {}", e),
+            MlError::FormatError(e) => write!(f, "Format error: {}", e),
         }
     }
 }
@@ -68,6 +94,21 @@ impl From<BackendError> for MlError {
     }
 }
 
+impl From<FormatError> for MlError {
+    fn from(error: FormatError) -> Self {
+        MlError::FormatError(error)
+    }
+}
+
+impl From<cetana_core::CoreError> for MlError {
+    fn from(error: cetana_core::CoreError) -> Self {
+        match error {
+            cetana_core::CoreError::Shape(s) => MlError::StringError(s),
+            cetana_core::CoreError::Format(s) => MlError::FormatError(FormatError::Invalid(s)),
+        }
+    }
+}
+
 impl From<String> for MlError {
     fn from(error: String) -> Self {
         MlError::StringError(error)
@@ -0,0 +1,181 @@
+//! Diagnostics.
+//!
+//! cetana reports device probing, backend selection and fallbacks through the `log` crate,
+//! under targets starting with `cetana`, so they show up in whatever logger the application
+//! installs (including `tracing` subscribers through `tracing-log`). [`init`] installs a
+//! minimal stderr logger for programs that don't have one.
+//!
+//! [`set_verbosity`] limits what cetana emits regardless of the logger, and every fallback
+//! to another device is also kept as a [`Fallback`] record, retrievable with [`fallbacks`].
+
+use crate::backend::DeviceType;
+use ::log::{Level, Log, Metadata, Record, SetLoggerError};
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+pub use ::log::LevelFilter;
+
+static VERBOSITY: AtomicUsize = AtomicUsize::new(LevelFilter::Info as usize);
+
+/// Sets the most detailed level cetana logs at. Defaults to `Info`.
+pub fn set_verbosity(level: LevelFilter) {
+    VERBOSITY.store(level as usize, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> LevelFilter {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => LevelFilter::Off,
+        1 => LevelFilter::Error,
+        2 => LevelFilter::Warn,
+        3 => LevelFilter::Info,
+        4 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+pub(crate) fn enabled(level: Level) -> bool {
+    level <= verbosity()
+}
+
+macro_rules! log_debug {
+    ($($arg:tt)+) => {
+        if $crate::log::enabled(::log::Level::Debug) {
+            ::log::debug!($($arg)+)
+        }
+    };
+}
+
+macro_rules! log_info {
+    ($($arg:tt)+) => {
+        if $crate::log::enabled(::log::Level::Info) {
+            ::log::info!($($arg)+)
+        }
+    };
+}
+
+macro_rules! log_warn {
+    ($($arg:tt)+) => {
+        if $crate::log::enabled(::log::Level::Warn) {
+            ::log::warn!($($arg)+)
+        }
+    };
+}
+
+#[allow(unused_imports)]
+pub(crate) use {log_debug, log_info, log_warn};
+
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        enabled(metadata.level())
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{} {}] {}", record.level(), record.target(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+/// Installs a logger that writes to stderr. The `CETANA_LOG` environment variable (`off`,
+/// `error`, `warn`, `info`, `debug` or `trace`) overrides the verbosity.
+///
+/// Fails if the application already installed a logger.
+pub fn init() -> Result<(), SetLoggerError> {
+    if let Some(level) = std::env::var("CETANA_LOG")
+        .ok()
+        .and_then(|value| value.parse().ok())
+    {
+        set_verbosity(level);
+    }
+
+    ::log::set_logger(&LOGGER)?;
+    ::log::set_max_level(LevelFilter::Trace);
+    Ok(())
+}
+
+/// A tensor or backend that ended up on a different device than the one asked for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fallback {
+    pub requested: DeviceType,
+    pub used: DeviceType,
+    /// Why the requested device couldn't be used.
+    pub reason: String,
+}
+
+impl Display for Fallback {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "fell back from {} to {}: {}",
+            self.requested, self.used, self.reason
+        )
+    }
+}
+
+// Older records are dropped past this many
+const MAX_FALLBACKS: usize = 64;
+
+static FALLBACKS: Mutex<VecDeque<Fallback>> = Mutex::new(VecDeque::new());
+
+pub(crate) fn record_fallback(requested: DeviceType, used: DeviceType, reason: String) {
+    let fallback = Fallback {
+        requested,
+        used,
+        reason,
+    };
+    log_warn!("{}", fallback);
+
+    let mut fallbacks = FALLBACKS.lock().unwrap_or_else(|p| p.into_inner());
+    if fallbacks.len() == MAX_FALLBACKS {
+        fallbacks.pop_front();
+    }
+    fallbacks.push_back(fallback);
+}
+
+/// The most recent fallbacks, oldest first.
+pub fn fallbacks() -> Vec<Fallback> {
+    let fallbacks = FALLBACKS.lock().unwrap_or_else(|p| p.into_inner());
+    fallbacks.iter().cloned().collect()
+}
+
+pub fn clear_fallbacks() {
+    FALLBACKS.lock().unwrap_or_else(|p| p.into_inner()).clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verbosity_and_fallback_records() {
+        let previous = verbosity();
+        set_verbosity(LevelFilter::Warn);
+        assert!(enabled(Level::Warn));
+        assert!(!enabled(Level::Info));
+        set_verbosity(previous);
+
+        record_fallback(
+            DeviceType::Custom("missing"),
+            DeviceType::Cpu,
+            "not registered".to_string(),
+        );
+        let recorded = fallbacks();
+        let fallback = recorded
+            .iter()
+            .find(|f| f.requested == DeviceType::Custom("missing"))
+            .unwrap();
+        assert_eq!(fallback.used, DeviceType::Cpu);
+        assert_eq!(
+            fallback.to_string(),
+            "fell back from Custom(\"missing\") to Cpu: not registered"
+        );
+    }
+}
@@ -97,6 +97,150 @@ pub fn calculate_binary_cross_entropy_loss(
     Ok(-mean_loss)
 }
 
+/// A training objective: its value for a batch and its gradient with respect to the
+/// predictions, which starts backpropagation.
+pub trait Loss {
+    fn loss(&self, predictions: &Tensor, targets: &Tensor) -> MlResult<f32>;
+
+    fn gradient(&self, predictions: &Tensor, targets: &Tensor) -> MlResult<Tensor>;
+}
+
+// `targets` in the shape of `predictions`, when they have as many elements, so `[batch]`
+// targets can meet `[batch, 1]` predictions
+fn shaped_like(predictions: &Tensor, targets: &Tensor) -> MlResult<Tensor> {
+    if predictions.shape() == targets.shape() {
+        return Ok(targets.clone());
+    }
+    if predictions.data().len() != targets.data().len() {
+        return Err(LossError::InvalidShape {
+            expected: predictions.shape().to_vec(),
+            got: targets.shape().to_vec(),
+        }
+        .into());
+    }
+    targets.reshape(predictions.shape())
+}
+
+/// Mean squared error; see [`calculate_mse_loss`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MseLoss;
+
+impl Loss for MseLoss {
+    fn loss(&self, predictions: &Tensor, targets: &Tensor) -> MlResult<f32> {
+        calculate_mse_loss(predictions, &shaped_like(predictions, targets)?)
+    }
+
+    fn gradient(&self, predictions: &Tensor, targets: &Tensor) -> MlResult<Tensor> {
+        let targets = shaped_like(predictions, targets)?;
+        let n = predictions.data().len().max(1) as f32;
+        predictions.sub(&targets)?.mul_scalar(2.0 / n)
+    }
+}
+
+/// Binary cross entropy of probabilities; see [`calculate_binary_cross_entropy_loss`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BinaryCrossEntropyLoss;
+
+impl Loss for BinaryCrossEntropyLoss {
+    fn loss(&self, predictions: &Tensor, targets: &Tensor) -> MlResult<f32> {
+        calculate_binary_cross_entropy_loss(predictions, &shaped_like(predictions, targets)?)
+    }
+
+    fn gradient(&self, predictions: &Tensor, targets: &Tensor) -> MlResult<Tensor> {
+        let targets = shaped_like(predictions, targets)?;
+        let epsilon = 1e-7;
+        let n = predictions.data().len().max(1) as f32;
+        let grad = predictions
+            .data()
+            .iter()
+            .zip(targets.data())
+            .map(|(&p, &y)| {
+                let p = p.clamp(epsilon, 1.0 - epsilon);
+                (p - y) / (p * (1.0 - p) * n)
+            })
+            .collect();
+        Tensor::from_vec(grad, predictions.shape())
+    }
+}
+
+/// Cross entropy of `[batch, classes]` logits against class indices, one per row, with the
+/// softmax folded in.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CrossEntropyLoss;
+
+impl CrossEntropyLoss {
+    // The softmax of each row and each row's class index
+    fn softmax_and_classes(
+        predictions: &Tensor,
+        targets: &Tensor,
+    ) -> MlResult<(Vec<f32>, Vec<usize>)> {
+        let [batch, classes] = predictions.shape()[..] else {
+            return Err(LossError::InvalidOperation {
+                op: "cross_entropy",
+                reason: format!(
+                    "expected [batch, classes] logits, got {:?}",
+                    predictions.shape()
+                ),
+            }
+            .into());
+        };
+        if targets.data().len() != batch {
+            return Err(LossError::InvalidShape {
+                expected: vec![batch],
+                got: targets.shape().to_vec(),
+            }
+            .into());
+        }
+        let labels = targets
+            .data()
+            .iter()
+            .map(|&t| {
+                let label = t as usize;
+                if t < 0.0 || t.fract() != 0.0 || label >= classes {
+                    return Err(LossError::InvalidOperation {
+                        op: "cross_entropy",
+                        reason: format!("{} is not a class index below {}", t, classes),
+                    });
+                }
+                Ok(label)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut softmax = predictions.data().to_vec();
+        for row in softmax.chunks_exact_mut(classes.max(1)) {
+            let max = row.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+            row.iter_mut().for_each(|x| *x = (*x - max).exp());
+            let sum: f32 = row.iter().sum();
+            row.iter_mut().for_each(|x| *x /= sum);
+        }
+        Ok((softmax, labels))
+    }
+}
+
+impl Loss for CrossEntropyLoss {
+    fn loss(&self, predictions: &Tensor, targets: &Tensor) -> MlResult<f32> {
+        let (softmax, labels) = Self::softmax_and_classes(predictions, targets)?;
+        let classes = predictions.shape()[1];
+        let total: f32 = labels
+            .iter()
+            .enumerate()
+            .map(|(row, &label)| -softmax[row * classes + label].max(1e-15).ln())
+            .sum();
+        Ok(total / labels.len().max(1) as f32)
+    }
+
+    fn gradient(&self, predictions: &Tensor, targets: &Tensor) -> MlResult<Tensor> {
+        let (mut grad, labels) = Self::softmax_and_classes(predictions, targets)?;
+        let classes = predictions.shape()[1];
+        for (row, &label) in labels.iter().enumerate() {
+            grad[row * classes + label] -= 1.0;
+        }
+        let batch = labels.len().max(1) as f32;
+        grad.iter_mut().for_each(|g| *g /= batch);
+        Tensor::from_vec(grad, predictions.shape())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,216 @@
+//! Memory used by tensors, on the host and on devices.
+//!
+//! Every tensor's host data is counted as it is created, copied and dropped, so
+//! [`host_memory`] gives the bytes held right now and the peak since the last
+//! [`reset_peak_host_memory`]. Device memory comes from each backend's caching allocator
+//! through [`device_memory`].
+//!
+//! [`track_leaks`] records every tensor created while the returned guard lives; whatever is
+//! still alive when the guard is dropped is logged as a warning, or can be listed with
+//! [`LeakCheck::leaked`]:
+//!
+//! ```ignore
+//! let check = cetana::memory::track_leaks();
+//! let output = model.forward(&input)?;
+//! drop(output);
+//! assert!(check.leaked().is_empty());
+//! ```
+//!
+//! Set `RUST_BACKTRACE=1` for each leaked tensor to come with where it was created.
+
+use std::backtrace::Backtrace;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::backend::{DeviceType, MemoryStats};
+use crate::log::log_warn;
+use crate::tensor::backend_for;
+use crate::MlResult;
+
+static CURRENT_BYTES: AtomicU64 = AtomicU64::new(0);
+static PEAK_BYTES: AtomicU64 = AtomicU64::new(0);
+static LIVE_TENSORS: AtomicUsize = AtomicUsize::new(0);
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+// The number of leak checks in progress, and the tensors created while any was
+static LEAK_CHECKS: AtomicUsize = AtomicUsize::new(0);
+static TRACKED: Mutex<BTreeMap<u64, LiveTensor>> = Mutex::new(BTreeMap::new());
+
+/// Host memory held by tensor data.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HostMemory {
+    /// Bytes of tensor data on the host right now.
+    pub current_bytes: u64,
+    /// The most `current_bytes` has been since the last [`reset_peak_host_memory`].
+    pub peak_bytes: u64,
+    /// Tensors alive, wherever their data is. Clones of a tensor count separately.
+    pub live_tensors: usize,
+}
+
+pub fn host_memory() -> HostMemory {
+    HostMemory {
+        current_bytes: CURRENT_BYTES.load(Ordering::Relaxed),
+        peak_bytes: PEAK_BYTES.load(Ordering::Relaxed),
+        live_tensors: LIVE_TENSORS.load(Ordering::Relaxed),
+    }
+}
+
+/// Starts measuring the peak again from the current usage, e.g. before each epoch.
+pub fn reset_peak_host_memory() {
+    PEAK_BYTES.store(CURRENT_BYTES.load(Ordering::Relaxed), Ordering::Relaxed);
+}
+
+/// Device memory held by the backend for `device`, or `None` if the backend doesn't cache
+/// allocations (the CPU, and devices whose memory the driver manages).
+pub fn device_memory(device: DeviceType) -> MlResult<Option<MemoryStats>> {
+    Ok(backend_for(device)?.memory_stats())
+}
+
+/// A tensor created during a [`LeakCheck`].
+#[derive(Debug, Clone)]
+pub struct LiveTensor {
+    /// The order the tensor was created in, across the process.
+    pub id: u64,
+    pub elements: usize,
+    /// Where the tensor was created, if backtraces are enabled.
+    pub backtrace: Option<Arc<Backtrace>>,
+}
+
+impl Display for LiveTensor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "tensor #{} of {} elements", self.id, self.elements)?;
+        if let Some(backtrace) = &self.backtrace {
+            write!(f, ", created at:\n{}", backtrace)?;
+        }
+        Ok(())
+    }
+}
+
+/// Records the tensors created from now until the returned guard is dropped, warning about
+/// any still alive then.
+///
+/// Tensors created on other threads in the meantime are recorded too.
+pub fn track_leaks() -> LeakCheck {
+    LEAK_CHECKS.fetch_add(1, Ordering::SeqCst);
+    LeakCheck {
+        first: NEXT_ID.load(Ordering::SeqCst),
+    }
+}
+
+/// A leak check started by [`track_leaks`].
+#[derive(Debug)]
+pub struct LeakCheck {
+    first: u64,
+}
+
+impl LeakCheck {
+    /// The tensors created since the check started that are still alive, oldest first.
+    pub fn leaked(&self) -> Vec<LiveTensor> {
+        let tracked = TRACKED.lock().unwrap_or_else(|e| e.into_inner());
+        tracked
+            .range(self.first..)
+            .map(|(_, t)| t.clone())
+            .collect()
+    }
+}
+
+impl Drop for LeakCheck {
+    fn drop(&mut self) {
+        let leaked = self.leaked();
+        if !leaked.is_empty() {
+            let total: usize = leaked.iter().map(|t| t.elements).sum();
+            log_warn!(
+                "{} tensors ({} elements) outlived their leak check: {}",
+                leaked.len(),
+                total,
+                leaked
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            );
+        }
+        if LEAK_CHECKS.fetch_sub(1, Ordering::SeqCst) == 1 {
+            TRACKED.lock().unwrap_or_else(|e| e.into_inner()).clear();
+        }
+    }
+}
+
+// The id the next tensor created will get
+pub(crate) fn next_tensor_id() -> u64 {
+    NEXT_ID.load(Ordering::SeqCst)
+}
+
+/// Counts a new tensor of `elements` elements, returning its id.
+pub(crate) fn tensor_created(elements: usize) -> u64 {
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    LIVE_TENSORS.fetch_add(1, Ordering::Relaxed);
+    if LEAK_CHECKS.load(Ordering::SeqCst) > 0 {
+        let backtrace = Backtrace::capture();
+        let live = LiveTensor {
+            id,
+            elements,
+            backtrace: (backtrace.status() == std::backtrace::BacktraceStatus::Captured)
+                .then(|| Arc::new(backtrace)),
+        };
+        TRACKED
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(id, live);
+    }
+    id
+}
+
+pub(crate) fn tensor_dropped(id: u64) {
+    LIVE_TENSORS.fetch_sub(1, Ordering::Relaxed);
+    if LEAK_CHECKS.load(Ordering::SeqCst) > 0 {
+        TRACKED
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&id);
+    }
+}
+
+/// Counts `elements` floats of tensor data placed on the host.
+pub(crate) fn host_allocated(elements: usize) {
+    let bytes = (elements * std::mem::size_of::<f32>()) as u64;
+    let current = CURRENT_BYTES.fetch_add(bytes, Ordering::Relaxed) + bytes;
+    PEAK_BYTES.fetch_max(current, Ordering::Relaxed);
+}
+
+pub(crate) fn host_freed(elements: usize) {
+    let bytes = (elements * std::mem::size_of::<f32>()) as u64;
+    CURRENT_BYTES.fetch_sub(bytes, Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tensor::Tensor;
+
+    #[test]
+    fn test_memory_tracking() -> MlResult<()> {
+        // Other tests create tensors concurrently, so only what this one holds is certain
+        let tensor = Tensor::from_vec(vec![1.0; 12_345], &[12_345])?;
+        let usage = host_memory();
+        assert!(usage.current_bytes >= 4 * 12_345);
+        assert!(usage.peak_bytes >= usage.current_bytes);
+        assert!(usage.live_tensors >= 1);
+
+        let check = track_leaks();
+        let kept = tensor.mul_scalar(2.0)?;
+        let dropped = Tensor::from_vec(vec![0.0; 7_777], &[7_777])?;
+        drop(dropped);
+        let leaked = check.leaked();
+        assert!(leaked.iter().any(|t| t.elements == 12_345));
+        assert!(leaked.iter().all(|t| t.elements != 7_777));
+        drop(kept);
+        assert!(check.leaked().iter().all(|t| t.elements != 12_345));
+        drop(check);
+
+        assert_eq!(device_memory(DeviceType::Cpu)?, None);
+        Ok(())
+    }
+}
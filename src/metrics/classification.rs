@@ -0,0 +1,338 @@
+use super::{predicted_classes, target_classes, Average, Metric};
+use crate::tensor::Tensor;
+use crate::MlResult;
+
+/// The fraction of samples whose predicted class is the target.
+#[derive(Debug, Clone, Default)]
+pub struct Accuracy {
+    correct: usize,
+    total: usize,
+}
+
+impl Accuracy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Metric for Accuracy {
+    fn name(&self) -> String {
+        "accuracy".to_string()
+    }
+
+    fn update(&mut self, predictions: &Tensor, targets: &Tensor) -> MlResult<()> {
+        let (classes, predicted) = predicted_classes(predictions)?;
+        let targets = target_classes(targets, classes, predicted.len())?;
+        self.correct += predicted
+            .iter()
+            .zip(&targets)
+            .filter(|(p, t)| p == t)
+            .count();
+        self.total += predicted.len();
+        Ok(())
+    }
+
+    fn compute(&self) -> f32 {
+        ratio(self.correct, self.total)
+    }
+
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// The fraction of samples whose target is among the `k` highest scored classes.
+/// Predictions must be `[batch, classes]` scores.
+#[derive(Debug, Clone)]
+pub struct TopKAccuracy {
+    k: usize,
+    correct: usize,
+    total: usize,
+}
+
+impl TopKAccuracy {
+    pub fn new(k: usize) -> Self {
+        Self {
+            k: k.max(1),
+            correct: 0,
+            total: 0,
+        }
+    }
+}
+
+impl Metric for TopKAccuracy {
+    fn name(&self) -> String {
+        format!("top{}_accuracy", self.k)
+    }
+
+    fn update(&mut self, predictions: &Tensor, targets: &Tensor) -> MlResult<()> {
+        let [rows, classes] = predictions.shape()[..] else {
+            return Err(format!(
+                "Top-k accuracy needs [batch, classes] scores, got {:?}",
+                predictions.shape()
+            )
+            .into());
+        };
+        let targets = target_classes(targets, classes, rows)?;
+        for (row, &target) in predictions.data().chunks_exact(classes).zip(&targets) {
+            // The target is in the top k when fewer than k classes score above it
+            let above = row.iter().filter(|&&x| x > row[target]).count();
+            self.correct += usize::from(above < self.k);
+        }
+        self.total += rows;
+        Ok(())
+    }
+
+    fn compute(&self) -> f32 {
+        ratio(self.correct, self.total)
+    }
+
+    fn reset(&mut self) {
+        self.correct = 0;
+        self.total = 0;
+    }
+}
+
+// Per-class true positives, false positives and false negatives
+#[derive(Debug, Clone, Default)]
+struct ClassCounts {
+    true_positives: Vec<usize>,
+    false_positives: Vec<usize>,
+    false_negatives: Vec<usize>,
+}
+
+impl ClassCounts {
+    fn update(&mut self, predictions: &Tensor, targets: &Tensor) -> MlResult<()> {
+        let (classes, predicted) = predicted_classes(predictions)?;
+        let targets = target_classes(targets, classes, predicted.len())?;
+        if self.true_positives.len() < classes {
+            self.true_positives.resize(classes, 0);
+            self.false_positives.resize(classes, 0);
+            self.false_negatives.resize(classes, 0);
+        }
+        for (&p, &t) in predicted.iter().zip(&targets) {
+            if p == t {
+                self.true_positives[t] += 1;
+            } else {
+                self.false_positives[p] += 1;
+                self.false_negatives[t] += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn support(&self, class: usize) -> usize {
+        self.true_positives[class] + self.false_negatives[class]
+    }
+
+    fn precision(&self, class: usize) -> f32 {
+        let tp = self.true_positives[class];
+        ratio(tp, tp + self.false_positives[class])
+    }
+
+    fn recall(&self, class: usize) -> f32 {
+        ratio(self.true_positives[class], self.support(class))
+    }
+
+    fn f1(&self, class: usize) -> f32 {
+        let tp = self.true_positives[class];
+        ratio(
+            2 * tp,
+            2 * tp + self.false_positives[class] + self.false_negatives[class],
+        )
+    }
+
+    // Combines the per-class `score`, with `pooled` computing the micro average from
+    // summed true positives, false positives and false negatives
+    fn average(
+        &self,
+        average: Average,
+        score: impl Fn(&Self, usize) -> f32,
+        pooled: impl Fn(usize, usize, usize) -> f32,
+    ) -> f32 {
+        let classes = self.true_positives.len();
+        if classes == 0 {
+            return 0.0;
+        }
+        match average {
+            Average::Macro => (0..classes).map(|c| score(self, c)).sum::<f32>() / classes as f32,
+            Average::Weighted => {
+                let total: usize = (0..classes).map(|c| self.support(c)).sum();
+                let weighted: f32 = (0..classes)
+                    .map(|c| score(self, c) * self.support(c) as f32)
+                    .sum();
+                if total == 0 {
+                    0.0
+                } else {
+                    weighted / total as f32
+                }
+            }
+            Average::Micro => pooled(
+                self.true_positives.iter().sum(),
+                self.false_positives.iter().sum(),
+                self.false_negatives.iter().sum(),
+            ),
+            Average::Binary if classes > 1 => score(self, 1),
+            Average::Binary => 0.0,
+        }
+    }
+}
+
+// `numerator / denominator`, or zero for an empty denominator
+fn ratio(numerator: usize, denominator: usize) -> f32 {
+    if denominator == 0 {
+        0.0
+    } else {
+        numerator as f32 / denominator as f32
+    }
+}
+
+macro_rules! class_metric {
+    ($(#[$doc:meta])* $name:ident, $label:literal, $score:ident, $pooled:expr) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, Default)]
+        pub struct $name {
+            average: Average,
+            counts: ClassCounts,
+        }
+
+        impl $name {
+            pub fn new(average: Average) -> Self {
+                Self {
+                    average,
+                    counts: ClassCounts::default(),
+                }
+            }
+        }
+
+        impl Metric for $name {
+            fn name(&self) -> String {
+                format!(concat!($label, "_{}"), self.average.suffix())
+            }
+
+            fn update(&mut self, predictions: &Tensor, targets: &Tensor) -> MlResult<()> {
+                self.counts.update(predictions, targets)
+            }
+
+            fn compute(&self) -> f32 {
+                self.counts
+                    .average(self.average, ClassCounts::$score, $pooled)
+            }
+
+            fn reset(&mut self) {
+                self.counts = ClassCounts::default();
+            }
+        }
+    };
+}
+
+class_metric!(
+    /// Of the samples predicted as a class, the fraction that are of it.
+    Precision,
+    "precision",
+    precision,
+    |tp, fp, _| ratio(tp, tp + fp)
+);
+
+class_metric!(
+    /// Of the samples of a class, the fraction predicted as it.
+    Recall,
+    "recall",
+    recall,
+    |tp, _, fn_| ratio(tp, tp + fn_)
+);
+
+class_metric!(
+    /// The harmonic mean of precision and recall.
+    F1Score,
+    "f1",
+    f1,
+    |tp, fp, fn_| ratio(2 * tp, 2 * tp + fp + fn_)
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{DataLoader, HostTensor, TensorDataset};
+    use crate::loss::CrossEntropyLoss;
+    use crate::nn::Linear;
+    use crate::train::Trainer;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn test_classification_metrics() -> MlResult<()> {
+        // Targets 0 0 0 1 1 2, predicted 0 0 1 1 2 2, split over two batches
+        let scores = Tensor::from_vec(
+            vec![
+                0.9, 0.1, 0.0, 0.8, 0.1, 0.1, 0.3, 0.6, 0.1, //
+                0.1, 0.7, 0.2, 0.0, 0.4, 0.6, 0.2, 0.2, 0.6,
+            ],
+            &[6, 3],
+        )?;
+        let first = Tensor::from_vec(scores.data()[..9].to_vec(), &[3, 3])?;
+        let second = Tensor::from_vec(scores.data()[9..].to_vec(), &[3, 3])?;
+        let targets = [
+            Tensor::from_vec(vec![0.0, 0.0, 0.0], &[3])?,
+            Tensor::from_vec(vec![1.0, 1.0, 2.0], &[3])?,
+        ];
+
+        let mut metrics: Vec<Box<dyn Metric>> = vec![
+            Box::new(Accuracy::new()),
+            Box::new(Precision::new(Average::Macro)),
+            Box::new(Recall::new(Average::Weighted)),
+            Box::new(F1Score::new(Average::Macro)),
+            Box::new(F1Score::new(Average::Micro)),
+            Box::new(TopKAccuracy::new(2)),
+        ];
+        for metric in &mut metrics {
+            metric.update(&first, &targets[0])?;
+            metric.update(&second, &targets[1])?;
+        }
+        let values: Vec<(String, f32)> = metrics.iter().map(|m| (m.name(), m.compute())).collect();
+        let expected = [
+            ("accuracy", 4.0 / 6.0),
+            // Per class precision 1, 1/2, 1/2 and recall 2/3, 1/2, 1
+            ("precision_macro", 2.0 / 3.0),
+            ("recall_weighted", (3.0 * 2.0 / 3.0 + 2.0 * 0.5 + 1.0) / 6.0),
+            ("f1_macro", (0.8 + 0.5 + 2.0 / 3.0) / 3.0),
+            ("f1_micro", 4.0 / 6.0),
+            ("top2_accuracy", 1.0),
+        ];
+        for ((name, value), (expected_name, expected_value)) in values.iter().zip(expected) {
+            assert_eq!(name, expected_name);
+            assert!(close(*value, expected_value), "{} = {}", name, value);
+        }
+
+        // Probabilities are thresholded at 0.5
+        let mut binary = Recall::new(Average::Binary);
+        binary.update(
+            &Tensor::from_vec(vec![0.7, 0.2, 0.4, 0.9], &[4, 1])?,
+            &Tensor::from_vec(vec![1.0, 0.0, 1.0, 1.0], &[4])?,
+        )?;
+        assert!(close(binary.compute(), 2.0 / 3.0));
+        binary.reset();
+        assert_eq!(binary.compute(), 0.0);
+        assert!(Accuracy::new()
+            .update(&first, &targets[1].mul_scalar(2.0)?)
+            .is_err());
+
+        // The trainer logs metrics next to the loss
+        let x: Vec<f32> = (0..40)
+            .map(|i| if i % 4 < 2 { 1.0 } else { -1.0 })
+            .collect();
+        let y: Vec<f32> = (0..20).map(|i| (i % 2) as f32).collect();
+        let dataset =
+            TensorDataset::from_host(HostTensor::new(x, &[20, 2])?, HostTensor::new(y, &[20])?)?;
+        let loader = DataLoader::new(dataset, 5);
+        let mut trainer = Trainer::new(Linear::new(2, 2, true)?, CrossEntropyLoss)
+            .learning_rate(0.5)
+            .metric(Accuracy::new());
+        let history = trainer.fit(&loader, 20)?;
+        assert_eq!(history.metric("accuracy").len(), 20);
+        assert_eq!(trainer.evaluate(&loader)?.get("accuracy"), Some(1.0));
+        Ok(())
+    }
+}
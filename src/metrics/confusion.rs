@@ -0,0 +1,203 @@
+use std::fmt::{Display, Formatter};
+
+use super::{predicted_classes, target_classes};
+use crate::tensor::Tensor;
+use crate::MlResult;
+
+/// Counts of samples by target class and predicted class, accumulated over batches.
+///
+/// Rows are targets and columns predictions, so the diagonal holds the correct predictions.
+/// Displaying it draws a table for the terminal:
+///
+/// ```text
+///        predicted
+/// target   cat  dog
+///    cat    12    3
+///    dog     1   14
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfusionMatrix {
+    classes: usize,
+    counts: Vec<usize>,
+    labels: Option<Vec<String>>,
+}
+
+impl ConfusionMatrix {
+    pub fn new(classes: usize) -> Self {
+        Self {
+            classes,
+            counts: vec![0; classes * classes],
+            labels: None,
+        }
+    }
+
+    /// Names the classes in the rendered table; without names they are numbered.
+    pub fn with_labels<S: ToString>(mut self, labels: &[S]) -> MlResult<Self> {
+        if labels.len() != self.classes {
+            return Err(format!("{} labels for {} classes", labels.len(), self.classes).into());
+        }
+        self.labels = Some(labels.iter().map(ToString::to_string).collect());
+        Ok(self)
+    }
+
+    /// Adds a batch of predictions, read as the [metrics module](super) describes.
+    pub fn update(&mut self, predictions: &Tensor, targets: &Tensor) -> MlResult<()> {
+        let (classes, predicted) = predicted_classes(predictions)?;
+        if classes != self.classes {
+            return Err(format!(
+                "Predictions for {} classes in a confusion matrix of {}",
+                classes, self.classes
+            )
+            .into());
+        }
+        let targets = target_classes(targets, classes, predicted.len())?;
+        self.update_classes(&predicted, &targets)
+    }
+
+    /// Adds predicted and target class indices.
+    pub fn update_classes(&mut self, predicted: &[usize], targets: &[usize]) -> MlResult<()> {
+        if predicted.len() != targets.len() {
+            return Err(format!(
+                "{} predictions but {} targets",
+                predicted.len(),
+                targets.len()
+            )
+            .into());
+        }
+        if let Some(&class) = predicted
+            .iter()
+            .chain(targets)
+            .find(|&&c| c >= self.classes)
+        {
+            return Err(format!(
+                "Class {} is out of range for {} classes",
+                class, self.classes
+            )
+            .into());
+        }
+        for (&p, &t) in predicted.iter().zip(targets) {
+            self.counts[t * self.classes + p] += 1;
+        }
+        Ok(())
+    }
+
+    pub fn num_classes(&self) -> usize {
+        self.classes
+    }
+
+    /// How many samples of class `target` were predicted as `predicted`.
+    pub fn get(&self, target: usize, predicted: usize) -> usize {
+        self.counts[target * self.classes + predicted]
+    }
+
+    /// The counts, row by row.
+    pub fn counts(&self) -> &[usize] {
+        &self.counts
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    pub fn accuracy(&self) -> f32 {
+        let correct: usize = (0..self.classes).map(|c| self.get(c, c)).sum();
+        match self.total() {
+            0 => 0.0,
+            total => correct as f32 / total as f32,
+        }
+    }
+
+    /// Each row divided by its total, so entry `(t, p)` is the fraction of class `t`
+    /// predicted as `p`. Rows of classes never seen stay zero.
+    pub fn normalized(&self) -> Vec<f32> {
+        self.counts
+            .chunks_exact(self.classes.max(1))
+            .flat_map(|row| {
+                let total: usize = row.iter().sum();
+                row.iter().map(move |&count| {
+                    if total == 0 {
+                        0.0
+                    } else {
+                        count as f32 / total as f32
+                    }
+                })
+            })
+            .collect()
+    }
+
+    pub fn reset(&mut self) {
+        self.counts.iter_mut().for_each(|count| *count = 0);
+    }
+
+    fn label(&self, class: usize) -> String {
+        match &self.labels {
+            Some(labels) => labels[class].clone(),
+            None => class.to_string(),
+        }
+    }
+}
+
+impl Display for ConfusionMatrix {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let labels: Vec<String> = (0..self.classes).map(|c| self.label(c)).collect();
+        let width = labels
+            .iter()
+            .map(String::len)
+            .chain(self.counts.iter().map(|count| count.to_string().len()))
+            .max()
+            .unwrap_or(1);
+        let first = labels
+            .iter()
+            .map(String::len)
+            .max()
+            .unwrap_or(0)
+            .max("target".len());
+
+        writeln!(f, "{:first$} predicted", "")?;
+        write!(f, "{:<first$}", "target")?;
+        for label in &labels {
+            write!(f, "  {:>width$}", label)?;
+        }
+        for (class, row) in self.counts.chunks_exact(self.classes.max(1)).enumerate() {
+            write!(f, "\n{:>first$}", labels[class])?;
+            for count in row {
+                write!(f, "  {:>width$}", count)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_confusion_matrix() -> MlResult<()> {
+        let mut matrix = ConfusionMatrix::new(2).with_labels(&["cat", "dog"])?;
+        matrix.update(
+            &Tensor::from_vec(vec![0.9, 0.1, 0.2, 0.8, 0.6, 0.4, 0.3, 0.7], &[4, 2])?,
+            &Tensor::from_vec(vec![0.0, 0.0, 1.0, 1.0], &[4])?,
+        )?;
+        matrix.update_classes(&[1], &[1])?;
+        assert_eq!(matrix.counts(), &[1, 1, 1, 2]);
+        assert_eq!(matrix.get(1, 0), 1);
+        assert!((matrix.accuracy() - 0.6).abs() < 1e-6);
+        assert_eq!(matrix.normalized()[3], 2.0 / 3.0);
+        assert_eq!(
+            matrix.to_string(),
+            "       predicted\n\
+             target  cat  dog\n   \
+                cat    1    1\n   \
+                dog    1    2"
+        );
+        assert!(matrix.update_classes(&[2], &[0]).is_err());
+        assert!(matrix
+            .update(
+                &Tensor::from_vec(vec![0.1, 0.2, 0.7], &[1, 3])?,
+                &Tensor::from_vec(vec![0.0], &[1])?
+            )
+            .is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+//! Evaluation metrics accumulated over batches.
+//!
+//! A [`Metric`] is updated with each batch's predictions and targets and computed once the
+//! epoch is over, so its value covers every sample rather than averaging per-batch values.
+//! A [`Trainer`](crate::train::Trainer) given metrics logs them with the loss.
+//!
+//! Predictions are either `[batch, classes]` scores, whose largest entry is the predicted
+//! class, or one probability per sample (`[batch]` or `[batch, 1]`), read as class 1 when it
+//! is at least 0.5. Targets are class indices, one per sample.
+
+use crate::tensor::Tensor;
+use crate::MlResult;
+
+pub mod classification;
+pub mod confusion;
+pub mod ranking;
+
+pub use classification::{Accuracy, F1Score, Precision, Recall, TopKAccuracy};
+pub use confusion::ConfusionMatrix;
+pub use ranking::{average_precision, pr_curve, roc_auc, roc_curve, PrAuc, RocAuc};
+
+/// A value summarizing predictions, accumulated batch by batch.
+pub trait Metric {
+    /// The name the metric is logged under, such as `"accuracy"`.
+    fn name(&self) -> String;
+
+    /// Adds a batch of predictions and their targets.
+    fn update(&mut self, predictions: &Tensor, targets: &Tensor) -> MlResult<()>;
+
+    /// The metric over everything added since the last reset.
+    fn compute(&self) -> f32;
+
+    /// Forgets everything added, ready for a new epoch.
+    fn reset(&mut self);
+}
+
+/// How per-class precision, recall and F1 scores combine into one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Average {
+    /// The unweighted mean over classes, so rare classes count as much as common ones.
+    #[default]
+    Macro,
+    /// Pooling every class's true and false positives and negatives first; for
+    /// single-label classification, micro precision, recall and F1 all equal accuracy.
+    Micro,
+    /// The mean over classes weighted by how many targets each class has.
+    Weighted,
+    /// Class 1 alone, for binary problems.
+    Binary,
+}
+
+impl Average {
+    fn suffix(self) -> &'static str {
+        match self {
+            Average::Macro => "macro",
+            Average::Micro => "micro",
+            Average::Weighted => "weighted",
+            Average::Binary => "binary",
+        }
+    }
+}
+
+/// The number of classes and the predicted class of each sample.
+pub(crate) fn predicted_classes(predictions: &Tensor) -> MlResult<(usize, Vec<usize>)> {
+    match predictions.shape() {
+        [_, classes] if *classes > 1 => {
+            let classes = *classes;
+            let predicted = predictions
+                .data()
+                .chunks_exact(classes)
+                .map(|row| {
+                    row.iter()
+                        .enumerate()
+                        .fold((0, f32::NEG_INFINITY), |best, (i, &x)| {
+                            if x > best.1 {
+                                (i, x)
+                            } else {
+                                best
+                            }
+                        })
+                        .0
+                })
+                .collect();
+            Ok((classes, predicted))
+        }
+        [_] | [_, 1] => Ok((
+            2,
+            predictions
+                .data()
+                .iter()
+                .map(|&p| usize::from(p >= 0.5))
+                .collect(),
+        )),
+        shape => Err(format!(
+            "Expected [batch, classes] scores or [batch] probabilities, got {:?}",
+            shape
+        )
+        .into()),
+    }
+}
+
+/// Targets as class indices below `classes`, one per prediction.
+pub(crate) fn target_classes(
+    targets: &Tensor,
+    classes: usize,
+    rows: usize,
+) -> MlResult<Vec<usize>> {
+    if targets.data().len() != rows {
+        return Err(format!(
+            "{} predictions but {} targets of shape {:?}",
+            rows,
+            targets.data().len(),
+            targets.shape()
+        )
+        .into());
+    }
+    targets
+        .data()
+        .iter()
+        .map(|&t| {
+            if t < 0.0 || t.fract() != 0.0 || t as usize >= classes {
+                return Err(format!("{} is not a class index below {}", t, classes).into());
+            }
+            Ok(t as usize)
+        })
+        .collect()
+}
@@ -0,0 +1,280 @@
+use super::{target_classes, Metric};
+use crate::tensor::Tensor;
+use crate::MlResult;
+
+/// The points of the ROC curve of `scores` against binary `labels`, as
+/// `(false positive rate, true positive rate)` from `(0, 0)` to `(1, 1)`, one per distinct
+/// score threshold.
+pub fn roc_curve(scores: &[f32], labels: &[bool]) -> Vec<(f32, f32)> {
+    let (positives, negatives) = class_totals(labels);
+    let mut curve = vec![(0.0, 0.0)];
+    curve.extend(
+        thresholds(scores, labels).map(|(tp, fp)| (ratio(fp, negatives), ratio(tp, positives))),
+    );
+    curve
+}
+
+/// The area under the ROC curve: the chance that a random positive scores above a random
+/// negative, counting ties as half. Zero when either class is missing.
+pub fn roc_auc(scores: &[f32], labels: &[bool]) -> f32 {
+    let (positives, negatives) = class_totals(labels);
+    if positives == 0 || negatives == 0 {
+        return 0.0;
+    }
+    roc_curve(scores, labels)
+        .windows(2)
+        .map(|pair| (pair[1].0 - pair[0].0) * (pair[1].1 + pair[0].1) / 2.0)
+        .sum()
+}
+
+/// The points of the precision-recall curve of `scores` against binary `labels`, as
+/// `(recall, precision)`, one per distinct score threshold from the highest down.
+pub fn pr_curve(scores: &[f32], labels: &[bool]) -> Vec<(f32, f32)> {
+    let (positives, _) = class_totals(labels);
+    thresholds(scores, labels)
+        .map(|(tp, fp)| (ratio(tp, positives), ratio(tp, tp + fp)))
+        .collect()
+}
+
+/// The area under the precision-recall curve, as average precision: the precision at each
+/// threshold weighted by the recall it adds. Zero without positives.
+pub fn average_precision(scores: &[f32], labels: &[bool]) -> f32 {
+    let mut previous_recall = 0.0;
+    pr_curve(scores, labels)
+        .into_iter()
+        .map(|(recall, precision)| {
+            let area = (recall - previous_recall) * precision;
+            previous_recall = recall;
+            area
+        })
+        .sum()
+}
+
+fn class_totals(labels: &[bool]) -> (usize, usize) {
+    let positives = labels.iter().filter(|&&l| l).count();
+    (positives, labels.len() - positives)
+}
+
+// Cumulative true and false positives as the threshold drops past each distinct score
+fn thresholds<'a>(
+    scores: &'a [f32],
+    labels: &'a [bool],
+) -> impl Iterator<Item = (usize, usize)> + 'a {
+    let mut order: Vec<usize> = (0..scores.len().min(labels.len())).collect();
+    order.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+    let (mut tp, mut fp) = (0, 0);
+    let mut position = 0;
+    std::iter::from_fn(move || {
+        let &first = order.get(position)?;
+        // Samples with equal scores cross the threshold together
+        while let Some(&i) = order.get(position) {
+            if scores[i] != scores[first] {
+                break;
+            }
+            if labels[i] {
+                tp += 1;
+            } else {
+                fp += 1;
+            }
+            position += 1;
+        }
+        Some((tp, fp))
+    })
+}
+
+fn ratio(numerator: usize, denominator: usize) -> f32 {
+    if denominator == 0 {
+        0.0
+    } else {
+        numerator as f32 / denominator as f32
+    }
+}
+
+// Scores and targets kept over batches. One score per sample, or the column for class 1 of
+// two, is a binary problem; more columns are averaged one class against the rest.
+#[derive(Debug, Clone, Default)]
+struct Scores {
+    columns: usize,
+    scores: Vec<f32>,
+    targets: Vec<usize>,
+}
+
+impl Scores {
+    fn update(&mut self, predictions: &Tensor, targets: &Tensor) -> MlResult<()> {
+        let (rows, columns) = match predictions.shape() {
+            [rows] => (*rows, 1),
+            [rows, columns] => (*rows, *columns),
+            shape => {
+                return Err(format!(
+                    "Expected [batch, classes] scores or [batch] probabilities, got {:?}",
+                    shape
+                )
+                .into())
+            }
+        };
+        if self.columns != 0 && self.columns != columns {
+            return Err(format!("{} score columns after {}", columns, self.columns).into());
+        }
+        self.targets
+            .extend(target_classes(targets, columns.max(2), rows)?);
+        self.scores.extend_from_slice(predictions.data());
+        self.columns = columns;
+        Ok(())
+    }
+
+    // The area `area` gives each one-against-rest problem, averaged over classes that
+    // have both positives and negatives
+    fn average(&self, area: fn(&[f32], &[bool]) -> f32) -> f32 {
+        if self.columns <= 2 {
+            let scores: Vec<f32> = match self.columns {
+                2 => self.scores.iter().skip(1).step_by(2).copied().collect(),
+                _ => self.scores.clone(),
+            };
+            let labels: Vec<bool> = self.targets.iter().map(|&t| t == 1).collect();
+            return area(&scores, &labels);
+        }
+
+        let mut total = 0.0;
+        let mut counted = 0;
+        for class in 0..self.columns {
+            let labels: Vec<bool> = self.targets.iter().map(|&t| t == class).collect();
+            let (positives, negatives) = class_totals(&labels);
+            if positives == 0 || negatives == 0 {
+                continue;
+            }
+            let scores: Vec<f32> = self
+                .scores
+                .iter()
+                .skip(class)
+                .step_by(self.columns)
+                .copied()
+                .collect();
+            total += area(&scores, &labels);
+            counted += 1;
+        }
+        if counted == 0 {
+            0.0
+        } else {
+            total / counted as f32
+        }
+    }
+}
+
+/// The area under the ROC curve over every batch added; see [`roc_auc`]. Multi-class
+/// scores give the mean of each class against the rest.
+#[derive(Debug, Clone, Default)]
+pub struct RocAuc {
+    scores: Scores,
+}
+
+impl RocAuc {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Metric for RocAuc {
+    fn name(&self) -> String {
+        "roc_auc".to_string()
+    }
+
+    fn update(&mut self, predictions: &Tensor, targets: &Tensor) -> MlResult<()> {
+        self.scores.update(predictions, targets)
+    }
+
+    fn compute(&self) -> f32 {
+        self.scores.average(roc_auc)
+    }
+
+    fn reset(&mut self) {
+        self.scores = Scores::default();
+    }
+}
+
+/// The area under the precision-recall curve over every batch added, as
+/// [`average_precision`]. Multi-class scores give the mean of each class against the rest.
+#[derive(Debug, Clone, Default)]
+pub struct PrAuc {
+    scores: Scores,
+}
+
+impl PrAuc {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Metric for PrAuc {
+    fn name(&self) -> String {
+        "pr_auc".to_string()
+    }
+
+    fn update(&mut self, predictions: &Tensor, targets: &Tensor) -> MlResult<()> {
+        self.scores.update(predictions, targets)
+    }
+
+    fn compute(&self) -> f32 {
+        self.scores.average(average_precision)
+    }
+
+    fn reset(&mut self) {
+        self.scores = Scores::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    #[test]
+    fn test_roc_and_pr_auc() -> MlResult<()> {
+        let scores = [0.1, 0.4, 0.35, 0.8];
+        let labels = [false, false, true, true];
+        assert_eq!(
+            roc_curve(&scores, &labels),
+            [(0.0, 0.0), (0.0, 0.5), (0.5, 0.5), (0.5, 1.0), (1.0, 1.0)]
+        );
+        assert!(close(roc_auc(&scores, &labels), 0.75));
+        // Precision 1 at recall 0.5, then 2/3 at recall 1
+        assert!(close(
+            average_precision(&scores, &labels),
+            0.5 + 0.5 * 2.0 / 3.0
+        ));
+        // Ties count as half
+        assert!(close(roc_auc(&[0.5, 0.5], &[false, true]), 0.5));
+        assert_eq!(roc_auc(&[0.3, 0.6], &[true, true]), 0.0);
+
+        // Accumulated over batches, from probabilities or two-class scores
+        let mut auc = RocAuc::new();
+        auc.update(
+            &Tensor::from_vec(vec![0.1, 0.4], &[2, 1])?,
+            &Tensor::from_vec(vec![0.0, 0.0], &[2])?,
+        )?;
+        auc.update(
+            &Tensor::from_vec(vec![0.35, 0.8], &[2])?,
+            &Tensor::from_vec(vec![1.0, 1.0], &[2])?,
+        )?;
+        assert!(close(auc.compute(), 0.75));
+        let mut two = PrAuc::new();
+        two.update(
+            &Tensor::from_vec(vec![0.9, 0.1, 0.6, 0.4, 0.65, 0.35, 0.2, 0.8], &[4, 2])?,
+            &Tensor::from_vec(vec![0.0, 0.0, 1.0, 1.0], &[4])?,
+        )?;
+        assert!(close(two.compute(), average_precision(&scores, &labels)));
+
+        // Every class ranked perfectly against the rest
+        let mut multi = RocAuc::new();
+        multi.update(
+            &Tensor::from_vec(vec![0.8, 0.1, 0.1, 0.2, 0.7, 0.1, 0.1, 0.3, 0.6], &[3, 3])?,
+            &Tensor::from_vec(vec![0.0, 1.0, 2.0], &[3])?,
+        )?;
+        assert_eq!(multi.compute(), 1.0);
+        multi.reset();
+        assert_eq!(multi.compute(), 0.0);
+        Ok(())
+    }
+}
@@ -25,8 +25,8 @@ impl Activation for Sigmoid {
         let exp_neg = neg_input.exp()?;
         let denominator = exp_neg.add_scalar(1.0)?;
 
-        let ones = Tensor::from_vec(vec![1.0; input.data().len()], input.shape())?;
-        ones.div(&denominator)
+        // A tensor op rather than dividing a tensor of ones, so the pass can be traced
+        denominator.pow(-1.0)
     }
 
     fn act_backward(&self, input: &Tensor, grad_output: &Tensor) -> MlResult<Tensor> {
@@ -50,7 +50,7 @@ mod tests {
         let output = sigmoid.act_forward(&input)?;
 
         // Check approximate values
-        let expected = vec![0.119, 0.269, 0.5, 0.731, 0.881];
+        let expected = [0.119, 0.269, 0.5, 0.731, 0.881];
         for (a, &b) in output.data().iter().zip(expected.iter()) {
             assert!((a - b).abs() < 0.001);
         }
@@ -66,7 +66,7 @@ mod tests {
         let grad_input = sigmoid.act_backward(&input, &grad_output)?;
 
         // Check derivative values: sigmoid(x) * (1 - sigmoid(x))
-        let expected = vec![0.197, 0.25, 0.197];
+        let expected = [0.197, 0.25, 0.197];
         for (a, &b) in grad_input.data().iter().zip(expected.iter()) {
             assert!((a - b).abs() < 0.001);
         }
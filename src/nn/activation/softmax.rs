@@ -1,3 +1,4 @@
+use crate::amp::full_precision;
 use crate::{nn::Activation, tensor::Tensor, MlResult};
 
 pub struct Softmax;
@@ -15,7 +16,18 @@ impl Softmax {
 }
 
 impl Activation for Softmax {
+    // The exponentials and their sum stay in f32 under autocast
     fn act_forward(&self, input: &Tensor) -> MlResult<Tensor> {
+        full_precision(|| self.forward_f32(input))
+    }
+
+    fn act_backward(&self, input: &Tensor, grad_output: &Tensor) -> MlResult<Tensor> {
+        full_precision(|| self.backward_f32(input, grad_output))
+    }
+}
+
+impl Softmax {
+    fn forward_f32(&self, input: &Tensor) -> MlResult<Tensor> {
         let batch_size = input.shape()[0];
         let num_classes = input.shape()[1];
         let mut result = vec![0.0; input.data().len()];
@@ -45,8 +57,8 @@ impl Activation for Softmax {
         Tensor::from_vec(result, input.shape())
     }
 
-    fn act_backward(&self, input: &Tensor, grad_output: &Tensor) -> MlResult<Tensor> {
-        let softmax_output = self.act_forward(input)?;
+    fn backward_f32(&self, input: &Tensor, grad_output: &Tensor) -> MlResult<Tensor> {
+        let softmax_output = self.forward_f32(input)?;
         let batch_size = input.shape()[0];
         let num_classes = input.shape()[1];
         let mut result = vec![0.0; input.data().len()];
@@ -93,7 +105,7 @@ mod tests {
         assert!((sum - 1.0).abs() < 1e-6);
 
         // Check approximate values
-        let expected = vec![0.090, 0.245, 0.665];
+        let expected = [0.090, 0.245, 0.665];
         for (a, &b) in output.data().iter().zip(expected.iter()) {
             assert!((a - b).abs() < 0.001);
         }
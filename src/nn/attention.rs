@@ -0,0 +1,354 @@
+use crate::nn::{KVCache, Layer, Linear};
+use crate::tensor::{Tensor, TensorError};
+use crate::MlResult;
+
+/// Multi-head scaled dot-product self-attention over `[batch, seq, embed_dim]` inputs.
+///
+/// The input is projected to queries, keys and values, split into `heads` heads of
+/// `embed_dim / heads` features, and each query attends to the keys with weights
+/// `softmax(q·k / sqrt(head_dim))`. The heads' results are concatenated and projected back
+/// to `embed_dim`. A [`causal`](MultiHeadAttention::causal) layer only lets each position
+/// attend to itself and the positions before it, as decoders do.
+///
+/// For generation, [`MultiHeadAttention::forward_cached`] runs on the new tokens only,
+/// keeping the keys and values of earlier ones in a [`KVCache`].
+pub struct MultiHeadAttention {
+    query: Linear,
+    key: Linear,
+    value: Linear,
+    output: Linear,
+    embed_dim: usize,
+    heads: usize,
+    causal: bool,
+}
+
+impl MultiHeadAttention {
+    /// Creates a layer with `heads` heads, which must divide `embed_dim`.
+    pub fn new(embed_dim: usize, heads: usize) -> MlResult<Self> {
+        if heads == 0 || !embed_dim.is_multiple_of(heads) {
+            return Err(format!("{} heads don't divide embed_dim {}", heads, embed_dim).into());
+        }
+        Ok(Self {
+            query: Linear::new(embed_dim, embed_dim, true)?,
+            key: Linear::new(embed_dim, embed_dim, true)?,
+            value: Linear::new(embed_dim, embed_dim, true)?,
+            output: Linear::new(embed_dim, embed_dim, true)?,
+            embed_dim,
+            heads,
+            causal: false,
+        })
+    }
+
+    /// Masks out the positions after each query's own.
+    pub fn causal(mut self, causal: bool) -> Self {
+        self.causal = causal;
+        self
+    }
+
+    pub fn embed_dim(&self) -> usize {
+        self.embed_dim
+    }
+
+    pub fn heads(&self) -> usize {
+        self.heads
+    }
+
+    /// Runs on the positions in `input` that follow the ones whose keys and values `cache`
+    /// holds for `layer`, appending theirs. Each new position attends to the cached ones
+    /// and, for a causal layer, the new ones up to its own, giving the same result as a
+    /// forward pass over the whole sequence.
+    pub fn forward_cached(
+        &self,
+        input: &Tensor,
+        cache: &mut KVCache,
+        layer: usize,
+    ) -> MlResult<Tensor> {
+        let (batch, seq, embed) = self.dims(input)?;
+        let x = input.reshape(&[batch * seq, embed])?;
+        let query = self.query.forward(&x)?;
+        let key = self.key.forward(&x)?.reshape(&[batch, seq, embed])?;
+        let value = self.value.forward(&x)?.reshape(&[batch, seq, embed])?;
+
+        let (keys, values) = cache.append(layer, key, value)?;
+        let attention = self.attend(&query, keys, values, batch, seq);
+        self.project(attention.context, [batch, seq, embed])
+    }
+
+    fn dims(&self, input: &Tensor) -> MlResult<(usize, usize, usize)> {
+        match *input.shape() {
+            [batch, seq, embed] if embed == self.embed_dim => Ok((batch, seq, embed)),
+            _ => Err(TensorError::InvalidShape {
+                expected: vec![0, 0, self.embed_dim],
+                got: input.shape().to_vec(),
+            }
+            .into()),
+        }
+    }
+
+    // Each of the `seq` queries per batch row attends to the `[batch, kv_len, embed]` keys
+    // and values, the last `seq` of which are at the queries' own positions
+    fn attend(
+        &self,
+        query: &Tensor,
+        keys: &Tensor,
+        values: &Tensor,
+        batch: usize,
+        seq: usize,
+    ) -> Attention {
+        let (kv_len, embed) = (keys.shape()[1], keys.shape()[2]);
+        let head_dim = embed / self.heads;
+        let scale = 1.0 / (head_dim as f32).sqrt();
+        let (q, k, v) = (query.data(), keys.data(), values.data());
+
+        let mut context = vec![0.0; batch * seq * embed];
+        let mut weights = vec![0.0; batch * self.heads * seq * kv_len];
+        for b in 0..batch {
+            for h in 0..self.heads {
+                for i in 0..seq {
+                    let q_row = &q[(b * seq + i) * embed + h * head_dim..][..head_dim];
+                    // Query i is at key position i + kv_len - seq
+                    let visible = if self.causal {
+                        i + kv_len - seq + 1
+                    } else {
+                        kv_len
+                    };
+                    let row = &mut weights[((b * self.heads + h) * seq + i) * kv_len..][..kv_len];
+                    for (j, weight) in row.iter_mut().enumerate().take(visible) {
+                        let k_row = &k[(b * kv_len + j) * embed + h * head_dim..][..head_dim];
+                        let dot: f32 = q_row.iter().zip(k_row).map(|(x, y)| x * y).sum();
+                        *weight = scale * dot;
+                    }
+                    softmax(&mut row[..visible]);
+
+                    let out = &mut context[(b * seq + i) * embed + h * head_dim..][..head_dim];
+                    for (j, &weight) in row.iter().enumerate().take(visible) {
+                        let v_row = &v[(b * kv_len + j) * embed + h * head_dim..][..head_dim];
+                        for (o, &value) in out.iter_mut().zip(v_row) {
+                            *o += weight * value;
+                        }
+                    }
+                }
+            }
+        }
+        Attention { context, weights }
+    }
+
+    fn project(&self, context: Vec<f32>, [batch, seq, embed]: [usize; 3]) -> MlResult<Tensor> {
+        let context = Tensor::from_vec(context, &[batch * seq, embed])?;
+        self.output.forward(&context)?.reshape(&[batch, seq, embed])
+    }
+}
+
+// The heads' concatenated results, `[batch * seq, embed]`, and the attention weights,
+// `[batch, heads, seq, kv_len]`
+struct Attention {
+    context: Vec<f32>,
+    weights: Vec<f32>,
+}
+
+fn softmax(row: &mut [f32]) {
+    let max = row.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let mut sum = 0.0;
+    for x in row.iter_mut() {
+        *x = (*x - max).exp();
+        sum += *x;
+    }
+    row.iter_mut().for_each(|x| *x /= sum);
+}
+
+impl Layer for MultiHeadAttention {
+    fn forward(&self, input: &Tensor) -> MlResult<Tensor> {
+        let (batch, seq, embed) = self.dims(input)?;
+        let x = input.reshape(&[batch * seq, embed])?;
+        let query = self.query.forward(&x)?;
+        let key = self.key.forward(&x)?.reshape(&[batch, seq, embed])?;
+        let value = self.value.forward(&x)?.reshape(&[batch, seq, embed])?;
+
+        let attention = self.attend(&query, &key, &value, batch, seq);
+        self.project(attention.context, [batch, seq, embed])
+    }
+
+    fn backward(
+        &mut self,
+        input: &Tensor,
+        grad_output: &Tensor,
+        learning_rate: f32,
+    ) -> MlResult<Tensor> {
+        let (batch, seq, embed) = self.dims(input)?;
+        let x = input.reshape(&[batch * seq, embed])?;
+        let query = self.query.forward(&x)?;
+        let key = self.key.forward(&x)?.reshape(&[batch, seq, embed])?;
+        let value = self.value.forward(&x)?.reshape(&[batch, seq, embed])?;
+        let attention = self.attend(&query, &key, &value, batch, seq);
+
+        let context = Tensor::from_vec(attention.context, &[batch * seq, embed])?;
+        let grad_output = grad_output.reshape(&[batch * seq, embed])?;
+        let grad_context = self
+            .output
+            .backward(&context, &grad_output, learning_rate)?;
+
+        // Back through the weighted sums and the softmax of each query's scores
+        let head_dim = embed / self.heads;
+        let scale = 1.0 / (head_dim as f32).sqrt();
+        let (q, k, v, dc) = (query.data(), key.data(), value.data(), grad_context.data());
+        let mut grad_q = vec![0.0; q.len()];
+        let mut grad_k = vec![0.0; k.len()];
+        let mut grad_v = vec![0.0; v.len()];
+        let mut grad_weights = vec![0.0; seq];
+        for b in 0..batch {
+            for h in 0..self.heads {
+                let offset = |t: usize| (b * seq + t) * embed + h * head_dim;
+                for i in 0..seq {
+                    let weights =
+                        &attention.weights[((b * self.heads + h) * seq + i) * seq..][..seq];
+                    let dc_row = &dc[offset(i)..][..head_dim];
+                    for j in 0..seq {
+                        let v_row = &v[offset(j)..][..head_dim];
+                        grad_weights[j] = dc_row.iter().zip(v_row).map(|(x, y)| x * y).sum();
+                        for (g, &d) in grad_v[offset(j)..][..head_dim].iter_mut().zip(dc_row) {
+                            *g += weights[j] * d;
+                        }
+                    }
+
+                    let mean: f32 = weights.iter().zip(&grad_weights).map(|(w, g)| w * g).sum();
+                    for j in 0..seq {
+                        let grad_score = weights[j] * (grad_weights[j] - mean) * scale;
+                        for c in 0..head_dim {
+                            grad_q[offset(i) + c] += grad_score * k[offset(j) + c];
+                            grad_k[offset(j) + c] += grad_score * q[offset(i) + c];
+                        }
+                    }
+                }
+            }
+        }
+
+        let grads = [grad_q, grad_k, grad_v].map(|g| Tensor::from_vec(g, &[batch * seq, embed]));
+        let [grad_q, grad_k, grad_v] = grads;
+        let grad_input = self
+            .query
+            .backward(&x, &grad_q?, learning_rate)?
+            .add(&self.key.backward(&x, &grad_k?, learning_rate)?)?
+            .add(&self.value.backward(&x, &grad_v?, learning_rate)?)?;
+        grad_input.reshape(input.shape())
+    }
+
+    fn named_parameters(&self) -> Vec<(String, &Tensor)> {
+        let layers = [
+            ("query", &self.query),
+            ("key", &self.key),
+            ("value", &self.value),
+            ("output", &self.output),
+        ];
+        layers
+            .into_iter()
+            .flat_map(|(prefix, layer)| {
+                layer
+                    .named_parameters()
+                    .into_iter()
+                    .map(move |(name, param)| (format!("{}.{}", prefix, name), param))
+            })
+            .collect()
+    }
+
+    fn named_parameters_mut(&mut self) -> Vec<(String, &mut Tensor)> {
+        let layers = [
+            ("query", &mut self.query),
+            ("key", &mut self.key),
+            ("value", &mut self.value),
+            ("output", &mut self.output),
+        ];
+        layers
+            .into_iter()
+            .flat_map(|(prefix, layer)| {
+                layer
+                    .named_parameters_mut()
+                    .into_iter()
+                    .map(move |(name, param)| (format!("{}.{}", prefix, name), param))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sequence(batch: usize, seq: usize, embed: usize) -> MlResult<Tensor> {
+        let data = (0..batch * seq * embed)
+            .map(|i| ((i * 7) % 11) as f32 / 5.0 - 1.0)
+            .collect();
+        Tensor::from_vec(data, &[batch, seq, embed])
+    }
+
+    #[test]
+    fn test_cached_attention() -> MlResult<()> {
+        let attention = MultiHeadAttention::new(4, 2)?.causal(true);
+        let input = sequence(2, 5, 4)?;
+        let full = attention.forward(&input)?;
+
+        // A prompt of three positions, then one position at a time
+        let mut cache = KVCache::new(1);
+        let mut outputs = Vec::new();
+        for (start, end) in [(0, 3), (3, 4), (4, 5)] {
+            let mut data = Vec::new();
+            for b in 0..2 {
+                data.extend_from_slice(&input.data()[(b * 5 + start) * 4..(b * 5 + end) * 4]);
+            }
+            let step = Tensor::from_vec(data, &[2, end - start, 4])?;
+            outputs.push(attention.forward_cached(&step, &mut cache, 0)?);
+        }
+        assert_eq!(cache.seq_len(0), 5);
+        for b in 0..2 {
+            let cached = outputs.iter().flat_map(|out| {
+                let len = out.shape()[1] * 4;
+                out.data()[b * len..(b + 1) * len].to_vec()
+            });
+            for (c, f) in cached.zip(&full.data()[b * 20..(b + 1) * 20]) {
+                assert!((c - f).abs() < 1e-5, "{} vs {}", c, f);
+            }
+        }
+        assert!(MultiHeadAttention::new(4, 3).is_err());
+        assert!(attention
+            .forward(&Tensor::from_vec(vec![0.0; 6], &[1, 2, 3])?)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_attention_backward() -> MlResult<()> {
+        // The input gradient matches finite differences of sum(output * grad)
+        let mut attention = MultiHeadAttention::new(4, 2)?.causal(true);
+        let input = sequence(1, 3, 4)?;
+        let grad = sequence(1, 3, 4)?.mul_scalar(0.5)?.add_scalar(0.25)?;
+        let loss = |layer: &MultiHeadAttention, x: &Tensor| -> MlResult<f32> {
+            layer.forward(x)?.mul(&grad)?.sum_all()
+        };
+
+        let eps = 1e-2;
+        let mut numeric = Vec::new();
+        for i in 0..input.data().len() {
+            let mut data = input.data().to_vec();
+            data[i] += eps;
+            let plus = loss(&attention, &Tensor::from_vec(data.clone(), &[1, 3, 4])?)?;
+            data[i] -= 2.0 * eps;
+            let minus = loss(&attention, &Tensor::from_vec(data, &[1, 3, 4])?)?;
+            numeric.push((plus - minus) / (2.0 * eps));
+        }
+
+        let before = attention.state_dict();
+        let grad_input = attention.backward(&input, &grad, 0.0)?;
+        assert_eq!(grad_input.shape(), &[1, 3, 4]);
+        for (a, n) in grad_input.data().iter().zip(&numeric) {
+            assert!((a - n).abs() < 1e-2, "{} vs {}", a, n);
+        }
+        assert_eq!(attention.named_parameters().len(), 8);
+        assert_eq!(
+            attention
+                .state_dict()
+                .get("key.weight")
+                .map(|t| t.data().to_vec()),
+            before.get("key.weight").map(|t| t.data().to_vec())
+        );
+        Ok(())
+    }
+}
@@ -1,21 +1,217 @@
-use crate::{nn::Layer, tensor::Tensor, MlResult};
+use crate::nn::{Layer, Parameter};
+use crate::prune::{self, Prune};
+use crate::quantize::{quantize_weight, ActivationParams, Granularity, Quantize, QuantizedConv2d};
+use crate::{tensor::Tensor, MlResult};
 
 /// Represents different padding modes for the convolutional layer
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PaddingMode {
-    Valid, // No padding
-    Same,  // Pad to maintain input spatial dimensions
+    Valid,  // No padding
+    Same,   // Pad to maintain input spatial dimensions
+    Causal, // Pad only the start, so no output depends on later inputs (Conv1d only)
+}
+
+impl PaddingMode {
+    /// The padding on each side of a dimension of `input_size`.
+    pub(crate) fn amount(self, input_size: usize, kernel_size: usize, stride: usize) -> usize {
+        self.amounts(input_size, kernel_size, stride).0
+    }
+
+    /// The padding before and after a dimension of `input_size`, for a kernel spanning
+    /// `kernel_size` positions. Odd `Same` padding puts the extra position at the end.
+    pub(crate) fn amounts(
+        self,
+        input_size: usize,
+        kernel_size: usize,
+        stride: usize,
+    ) -> (usize, usize) {
+        match self {
+            PaddingMode::Valid => (0, 0),
+            PaddingMode::Same => {
+                let output_size = input_size.div_ceil(stride);
+                let total_padding =
+                    ((output_size - 1) * stride + kernel_size).saturating_sub(input_size);
+                (total_padding / 2, total_padding - total_padding / 2)
+            }
+            PaddingMode::Causal => (kernel_size - 1, 0),
+        }
+    }
+}
+
+/// 1D convolutional layer over `[batch, channels, length]` sequences, with optional
+/// dilation. With [`PaddingMode::Causal`] and growing dilations, stacks of these make the
+/// WaveNet / TCN style sequence models whose outputs never look ahead.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Conv1d {
+    in_channels: usize,
+    out_channels: usize,
+    kernel_size: usize,
+    stride: usize,
+    dilation: usize,
+    padding: PaddingMode,
+    weights: Parameter,
+    bias: Option<Parameter>,
+}
+
+impl Conv1d {
+    /// Creates a new Conv1d layer
+    ///
+    /// # Arguments
+    /// * `in_channels` - Number of input channels
+    /// * `out_channels` - Number of output channels
+    /// * `kernel_size` - Number of taps of the kernel
+    /// * `stride` - Stride of the convolution
+    /// * `dilation` - Spacing between the kernel's taps, 1 for a dense kernel
+    /// * `padding` - Padding mode to use
+    /// * `use_bias` - Whether to include a bias term
+    pub fn new(
+        in_channels: usize,
+        out_channels: usize,
+        kernel_size: usize,
+        stride: usize,
+        dilation: usize,
+        padding: PaddingMode,
+        use_bias: bool,
+    ) -> MlResult<Self> {
+        if kernel_size == 0 || stride == 0 || dilation == 0 {
+            return Err("Conv1d needs a positive kernel size, stride and dilation".into());
+        }
+
+        let k = 1.0 / ((in_channels * kernel_size) as f32).sqrt();
+        let mut rng = crate::nn::random::SimpleRng::new(crate::nn::random::next_seed());
+        let weight_data = (0..out_channels * in_channels * kernel_size)
+            .map(|_| rng.gen_range(-k, k))
+            .collect();
+        let weights = Tensor::from_vec(weight_data, &[out_channels, in_channels, kernel_size])?;
+
+        let bias = if use_bias {
+            let bias_data = (0..out_channels).map(|_| rng.gen_range(-k, k)).collect();
+            Some(Tensor::from_vec(bias_data, &[out_channels])?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            in_channels,
+            out_channels,
+            kernel_size,
+            stride,
+            dilation,
+            padding,
+            weights: weights.into(),
+            bias: bias.map(Parameter::new),
+        })
+    }
+
+    pub fn weights(&self) -> &Tensor {
+        &self.weights
+    }
+
+    // The padding before and after a sequence of `length`
+    fn get_padding(&self, length: usize) -> (usize, usize) {
+        let span = self.dilation * (self.kernel_size - 1) + 1;
+        self.padding.amounts(length, span, self.stride)
+    }
+}
+
+impl Layer for Conv1d {
+    fn forward(&self, input: &Tensor) -> MlResult<Tensor> {
+        if input.shape().len() != 3 {
+            return Err("Conv1d expects 3D input (batch_size, channels, length)".into());
+        }
+
+        let padding = self.get_padding(input.shape()[2]);
+        let output = input.conv1d(&self.weights, self.stride, padding, self.dilation)?;
+        let Some(ref bias) = self.bias else {
+            return Ok(output);
+        };
+
+        let length = output.shape()[2];
+        let data = output
+            .data()
+            .iter()
+            .enumerate()
+            .map(|(i, &value)| value + bias.data()[(i / length) % self.out_channels])
+            .collect();
+        Tensor::from_vec(data, output.shape())
+    }
+
+    fn backward(
+        &mut self,
+        input: &Tensor,
+        grad_output: &Tensor,
+        learning_rate: f32,
+    ) -> MlResult<Tensor> {
+        let (batch, length) = (input.shape()[0], input.shape()[2]);
+        let out_length = grad_output.shape()[2];
+        let (before, _) = self.get_padding(length);
+        let (channels, kernel) = (self.in_channels, self.kernel_size);
+
+        let (x, w, grad) = (input.data(), self.weights.data(), grad_output.data());
+        let mut grad_input = vec![0.0; x.len()];
+        let mut grad_weights = vec![0.0; w.len()];
+        let mut grad_bias = vec![0.0; self.out_channels];
+        for b in 0..batch {
+            for oc in 0..self.out_channels {
+                let grad_row = &grad[(b * self.out_channels + oc) * out_length..][..out_length];
+                grad_bias[oc] += grad_row.iter().sum::<f32>();
+                for c in 0..channels {
+                    let start = (b * channels + c) * length;
+                    for j in 0..kernel {
+                        let tap = (oc * channels + c) * kernel + j;
+                        for (t, &g) in grad_row.iter().enumerate() {
+                            // The input position this tap read for output `t`
+                            let position =
+                                (t * self.stride + j * self.dilation).wrapping_sub(before);
+                            if position < length {
+                                grad_input[start + position] += g * w[tap];
+                                grad_weights[tap] += g * x[start + position];
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        let weight_grad = Tensor::from_vec(grad_weights, self.weights.shape())?;
+        *self.weights = self.weights.sub(&weight_grad.mul_scalar(learning_rate)?)?;
+        if let Some(bias) = self.bias.as_deref_mut() {
+            let bias_grad = Tensor::from_vec(grad_bias, &[self.out_channels])?;
+            *bias = bias.sub(&bias_grad.mul_scalar(learning_rate)?)?;
+        }
+
+        Tensor::from_vec(grad_input, input.shape())
+    }
+
+    fn named_parameters(&self) -> Vec<(String, &Tensor)> {
+        let mut params = vec![("weight".to_string(), &*self.weights)];
+        params.extend(self.bias.as_deref().map(|bias| ("bias".to_string(), bias)));
+        params
+    }
+
+    fn named_parameters_mut(&mut self) -> Vec<(String, &mut Tensor)> {
+        let mut params = vec![("weight".to_string(), &mut *self.weights)];
+        params.extend(
+            self.bias
+                .as_deref_mut()
+                .map(|bias| ("bias".to_string(), bias)),
+        );
+        params
+    }
 }
 
 /// 2D Convolutional Layer
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Conv2d {
     in_channels: usize,
     out_channels: usize,
     kernel_size: usize,
     stride: usize,
     padding: PaddingMode,
-    weights: Tensor,
-    bias: Option<Tensor>,
+    groups: usize,
+    weights: Parameter,
+    bias: Option<Parameter>,
 }
 
 impl Conv2d {
@@ -36,22 +232,55 @@ impl Conv2d {
         padding: PaddingMode,
         use_bias: bool,
     ) -> MlResult<Self> {
+        Self::grouped(
+            in_channels,
+            out_channels,
+            kernel_size,
+            stride,
+            padding,
+            1,
+            use_bias,
+        )
+    }
+
+    /// Creates a Conv2d layer whose channels are split into `groups`, each convolved with
+    /// its own filters, as [`Tensor::conv2d_grouped`] computes it. Both channel counts must
+    /// be multiples of `groups`.
+    pub fn grouped(
+        in_channels: usize,
+        out_channels: usize,
+        kernel_size: usize,
+        stride: usize,
+        padding: PaddingMode,
+        groups: usize,
+        use_bias: bool,
+    ) -> MlResult<Self> {
+        if padding == PaddingMode::Causal {
+            return Err("Causal padding is only supported by Conv1d".into());
+        }
+        if groups == 0
+            || !in_channels.is_multiple_of(groups)
+            || !out_channels.is_multiple_of(groups)
+        {
+            return Err(format!(
+                "Conv2d channels {} -> {} can't be split into {} groups",
+                in_channels, out_channels, groups
+            )
+            .into());
+        }
+        let group_inputs = in_channels / groups;
+
         // Initialize weights using Xavier initialization
-        let k = 1.0 / ((in_channels * kernel_size * kernel_size) as f32).sqrt();
-        let mut rng = crate::nn::random::SimpleRng::new(
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .map(|d| d.as_nanos() as u64)
-                .map_err(|e| format!("Time error: {}", e))?,
-        );
+        let k = 1.0 / ((group_inputs * kernel_size * kernel_size) as f32).sqrt();
+        let mut rng = crate::nn::random::SimpleRng::new(crate::nn::random::next_seed());
 
-        let weight_data: Vec<f32> = (0..out_channels * in_channels * kernel_size * kernel_size)
+        let weight_data: Vec<f32> = (0..out_channels * group_inputs * kernel_size * kernel_size)
             .map(|_| rng.gen_range(-k, k))
             .collect();
 
         let weights = Tensor::from_vec(
             weight_data,
-            &[out_channels, in_channels, kernel_size, kernel_size],
+            &[out_channels, group_inputs, kernel_size, kernel_size],
         )?;
 
         let bias = if use_bias {
@@ -67,25 +296,54 @@ impl Conv2d {
             kernel_size,
             stride,
             padding,
-            weights,
-            bias,
+            groups,
+            weights: weights.into(),
+            bias: bias.map(Parameter::new),
         })
     }
 
+    /// Creates a depthwise Conv2d layer, with one group per input channel and
+    /// `multiplier` filters for each. Followed by a 1x1 [`Conv2d::new`] it makes the
+    /// depthwise-separable convolution of MobileNet-style networks.
+    pub fn depthwise(
+        channels: usize,
+        multiplier: usize,
+        kernel_size: usize,
+        stride: usize,
+        padding: PaddingMode,
+        use_bias: bool,
+    ) -> MlResult<Self> {
+        Self::grouped(
+            channels,
+            channels * multiplier,
+            kernel_size,
+            stride,
+            padding,
+            channels,
+            use_bias,
+        )
+    }
+
     fn get_padding(&self, input_size: usize) -> usize {
-        match self.padding {
-            PaddingMode::Valid => 0,
-            PaddingMode::Same => {
-                let output_size = (input_size + self.stride - 1) / self.stride;
-                let total_padding = (output_size - 1) * self.stride + self.kernel_size - input_size;
-                total_padding / 2
-            }
-        }
+        self.padding
+            .amount(input_size, self.kernel_size, self.stride)
     }
 
     pub fn weights(&self) -> &Tensor {
         &self.weights
     }
+
+    pub fn groups(&self) -> usize {
+        self.groups
+    }
+
+    // Channel pruning and quantization keep the channels in one group
+    fn check_ungrouped(&self, what: &str) -> MlResult<()> {
+        if self.groups != 1 {
+            return Err(format!("{} of grouped convolutions isn't supported", what).into());
+        }
+        Ok(())
+    }
 }
 
 impl Layer for Conv2d {
@@ -95,70 +353,28 @@ impl Layer for Conv2d {
             return Err("Conv2d expects 4D input (batch_size, channels, height, width)".into());
         }
 
-        let batch_size = input_shape[0];
-        let height = input_shape[2];
-        let width = input_shape[3];
-
-        let padding = self.get_padding(height);
-        let output_height = (height + 2 * padding - self.kernel_size) / self.stride + 1;
-        let output_width = (width + 2 * padding - self.kernel_size) / self.stride + 1;
-
-        let mut output = vec![0.0; batch_size * self.out_channels * output_height * output_width];
-
-        // Perform convolution operation
-        for b in 0..batch_size {
-            for c_out in 0..self.out_channels {
-                for h in 0..output_height {
-                    for w in 0..output_width {
-                        let mut sum = 0.0;
-
-                        for c_in in 0..self.in_channels {
-                            for kh in 0..self.kernel_size {
-                                for kw in 0..self.kernel_size {
-                                    let h_in = h * self.stride + kh;
-                                    let w_in = w * self.stride + kw;
-
-                                    if h_in >= padding
-                                        && w_in >= padding
-                                        && h_in < height + padding
-                                        && w_in < width + padding
-                                    {
-                                        let h_in = h_in - padding;
-                                        let w_in = w_in - padding;
-
-                                        let input_idx =
-                                            ((b * self.in_channels + c_in) * height + h_in) * width
-                                                + w_in;
-                                        let weight_idx = ((c_out * self.in_channels + c_in)
-                                            * self.kernel_size
-                                            + kh)
-                                            * self.kernel_size
-                                            + kw;
-
-                                        sum += input.data()[input_idx]
-                                            * self.weights.data()[weight_idx];
-                                    }
-                                }
-                            }
-                        }
-
-                        if let Some(ref bias) = self.bias {
-                            sum += bias.data()[c_out];
-                        }
+        let padding = self.get_padding(input_shape[2]);
+        let output = input.conv2d_grouped(
+            &self.weights,
+            (self.stride, self.stride),
+            (padding, padding),
+            self.groups,
+        )?;
 
-                        let output_idx = ((b * self.out_channels + c_out) * output_height + h)
-                            * output_width
-                            + w;
-                        output[output_idx] = sum;
-                    }
-                }
-            }
-        }
+        let Some(ref bias) = self.bias else {
+            return Ok(output);
+        };
 
-        Tensor::from_vec(
-            output,
-            &[batch_size, self.out_channels, output_height, output_width],
-        )
+        // Broadcast the bias over every output channel's feature map
+        let shape = output.shape().to_vec();
+        let spatial = shape[2] * shape[3];
+        let data = output
+            .data()
+            .iter()
+            .enumerate()
+            .map(|(i, &value)| value + bias.data()[(i / spatial) % self.out_channels])
+            .collect();
+        Tensor::from_vec(data, &shape)
     }
 
     /// Computes the gradient for backpropagation
@@ -174,9 +390,11 @@ impl Layer for Conv2d {
         let width = input_shape[3];
 
         let padding = self.get_padding(height);
+        let group_inputs = self.in_channels / self.groups;
+        let group_outputs = self.out_channels / self.groups;
         let mut grad_input = vec![0.0; batch_size * self.in_channels * height * width];
         let mut grad_weights =
-            vec![0.0; self.out_channels * self.in_channels * self.kernel_size * self.kernel_size];
+            vec![0.0; self.out_channels * group_inputs * self.kernel_size * self.kernel_size];
         let grad_bias = if self.bias.is_some() {
             vec![0.0; self.out_channels]
         } else {
@@ -187,7 +405,9 @@ impl Layer for Conv2d {
         // For each batch and channel
         for b in 0..batch_size {
             for c_out in 0..self.out_channels {
-                for c_in in 0..self.in_channels {
+                // Each output channel only sees the input channels of its group
+                let first_input = c_out / group_outputs * group_inputs;
+                for c_in in first_input..first_input + group_inputs {
                     for h in 0..height {
                         for w in 0..width {
                             // Calculate gradients for input and weights
@@ -216,7 +436,8 @@ impl Layer for Conv2d {
                                                 ((b * self.in_channels + c_in) * height + h)
                                                     * width
                                                     + w;
-                                            let weight_idx = ((c_out * self.in_channels + c_in)
+                                            let weight_idx = ((c_out * group_inputs + c_in
+                                                - first_input)
                                                 * self.kernel_size
                                                 + kh)
                                                 * self.kernel_size
@@ -241,21 +462,97 @@ impl Layer for Conv2d {
             grad_weights,
             &[
                 self.out_channels,
-                self.in_channels,
+                group_inputs,
                 self.kernel_size,
                 self.kernel_size,
             ],
         )?;
-        self.weights = self.weights.sub(&weight_grad.mul_scalar(learning_rate)?)?;
+        *self.weights = self.weights.sub(&weight_grad.mul_scalar(learning_rate)?)?;
 
         // Update bias if it exists
-        if let Some(bias) = &mut self.bias {
+        if let Some(bias) = self.bias.as_deref_mut() {
             let bias_grad = Tensor::from_vec(grad_bias, &[self.out_channels])?;
             *bias = bias.sub(&bias_grad.mul_scalar(learning_rate)?)?;
         }
 
         Tensor::from_vec(grad_input, input_shape)
     }
+
+    fn named_parameters(&self) -> Vec<(String, &Tensor)> {
+        let mut params = vec![("weight".to_string(), &*self.weights)];
+        params.extend(self.bias.as_deref().map(|bias| ("bias".to_string(), bias)));
+        params
+    }
+
+    fn named_parameters_mut(&mut self) -> Vec<(String, &mut Tensor)> {
+        let mut params = vec![("weight".to_string(), &mut *self.weights)];
+        params.extend(
+            self.bias
+                .as_deref_mut()
+                .map(|bias| ("bias".to_string(), bias)),
+        );
+        params
+    }
+}
+
+impl Prune for Conv2d {
+    fn output_norms(&self) -> Vec<f32> {
+        prune::channel_norms(&self.weights)
+    }
+
+    fn num_inputs(&self) -> usize {
+        self.in_channels
+    }
+
+    fn keep_outputs(&mut self, keep: &[usize]) -> MlResult<()> {
+        self.check_ungrouped("Channel pruning")?;
+        let bias = self
+            .bias
+            .as_deref()
+            .map(|bias| prune::select(bias, 0, keep))
+            .transpose()?;
+        *self.weights = prune::select(&self.weights, 0, keep)?;
+        if let (Some(param), Some(bias)) = (self.bias.as_deref_mut(), bias) {
+            *param = bias;
+        }
+        self.out_channels = keep.len();
+        Ok(())
+    }
+
+    fn keep_inputs(&mut self, keep: &[usize]) -> MlResult<()> {
+        self.check_ungrouped("Channel pruning")?;
+        *self.weights = prune::select(&self.weights, 1, keep)?;
+        self.in_channels = keep.len();
+        Ok(())
+    }
+}
+
+impl Quantize for Conv2d {
+    fn quantize_static(
+        &self,
+        input: ActivationParams,
+        output: ActivationParams,
+        granularity: Granularity,
+    ) -> MlResult<Box<dyn Layer>> {
+        self.check_ungrouped("Quantization")?;
+        let layer = QuantizedConv2d::new(
+            quantize_weight(&self.weights, granularity)?,
+            self.bias.as_deref(),
+            self.stride,
+            self.padding,
+            input,
+            output,
+        )?;
+        Ok(Box::new(layer))
+    }
+
+    fn quantize_dynamic(&self, granularity: Granularity) -> MlResult<Box<dyn Layer>> {
+        self.check_ungrouped("Quantization")?;
+        let weight = quantize_weight(&self.weights, granularity)?;
+        let layer =
+            QuantizedConv2d::dynamic(weight, self.bias.as_deref(), self.stride, self.padding)?;
+        Ok(Box::new(layer))
+    }
 }
 
 #[cfg(test)]
@@ -292,4 +589,78 @@ mod tests {
         assert_eq!(grad_input.shape(), input.shape());
         Ok(())
     }
+
+    #[test]
+    fn test_conv1d_causal_dilated() -> MlResult<()> {
+        let mut conv = Conv1d::new(1, 1, 2, 1, 2, PaddingMode::Causal, true)?;
+        *conv.weights = Tensor::from_vec(vec![1.0, 10.0], &[1, 1, 2])?;
+        *conv.bias.as_deref_mut().unwrap() = Tensor::from_vec(vec![0.5], &[1])?;
+        let input = Tensor::from_vec(vec![1.0, 2.0, 3.0, 4.0, 5.0], &[1, 1, 5])?;
+
+        // Output t is x[t - 2] + 10 x[t]: the length is kept and nothing looks ahead
+        let output = conv.forward(&input)?;
+        assert_eq!(output.data(), &[10.5, 20.5, 31.5, 42.5, 53.5]);
+        let changed = Tensor::from_vec(vec![1.0, 2.0, 3.0, 4.0, 9.0], &[1, 1, 5])?;
+        assert_eq!(conv.forward(&changed)?.data()[..4], output.data()[..4]);
+
+        let same = Conv1d::new(2, 3, 3, 1, 2, PaddingMode::Same, false)?;
+        let sequence = Tensor::from_vec(vec![0.5; 14], &[1, 2, 7])?;
+        assert_eq!(same.forward(&sequence)?.shape(), &[1, 3, 7]);
+
+        // A gradient step on the last output moves the taps that read it
+        let grad = Tensor::from_vec(vec![0.0, 0.0, 0.0, 0.0, 1.0], &[1, 1, 5])?;
+        let grad_input = conv.backward(&input, &grad, 0.1)?;
+        assert_eq!(grad_input.data(), &[0.0, 0.0, 1.0, 0.0, 10.0]);
+        assert!((conv.weights.data()[0] - 0.7).abs() < 1e-6);
+        assert!((conv.weights.data()[1] - 9.5).abs() < 1e-6);
+
+        assert!(Conv1d::new(1, 1, 2, 1, 0, PaddingMode::Valid, false).is_err());
+        assert!(Conv2d::new(1, 1, 2, 1, PaddingMode::Causal, false).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_grouped_conv2d() -> MlResult<()> {
+        let input = Tensor::from_vec((0..36).map(|x| x as f32 * 0.1).collect(), &[1, 4, 3, 3])?;
+
+        // Two groups match two separate convolutions over their halves of the channels
+        let conv = Conv2d::grouped(4, 6, 2, 1, PaddingMode::Valid, 2, false)?;
+        assert_eq!(conv.weights.shape(), &[6, 2, 2, 2]);
+        let output = conv.forward(&input)?;
+        assert_eq!(output.shape(), &[1, 6, 2, 2]);
+        for g in 0..2 {
+            let half = input.narrow(1, g * 2, 2)?;
+            let expected = half.conv2d(&conv.weights.narrow(0, g * 3, 3)?, (1, 1), (0, 0))?;
+            assert_eq!(output.narrow(1, g * 3, 3)?.data(), expected.data());
+        }
+
+        // The depthwise kernel matches a convolution of each channel on its own
+        let depthwise = Conv2d::depthwise(4, 2, 3, 2, PaddingMode::Same, true)?;
+        assert_eq!(depthwise.weights.shape(), &[8, 1, 3, 3]);
+        let plain = input.conv2d_grouped(&depthwise.weights, (2, 2), (1, 1), 4)?;
+        assert_eq!(plain.shape(), &[1, 8, 2, 2]);
+        for c in 0..4 {
+            let channel = input.narrow(1, c, 1)?;
+            let filters = depthwise.weights.narrow(0, c * 2, 2)?;
+            let expected = channel.conv2d(&filters, (2, 2), (1, 1))?;
+            let got = plain.narrow(1, c * 2, 2)?;
+            for (a, b) in got.data().iter().zip(expected.data()) {
+                assert!((a - b).abs() < 1e-5);
+            }
+        }
+        assert_eq!(depthwise.forward(&input)?.shape(), &[1, 8, 2, 2]);
+
+        let mut depthwise = depthwise;
+        let grad = Tensor::from_vec(vec![1.0; 32], &[1, 8, 2, 2])?;
+        assert_eq!(
+            depthwise.backward(&input, &grad, 0.1)?.shape(),
+            input.shape()
+        );
+        assert!(depthwise.keep_outputs(&[0, 1]).is_err());
+        assert!(Conv2d::grouped(4, 6, 2, 1, PaddingMode::Valid, 4, false).is_err());
+        assert!(input
+            .conv2d_grouped(&conv.weights, (1, 1), (0, 0), 3)
+            .is_err());
+        Ok(())
+    }
 }
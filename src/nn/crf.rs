@@ -0,0 +1,386 @@
+use crate::nn::random::with_global_rng;
+use crate::nn::{Layer, Parameter};
+use crate::tensor::{Tensor, TensorError};
+use crate::MlResult;
+
+/// A linear-chain conditional random field over `[batch, seq, num_tags]` emission scores,
+/// the output layer of taggers that label every token of a sentence, as for named entities.
+///
+/// A tagging's score is the sum of its tags' emission scores and of the learned scores of
+/// starting on its first tag, moving between each pair of neighbouring tags and ending on
+/// its last one, so the layer can learn that `I-PER` never follows `O`.
+/// [`Crf::loss`] is the negative log-likelihood of the gold tags, normalized over every
+/// tagging with the forward algorithm, and [`Crf::loss_backward`] trains on it.
+/// [`Layer::forward`] gives the best tagging by Viterbi decoding, as [`Crf::decode`] does.
+///
+/// The sequences of a batch have the same length; pad none of them, or group them by length.
+pub struct Crf {
+    /// Score of moving from the tag of the row to the tag of the column
+    transitions: Parameter,
+    start_transitions: Parameter,
+    end_transitions: Parameter,
+}
+
+impl Crf {
+    /// Creates a CRF over `num_tags` tags with small random transition scores.
+    pub fn new(num_tags: usize) -> MlResult<Self> {
+        if num_tags == 0 {
+            return Err("A CRF needs at least one tag".into());
+        }
+        let random = |len: usize, shape: &[usize]| {
+            let data: Vec<f32> =
+                with_global_rng(|rng| (0..len).map(|_| rng.gen_range(-0.1, 0.1)).collect());
+            Tensor::from_vec(data, shape)
+        };
+        Ok(Self {
+            transitions: random(num_tags * num_tags, &[num_tags, num_tags])?.into(),
+            start_transitions: random(num_tags, &[num_tags])?.into(),
+            end_transitions: random(num_tags, &[num_tags])?.into(),
+        })
+    }
+
+    pub fn num_tags(&self) -> usize {
+        self.start_transitions.shape()[0]
+    }
+
+    /// The negative log-likelihood of `tags`, `[batch, seq]` tag indices, under
+    /// `emissions`, averaged over the batch.
+    pub fn loss(&self, emissions: &Tensor, tags: &Tensor) -> MlResult<f32> {
+        let (batch, seq, _) = self.dims(emissions)?;
+        let tags = self.tag_indices(tags, batch, seq)?;
+        let total: f32 = (self.sequences(emissions, batch, seq))
+            .zip(tags.chunks(seq))
+            .map(|(emissions, tags)| {
+                let alpha = self.alpha(emissions);
+                self.log_partition(&alpha) - self.score(emissions, tags)
+            })
+            .sum();
+        Ok(total / batch.max(1) as f32)
+    }
+
+    /// Takes a gradient descent step on [`Crf::loss`] for the transition scores and returns
+    /// its gradient with respect to `emissions`, to backpropagate into the layers that
+    /// produced them.
+    ///
+    /// The gradient of each emission score is the probability of its tag at its position,
+    /// from the forward-backward algorithm, less one where it is the gold tag.
+    pub fn loss_backward(
+        &mut self,
+        emissions: &Tensor,
+        tags: &Tensor,
+        learning_rate: f32,
+    ) -> MlResult<Tensor> {
+        let (batch, seq, num_tags) = self.dims(emissions)?;
+        let tags = self.tag_indices(tags, batch, seq)?;
+        let scale = 1.0 / batch.max(1) as f32;
+
+        let mut grad_emissions = vec![0.0; emissions.data().len()];
+        let mut grad_transitions = vec![0.0; num_tags * num_tags];
+        let mut grad_start = vec![0.0; num_tags];
+        let mut grad_end = vec![0.0; num_tags];
+        let transitions = self.transitions.data();
+        let sequences = self.sequences(emissions, batch, seq).zip(tags.chunks(seq));
+        for ((emissions, tags), grad) in sequences.zip(grad_emissions.chunks_mut(seq * num_tags)) {
+            let alpha = self.alpha(emissions);
+            let beta = self.beta(emissions);
+            let log_z = self.log_partition(&alpha);
+
+            // Expected counts under the model, less the gold tagging's
+            for t in 0..seq {
+                for j in 0..num_tags {
+                    let at = t * num_tags + j;
+                    let marginal = (alpha[at] + beta[at] - log_z).exp();
+                    grad[at] = marginal * scale;
+                    if t > 0 {
+                        for i in 0..num_tags {
+                            let pair = alpha[(t - 1) * num_tags + i]
+                                + transitions[i * num_tags + j]
+                                + emissions[at]
+                                + beta[at]
+                                - log_z;
+                            grad_transitions[i * num_tags + j] += pair.exp() * scale;
+                        }
+                    }
+                    if t == 0 {
+                        grad_start[j] += marginal * scale;
+                    }
+                    if t == seq - 1 {
+                        grad_end[j] += marginal * scale;
+                    }
+                }
+                grad[t * num_tags + tags[t]] -= scale;
+                if t > 0 {
+                    grad_transitions[tags[t - 1] * num_tags + tags[t]] -= scale;
+                }
+            }
+            grad_start[tags[0]] -= scale;
+            grad_end[tags[seq - 1]] -= scale;
+        }
+
+        for (param, grad) in [
+            (&mut self.transitions, grad_transitions),
+            (&mut self.start_transitions, grad_start),
+            (&mut self.end_transitions, grad_end),
+        ] {
+            let data = (param.data().iter().zip(&grad))
+                .map(|(p, g)| p - learning_rate * g)
+                .collect();
+            **param = Tensor::from_vec(data, param.shape())?.to_device(param.device())?;
+        }
+        Tensor::from_vec(grad_emissions, emissions.shape())?.to_device(emissions.device())
+    }
+
+    /// The highest-scoring tagging of each sequence, as `[batch, seq]` tag indices, found
+    /// with the Viterbi algorithm.
+    pub fn decode(&self, emissions: &Tensor) -> MlResult<Tensor> {
+        let (batch, seq, num_tags) = self.dims(emissions)?;
+        let transitions = self.transitions.data();
+        let mut result = Vec::with_capacity(batch * seq);
+        for emissions in self.sequences(emissions, batch, seq) {
+            // The best score of a tagging ending on each tag, and where each came from
+            let mut best: Vec<f32> = (self.start_transitions.data().iter())
+                .zip(&emissions[..num_tags])
+                .map(|(s, e)| s + e)
+                .collect();
+            let mut backpointers = Vec::with_capacity((seq - 1) * num_tags);
+            for emission in emissions.chunks(num_tags).skip(1) {
+                let next = (0..num_tags)
+                    .map(|j| {
+                        let (from, score) = (0..num_tags)
+                            .map(|i| (i, best[i] + transitions[i * num_tags + j]))
+                            .fold((0, f32::NEG_INFINITY), |a, b| if b.1 > a.1 { b } else { a });
+                        backpointers.push(from);
+                        score + emission[j]
+                    })
+                    .collect();
+                best = next;
+            }
+
+            let ends = best.iter().zip(self.end_transitions.data());
+            let (mut tag, _) = ends
+                .map(|(b, e)| b + e)
+                .enumerate()
+                .fold((0, f32::NEG_INFINITY), |a, b| if b.1 > a.1 { b } else { a });
+            let mut path = vec![tag; seq];
+            for t in (1..seq).rev() {
+                tag = backpointers[(t - 1) * num_tags + tag];
+                path[t - 1] = tag;
+            }
+            result.extend(path.into_iter().map(|tag| tag as f32));
+        }
+        Tensor::from_vec(result, &[batch, seq])
+    }
+
+    fn dims(&self, emissions: &Tensor) -> MlResult<(usize, usize, usize)> {
+        match *emissions.shape() {
+            [batch, seq, tags] if tags == self.num_tags() && seq > 0 => Ok((batch, seq, tags)),
+            _ => Err(TensorError::InvalidShape {
+                expected: vec![0, 0, self.num_tags()],
+                got: emissions.shape().to_vec(),
+            }
+            .into()),
+        }
+    }
+
+    // `tags` as indices, checked against the batch and the number of tags
+    fn tag_indices(&self, tags: &Tensor, batch: usize, seq: usize) -> MlResult<Vec<usize>> {
+        if tags.shape() != [batch, seq] {
+            return Err(TensorError::InvalidShape {
+                expected: vec![batch, seq],
+                got: tags.shape().to_vec(),
+            }
+            .into());
+        }
+        (tags.data().iter())
+            .map(|&tag| {
+                if tag < 0.0 || tag.fract() != 0.0 || tag as usize >= self.num_tags() {
+                    return Err(format!("{} is not a tag below {}", tag, self.num_tags()).into());
+                }
+                Ok(tag as usize)
+            })
+            .collect()
+    }
+
+    // Each sequence's `[seq, num_tags]` emission scores
+    fn sequences<'a>(
+        &self,
+        emissions: &'a Tensor,
+        batch: usize,
+        seq: usize,
+    ) -> impl Iterator<Item = &'a [f32]> {
+        emissions.data().chunks(seq * self.num_tags()).take(batch)
+    }
+
+    // The score of `tags` under a sequence's `emissions`
+    fn score(&self, emissions: &[f32], tags: &[usize]) -> f32 {
+        let num_tags = self.num_tags();
+        let transitions = self.transitions.data();
+        let steps: f32 = (tags.iter().enumerate())
+            .map(|(t, &tag)| emissions[t * num_tags + tag])
+            .chain(tags.windows(2).map(|w| transitions[w[0] * num_tags + w[1]]))
+            .sum();
+        self.start_transitions.data()[tags[0]]
+            + steps
+            + self.end_transitions.data()[tags[tags.len() - 1]]
+    }
+
+    // The log of the total score of the taggings of each prefix that end on each tag, from
+    // the start, as `[seq, num_tags]`
+    fn alpha(&self, emissions: &[f32]) -> Vec<f32> {
+        let num_tags = self.num_tags();
+        let transitions = self.transitions.data();
+        let mut alpha = Vec::with_capacity(emissions.len());
+        alpha.extend(
+            (self.start_transitions.data().iter())
+                .zip(&emissions[..num_tags])
+                .map(|(s, e)| s + e),
+        );
+        for t in 1..emissions.len() / num_tags {
+            for j in 0..num_tags {
+                let previous = &alpha[(t - 1) * num_tags..t * num_tags];
+                let total = log_sum_exp(
+                    (previous.iter().enumerate()).map(|(i, a)| a + transitions[i * num_tags + j]),
+                );
+                alpha.push(total + emissions[t * num_tags + j]);
+            }
+        }
+        alpha
+    }
+
+    // The same as `alpha` for the rest of the sequence after each position and tag, to
+    // the end
+    fn beta(&self, emissions: &[f32]) -> Vec<f32> {
+        let num_tags = self.num_tags();
+        let seq = emissions.len() / num_tags;
+        let transitions = self.transitions.data();
+        let mut beta = vec![0.0; emissions.len()];
+        beta[(seq - 1) * num_tags..].copy_from_slice(self.end_transitions.data());
+        for t in (0..seq - 1).rev() {
+            for i in 0..num_tags {
+                let total = log_sum_exp((0..num_tags).map(|j| {
+                    let next = (t + 1) * num_tags + j;
+                    transitions[i * num_tags + j] + emissions[next] + beta[next]
+                }));
+                beta[t * num_tags + i] = total;
+            }
+        }
+        beta
+    }
+
+    // The log of the total score of every tagging
+    fn log_partition(&self, alpha: &[f32]) -> f32 {
+        let last = &alpha[alpha.len() - self.num_tags()..];
+        log_sum_exp(
+            last.iter()
+                .zip(self.end_transitions.data())
+                .map(|(a, e)| a + e),
+        )
+    }
+}
+
+impl Layer for Crf {
+    fn forward(&self, input: &Tensor) -> MlResult<Tensor> {
+        self.decode(input)
+    }
+
+    /// Decoded tags have no gradient; train the CRF with [`Crf::loss_backward`] instead.
+    fn backward(&mut self, _: &Tensor, _: &Tensor, _: f32) -> MlResult<Tensor> {
+        Err("A CRF is trained through Crf::loss_backward, not its decoded tags".into())
+    }
+
+    fn named_parameters(&self) -> Vec<(String, &Tensor)> {
+        vec![
+            ("transitions".to_string(), &*self.transitions),
+            ("start_transitions".to_string(), &*self.start_transitions),
+            ("end_transitions".to_string(), &*self.end_transitions),
+        ]
+    }
+
+    fn named_parameters_mut(&mut self) -> Vec<(String, &mut Tensor)> {
+        vec![
+            ("transitions".to_string(), &mut *self.transitions),
+            (
+                "start_transitions".to_string(),
+                &mut *self.start_transitions,
+            ),
+            ("end_transitions".to_string(), &mut *self.end_transitions),
+        ]
+    }
+}
+
+// The log of the sum of the exponentials of `values`, without overflowing
+fn log_sum_exp(values: impl Iterator<Item = f32> + Clone) -> f32 {
+    let max = values.clone().fold(f32::NEG_INFINITY, f32::max);
+    if max == f32::NEG_INFINITY {
+        return max;
+    }
+    max + values.map(|v| (v - max).exp()).sum::<f32>().ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every tagging of a sequence of `seq` positions over `num_tags` tags
+    fn taggings(seq: usize, num_tags: usize) -> Vec<Vec<usize>> {
+        (0..num_tags.pow(seq as u32))
+            .map(|n| {
+                (0..seq)
+                    .map(|t| n / num_tags.pow(t as u32) % num_tags)
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_crf() -> MlResult<()> {
+        crate::seed_all(3);
+        let crf = Crf::new(3)?;
+        let values = (0..12).map(|i| ((i * 7) % 5) as f32 * 0.4 - 0.8).collect();
+        let emissions = Tensor::from_vec(values, &[1, 4, 3])?;
+        let tags = Tensor::from_vec(vec![0.0, 2.0, 2.0, 1.0], &[1, 4])?;
+
+        // The loss matches normalizing over all 81 taggings by brute force
+        let scores: Vec<f32> = (taggings(4, 3).iter())
+            .map(|tags| crf.score(emissions.data(), tags))
+            .collect();
+        let log_z = log_sum_exp(scores.iter().copied());
+        let expected = log_z - crf.score(emissions.data(), &[0, 2, 2, 1]);
+        assert!((crf.loss(&emissions, &tags)? - expected).abs() < 1e-4);
+
+        // Viterbi finds the best of them
+        let best = (0..scores.len()).fold(0, |a, b| if scores[b] > scores[a] { b } else { a });
+        let decoded = crf.decode(&emissions)?;
+        let decoded: Vec<usize> = decoded.data().iter().map(|&t| t as usize).collect();
+        assert_eq!(decoded, taggings(4, 3)[best]);
+        assert_eq!(crf.forward(&emissions)?.shape(), &[1, 4]);
+
+        // The emission gradient matches finite differences
+        let mut trained = Crf::new(3)?;
+        trained.load_state_dict(&crf.state_dict(), true)?;
+        let grad = trained.loss_backward(&emissions, &tags, 0.0)?;
+        for at in [0, 5, 11] {
+            let mut nudged = emissions.data().to_vec();
+            nudged[at] += 1e-2;
+            let nudged = Tensor::from_vec(nudged, &[1, 4, 3])?;
+            let numeric = (crf.loss(&nudged, &tags)? - crf.loss(&emissions, &tags)?) / 1e-2;
+            assert!((grad.data()[at] - numeric).abs() < 1e-2, "{}", at);
+        }
+
+        // Training makes the gold tagging the decoded one
+        for _ in 0..100 {
+            trained.loss_backward(&emissions, &tags, 0.5)?;
+        }
+        assert!(trained.loss(&emissions, &tags)? < crf.loss(&emissions, &tags)?);
+        assert_eq!(trained.decode(&emissions)?.data(), tags.data());
+
+        assert!(crf
+            .loss(&emissions, &Tensor::from_vec(vec![3.0; 4], &[1, 4])?)
+            .is_err());
+        assert!(crf
+            .decode(&Tensor::from_vec(vec![0.0; 8], &[1, 4, 2])?)
+            .is_err());
+        assert!(trained.backward(&emissions, &tags, 0.1).is_err());
+        Ok(())
+    }
+}
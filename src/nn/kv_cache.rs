@@ -0,0 +1,230 @@
+use crate::tensor::{Tensor, TensorError};
+use crate::MlResult;
+
+/// The keys and values attention layers computed for the positions already decoded, so
+/// each step of autoregressive generation only runs the model on the new tokens.
+///
+/// Each layer's keys and values are `[batch, positions, dim]` tensors, filled in by
+/// [`MultiHeadAttention::forward_cached`](crate::nn::MultiHeadAttention::forward_cached).
+/// With a [`window`](KVCache::window), positions older than the most recent `window` are
+/// dropped before each append, bounding memory for long generations. [`KVCache::reorder`]
+/// picks the batch rows to continue from, as beam search does after each step.
+///
+/// ```ignore
+/// let mut cache = KVCache::new(layers.len()).window(1024);
+/// let mut logits = model.forward_cached(&prompt, &mut cache)?;
+/// for _ in 0..max_new_tokens {
+///     let next = pick(&logits)?;
+///     logits = model.forward_cached(&next, &mut cache)?;
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct KVCache {
+    layers: Vec<Option<LayerCache>>,
+    window: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+struct LayerCache {
+    keys: Tensor,
+    values: Tensor,
+    // Positions appended in total, including those dropped from the window
+    position: usize,
+}
+
+impl KVCache {
+    /// An empty cache for a model with `num_layers` attention layers.
+    pub fn new(num_layers: usize) -> Self {
+        Self {
+            layers: vec![None; num_layers],
+            window: None,
+        }
+    }
+
+    /// Keeps only the most recent `window` positions from earlier appends.
+    pub fn window(mut self, window: usize) -> Self {
+        self.window = Some(window);
+        self
+    }
+
+    pub fn num_layers(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// The keys and values `layer` holds, if anything was appended to it.
+    pub fn get(&self, layer: usize) -> Option<(&Tensor, &Tensor)> {
+        let cache = self.layers.get(layer)?.as_ref()?;
+        Some((&cache.keys, &cache.values))
+    }
+
+    /// How many positions `layer` holds.
+    pub fn seq_len(&self, layer: usize) -> usize {
+        self.get(layer).map_or(0, |(keys, _)| keys.shape()[1])
+    }
+
+    /// How many positions were appended to `layer` since it was empty, including any the
+    /// window dropped: the position of the next token.
+    pub fn position(&self, layer: usize) -> usize {
+        match self.layers.get(layer) {
+            Some(Some(cache)) => cache.position,
+            _ => 0,
+        }
+    }
+
+    /// Appends the `[batch, positions, dim]` keys and values of new positions to `layer`,
+    /// returning everything it holds.
+    pub fn append(
+        &mut self,
+        layer: usize,
+        keys: Tensor,
+        values: Tensor,
+    ) -> MlResult<(&Tensor, &Tensor)> {
+        if keys.shape().len() != 3 || keys.shape() != values.shape() {
+            return Err(TensorError::InvalidShape {
+                expected: keys.shape().to_vec(),
+                got: values.shape().to_vec(),
+            }
+            .into());
+        }
+        let num_layers = self.layers.len();
+        let slot = self.layers.get_mut(layer).ok_or_else(|| {
+            format!(
+                "Layer {} is out of range for a cache of {} layers",
+                layer, num_layers
+            )
+        })?;
+
+        let added = keys.shape()[1];
+        let cache = match slot.as_ref() {
+            None => LayerCache {
+                keys,
+                values,
+                position: added,
+            },
+            Some(cache) => {
+                let (batch, _, dim) = dims(&cache.keys);
+                if keys.shape()[0] != batch || keys.shape()[2] != dim {
+                    return Err(TensorError::InvalidShape {
+                        expected: vec![batch, added, dim],
+                        got: keys.shape().to_vec(),
+                    }
+                    .into());
+                }
+                let keep = self.window.unwrap_or(usize::MAX);
+                LayerCache {
+                    keys: concat(&cache.keys, &keys, keep)?,
+                    values: concat(&cache.values, &values, keep)?,
+                    position: cache.position + added,
+                }
+            }
+        };
+        let cache = slot.insert(cache);
+        Ok((&cache.keys, &cache.values))
+    }
+
+    /// Replaces each layer's batch with the rows `indices` pick from it, in that order. The
+    /// same row may be picked more than once, so a batch of one prompt can be expanded into
+    /// beams.
+    pub fn reorder(&mut self, indices: &[usize]) -> MlResult<()> {
+        for cache in self.layers.iter().flatten() {
+            let batch = cache.keys.shape()[0];
+            if let Some(index) = indices.iter().find(|&&index| index >= batch) {
+                return Err(
+                    format!("Index {} is out of range for a batch of {}", index, batch).into(),
+                );
+            }
+        }
+        for cache in self.layers.iter_mut().flatten() {
+            cache.keys = select_rows(&cache.keys, indices)?;
+            cache.values = select_rows(&cache.values, indices)?;
+        }
+        Ok(())
+    }
+
+    /// Empties every layer, for decoding a new sequence.
+    pub fn clear(&mut self) {
+        self.layers.iter_mut().for_each(|cache| *cache = None);
+    }
+}
+
+fn dims(tensor: &Tensor) -> (usize, usize, usize) {
+    let shape = tensor.shape();
+    (shape[0], shape[1], shape[2])
+}
+
+// `new` after the last `keep` positions of `old`, along axis 1
+fn concat(old: &Tensor, new: &Tensor, keep: usize) -> MlResult<Tensor> {
+    let old_len = old.shape()[1];
+    let kept = old.slice(1, old_len.saturating_sub(keep), old_len)?;
+    Tensor::cat(&[&kept, new], 1)
+}
+
+fn select_rows(tensor: &Tensor, indices: &[usize]) -> MlResult<Tensor> {
+    let row: usize = tensor.shape()[1..].iter().product();
+    let mut data = Vec::with_capacity(indices.len() * row);
+    for &index in indices {
+        data.extend_from_slice(&tensor.data()[index * row..(index + 1) * row]);
+    }
+    let mut shape = tensor.shape().to_vec();
+    shape[0] = indices.len();
+    Tensor::from_vec(data, &shape)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // [batch, positions, 1] with the values of `rows`
+    fn tensor(rows: &[&[f32]]) -> MlResult<Tensor> {
+        let data: Vec<f32> = rows.iter().flat_map(|row| row.iter().copied()).collect();
+        Tensor::from_vec(data, &[rows.len(), rows[0].len(), 1])
+    }
+
+    #[test]
+    fn test_kv_cache() -> MlResult<()> {
+        let mut cache = KVCache::new(2).window(3);
+        assert!(cache.get(0).is_none());
+
+        let keys = tensor(&[&[1.0, 2.0], &[5.0, 6.0]])?;
+        cache.append(0, keys.clone(), keys.mul_scalar(10.0)?)?;
+        let (keys, values) =
+            cache.append(0, tensor(&[&[3.0], &[7.0]])?, tensor(&[&[30.0], &[70.0]])?)?;
+        assert_eq!(keys.data(), &[1.0, 2.0, 3.0, 5.0, 6.0, 7.0]);
+        assert_eq!(values.data(), &[10.0, 20.0, 30.0, 50.0, 60.0, 70.0]);
+
+        // The window drops the oldest positions before appending
+        let (keys, _) = cache.append(
+            0,
+            tensor(&[&[4.0, 4.5], &[8.0, 8.5]])?,
+            tensor(&[&[0.0, 0.0], &[0.0, 0.0]])?,
+        )?;
+        assert_eq!(keys.shape(), &[2, 5, 1]);
+        assert_eq!(keys.data()[..5], [1.0, 2.0, 3.0, 4.0, 4.5][..]);
+        let (keys, _) = cache.append(0, tensor(&[&[9.0], &[9.5]])?, tensor(&[&[0.0], &[0.0]])?)?;
+        assert_eq!(keys.data(), &[3.0, 4.0, 4.5, 9.0, 7.0, 8.0, 8.5, 9.5]);
+        assert_eq!((cache.seq_len(0), cache.position(0)), (4, 6));
+        assert_eq!((cache.seq_len(1), cache.position(1)), (0, 0));
+
+        // Mismatched batches and layers out of range are errors
+        assert!(cache
+            .append(0, tensor(&[&[1.0]])?, tensor(&[&[1.0]])?)
+            .is_err());
+        assert!(cache
+            .append(2, tensor(&[&[1.0]])?, tensor(&[&[1.0]])?)
+            .is_err());
+
+        // Beams continue from the rows picked
+        cache.reorder(&[1, 1, 0])?;
+        let (keys, _) = cache.get(0).ok_or("layer 0 is filled")?;
+        assert_eq!(keys.shape(), &[3, 4, 1]);
+        assert_eq!(
+            keys.data()[4..],
+            [7.0, 8.0, 8.5, 9.5, 3.0, 4.0, 4.5, 9.0][..]
+        );
+        assert!(cache.reorder(&[3]).is_err());
+
+        cache.clear();
+        assert_eq!(cache.position(0), 0);
+        Ok(())
+    }
+}
@@ -1,17 +1,23 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::io::{Cursor, Read, Write};
 
-use crate::serialize::{Deserialize, Model, Serialize};
-use crate::{nn::Layer, tensor::Tensor, MlResult};
+use crate::nn::{Layer, Parameter};
+use crate::prune::{self, Prune};
+use crate::quantize::{quantize_weight, ActivationParams, Granularity, Quantize, QuantizedLinear};
+use crate::serialize::format::{self, ByteReader};
+use crate::serialize::state_dict::write_entries;
+use crate::serialize::{Deserialize, FormatError, Model, Serialize, StateDict};
+use crate::{tensor::Tensor, MlResult};
 
 use aporia::{backend::Xoshiro256StarStar, Rng};
 /// A fully connected (linear/dense) neural network layer.
 ///
 /// Applies a linear transformation to the incoming data: y = xW^T + b
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Linear {
     /// Weight matrix of shape [out_features, in_features]
-    weight: Tensor,
+    weight: Parameter,
     /// Optional bias vector of shape [out_features]
-    bias: Option<Tensor>,
+    bias: Option<Parameter>,
 }
 
 impl Linear {
@@ -25,11 +31,7 @@ impl Linear {
     /// # Returns
     /// * `MlResult<Self>` - A new Linear layer instance
     pub fn new(in_features: usize, out_features: usize, bias: bool) -> MlResult<Self> {
-        // Get seed from system time
-        let seed = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .map(|d| d.as_nanos() as u64)
-            .map_err(|e| format!("Time went backwards: {}", e))?;
+        let seed = crate::nn::random::next_seed();
 
         let rng_backend = Xoshiro256StarStar::new(seed);
         let mut rng = Rng::new(rng_backend);
@@ -68,7 +70,28 @@ impl Linear {
             None
         };
 
-        Ok(Self { weight, bias })
+        Ok(Self::from_tensors(weight, bias))
+    }
+
+    fn from_tensors(weight: Tensor, bias: Option<Tensor>) -> Self {
+        Self {
+            weight: weight.into(),
+            bias: bias.map(Parameter::new),
+        }
+    }
+
+    // The layer's parameters as they are, for the tensor-parallel layers that hold a shard
+    #[cfg(feature = "distributed")]
+    pub(crate) fn into_parts(self) -> (Tensor, Option<Tensor>) {
+        (
+            self.weight.into_inner(),
+            self.bias.map(Parameter::into_inner),
+        )
+    }
+
+    #[cfg(feature = "distributed")]
+    pub(crate) fn from_parts(weight: Tensor, bias: Option<Tensor>) -> Self {
+        Self::from_tensors(weight, bias)
     }
 
     // Add getter methods for testing
@@ -79,13 +102,13 @@ impl Linear {
 
     #[cfg(test)]
     pub fn bias(&self) -> Option<&Tensor> {
-        self.bias.as_ref()
+        self.bias.as_deref()
     }
 }
 
 impl Layer for Linear {
     fn forward(&self, input: &Tensor) -> MlResult<Tensor> {
-        let output = input.matmul(&self.weight.transpose()?)?;
+        let output = input.matmul(&self.weight.transpose(0, 1)?)?;
 
         if let Some(bias) = &self.bias {
             output.add(bias)
@@ -104,81 +127,164 @@ impl Layer for Linear {
         let grad_input = grad_output.matmul(&self.weight)?;
 
         // Compute gradient with respect to weights
-        let grad_weights = grad_output.transpose()?.matmul(input)?;
+        let grad_weights = grad_output.transpose(0, 1)?.matmul(input)?;
 
         // Update weights using gradient descent
         let weight_update = grad_weights.mul_scalar(learning_rate)?;
-        self.weight = self.weight.sub(&weight_update)?;
+        *self.weight = self.weight.sub(&weight_update)?;
 
         // Update bias if it exists
-        if let Some(bias) = &mut self.bias {
+        if let Some(bias) = self.bias.as_deref_mut() {
             // For bias, we need to sum across the batch dimension (dim 0)
-            let grad_bias = grad_output.sum(0)?;
+            let grad_bias = grad_output.sum(&[0], false)?;
             // Apply learning rate
             let bias_update = grad_bias.mul_scalar(learning_rate)?;
-            // The bias should be a 1D tensor of shape [out_features]
-            *bias = bias.sub(&bias_update.reshape(&[bias.shape()[0]])?)?;
+            *bias = bias.sub(&bias_update)?;
         }
 
         Ok(grad_input)
     }
+
+    fn named_parameters(&self) -> Vec<(String, &Tensor)> {
+        let mut params = vec![("weight".to_string(), &*self.weight)];
+        params.extend(self.bias.as_deref().map(|bias| ("bias".to_string(), bias)));
+        params
+    }
+
+    fn named_parameters_mut(&mut self) -> Vec<(String, &mut Tensor)> {
+        let mut params = vec![("weight".to_string(), &mut *self.weight)];
+        params.extend(
+            self.bias
+                .as_deref_mut()
+                .map(|bias| ("bias".to_string(), bias)),
+        );
+        params
+    }
 }
 
 impl Serialize for Linear {
     fn serialize(&self) -> Vec<u8> {
-        let mut bytes = Vec::new();
-
-        // Serialize weights
-        let weight_bytes = self.weight.serialize();
-        bytes.extend_from_slice(&(weight_bytes.len() as u32).to_le_bytes());
-        bytes.extend(weight_bytes);
+        self.state_dict().serialize()
+    }
 
-        // Serialize bias flag and data if present
-        let has_bias = self.bias.is_some() as u8;
-        bytes.push(has_bias);
+    fn serialize_into<W: Write>(&self, writer: W) -> MlResult<()> {
+        let params = self.named_parameters();
+        let entries: Vec<_> = params.iter().map(|(name, p)| (name.as_str(), *p)).collect();
+        write_entries(writer, &entries)
+    }
+}
 
-        if let Some(ref bias) = self.bias {
-            let bias_bytes = bias.serialize();
-            bytes.extend_from_slice(&(bias_bytes.len() as u32).to_le_bytes());
-            bytes.extend(bias_bytes);
+impl Deserialize for Linear {
+    fn deserialize(bytes: &[u8]) -> MlResult<Self> {
+        if !StateDict::is_state_dict(bytes) {
+            return Self::deserialize_fields(bytes);
         }
+        Self::from_state_dict(StateDict::deserialize(bytes)?)
+    }
 
-        bytes
+    fn deserialize_from<R: Read>(mut reader: R) -> MlResult<Self> {
+        let mut magic = [0; 4];
+        format::read_exact(&mut reader, &mut magic)?;
+        let mut reader = Cursor::new(magic).chain(reader);
+        if !StateDict::is_state_dict(&magic) {
+            let mut bytes = Vec::new();
+            reader
+                .read_to_end(&mut bytes)
+                .map_err(|e| format!("Failed to read data: {}", e))?;
+            return Self::deserialize_fields(&bytes);
+        }
+        Self::from_state_dict(StateDict::deserialize_from(reader)?)
     }
 }
 
-impl Deserialize for Linear {
-    fn deserialize(bytes: &[u8]) -> MlResult<Self> {
-        let mut cursor = 0;
+impl Linear {
+    fn from_state_dict(mut state: StateDict) -> MlResult<Self> {
+        let weight = state
+            .remove("weight")
+            .ok_or_else(|| FormatError::Invalid("missing weight".into()))?;
+        let bias = state.remove("bias");
+        if let Some(name) = state.keys().next() {
+            return Err(FormatError::Invalid(format!("unexpected parameter {}", name)).into());
+        }
+        if weight.shape().len() != 2
+            || bias
+                .as_ref()
+                .is_some_and(|b| b.shape() != [weight.shape()[0]])
+        {
+            return Err(FormatError::Invalid("parameter shapes don't match".into()).into());
+        }
 
-        // Read weight size
-        let weight_size = u32::from_le_bytes(bytes[0..4].try_into().unwrap()) as usize;
-        cursor += 4;
+        Ok(Linear::from_tensors(weight, bias))
+    }
 
-        // Deserialize weight
-        let weight = Tensor::deserialize(&bytes[cursor..cursor + weight_size])?;
-        cursor += weight_size;
+    /// Reads the field-by-field layout written before layers were saved as state dicts.
+    fn deserialize_fields(bytes: &[u8]) -> MlResult<Self> {
+        let mut reader = ByteReader::new(bytes);
 
-        // Read bias flag
-        let has_bias = bytes[cursor] != 0;
-        cursor += 1;
+        // Deserialize weight
+        let weight = Tensor::deserialize(reader.prefixed()?)?;
 
         // Deserialize bias if present
-        let bias = if has_bias {
-            let bias_size =
-                u32::from_le_bytes(bytes[cursor..cursor + 4].try_into().unwrap()) as usize;
-            cursor += 4;
-            Some(Tensor::deserialize(&bytes[cursor..cursor + bias_size])?)
-        } else {
-            None
+        let bias = match reader.u8()? {
+            0 => None,
+            1 => Some(Tensor::deserialize(reader.prefixed()?)?),
+            flag => return Err(FormatError::Invalid(format!("bias flag {}", flag)).into()),
         };
+        reader.finish()?;
 
-        Ok(Linear { weight, bias })
+        Ok(Linear::from_tensors(weight, bias))
     }
 }
 
 impl Model for Linear {}
 
+impl Quantize for Linear {
+    fn quantize_static(
+        &self,
+        input: ActivationParams,
+        output: ActivationParams,
+        granularity: Granularity,
+    ) -> MlResult<Box<dyn Layer>> {
+        let weight = quantize_weight(&self.weight, granularity)?;
+        let layer = QuantizedLinear::new(weight, self.bias.as_deref(), input, output)?;
+        Ok(Box::new(layer))
+    }
+
+    fn quantize_dynamic(&self, granularity: Granularity) -> MlResult<Box<dyn Layer>> {
+        let weight = quantize_weight(&self.weight, granularity)?;
+        let layer = QuantizedLinear::dynamic(weight, self.bias.as_deref())?;
+        Ok(Box::new(layer))
+    }
+}
+
+impl Prune for Linear {
+    fn output_norms(&self) -> Vec<f32> {
+        prune::channel_norms(&self.weight)
+    }
+
+    fn num_inputs(&self) -> usize {
+        self.weight.shape()[1]
+    }
+
+    fn keep_outputs(&mut self, keep: &[usize]) -> MlResult<()> {
+        let bias = self
+            .bias
+            .as_deref()
+            .map(|bias| prune::select(bias, 0, keep))
+            .transpose()?;
+        *self.weight = prune::select(&self.weight, 0, keep)?;
+        if let (Some(param), Some(bias)) = (self.bias.as_deref_mut(), bias) {
+            *param = bias;
+        }
+        Ok(())
+    }
+
+    fn keep_inputs(&mut self, keep: &[usize]) -> MlResult<()> {
+        *self.weight = prune::select(&self.weight, 1, keep)?;
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -240,4 +346,48 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_state_dict() -> MlResult<()> {
+        let source = Linear::new(2, 3, true)?;
+        let state = source.state_dict();
+        assert_eq!(state.keys().collect::<Vec<_>>(), ["weight", "bias"]);
+
+        let mut target = Linear::new(2, 3, true)?;
+        assert!(target.load_state_dict(&state, true)?.is_clean());
+        assert_eq!(target.weight().data(), source.weight().data());
+
+        // Non-strict loading reports what didn't line up and loads the rest
+        let mut partial = StateDict::new();
+        partial.insert("weight", Tensor::from_vec(vec![0.0; 6], &[3, 2])?);
+        partial.insert("running_mean", Tensor::from_vec(vec![0.0; 3], &[3])?);
+        assert!(target.load_state_dict(&partial, true).is_err());
+        assert_eq!(target.weight().data(), source.weight().data());
+
+        let report = target.load_state_dict(&partial, false)?;
+        assert_eq!(report.missing_keys, ["bias"]);
+        assert_eq!(report.unexpected_keys, ["running_mean"]);
+        assert_eq!(target.weight().data(), &[0.0; 6]);
+
+        let mut wrong_shape = StateDict::new();
+        wrong_shape.insert("weight", Tensor::from_vec(vec![0.0; 6], &[2, 3])?);
+        assert!(target.load_state_dict(&wrong_shape, false).is_err());
+
+        // Round trip through the serialized form, and the older field-by-field layout
+        let restored = Linear::deserialize(&source.serialize())?;
+        assert_eq!(
+            restored.bias().map(|b| b.data()),
+            source.bias().map(|b| b.data())
+        );
+
+        let weight = source.weight().serialize();
+        let mut legacy = (weight.len() as u32).to_le_bytes().to_vec();
+        legacy.extend(weight);
+        legacy.push(0);
+        let restored = Linear::deserialize(&legacy)?;
+        assert_eq!(restored.weight().data(), source.weight().data());
+        assert!(restored.bias().is_none());
+
+        Ok(())
+    }
 }
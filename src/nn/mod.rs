@@ -1,18 +1,27 @@
 pub mod activation;
+pub mod attention;
 pub mod conv;
+pub mod crf;
+pub mod kv_cache;
 pub mod linear;
+pub mod parameter;
 pub mod pooling;
 pub mod random;
 
 pub use activation::{Activation, ReLU, Sigmoid, Swish, Tanh};
-pub use conv::{Conv2d, PaddingMode};
+pub use attention::MultiHeadAttention;
+pub use conv::{Conv1d, Conv2d, PaddingMode};
+pub use crf::Crf;
+pub use kv_cache::KVCache;
 pub use linear::Linear;
-pub use pooling::{Pooling, PoolingType};
+pub use parameter::{Buffer, Parameter};
+pub use pooling::{AdaptivePooling, FractionalMaxPooling, Pooling, PoolingType};
+
+use crate::amp::Precision;
+use crate::backend::DeviceType;
+use crate::serialize::state_dict::match_keys;
+use crate::serialize::{LoadReport, StateDict};
 
-/// A trait representing a neural network module/layer.
-///
-/// This trait defines the basic interface that all neural network modules must implement.
-/// Each module should be able to perform a forward pass on input data.
 // pub trait Module {
 //     /// Performs a forward pass through the module.
 //     ///
@@ -24,6 +33,10 @@ pub use pooling::{Pooling, PoolingType};
 //     fn forward(&self, input: &crate::tensor::Tensor) -> crate::MlResult<crate::tensor::Tensor>;
 // }
 
+/// A trait representing a neural network module/layer.
+///
+/// This trait defines the basic interface that all neural network modules must implement.
+/// Each module should be able to perform a forward pass on input data.
 pub trait Layer {
     /// Performs a forward pass through the layer.
     fn forward(&self, input: &crate::tensor::Tensor) -> crate::MlResult<crate::tensor::Tensor>;
@@ -35,4 +48,122 @@ pub trait Layer {
         grad_output: &crate::tensor::Tensor,
         learning_rate: f32,
     ) -> crate::MlResult<crate::tensor::Tensor>;
+
+    /// The layer's trainable tensors by name, in a fixed order. Layers without weights keep
+    /// the default.
+    fn named_parameters(&self) -> Vec<(String, &crate::tensor::Tensor)> {
+        Vec::new()
+    }
+
+    /// Mutable access to the same tensors as [`Layer::named_parameters`], in the same order.
+    fn named_parameters_mut(&mut self) -> Vec<(String, &mut crate::tensor::Tensor)> {
+        Vec::new()
+    }
+
+    /// The layer's trainable tensors.
+    fn parameters_mut(&mut self) -> Vec<&mut crate::tensor::Tensor> {
+        self.named_parameters_mut()
+            .into_iter()
+            .map(|(_, param)| param)
+            .collect()
+    }
+
+    /// The layer's non-trainable state, such as running statistics, by name. It moves and
+    /// is saved along with the parameters; layers without any keep the default.
+    fn named_buffers(&self) -> Vec<(String, &crate::tensor::Tensor)> {
+        Vec::new()
+    }
+
+    /// Mutable access to the same tensors as [`Layer::named_buffers`], in the same order.
+    fn named_buffers_mut(&mut self) -> Vec<(String, &mut crate::tensor::Tensor)> {
+        Vec::new()
+    }
+
+    /// Moves every parameter and buffer to `device`, including those of nested layers.
+    ///
+    /// Each tensor is replaced in place, so names, order and [`Parameter`] ids stay the same.
+    /// Nothing is moved unless every tensor can be.
+    fn to_device(&mut self, device: DeviceType) -> crate::MlResult<()> {
+        let moved = |tensors: Vec<(String, &crate::tensor::Tensor)>| {
+            tensors
+                .into_iter()
+                .map(|(_, tensor)| tensor.to_device(device))
+                .collect::<crate::MlResult<Vec<_>>>()
+        };
+        let params = moved(self.named_parameters())?;
+        let buffers = moved(self.named_buffers())?;
+
+        for ((_, param), moved) in self.named_parameters_mut().into_iter().zip(params) {
+            *param = moved;
+        }
+        for ((_, buffer), moved) in self.named_buffers_mut().into_iter().zip(buffers) {
+            *buffer = moved;
+        }
+        Ok(())
+    }
+
+    /// Rounds every parameter and buffer to `precision`, in place like
+    /// [`Layer::to_device`]. Values are still stored as f32, as everywhere in cetana.
+    fn to_dtype(&mut self, precision: Precision) {
+        for (_, param) in self.named_parameters_mut() {
+            *param = param.round_to(precision);
+        }
+        for (_, buffer) in self.named_buffers_mut() {
+            *buffer = buffer.round_to(precision);
+        }
+    }
+
+    /// A copy of every parameter and buffer, keyed by name.
+    fn state_dict(&self) -> StateDict {
+        self.named_parameters()
+            .into_iter()
+            .chain(self.named_buffers())
+            .map(|(name, param)| (name, param.clone()))
+            .collect()
+    }
+
+    /// Copies the tensors in `state` into the parameters and buffers of the same name.
+    /// Quantized entries are dequantized into them.
+    ///
+    /// With `strict`, any missing or unexpected key is an error; otherwise they are listed in
+    /// the returned report and the matching parameters are still loaded. A shape mismatch is
+    /// always an error. Nothing is changed unless the whole load succeeds.
+    fn load_state_dict(&mut self, state: &StateDict, strict: bool) -> crate::MlResult<LoadReport> {
+        let tensors: Vec<_> = self
+            .named_parameters()
+            .into_iter()
+            .chain(self.named_buffers())
+            .collect();
+        let wanted: Vec<_> = tensors
+            .iter()
+            .map(|(n, p)| (n.as_str(), p.shape()))
+            .collect();
+        let offered: Vec<_> = state
+            .iter()
+            .map(|(n, t)| (n, t.shape()))
+            .chain(state.iter_quantized().map(|(n, q)| (n, q.shape())))
+            .collect();
+        let report = match_keys(&wanted, &offered, strict)?;
+
+        let mut dequantized = Vec::new();
+        for (name, _) in &tensors {
+            if let Some(quantized) = state.get_quantized(name) {
+                dequantized.push((name.clone(), quantized.dequantize()?));
+            }
+        }
+        let mut load = |name: String, tensor: &mut crate::tensor::Tensor| {
+            if let Some(loaded) = state.get(&name) {
+                *tensor = loaded.clone();
+            } else if let Some(i) = dequantized.iter().position(|(n, _)| *n == name) {
+                *tensor = dequantized.swap_remove(i).1;
+            }
+        };
+        for (name, param) in self.named_parameters_mut() {
+            load(name, param);
+        }
+        for (name, buffer) in self.named_buffers_mut() {
+            load(name, buffer);
+        }
+        Ok(report)
+    }
 }
@@ -0,0 +1,166 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::tensor::Tensor;
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+// A tensor a layer owns, with an id that outlives replacing the tensor
+macro_rules! owned_tensor {
+    ($(#[$doc:meta])* $name:ident) => {
+        $(#[$doc])*
+        #[derive(Debug)]
+        pub struct $name {
+            tensor: Tensor,
+            id: u64,
+        }
+
+        impl $name {
+            pub fn new(tensor: Tensor) -> Self {
+                Self {
+                    tensor,
+                    id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+                }
+            }
+
+            /// An id no other parameter or buffer has, kept when the tensor is replaced.
+            pub fn id(&self) -> u64 {
+                self.id
+            }
+
+            pub fn into_inner(self) -> Tensor {
+                self.tensor
+            }
+        }
+
+        // A copy is a separate tensor, so it gets an id of its own
+        impl Clone for $name {
+            fn clone(&self) -> Self {
+                Self::new(self.tensor.clone())
+            }
+        }
+
+        impl From<Tensor> for $name {
+            fn from(tensor: Tensor) -> Self {
+                Self::new(tensor)
+            }
+        }
+
+        impl Deref for $name {
+            type Target = Tensor;
+
+            fn deref(&self) -> &Tensor {
+                &self.tensor
+            }
+        }
+
+        impl DerefMut for $name {
+            fn deref_mut(&mut self) -> &mut Tensor {
+                &mut self.tensor
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl serde::Serialize for $name {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serde::Serialize::serialize(&self.tensor, serializer)
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<'de> serde::Deserialize<'de> for $name {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                <Tensor as serde::Deserialize>::deserialize(deserializer).map(Self::new)
+            }
+        }
+    };
+}
+
+owned_tensor! {
+    /// A trainable tensor of a layer, listed by
+    /// [`Layer::named_parameters`](super::Layer::named_parameters).
+    ///
+    /// Assigning through it, as weight updates and [`Layer::to_device`](super::Layer::to_device)
+    /// do, replaces the tensor but keeps the parameter's [`id`](Parameter::id).
+    Parameter
+}
+
+owned_tensor! {
+    /// Non-trainable state of a layer that still belongs to the model, such as running
+    /// statistics, listed by [`Layer::named_buffers`](super::Layer::named_buffers). Buffers
+    /// move and are saved with the parameters but aren't updated by backpropagation.
+    Buffer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amp::Precision;
+    use crate::backend::DeviceType;
+    use crate::nn::Layer;
+    use crate::serialize::StateDict;
+    use crate::MlResult;
+
+    // Scales its input and keeps a running mean of it
+    struct Scale {
+        scale: Parameter,
+        running_mean: Buffer,
+    }
+
+    impl Layer for Scale {
+        fn forward(&self, input: &Tensor) -> MlResult<Tensor> {
+            input.mul(&self.scale)
+        }
+
+        fn backward(&mut self, _: &Tensor, grad: &Tensor, _: f32) -> MlResult<Tensor> {
+            grad.mul(&self.scale)
+        }
+
+        fn named_parameters(&self) -> Vec<(String, &Tensor)> {
+            vec![("scale".to_string(), &*self.scale)]
+        }
+
+        fn named_parameters_mut(&mut self) -> Vec<(String, &mut Tensor)> {
+            vec![("scale".to_string(), &mut *self.scale)]
+        }
+
+        fn named_buffers(&self) -> Vec<(String, &Tensor)> {
+            vec![("running_mean".to_string(), &*self.running_mean)]
+        }
+
+        fn named_buffers_mut(&mut self) -> Vec<(String, &mut Tensor)> {
+            vec![("running_mean".to_string(), &mut *self.running_mean)]
+        }
+    }
+
+    #[test]
+    fn test_parameters_and_buffers() -> MlResult<()> {
+        let mut layer = Scale {
+            scale: Tensor::from_vec(vec![1.0 / 3.0, 2.0], &[2])?.into(),
+            running_mean: Tensor::from_vec(vec![0.1, 0.2], &[2])?.into(),
+        };
+        let ids = (layer.scale.id(), layer.running_mean.id());
+        assert_ne!(ids.0, ids.1);
+        assert_ne!(layer.scale.clone().id(), ids.0);
+
+        // Moves and rounding replace the tensors but keep the ids
+        layer.to_device(DeviceType::Cpu)?;
+        layer.to_dtype(Precision::BF16);
+        assert_eq!((layer.scale.id(), layer.running_mean.id()), ids);
+        assert_eq!(layer.scale.device(), DeviceType::Cpu);
+        assert_eq!(layer.scale.data()[0], Precision::BF16.round(1.0 / 3.0));
+
+        // Buffers are saved and loaded with the parameters
+        let state = layer.state_dict();
+        assert_eq!(state.keys().count(), 2);
+        let mut loaded = StateDict::new();
+        loaded.insert("scale", Tensor::from_vec(vec![4.0, 5.0], &[2])?);
+        loaded.insert("running_mean", Tensor::from_vec(vec![6.0, 7.0], &[2])?);
+        layer.load_state_dict(&loaded, true)?;
+        assert_eq!(layer.running_mean.data(), &[6.0, 7.0]);
+        assert_eq!(layer.running_mean.id(), ids.1);
+        loaded.remove("running_mean");
+        assert!(layer.load_state_dict(&loaded, true).is_err());
+        Ok(())
+    }
+}
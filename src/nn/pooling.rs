@@ -1,13 +1,16 @@
+use crate::nn::random::with_global_rng;
 use crate::{nn::Layer, tensor::Tensor, MlResult};
 
 /// Represents different types of pooling operations
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PoolingType {
     Max,
     Average,
 }
 
 /// A pooling layer that performs either max or average pooling.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Pooling {
     kernel_size: usize,
     stride: usize,
@@ -160,6 +163,220 @@ impl Layer for Pooling {
     }
 }
 
+/// Pools each feature map down to a fixed `output_size`, whatever the input resolution, by
+/// splitting it into that many nearly equal regions. `AdaptivePooling::new((1, 1),
+/// PoolingType::Average)` is the global average pooling that ends most vision backbones.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AdaptivePooling {
+    output_size: (usize, usize),
+    pooling_type: PoolingType,
+}
+
+impl AdaptivePooling {
+    pub fn new(output_size: (usize, usize), pooling_type: PoolingType) -> Self {
+        Self {
+            output_size,
+            pooling_type,
+        }
+    }
+
+    // The row and column ranges each output pools over
+    fn regions(&self, input: &Tensor) -> MlResult<(Vec<Region>, Vec<Region>)> {
+        let (height, width) = spatial_size(input, "AdaptivePooling")?;
+        let (out_h, out_w) = self.output_size;
+        if out_h == 0 || out_w == 0 {
+            return Err("AdaptivePooling needs a nonzero output size".into());
+        }
+        Ok((
+            adaptive_regions(height, out_h),
+            adaptive_regions(width, out_w),
+        ))
+    }
+}
+
+impl Layer for AdaptivePooling {
+    fn forward(&self, input: &Tensor) -> MlResult<Tensor> {
+        let (rows, cols) = self.regions(input)?;
+        pool_regions(input, &rows, &cols, self.pooling_type)
+    }
+
+    fn backward(
+        &mut self,
+        input: &Tensor,
+        grad_output: &Tensor,
+        _learning_rate: f32,
+    ) -> MlResult<Tensor> {
+        let (rows, cols) = self.regions(input)?;
+        region_gradients(input, grad_output, &rows, &cols, self.pooling_type)
+    }
+}
+
+/// Fractional max pooling (Graham, 2014): shrinks each feature map to `output_size` by a
+/// factor that needn't be an integer, taking the maximum over disjoint regions of
+/// `floor(ratio)` or `ceil(ratio)` rows and columns in a pseudo-random order.
+///
+/// The order is drawn from the global generator when the layer is created and kept until
+/// [`FractionalMaxPooling::resample`], so the backward pass sees the regions the forward
+/// pass used. Resampling for each batch gives the random augmentation of the original.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FractionalMaxPooling {
+    output_size: (usize, usize),
+    // Where the pseudo-random sequences of region sizes start, in [0, 1)
+    offsets: (f32, f32),
+}
+
+impl FractionalMaxPooling {
+    pub fn new(output_size: (usize, usize)) -> Self {
+        let mut layer = Self {
+            output_size,
+            offsets: (0.0, 0.0),
+        };
+        layer.resample();
+        layer
+    }
+
+    /// Draws a new arrangement of pooling regions.
+    pub fn resample(&mut self) {
+        self.offsets = with_global_rng(|rng| (rng.next_f32(), rng.next_f32()));
+    }
+
+    fn regions(&self, input: &Tensor) -> MlResult<(Vec<Region>, Vec<Region>)> {
+        let (height, width) = spatial_size(input, "FractionalMaxPooling")?;
+        let (out_h, out_w) = self.output_size;
+        if out_h == 0 || out_w == 0 || out_h > height || out_w > width {
+            return Err(format!(
+                "FractionalMaxPooling can't shrink {}x{} to {}x{}",
+                height, width, out_h, out_w
+            )
+            .into());
+        }
+        Ok((
+            fractional_regions(height, out_h, self.offsets.0),
+            fractional_regions(width, out_w, self.offsets.1),
+        ))
+    }
+}
+
+impl Layer for FractionalMaxPooling {
+    fn forward(&self, input: &Tensor) -> MlResult<Tensor> {
+        let (rows, cols) = self.regions(input)?;
+        pool_regions(input, &rows, &cols, PoolingType::Max)
+    }
+
+    fn backward(
+        &mut self,
+        input: &Tensor,
+        grad_output: &Tensor,
+        _learning_rate: f32,
+    ) -> MlResult<Tensor> {
+        let (rows, cols) = self.regions(input)?;
+        region_gradients(input, grad_output, &rows, &cols, PoolingType::Max)
+    }
+}
+
+// A range of rows or columns, from the first to one past the last
+type Region = (usize, usize);
+
+fn spatial_size(input: &Tensor, layer: &str) -> MlResult<(usize, usize)> {
+    match input.shape() {
+        &[_, _, height, width] => Ok((height, width)),
+        _ => Err(format!(
+            "{} expects 4D input (batch_size, channels, height, width)",
+            layer
+        )
+        .into()),
+    }
+}
+
+// `output` regions covering `input` positions, overlapping by at most one when it doesn't
+// divide evenly
+fn adaptive_regions(input: usize, output: usize) -> Vec<Region> {
+    (0..output)
+        .map(|i| (i * input / output, ((i + 1) * input).div_ceil(output)))
+        .collect()
+}
+
+// `output` disjoint regions covering `input` positions, whose sizes follow the sequence
+// `floor(ratio * (i + offset))` differences
+fn fractional_regions(input: usize, output: usize, offset: f32) -> Vec<Region> {
+    let ratio = input as f32 / output as f32;
+    let start = (ratio * offset).floor() as usize;
+    let boundary = |i: usize| match i {
+        i if i == output => input,
+        i => ((ratio * (i as f32 + offset)).floor() as usize - start).min(input),
+    };
+    (0..output)
+        .map(|i| (boundary(i), boundary(i + 1)))
+        .collect()
+}
+
+// Pools each feature map of the NCHW `input` over every pair of a row and a column region
+fn pool_regions(
+    input: &Tensor,
+    rows: &[Region],
+    cols: &[Region],
+    pooling_type: PoolingType,
+) -> MlResult<Tensor> {
+    let shape = input.shape();
+    let width = shape[3];
+    let mut output = Vec::with_capacity(shape[0] * shape[1] * rows.len() * cols.len());
+    for map in input.data().chunks(shape[2] * width) {
+        for &(top, bottom) in rows {
+            for &(left, right) in cols {
+                let values = (top..bottom).flat_map(|y| &map[y * width + left..y * width + right]);
+                output.push(match pooling_type {
+                    PoolingType::Max => values.copied().fold(f32::NEG_INFINITY, f32::max),
+                    PoolingType::Average => {
+                        values.sum::<f32>() / ((bottom - top) * (right - left)) as f32
+                    }
+                });
+            }
+        }
+    }
+    Tensor::from_vec(output, &[shape[0], shape[1], rows.len(), cols.len()])
+}
+
+// The gradient of `pool_regions` with respect to `input`: each output's gradient goes to the
+// maximum of its region, or is shared evenly across it
+fn region_gradients(
+    input: &Tensor,
+    grad_output: &Tensor,
+    rows: &[Region],
+    cols: &[Region],
+    pooling_type: PoolingType,
+) -> MlResult<Tensor> {
+    let shape = input.shape();
+    let (plane, width) = (shape[2] * shape[3], shape[3]);
+    let mut grad_input = vec![0.0; input.data().len()];
+    let grads = grad_output.data().chunks(rows.len() * cols.len());
+    for ((map, grad), grad_map) in input
+        .data()
+        .chunks(plane)
+        .zip(grads)
+        .zip(grad_input.chunks_mut(plane))
+    {
+        let regions = rows
+            .iter()
+            .flat_map(|&row| cols.iter().map(move |&col| (row, col)));
+        for (((top, bottom), (left, right)), &g) in regions.zip(grad) {
+            let positions = (top..bottom).flat_map(|y| (left..right).map(move |x| y * width + x));
+            match pooling_type {
+                PoolingType::Max => {
+                    let max = positions
+                        .max_by(|&a, &b| map[a].total_cmp(&map[b]).then(b.cmp(&a)))
+                        .unwrap_or(0);
+                    grad_map[max] += g;
+                }
+                PoolingType::Average => {
+                    let share = g / ((bottom - top) * (right - left)) as f32;
+                    positions.for_each(|i| grad_map[i] += share);
+                }
+            }
+        }
+    }
+    Tensor::from_vec(grad_input, shape)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -212,4 +429,65 @@ mod tests {
         assert_eq!(grad_input.shape(), input.shape());
         Ok(())
     }
+
+    #[test]
+    fn test_adaptive_pooling() -> MlResult<()> {
+        let input = Tensor::from_vec((1..=16).map(|x| x as f32).collect(), &[1, 1, 4, 4])?;
+
+        // Global average pooling
+        let mut global = AdaptivePooling::new((1, 1), PoolingType::Average);
+        assert_eq!(global.forward(&input)?.data(), &[8.5]);
+
+        // The same output size from any resolution, matching fixed pooling where it divides
+        let mut pool = AdaptivePooling::new((2, 2), PoolingType::Max);
+        assert_eq!(pool.forward(&input)?.data(), &[6.0, 8.0, 14.0, 16.0]);
+        let larger = Tensor::from_vec((0..35).map(|x| x as f32).collect(), &[1, 1, 5, 7])?;
+        let output = pool.forward(&larger)?;
+        assert_eq!(output.shape(), &[1, 1, 2, 2]);
+        assert_eq!(output.data(), &[17.0, 20.0, 31.0, 34.0]);
+
+        let grad = Tensor::from_vec(vec![1.0; 4], &[1, 1, 2, 2])?;
+        let grad_input = pool.backward(&input, &grad, 0.1)?;
+        assert_eq!(grad_input.data().iter().sum::<f32>(), 4.0);
+        assert_eq!(grad_input.data()[5], 1.0);
+        let total = Tensor::from_vec(vec![16.0], &[1, 1, 1, 1])?;
+        let grad_input = global.backward(&input, &total, 0.1)?;
+        assert!(grad_input.data().iter().all(|&g| g == 1.0));
+
+        assert!(AdaptivePooling::new((0, 1), PoolingType::Max)
+            .forward(&input)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_fractional_max_pooling() -> MlResult<()> {
+        crate::seed_all(3);
+        let input = Tensor::from_vec((0..100).map(|x| x as f32).collect(), &[1, 1, 10, 10])?;
+        let mut pool = FractionalMaxPooling::new((7, 7));
+
+        // Regions of one or two rows and columns that tile the input
+        for offset in [0.0, 0.3, 0.99] {
+            let regions = fractional_regions(10, 7, offset);
+            assert_eq!((regions[0].0, regions[6].1), (0, 10));
+            assert!(regions.windows(2).all(|w| w[0].1 == w[1].0));
+            assert!(regions.iter().all(|&(a, b)| (1..=2).contains(&(b - a))));
+        }
+
+        let output = pool.forward(&input)?;
+        assert_eq!(output.shape(), &[1, 1, 7, 7]);
+        assert_eq!(output.data()[48], 99.0);
+
+        // Backward sends the gradient to the maxima the forward pass picked
+        let grad = Tensor::from_vec(vec![1.0; 49], &[1, 1, 7, 7])?;
+        let grad_input = pool.backward(&input, &grad, 0.1)?;
+        for (i, &value) in output.data().iter().enumerate() {
+            assert_eq!(grad_input.data()[value as usize], 1.0, "output {}", i);
+        }
+
+        pool.resample();
+        assert_eq!(pool.forward(&input)?.shape(), &[1, 1, 7, 7]);
+        assert!(FractionalMaxPooling::new((11, 5)).forward(&input).is_err());
+        Ok(())
+    }
 }
@@ -1,3 +1,65 @@
+use std::cell::RefCell;
+
+thread_local! {
+    // The generator seeds not given explicitly are drawn from, started from the system time
+    // unless `seed_all` or `set_rng_state` set it first
+    static GLOBAL_RNG: RefCell<Option<SimpleRng>> = const { RefCell::new(None) };
+}
+
+/// Seeds every source of randomness in cetana at once: layer initialization, and the
+/// default seeds of [`DataLoader`](crate::data::DataLoader) shuffling,
+/// [`StreamLoader`](crate::data::StreamLoader) buffers and random transforms.
+///
+/// Each of those draws its seed from one generator when it is created, so a program that
+/// builds the same things in the same order after `seed_all` gets the same results. Seeds
+/// set explicitly, like [`DataLoader::seed`](crate::data::DataLoader::seed), still take
+/// precedence.
+///
+/// The generator belongs to the calling thread, so seed the thread that builds the models
+/// and loaders; other threads building things at the same time can't disturb its sequence.
+pub fn seed_all(seed: u64) {
+    set_rng_state(seed);
+}
+
+/// The calling thread's generator's position, to save with a checkpoint and hand back to
+/// [`set_rng_state`] on resume.
+pub fn rng_state() -> u64 {
+    with_global_rng(|rng| rng.state())
+}
+
+/// Moves the calling thread's generator to a position [`rng_state`] returned.
+pub fn set_rng_state(state: u64) {
+    GLOBAL_RNG.with(|global| *global.borrow_mut() = Some(SimpleRng::new(state)));
+}
+
+/// A seed for a new generator, drawn from the calling thread's one.
+pub(crate) fn next_seed() -> u64 {
+    with_global_rng(|rng| rng.next_u64())
+}
+
+pub(crate) fn with_global_rng<T>(f: impl FnOnce(&mut SimpleRng) -> T) -> T {
+    GLOBAL_RNG.with(|global| {
+        let mut global = global.borrow_mut();
+        let rng = global.get_or_insert_with(|| SimpleRng::new(unseeded()));
+        f(rng)
+    })
+}
+
+// The seed of a generator nobody seeded
+#[cfg(not(target_arch = "wasm32"))]
+fn unseeded() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+// wasm32-unknown-unknown has no clock in std, so the browser supplies the entropy
+#[cfg(target_arch = "wasm32")]
+fn unseeded() -> u64 {
+    (js_sys::Math::random() * (1u64 << 53) as f64) as u64
+}
+
 pub struct SimpleRng {
     state: u64,
 }
@@ -7,6 +69,12 @@ impl SimpleRng {
         Self { state: seed }
     }
 
+    /// The generator's position in its sequence. `SimpleRng::new(rng.state())` continues
+    /// exactly where `rng` is.
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
     // Xoshiro256** algorithm (simplified version)
     fn next_u64(&mut self) -> u64 {
         let result = self.state.rotate_left(5).wrapping_mul(5);
@@ -33,6 +101,19 @@ impl SimpleRng {
         let range = max - min;
         min + range * self.next_f32()
     }
+
+    /// A uniformly chosen index below `bound`, which must not be zero.
+    pub fn gen_index(&mut self, bound: usize) -> usize {
+        debug_assert!(bound > 0);
+        ((self.next_u64() as u128 * bound as u128) >> 64) as usize
+    }
+
+    /// Puts `items` in a uniformly random order.
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.gen_index(i + 1));
+        }
+    }
 }
 
 #[cfg(test)]
@@ -61,4 +142,32 @@ mod tests {
         let mean = sum / n as f32;
         assert!((mean - 0.5).abs() < 0.1);
     }
+
+    #[test]
+    fn test_seed_all() -> crate::MlResult<()> {
+        use crate::data::{DataLoader, HostTensor, TensorDataset};
+        use crate::nn::Linear;
+
+        let run = || -> crate::MlResult<(Vec<f32>, Vec<f32>)> {
+            let model = Linear::new(3, 2, true)?;
+            let dataset = TensorDataset::from_host(
+                HostTensor::new((0..10).map(|i| i as f32).collect(), &[10, 1])?,
+                HostTensor::new(vec![0.0; 10], &[10])?,
+            )?;
+            let loader = DataLoader::new(dataset, 10).shuffle(true);
+            let (inputs, _) = loader.iter().next().unwrap()?;
+            Ok((model.weight().data().to_vec(), inputs.data().to_vec()))
+        };
+
+        seed_all(11);
+        let first = run()?;
+        let state = rng_state();
+        let after = run()?;
+        seed_all(11);
+        assert_eq!(run()?, first);
+        set_rng_state(state);
+        assert_eq!(run()?, after);
+        assert_ne!(first.1, after.1);
+        Ok(())
+    }
 }
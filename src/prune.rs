@@ -0,0 +1,381 @@
+//! Pruning, for compressing models to run on small devices.
+//!
+//! Unstructured pruning zeroes the individual weights of smallest magnitude: [`Pruned`]
+//! wraps a layer with a [`magnitude_mask`] for each of its weights and keeps the pruned
+//! weights at zero while it trains, so the model can recover from the pruning. This leaves
+//! the shapes as they were, so it pays off with sparse storage or kernels.
+//!
+//! Structured pruning removes whole output channels of a [`Linear`](crate::nn::Linear) or
+//! [`Conv2d`](crate::nn::Conv2d), those whose weights have the smallest L1 norm, shrinking
+//! the weight tensors themselves. [`prune_channels`] also drops the matching inputs of the
+//! layer that follows, so the pair still fits together.
+//!
+//! ```ignore
+//! let kept = prune_channels(&mut conv, Some(&mut linear), 0.5)?;
+//! let mut model = Pruned::magnitude(model, 0.8)?;
+//! trainer.fit(&mut model, &train)?;
+//! println!("{}", sparsity(&model));
+//! ```
+
+use std::fmt::{Display, Formatter};
+
+use crate::nn::Layer;
+use crate::tensor::{Tensor, TensorError};
+use crate::MlResult;
+
+/// A layer whose output channels can be removed, and whose inputs can be removed to match
+/// the layer before it.
+pub trait Prune: Layer {
+    /// The L1 norm of the weights of each output channel.
+    fn output_norms(&self) -> Vec<f32>;
+
+    /// The number of inputs the layer takes: input features or channels.
+    fn num_inputs(&self) -> usize;
+
+    /// Keeps only the output channels at `keep`, in that order.
+    fn keep_outputs(&mut self, keep: &[usize]) -> MlResult<()>;
+
+    /// Keeps only the inputs at `keep`, in that order.
+    fn keep_inputs(&mut self, keep: &[usize]) -> MlResult<()>;
+}
+
+/// Removes the `amount` fraction of `layer`'s output channels with the smallest weight
+/// norms, always keeping at least one, and the inputs of `next` they fed. Returns the
+/// indices of the channels kept.
+///
+/// If `next` takes several inputs per channel, as a [`Linear`](crate::nn::Linear) after a
+/// flattened [`Conv2d`](crate::nn::Conv2d) does, each channel's inputs are taken to be
+/// contiguous, as flattening `[batch, channels, height, width]` leaves them.
+pub fn prune_channels(
+    layer: &mut dyn Prune,
+    next: Option<&mut dyn Prune>,
+    amount: f32,
+) -> MlResult<Vec<usize>> {
+    check_amount(amount)?;
+    let norms = layer.output_norms();
+    let channels = norms.len();
+    let remove = ((channels as f32 * amount) as usize).min(channels.saturating_sub(1));
+
+    let mut order: Vec<usize> = (0..channels).collect();
+    order.sort_by(|&a, &b| norms[b].total_cmp(&norms[a]));
+    let mut keep = order[..channels - remove].to_vec();
+    keep.sort_unstable();
+
+    let inputs = match &next {
+        Some(next) => {
+            let inputs = next.num_inputs();
+            if channels == 0 || !inputs.is_multiple_of(channels) {
+                return Err(format!(
+                    "The next layer's {} inputs don't divide into {} channels",
+                    inputs, channels
+                )
+                .into());
+            }
+            let block = inputs / channels;
+            keep.iter()
+                .flat_map(|&channel| channel * block..(channel + 1) * block)
+                .collect()
+        }
+        None => Vec::new(),
+    };
+
+    layer.keep_outputs(&keep)?;
+    if let Some(next) = next {
+        next.keep_inputs(&inputs)?;
+    }
+    Ok(keep)
+}
+
+/// A mask of `weight`'s shape that is 0 at the `amount` fraction of its elements with the
+/// smallest magnitude and 1 elsewhere.
+pub fn magnitude_mask(weight: &Tensor, amount: f32) -> MlResult<Tensor> {
+    check_amount(amount)?;
+    let data = weight.data();
+    let mut order: Vec<usize> = (0..data.len()).collect();
+    order.sort_by(|&a, &b| data[a].abs().total_cmp(&data[b].abs()));
+
+    let mut mask = vec![1.0; data.len()];
+    let remove = (data.len() as f32 * amount) as usize;
+    for &i in &order[..remove] {
+        mask[i] = 0.0;
+    }
+    Tensor::from_vec(mask, weight.shape())
+}
+
+/// A layer with some of its weights pruned to zero, which stay zero through training.
+///
+/// Parameters are masked if their name ends in `weight`; biases are left whole. The
+/// wrapper has the parameters of the layer it wraps, so its state dict loads into an
+/// unwrapped layer.
+pub struct Pruned<L: Layer> {
+    layer: L,
+    masks: Vec<(String, Tensor)>,
+}
+
+impl<L: Layer> Pruned<L> {
+    /// Prunes the `amount` fraction of smallest-magnitude elements from each of `layer`'s
+    /// weights.
+    pub fn magnitude(mut layer: L, amount: f32) -> MlResult<Self> {
+        let masks = layer
+            .named_parameters()
+            .into_iter()
+            .filter(|(name, _)| name.ends_with("weight"))
+            .map(|(name, weight)| Ok((name, magnitude_mask(weight, amount)?)))
+            .collect::<MlResult<Vec<_>>>()?;
+        apply_masks(&mut layer, &masks)?;
+        Ok(Self { layer, masks })
+    }
+
+    /// The mask of each pruned parameter, by name.
+    pub fn masks(&self) -> &[(String, Tensor)] {
+        &self.masks
+    }
+
+    pub fn layer(&self) -> &L {
+        &self.layer
+    }
+
+    /// The wrapped layer, with its pruned weights at zero.
+    pub fn into_inner(self) -> L {
+        self.layer
+    }
+}
+
+impl<L: Layer> Layer for Pruned<L> {
+    fn forward(&self, input: &Tensor) -> MlResult<Tensor> {
+        self.layer.forward(input)
+    }
+
+    fn backward(
+        &mut self,
+        input: &Tensor,
+        grad_output: &Tensor,
+        learning_rate: f32,
+    ) -> MlResult<Tensor> {
+        let grad_input = self.layer.backward(input, grad_output, learning_rate)?;
+        apply_masks(&mut self.layer, &self.masks)?;
+        Ok(grad_input)
+    }
+
+    fn named_parameters(&self) -> Vec<(String, &Tensor)> {
+        self.layer.named_parameters()
+    }
+
+    fn named_parameters_mut(&mut self) -> Vec<(String, &mut Tensor)> {
+        self.layer.named_parameters_mut()
+    }
+
+    fn named_buffers(&self) -> Vec<(String, &Tensor)> {
+        self.layer.named_buffers()
+    }
+
+    fn named_buffers_mut(&mut self) -> Vec<(String, &mut Tensor)> {
+        self.layer.named_buffers_mut()
+    }
+}
+
+fn apply_masks(layer: &mut dyn Layer, masks: &[(String, Tensor)]) -> MlResult<()> {
+    for (name, param) in layer.named_parameters_mut() {
+        if let Some((_, mask)) = masks.iter().find(|(masked, _)| *masked == name) {
+            *param = param.mul(mask)?;
+        }
+    }
+    Ok(())
+}
+
+/// How many of one parameter's elements are zero.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParameterSparsity {
+    pub name: String,
+    pub zeros: usize,
+    pub total: usize,
+}
+
+/// How many of a layer's parameters are zero, parameter by parameter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SparsityReport {
+    pub parameters: Vec<ParameterSparsity>,
+}
+
+impl SparsityReport {
+    pub fn zeros(&self) -> usize {
+        self.parameters.iter().map(|param| param.zeros).sum()
+    }
+
+    pub fn total(&self) -> usize {
+        self.parameters.iter().map(|param| param.total).sum()
+    }
+
+    /// The fraction of all parameter elements that are zero.
+    pub fn sparsity(&self) -> f32 {
+        self.zeros() as f32 / self.total().max(1) as f32
+    }
+}
+
+impl Display for SparsityReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for param in &self.parameters {
+            writeln!(
+                f,
+                "{}: {}/{} zero ({:.2}%)",
+                param.name,
+                param.zeros,
+                param.total,
+                100.0 * param.zeros as f32 / param.total.max(1) as f32
+            )?;
+        }
+        write!(
+            f,
+            "total: {}/{} zero ({:.2}%)",
+            self.zeros(),
+            self.total(),
+            100.0 * self.sparsity()
+        )
+    }
+}
+
+/// Counts the zeros in each of `layer`'s parameters.
+pub fn sparsity(layer: &dyn Layer) -> SparsityReport {
+    let parameters = layer
+        .named_parameters()
+        .into_iter()
+        .map(|(name, param)| ParameterSparsity {
+            name,
+            zeros: param.data().iter().filter(|&&x| x == 0.0).count(),
+            total: param.data().len(),
+        })
+        .collect();
+    SparsityReport { parameters }
+}
+
+fn check_amount(amount: f32) -> MlResult<()> {
+    if !(0.0..=1.0).contains(&amount) {
+        return Err(format!("Pruning amount {} is not between 0 and 1", amount).into());
+    }
+    Ok(())
+}
+
+// The L1 norm of each slice of `weight` along its first axis
+pub(crate) fn channel_norms(weight: &Tensor) -> Vec<f32> {
+    let channels = weight.shape()[0];
+    let size = weight.data().len() / channels.max(1);
+    weight
+        .data()
+        .chunks(size.max(1))
+        .take(channels)
+        .map(|channel| channel.iter().map(|x| x.abs()).sum())
+        .collect()
+}
+
+// The slices of `tensor` at `keep` along `axis`, in that order
+pub(crate) fn select(tensor: &Tensor, axis: usize, keep: &[usize]) -> MlResult<Tensor> {
+    let shape = tensor.shape();
+    if axis >= shape.len() {
+        return Err(TensorError::InvalidAxis {
+            axis,
+            shape: shape.to_vec(),
+        }
+        .into());
+    }
+    if keep.is_empty() || keep.iter().any(|&i| i >= shape[axis]) {
+        return Err(TensorError::InvalidOperation {
+            op: "prune",
+            reason: format!(
+                "Cannot keep {:?} of {} channels along axis {}",
+                keep, shape[axis], axis
+            ),
+        }
+        .into());
+    }
+
+    let outer: usize = shape[..axis].iter().product();
+    let inner: usize = shape[axis + 1..].iter().product();
+    let mut data = Vec::with_capacity(outer * keep.len() * inner);
+    for block in tensor.data().chunks(shape[axis] * inner) {
+        for &i in keep {
+            data.extend_from_slice(&block[i * inner..(i + 1) * inner]);
+        }
+    }
+    let mut shape = shape.to_vec();
+    shape[axis] = keep.len();
+    Tensor::from_vec(data, &shape)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::{Conv2d, Linear, PaddingMode, ReLU};
+
+    fn set(layer: &mut dyn Layer, name: &str, data: Vec<f32>) -> MlResult<()> {
+        for (param_name, param) in layer.named_parameters_mut() {
+            if param_name == name {
+                *param = Tensor::from_vec(data.clone(), param.shape())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn wave(shape: &[usize]) -> MlResult<Tensor> {
+        let len = shape.iter().product();
+        Tensor::from_vec((0..len).map(|i| (i as f32 * 0.7).sin()).collect(), shape)
+    }
+
+    #[test]
+    fn test_pruning() -> MlResult<()> {
+        let weight = Tensor::from_vec(vec![0.5, -0.1, 2.0, -3.0, 0.2, 1.0], &[2, 3])?;
+        let mask = magnitude_mask(&weight, 0.5)?;
+        assert_eq!(mask.data(), &[0.0, 0.0, 1.0, 1.0, 0.0, 1.0]);
+        assert!(magnitude_mask(&weight, 1.5).is_err());
+
+        // Pruned weights stay zero through training
+        let mut pruned = Pruned::magnitude(Linear::new(4, 3, true)?, 0.75)?;
+        let input = wave(&[5, 4])?;
+        let grad = wave(&[5, 3])?;
+        pruned.backward(&input, &grad, 0.1)?;
+        let report = sparsity(&pruned);
+        assert_eq!(report.parameters[0].zeros, 9);
+        assert_eq!((report.zeros(), report.total()), (9, 15));
+        assert!(report.to_string().ends_with("total: 9/15 zero (60.00%)"));
+
+        // Removing a dead hidden unit leaves the outputs as they were
+        let mut first = Linear::new(4, 3, true)?;
+        let mut second = Linear::new(3, 2, true)?;
+        set(
+            &mut first,
+            "weight",
+            vec![1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.5, 0.5, 0.5, 0.5],
+        )?;
+        set(&mut first, "bias", vec![0.1, 0.0, 0.2])?;
+        let hidden = |first: &Linear| ReLU.forward(&first.forward(&input)?);
+        let expected = second.forward(&hidden(&first)?)?;
+        let kept = prune_channels(&mut first, Some(&mut second), 0.34)?;
+        assert_eq!(kept, [0, 2]);
+        assert_eq!(second.num_inputs(), 2);
+        let actual = second.forward(&hidden(&first)?)?;
+        for (e, a) in expected.data().iter().zip(actual.data()) {
+            assert!((e - a).abs() < 1e-5);
+        }
+
+        // A convolution feeding a flattened linear layer drops each channel's block
+        let mut conv = Conv2d::new(1, 3, 1, 1, PaddingMode::Valid, false)?;
+        set(&mut conv, "weight", vec![0.1, -2.0, 1.0])?;
+        let mut linear = Linear::new(3 * 4, 2, false)?;
+        let features: Vec<f32> = (0..24).map(|i| i as f32).collect();
+        set(&mut linear, "weight", features)?;
+        assert_eq!(prune_channels(&mut conv, Some(&mut linear), 0.5)?, [1, 2]);
+        assert_eq!(conv.weights().data(), &[-2.0, 1.0]);
+        let params = linear.named_parameters();
+        let weight = params[0].1;
+        assert_eq!(weight.shape(), &[2, 8]);
+        assert_eq!(
+            &weight.data()[..8],
+            &[4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 11.0]
+        );
+        let output = conv.forward(&wave(&[1, 1, 2, 2])?)?;
+        assert_eq!(output.shape(), &[1, 2, 2, 2]);
+
+        let mut odd = Linear::new(5, 2, false)?;
+        assert!(prune_channels(&mut conv, Some(&mut odd), 0.5).is_err());
+        Ok(())
+    }
+}
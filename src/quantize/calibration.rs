@@ -0,0 +1,227 @@
+use std::rc::Rc;
+
+use super::{Granularity, MinMaxObserver, Quantize};
+use crate::nn::Layer;
+use crate::tensor::Tensor;
+use crate::MlResult;
+
+enum Stage {
+    Quantize(Box<dyn Quantize>),
+    // Shared with the converted model, since layers can't be cloned through `dyn Layer`
+    Float(Rc<dyn Layer>),
+}
+
+impl Stage {
+    fn layer(&self) -> &dyn Layer {
+        match self {
+            Stage::Quantize(layer) => layer.as_ref(),
+            Stage::Float(layer) => layer.as_ref(),
+        }
+    }
+}
+
+/// A chain of layers being calibrated for int8 inference; see the [module docs](crate::quantize).
+///
+/// Its [`Layer::forward`] runs the original f32 layers without recording anything, so it
+/// serves as the reference when comparing against the converted model.
+pub struct Calibration {
+    stages: Vec<Stage>,
+    // The range of the input of each stage, then of the last stage's output
+    observers: Vec<MinMaxObserver>,
+    granularity: Granularity,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Calibration {
+    /// A calibration whose layers get per-channel weights.
+    pub fn new() -> Self {
+        Self::with_granularity(Granularity::PerChannel)
+    }
+
+    /// A calibration whose layers get weights quantized at `granularity`.
+    pub fn with_granularity(granularity: Granularity) -> Self {
+        Self {
+            stages: Vec::new(),
+            observers: vec![MinMaxObserver::new()],
+            granularity,
+        }
+    }
+
+    /// Appends a layer that will run in int8.
+    pub fn quantize(mut self, layer: impl Quantize + 'static) -> Self {
+        self.stages.push(Stage::Quantize(Box::new(layer)));
+        self.observers.push(MinMaxObserver::new());
+        self
+    }
+
+    /// Appends a layer that stays in f32, such as an activation.
+    pub fn float(mut self, layer: impl Layer + 'static) -> Self {
+        self.stages.push(Stage::Float(Rc::new(layer)));
+        self.observers.push(MinMaxObserver::new());
+        self
+    }
+
+    /// Runs `input` through the layers in f32, widening the recorded range of every
+    /// activation, and returns the output.
+    pub fn observe(&mut self, input: &Tensor) -> MlResult<Tensor> {
+        self.observers[0].observe(input);
+        let mut x = input.clone();
+        for (stage, observer) in self.stages.iter().zip(&mut self.observers[1..]) {
+            x = stage.layer().forward(&x)?;
+            observer.observe(&x);
+        }
+        Ok(x)
+    }
+
+    /// The recorded ranges: the model input's, then each layer output's.
+    pub fn observers(&self) -> &[MinMaxObserver] {
+        &self.observers
+    }
+
+    /// Builds the int8 model from the recorded ranges. Every quantized layer needs its
+    /// input and output to have been observed.
+    pub fn convert(&self) -> MlResult<QuantizedModel> {
+        let layers = self
+            .stages
+            .iter()
+            .enumerate()
+            .map(|(i, stage)| match stage {
+                Stage::Quantize(layer) => {
+                    let input = self.observers[i].params()?;
+                    let output = self.observers[i + 1].params()?;
+                    Ok(Rc::from(layer.quantize_static(
+                        input,
+                        output,
+                        self.granularity,
+                    )?))
+                }
+                Stage::Float(layer) => Ok(Rc::clone(layer)),
+            })
+            .collect::<MlResult<_>>()?;
+        Ok(QuantizedModel { layers })
+    }
+
+    /// Builds a model whose int8 layers are quantized dynamically, without the recorded
+    /// ranges, so it can be called before any calibration.
+    pub fn convert_dynamic(&self) -> MlResult<QuantizedModel> {
+        let layers = self
+            .stages
+            .iter()
+            .map(|stage| match stage {
+                Stage::Quantize(layer) => Ok(Rc::from(layer.quantize_dynamic(self.granularity)?)),
+                Stage::Float(layer) => Ok(Rc::clone(layer)),
+            })
+            .collect::<MlResult<_>>()?;
+        Ok(QuantizedModel { layers })
+    }
+}
+
+impl Layer for Calibration {
+    fn forward(&self, input: &Tensor) -> MlResult<Tensor> {
+        let mut x = input.clone();
+        for stage in &self.stages {
+            x = stage.layer().forward(&x)?;
+        }
+        Ok(x)
+    }
+
+    fn backward(&mut self, _: &Tensor, _: &Tensor, _: f32) -> MlResult<Tensor> {
+        Err("Calibration runs inference only; train the layers before adding them".into())
+    }
+}
+
+/// The int8 model produced by [`Calibration::convert`] or [`QatModel::convert`]: int8
+/// layers interleaved with the f32 ones, which are shared with the calibration.
+///
+/// [`QatModel::convert`]: super::QatModel::convert
+pub struct QuantizedModel {
+    layers: Vec<Rc<dyn Layer>>,
+}
+
+impl QuantizedModel {
+    pub(super) fn from_layers(layers: Vec<Rc<dyn Layer>>) -> Self {
+        Self { layers }
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+}
+
+impl Layer for QuantizedModel {
+    fn forward(&self, input: &Tensor) -> MlResult<Tensor> {
+        let mut x = input.clone();
+        for layer in &self.layers {
+            x = layer.forward(&x)?;
+        }
+        Ok(x)
+    }
+
+    fn backward(&mut self, _: &Tensor, _: &Tensor, _: f32) -> MlResult<Tensor> {
+        Err("Quantized layers run inference only".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::{Conv2d, Linear, PaddingMode, ReLU};
+    use crate::quantize::compare;
+
+    struct Flatten;
+
+    impl Layer for Flatten {
+        fn forward(&self, input: &Tensor) -> MlResult<Tensor> {
+            input.reshape(&[input.shape()[0], input.data().len() / input.shape()[0]])
+        }
+
+        fn backward(&mut self, input: &Tensor, grad: &Tensor, _: f32) -> MlResult<Tensor> {
+            grad.reshape(input.shape())
+        }
+    }
+
+    #[test]
+    fn test_static_quantization() -> MlResult<()> {
+        crate::seed_all(7);
+        let mut calibration = Calibration::new()
+            .quantize(Conv2d::new(1, 4, 3, 1, PaddingMode::Same, true)?)
+            .float(ReLU)
+            .float(Flatten)
+            .quantize(Linear::new(4 * 4 * 4, 3, true)?);
+        assert!(calibration.convert().is_err());
+
+        let inputs: Vec<Tensor> = (0..4)
+            .map(|i| {
+                let data = (0..2 * 16).map(|j| ((i * 7 + j) % 11) as f32 / 5.0 - 1.0);
+                Tensor::from_vec(data.collect(), &[2, 1, 4, 4])
+            })
+            .collect::<MlResult<_>>()?;
+        for input in &inputs {
+            calibration.observe(input)?;
+        }
+        let (min, max) = calibration.observers()[0].range().unwrap();
+        assert_eq!((min, max), (-1.0, 1.0));
+
+        let quantized = calibration.convert()?;
+        assert_eq!(quantized.len(), 4);
+        let report = compare(&calibration, &quantized, &inputs)?;
+        assert_eq!(report.samples, 8);
+        assert!(report.sqnr_db > 25.0, "{}", report);
+        assert_eq!(report.top1_agreement, 1.0);
+
+        // Dynamic quantization needs no calibration and keeps outputs in f32
+        let dynamic = calibration.convert_dynamic()?;
+        let report = compare(&calibration, &dynamic, &inputs)?;
+        assert!(report.sqnr_db > 25.0, "{}", report);
+        Ok(())
+    }
+}
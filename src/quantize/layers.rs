@@ -0,0 +1,493 @@
+use super::ActivationParams;
+use crate::backend::{im2col, matmul_i8, requantize, Conv2dShape};
+use crate::nn::{Layer, PaddingMode};
+use crate::tensor::{QuantDtype, QuantParams, QuantizedTensor, Tensor};
+use crate::MlResult;
+
+/// How finely weights are quantized.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Granularity {
+    /// One scale for the whole weight.
+    PerTensor,
+    /// A scale for each output channel, so filters of small weights keep their precision
+    /// next to filters of large ones. Conv nets, whose filters vary widely, need this.
+    #[default]
+    PerChannel,
+}
+
+/// Quantizes a weight to int8 the way the int8 layers take it: symmetrically, with zero
+/// points of 0, and per output channel along axis 0 unless `granularity` says otherwise.
+pub fn quantize_weight(weight: &Tensor, granularity: Granularity) -> MlResult<QuantizedTensor> {
+    let data = weight.data();
+    let rows = weight.shape().first().copied().unwrap_or(1);
+    let cols = data.len() / rows.max(1);
+    let scales: Vec<f32> = match granularity {
+        Granularity::PerTensor => vec![symmetric_scale(data); rows],
+        Granularity::PerChannel => (0..rows)
+            .map(|row| symmetric_scale(&data[row * cols..(row + 1) * cols]))
+            .collect(),
+    };
+    let values = data
+        .iter()
+        .enumerate()
+        .map(|(i, &x)| {
+            let params = ActivationParams {
+                scale: scales[i / cols],
+                zero_point: 0,
+            };
+            params.quantize(x)
+        })
+        .collect();
+
+    let params = match granularity {
+        Granularity::PerTensor => QuantParams::PerTensor {
+            scale: scales.first().copied().unwrap_or(1.0),
+            zero_point: 0,
+        },
+        Granularity::PerChannel => QuantParams::PerChannel {
+            axis: 0,
+            zero_points: vec![0; scales.len()],
+            scales,
+        },
+    };
+    QuantizedTensor::from_parts(QuantDtype::Int8, weight.shape(), values, params)
+}
+
+fn symmetric_scale(values: &[f32]) -> f32 {
+    let largest = values.iter().fold(0.0f32, |m, x| m.max(x.abs()));
+    if largest > 0.0 {
+        largest / 127.0
+    } else {
+        1.0
+    }
+}
+
+// Rounds a weight to the values the int8 layers will hold, keeping it in f32
+pub(super) fn fake_quantize_weight(weight: &Tensor, granularity: Granularity) -> MlResult<Tensor> {
+    quantize_weight(weight, granularity)?.dequantize()
+}
+
+// A `[out, k]` weight quantized symmetrically, so its zero points are 0, with what the int8
+// GEMM needs per output row: the dequantizing scale and the sum of the row, to correct for
+// the input's zero point
+#[derive(Debug, Clone)]
+struct Int8Weight {
+    weight: QuantizedTensor,
+    rows: usize,
+    cols: usize,
+    scales: Vec<f32>,
+    row_sums: Vec<i32>,
+    bias: Option<Vec<f32>>,
+}
+
+impl Int8Weight {
+    fn new(weight: QuantizedTensor, bias: Option<&Tensor>) -> MlResult<Self> {
+        let rows = weight.shape()[0];
+        let cols = weight.values().len() / rows.max(1);
+        let scales = match weight.params() {
+            QuantParams::PerTensor {
+                scale,
+                zero_point: 0,
+            } => vec![*scale; rows],
+            QuantParams::PerChannel {
+                axis: 0,
+                scales,
+                zero_points,
+            } if zero_points.iter().all(|&zp| zp == 0) => scales.clone(),
+            params => {
+                return Err(format!(
+                    "Int8 layers need weights quantized symmetrically, per tensor or per output channel; got {:?}",
+                    params
+                )
+                .into())
+            }
+        };
+        if let Some(bias) = bias.filter(|bias| bias.data().len() != rows) {
+            return Err(format!(
+                "Expected a bias of {} values, got shape {:?}",
+                rows,
+                bias.shape()
+            )
+            .into());
+        }
+        let row_sums = weight
+            .values()
+            .chunks(cols.max(1))
+            .map(|row| row.iter().map(|&q| q as i32).sum())
+            .collect();
+
+        Ok(Self {
+            weight,
+            rows,
+            cols,
+            scales,
+            row_sums,
+            bias: bias.map(|bias| bias.data().to_vec()),
+        })
+    }
+
+    // The `[rows, n]` product of the weight and `[cols, n]` input columns quantized with
+    // `input`. With `output` the result is requantized to int8, as the next int8 layer
+    // would see it; otherwise the accumulators are scaled straight back to f32.
+    fn gemm(
+        &self,
+        columns: &[i8],
+        n: usize,
+        input: ActivationParams,
+        output: Option<ActivationParams>,
+    ) -> Vec<f32> {
+        if n == 0 {
+            return Vec::new();
+        }
+        let acc = matmul_i8(self.weight.values(), columns, self.rows, self.cols, n);
+        acc.chunks(n)
+            .enumerate()
+            .flat_map(|(row, values)| {
+                // sum(w * (q - zp)) = sum(w * q) - zp * sum(w)
+                let shift = input.zero_point * self.row_sums[row];
+                let scale = input.scale * self.scales[row];
+                let bias = self.bias.as_ref().map_or(0.0, |bias| bias[row]);
+                match output {
+                    Some(output) => {
+                        let bias = (bias / scale).round() as i32;
+                        let corrected: Vec<i32> =
+                            values.iter().map(|&a| a - shift + bias).collect();
+                        requantize(&corrected, scale / output.scale, output.zero_point)
+                            .into_iter()
+                            .map(|q| output.dequantize(q))
+                            .collect::<Vec<_>>()
+                    }
+                    None => values
+                        .iter()
+                        .map(|&a| (a - shift) as f32 * scale + bias)
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+}
+
+// How a layer quantizes its activations
+#[derive(Debug, Clone, Copy)]
+enum Activations {
+    // With ranges fixed by calibration, requantizing the output to int8
+    Static {
+        input: ActivationParams,
+        output: ActivationParams,
+    },
+    // With the range of each batch, returning f32 outputs
+    Dynamic,
+}
+
+impl Activations {
+    fn input(&self, input: &Tensor) -> ActivationParams {
+        match self {
+            Activations::Static { input, .. } => *input,
+            Activations::Dynamic => {
+                let data = input.data();
+                let (min, max) = data
+                    .iter()
+                    .filter(|x| x.is_finite())
+                    .fold((0.0f32, 0.0f32), |(lo, hi), &x| (lo.min(x), hi.max(x)));
+                ActivationParams::from_range(min, max)
+            }
+        }
+    }
+
+    fn output(&self) -> Option<ActivationParams> {
+        match self {
+            Activations::Static { output, .. } => Some(*output),
+            Activations::Dynamic => None,
+        }
+    }
+}
+
+/// A [`Linear`](crate::nn::Linear) layer computing in int8. It takes and returns f32
+/// tensors, quantizing its input on the way in.
+///
+/// Its weight is a [`QuantizedTensor`] from [`quantize_weight`], or one read back from a
+/// [`StateDict`](crate::serialize::StateDict), which stores per-channel parameters as is.
+#[derive(Debug, Clone)]
+pub struct QuantizedLinear {
+    weight: Int8Weight,
+    activations: Activations,
+}
+
+impl QuantizedLinear {
+    /// Builds the layer from an int8 `[out_features, in_features]` weight and the f32 bias,
+    /// for inputs and outputs quantized with `input` and `output`.
+    pub fn new(
+        weight: QuantizedTensor,
+        bias: Option<&Tensor>,
+        input: ActivationParams,
+        output: ActivationParams,
+    ) -> MlResult<Self> {
+        Self::with_activations(weight, bias, Activations::Static { input, output })
+    }
+
+    /// Builds the layer from an int8 `[out_features, in_features]` weight and the f32 bias,
+    /// quantizing each input with the range of its own batch. Outputs stay in f32, so no
+    /// calibration is needed.
+    pub fn dynamic(weight: QuantizedTensor, bias: Option<&Tensor>) -> MlResult<Self> {
+        Self::with_activations(weight, bias, Activations::Dynamic)
+    }
+
+    fn with_activations(
+        weight: QuantizedTensor,
+        bias: Option<&Tensor>,
+        activations: Activations,
+    ) -> MlResult<Self> {
+        if weight.shape().len() != 2 {
+            return Err(format!("Expected a 2D weight, got shape {:?}", weight.shape()).into());
+        }
+        Ok(Self {
+            weight: Int8Weight::new(weight, bias)?,
+            activations,
+        })
+    }
+
+    pub fn weight(&self) -> &QuantizedTensor {
+        &self.weight.weight
+    }
+
+    pub fn bias(&self) -> Option<&[f32]> {
+        self.weight.bias.as_deref()
+    }
+}
+
+impl Layer for QuantizedLinear {
+    fn forward(&self, input: &Tensor) -> MlResult<Tensor> {
+        let (batch, features) = match input.shape() {
+            &[batch, features] if features == self.weight.cols => (batch, features),
+            shape => {
+                return Err(format!(
+                    "QuantizedLinear expects [batch, {}] input, got {:?}",
+                    self.weight.cols, shape
+                )
+                .into())
+            }
+        };
+
+        // The GEMM wants the input as [features, batch] columns
+        let params = self.activations.input(input);
+        let mut columns = vec![0; features * batch];
+        for (b, row) in input.data().chunks(features.max(1)).enumerate() {
+            for (f, &x) in row.iter().enumerate() {
+                columns[f * batch + b] = params.quantize(x);
+            }
+        }
+
+        let out = self.weight.rows;
+        let output = self
+            .weight
+            .gemm(&columns, batch, params, self.activations.output());
+        let mut result = vec![0.0; batch * out];
+        for (o, row) in output.chunks(batch.max(1)).enumerate() {
+            for (b, &value) in row.iter().enumerate() {
+                result[b * out + o] = value;
+            }
+        }
+        Tensor::from_vec(result, &[batch, out])
+    }
+
+    fn backward(&mut self, _: &Tensor, _: &Tensor, _: f32) -> MlResult<Tensor> {
+        Err("Quantized layers run inference only".into())
+    }
+}
+
+/// A [`Conv2d`](crate::nn::Conv2d) layer computing in int8, taking and returning f32
+/// tensors like [`QuantizedLinear`].
+#[derive(Debug, Clone)]
+pub struct QuantizedConv2d {
+    weight: Int8Weight,
+    weight_shape: [usize; 4],
+    stride: usize,
+    padding: PaddingMode,
+    activations: Activations,
+}
+
+impl QuantizedConv2d {
+    /// Builds the layer from an int8 `[out_channels, in_channels, k, k]` weight and the f32
+    /// bias, for inputs and outputs quantized with `input` and `output`.
+    pub fn new(
+        weight: QuantizedTensor,
+        bias: Option<&Tensor>,
+        stride: usize,
+        padding: PaddingMode,
+        input: ActivationParams,
+        output: ActivationParams,
+    ) -> MlResult<Self> {
+        let activations = Activations::Static { input, output };
+        Self::with_activations(weight, bias, stride, padding, activations)
+    }
+
+    /// Like [`QuantizedLinear::dynamic`], quantizing each input with its own range.
+    pub fn dynamic(
+        weight: QuantizedTensor,
+        bias: Option<&Tensor>,
+        stride: usize,
+        padding: PaddingMode,
+    ) -> MlResult<Self> {
+        Self::with_activations(weight, bias, stride, padding, Activations::Dynamic)
+    }
+
+    fn with_activations(
+        weight: QuantizedTensor,
+        bias: Option<&Tensor>,
+        stride: usize,
+        padding: PaddingMode,
+        activations: Activations,
+    ) -> MlResult<Self> {
+        let weight_shape = match weight.shape() {
+            &[o, i, kh, kw] => [o, i, kh, kw],
+            shape => return Err(format!("Expected a 4D weight, got shape {:?}", shape).into()),
+        };
+        Ok(Self {
+            weight: Int8Weight::new(weight, bias)?,
+            weight_shape,
+            stride,
+            padding,
+            activations,
+        })
+    }
+
+    pub fn weight(&self) -> &QuantizedTensor {
+        &self.weight.weight
+    }
+
+    pub fn bias(&self) -> Option<&[f32]> {
+        self.weight.bias.as_deref()
+    }
+}
+
+impl Layer for QuantizedConv2d {
+    fn forward(&self, input: &Tensor) -> MlResult<Tensor> {
+        let &[batch, channels, height, width] = input.shape() else {
+            return Err("Conv2d expects 4D input (batch_size, channels, height, width)".into());
+        };
+        let kernel = self.weight_shape[2];
+        let shape = Conv2dShape::new(
+            [batch, channels, height, width],
+            self.weight_shape,
+            (self.stride, self.stride),
+            (
+                self.padding.amount(height, kernel, self.stride),
+                self.padding.amount(width, kernel, self.stride),
+            ),
+        )?;
+        let [_, _, out_h, out_w] = shape.output();
+
+        // Unfold `q - zero_point`, so padding is a real zero, then shift back to int8
+        let params = self.activations.input(input);
+        let centered: Vec<f32> = input
+            .data()
+            .iter()
+            .map(|&x| (params.quantize(x) as i32 - params.zero_point) as f32)
+            .collect();
+
+        let mut result = Vec::with_capacity(shape.output_len());
+        for b in 0..batch {
+            let columns: Vec<i8> = im2col(&centered, &shape, b)
+                .into_iter()
+                .map(|c| (c as i32 + params.zero_point) as i8)
+                .collect();
+            result.extend(self.weight.gemm(
+                &columns,
+                out_h * out_w,
+                params,
+                self.activations.output(),
+            ));
+        }
+        Tensor::from_vec(result, &shape.output())
+    }
+
+    fn backward(&mut self, _: &Tensor, _: &Tensor, _: f32) -> MlResult<Tensor> {
+        Err("Quantized layers run inference only".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quantize::compare;
+    use crate::serialize::{Deserialize, Serialize, StateDict};
+
+    #[test]
+    fn test_per_channel_weights() -> MlResult<()> {
+        // Filters two orders of magnitude apart
+        let weight: Vec<f32> = (0..2 * 9)
+            .map(|i| ((i * 5) % 7) as f32 - 3.0)
+            .enumerate()
+            .map(|(i, x)| if i < 9 { x / 100.0 } else { x })
+            .collect();
+        let weight = Tensor::from_vec(weight, &[2, 1, 3, 3])?;
+        let bias = Tensor::from_vec(vec![0.01, -0.5], &[2])?;
+        let input = Tensor::from_vec(
+            (0..25).map(|i| (i % 6) as f32 / 3.0 - 1.0).collect(),
+            &[1, 1, 5, 5],
+        )?;
+
+        let per_channel = quantize_weight(&weight, Granularity::PerChannel)?;
+        assert!(matches!(
+            per_channel.params(),
+            QuantParams::PerChannel { axis: 0, .. }
+        ));
+        let reference = Reference(weight.clone(), bias.clone());
+        let error = |weight: QuantizedTensor| -> MlResult<f32> {
+            let layer = QuantizedConv2d::dynamic(weight, Some(&bias), 1, PaddingMode::Valid)?;
+            let output = layer.forward(&input)?;
+            let expected = reference.forward(&input)?;
+            // The small filter's channel
+            let len = output.data().len() / 2;
+            Ok((0..len)
+                .map(|i| (output.data()[i] - expected.data()[i]).abs())
+                .fold(0.0, f32::max))
+        };
+        let coarse = error(quantize_weight(&weight, Granularity::PerTensor)?)?;
+        let fine = error(per_channel.clone())?;
+        assert!(fine * 10.0 < coarse, "{} vs {}", fine, coarse);
+
+        // The weight survives a state dict, and the layer rebuilt from it matches
+        let mut state = StateDict::new();
+        state.insert_quantized("conv.weight", per_channel.clone());
+        state.insert("conv.bias", bias.clone());
+        let state = StateDict::deserialize(&state.serialize())?;
+        let loaded = state.get_quantized("conv.weight").unwrap().clone();
+        assert_eq!(loaded, per_channel);
+        let original = QuantizedConv2d::dynamic(per_channel, Some(&bias), 1, PaddingMode::Valid)?;
+        let rebuilt =
+            QuantizedConv2d::dynamic(loaded, state.get("conv.bias"), 1, PaddingMode::Valid)?;
+        let report = compare(&original, &rebuilt, &[input])?;
+        assert_eq!(report.max_abs_error, 0.0);
+
+        // Weights with zero points can't feed the int8 GEMM
+        let affine = QuantizedTensor::from_parts(
+            QuantDtype::Int8,
+            &[2, 1, 3, 3],
+            vec![0; 18],
+            QuantParams::PerTensor {
+                scale: 0.1,
+                zero_point: 3,
+            },
+        )?;
+        assert!(QuantizedConv2d::dynamic(affine, None, 1, PaddingMode::Valid).is_err());
+        Ok(())
+    }
+
+    // A float convolution to compare against, with the weights kept exact
+    struct Reference(Tensor, Tensor);
+
+    impl Layer for Reference {
+        fn forward(&self, input: &Tensor) -> MlResult<Tensor> {
+            let mut conv = crate::nn::Conv2d::new(1, 2, 3, 1, PaddingMode::Valid, true)?;
+            for (param, value) in conv.parameters_mut().into_iter().zip([&self.0, &self.1]) {
+                *param = value.clone();
+            }
+            conv.forward(input)
+        }
+
+        fn backward(&mut self, _: &Tensor, _: &Tensor, _: f32) -> MlResult<Tensor> {
+            Err("The reference convolution only runs inference".into())
+        }
+    }
+}
@@ -0,0 +1,71 @@
+//! Post-training int8 quantization.
+//!
+//! A [`Calibration`] chains a model's layers, marking which ones run in int8. Running
+//! representative inputs through it records the range every activation takes, and
+//! [`Calibration::convert`] then gives a [`QuantizedModel`] in which those layers hold int8
+//! weights, quantize their inputs with a scale and zero point fitted to the recorded range,
+//! multiply with [`matmul_i8`](crate::backend::matmul_i8) and requantize the result to int8.
+//! [`compare`] measures how far the quantized model's outputs drift from the f32 ones.
+//!
+//! Weights are quantized per output channel by default; [`Granularity::PerTensor`] gives
+//! them a single scale instead, which is coarser but what some runtimes expect.
+//!
+//! Dynamic quantization, with [`quantize_dynamic`] or [`Calibration::convert_dynamic`],
+//! skips calibration: weights are quantized ahead of time, inputs with the range of each
+//! batch, and outputs are left in f32.
+//!
+//! Quantization-aware training with a [`QatModel`] trains through [`FakeQuantize`] layers
+//! and int8-rounded weights, so the model learns to tolerate the rounding before it is
+//! converted.
+//!
+//! ```ignore
+//! let mut calibration = Calibration::new()
+//!     .quantize(Conv2d::new(1, 8, 3, 1, PaddingMode::Same, true)?)
+//!     .float(ReLU)
+//!     .quantize(Linear::new(8 * 28 * 28, 10, true)?);
+//! for batch in &calibration_batches {
+//!     calibration.observe(batch)?;
+//! }
+//! let quantized = calibration.convert()?;
+//! println!("{}", compare(&calibration, &quantized, &test_batches)?);
+//! ```
+
+mod calibration;
+mod layers;
+mod observer;
+mod qat;
+mod report;
+
+pub use calibration::{Calibration, QuantizedModel};
+pub use layers::{quantize_weight, Granularity, QuantizedConv2d, QuantizedLinear};
+pub use observer::{ActivationParams, MinMaxObserver};
+pub use qat::{FakeQuantize, QatModel};
+pub use report::{compare, QuantizationReport};
+
+use crate::nn::Layer;
+use crate::MlResult;
+
+/// A layer with an int8 counterpart.
+pub trait Quantize: Layer {
+    /// The int8 version of this layer, for inputs and outputs quantized with `input` and
+    /// `output`, with weights quantized at `granularity`.
+    fn quantize_static(
+        &self,
+        input: ActivationParams,
+        output: ActivationParams,
+        granularity: Granularity,
+    ) -> MlResult<Box<dyn Layer>>;
+
+    /// The int8 version of this layer that quantizes each input with the range of its own
+    /// batch and returns f32 outputs; see [`quantize_dynamic`].
+    fn quantize_dynamic(&self, granularity: Granularity) -> MlResult<Box<dyn Layer>>;
+}
+
+/// Dynamically quantizes `layer`: its weights are quantized to int8 now and its inputs on
+/// the fly, batch by batch, while it keeps taking and returning f32 tensors. This needs no
+/// calibration data and shrinks the weights fourfold, which suits MLPs whose cost is in
+/// their weights. Weights are quantized per output channel. For a chain of layers, see
+/// [`Calibration::convert_dynamic`].
+pub fn quantize_dynamic(layer: &dyn Quantize) -> MlResult<Box<dyn Layer>> {
+    layer.quantize_dynamic(Granularity::PerChannel)
+}
@@ -0,0 +1,58 @@
+use crate::tensor::{affine_params, quantize_value, QuantDtype, Tensor};
+use crate::MlResult;
+
+/// The scale and zero point of an int8 activation: `real = scale * (q - zero_point)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ActivationParams {
+    pub scale: f32,
+    pub zero_point: i32,
+}
+
+impl ActivationParams {
+    /// The parameters mapping `min..=max`, widened to include zero, onto the int8 range.
+    pub fn from_range(min: f32, max: f32) -> Self {
+        let (scale, zero_point) = affine_params([min, max].into_iter(), QuantDtype::Int8);
+        Self { scale, zero_point }
+    }
+
+    pub fn quantize(&self, x: f32) -> i8 {
+        quantize_value(x, self.scale, self.zero_point, QuantDtype::Int8)
+    }
+
+    pub fn dequantize(&self, q: i8) -> f32 {
+        self.scale * (q as i32 - self.zero_point) as f32
+    }
+}
+
+/// Tracks the smallest and largest values an activation takes.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MinMaxObserver {
+    range: Option<(f32, f32)>,
+}
+
+impl MinMaxObserver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Widens the range to cover `tensor`. Non-finite values are ignored.
+    pub fn observe(&mut self, tensor: &Tensor) {
+        for &x in tensor.data().iter().filter(|x| x.is_finite()) {
+            let (lo, hi) = self.range.unwrap_or((x, x));
+            self.range = Some((lo.min(x), hi.max(x)));
+        }
+    }
+
+    /// The `(min, max)` seen so far, if anything was observed.
+    pub fn range(&self) -> Option<(f32, f32)> {
+        self.range
+    }
+
+    /// The int8 parameters covering the observed range.
+    pub fn params(&self) -> MlResult<ActivationParams> {
+        let (min, max) = self
+            .range
+            .ok_or("No values observed; calibrate with some inputs first")?;
+        Ok(ActivationParams::from_range(min, max))
+    }
+}
@@ -0,0 +1,358 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::layers::fake_quantize_weight;
+use super::{ActivationParams, Granularity, MinMaxObserver, Quantize, QuantizedModel};
+use crate::nn::Layer;
+use crate::tensor::Tensor;
+use crate::MlResult;
+
+/// A layer that rounds its input to int8 and back, so training sees the error quantization
+/// will add, and passes gradients straight through except where the input was clipped.
+///
+/// It records the range of its inputs while observing, which is also what it quantizes to.
+/// Stopping observation once the ranges settle, late in training, lets the weights adapt to
+/// fixed quantization parameters.
+#[derive(Debug, Default)]
+pub struct FakeQuantize {
+    observer: RefCell<MinMaxObserver>,
+    frozen: bool,
+}
+
+impl FakeQuantize {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether forward passes widen the recorded range. On by default.
+    pub fn set_observing(&mut self, observing: bool) {
+        self.frozen = !observing;
+    }
+
+    /// The parameters inputs are quantized with, once something was observed.
+    pub fn params(&self) -> MlResult<ActivationParams> {
+        self.observer.borrow().params()
+    }
+
+    fn observe(&self, input: &Tensor) -> MlResult<ActivationParams> {
+        if !self.frozen {
+            self.observer.borrow_mut().observe(input);
+        }
+        self.params()
+    }
+}
+
+impl Layer for FakeQuantize {
+    fn forward(&self, input: &Tensor) -> MlResult<Tensor> {
+        let params = self.observe(input)?;
+        let data = input
+            .data()
+            .iter()
+            .map(|&x| params.dequantize(params.quantize(x)))
+            .collect();
+        Tensor::from_vec(data, input.shape())
+    }
+
+    fn backward(&mut self, input: &Tensor, grad_output: &Tensor, _: f32) -> MlResult<Tensor> {
+        let params = self.params()?;
+        let (lo, hi) = (params.dequantize(i8::MIN), params.dequantize(i8::MAX));
+        let data = input
+            .data()
+            .iter()
+            .zip(grad_output.data())
+            .map(|(&x, &g)| if (lo..=hi).contains(&x) { g } else { 0.0 })
+            .collect();
+        Tensor::from_vec(data, input.shape())
+    }
+}
+
+// A layer whose weights are kept fake-quantized for the forward and backward passes, while
+// the updates accumulate in f32 master copies
+struct QatLayer {
+    layer: Box<dyn Quantize>,
+    // The index among the layer's parameters and f32 value of each weight; biases, which
+    // the int8 layers keep at higher precision, are left alone
+    master: Vec<(usize, Tensor)>,
+    granularity: Granularity,
+}
+
+impl QatLayer {
+    fn new(layer: Box<dyn Quantize>, granularity: Granularity) -> MlResult<Self> {
+        let master = layer
+            .named_parameters()
+            .into_iter()
+            .enumerate()
+            .filter(|(_, (_, param))| param.shape().len() >= 2)
+            .map(|(i, (_, param))| (i, param.clone()))
+            .collect();
+        let mut qat = Self {
+            layer,
+            master,
+            granularity,
+        };
+        qat.refresh()?;
+        Ok(qat)
+    }
+
+    fn refresh(&mut self) -> MlResult<()> {
+        let mut params = self.layer.named_parameters_mut();
+        for (i, master) in &self.master {
+            *params[*i].1 = fake_quantize_weight(master, self.granularity)?;
+        }
+        Ok(())
+    }
+
+    fn backward(&mut self, input: &Tensor, grad_output: &Tensor, lr: f32) -> MlResult<Tensor> {
+        let before: Vec<Tensor> = self
+            .layer
+            .named_parameters()
+            .into_iter()
+            .map(|(_, param)| param.clone())
+            .collect();
+        let grad_input = self.layer.backward(input, grad_output, lr)?;
+
+        // Straight through: the masters take whatever step the rounded weights took
+        let params = self.layer.named_parameters();
+        for (i, master) in &mut self.master {
+            *master = master.add(&params[*i].1.sub(&before[*i])?)?;
+        }
+        drop(params);
+        self.refresh()?;
+        Ok(grad_input)
+    }
+}
+
+enum Stage {
+    Quantize {
+        input: FakeQuantize,
+        layer: QatLayer,
+        output: FakeQuantize,
+    },
+    Float(Box<dyn Layer>),
+}
+
+impl Stage {
+    // The stage's output along with the inputs of its inner layers
+    fn forward(&self, x: &Tensor) -> MlResult<(Tensor, Vec<Tensor>)> {
+        match self {
+            Stage::Quantize {
+                input,
+                layer,
+                output,
+            } => {
+                let quantized = input.forward(x)?;
+                let y = layer.layer.forward(&quantized)?;
+                let out = output.forward(&y)?;
+                Ok((out, vec![x.clone(), quantized, y]))
+            }
+            Stage::Float(layer) => Ok((layer.forward(x)?, vec![x.clone()])),
+        }
+    }
+
+    fn backward(&mut self, inputs: &[Tensor], grad: &Tensor, lr: f32) -> MlResult<Tensor> {
+        match self {
+            Stage::Quantize {
+                input,
+                layer,
+                output,
+            } => {
+                let grad = output.backward(&inputs[2], grad, lr)?;
+                let grad = layer.backward(&inputs[1], &grad, lr)?;
+                input.backward(&inputs[0], &grad, lr)
+            }
+            Stage::Float(layer) => layer.backward(&inputs[0], grad, lr),
+        }
+    }
+}
+
+/// A chain of layers trained with quantization in the loop, then converted to int8.
+///
+/// Each layer added with [`QatModel::quantize`] trains on int8-rounded weights, with f32
+/// copies taking the updates, between [`FakeQuantize`] layers on its input and output whose
+/// ranges become the quantized layer's parameters.
+///
+/// ```ignore
+/// let mut model = QatModel::new()
+///     .quantize(Linear::new(16, 32, true)?)?
+///     .float(ReLU)
+///     .quantize(Linear::new(32, 4, true)?)?;
+/// let mut trainer = Trainer::new(model, MseLoss);
+/// trainer.fit(&loader, 10)?;
+/// let quantized = trainer.into_model().convert()?;
+/// ```
+pub struct QatModel {
+    stages: Vec<Stage>,
+    granularity: Granularity,
+}
+
+impl Default for QatModel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl QatModel {
+    /// A model whose layers train with per-channel weights.
+    pub fn new() -> Self {
+        Self::with_granularity(Granularity::PerChannel)
+    }
+
+    /// A model whose layers train with, and convert to, weights quantized at `granularity`.
+    pub fn with_granularity(granularity: Granularity) -> Self {
+        Self {
+            stages: Vec::new(),
+            granularity,
+        }
+    }
+
+    /// Appends a layer to train for int8, starting from its current weights.
+    pub fn quantize(mut self, layer: impl Quantize + 'static) -> MlResult<Self> {
+        self.stages.push(Stage::Quantize {
+            input: FakeQuantize::new(),
+            layer: QatLayer::new(Box::new(layer), self.granularity)?,
+            output: FakeQuantize::new(),
+        });
+        Ok(self)
+    }
+
+    /// Appends a layer that stays in f32.
+    pub fn float(mut self, layer: impl Layer + 'static) -> Self {
+        self.stages.push(Stage::Float(Box::new(layer)));
+        self
+    }
+
+    /// Stops or resumes updating the ranges of every [`FakeQuantize`].
+    pub fn set_observing(&mut self, observing: bool) {
+        for stage in &mut self.stages {
+            if let Stage::Quantize { input, output, .. } = stage {
+                input.set_observing(observing);
+                output.set_observing(observing);
+            }
+        }
+    }
+
+    /// The int8 model, with the trained weights and the ranges observed in training.
+    pub fn convert(self) -> MlResult<QuantizedModel> {
+        let granularity = self.granularity;
+        let layers = self
+            .stages
+            .into_iter()
+            .map(|stage| match stage {
+                Stage::Quantize {
+                    input,
+                    layer,
+                    output,
+                } => {
+                    let layer = layer.layer.quantize_static(
+                        input.params()?,
+                        output.params()?,
+                        granularity,
+                    )?;
+                    Ok(Rc::from(layer))
+                }
+                Stage::Float(layer) => Ok(Rc::from(layer)),
+            })
+            .collect::<MlResult<_>>()?;
+        Ok(QuantizedModel::from_layers(layers))
+    }
+}
+
+impl Layer for QatModel {
+    fn forward(&self, input: &Tensor) -> MlResult<Tensor> {
+        let mut x = input.clone();
+        for stage in &self.stages {
+            x = stage.forward(&x)?.0;
+        }
+        Ok(x)
+    }
+
+    fn backward(
+        &mut self,
+        input: &Tensor,
+        grad_output: &Tensor,
+        learning_rate: f32,
+    ) -> MlResult<Tensor> {
+        let mut inputs = Vec::with_capacity(self.stages.len());
+        let mut x = input.clone();
+        for stage in &self.stages {
+            let (out, stage_inputs) = stage.forward(&x)?;
+            inputs.push(stage_inputs);
+            x = out;
+        }
+
+        let mut grad = grad_output.clone();
+        for (stage, stage_inputs) in self.stages.iter_mut().zip(&inputs).rev() {
+            grad = stage.backward(stage_inputs, &grad, learning_rate)?;
+        }
+        Ok(grad)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::{Linear, ReLU};
+    use crate::quantize::compare;
+
+    #[test]
+    fn test_quantization_aware_training() -> MlResult<()> {
+        // Gradients pass where the input was in range
+        let mut fake = FakeQuantize::new();
+        let input = Tensor::from_vec(vec![-1.0, 0.3, 2.0], &[1, 3])?;
+        let rounded = fake.forward(&input)?;
+        assert!((rounded.data()[1] - 0.3).abs() < 3.0 / 255.0);
+        fake.set_observing(false);
+        let wider = Tensor::from_vec(vec![-1.0, 0.3, 5.0], &[1, 3])?;
+        assert_eq!(fake.forward(&wider)?.data()[2], rounded.data()[2]);
+        let grad = fake.backward(&wider, &Tensor::from_vec(vec![1.0; 3], &[1, 3])?, 0.1)?;
+        assert_eq!(grad.data(), &[1.0, 1.0, 0.0]);
+
+        // Learn y = x0 - x1 through the fake-quantized layers
+        crate::seed_all(3);
+        let mut model = QatModel::new()
+            .quantize(Linear::new(2, 8, true)?)?
+            .float(ReLU)
+            .quantize(Linear::new(8, 1, true)?)?;
+        let x = Tensor::from_vec(
+            vec![0.0, 1.0, 1.0, 0.0, 0.5, 0.5, 1.0, 1.0, 0.2, 0.9, 0.8, 0.1],
+            &[6, 2],
+        )?;
+        let target: Vec<f32> = x.data().chunks(2).map(|p| p[0] - p[1]).collect();
+        let loss = |model: &QatModel| -> MlResult<(f32, Tensor)> {
+            let y = model.forward(&x)?;
+            let diff: Vec<f32> = y.data().iter().zip(&target).map(|(y, t)| y - t).collect();
+            let loss = diff.iter().map(|d| d * d).sum::<f32>() / 6.0;
+            let grad = diff.iter().map(|d| 2.0 * d / 6.0).collect();
+            Ok((loss, Tensor::from_vec(grad, &[6, 1])?))
+        };
+
+        let (initial, _) = loss(&model)?;
+        for _ in 0..300 {
+            let (_, grad) = loss(&model)?;
+            model.backward(&x, &grad, 0.1)?;
+        }
+        let (trained, _) = loss(&model)?;
+        assert!(trained < initial / 4.0, "{} -> {}", initial, trained);
+
+        // The int8 model computes what training simulated
+        let reference = model.forward(&x)?;
+        let quantized = model.convert()?;
+        let report = compare(&Fixed(reference), &quantized, &[x])?;
+        assert!(report.max_abs_error < 0.05, "{}", report);
+        Ok(())
+    }
+
+    // Returns the same output whatever the input
+    struct Fixed(Tensor);
+
+    impl Layer for Fixed {
+        fn forward(&self, _: &Tensor) -> MlResult<Tensor> {
+            Ok(self.0.clone())
+        }
+
+        // The output doesn't depend on the input, so its gradient is zero
+        fn backward(&mut self, input: &Tensor, _: &Tensor, _: f32) -> MlResult<Tensor> {
+            input.mul_scalar(0.0)
+        }
+    }
+}
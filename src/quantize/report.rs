@@ -0,0 +1,104 @@
+use std::fmt::{Display, Formatter};
+
+use crate::nn::Layer;
+use crate::tensor::Tensor;
+use crate::MlResult;
+
+/// How closely a quantized model's outputs follow the f32 model's.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuantizationReport {
+    /// The number of output rows compared.
+    pub samples: usize,
+    pub max_abs_error: f32,
+    pub mean_abs_error: f32,
+    /// Signal to quantization noise ratio of the outputs, in dB; higher is better and
+    /// infinite when they match exactly.
+    pub sqnr_db: f32,
+    /// The fraction of rows whose largest output is at the same position in both models,
+    /// i.e. the top-1 agreement of a classifier.
+    pub top1_agreement: f32,
+}
+
+impl Display for QuantizationReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} samples: max abs error {:.6}, mean abs error {:.6}, SQNR {:.1} dB, top-1 agreement {:.2}%",
+            self.samples,
+            self.max_abs_error,
+            self.mean_abs_error,
+            self.sqnr_db,
+            self.top1_agreement * 100.0
+        )
+    }
+}
+
+/// Runs `inputs` through `reference` and `quantized` and compares their outputs. Each
+/// output's first dimension is taken as the batch.
+pub fn compare(
+    reference: &dyn Layer,
+    quantized: &dyn Layer,
+    inputs: &[Tensor],
+) -> MlResult<QuantizationReport> {
+    let mut samples = 0;
+    let mut agreeing = 0;
+    let mut count = 0;
+    let (mut max_abs_error, mut abs_error) = (0.0f32, 0.0f64);
+    let (mut signal, mut noise) = (0.0f64, 0.0f64);
+
+    for input in inputs {
+        let expected = reference.forward(input)?;
+        let actual = quantized.forward(input)?;
+        if expected.shape() != actual.shape() {
+            return Err(format!(
+                "Outputs differ in shape: {:?} and {:?}",
+                expected.shape(),
+                actual.shape()
+            )
+            .into());
+        }
+
+        for (&e, &a) in expected.data().iter().zip(actual.data()) {
+            let error = (e - a).abs();
+            max_abs_error = max_abs_error.max(error);
+            abs_error += error as f64;
+            signal += (e as f64).powi(2);
+            noise += (error as f64).powi(2);
+        }
+        count += expected.data().len();
+
+        let rows = expected.shape().first().copied().unwrap_or(1);
+        let width = expected.data().len() / rows.max(1);
+        if width > 0 {
+            for (e, a) in expected
+                .data()
+                .chunks(width)
+                .zip(actual.data().chunks(width))
+            {
+                agreeing += (argmax(e) == argmax(a)) as usize;
+            }
+        }
+        samples += rows;
+    }
+
+    let sqnr_db = if noise == 0.0 {
+        f32::INFINITY
+    } else {
+        (10.0 * (signal / noise).log10()) as f32
+    };
+    Ok(QuantizationReport {
+        samples,
+        max_abs_error,
+        mean_abs_error: (abs_error / count.max(1) as f64) as f32,
+        sqnr_db,
+        top1_agreement: agreeing as f32 / samples.max(1) as f32,
+    })
+}
+
+fn argmax(values: &[f32]) -> usize {
+    values
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map_or(0, |(i, _)| i)
+}
@@ -0,0 +1,383 @@
+//! Reinforcement-learning utilities.
+//!
+//! Trajectories are time-major: `rewards`, `values` and `dones` have shape `[steps]` for one
+//! environment or `[steps, envs]` for several run side by side, and a `done` of 1 marks the
+//! last step of an episode, past which nothing is bootstrapped. [`discounted_returns`] and
+//! [`gae`] turn them into the targets of policy-gradient methods; [`ReplayBuffer`] keeps
+//! transitions for off-policy ones like DQN, and [`epsilon_greedy`] and [`softmax_action`]
+//! pick actions while exploring.
+//!
+//! ```ignore
+//! let Advantages { advantages, returns } =
+//!     gae(&rewards, &values, &dones, &last_value, 0.99, 0.95)?;
+//! let actions = epsilon_greedy(&q_network.forward(&states)?, 0.1, &mut rng)?;
+//! ```
+
+use crate::distributions::{Categorical, Distribution};
+use crate::nn::random::SimpleRng;
+use crate::tensor::{Tensor, TensorError};
+use crate::MlResult;
+
+/// The discounted return of every step, `r_t + gamma * G_{t+1}`, restarting at each
+/// episode boundary.
+///
+/// The steps after the last one are worth `bootstrap`, of shape `[envs]` (or `[]` for one
+/// environment), usually the value estimate of the state the trajectory stopped in; without
+/// it they are worth nothing.
+pub fn discounted_returns(
+    rewards: &Tensor,
+    dones: &Tensor,
+    gamma: f32,
+    bootstrap: Option<&Tensor>,
+) -> MlResult<Tensor> {
+    let (steps, envs) = trajectory(rewards)?;
+    check_shape(rewards, dones)?;
+    let mut running = final_values(rewards, bootstrap)?;
+
+    let (r, d) = (rewards.data(), dones.data());
+    let mut returns = vec![0.0; r.len()];
+    for t in (0..steps).rev() {
+        for (e, running) in running.iter_mut().enumerate() {
+            let i = t * envs + e;
+            *running = r[i] + gamma * (1.0 - d[i]) * *running;
+            returns[i] = *running;
+        }
+    }
+    Tensor::from_vec(returns, rewards.shape())
+}
+
+/// The output of [`gae`]: the advantage of every step, and the return targets for the value
+/// function, `advantages + values`.
+#[derive(Debug, Clone)]
+pub struct Advantages {
+    pub advantages: Tensor,
+    pub returns: Tensor,
+}
+
+/// Generalized advantage estimation (Schulman et al.): the `gamma * lambda` discounted sum
+/// of the temporal-difference errors `r_t + gamma * V_{t+1} - V_t`.
+///
+/// `values` are the value estimates of each step's state, and `next_value` that of the
+/// state after the last step, shaped like a step of `rewards`. A `lambda` of 1 gives the
+/// discounted return minus the value, 0 the one-step TD error.
+pub fn gae(
+    rewards: &Tensor,
+    values: &Tensor,
+    dones: &Tensor,
+    next_value: &Tensor,
+    gamma: f32,
+    lambda: f32,
+) -> MlResult<Advantages> {
+    let (steps, envs) = trajectory(rewards)?;
+    check_shape(rewards, values)?;
+    check_shape(rewards, dones)?;
+    let last = final_values(rewards, Some(next_value))?;
+
+    let (r, v, d) = (rewards.data(), values.data(), dones.data());
+    let mut advantages = vec![0.0; r.len()];
+    let mut running = vec![0.0; envs];
+    for t in (0..steps).rev() {
+        for (e, running) in running.iter_mut().enumerate() {
+            let i = t * envs + e;
+            let next = if t + 1 < steps { v[i + envs] } else { last[e] };
+            let live = 1.0 - d[i];
+            let delta = r[i] + gamma * live * next - v[i];
+            *running = delta + gamma * lambda * live * *running;
+            advantages[i] = *running;
+        }
+    }
+
+    let returns = advantages.iter().zip(v).map(|(a, v)| a + v).collect();
+    Ok(Advantages {
+        advantages: Tensor::from_vec(advantages, rewards.shape())?,
+        returns: Tensor::from_vec(returns, rewards.shape())?,
+    })
+}
+
+/// One step of experience: the agent took `action` in `state`, got `reward` and ended up in
+/// `next_state`, which `done` marks as the end of the episode.
+#[derive(Debug, Clone)]
+pub struct Transition {
+    pub state: Tensor,
+    pub action: Tensor,
+    pub reward: f32,
+    pub next_state: Tensor,
+    pub done: bool,
+}
+
+/// Transitions stacked along a new leading axis: `rewards` and `dones` have shape
+/// `[batch]`, with `dones` 1 or 0, and the rest `[batch, ...]` of their transitions' shape.
+#[derive(Debug, Clone)]
+pub struct TransitionBatch {
+    pub states: Tensor,
+    pub actions: Tensor,
+    pub rewards: Tensor,
+    pub next_states: Tensor,
+    pub dones: Tensor,
+}
+
+/// A fixed-size store of past transitions to train on, replacing the oldest once full.
+///
+/// Every transition must have the same state and action shapes as the first, so batches
+/// can be stacked.
+#[derive(Debug, Clone)]
+pub struct ReplayBuffer {
+    capacity: usize,
+    transitions: Vec<Transition>,
+    // Where the next transition goes once the buffer is full
+    next: usize,
+}
+
+impl ReplayBuffer {
+    pub fn new(capacity: usize) -> MlResult<Self> {
+        if capacity == 0 {
+            return Err("A replay buffer needs room for at least one transition".into());
+        }
+        Ok(Self {
+            capacity,
+            transitions: Vec::with_capacity(capacity),
+            next: 0,
+        })
+    }
+
+    pub fn push(&mut self, transition: Transition) -> MlResult<()> {
+        if let Some(first) = self.transitions.first() {
+            check_shape(&first.state, &transition.state)?;
+            check_shape(&first.state, &transition.next_state)?;
+            check_shape(&first.action, &transition.action)?;
+        } else {
+            check_shape(&transition.state, &transition.next_state)?;
+        }
+
+        if self.transitions.len() < self.capacity {
+            self.transitions.push(transition);
+        } else {
+            self.transitions[self.next] = transition;
+            self.next = (self.next + 1) % self.capacity;
+        }
+        Ok(())
+    }
+
+    /// `batch_size` transitions drawn uniformly at random, with replacement.
+    pub fn sample(&self, batch_size: usize, rng: &mut SimpleRng) -> MlResult<TransitionBatch> {
+        if self.transitions.is_empty() {
+            return Err("Can't sample from an empty replay buffer".into());
+        }
+        let batch: Vec<&Transition> = (0..batch_size)
+            .map(|_| &self.transitions[rng.gen_index(self.transitions.len())])
+            .collect();
+
+        let flags = |f: fn(&Transition) -> f32| {
+            Tensor::from_vec(batch.iter().map(|&t| f(t)).collect(), &[batch_size])
+        };
+        Ok(TransitionBatch {
+            states: stack(&batch, |t| &t.state)?,
+            actions: stack(&batch, |t| &t.action)?,
+            rewards: flags(|t| t.reward)?,
+            next_states: stack(&batch, |t| &t.next_state)?,
+            dones: flags(|t| if t.done { 1.0 } else { 0.0 })?,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.transitions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transitions.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn clear(&mut self) {
+        self.transitions.clear();
+        self.next = 0;
+    }
+}
+
+/// For each row of `q_values`, a uniformly random action with probability `epsilon` and
+/// otherwise the one of largest value. Actions are indices stored as f32, one per row, as
+/// [`Categorical`] samples are.
+pub fn epsilon_greedy(q_values: &Tensor, epsilon: f32, rng: &mut SimpleRng) -> MlResult<Tensor> {
+    let k = actions(q_values)?;
+    let data = q_values
+        .data()
+        .chunks(k)
+        .map(|row| {
+            if rng.next_f32() < epsilon {
+                rng.gen_index(k) as f32
+            } else {
+                argmax(row) as f32
+            }
+        })
+        .collect();
+    Tensor::from_vec(data, &q_values.shape()[..q_values.shape().len() - 1])
+}
+
+/// For each row of `logits`, an action drawn with probability `softmax(logits / temperature)`
+/// (Boltzmann exploration): a high temperature explores evenly, a low one is nearly greedy.
+pub fn softmax_action(logits: &Tensor, temperature: f32, rng: &mut SimpleRng) -> MlResult<Tensor> {
+    if temperature <= 0.0 {
+        return Err("The temperature must be positive".into());
+    }
+    Categorical::new(logits.mul_scalar(1.0 / temperature)?)?.sample(rng)
+}
+
+// The number of steps and of environments of a trajectory tensor
+fn trajectory(rewards: &Tensor) -> MlResult<(usize, usize)> {
+    match *rewards.shape() {
+        [steps] => Ok((steps, 1)),
+        [steps, envs] => Ok((steps, envs)),
+        _ => Err(format!(
+            "Expected a trajectory of shape [steps] or [steps, envs], got {:?}",
+            rewards.shape()
+        )
+        .into()),
+    }
+}
+
+// The worth of the state after the last step for each environment, zero if not given
+fn final_values(rewards: &Tensor, values: Option<&Tensor>) -> MlResult<Vec<f32>> {
+    let step_shape = &rewards.shape()[1..];
+    match values {
+        Some(values) if values.shape() != step_shape => Err(TensorError::InvalidShape {
+            expected: step_shape.to_vec(),
+            got: values.shape().to_vec(),
+        }
+        .into()),
+        Some(values) => Ok(values.data().to_vec()),
+        None => Ok(vec![0.0; step_shape.iter().product()]),
+    }
+}
+
+// The tensors `field` picks out of `batch`, stacked along a new leading axis
+fn stack(batch: &[&Transition], field: fn(&Transition) -> &Tensor) -> MlResult<Tensor> {
+    let mut shape = vec![batch.len()];
+    shape.extend_from_slice(batch.first().map_or(&[][..], |t| field(t).shape()));
+    let data = batch
+        .iter()
+        .flat_map(|t| field(t).data())
+        .copied()
+        .collect();
+    Tensor::from_vec(data, &shape)
+}
+
+fn check_shape(expected: &Tensor, got: &Tensor) -> MlResult<()> {
+    if expected.shape() != got.shape() {
+        return Err(TensorError::InvalidShape {
+            expected: expected.shape().to_vec(),
+            got: got.shape().to_vec(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+// The size of the last axis, which holds the actions
+fn actions(q_values: &Tensor) -> MlResult<usize> {
+    match q_values.shape().last() {
+        Some(&k) if k > 0 => Ok(k),
+        _ => Err(format!(
+            "Expected values with an action axis, got shape {:?}",
+            q_values.shape()
+        )
+        .into()),
+    }
+}
+
+fn argmax(values: &[f32]) -> usize {
+    values
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map_or(0, |(i, _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::assert_close;
+
+    #[test]
+    fn test_returns_and_advantages() -> MlResult<()> {
+        // Two environments side by side; the first ends an episode after step 1
+        let rewards = Tensor::from_vec(vec![1.0, 0.0, 1.0, 0.0, 1.0, 1.0], &[3, 2])?;
+        let dones = Tensor::from_vec(vec![0.0, 0.0, 1.0, 0.0, 0.0, 0.0], &[3, 2])?;
+        let bootstrap = Tensor::from_vec(vec![10.0, 0.0], &[2])?;
+
+        let returns = discounted_returns(&rewards, &dones, 0.5, Some(&bootstrap))?;
+        assert_close(returns.data(), &[1.5, 0.25, 1.0, 0.5, 6.0, 1.0]);
+        let returns = discounted_returns(&rewards, &dones, 0.5, None)?;
+        assert_close(returns.data(), &[1.5, 0.25, 1.0, 0.5, 1.0, 1.0]);
+
+        // With lambda = 1 and zero values, GAE is the discounted return
+        let values = Tensor::from_vec(vec![0.0; 6], &[3, 2])?;
+        let out = gae(&rewards, &values, &dones, &bootstrap, 0.5, 1.0)?;
+        assert_close(out.advantages.data(), &[1.5, 0.25, 1.0, 0.5, 6.0, 1.0]);
+
+        // With lambda = 0 it is the one-step TD error
+        let values = Tensor::from_vec(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], &[3, 2])?;
+        let out = gae(&rewards, &values, &dones, &bootstrap, 0.5, 0.0)?;
+        assert_close(out.advantages.data(), &[1.5, 0.0, -2.0, -1.0, 1.0, -5.0]);
+        assert_close(out.returns.data(), &[2.5, 2.0, 1.0, 3.0, 6.0, 1.0]);
+
+        assert!(gae(&rewards, &values, &dones, &values, 0.5, 0.9).is_err());
+        let flat = Tensor::from_vec(vec![1.0; 3], &[3])?;
+        assert!(discounted_returns(&rewards, &flat, 0.5, None).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_replay_buffer_and_exploration() -> MlResult<()> {
+        let mut rng = SimpleRng::new(3);
+        let mut buffer = ReplayBuffer::new(3)?;
+        for step in 0..5 {
+            let state = Tensor::from_vec(vec![step as f32; 2], &[2])?;
+            buffer.push(Transition {
+                next_state: state.add_scalar(1.0)?,
+                state,
+                action: Tensor::from_vec(vec![step as f32], &[1])?,
+                reward: step as f32,
+                done: step == 4,
+            })?;
+        }
+        // The two oldest transitions were replaced
+        assert_eq!(buffer.len(), 3);
+        let batch = buffer.sample(8, &mut rng)?;
+        assert_eq!(batch.states.shape(), &[8, 2]);
+        assert_eq!(batch.actions.shape(), &[8, 1]);
+        for i in 0..8 {
+            let reward = batch.rewards.data()[i];
+            assert!(reward >= 2.0);
+            assert_eq!(batch.next_states.data()[2 * i], reward + 1.0);
+            assert_eq!(batch.dones.data()[i], if reward == 4.0 { 1.0 } else { 0.0 });
+        }
+        let wrong = Tensor::from_vec(vec![0.0; 3], &[3])?;
+        assert!(buffer
+            .push(Transition {
+                state: wrong.clone(),
+                action: Tensor::from_vec(vec![0.0], &[1])?,
+                reward: 0.0,
+                next_state: wrong,
+                done: false,
+            })
+            .is_err());
+
+        // Greedy without exploration, uniform with full exploration
+        let q = Tensor::from_vec(vec![0.1, 0.9, 0.3, 2.0, -1.0, 0.0], &[2, 3])?;
+        assert_eq!(epsilon_greedy(&q, 0.0, &mut rng)?.data(), &[1.0, 0.0]);
+        let mut counts = [0; 3];
+        for _ in 0..3000 {
+            counts[epsilon_greedy(&q, 1.0, &mut rng)?.data()[0] as usize] += 1;
+        }
+        assert!(counts.iter().all(|&c| c > 800), "{:?}", counts);
+
+        // A low temperature is nearly greedy
+        let actions = softmax_action(&q, 0.01, &mut rng)?;
+        assert_eq!(actions.data(), &[1.0, 0.0]);
+        assert!(softmax_action(&q, 0.0, &mut rng).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,341 @@
+//! Checkpoints of a whole training run.
+//!
+//! A [`Checkpoint`] holds everything needed to continue training as if it had never stopped:
+//! the model's parameters, the state of whatever drives the updates (an optimizer, a learning
+//! rate schedule, a [`GradScaler`](crate::amp::GradScaler)), the random number generator and
+//! the epoch and step counters.
+//!
+//! ```ignore
+//! let run = TrainingRun::new(&mut model).optimizer(&mut scaler).rng(&mut rng);
+//! Checkpoint::capture(&run, epoch, step)?.save("run.ckpt")?;
+//!
+//! // Later, with freshly constructed parts:
+//! let mut run = TrainingRun::new(&mut model).optimizer(&mut scaler).rng(&mut rng);
+//! let checkpoint = Checkpoint::resume("run.ckpt", &mut run)?;
+//! for epoch in checkpoint.epoch.. { /* ... */ }
+//! ```
+
+#[cfg(feature = "fs")]
+use std::fs::File;
+#[cfg(feature = "fs")]
+use std::io::{BufReader, BufWriter};
+use std::io::{Read, Write};
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+use super::format::{self, FormatError};
+use super::{Deserialize, Serialize, StateDict};
+use crate::nn::random::{self, SimpleRng};
+use crate::nn::Layer;
+use crate::MlResult;
+
+const CHECKPOINT_MAGIC: &[u8; 4] = b"SPCK";
+
+/// Training state other than model parameters that has to be saved for a run to resume
+/// exactly, such as an optimizer's running averages or a scheduler's position.
+pub trait TrainingState {
+    fn state_dict(&self) -> MlResult<StateDict>;
+
+    fn load_state_dict(&mut self, state: &StateDict) -> MlResult<()>;
+}
+
+/// The parts of a training run a checkpoint is taken from and restored into. Only the model
+/// is required.
+pub struct TrainingRun<'a> {
+    model: &'a mut dyn Layer,
+    optimizer: Option<&'a mut dyn TrainingState>,
+    scheduler: Option<&'a mut dyn TrainingState>,
+    rng: Option<RunRng<'a>>,
+}
+
+// The generator a run draws from: its own, or the global one `seed_all` seeds
+enum RunRng<'a> {
+    Local(&'a mut SimpleRng),
+    Global,
+}
+
+impl<'a> TrainingRun<'a> {
+    pub fn new(model: &'a mut dyn Layer) -> Self {
+        Self {
+            model,
+            optimizer: None,
+            scheduler: None,
+            rng: None,
+        }
+    }
+
+    pub fn optimizer(mut self, optimizer: &'a mut dyn TrainingState) -> Self {
+        self.optimizer = Some(optimizer);
+        self
+    }
+
+    pub fn scheduler(mut self, scheduler: &'a mut dyn TrainingState) -> Self {
+        self.scheduler = Some(scheduler);
+        self
+    }
+
+    pub fn rng(mut self, rng: &'a mut SimpleRng) -> Self {
+        self.rng = Some(RunRng::Local(rng));
+        self
+    }
+
+    /// Saves and restores the global generator behind [`seed_all`](crate::seed_all)
+    /// instead of a generator of the run's own.
+    pub fn global_rng(mut self) -> Self {
+        self.rng = Some(RunRng::Global);
+        self
+    }
+}
+
+/// A snapshot of a training run, written as a single file.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    /// The epoch to continue from.
+    pub epoch: u64,
+    /// The global step to continue from.
+    pub step: u64,
+    pub model: StateDict,
+    pub optimizer: Option<StateDict>,
+    pub scheduler: Option<StateDict>,
+    pub rng: Option<u64>,
+}
+
+impl Checkpoint {
+    /// Snapshots every part of `run`.
+    pub fn capture(run: &TrainingRun<'_>, epoch: u64, step: u64) -> MlResult<Self> {
+        Ok(Self {
+            epoch,
+            step,
+            model: run.model.state_dict(),
+            optimizer: run.optimizer.as_ref().map(|o| o.state_dict()).transpose()?,
+            scheduler: run.scheduler.as_ref().map(|s| s.state_dict()).transpose()?,
+            rng: run.rng.as_ref().map(|rng| match rng {
+                RunRng::Local(rng) => rng.state(),
+                RunRng::Global => random::rng_state(),
+            }),
+        })
+    }
+
+    /// Puts the snapshot back into `run`. The model must match the saved parameters exactly,
+    /// and every part `run` has must have been saved; parts `run` leaves out are skipped.
+    pub fn restore(&self, run: &mut TrainingRun<'_>) -> MlResult<()> {
+        let missing = [
+            (
+                "optimizer",
+                run.optimizer.is_some() && self.optimizer.is_none(),
+            ),
+            (
+                "scheduler",
+                run.scheduler.is_some() && self.scheduler.is_none(),
+            ),
+            ("rng", run.rng.is_some() && self.rng.is_none()),
+        ];
+        if let Some((part, _)) = missing.iter().find(|(_, missing)| *missing) {
+            return Err(format!("Checkpoint has no {} state to restore", part).into());
+        }
+
+        run.model.load_state_dict(&self.model, true)?;
+        if let (Some(optimizer), Some(state)) = (run.optimizer.as_mut(), &self.optimizer) {
+            optimizer.load_state_dict(state)?;
+        }
+        if let (Some(scheduler), Some(state)) = (run.scheduler.as_mut(), &self.scheduler) {
+            scheduler.load_state_dict(state)?;
+        }
+        match (run.rng.as_mut(), self.rng) {
+            (Some(RunRng::Local(rng)), Some(state)) => **rng = SimpleRng::new(state),
+            (Some(RunRng::Global), Some(state)) => random::set_rng_state(state),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Loads the checkpoint at `path` into `run` and returns it, for its counters.
+    #[cfg(feature = "fs")]
+    pub fn resume<P: AsRef<Path>>(path: P, run: &mut TrainingRun<'_>) -> MlResult<Self> {
+        let checkpoint = Self::load(path)?;
+        checkpoint.restore(run)?;
+        Ok(checkpoint)
+    }
+
+    #[cfg(feature = "fs")]
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> MlResult<()> {
+        let file = File::create(path).map_err(|e| format!("Failed to create file: {}", e))?;
+        self.save_to(BufWriter::new(file))
+    }
+
+    #[cfg(feature = "fs")]
+    pub fn load<P: AsRef<Path>>(path: P) -> MlResult<Self> {
+        let file = File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
+        Self::load_from(BufReader::new(file))
+    }
+
+    pub fn save_to<W: Write>(&self, writer: W) -> MlResult<()> {
+        format::write_file(writer, self)
+    }
+
+    pub fn load_from<R: Read>(reader: R) -> MlResult<Self> {
+        format::read_file(reader)
+    }
+}
+
+fn write_section<W: Write>(writer: &mut W, state: Option<&StateDict>) -> MlResult<()> {
+    match state {
+        Some(state) => {
+            let mut prefix = vec![1];
+            prefix.extend_from_slice(&state.serialized_len().to_le_bytes());
+            format::write_all(&mut *writer, &prefix)?;
+            state.serialize_into(writer)
+        }
+        None => format::write_all(writer, &[0]),
+    }
+}
+
+fn read_section<R: Read>(reader: &mut R) -> MlResult<Option<StateDict>> {
+    let mut flag = [0];
+    format::read_exact(&mut *reader, &mut flag)?;
+    match flag[0] {
+        0 => Ok(None),
+        1 => {
+            let len = format::read_u64(&mut *reader)?;
+            let mut section = reader.take(len);
+            let state = StateDict::deserialize_from(&mut section)?;
+            if section.limit() != 0 {
+                return Err(FormatError::Invalid("section length".into()).into());
+            }
+            Ok(Some(state))
+        }
+        flag => Err(FormatError::Invalid(format!("section flag {}", flag)).into()),
+    }
+}
+
+impl Serialize for Checkpoint {
+    fn serialize(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.serialize_into(&mut bytes)
+            .expect("writing to a Vec can't fail");
+        bytes
+    }
+
+    fn serialize_into<W: Write>(&self, mut writer: W) -> MlResult<()> {
+        let mut header = Vec::new();
+        header.extend_from_slice(CHECKPOINT_MAGIC);
+        header.extend_from_slice(&self.epoch.to_le_bytes());
+        header.extend_from_slice(&self.step.to_le_bytes());
+
+        match self.rng {
+            Some(state) => {
+                header.push(1);
+                header.extend_from_slice(&state.to_le_bytes());
+            }
+            None => header.push(0),
+        }
+        format::write_all(&mut writer, &header)?;
+
+        write_section(&mut writer, Some(&self.model))?;
+        write_section(&mut writer, self.optimizer.as_ref())?;
+        write_section(&mut writer, self.scheduler.as_ref())
+    }
+}
+
+impl Deserialize for Checkpoint {
+    fn deserialize(bytes: &[u8]) -> MlResult<Self> {
+        let mut reader = bytes;
+        let checkpoint = Self::deserialize_from(&mut reader)?;
+        if !reader.is_empty() {
+            return Err(FormatError::TrailingBytes(reader.len()).into());
+        }
+        Ok(checkpoint)
+    }
+
+    fn deserialize_from<R: Read>(mut reader: R) -> MlResult<Self> {
+        let mut magic = [0; 4];
+        format::read_exact(&mut reader, &mut magic)?;
+        if &magic != CHECKPOINT_MAGIC {
+            return Err(FormatError::BadMagic.into());
+        }
+
+        let epoch = format::read_u64(&mut reader)?;
+        let step = format::read_u64(&mut reader)?;
+        let mut flag = [0];
+        format::read_exact(&mut reader, &mut flag)?;
+        let rng = match flag[0] {
+            0 => None,
+            1 => Some(format::read_u64(&mut reader)?),
+            flag => return Err(FormatError::Invalid(format!("rng flag {}", flag)).into()),
+        };
+
+        let model = read_section(&mut reader)?
+            .ok_or_else(|| FormatError::Invalid("missing model state".into()))?;
+        let optimizer = read_section(&mut reader)?;
+        let scheduler = read_section(&mut reader)?;
+
+        Ok(Self {
+            epoch,
+            step,
+            model,
+            optimizer,
+            scheduler,
+            rng,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amp::GradScaler;
+    use crate::nn::Linear;
+    use crate::tensor::Tensor;
+
+    fn train_step(model: &mut Linear, scaler: &mut GradScaler, rng: &mut SimpleRng) {
+        let input: Vec<f32> = (0..6).map(|_| rng.gen_range(-1.0, 1.0)).collect();
+        let input = Tensor::from_vec(input, &[2, 3]).unwrap();
+        let output = model.forward(&input).unwrap();
+        let grad = scaler.scale(&output.mul_scalar(0.5).unwrap()).unwrap();
+        scaler.backward(model, &input, &grad, 0.1).unwrap();
+        scaler.update();
+    }
+
+    #[test]
+    fn test_resume_is_exact() -> MlResult<()> {
+        let path = "test_resume.ckpt";
+        let mut model = Linear::new(3, 2, true)?;
+        let mut scaler = GradScaler::new(1024.0, 2.0, 0.5, 2);
+        let mut rng = SimpleRng::new(7);
+
+        train_step(&mut model, &mut scaler, &mut rng);
+        let run = TrainingRun::new(&mut model)
+            .optimizer(&mut scaler)
+            .rng(&mut rng);
+        Checkpoint::capture(&run, 1, 1)?.save(path)?;
+        for _ in 0..3 {
+            train_step(&mut model, &mut scaler, &mut rng);
+        }
+
+        let mut resumed = Linear::new(3, 2, true)?;
+        let mut resumed_scaler = GradScaler::default();
+        let mut resumed_rng = SimpleRng::new(0);
+        let mut run = TrainingRun::new(&mut resumed)
+            .optimizer(&mut resumed_scaler)
+            .rng(&mut resumed_rng);
+        let checkpoint = Checkpoint::resume(path, &mut run)?;
+        assert_eq!((checkpoint.epoch, checkpoint.step), (1, 1));
+        assert!(checkpoint.scheduler.is_none());
+        for _ in 0..3 {
+            train_step(&mut resumed, &mut resumed_scaler, &mut resumed_rng);
+        }
+
+        assert_eq!(resumed.weight().data(), model.weight().data());
+        assert_eq!(resumed.bias().unwrap().data(), model.bias().unwrap().data());
+        assert_eq!(resumed_scaler.get_scale(), scaler.get_scale());
+        assert_eq!(resumed_rng.state(), rng.state());
+
+        // A part the checkpoint never saved can't be restored
+        let mut scheduler = GradScaler::default();
+        let mut run = TrainingRun::new(&mut resumed).scheduler(&mut scheduler);
+        assert!(Checkpoint::resume(path, &mut run).is_err());
+
+        std::fs::remove_file(path).expect("Failed to remove test file");
+        Ok(())
+    }
+}
@@ -0,0 +1,248 @@
+//! Moving tensors between checkpoint formats, as the `cetana-convert` tool does.
+//!
+//! Every format is read into a [`StateDict`] and written from one, so any readable format
+//! converts to any writable one. Tensors are f32 in between, so converting to a format never
+//! loses anything a cetana model could hold.
+
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::str::FromStr;
+
+use super::{
+    load_npz, load_onnx, load_pt, load_safetensors, save_npz, save_onnx, save_safetensors,
+    GgufFile, MappedStateDict, StateDict,
+};
+use crate::tensor::Tensor;
+use crate::{MlError, MlResult};
+
+/// The checkpoint formats cetana can read, and in most cases write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TensorFormat {
+    /// cetana's own state dict files, from [`StateDict::save`] or [`Model::save`](super::Model::save).
+    Native,
+    Safetensors,
+    /// A single NumPy array, named after the file.
+    Npy,
+    Npz,
+    /// The initializers of an ONNX model.
+    Onnx,
+    /// PyTorch `torch.save` checkpoints; read only.
+    PyTorch,
+    /// GGUF files; read only.
+    Gguf,
+}
+
+impl TensorFormat {
+    pub const ALL: [TensorFormat; 7] = [
+        TensorFormat::Native,
+        TensorFormat::Safetensors,
+        TensorFormat::Npy,
+        TensorFormat::Npz,
+        TensorFormat::Onnx,
+        TensorFormat::PyTorch,
+        TensorFormat::Gguf,
+    ];
+
+    /// Picks the format from a file's extension: `.spn`, `.safetensors`, `.npy`, `.npz`,
+    /// `.onnx`, `.pt`/`.pth` or `.gguf`.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> MlResult<Self> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase)
+            .unwrap_or_default();
+        match extension.as_str() {
+            "spn" => Ok(TensorFormat::Native),
+            "pth" => Ok(TensorFormat::PyTorch),
+            extension => extension.parse().map_err(|_| {
+                format!(
+                    "Can't tell the format of {} from its extension",
+                    path.display()
+                )
+                .into()
+            }),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TensorFormat::Native => "native",
+            TensorFormat::Safetensors => "safetensors",
+            TensorFormat::Npy => "npy",
+            TensorFormat::Npz => "npz",
+            TensorFormat::Onnx => "onnx",
+            TensorFormat::PyTorch => "pt",
+            TensorFormat::Gguf => "gguf",
+        }
+    }
+
+    pub fn can_write(self) -> bool {
+        !matches!(self, TensorFormat::PyTorch | TensorFormat::Gguf)
+    }
+
+    /// Reads every tensor of the file at `path`.
+    pub fn load<P: AsRef<Path>>(self, path: P) -> MlResult<StateDict> {
+        let path = path.as_ref();
+        match self {
+            TensorFormat::Native => StateDict::load(path),
+            TensorFormat::Safetensors => load_safetensors(path),
+            TensorFormat::Npy => {
+                let name = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .unwrap_or("tensor");
+                Ok([(name.to_string(), Tensor::from_npy(path)?)]
+                    .into_iter()
+                    .collect())
+            }
+            TensorFormat::Npz => Ok(load_npz(path)?.into_iter().collect()),
+            TensorFormat::Onnx => load_onnx(path),
+            TensorFormat::PyTorch => load_pt(path),
+            TensorFormat::Gguf => GgufFile::open(path)?.to_state_dict(),
+        }
+    }
+
+    /// Writes `state` to `path`. An `.npy` file holds exactly one tensor. Only native files
+    /// keep quantized tensors as they are; other formats get them dequantized.
+    pub fn save<P: AsRef<Path>>(self, path: P, state: &StateDict) -> MlResult<()> {
+        let dequantized;
+        let state = if self != TensorFormat::Native && state.iter_quantized().next().is_some() {
+            dequantized = dequantize_all(state)?;
+            &dequantized
+        } else {
+            state
+        };
+        match self {
+            TensorFormat::Native => state.save(path),
+            TensorFormat::Safetensors => save_safetensors(path, state),
+            TensorFormat::Npy => match state.iter().collect::<Vec<_>>()[..] {
+                [(_, tensor)] => tensor.to_npy(path),
+                _ => Err(format!(
+                    "An npy file holds one tensor, not {}; use npz instead",
+                    state.len()
+                )
+                .into()),
+            },
+            TensorFormat::Npz => save_npz(path, &state.iter().collect::<Vec<_>>()),
+            TensorFormat::Onnx => save_onnx(path, state),
+            TensorFormat::PyTorch | TensorFormat::Gguf => {
+                Err(format!("Writing {} files is not supported", self).into())
+            }
+        }
+    }
+
+    /// The name and shape of every tensor in the file at `path`. Native and GGUF files are
+    /// listed from their headers alone; other formats are read in full.
+    pub fn inventory<P: AsRef<Path>>(self, path: P) -> MlResult<Vec<(String, Vec<usize>)>> {
+        match self {
+            TensorFormat::Native => {
+                let mapped = MappedStateDict::open(path)?;
+                Ok(mapped
+                    .keys()
+                    .map(|name| (name.to_string(), mapped.shape(name).unwrap().to_vec()))
+                    .collect())
+            }
+            TensorFormat::Gguf => Ok(GgufFile::open(path)?
+                .tensors()
+                .iter()
+                .map(|info| (info.name.clone(), info.shape.clone()))
+                .collect()),
+            _ => Ok(self
+                .load(path)?
+                .iter()
+                .map(|(name, tensor)| (name.to_string(), tensor.shape().to_vec()))
+                .collect()),
+        }
+    }
+}
+
+impl Display for TensorFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+impl FromStr for TensorFormat {
+    type Err = MlError;
+
+    fn from_str(s: &str) -> MlResult<Self> {
+        TensorFormat::ALL
+            .into_iter()
+            .find(|format| format.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("Unknown tensor format {:?}", s).into())
+    }
+}
+
+// A copy of `state` with every quantized tensor dequantized, in key order
+fn dequantize_all(state: &StateDict) -> MlResult<StateDict> {
+    let mut floats = StateDict::new();
+    for name in state.keys() {
+        let tensor = match state.get_quantized(name) {
+            Some(quantized) => quantized.dequantize()?,
+            None => state.get(name).unwrap().clone(),
+        };
+        floats.insert(name, tensor);
+    }
+    Ok(floats)
+}
+
+/// Converts the file at `input` to `output`, each in the format its extension implies.
+pub fn convert<P: AsRef<Path>, Q: AsRef<Path>>(input: P, output: Q) -> MlResult<StateDict> {
+    let state = TensorFormat::from_path(&input)?.load(input)?;
+    TensorFormat::from_path(&output)?.save(output, &state)?;
+    Ok(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_between_formats() -> MlResult<()> {
+        let mut state = StateDict::new();
+        state.insert(
+            "fc.weight",
+            Tensor::from_vec(vec![1.0, -2.0, 3.5, 4.0], &[2, 2])?,
+        );
+        state.insert("fc.bias", Tensor::from_vec(vec![0.25, 0.5], &[2])?);
+        state.save("test_convert.spn")?;
+
+        // Around every writable format and back to native
+        let mut previous = "test_convert.spn".to_string();
+        for extension in ["safetensors", "npz", "onnx", "spn"] {
+            let next = format!("test_convert_out.{}", extension);
+            convert(&previous, &next)?;
+            previous = next;
+        }
+        let back = StateDict::load(&previous)?;
+        assert_eq!(back.keys().collect::<Vec<_>>(), ["fc.weight", "fc.bias"]);
+        assert_eq!(
+            back.get("fc.weight").unwrap().data(),
+            &[1.0, -2.0, 3.5, 4.0]
+        );
+        assert_eq!(
+            TensorFormat::Native.inventory(&previous)?,
+            [
+                ("fc.weight".to_string(), vec![2, 2]),
+                ("fc.bias".to_string(), vec![2])
+            ]
+        );
+
+        assert!(convert("test_convert.spn", "test_convert_out.npy").is_err());
+        assert!(convert("test_convert.spn", "test_convert_out.gguf").is_err());
+        assert_eq!(TensorFormat::from_path("a/b.PTH")?, TensorFormat::PyTorch);
+        assert!(TensorFormat::from_path("weights.bin").is_err());
+
+        for path in [
+            "test_convert.spn",
+            "test_convert_out.safetensors",
+            "test_convert_out.npz",
+            "test_convert_out.onnx",
+            "test_convert_out.spn",
+        ] {
+            std::fs::remove_file(path).expect("Failed to remove test file");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,937 @@
+//! The binary layout of model files and the tensors inside them.
+//!
+//! A model file is a header followed by the model's serialized bytes:
+//!
+//! | bytes | field                                      |
+//! |-------|--------------------------------------------|
+//! | 4     | magic, `SPNF`                              |
+//! | 2     | format version                             |
+//! | 2     | reserved, zero                             |
+//! | 8     | payload length                             |
+//! | 4     | CRC-32 of the payload                      |
+//!
+//! Each tensor in the payload is a record of its own:
+//!
+//! | bytes   | field                                    |
+//! |---------|------------------------------------------|
+//! | 4       | magic, `SPTF`                            |
+//! | 2       | format version                           |
+//! | 1       | dtype tag: 0 = f32, 1 = f16, 2 = f64     |
+//! | 1       | element byte order: 0 = little, 1 = big  |
+//! | 4       | number of dimensions                     |
+//! | 8 each  | dimensions                               |
+//! | 4       | CRC-32 of the data                       |
+//! | rest    | elements                                 |
+//!
+//! Quantized tensors have a record of their own, carrying the parameters that dequantize
+//! them:
+//!
+//! | bytes   | field                                              |
+//! |---------|----------------------------------------------------|
+//! | 4       | magic, `SPQT`                                      |
+//! | 2       | format version                                     |
+//! | 1       | dtype tag: 0 = int8, 1 = int4                      |
+//! | 1       | scheme: 0 = per tensor, 1 = per channel            |
+//! | 4       | number of dimensions                               |
+//! | 8 each  | dimensions                                         |
+//! | 4       | channel axis, per channel only                     |
+//! | 4 each  | f32 scales, one or one per channel                 |
+//! | 4 each  | i32 zero points, as many as scales                 |
+//! | 4       | CRC-32 of the data                                 |
+//! | rest    | values, one per byte or for int4 two per byte, low nibble first |
+//!
+//! Headers are little-endian on every platform, and records written by this build store
+//! their elements little-endian too; readers also accept big-endian elements. A record's
+//! byte count must match its shape exactly. Files written before versioning (`SPN1` files and tensors
+//! without a record header) are still read, as version 1. Quantized records first appear in
+//! version 3.
+
+use super::npy::f16_to_f32;
+use super::{Deserialize, Serialize};
+use crate::backend::DType;
+use crate::tensor::TensorError;
+use crate::{MlError, MlResult};
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+
+/// The format version this build writes. Readers accept this version and older ones.
+pub const FORMAT_VERSION: u16 = 3;
+
+const FILE_MAGIC: &[u8; 4] = b"SPNF";
+const LEGACY_FILE_MAGIC: &[u8; 4] = b"SPN1";
+const TENSOR_MAGIC: &[u8; 4] = b"SPTF";
+const QUANTIZED_MAGIC: &[u8; 4] = b"SPQT";
+
+/// Why serialized bytes couldn't be read.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormatError {
+    /// The bytes don't start with a known magic number.
+    BadMagic,
+    /// Written by a newer version of the format than this build understands.
+    UnsupportedVersion {
+        found: u16,
+        supported: u16,
+    },
+    UnsupportedDtype(u8),
+    /// A tensor record declares a byte order other than little- or big-endian.
+    UnsupportedByteOrder(u8),
+    /// The data ends early, as in a partially written or cut off file.
+    Truncated {
+        needed: usize,
+        available: usize,
+    },
+    /// Bytes left over after a complete record.
+    TrailingBytes(usize),
+    /// The data doesn't match its stored CRC-32.
+    ChecksumMismatch {
+        expected: u32,
+        found: u32,
+    },
+    Invalid(String),
+}
+
+impl std::error::Error for FormatError {}
+
+impl Display for FormatError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormatError::BadMagic => write!(f, "Not a cetana file: bad magic number"),
+            FormatError::UnsupportedVersion { found, supported } => write!(
+                f,
+                "Unsupported format version {} (this build reads up to {})",
+                found, supported
+            ),
+            FormatError::UnsupportedDtype(tag) => write!(f, "Unsupported dtype tag {}", tag),
+            FormatError::UnsupportedByteOrder(tag) => {
+                write!(f, "Unsupported byte order tag {}", tag)
+            }
+            FormatError::Truncated { needed, available } => write!(
+                f,
+                "Truncated data: needed {} more bytes, {} available",
+                needed, available
+            ),
+            FormatError::TrailingBytes(count) => {
+                write!(f, "Corrupt data: {} unexpected trailing bytes", count)
+            }
+            FormatError::ChecksumMismatch { expected, found } => write!(
+                f,
+                "Corrupt data: checksum {:08x} doesn't match stored {:08x}",
+                found, expected
+            ),
+            FormatError::Invalid(reason) => write!(f, "Corrupt data: {}", reason),
+        }
+    }
+}
+
+/// Bounds-checked little-endian reads over a byte slice.
+pub struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8], FormatError> {
+        if len > self.remaining() {
+            return Err(FormatError::Truncated {
+                needed: len,
+                available: self.remaining(),
+            });
+        }
+        let bytes = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, FormatError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, FormatError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, FormatError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, FormatError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// Reads a `u32` length prefix followed by that many bytes.
+    pub fn prefixed(&mut self) -> Result<&'a [u8], FormatError> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    /// Fails if anything is left unread.
+    pub fn finish(&self) -> Result<(), FormatError> {
+        match self.remaining() {
+            0 => Ok(()),
+            count => Err(FormatError::TrailingBytes(count)),
+        }
+    }
+}
+
+fn check_version(version: u16) -> Result<(), FormatError> {
+    if version > FORMAT_VERSION {
+        return Err(FormatError::UnsupportedVersion {
+            found: version,
+            supported: FORMAT_VERSION,
+        });
+    }
+    Ok(())
+}
+
+fn check_crc(data: &[u8], expected: u32) -> Result<(), FormatError> {
+    let found = crc32fast::hash(data);
+    if found != expected {
+        return Err(FormatError::ChecksumMismatch { expected, found });
+    }
+    Ok(())
+}
+
+/// Wraps a model's serialized bytes in the file header.
+pub fn encode_file(payload: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(20 + payload.len());
+    bytes.extend_from_slice(FILE_MAGIC);
+    bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&0u16.to_le_bytes());
+    bytes.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&crc32fast::hash(payload).to_le_bytes());
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+/// Checks a model file's header and checksum and returns its payload.
+pub fn decode_file(bytes: &[u8]) -> Result<&[u8], FormatError> {
+    let (payload, crc) = split_file(bytes)?;
+    if let Some(crc) = crc {
+        check_crc(payload, crc)?;
+    }
+    Ok(payload)
+}
+
+/// Checks a model file's header and returns its payload along with the payload's checksum,
+/// which legacy files don't have, without reading the payload itself.
+pub(crate) fn split_file(bytes: &[u8]) -> Result<(&[u8], Option<u32>), FormatError> {
+    let mut reader = ByteReader::new(bytes);
+    let magic = reader.take(4).map_err(|_| FormatError::BadMagic)?;
+
+    if magic == LEGACY_FILE_MAGIC {
+        let len = reader.u64()? as usize;
+        let payload = reader.take(len)?;
+        reader.finish()?;
+        return Ok((payload, None));
+    }
+    if magic != FILE_MAGIC {
+        return Err(FormatError::BadMagic);
+    }
+
+    check_version(reader.u16()?)?;
+    reader.u16()?;
+    let len = reader.u64()? as usize;
+    let crc = reader.u32()?;
+    let payload = reader.take(len)?;
+    reader.finish()?;
+    Ok((payload, Some(crc)))
+}
+
+/// Writes `value` to `writer` in the file container, without holding its serialized bytes
+/// in memory: a first pass only measures and checksums them, a second writes them out.
+pub fn write_file<W: Write, T: Serialize + ?Sized>(mut writer: W, value: &T) -> MlResult<()> {
+    let mut measure = ChecksumWriter::new(std::io::sink());
+    value.serialize_into(&mut measure)?;
+
+    let mut header = Vec::with_capacity(20);
+    header.extend_from_slice(FILE_MAGIC);
+    header.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    header.extend_from_slice(&0u16.to_le_bytes());
+    header.extend_from_slice(&measure.len.to_le_bytes());
+    header.extend_from_slice(&measure.hasher.finalize().to_le_bytes());
+    write_all(&mut writer, &header)?;
+
+    let mut check = ChecksumWriter::new(&mut writer);
+    value.serialize_into(&mut check)?;
+    if check.len != measure.len {
+        return Err(FormatError::Invalid("value changed size while being written".into()).into());
+    }
+    writer
+        .flush()
+        .map_err(|e| format!("Failed to write data: {}", e).into())
+}
+
+/// Reads a value written by [`write_file`] (or an older `SPN1` file) from `reader`, consuming
+/// exactly the file's bytes. A payload that fails its checksum is reported as corrupt even
+/// if the value itself could not be decoded.
+pub fn read_file<R: Read, T: Deserialize>(reader: R) -> MlResult<T> {
+    read_file_with(reader, |payload| T::deserialize_from(payload))
+}
+
+/// [`read_file`] with the payload read by `read` rather than a [`Deserialize`] impl.
+pub(crate) fn read_file_with<R: Read, T>(
+    mut reader: R,
+    read: impl FnOnce(&mut dyn Read) -> MlResult<T>,
+) -> MlResult<T> {
+    let mut magic = [0; 4];
+    read_exact(&mut reader, &mut magic).map_err(|_| FormatError::BadMagic)?;
+
+    let (len, crc) = if &magic == LEGACY_FILE_MAGIC {
+        (read_u64(&mut reader)?, None)
+    } else if &magic == FILE_MAGIC {
+        let mut version = [0; 4];
+        read_exact(&mut reader, &mut version)?;
+        check_version(u16::from_le_bytes([version[0], version[1]]))?;
+        let len = read_u64(&mut reader)?;
+        let mut crc = [0; 4];
+        read_exact(&mut reader, &mut crc)?;
+        (len, Some(u32::from_le_bytes(crc)))
+    } else {
+        return Err(FormatError::BadMagic.into());
+    };
+
+    let mut payload = ChecksumReader::new(reader.take(len));
+    let value = read(&mut payload);
+    let trailing = std::io::copy(&mut payload, &mut std::io::sink())
+        .map_err(|e| format!("Failed to read data: {}", e))?;
+
+    if payload.len < len {
+        return Err(FormatError::Truncated {
+            needed: len as usize,
+            available: payload.len as usize,
+        }
+        .into());
+    }
+    if let Some(expected) = crc {
+        let found = payload.hasher.finalize();
+        if found != expected {
+            return Err(FormatError::ChecksumMismatch { expected, found }.into());
+        }
+    }
+    let value = value?;
+    if trailing > 0 {
+        return Err(FormatError::TrailingBytes(trailing as usize).into());
+    }
+    Ok(value)
+}
+
+/// Counts and checksums the bytes written through it.
+pub(crate) struct ChecksumWriter<W> {
+    inner: W,
+    hasher: crc32fast::Hasher,
+    len: u64,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: crc32fast::Hasher::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.len += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Counts and checksums the bytes read through it.
+pub(crate) struct ChecksumReader<R> {
+    inner: R,
+    hasher: crc32fast::Hasher,
+    len: u64,
+}
+
+impl<R: Read> ChecksumReader<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: crc32fast::Hasher::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        self.len += read as u64;
+        Ok(read)
+    }
+}
+
+pub(crate) fn write_all<W: Write>(mut writer: W, bytes: &[u8]) -> MlResult<()> {
+    writer
+        .write_all(bytes)
+        .map_err(|e| format!("Failed to write data: {}", e).into())
+}
+
+pub(crate) fn read_to_end<R: Read>(mut reader: R) -> MlResult<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("Failed to read data: {}", e))?;
+    Ok(bytes)
+}
+
+/// Fills `buf`, reporting a stream that ends first as [`FormatError::Truncated`].
+pub(crate) fn read_exact<R: Read>(mut reader: R, buf: &mut [u8]) -> MlResult<()> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => {
+                return Err(FormatError::Truncated {
+                    needed: buf.len(),
+                    available: filled,
+                }
+                .into())
+            }
+            Ok(read) => filled += read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(format!("Failed to read data: {}", e).into()),
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn read_u32<R: Read>(reader: R) -> MlResult<u32> {
+    let mut bytes = [0; 4];
+    read_exact(reader, &mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+pub(crate) fn read_u64<R: Read>(reader: R) -> MlResult<u64> {
+    let mut bytes = [0; 8];
+    read_exact(reader, &mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn dtype_tag(dtype: DType) -> u8 {
+    match dtype {
+        DType::F32 => 0,
+        DType::F16 => 1,
+        DType::F64 => 2,
+    }
+}
+
+fn dtype_from_tag(tag: u8) -> Result<DType, FormatError> {
+    match tag {
+        0 => Ok(DType::F32),
+        1 => Ok(DType::F16),
+        2 => Ok(DType::F64),
+        _ => Err(FormatError::UnsupportedDtype(tag)),
+    }
+}
+
+/// Encodes a tensor record holding f32 `data` of the given `shape`.
+pub fn encode_tensor(shape: &[usize], data: &[f32]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(tensor_record_len(shape));
+    let data: Vec<u8> = data.iter().flat_map(|value| value.to_le_bytes()).collect();
+    bytes.extend(tensor_header(shape, crc32fast::hash(&data)));
+    bytes.extend_from_slice(&data);
+    bytes
+}
+
+/// The size of the record [`encode_tensor`] writes for an f32 tensor of `shape`.
+pub fn tensor_record_len(shape: &[usize]) -> usize {
+    16 + 8 * shape.len() + 4 * shape.iter().product::<usize>()
+}
+
+fn tensor_header(shape: &[usize], crc: u32) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(16 + 8 * shape.len());
+    bytes.extend_from_slice(TENSOR_MAGIC);
+    bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    bytes.push(dtype_tag(DType::F32));
+    // Elements are little-endian
+    bytes.push(0);
+    bytes.extend_from_slice(&(shape.len() as u32).to_le_bytes());
+    for &dim in shape {
+        bytes.extend_from_slice(&(dim as u64).to_le_bytes());
+    }
+    bytes.extend_from_slice(&crc.to_le_bytes());
+    bytes
+}
+
+// Elements are converted to bytes this many at a time when streaming
+const STREAM_CHUNK: usize = 16 * 1024;
+
+/// Writes the same record as [`encode_tensor`] to `writer`, a chunk at a time, so the
+/// tensor's bytes never exist as one buffer.
+pub fn write_tensor<W: Write>(mut writer: W, shape: &[usize], data: &[f32]) -> MlResult<()> {
+    let mut hasher = crc32fast::Hasher::new();
+    let mut chunk = Vec::with_capacity(4 * STREAM_CHUNK.min(data.len()));
+    for values in data.chunks(STREAM_CHUNK) {
+        chunk.clear();
+        chunk.extend(values.iter().flat_map(|value| value.to_le_bytes()));
+        hasher.update(&chunk);
+    }
+
+    write_all(&mut writer, &tensor_header(shape, hasher.finalize()))?;
+    for values in data.chunks(STREAM_CHUNK) {
+        chunk.clear();
+        chunk.extend(values.iter().flat_map(|value| value.to_le_bytes()));
+        write_all(&mut writer, &chunk)?;
+    }
+    Ok(())
+}
+
+/// The order of the bytes within each element of a tensor record. Headers and lengths are
+/// always little-endian; records declare the order of their elements, so data produced on
+/// a big-endian machine can be stored without converting it first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+    Little,
+    Big,
+}
+
+impl ByteOrder {
+    fn from_tag(tag: u8) -> Result<Self, FormatError> {
+        match tag {
+            0 => Ok(ByteOrder::Little),
+            1 => Ok(ByteOrder::Big),
+            _ => Err(FormatError::UnsupportedByteOrder(tag)),
+        }
+    }
+}
+
+struct RecordHeader {
+    shape: Vec<usize>,
+    dtype: DType,
+    order: ByteOrder,
+    /// Version 1 records have no checksum.
+    crc: Option<u32>,
+}
+
+// Reads a tensor record's header, or the rank and dimensions of a version 1 record: a u32
+// rank, u32 dimensions and little-endian f32 elements.
+fn read_header<R: Read>(mut reader: R) -> MlResult<RecordHeader> {
+    let mut magic = [0; 4];
+    read_exact(&mut reader, &mut magic)?;
+
+    if &magic != TENSOR_MAGIC {
+        let ndim = u32::from_le_bytes(magic) as usize;
+        let mut shape = Vec::with_capacity(ndim.min(64));
+        for _ in 0..ndim {
+            shape.push(read_u32(&mut reader)? as usize);
+        }
+        return Ok(RecordHeader {
+            shape,
+            dtype: DType::F32,
+            order: ByteOrder::Little,
+            crc: None,
+        });
+    }
+
+    let mut header = [0; 8];
+    read_exact(&mut reader, &mut header)?;
+    check_version(u16::from_le_bytes([header[0], header[1]]))?;
+    let dtype = dtype_from_tag(header[2])?;
+    let order = ByteOrder::from_tag(header[3])?;
+    let ndim = u32::from_le_bytes(header[4..8].try_into().unwrap()) as usize;
+
+    let mut shape = Vec::with_capacity(ndim.min(64));
+    for _ in 0..ndim {
+        shape.push(read_u64(&mut reader)? as usize);
+    }
+    let crc = read_u32(&mut reader)?;
+    Ok(RecordHeader {
+        shape,
+        dtype,
+        order,
+        crc: Some(crc),
+    })
+}
+
+// Checks that `available` bytes of elements are exactly what the header's shape calls for.
+fn check_data_length(header: &RecordHeader, available: usize) -> MlResult<usize> {
+    let size = header.dtype.size_in_bytes();
+    let needed = element_bytes(&header.shape, size)?;
+    if available < needed {
+        return Err(TensorError::InvalidDataLength {
+            expected: needed / size,
+            got: available / size,
+        }
+        .into());
+    }
+    if available > needed {
+        return Err(FormatError::TrailingBytes(available - needed).into());
+    }
+    Ok(needed)
+}
+
+/// Decodes a tensor record, or the unversioned layout older releases wrote, into its shape
+/// and elements converted to f32. The record must fill `bytes` exactly: elements missing for
+/// the shape are a [`TensorError::InvalidDataLength`], extra bytes
+/// [`FormatError::TrailingBytes`].
+pub fn decode_tensor(bytes: &[u8]) -> MlResult<(Vec<usize>, Vec<f32>)> {
+    let mut data = bytes;
+    let header = read_header(&mut data)?;
+    check_data_length(&header, data.len())?;
+    if let Some(crc) = header.crc {
+        check_crc(data, crc)?;
+    }
+
+    let mut values = Vec::with_capacity(data.len() / header.dtype.size_in_bytes());
+    decode_values(header.dtype, header.order, data, &mut values);
+    Ok((header.shape, values))
+}
+
+fn decode_values(dtype: DType, order: ByteOrder, data: &[u8], out: &mut Vec<f32>) {
+    let values = data.chunks_exact(dtype.size_in_bytes());
+    macro_rules! read {
+        ($ty:ty, $v:expr) => {
+            match order {
+                ByteOrder::Little => <$ty>::from_le_bytes($v.try_into().unwrap()),
+                ByteOrder::Big => <$ty>::from_be_bytes($v.try_into().unwrap()),
+            }
+        };
+    }
+    match dtype {
+        DType::F32 => out.extend(values.map(|v| read!(f32, v))),
+        DType::F16 => out.extend(values.map(|v| f16_to_f32(read!(u16, v)))),
+        DType::F64 => out.extend(values.map(|v| read!(f64, v) as f32)),
+    }
+}
+
+/// Reads one tensor record, or the unversioned layout older releases wrote, from `reader`,
+/// consuming exactly the record's bytes. A stream that ends among the elements is a
+/// [`TensorError::InvalidDataLength`].
+pub fn read_tensor<R: Read>(mut reader: R) -> MlResult<(Vec<usize>, Vec<f32>)> {
+    let header = read_header(&mut reader)?;
+
+    let size = header.dtype.size_in_bytes();
+    let needed = element_bytes(&header.shape, size)?;
+    let mut remaining = needed;
+    let mut values = Vec::with_capacity((remaining / size).min(STREAM_CHUNK * 64));
+    let mut hasher = crc32fast::Hasher::new();
+    let mut chunk = vec![0; (STREAM_CHUNK * size).min(remaining)];
+    while remaining > 0 {
+        let chunk = &mut chunk[..remaining.min(STREAM_CHUNK * size)];
+        if let Err(e) = read_exact(&mut reader, chunk) {
+            return match e {
+                MlError::FormatError(FormatError::Truncated { available, .. }) => {
+                    Err(TensorError::InvalidDataLength {
+                        expected: needed / size,
+                        got: (needed - remaining + available) / size,
+                    }
+                    .into())
+                }
+                e => Err(e),
+            };
+        }
+        hasher.update(chunk);
+        decode_values(header.dtype, header.order, chunk, &mut values);
+        remaining -= chunk.len();
+    }
+
+    if let Some(expected) = header.crc {
+        let found = hasher.finalize();
+        if found != expected {
+            return Err(FormatError::ChecksumMismatch { expected, found }.into());
+        }
+    }
+    Ok((header.shape, values))
+}
+
+/// Reads only the shape from a tensor record's header, checking that the record holds
+/// exactly the elements the shape calls for.
+#[cfg_attr(not(feature = "fs"), allow(dead_code))]
+pub(crate) fn tensor_shape(bytes: &[u8]) -> MlResult<Vec<usize>> {
+    let mut data = bytes;
+    let header = read_header(&mut data)?;
+    check_data_length(&header, data.len())?;
+    Ok(header.shape)
+}
+
+/// The fields of a quantized tensor record, with the values already packed into `data`.
+pub(crate) struct QuantizedRecord {
+    pub int4: bool,
+    pub shape: Vec<usize>,
+    /// The channel axis, or `None` for a single scale and zero point.
+    pub axis: Option<usize>,
+    pub scales: Vec<f32>,
+    pub zero_points: Vec<i32>,
+    pub data: Vec<u8>,
+}
+
+impl QuantizedRecord {
+    fn data_len(int4: bool, shape: &[usize]) -> Result<usize, FormatError> {
+        let count = element_bytes(shape, 1)?;
+        Ok(if int4 { count.div_ceil(2) } else { count })
+    }
+}
+
+/// Whether `bytes` start a quantized tensor record rather than a float one.
+pub(crate) fn is_quantized(bytes: &[u8]) -> bool {
+    bytes.starts_with(QUANTIZED_MAGIC)
+}
+
+/// Encodes a quantized tensor record.
+pub(crate) fn encode_quantized(record: &QuantizedRecord) -> Vec<u8> {
+    let mut bytes = QUANTIZED_MAGIC.to_vec();
+    bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    bytes.push(record.int4 as u8);
+    bytes.push(record.axis.is_some() as u8);
+    bytes.extend_from_slice(&(record.shape.len() as u32).to_le_bytes());
+    for &dim in &record.shape {
+        bytes.extend_from_slice(&(dim as u64).to_le_bytes());
+    }
+    if let Some(axis) = record.axis {
+        bytes.extend_from_slice(&(axis as u32).to_le_bytes());
+    }
+    for scale in &record.scales {
+        bytes.extend_from_slice(&scale.to_le_bytes());
+    }
+    for zero_point in &record.zero_points {
+        bytes.extend_from_slice(&zero_point.to_le_bytes());
+    }
+    bytes.extend_from_slice(&crc32fast::hash(&record.data).to_le_bytes());
+    bytes.extend_from_slice(&record.data);
+    bytes
+}
+
+/// Decodes a quantized tensor record, which must fill `bytes` exactly. The values are left
+/// packed; whether they fit their dtype is up to the caller.
+pub(crate) fn decode_quantized(bytes: &[u8]) -> MlResult<QuantizedRecord> {
+    let mut reader = ByteReader::new(bytes);
+    let (mut record, crc) = read_quantized_header(&mut reader)?;
+    let data = reader.take(reader.remaining())?;
+    check_quantized_length(&record, data.len())?;
+    check_crc(data, crc)?;
+    record.data = data.to_vec();
+    Ok(record)
+}
+
+/// Reads only the shape from a quantized record's header, checking that the record holds
+/// exactly the values the shape calls for.
+#[cfg_attr(not(feature = "fs"), allow(dead_code))]
+pub(crate) fn quantized_shape(bytes: &[u8]) -> MlResult<Vec<usize>> {
+    let mut reader = ByteReader::new(bytes);
+    let (record, _) = read_quantized_header(&mut reader)?;
+    check_quantized_length(&record, reader.remaining())?;
+    Ok(record.shape)
+}
+
+// Reads everything before a quantized record's values, returning it with no data and the
+// stored checksum.
+fn read_quantized_header(reader: &mut ByteReader) -> MlResult<(QuantizedRecord, u32)> {
+    if reader.take(4)? != QUANTIZED_MAGIC {
+        return Err(FormatError::BadMagic.into());
+    }
+    check_version(reader.u16()?)?;
+    let int4 = match reader.u8()? {
+        0 => false,
+        1 => true,
+        tag => return Err(FormatError::UnsupportedDtype(tag).into()),
+    };
+    let per_channel = match reader.u8()? {
+        0 => false,
+        1 => true,
+        tag => {
+            return Err(FormatError::Invalid(format!("unknown quantization scheme {}", tag)).into())
+        }
+    };
+
+    let ndim = reader.u32()? as usize;
+    let mut shape = Vec::with_capacity(ndim.min(64));
+    for _ in 0..ndim {
+        shape.push(reader.u64()? as usize);
+    }
+    let axis = if per_channel {
+        let axis = reader.u32()? as usize;
+        if axis >= shape.len() {
+            return Err(TensorError::InvalidAxis { axis, shape }.into());
+        }
+        Some(axis)
+    } else {
+        None
+    };
+
+    let channels = axis.map_or(1, |axis| shape[axis]);
+    if channels > reader.remaining() / 8 {
+        return Err(FormatError::Truncated {
+            needed: 8 * channels,
+            available: reader.remaining(),
+        }
+        .into());
+    }
+    let mut scales = Vec::with_capacity(channels);
+    for _ in 0..channels {
+        scales.push(f32::from_bits(reader.u32()?));
+    }
+    let mut zero_points = Vec::with_capacity(channels);
+    for _ in 0..channels {
+        zero_points.push(reader.u32()? as i32);
+    }
+    let crc = reader.u32()?;
+
+    let record = QuantizedRecord {
+        int4,
+        shape,
+        axis,
+        scales,
+        zero_points,
+        data: Vec::new(),
+    };
+    Ok((record, crc))
+}
+
+fn check_quantized_length(record: &QuantizedRecord, available: usize) -> MlResult<()> {
+    let needed = QuantizedRecord::data_len(record.int4, &record.shape)?;
+    if available < needed {
+        return Err(TensorError::InvalidDataLength {
+            expected: needed,
+            got: available,
+        }
+        .into());
+    }
+    if available > needed {
+        return Err(FormatError::TrailingBytes(available - needed).into());
+    }
+    Ok(())
+}
+
+fn element_bytes(shape: &[usize], size: usize) -> Result<usize, FormatError> {
+    shape
+        .iter()
+        .try_fold(size, |total, &dim| total.checked_mul(dim))
+        .ok_or_else(|| FormatError::Invalid(format!("shape {:?} is too large", shape)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tensor_record_detects_corruption() {
+        let bytes = encode_tensor(&[2, 2], &[1.0, -2.0, 3.5, 4.0]);
+        assert_eq!(
+            decode_tensor(&bytes).unwrap(),
+            (vec![2, 2], vec![1.0, -2.0, 3.5, 4.0])
+        );
+
+        assert!(matches!(
+            decode_tensor(&bytes[..bytes.len() - 3]),
+            Err(MlError::TensorError(TensorError::InvalidDataLength {
+                expected: 4,
+                got: 3
+            }))
+        ));
+
+        let mut flipped = bytes.clone();
+        *flipped.last_mut().unwrap() ^= 0x40;
+        assert!(matches!(
+            decode_tensor(&flipped),
+            Err(MlError::FormatError(FormatError::ChecksumMismatch { .. }))
+        ));
+
+        let mut future = bytes.clone();
+        future[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            decode_tensor(&future),
+            Err(MlError::FormatError(FormatError::UnsupportedVersion {
+                found,
+                supported: FORMAT_VERSION
+            })) if found == FORMAT_VERSION + 1
+        ));
+
+        let mut unknown_dtype = bytes;
+        unknown_dtype[6] = 9;
+        assert!(matches!(
+            decode_tensor(&unknown_dtype),
+            Err(MlError::FormatError(FormatError::UnsupportedDtype(9)))
+        ));
+    }
+
+    #[test]
+    fn test_tensor_record_validates_layout() {
+        let bytes = encode_tensor(&[3], &[1.0, 2.0, 3.0]);
+
+        // Garbage after the elements is an error, not silently dropped
+        let mut padded = bytes.clone();
+        padded.extend_from_slice(&[0; 5]);
+        assert!(matches!(
+            decode_tensor(&padded),
+            Err(MlError::FormatError(FormatError::TrailingBytes(5)))
+        ));
+        assert!(matches!(
+            tensor_shape(&padded),
+            Err(MlError::FormatError(FormatError::TrailingBytes(5)))
+        ));
+
+        // A short stream yields no tensor rather than a smaller one
+        assert!(matches!(
+            read_tensor(&bytes[..bytes.len() - 4]),
+            Err(MlError::TensorError(TensorError::InvalidDataLength {
+                expected: 3,
+                got: 2
+            }))
+        ));
+
+        // Big-endian elements are read back as written
+        let values = [1.0f32, -2.5, 3.25];
+        let data: Vec<u8> = values.iter().flat_map(|v| v.to_be_bytes()).collect();
+        let mut big = tensor_header(&[3], crc32fast::hash(&data));
+        big[7] = 1;
+        big.extend_from_slice(&data);
+        assert_eq!(decode_tensor(&big).unwrap(), (vec![3], values.to_vec()));
+        assert_eq!(read_tensor(&big[..]).unwrap(), (vec![3], values.to_vec()));
+
+        big[7] = 2;
+        assert!(matches!(
+            decode_tensor(&big),
+            Err(MlError::FormatError(FormatError::UnsupportedByteOrder(2)))
+        ));
+    }
+
+    #[test]
+    fn test_legacy_tensors_and_files() {
+        // Version 1 tensor: rank, dimensions and data with no header
+        let mut legacy = Vec::new();
+        for word in [1u32, 2] {
+            legacy.extend_from_slice(&word.to_le_bytes());
+        }
+        legacy.extend_from_slice(&1.5f32.to_le_bytes());
+        legacy.extend_from_slice(&2.5f32.to_le_bytes());
+        assert_eq!(decode_tensor(&legacy).unwrap(), (vec![2], vec![1.5, 2.5]));
+        assert!(matches!(
+            decode_tensor(&legacy[..10]),
+            Err(MlError::TensorError(TensorError::InvalidDataLength {
+                expected: 2,
+                got: 0
+            }))
+        ));
+
+        let mut file = LEGACY_FILE_MAGIC.to_vec();
+        file.extend_from_slice(&(legacy.len() as u64).to_le_bytes());
+        file.extend_from_slice(&legacy);
+        assert_eq!(decode_file(&file), Ok(&legacy[..]));
+
+        let file = encode_file(&legacy);
+        assert_eq!(decode_file(&file), Ok(&legacy[..]));
+        assert!(matches!(
+            decode_file(&file[..file.len() - 1]),
+            Err(FormatError::Truncated { .. })
+        ));
+        assert_eq!(decode_file(b"NOPE1234"), Err(FormatError::BadMagic));
+    }
+}
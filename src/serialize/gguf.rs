@@ -0,0 +1,720 @@
+//! Reading GGUF files, the checkpoint format of llama.cpp and the rest of the GGML ecosystem.
+//!
+//! A GGUF file is a header, a table of typed metadata, a table of tensor descriptions and
+//! then the aligned tensor data. The file is memory mapped and tensors are only read when
+//! asked for; quantized tensors are dequantized to f32 then, as are f16, bf16 and f64 ones.
+//!
+//! GGUF lists dimensions innermost first. Shapes here are in cetana's row-major order, so a
+//! GGUF `[n_embd, n_vocab]` embedding comes out as `[n_vocab, n_embd]`.
+
+use std::fs::File;
+use std::path::Path;
+
+use super::format::{ByteReader, FormatError};
+use super::mmap::Mmap;
+use super::npy::f16_to_f32;
+use super::state_dict::match_keys;
+use super::{LoadReport, StateDict};
+use crate::nn::Layer;
+use crate::tensor::Tensor;
+use crate::MlResult;
+
+const GGUF_MAGIC: &[u8; 4] = b"GGUF";
+const DEFAULT_ALIGNMENT: u64 = 32;
+
+/// A metadata value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GgufValue {
+    U8(u8),
+    I8(i8),
+    U16(u16),
+    I16(i16),
+    U32(u32),
+    I32(i32),
+    U64(u64),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    Bool(bool),
+    String(String),
+    Array(Vec<GgufValue>),
+}
+
+impl GgufValue {
+    /// The value as an unsigned integer, if it is a non-negative integer of any width.
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            GgufValue::U8(v) => Some(v as u64),
+            GgufValue::U16(v) => Some(v as u64),
+            GgufValue::U32(v) => Some(v as u64),
+            GgufValue::U64(v) => Some(v),
+            GgufValue::I8(v) => u64::try_from(v).ok(),
+            GgufValue::I16(v) => u64::try_from(v).ok(),
+            GgufValue::I32(v) => u64::try_from(v).ok(),
+            GgufValue::I64(v) => u64::try_from(v).ok(),
+            _ => None,
+        }
+    }
+
+    /// The value as a float, if it is a number.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            GgufValue::F32(v) => Some(v as f64),
+            GgufValue::F64(v) => Some(v),
+            GgufValue::I8(v) => Some(v as f64),
+            GgufValue::I16(v) => Some(v as f64),
+            GgufValue::I32(v) => Some(v as f64),
+            GgufValue::I64(v) => Some(v as f64),
+            _ => self.as_u64().map(|v| v as f64),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            GgufValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[GgufValue]> {
+        match self {
+            GgufValue::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+/// The GGML element and block formats this reader can decode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GgmlType {
+    F32,
+    F16,
+    BF16,
+    F64,
+    Q4_0,
+    Q4_1,
+    Q5_0,
+    Q5_1,
+    Q8_0,
+    Q8_1,
+    Q2K,
+    Q3K,
+    Q4K,
+    Q5K,
+    Q6K,
+    Q8K,
+}
+
+impl GgmlType {
+    fn from_id(id: u32) -> Result<Self, FormatError> {
+        Ok(match id {
+            0 => GgmlType::F32,
+            1 => GgmlType::F16,
+            2 => GgmlType::Q4_0,
+            3 => GgmlType::Q4_1,
+            6 => GgmlType::Q5_0,
+            7 => GgmlType::Q5_1,
+            8 => GgmlType::Q8_0,
+            9 => GgmlType::Q8_1,
+            10 => GgmlType::Q2K,
+            11 => GgmlType::Q3K,
+            12 => GgmlType::Q4K,
+            13 => GgmlType::Q5K,
+            14 => GgmlType::Q6K,
+            15 => GgmlType::Q8K,
+            28 => GgmlType::F64,
+            30 => GgmlType::BF16,
+            _ => {
+                return Err(FormatError::Invalid(format!(
+                    "unsupported GGML type {}",
+                    id
+                )))
+            }
+        })
+    }
+
+    /// Elements per block.
+    pub fn block_size(self) -> usize {
+        match self {
+            GgmlType::F32 | GgmlType::F16 | GgmlType::BF16 | GgmlType::F64 => 1,
+            GgmlType::Q4_0
+            | GgmlType::Q4_1
+            | GgmlType::Q5_0
+            | GgmlType::Q5_1
+            | GgmlType::Q8_0
+            | GgmlType::Q8_1 => 32,
+            GgmlType::Q2K | GgmlType::Q3K | GgmlType::Q4K | GgmlType::Q5K | GgmlType::Q6K => 256,
+            GgmlType::Q8K => 256,
+        }
+    }
+
+    /// Bytes per block.
+    pub fn block_bytes(self) -> usize {
+        match self {
+            GgmlType::F32 => 4,
+            GgmlType::F16 | GgmlType::BF16 => 2,
+            GgmlType::F64 => 8,
+            GgmlType::Q4_0 => 18,
+            GgmlType::Q4_1 => 20,
+            GgmlType::Q5_0 => 22,
+            GgmlType::Q5_1 => 24,
+            GgmlType::Q8_0 => 34,
+            GgmlType::Q8_1 => 36,
+            GgmlType::Q2K => 84,
+            GgmlType::Q3K => 110,
+            GgmlType::Q4K => 144,
+            GgmlType::Q5K => 176,
+            GgmlType::Q6K => 210,
+            GgmlType::Q8K => 292,
+        }
+    }
+}
+
+/// Where a tensor is in the file and how it is stored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GgufTensorInfo {
+    pub name: String,
+    /// Row-major, outermost dimension first.
+    pub shape: Vec<usize>,
+    pub dtype: GgmlType,
+    /// Offset from the start of the tensor data section.
+    pub offset: u64,
+}
+
+impl GgufTensorInfo {
+    fn data_len(&self) -> Result<usize, FormatError> {
+        let elements = self
+            .shape
+            .iter()
+            .try_fold(1usize, |total, &dim| total.checked_mul(dim))
+            .ok_or_else(|| FormatError::Invalid(format!("{} is too large", self.name)))?;
+        let block = self.dtype.block_size();
+        if self.shape.last().is_some_and(|&inner| inner % block != 0) {
+            return Err(FormatError::Invalid(format!(
+                "{} rows don't divide into {:?} blocks",
+                self.name, self.dtype
+            )));
+        }
+        Ok(elements / block * self.dtype.block_bytes())
+    }
+}
+
+/// An open GGUF file.
+pub struct GgufFile {
+    map: Mmap,
+    version: u32,
+    metadata: Vec<(String, GgufValue)>,
+    tensors: Vec<GgufTensorInfo>,
+    data_start: usize,
+}
+
+impl GgufFile {
+    /// Maps the file at `path` and reads its metadata and tensor table.
+    pub fn open<P: AsRef<Path>>(path: P) -> MlResult<Self> {
+        let file = File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
+        let map = Mmap::open(&file).map_err(|e| format!("Failed to map file: {}", e))?;
+
+        let bytes = map.as_slice();
+        let mut reader = ByteReader::new(bytes);
+        if reader.take(4).map_err(|_| FormatError::BadMagic)? != GGUF_MAGIC {
+            return Err(FormatError::BadMagic.into());
+        }
+        let version = reader.u32()?;
+        if version > 3 && version.swap_bytes() <= 3 {
+            return Err(
+                FormatError::Invalid("big-endian GGUF files are not supported".into()).into(),
+            );
+        }
+        if !(2..=3).contains(&version) {
+            return Err(
+                FormatError::Invalid(format!("unsupported GGUF version {}", version)).into(),
+            );
+        }
+
+        let tensor_count = reader.u64()? as usize;
+        let metadata_count = reader.u64()? as usize;
+
+        let mut metadata = Vec::with_capacity(metadata_count.min(reader.remaining() / 13));
+        for _ in 0..metadata_count {
+            let key = read_string(&mut reader)?;
+            let kind = reader.u32()?;
+            metadata.push((key, read_value(&mut reader, kind)?));
+        }
+
+        let mut tensors = Vec::with_capacity(tensor_count.min(reader.remaining() / 24));
+        for _ in 0..tensor_count {
+            let name = read_string(&mut reader)?;
+            let ndim = reader.u32()? as usize;
+            let mut shape = Vec::with_capacity(ndim.min(reader.remaining() / 8));
+            for _ in 0..ndim {
+                shape.push(reader.u64()? as usize);
+            }
+            shape.reverse();
+            let dtype = GgmlType::from_id(reader.u32()?)?;
+            let offset = reader.u64()?;
+            tensors.push(GgufTensorInfo {
+                name,
+                shape,
+                dtype,
+                offset,
+            });
+        }
+
+        let alignment = metadata
+            .iter()
+            .find(|(key, _)| key == "general.alignment")
+            .and_then(|(_, value)| value.as_u64())
+            .unwrap_or(DEFAULT_ALIGNMENT)
+            .max(1) as usize;
+        let data_start = (bytes.len() - reader.remaining()).next_multiple_of(alignment);
+
+        // Check every tensor lies inside the file now, so reads can't fail on that later
+        for info in &tensors {
+            let end = (info.offset as usize)
+                .checked_add(info.data_len()?)
+                .and_then(|end| end.checked_add(data_start));
+            if end.is_none_or(|end| end > bytes.len()) {
+                return Err(FormatError::Truncated {
+                    needed: end.unwrap_or(usize::MAX),
+                    available: bytes.len(),
+                }
+                .into());
+            }
+        }
+
+        Ok(Self {
+            map,
+            version,
+            metadata,
+            tensors,
+            data_start,
+        })
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// All metadata, in file order.
+    pub fn metadata(&self) -> &[(String, GgufValue)] {
+        &self.metadata
+    }
+
+    pub fn get_metadata(&self, key: &str) -> Option<&GgufValue> {
+        self.metadata
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value)
+    }
+
+    /// The tensor table, in file order.
+    pub fn tensors(&self) -> &[GgufTensorInfo] {
+        &self.tensors
+    }
+
+    /// Reads tensor `name`, dequantized to f32.
+    pub fn tensor(&self, name: &str) -> MlResult<Tensor> {
+        let info = self
+            .tensors
+            .iter()
+            .find(|info| info.name == name)
+            .ok_or_else(|| format!("No tensor named {}", name))?;
+
+        let start = self.data_start + info.offset as usize;
+        let data = &self.map.as_slice()[start..start + info.data_len()?];
+        Tensor::from_vec(dequantize(info.dtype, data), &info.shape)
+    }
+
+    /// Reads every tensor into a state dict under its GGUF name.
+    pub fn to_state_dict(&self) -> MlResult<StateDict> {
+        self.tensors
+            .iter()
+            .map(|info| Ok((info.name.clone(), self.tensor(&info.name)?)))
+            .collect::<MlResult<Vec<_>>>()
+            .map(StateDict::from_iter)
+    }
+
+    /// Loads the tensors whose names match `layer`'s parameters, one at a time, with the
+    /// same key and shape checks as [`Layer::load_state_dict`].
+    pub fn load_into<L: Layer + ?Sized>(
+        &self,
+        layer: &mut L,
+        strict: bool,
+    ) -> MlResult<LoadReport> {
+        let params = layer.named_parameters_mut();
+        let wanted: Vec<_> = params
+            .iter()
+            .map(|(n, p)| (n.as_str(), p.shape()))
+            .collect();
+        let offered: Vec<_> = self
+            .tensors
+            .iter()
+            .map(|info| (info.name.as_str(), info.shape.as_slice()))
+            .collect();
+        let report = match_keys(&wanted, &offered, strict)?;
+
+        for (name, param) in params {
+            if self.tensors.iter().any(|info| info.name == name) {
+                let tensor = self.tensor(&name)?;
+                *param = if tensor.device() == param.device() {
+                    tensor
+                } else {
+                    tensor.to_device(param.device())?
+                };
+            }
+        }
+        Ok(report)
+    }
+}
+
+fn read_string(reader: &mut ByteReader<'_>) -> Result<String, FormatError> {
+    let len = reader.u64()? as usize;
+    String::from_utf8(reader.take(len)?.to_vec())
+        .map_err(|_| FormatError::Invalid("string is not UTF-8".into()))
+}
+
+fn read_value(reader: &mut ByteReader<'_>, kind: u32) -> Result<GgufValue, FormatError> {
+    Ok(match kind {
+        0 => GgufValue::U8(reader.u8()?),
+        1 => GgufValue::I8(reader.u8()? as i8),
+        2 => GgufValue::U16(reader.u16()?),
+        3 => GgufValue::I16(reader.u16()? as i16),
+        4 => GgufValue::U32(reader.u32()?),
+        5 => GgufValue::I32(reader.u32()? as i32),
+        6 => GgufValue::F32(f32::from_bits(reader.u32()?)),
+        7 => GgufValue::Bool(reader.u8()? != 0),
+        8 => GgufValue::String(read_string(reader)?),
+        9 => {
+            let kind = reader.u32()?;
+            let len = reader.u64()? as usize;
+            let mut values = Vec::with_capacity(len.min(reader.remaining()));
+            for _ in 0..len {
+                values.push(read_value(reader, kind)?);
+            }
+            GgufValue::Array(values)
+        }
+        10 => GgufValue::U64(reader.u64()?),
+        11 => GgufValue::I64(reader.u64()? as i64),
+        12 => GgufValue::F64(f64::from_bits(reader.u64()?)),
+        _ => {
+            return Err(FormatError::Invalid(format!(
+                "unknown metadata type {}",
+                kind
+            )))
+        }
+    })
+}
+
+fn f16_at(bytes: &[u8], at: usize) -> f32 {
+    f16_to_f32(u16::from_le_bytes([bytes[at], bytes[at + 1]]))
+}
+
+/// Dequantizes `data`, a whole number of `dtype` blocks, to f32.
+pub fn dequantize(dtype: GgmlType, data: &[u8]) -> Vec<f32> {
+    let blocks = data.chunks_exact(dtype.block_bytes());
+    let mut out = Vec::with_capacity(blocks.len() * dtype.block_size());
+    for block in blocks {
+        match dtype {
+            GgmlType::F32 => out.push(f32::from_le_bytes(block.try_into().unwrap())),
+            GgmlType::F16 => out.push(f16_at(block, 0)),
+            GgmlType::BF16 => out.push(f32::from_bits(
+                (u16::from_le_bytes([block[0], block[1]]) as u32) << 16,
+            )),
+            GgmlType::F64 => out.push(f64::from_le_bytes(block.try_into().unwrap()) as f32),
+            GgmlType::Q4_0 => dequantize_q4_0(block, &mut out),
+            GgmlType::Q4_1 => dequantize_q4_1(block, &mut out),
+            GgmlType::Q5_0 => dequantize_q5(block, false, &mut out),
+            GgmlType::Q5_1 => dequantize_q5(block, true, &mut out),
+            GgmlType::Q8_0 | GgmlType::Q8_1 => {
+                let d = f16_at(block, 0);
+                let qs = &block[block.len() - 32..];
+                out.extend(qs.iter().map(|&q| q as i8 as f32 * d));
+            }
+            GgmlType::Q2K => dequantize_q2_k(block, &mut out),
+            GgmlType::Q3K => dequantize_q3_k(block, &mut out),
+            GgmlType::Q4K => dequantize_q4_k(block, &mut out),
+            GgmlType::Q5K => dequantize_q5_k(block, &mut out),
+            GgmlType::Q6K => dequantize_q6_k(block, &mut out),
+            GgmlType::Q8K => {
+                let d = f32::from_le_bytes(block[..4].try_into().unwrap());
+                out.extend(block[4..260].iter().map(|&q| q as i8 as f32 * d));
+            }
+        }
+    }
+    out
+}
+
+// { f16 d; u8 qs[16] }: low nibbles are the first 16 values, high nibbles the last 16
+fn dequantize_q4_0(block: &[u8], out: &mut Vec<f32>) {
+    let d = f16_at(block, 0);
+    let qs = &block[2..18];
+    out.extend(qs.iter().map(|&q| ((q & 0xf) as i32 - 8) as f32 * d));
+    out.extend(qs.iter().map(|&q| ((q >> 4) as i32 - 8) as f32 * d));
+}
+
+// { f16 d; f16 m; u8 qs[16] }
+fn dequantize_q4_1(block: &[u8], out: &mut Vec<f32>) {
+    let (d, m) = (f16_at(block, 0), f16_at(block, 2));
+    let qs = &block[4..20];
+    out.extend(qs.iter().map(|&q| (q & 0xf) as f32 * d + m));
+    out.extend(qs.iter().map(|&q| (q >> 4) as f32 * d + m));
+}
+
+// Q5_0 is { f16 d; u8 qh[4]; u8 qs[16] }, centred on 16. Q5_1 is { f16 d; f16 m; u8 qh[4];
+// u8 qs[16] }, offset by m. `qh` holds the fifth bit of each of the 32 values.
+fn dequantize_q5(block: &[u8], with_min: bool, out: &mut Vec<f32>) {
+    let d = f16_at(block, 0);
+    let (m, rest) = if with_min {
+        (f16_at(block, 2), &block[4..])
+    } else {
+        (-16.0 * d, &block[2..])
+    };
+    let qh = u32::from_le_bytes(rest[..4].try_into().unwrap());
+    let qs = &rest[4..20];
+
+    for (j, &q) in qs.iter().enumerate() {
+        let high = ((qh >> j) << 4) & 0x10;
+        out.push(((q & 0xf) as u32 | high) as f32 * d + m);
+    }
+    for (j, &q) in qs.iter().enumerate() {
+        let high = (qh >> (j + 12)) & 0x10;
+        out.push(((q >> 4) as u32 | high) as f32 * d + m);
+    }
+}
+
+// { u8 scales[16]; u8 qs[64]; f16 d; f16 dmin }: 16 groups of 16 two-bit values, each
+// group with a 4-bit scale and 4-bit min
+fn dequantize_q2_k(block: &[u8], out: &mut Vec<f32>) {
+    let scales = &block[..16];
+    let (d, min) = (f16_at(block, 80), f16_at(block, 82));
+
+    let mut is = 0;
+    for q in block[16..80].chunks_exact(32) {
+        for shift in (0..8).step_by(2) {
+            for half in q.chunks_exact(16) {
+                let sc = scales[is];
+                is += 1;
+                let (dl, ml) = (d * (sc & 0xf) as f32, min * (sc >> 4) as f32);
+                out.extend(half.iter().map(|&v| dl * ((v >> shift) & 3) as f32 - ml));
+            }
+        }
+    }
+}
+
+// { u8 hmask[32]; u8 qs[64]; u8 scales[12]; f16 d }: three-bit values whose high bit is in
+// `hmask`, with 16 six-bit signed scales packed into 12 bytes
+fn dequantize_q3_k(block: &[u8], out: &mut Vec<f32>) {
+    let hmask = &block[..32];
+    let d = f16_at(block, 108);
+
+    let packed = &block[96..108];
+    let mut aux = [0u32; 4];
+    for (word, bytes) in aux.iter_mut().zip(packed.chunks_exact(4)) {
+        *word = u32::from_le_bytes(bytes.try_into().unwrap());
+    }
+    const KMASK1: u32 = 0x0303_0303;
+    const KMASK2: u32 = 0x0f0f_0f0f;
+    let tmp = aux[2];
+    aux[2] = ((aux[0] >> 4) & KMASK2) | (((tmp >> 4) & KMASK1) << 4);
+    aux[3] = ((aux[1] >> 4) & KMASK2) | (((tmp >> 6) & KMASK1) << 4);
+    aux[0] = (aux[0] & KMASK2) | ((tmp & KMASK1) << 4);
+    aux[1] = (aux[1] & KMASK2) | (((tmp >> 2) & KMASK1) << 4);
+    let scales: Vec<i8> = aux
+        .iter()
+        .flat_map(|w| w.to_le_bytes())
+        .map(|b| b as i8)
+        .collect();
+
+    let mut is = 0;
+    let mut m = 1u8;
+    for q in block[32..96].chunks_exact(32) {
+        for shift in (0..8).step_by(2) {
+            for (half, hm) in q.chunks_exact(16).zip(hmask.chunks_exact(16)) {
+                let dl = d * (scales[is] as i32 - 32) as f32;
+                is += 1;
+                out.extend(half.iter().zip(hm).map(|(&v, &h)| {
+                    let low = ((v >> shift) & 3) as i32;
+                    dl * (low - if h & m != 0 { 0 } else { 4 }) as f32
+                }));
+            }
+            m <<= 1;
+        }
+    }
+}
+
+// The 6-bit scale and min of group `j` in the 12 packed bytes of Q4_K and Q5_K
+fn scale_min_k4(j: usize, q: &[u8]) -> (f32, f32) {
+    if j < 4 {
+        ((q[j] & 63) as f32, (q[j + 4] & 63) as f32)
+    } else {
+        (
+            ((q[j + 4] & 0xf) | ((q[j - 4] >> 6) << 4)) as f32,
+            ((q[j + 4] >> 4) | ((q[j] >> 6) << 4)) as f32,
+        )
+    }
+}
+
+// { f16 d; f16 dmin; u8 scales[12]; u8 qs[128] }: 8 groups of 32 four-bit values
+fn dequantize_q4_k(block: &[u8], out: &mut Vec<f32>) {
+    let (d, min) = (f16_at(block, 0), f16_at(block, 2));
+    let scales = &block[4..16];
+
+    for (i, q) in block[16..144].chunks_exact(32).enumerate() {
+        let (sc1, m1) = scale_min_k4(2 * i, scales);
+        let (sc2, m2) = scale_min_k4(2 * i + 1, scales);
+        out.extend(q.iter().map(|&v| d * sc1 * (v & 0xf) as f32 - min * m1));
+        out.extend(q.iter().map(|&v| d * sc2 * (v >> 4) as f32 - min * m2));
+    }
+}
+
+// { f16 d; f16 dmin; u8 scales[12]; u8 qh[32]; u8 qs[128] }: Q4_K with a fifth bit per value
+fn dequantize_q5_k(block: &[u8], out: &mut Vec<f32>) {
+    let (d, min) = (f16_at(block, 0), f16_at(block, 2));
+    let scales = &block[4..16];
+    let qh = &block[16..48];
+
+    for (i, q) in block[48..176].chunks_exact(32).enumerate() {
+        let (sc1, m1) = scale_min_k4(2 * i, scales);
+        let (sc2, m2) = scale_min_k4(2 * i + 1, scales);
+        let (u1, u2) = (1u8 << (2 * i), 2u8 << (2 * i));
+        out.extend(q.iter().zip(qh).map(|(&v, &h)| {
+            let high = if h & u1 != 0 { 16 } else { 0 };
+            d * sc1 * ((v & 0xf) + high) as f32 - min * m1
+        }));
+        out.extend(q.iter().zip(qh).map(|(&v, &h)| {
+            let high = if h & u2 != 0 { 16 } else { 0 };
+            d * sc2 * ((v >> 4) + high) as f32 - min * m2
+        }));
+    }
+}
+
+// { u8 ql[128]; u8 qh[64]; i8 scales[16]; f16 d }: six-bit values centred on 32, with a
+// signed 8-bit scale per 16 values
+fn dequantize_q6_k(block: &[u8], out: &mut Vec<f32>) {
+    let d = f16_at(block, 208);
+    let start = out.len();
+    out.resize(start + 256, 0.0);
+    let y = &mut out[start..];
+
+    for n in 0..2 {
+        let ql = &block[64 * n..64 * n + 64];
+        let qh = &block[128 + 32 * n..128 + 32 * n + 32];
+        let sc = &block[192 + 8 * n..192 + 8 * n + 8];
+        let y = &mut y[128 * n..128 * n + 128];
+        for l in 0..32 {
+            let is = l / 16;
+            let q1 = ((ql[l] & 0xf) | ((qh[l] & 3) << 4)) as i32 - 32;
+            let q2 = ((ql[l + 32] & 0xf) | (((qh[l] >> 2) & 3) << 4)) as i32 - 32;
+            let q3 = ((ql[l] >> 4) | (((qh[l] >> 4) & 3) << 4)) as i32 - 32;
+            let q4 = ((ql[l + 32] >> 4) | (((qh[l] >> 6) & 3) << 4)) as i32 - 32;
+            y[l] = d * (sc[is] as i8) as f32 * q1 as f32;
+            y[l + 32] = d * (sc[is + 2] as i8) as f32 * q2 as f32;
+            y[l + 64] = d * (sc[is + 4] as i8) as f32 * q3 as f32;
+            y[l + 96] = d * (sc[is + 6] as i8) as f32 * q4 as f32;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_string(bytes: &mut Vec<u8>, s: &str) {
+        bytes.extend_from_slice(&(s.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(s.as_bytes());
+    }
+
+    #[test]
+    fn test_dequantize_blocks() {
+        // Q8_0 with d = 0.5 (f16 0x3800)
+        let mut q8 = vec![0x00, 0x38];
+        q8.extend((0..32).map(|i| (i as i8 - 16) as u8));
+        let values = dequantize(GgmlType::Q8_0, &q8);
+        assert_eq!(values[0], -8.0);
+        assert_eq!(values[31], 7.5);
+
+        // Q4_0 with d = 2: nibble 9 is +1, nibble 0 is -8
+        let mut q4 = vec![0x00, 0x40];
+        q4.extend([0x09; 16]);
+        let values = dequantize(GgmlType::Q4_0, &q4);
+        assert_eq!(values[..16], [2.0; 16]);
+        assert_eq!(values[16..], [-16.0; 16]);
+
+        // Q5_0: setting every high bit lifts each value by 16
+        let mut q5 = vec![0x00, 0x3c, 0xff, 0xff, 0xff, 0xff];
+        q5.extend([0x00; 16]);
+        assert_eq!(dequantize(GgmlType::Q5_0, &q5), vec![0.0; 32]);
+
+        // Q4_K with d = 1, dmin = 0 and every group scale 1
+        let mut q4k = vec![0x00, 0x3c, 0x00, 0x00];
+        q4k.extend([1, 1, 1, 1, 0, 0, 0, 0, 1, 1, 1, 1]);
+        q4k.extend([0x53; 128]);
+        let values = dequantize(GgmlType::Q4K, &q4k);
+        assert_eq!(values.len(), 256);
+        assert_eq!((values[0], values[32]), (3.0, 5.0));
+
+        // Q6_K with d = 1 and every scale 1: all-zero bits are -32
+        let mut q6k = vec![0u8; 192];
+        q6k.extend([1u8; 16]);
+        q6k.extend([0x00, 0x3c]);
+        assert_eq!(dequantize(GgmlType::Q6K, &q6k), vec![-32.0; 256]);
+    }
+
+    #[test]
+    fn test_read_gguf() -> MlResult<()> {
+        let path = "test_read.gguf";
+        let mut bytes = GGUF_MAGIC.to_vec();
+        bytes.extend_from_slice(&3u32.to_le_bytes());
+        bytes.extend_from_slice(&2u64.to_le_bytes());
+        bytes.extend_from_slice(&2u64.to_le_bytes());
+
+        push_string(&mut bytes, "general.architecture");
+        bytes.extend_from_slice(&8u32.to_le_bytes());
+        push_string(&mut bytes, "llama");
+        push_string(&mut bytes, "llama.context_length");
+        bytes.extend_from_slice(&4u32.to_le_bytes());
+        bytes.extend_from_slice(&4096u32.to_le_bytes());
+
+        // "weight": GGUF dims [3, 2] are row-major [2, 3], f32
+        push_string(&mut bytes, "weight");
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        bytes.extend_from_slice(&3u64.to_le_bytes());
+        bytes.extend_from_slice(&2u64.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&0u64.to_le_bytes());
+        // "bias": [2] in f16, after the weight's 24 bytes plus padding to 32
+        push_string(&mut bytes, "bias");
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&2u64.to_le_bytes());
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&32u64.to_le_bytes());
+
+        bytes.resize(bytes.len().next_multiple_of(32), 0);
+        for value in [1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend([0; 8]);
+        bytes.extend([0x00, 0x3c, 0x00, 0xc0]);
+        std::fs::write(path, &bytes).expect("Failed to write test file");
+
+        let gguf = GgufFile::open(path)?;
+        assert_eq!(gguf.version(), 3);
+        let arch = gguf.get_metadata("general.architecture");
+        assert_eq!(arch.and_then(|v| v.as_str()), Some("llama"));
+        let context = gguf.get_metadata("llama.context_length");
+        assert_eq!(context.and_then(|v| v.as_u64()), Some(4096));
+        assert_eq!(gguf.tensors()[0].shape, [2, 3]);
+
+        let mut layer = crate::nn::Linear::new(3, 2, true)?;
+        assert!(gguf.load_into(&mut layer, true)?.is_clean());
+        assert_eq!(layer.weight().data(), &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(layer.bias().unwrap().data(), &[1.0, -2.0]);
+
+        // A tensor table pointing past the end of the file is rejected up front
+        std::fs::write(path, &bytes[..bytes.len() - 2]).expect("Failed to write test file");
+        assert!(GgufFile::open(path).is_err());
+
+        std::fs::remove_file(path).expect("Failed to remove test file");
+        Ok(())
+    }
+}
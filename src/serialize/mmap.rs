@@ -0,0 +1,350 @@
+//! Lazy loading of state dict files through a memory map.
+//!
+//! Opening a [`MappedStateDict`] reads only the file header and each tensor's record header;
+//! a tensor's elements are paged in by the OS when that tensor is asked for, and checked
+//! against the record's checksum then. Loading a model this way needs memory for one tensor
+//! at a time on top of the model itself, rather than for the whole file.
+
+use std::fs::File;
+use std::ops::Range;
+use std::path::Path;
+
+use super::format::{self, ByteReader, FormatError};
+use super::state_dict::{match_keys, STATE_DICT_MAGIC};
+use super::{Deserialize, LoadReport, StateDict, TensorSelector};
+use crate::backend::DeviceType;
+use crate::nn::Layer;
+use crate::tensor::{QuantizedTensor, Tensor};
+use crate::MlResult;
+
+/// A read-only mapping of a whole file.
+#[cfg(unix)]
+pub(crate) struct Mmap {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+#[cfg(unix)]
+impl Mmap {
+    pub(crate) fn open(file: &File) -> std::io::Result<Self> {
+        use std::os::unix::io::AsRawFd;
+
+        let len = file.metadata()?.len() as usize;
+        if len == 0 {
+            return Ok(Self {
+                ptr: std::ptr::null_mut(),
+                len,
+            });
+        }
+
+        // SAFETY: a fresh private, read-only mapping of `len` bytes of an open file
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(Self { ptr, len })
+    }
+
+    pub(crate) fn as_slice(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        // SAFETY: the mapping stays valid for `len` bytes until `self` is dropped
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        if self.len != 0 {
+            // SAFETY: unmaps exactly the region mapped in `open`, which nothing borrows anymore
+            unsafe {
+                libc::munmap(self.ptr, self.len);
+            }
+        }
+    }
+}
+
+// The mapping is read-only, so sharing it between threads is no different from sharing a
+// `&[u8]`.
+#[cfg(unix)]
+unsafe impl Send for Mmap {}
+#[cfg(unix)]
+unsafe impl Sync for Mmap {}
+
+/// Without `mmap`, the file is read into memory up front. Tensors are still decoded one at
+/// a time.
+#[cfg(not(unix))]
+pub(crate) struct Mmap(Vec<u8>);
+
+#[cfg(not(unix))]
+impl Mmap {
+    pub(crate) fn open(mut file: &File) -> std::io::Result<Self> {
+        use std::io::Read;
+
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        Ok(Self(bytes))
+    }
+
+    pub(crate) fn as_slice(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+struct MappedEntry {
+    name: String,
+    shape: Vec<usize>,
+    /// The tensor record's position in the file.
+    record: Range<usize>,
+}
+
+/// A state dict file whose tensors are read only when asked for.
+///
+/// Works on anything written by [`StateDict::save`](super::StateDict::save), and on
+/// [`Model::save`](super::Model::save) files of layers that serialize as a state dict.
+/// The file must not be modified while it is open.
+pub struct MappedStateDict {
+    map: Mmap,
+    payload: Range<usize>,
+    checksum: Option<u32>,
+    entries: Vec<MappedEntry>,
+}
+
+impl MappedStateDict {
+    /// Maps the file at `path` and indexes its tensors, without reading their elements.
+    pub fn open<P: AsRef<Path>>(path: P) -> MlResult<Self> {
+        let file = File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
+        let map = Mmap::open(&file).map_err(|e| format!("Failed to map file: {}", e))?;
+
+        let bytes = map.as_slice();
+        let (payload, checksum) = format::split_file(bytes)?;
+        let start = payload.as_ptr() as usize - bytes.as_ptr() as usize;
+        let entries = index_entries(payload, start)?;
+
+        Ok(Self {
+            payload: start..start + payload.len(),
+            map,
+            checksum,
+            entries,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|entry| entry.name.as_str())
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.entry(name).is_ok()
+    }
+
+    /// The shape of tensor `name`, from its header alone.
+    pub fn shape(&self, name: &str) -> Option<&[usize]> {
+        self.entry(name).ok().map(|entry| entry.shape.as_slice())
+    }
+
+    /// Reads tensor `name` into host memory. Quantized tensors are dequantized; use
+    /// [`MappedStateDict::get_quantized`] to keep them as they are.
+    pub fn get(&self, name: &str) -> MlResult<Tensor> {
+        let record = self.record(name)?;
+        if format::is_quantized(record) {
+            QuantizedTensor::deserialize(record)?.dequantize()
+        } else {
+            Tensor::deserialize(record)
+        }
+    }
+
+    /// Reads quantized tensor `name`, failing if it was stored as a float tensor.
+    pub fn get_quantized(&self, name: &str) -> MlResult<QuantizedTensor> {
+        let record = self.record(name)?;
+        if !format::is_quantized(record) {
+            return Err(format!("{} is not a quantized tensor", name).into());
+        }
+        QuantizedTensor::deserialize(record)
+    }
+
+    fn record(&self, name: &str) -> MlResult<&[u8]> {
+        let entry = self.entry(name)?;
+        Ok(&self.map.as_slice()[entry.record.clone()])
+    }
+
+    /// Reads tensor `name` and puts it on `device`.
+    pub fn get_on(&self, name: &str, device: DeviceType) -> MlResult<Tensor> {
+        let tensor = self.get(name)?;
+        if tensor.device() == device {
+            Ok(tensor)
+        } else {
+            tensor.to_device(device)
+        }
+    }
+
+    /// Reads the tensors `selector` matches into host memory, leaving the rest of the file
+    /// untouched.
+    pub fn load_selected(&self, selector: &TensorSelector) -> MlResult<StateDict> {
+        selector.check_names(self.keys())?;
+        let mut state = StateDict::new();
+        for entry in self.entries.iter().filter(|e| selector.matches(&e.name)) {
+            let record = &self.map.as_slice()[entry.record.clone()];
+            if format::is_quantized(record) {
+                state.insert_quantized(entry.name.clone(), QuantizedTensor::deserialize(record)?);
+            } else {
+                state.insert(entry.name.clone(), Tensor::deserialize(record)?);
+            }
+        }
+        Ok(state)
+    }
+
+    /// Loads matching tensors into `layer`'s parameters one at a time, each on the device its
+    /// parameter is on. Keys and shapes are checked as in
+    /// [`Layer::load_state_dict`] before anything is read.
+    pub fn load_into<L: Layer + ?Sized>(
+        &self,
+        layer: &mut L,
+        strict: bool,
+    ) -> MlResult<LoadReport> {
+        let params = layer.named_parameters_mut();
+        let wanted: Vec<_> = params
+            .iter()
+            .map(|(n, p)| (n.as_str(), p.shape()))
+            .collect();
+        let offered: Vec<_> = self
+            .entries
+            .iter()
+            .map(|entry| (entry.name.as_str(), entry.shape.as_slice()))
+            .collect();
+        let report = match_keys(&wanted, &offered, strict)?;
+
+        for (name, param) in params {
+            if self.contains_key(&name) {
+                *param = self.get_on(&name, param.device())?;
+            }
+        }
+        Ok(report)
+    }
+
+    /// Checks the whole-file checksum, which reads every page of the file. Each tensor's own
+    /// checksum is already checked whenever it is read.
+    pub fn verify(&self) -> MlResult<()> {
+        if let Some(expected) = self.checksum {
+            let found = crc32fast::hash(&self.map.as_slice()[self.payload.clone()]);
+            if found != expected {
+                return Err(FormatError::ChecksumMismatch { expected, found }.into());
+            }
+        }
+        Ok(())
+    }
+
+    fn entry(&self, name: &str) -> MlResult<&MappedEntry> {
+        self.entries
+            .iter()
+            .find(|entry| entry.name == name)
+            .ok_or_else(|| format!("No tensor named {}", name).into())
+    }
+}
+
+// Walks the state dict layout, touching only names and record headers. `start` is the
+// payload's offset in the file.
+fn index_entries(payload: &[u8], start: usize) -> MlResult<Vec<MappedEntry>> {
+    let mut reader = ByteReader::new(payload);
+    if reader.take(4)? != STATE_DICT_MAGIC {
+        return Err(FormatError::BadMagic.into());
+    }
+
+    let count = reader.u32()? as usize;
+    let mut entries: Vec<MappedEntry> = Vec::with_capacity(count.min(reader.remaining() / 12));
+    for _ in 0..count {
+        let name = std::str::from_utf8(reader.prefixed()?)
+            .map_err(|_| FormatError::Invalid("parameter name is not UTF-8".into()))?;
+        if entries.iter().any(|entry| entry.name == name) {
+            return Err(FormatError::Invalid(format!("duplicate parameter {}", name)).into());
+        }
+
+        let len = reader.u64()? as usize;
+        let offset = start + payload.len() - reader.remaining();
+        let record = reader.take(len)?;
+        entries.push(MappedEntry {
+            name: name.to_string(),
+            shape: if format::is_quantized(record) {
+                format::quantized_shape(record)?
+            } else {
+                format::tensor_shape(record)?
+            },
+            record: offset..offset + len,
+        });
+    }
+    reader.finish()?;
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::Linear;
+    use crate::serialize::Model;
+
+    #[test]
+    fn test_mapped_state_dict() -> MlResult<()> {
+        let path = "test_mapped.spn";
+        let mut state = StateDict::new();
+        state.insert(
+            "weight",
+            Tensor::from_vec(vec![1.0, 2.0, 3.0, 4.0], &[2, 2])?,
+        );
+        state.insert("bias", Tensor::from_vec(vec![0.5, -0.5], &[2])?);
+        state.insert("extra", Tensor::from_vec(vec![9.0], &[1])?);
+        state.save(path)?;
+
+        let mapped = MappedStateDict::open(path)?;
+        assert_eq!(
+            mapped.keys().collect::<Vec<_>>(),
+            ["weight", "bias", "extra"]
+        );
+        assert_eq!(mapped.shape("weight"), Some(&[2, 2][..]));
+        assert_eq!(mapped.get("bias")?.data(), &[0.5, -0.5]);
+        assert!(mapped.get("missing").is_err());
+        mapped.verify()?;
+
+        let mut layer = Linear::new(2, 2, true)?;
+        assert!(mapped.load_into(&mut layer, true).is_err());
+        let report = mapped.load_into(&mut layer, false)?;
+        assert_eq!(report.unexpected_keys, ["extra"]);
+        assert_eq!(layer.weight().data(), &[1.0, 2.0, 3.0, 4.0]);
+
+        // Model files of layers that save as state dicts open the same way
+        layer.save(path)?;
+        let mapped = MappedStateDict::open(path)?;
+        assert_eq!(mapped.keys().collect::<Vec<_>>(), ["weight", "bias"]);
+
+        // A corrupt tensor only fails when it is read
+        let mut bytes = std::fs::read(path).expect("Failed to read test file");
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        std::fs::write(path, &bytes).expect("Failed to write test file");
+        let mapped = MappedStateDict::open(path)?;
+        assert!(mapped.get("weight").is_ok());
+        assert!(mapped.get("bias").is_err());
+        assert!(mapped.verify().is_err());
+
+        std::fs::remove_file(path).expect("Failed to remove test file");
+        Ok(())
+    }
+}
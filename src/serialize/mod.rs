@@ -1,19 +1,81 @@
+#[cfg(feature = "fs")]
 use std::fs::File;
+#[cfg(feature = "fs")]
+use std::io::{BufReader, BufWriter};
 use std::io::{Read, Write};
+#[cfg(feature = "fs")]
 use std::path::Path;
 
 use crate::prelude::Layer;
 use crate::MlResult;
 
-// Magic bytes to identify our format
-const MAGIC_BYTES: &[u8] = b"SPN1";
+pub mod checkpoint;
+#[cfg(feature = "fs")]
+pub mod convert;
+pub mod format;
+#[cfg(feature = "fs")]
+pub mod gguf;
+#[cfg(feature = "fs")]
+pub mod mmap;
+pub mod npy;
+pub mod onnx;
+pub mod pytorch;
+pub mod safetensors;
+pub mod select;
+pub mod state_dict;
+mod zip;
+
+pub use checkpoint::{Checkpoint, TrainingRun, TrainingState};
+#[cfg(feature = "fs")]
+pub use convert::{convert, TensorFormat};
+pub use format::{FormatError, FORMAT_VERSION};
+#[cfg(feature = "fs")]
+pub use gguf::GgufFile;
+#[cfg(feature = "fs")]
+pub use mmap::MappedStateDict;
+#[cfg(feature = "fs")]
+pub use npy::{load_npz, save_npz};
+pub use npy::{read_npz, write_npz};
+#[cfg(feature = "fs")]
+pub use onnx::{load_onnx, load_onnx_graph, save_onnx};
+pub use onnx::{read_onnx, read_onnx_graph, write_onnx};
+#[cfg(feature = "fs")]
+pub use pytorch::load_pt;
+pub use pytorch::read_pt;
+#[cfg(feature = "fs")]
+pub use safetensors::{load_safetensors, save_safetensors};
+#[cfg(all(feature = "half", feature = "fs"))]
+pub use safetensors::{load_safetensors_f16, save_safetensors_f16};
+pub use safetensors::{read_safetensors, write_safetensors};
+#[cfg(feature = "half")]
+pub use safetensors::{read_safetensors_f16, write_safetensors_f16, HalfTensors};
+pub use select::TensorSelector;
+pub use state_dict::{LoadReport, StateDict};
+
+use format::ByteReader;
 
 pub trait Serialize {
     fn serialize(&self) -> Vec<u8>;
+
+    /// Writes the same bytes as [`Serialize::serialize`] to `writer`. Types that can produce
+    /// their bytes piece by piece override this, so large values are never buffered whole.
+    fn serialize_into<W: Write>(&self, mut writer: W) -> MlResult<()> {
+        format::write_all(&mut writer, &self.serialize())
+    }
 }
 
 pub trait Deserialize: Sized {
     fn deserialize(bytes: &[u8]) -> MlResult<Self>;
+
+    /// Reads a value from `reader`. The default reads to the end of the stream first; types
+    /// whose bytes say where they end override this to read only what they need.
+    fn deserialize_from<R: Read>(mut reader: R) -> MlResult<Self> {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .map_err(|e| format!("Failed to read data: {}", e))?;
+        Self::deserialize(&bytes)
+    }
 }
 
 // Helper trait for serializing multiple components
@@ -48,87 +110,53 @@ impl<T: SerializeComponents> Serialize for T {
 // Implement automatic deserialization for types that implement DeserializeComponents
 impl<T: DeserializeComponents> Deserialize for T {
     fn deserialize(bytes: &[u8]) -> MlResult<Self> {
-        let mut cursor = 0;
-
-        // Read number of components
-        let num_components = u64::from_le_bytes(
-            bytes[cursor..cursor + 8]
-                .try_into()
-                .map_err(|_| "Invalid data format")?,
-        ) as usize;
-        cursor += 8;
-
-        // Read each component
-        let mut components = Vec::with_capacity(num_components);
+        let mut reader = ByteReader::new(bytes);
+
+        // Read number of components, each with its length prefix
+        let num_components = reader.u64()? as usize;
+        let mut components = Vec::with_capacity(num_components.min(reader.remaining() / 8));
         for _ in 0..num_components {
-            // Read component length
-            let len = u64::from_le_bytes(
-                bytes[cursor..cursor + 8]
-                    .try_into()
-                    .map_err(|_| "Invalid data format")?,
-            ) as usize;
-            cursor += 8;
-
-            // Read component data
-            let end = cursor + len;
-            if end > bytes.len() {
-                return Err("Invalid data format".into());
-            }
-            components.push(bytes[cursor..end].to_vec());
-            cursor = end;
+            let len = reader.u64()? as usize;
+            components.push(reader.take(len)?.to_vec());
         }
+        reader.finish()?;
 
         Self::deserialize_components(components)
     }
 }
 
+/// A layer that can be saved to and loaded from a file. Files carry a format version and a
+/// checksum of the model data, so truncated or corrupted files fail to load with a
+/// [`FormatError`] rather than producing a broken model.
 pub trait Model: Layer + Serialize + Deserialize {
+    #[cfg(feature = "fs")]
     fn save<P: AsRef<Path>>(&self, path: P) -> MlResult<()> {
-        let mut file = File::create(path).map_err(|e| format!("Failed to create file: {}", e))?;
-
-        // Write magic bytes
-        file.write_all(MAGIC_BYTES)
-            .map_err(|e| format!("Failed to write magic bytes: {}", e))?;
-
-        // Get serialized data
-        let data = self.serialize();
-
-        // Write data length as u64
-        let len = data.len() as u64;
-        file.write_all(&len.to_le_bytes())
-            .map_err(|e| format!("Failed to write data length: {}", e))?;
-
-        // Write actual data
-        file.write_all(&data)
-            .map_err(|e| format!("Failed to write data: {}", e))?;
-
-        Ok(())
+        let file = File::create(path).map_err(|e| format!("Failed to create file: {}", e))?;
+        self.save_to(BufWriter::new(file))
     }
 
+    #[cfg(feature = "fs")]
     fn load<P: AsRef<Path>>(path: P) -> MlResult<Self> {
-        let mut file = File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
+        let file = File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
+        let mut reader = BufReader::new(file);
+        let model = Self::load_from(&mut reader)?;
 
-        // Read and verify magic bytes
-        let mut magic = [0u8; 4];
-        file.read_exact(&mut magic)
-            .map_err(|e| format!("Failed to read magic bytes: {}", e))?;
-
-        if magic != MAGIC_BYTES {
-            return Err("Invalid file format".into());
+        let trailing = std::io::copy(&mut reader, &mut std::io::sink())
+            .map_err(|e| format!("Failed to read data: {}", e))?;
+        if trailing > 0 {
+            return Err(FormatError::TrailingBytes(trailing as usize).into());
         }
+        Ok(model)
+    }
 
-        // Read data length
-        let mut len_bytes = [0u8; 8];
-        file.read_exact(&mut len_bytes)
-            .map_err(|e| format!("Failed to read data length: {}", e))?;
-        let len = u64::from_le_bytes(len_bytes) as usize;
-
-        // Read data
-        let mut data = vec![0u8; len];
-        file.read_exact(&mut data)
-            .map_err(|e| format!("Failed to read data: {}", e))?;
+    /// Writes the model file to `writer` as it is serialized, e.g. straight to a socket.
+    fn save_to<W: Write>(&self, writer: W) -> MlResult<()> {
+        format::write_file(writer, self)
+    }
 
-        Self::deserialize(&data)
+    /// Reads one model file from `reader`, leaving anything after it unread.
+    fn load_from<R: Read>(reader: R) -> MlResult<Self> {
+        format::read_file(reader)
     }
 }
 
@@ -254,6 +282,70 @@ mod tests {
         std::fs::remove_file(temp_path).expect("Failed to remove test file");
     }
 
+    #[test]
+    fn test_corrupt_model_files() {
+        let temp_path = "test_corrupt_model.spn";
+        let model = crate::nn::Linear::new(3, 2, true).expect("Failed to create layer");
+        model.save(temp_path).expect("Failed to save model");
+        let bytes = std::fs::read(temp_path).expect("Failed to read test file");
+
+        // Cut off mid-tensor
+        std::fs::write(temp_path, &bytes[..bytes.len() - 5]).expect("Failed to write test file");
+        assert!(matches!(
+            crate::nn::Linear::load(temp_path),
+            Err(crate::MlError::FormatError(FormatError::Truncated { .. }))
+        ));
+
+        // A flipped bit in the weights
+        let mut flipped = bytes.clone();
+        flipped[40] ^= 1;
+        std::fs::write(temp_path, &flipped).expect("Failed to write test file");
+        assert!(matches!(
+            crate::nn::Linear::load(temp_path),
+            Err(crate::MlError::FormatError(
+                FormatError::ChecksumMismatch { .. }
+            ))
+        ));
+
+        std::fs::write(temp_path, &bytes).expect("Failed to write test file");
+        let loaded = crate::nn::Linear::load(temp_path).expect("Failed to load model");
+        assert_eq!(loaded.weight().data(), model.weight().data());
+
+        std::fs::remove_file(temp_path).expect("Failed to remove test file");
+    }
+
+    #[test]
+    fn test_streaming_save_load() {
+        let model = crate::nn::Linear::new(3, 2, true).expect("Failed to create layer");
+
+        // Streams write the same bytes as the in-memory path
+        let mut streamed = Vec::new();
+        model
+            .save_to(&mut streamed)
+            .expect("Failed to stream model");
+        assert_eq!(streamed, format::encode_file(&model.serialize()));
+        let mut record = Vec::new();
+        model
+            .weight()
+            .serialize_into(&mut record)
+            .expect("Failed to stream tensor");
+        assert_eq!(record, model.weight().serialize());
+
+        // Two models back to back on one stream, as over a socket
+        streamed.extend_from_slice(&streamed.clone());
+        let mut reader = std::io::Cursor::new(streamed);
+        for _ in 0..2 {
+            let loaded = crate::nn::Linear::load_from(&mut reader).expect("Failed to load model");
+            assert_eq!(loaded.weight().data(), model.weight().data());
+            assert_eq!(loaded.bias().unwrap().data(), model.bias().unwrap().data());
+        }
+        assert_eq!(reader.position() as usize, reader.get_ref().len());
+
+        let tensor = Tensor::deserialize_from(&record[..]).expect("Failed to read tensor");
+        assert_eq!(tensor.data(), model.weight().data());
+        assert!(Tensor::deserialize_from(&record[..record.len() - 1]).is_err());
+    }
+
     #[test]
     fn test_tensor_serialization_edge_cases() {
         // Test empty tensor
@@ -0,0 +1,394 @@
+//! NumPy `.npy` arrays and `.npz` archives, for exchanging tensors with Python scripts.
+//!
+//! Any float, integer or boolean dtype in either byte order is read and converted to f32,
+//! and arrays saved in Fortran order are brought back to row-major. Tensors are written as
+//! little-endian `<f4`, which `np.load` reads as `float32`.
+
+pub(crate) use cetana_core::safetensors::f16_to_f32;
+
+use super::format;
+use super::zip::{write_stored, ZipReader};
+use crate::tensor::Tensor;
+use crate::MlResult;
+#[cfg(feature = "fs")]
+use std::fs::File;
+#[cfg(feature = "fs")]
+use std::io::{BufReader, BufWriter};
+use std::io::{Read, Write};
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+const NPY_MAGIC: &[u8] = b"\x93NUMPY";
+
+impl Tensor {
+    /// Reads a `.npy` file.
+    #[cfg(feature = "fs")]
+    pub fn from_npy<P: AsRef<Path>>(path: P) -> MlResult<Tensor> {
+        let file = File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
+        Self::read_npy(BufReader::new(file))
+    }
+
+    /// Writes the tensor to a `.npy` file.
+    #[cfg(feature = "fs")]
+    pub fn to_npy<P: AsRef<Path>>(&self, path: P) -> MlResult<()> {
+        let file = File::create(path).map_err(|e| format!("Failed to create file: {}", e))?;
+        let mut writer = BufWriter::new(file);
+        self.write_npy(&mut writer)?;
+        writer
+            .flush()
+            .map_err(|e| format!("Failed to write npy data: {}", e))?;
+        Ok(())
+    }
+
+    pub fn read_npy<R: Read>(mut reader: R) -> MlResult<Tensor> {
+        let mut preamble = [0u8; 8];
+        read_exact(&mut reader, &mut preamble)?;
+        if &preamble[..6] != NPY_MAGIC {
+            return Err("Not an npy file".into());
+        }
+
+        let header_len = match preamble[6] {
+            1 => {
+                let mut len = [0u8; 2];
+                read_exact(&mut reader, &mut len)?;
+                u16::from_le_bytes(len) as usize
+            }
+            2 | 3 => {
+                let mut len = [0u8; 4];
+                read_exact(&mut reader, &mut len)?;
+                u32::from_le_bytes(len) as usize
+            }
+            major => {
+                return Err(format!("Unsupported npy version {}.{}", major, preamble[7]).into())
+            }
+        };
+        let mut header = vec![0u8; header_len];
+        read_exact(&mut reader, &mut header)?;
+        let header = NpyHeader::parse(&String::from_utf8_lossy(&header))?;
+
+        let len: usize = header.shape.iter().product();
+        let mut bytes = vec![0u8; len * header.dtype.size];
+        read_exact(&mut reader, &mut bytes)?;
+
+        let mut data = header.dtype.decode(&bytes);
+        if header.fortran_order {
+            data = fortran_to_c_order(&data, &header.shape);
+        }
+        Tensor::from_vec(data, &header.shape)
+    }
+
+    pub fn write_npy<W: Write>(&self, mut writer: W) -> MlResult<()> {
+        let shape = match self.shape() {
+            [len] => format!("({},)", len),
+            dims => format!(
+                "({})",
+                dims.iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        };
+        let dict = format!(
+            "{{'descr': '<f4', 'fortran_order': False, 'shape': {}, }}",
+            shape
+        );
+
+        // Version 1 stores the header length in a u16. The header is padded with spaces and
+        // ends in a newline so the data starts 64-byte aligned.
+        let (major, prefix_len) = if dict.len() + 64 <= u16::MAX as usize {
+            (1u8, 10)
+        } else {
+            (2u8, 12)
+        };
+        let unpadded = prefix_len + dict.len() + 1;
+        let header = format!(
+            "{}{}\n",
+            dict,
+            " ".repeat(unpadded.next_multiple_of(64) - unpadded)
+        );
+
+        let mut bytes = Vec::with_capacity(prefix_len + header.len() + self.data().len() * 4);
+        bytes.extend_from_slice(NPY_MAGIC);
+        bytes.extend_from_slice(&[major, 0]);
+        if major == 1 {
+            bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        } else {
+            bytes.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        }
+        bytes.extend_from_slice(header.as_bytes());
+        for value in self.data() {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+
+        writer
+            .write_all(&bytes)
+            .map_err(|e| format!("Failed to write npy data: {}", e))?;
+        Ok(())
+    }
+}
+
+fn read_exact<R: Read>(reader: &mut R, buf: &mut [u8]) -> MlResult<()> {
+    reader
+        .read_exact(buf)
+        .map_err(|e| format!("Failed to read npy data: {}", e).into())
+}
+
+struct NpyHeader {
+    dtype: NpyDtype,
+    fortran_order: bool,
+    shape: Vec<usize>,
+}
+
+impl NpyHeader {
+    // The header is a Python dict literal such as
+    // {'descr': '<f4', 'fortran_order': False, 'shape': (2, 3), }
+    fn parse(header: &str) -> MlResult<Self> {
+        let descr = dict_value(header, "descr")?;
+        let quote = descr.chars().next().filter(|c| *c == '\'' || *c == '"');
+        let descr = quote
+            .and_then(|quote| descr[1..].split(quote).next())
+            .ok_or("Structured npy dtypes aren't supported")?;
+
+        let fortran_order = dict_value(header, "fortran_order")?.starts_with("True");
+
+        let shape = dict_value(header, "shape")?;
+        let shape = shape
+            .strip_prefix('(')
+            .and_then(|shape| shape.split(')').next())
+            .ok_or("Invalid npy shape")?
+            .split(',')
+            .map(str::trim)
+            .filter(|dim| !dim.is_empty())
+            .map(|dim| dim.parse::<usize>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| format!("Invalid npy shape in header {}", header.trim()))?;
+
+        Ok(Self {
+            dtype: NpyDtype::parse(descr)?,
+            fortran_order,
+            shape,
+        })
+    }
+}
+
+fn dict_value<'a>(header: &'a str, key: &str) -> MlResult<&'a str> {
+    [format!("'{}':", key), format!("\"{}\":", key)]
+        .iter()
+        .find_map(|pattern| {
+            header
+                .find(pattern.as_str())
+                .map(|start| header[start + pattern.len()..].trim_start())
+        })
+        .ok_or_else(|| format!("npy header has no '{}'", key).into())
+}
+
+#[derive(Debug, Clone, Copy)]
+struct NpyDtype {
+    kind: char,
+    size: usize,
+    big_endian: bool,
+}
+
+impl NpyDtype {
+    fn parse(descr: &str) -> MlResult<Self> {
+        let (order, code) = match descr.chars().next() {
+            Some(order @ ('<' | '>' | '|' | '=')) => (order, &descr[1..]),
+            _ => ('=', descr),
+        };
+        let kind = code.chars().next().unwrap_or(' ');
+        let size = code
+            .get(1..)
+            .and_then(|size| size.parse().ok())
+            .unwrap_or(0);
+
+        match (kind, size) {
+            ('f', 2 | 4 | 8) | ('i' | 'u', 1 | 2 | 4 | 8) | ('b', 1) => Ok(Self {
+                kind,
+                size,
+                big_endian: order == '>' || (order == '=' && cfg!(target_endian = "big")),
+            }),
+            _ => Err(format!("Unsupported npy dtype '{}'", descr).into()),
+        }
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Vec<f32> {
+        let bits = 8 * self.size as u32;
+        bytes
+            .chunks_exact(self.size)
+            .map(|chunk| {
+                let mut raw = [0u8; 8];
+                raw[..self.size].copy_from_slice(chunk);
+                if self.big_endian {
+                    raw[..self.size].reverse();
+                }
+                let unsigned = u64::from_le_bytes(raw);
+
+                match (self.kind, self.size) {
+                    ('f', 2) => f16_to_f32(unsigned as u16),
+                    ('f', 4) => f32::from_bits(unsigned as u32),
+                    ('f', _) => f64::from_bits(unsigned) as f32,
+                    // Sign-extend from the value's width
+                    ('i', _) => (((unsigned << (64 - bits)) as i64) >> (64 - bits)) as f32,
+                    _ => unsigned as f32,
+                }
+            })
+            .collect()
+    }
+}
+
+fn fortran_to_c_order(data: &[f32], shape: &[usize]) -> Vec<f32> {
+    let mut strides = vec![1; shape.len()];
+    for d in 1..shape.len() {
+        strides[d] = strides[d - 1] * shape[d - 1];
+    }
+
+    (0..data.len())
+        .map(|index| {
+            let mut rest = index;
+            let mut source = 0;
+            for d in (0..shape.len()).rev() {
+                source += rest % shape[d] * strides[d];
+                rest /= shape[d];
+            }
+            data[source]
+        })
+        .collect()
+}
+
+/// Reads every array in an `.npz` archive, in archive order, named without the `.npy`
+/// extension. Reads archives from both `np.savez` and `np.savez_compressed`.
+#[cfg(feature = "fs")]
+pub fn load_npz<P: AsRef<Path>>(path: P) -> MlResult<Vec<(String, Tensor)>> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to read file: {}", e))?;
+    decode_npz(&bytes)
+}
+
+/// Like [`load_npz`], reading the archive's bytes from `reader` to its end.
+pub fn read_npz<R: Read>(reader: R) -> MlResult<Vec<(String, Tensor)>> {
+    decode_npz(&format::read_to_end(reader)?)
+}
+
+fn decode_npz(bytes: &[u8]) -> MlResult<Vec<(String, Tensor)>> {
+    let archive = ZipReader { bytes };
+
+    archive
+        .entries()?
+        .into_iter()
+        .map(|entry| {
+            let data = archive.extract(&entry)?;
+            let name = entry.name.strip_suffix(".npy").unwrap_or(&entry.name);
+            Ok((name.to_string(), Tensor::read_npy(data.as_slice())?))
+        })
+        .collect()
+}
+
+/// Writes `tensors` to an uncompressed `.npz` archive, which `np.load` opens as a dict of
+/// arrays keyed by name.
+#[cfg(feature = "fs")]
+pub fn save_npz<P: AsRef<Path>>(path: P, tensors: &[(&str, &Tensor)]) -> MlResult<()> {
+    std::fs::write(path, encode_npz(tensors)?)
+        .map_err(|e| format!("Failed to write file: {}", e))?;
+    Ok(())
+}
+
+/// Like [`save_npz`], writing the archive's bytes to `writer`.
+pub fn write_npz<W: Write>(writer: W, tensors: &[(&str, &Tensor)]) -> MlResult<()> {
+    format::write_all(writer, &encode_npz(tensors)?)
+}
+
+fn encode_npz(tensors: &[(&str, &Tensor)]) -> MlResult<Vec<u8>> {
+    let mut entries = Vec::with_capacity(tensors.len());
+    for (name, tensor) in tensors {
+        let mut npy = Vec::new();
+        tensor.write_npy(&mut npy)?;
+        entries.push((format!("{}.npy", name), npy));
+    }
+
+    write_stored(&entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Builds a version 1 npy file around `header` and raw `data`
+    fn npy_bytes(header: &str, data: &[u8]) -> Vec<u8> {
+        let mut bytes = NPY_MAGIC.to_vec();
+        bytes.extend_from_slice(&[1, 0]);
+        bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(header.as_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn test_npy_round_trip() -> MlResult<()> {
+        let tensor = Tensor::from_vec(vec![1.0, -2.5, 3.25, 4.0, 5.5, -6.0], &[2, 3])?;
+        let mut bytes = Vec::new();
+        tensor.write_npy(&mut bytes)?;
+
+        let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        assert_eq!((10 + header_len) % 64, 0);
+        assert!(String::from_utf8_lossy(&bytes[10..10 + header_len])
+            .starts_with("{'descr': '<f4', 'fortran_order': False, 'shape': (2, 3), }"));
+
+        let loaded = Tensor::read_npy(bytes.as_slice())?;
+        assert_eq!(loaded.shape(), &[2, 3]);
+        assert_eq!(loaded.data(), tensor.data());
+        Ok(())
+    }
+
+    #[test]
+    fn test_npy_dtypes_and_fortran_order() -> MlResult<()> {
+        // Big-endian float64 in Fortran order: columns [1, 2], [3, 4], [5, 6]
+        let data: Vec<u8> = [1.0f64, 2.0, 3.0, 4.0, 5.0, 6.0]
+            .iter()
+            .flat_map(|v| v.to_be_bytes())
+            .collect();
+        let bytes = npy_bytes(
+            "{'descr': '>f8', 'fortran_order': True, 'shape': (2, 3), }\n",
+            &data,
+        );
+        let tensor = Tensor::read_npy(bytes.as_slice())?;
+        assert_eq!(tensor.data(), &[1.0, 3.0, 5.0, 2.0, 4.0, 6.0]);
+
+        let data: Vec<u8> = [-3i16, 7].iter().flat_map(|v| v.to_le_bytes()).collect();
+        let bytes = npy_bytes(
+            "{'descr': '<i2', 'fortran_order': False, 'shape': (2,), }\n",
+            &data,
+        );
+        assert_eq!(Tensor::read_npy(bytes.as_slice())?.data(), &[-3.0, 7.0]);
+
+        let bytes = npy_bytes(
+            "{'descr': '<f2', 'fortran_order': False, 'shape': (), }\n",
+            &0x3e00u16.to_le_bytes(),
+        );
+        assert_eq!(Tensor::read_npy(bytes.as_slice())?.data(), &[1.5]);
+
+        let bytes = npy_bytes(
+            "{'descr': '<c8', 'fortran_order': False, 'shape': (1,), }\n",
+            &[0; 8],
+        );
+        assert!(Tensor::read_npy(bytes.as_slice()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_npz_round_trip() -> MlResult<()> {
+        let path = std::env::temp_dir().join("cetana_test_npz_round_trip.npz");
+        let weight = Tensor::from_vec(vec![1.0, 2.0, 3.0, 4.0], &[2, 2])?;
+        let bias = Tensor::from_vec(vec![0.5, -0.5], &[2])?;
+
+        save_npz(&path, &[("weight", &weight), ("bias", &bias)])?;
+        let loaded = load_npz(&path)?;
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded[0].0, "weight");
+        assert_eq!(loaded[0].1.shape(), &[2, 2]);
+        assert_eq!(loaded[0].1.data(), weight.data());
+        assert_eq!(loaded[1].0, "bias");
+        assert_eq!(loaded[1].1.data(), bias.data());
+        Ok(())
+    }
+}
@@ -0,0 +1,796 @@
+//! The weights and graphs of ONNX models.
+//!
+//! [`load_onnx`] reads a graph's initializers, the named constant tensors that hold a
+//! model's parameters, and not its operators. Initializers stored as `raw_data` or in the
+//! typed value fields are read for float, integer and boolean types and converted to f32.
+//! Tensors kept in external data files are not supported.
+//!
+//! [`load_onnx_graph`] also reads the operators, into a [`Graph`] with the initializers as
+//! constants, for the common operators of MLPs and CNNs: `MatMul`, `Gemm`, `Conv` without
+//! dilation, `BatchNormalization`, `Flatten`, `Transpose` (of 2D tensors unless it has a
+//! `perm`), `Add`, `Sub`, `Mul`, `Div`, `Relu`, `Sigmoid`, `Tanh`, `Exp`, `Log`, `Neg`,
+//! `Sqrt`, `Identity` and `Constant`.
+//! Any other operator is an error.
+//!
+//! Saving writes a model whose graph has the tensors as initializers and no nodes, which
+//! ONNX tooling can open to inspect or merge the weights into a graph.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+use super::format::{self, FormatError};
+use super::npy::f16_to_f32;
+use super::StateDict;
+use crate::graph::{Graph, NodeId, Op};
+use crate::tensor::Tensor;
+use crate::MlResult;
+
+// ModelProto
+const MODEL_IR_VERSION: u32 = 1;
+const MODEL_PRODUCER_NAME: u32 = 2;
+const MODEL_GRAPH: u32 = 7;
+const MODEL_OPSET_IMPORT: u32 = 8;
+// OperatorSetIdProto
+const OPSET_VERSION: u32 = 2;
+// GraphProto
+const GRAPH_NODE: u32 = 1;
+const GRAPH_NAME: u32 = 2;
+const GRAPH_INITIALIZER: u32 = 5;
+const GRAPH_INPUT: u32 = 11;
+const GRAPH_OUTPUT: u32 = 12;
+// ValueInfoProto
+const VALUE_INFO_NAME: u32 = 1;
+// NodeProto
+const NODE_INPUT: u32 = 1;
+const NODE_OUTPUT: u32 = 2;
+const NODE_OP_TYPE: u32 = 4;
+const NODE_ATTRIBUTE: u32 = 5;
+// AttributeProto
+const ATTRIBUTE_NAME: u32 = 1;
+const ATTRIBUTE_F: u32 = 2;
+const ATTRIBUTE_I: u32 = 3;
+const ATTRIBUTE_S: u32 = 4;
+const ATTRIBUTE_T: u32 = 5;
+const ATTRIBUTE_INTS: u32 = 8;
+// TensorProto
+const TENSOR_DIMS: u32 = 1;
+const TENSOR_DATA_TYPE: u32 = 2;
+const TENSOR_FLOAT_DATA: u32 = 4;
+const TENSOR_INT32_DATA: u32 = 5;
+const TENSOR_INT64_DATA: u32 = 7;
+const TENSOR_NAME: u32 = 8;
+const TENSOR_RAW_DATA: u32 = 9;
+const TENSOR_DOUBLE_DATA: u32 = 10;
+const TENSOR_UINT64_DATA: u32 = 11;
+const TENSOR_DATA_LOCATION: u32 = 14;
+
+// TensorProto.DataType
+const FLOAT: u64 = 1;
+const UINT8: u64 = 2;
+const INT8: u64 = 3;
+const UINT16: u64 = 4;
+const INT16: u64 = 5;
+const INT32: u64 = 6;
+const INT64: u64 = 7;
+const BOOL: u64 = 9;
+const FLOAT16: u64 = 10;
+const DOUBLE: u64 = 11;
+const UINT32: u64 = 12;
+const UINT64: u64 = 13;
+const BFLOAT16: u64 = 16;
+
+// The versions written into saved models: IR version 8, opset 17
+const IR_VERSION: u64 = 8;
+const OPSET: u64 = 17;
+
+/// Reads the initializers of the ONNX model at `path`, in graph order.
+#[cfg(feature = "fs")]
+pub fn load_onnx<P: AsRef<Path>>(path: P) -> MlResult<StateDict> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to read file: {}", e))?;
+    decode_model(&bytes)
+}
+
+/// Writes `state` as the initializers of an otherwise empty ONNX model, every tensor as
+/// `FLOAT`.
+#[cfg(feature = "fs")]
+pub fn save_onnx<P: AsRef<Path>>(path: P, state: &StateDict) -> MlResult<()> {
+    std::fs::write(path, encode_model(state))
+        .map_err(|e| format!("Failed to write file: {}", e).into())
+}
+
+/// Like [`load_onnx`], reading the model's bytes from `reader` to its end.
+pub fn read_onnx<R: Read>(reader: R) -> MlResult<StateDict> {
+    decode_model(&format::read_to_end(reader)?)
+}
+
+/// Like [`save_onnx`], writing the model's bytes to `writer`.
+pub fn write_onnx<W: Write>(writer: W, state: &StateDict) -> MlResult<()> {
+    format::write_all(writer, &encode_model(state))
+}
+
+/// Reads the ONNX model at `path` as a [`Graph`], with the graph's inputs that aren't
+/// initializers as its inputs, in order.
+#[cfg(feature = "fs")]
+pub fn load_onnx_graph<P: AsRef<Path>>(path: P) -> MlResult<Graph> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to read file: {}", e))?;
+    decode_graph(&bytes)
+}
+
+/// Like [`load_onnx_graph`], reading the model's bytes from `reader` to its end.
+pub fn read_onnx_graph<R: Read>(reader: R) -> MlResult<Graph> {
+    decode_graph(&format::read_to_end(reader)?)
+}
+
+fn decode_model(bytes: &[u8]) -> MlResult<StateDict> {
+    let mut state = StateDict::new();
+    let mut model = Message::new(bytes);
+    while let Some((field, value)) = model.next_field()? {
+        if field != MODEL_GRAPH {
+            continue;
+        }
+        let mut graph = Message::new(value.bytes()?);
+        while let Some((field, value)) = graph.next_field()? {
+            if field == GRAPH_INITIALIZER {
+                let (name, tensor) = decode_tensor(value.bytes()?)?;
+                if state.insert(name.clone(), tensor).is_some() {
+                    return Err(
+                        FormatError::Invalid(format!("duplicate initializer {}", name)).into(),
+                    );
+                }
+            }
+        }
+    }
+    Ok(state)
+}
+
+fn decode_graph(bytes: &[u8]) -> MlResult<Graph> {
+    let mut initializers = Vec::new();
+    let mut inputs = Vec::new();
+    let mut outputs = Vec::new();
+    let mut nodes = Vec::new();
+    let mut model = Message::new(bytes);
+    while let Some((field, value)) = model.next_field()? {
+        if field != MODEL_GRAPH {
+            continue;
+        }
+        let mut graph = Message::new(value.bytes()?);
+        while let Some((field, value)) = graph.next_field()? {
+            match field {
+                GRAPH_NODE => nodes.push(decode_node(value.bytes()?)?),
+                GRAPH_INITIALIZER => initializers.push(decode_tensor(value.bytes()?)?),
+                GRAPH_INPUT => inputs.push(decode_value_name(value.bytes()?)?),
+                GRAPH_OUTPUT => outputs.push(decode_value_name(value.bytes()?)?),
+                _ => {}
+            }
+        }
+    }
+
+    let mut graph = Graph::new();
+    let mut values = HashMap::new();
+    for (name, tensor) in initializers {
+        values.insert(name, graph.constant(tensor));
+    }
+    // Older exporters list the initializers among the inputs too
+    for name in inputs {
+        values.entry(name).or_insert_with(|| graph.input());
+    }
+    for node in &nodes {
+        let output = import_node(&mut graph, &values, node)?;
+        if let Some(name) = node.outputs.first() {
+            values.insert(name.clone(), output);
+        }
+    }
+
+    let outputs = outputs
+        .iter()
+        .map(|name| {
+            values
+                .get(name)
+                .copied()
+                .ok_or_else(|| FormatError::Invalid(format!("graph output {}", name)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    graph.set_outputs(&outputs)?;
+    Ok(graph)
+}
+
+/// An operator of an ONNX graph, with the names of the values it reads and writes.
+struct OnnxNode {
+    op_type: String,
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+    attributes: Vec<(String, Attribute)>,
+}
+
+#[derive(Default)]
+struct Attribute {
+    float: Option<f32>,
+    int: Option<i64>,
+    string: Option<String>,
+    tensor: Option<Tensor>,
+    ints: Vec<i64>,
+}
+
+impl OnnxNode {
+    fn attribute(&self, name: &str) -> Option<&Attribute> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, attribute)| attribute)
+    }
+
+    fn float(&self, name: &str, default: f32) -> f32 {
+        self.attribute(name)
+            .and_then(|a| a.float)
+            .unwrap_or(default)
+    }
+
+    fn int(&self, name: &str, default: i64) -> i64 {
+        self.attribute(name).and_then(|a| a.int).unwrap_or(default)
+    }
+
+    fn ints(&self, name: &str) -> Option<&[i64]> {
+        self.attribute(name).map(|a| a.ints.as_slice())
+    }
+
+    fn unsupported(&self, what: &str) -> crate::MlError {
+        format!("Unsupported ONNX {}: {}", self.op_type, what).into()
+    }
+}
+
+fn decode_node(bytes: &[u8]) -> MlResult<OnnxNode> {
+    let mut node = OnnxNode {
+        op_type: String::new(),
+        inputs: Vec::new(),
+        outputs: Vec::new(),
+        attributes: Vec::new(),
+    };
+    let mut message = Message::new(bytes);
+    while let Some((field, value)) = message.next_field()? {
+        match field {
+            NODE_INPUT => node.inputs.push(decode_string(value.bytes()?)?),
+            NODE_OUTPUT => node.outputs.push(decode_string(value.bytes()?)?),
+            NODE_OP_TYPE => node.op_type = decode_string(value.bytes()?)?,
+            NODE_ATTRIBUTE => node.attributes.push(decode_attribute(value.bytes()?)?),
+            _ => {}
+        }
+    }
+    Ok(node)
+}
+
+fn decode_attribute(bytes: &[u8]) -> MlResult<(String, Attribute)> {
+    let mut name = String::new();
+    let mut attribute = Attribute::default();
+    let mut message = Message::new(bytes);
+    while let Some((field, value)) = message.next_field()? {
+        match field {
+            ATTRIBUTE_NAME => name = decode_string(value.bytes()?)?,
+            ATTRIBUTE_F => value.for_each_fixed32(|v| attribute.float = Some(f32::from_bits(v)))?,
+            ATTRIBUTE_I => attribute.int = Some(value.varint()? as i64),
+            ATTRIBUTE_S => attribute.string = Some(decode_string(value.bytes()?)?),
+            ATTRIBUTE_T => attribute.tensor = Some(decode_tensor(value.bytes()?)?.1),
+            ATTRIBUTE_INTS => value.for_each_varint(|v| attribute.ints.push(v as i64))?,
+            _ => {}
+        }
+    }
+    Ok((name, attribute))
+}
+
+fn decode_value_name(bytes: &[u8]) -> MlResult<String> {
+    let mut message = Message::new(bytes);
+    while let Some((field, value)) = message.next_field()? {
+        if field == VALUE_INFO_NAME {
+            return decode_string(value.bytes()?);
+        }
+    }
+    Err(FormatError::Invalid("graph input or output without a name".into()).into())
+}
+
+fn decode_string(bytes: &[u8]) -> MlResult<String> {
+    String::from_utf8(bytes.to_vec())
+        .map_err(|_| FormatError::Invalid("string is not UTF-8".into()).into())
+}
+
+// Adds the ops computing `node`'s first output to `graph`, returning the last of them
+fn import_node(
+    graph: &mut Graph,
+    values: &HashMap<String, NodeId>,
+    node: &OnnxNode,
+) -> MlResult<NodeId> {
+    // Optional inputs left out are empty names
+    let mut inputs = Vec::new();
+    for name in node.inputs.iter().filter(|name| !name.is_empty()) {
+        let id = values.get(name).ok_or_else(|| {
+            FormatError::Invalid(format!(
+                "{} reads {} before it is computed",
+                node.op_type, name
+            ))
+        })?;
+        inputs.push(*id);
+    }
+
+    let unary = |op| (op, 1);
+    let (op, arity) = match node.op_type.as_str() {
+        "Constant" => {
+            let value = node.attribute("value").and_then(|a| a.tensor.clone());
+            return Ok(graph.constant(value.ok_or_else(|| node.unsupported("no tensor value"))?));
+        }
+        "Identity" => {
+            return inputs
+                .first()
+                .copied()
+                .ok_or_else(|| node.unsupported("no input"))
+        }
+        "Gemm" => return import_gemm(graph, node, &inputs),
+        "MatMul" => (Op::MatMul, 2),
+        "Add" => (Op::Add, 2),
+        "Sub" => (Op::Sub, 2),
+        "Mul" => (Op::Mul, 2),
+        "Div" => (Op::Div, 2),
+        "Relu" => unary(Op::Relu),
+        "Sigmoid" => unary(Op::Sigmoid),
+        "Tanh" => unary(Op::Tanh),
+        "Exp" => unary(Op::Exp),
+        "Log" => unary(Op::Log),
+        "Neg" => unary(Op::Neg),
+        "Sqrt" => unary(Op::Sqrt),
+        "Transpose" => match node.ints("perm") {
+            None | Some([1, 0]) => unary(Op::Transpose),
+            Some(perm) if perm.iter().all(|&axis| axis >= 0) => unary(Op::Permute(
+                perm.iter().map(|&axis| axis as usize).collect(),
+            )),
+            Some(_) => return Err(node.unsupported("negative axes in perm")),
+        },
+        "Flatten" => match node.int("axis", 1) {
+            axis @ 0.. => unary(Op::Flatten(axis as usize)),
+            _ => return Err(node.unsupported("negative axis")),
+        },
+        "BatchNormalization" => {
+            let epsilon = node.float("epsilon", 1e-5);
+            inputs.truncate(5);
+            (Op::BatchNorm { epsilon }, 5)
+        }
+        "Conv" => {
+            let op = conv_op(node)?;
+            (op, inputs.len().clamp(2, 3))
+        }
+        _ => return Err(format!("Unsupported ONNX operator {}", node.op_type).into()),
+    };
+    if inputs.len() != arity {
+        return Err(node.unsupported(&format!("{} inputs", inputs.len())));
+    }
+    graph.push(op, &inputs)
+}
+
+// Gemm is alpha * A' @ B' + beta * C, with A' and B' optionally transposed
+fn import_gemm(graph: &mut Graph, node: &OnnxNode, inputs: &[NodeId]) -> MlResult<NodeId> {
+    let (&a, &b) = match inputs {
+        [a, b] | [a, b, _] => (a, b),
+        _ => return Err(node.unsupported(&format!("{} inputs", inputs.len()))),
+    };
+    let a = match node.int("transA", 0) {
+        0 => a,
+        _ => graph.push(Op::Transpose, &[a])?,
+    };
+    let b = match node.int("transB", 0) {
+        0 => b,
+        _ => graph.push(Op::Transpose, &[b])?,
+    };
+    let mut output = graph.push(Op::MatMul, &[a, b])?;
+    let alpha = node.float("alpha", 1.0);
+    if alpha != 1.0 {
+        output = graph.push(Op::MulScalar(alpha), &[output])?;
+    }
+    if let Some(&c) = inputs.get(2) {
+        let beta = node.float("beta", 1.0);
+        let c = if beta == 1.0 {
+            c
+        } else {
+            graph.push(Op::MulScalar(beta), &[c])?
+        };
+        output = graph.push(Op::Add, &[output, c])?;
+    }
+    Ok(output)
+}
+
+fn conv_op(node: &OnnxNode) -> MlResult<Op> {
+    let groups = match node.int("group", 1) {
+        groups if groups > 0 => groups as usize,
+        _ => return Err(node.unsupported("group")),
+    };
+    if node
+        .ints("dilations")
+        .is_some_and(|d| d.iter().any(|&d| d != 1))
+    {
+        return Err(node.unsupported("dilation"));
+    }
+    let auto_pad = node.attribute("auto_pad").and_then(|a| a.string.as_deref());
+    if auto_pad.is_some_and(|pad| pad != "NOTSET") {
+        return Err(node.unsupported("auto_pad"));
+    }
+
+    let stride = match node.ints("strides") {
+        None => (1, 1),
+        Some(&[h, w]) if h > 0 && w > 0 => (h as usize, w as usize),
+        Some(_) => return Err(node.unsupported("strides")),
+    };
+    let padding = match node.ints("pads") {
+        None => (0, 0),
+        Some(&[top, left, bottom, right])
+            if top == bottom && left == right && top >= 0 && left >= 0 =>
+        {
+            (top as usize, left as usize)
+        }
+        Some(_) => return Err(node.unsupported("asymmetric padding")),
+    };
+    Ok(Op::Conv2d {
+        stride,
+        padding,
+        groups,
+    })
+}
+
+fn decode_tensor(bytes: &[u8]) -> MlResult<(String, Tensor)> {
+    let mut dims = Vec::new();
+    let mut data_type = 0;
+    let mut name = String::new();
+    let mut raw = None;
+    let mut values = Vec::new();
+    let mut int32 = Vec::new();
+
+    let mut tensor = Message::new(bytes);
+    while let Some((field, value)) = tensor.next_field()? {
+        match field {
+            TENSOR_DIMS => value.for_each_varint(|dim| dims.push(dim as usize))?,
+            TENSOR_DATA_TYPE => data_type = value.varint()?,
+            TENSOR_NAME => {
+                name = String::from_utf8(value.bytes()?.to_vec())
+                    .map_err(|_| FormatError::Invalid("initializer name is not UTF-8".into()))?
+            }
+            TENSOR_RAW_DATA => raw = Some(value.bytes()?),
+            TENSOR_FLOAT_DATA => value.for_each_fixed32(|v| values.push(f32::from_bits(v)))?,
+            TENSOR_DOUBLE_DATA => {
+                value.for_each_fixed64(|v| values.push(f64::from_bits(v) as f32))?
+            }
+            TENSOR_INT32_DATA => value.for_each_varint(|v| int32.push(v))?,
+            TENSOR_INT64_DATA => value.for_each_varint(|v| values.push(v as i64 as f32))?,
+            TENSOR_UINT64_DATA => value.for_each_varint(|v| values.push(v as f32))?,
+            TENSOR_DATA_LOCATION if value.varint()? != 0 => {
+                return Err(format!("Initializer {} is stored in an external file", name).into())
+            }
+            _ => {}
+        }
+    }
+
+    // Narrow types are stored widened to int32, and half floats as their bit pattern
+    values.extend(int32.into_iter().map(|v| match data_type {
+        FLOAT16 => f16_to_f32(v as u16),
+        BFLOAT16 => f32::from_bits((v as u32) << 16),
+        _ => v as i32 as f32,
+    }));
+
+    if let Some(raw) = raw {
+        let (size, convert) = raw_decoder(data_type)
+            .ok_or_else(|| format!("Unsupported ONNX data type {} of {}", data_type, name))?;
+        if raw.len() % size != 0 {
+            return Err(FormatError::Invalid(format!("raw data length of {}", name)).into());
+        }
+        values = raw.chunks_exact(size).map(convert).collect();
+    } else if raw_decoder(data_type).is_none() {
+        return Err(format!("Unsupported ONNX data type {} of {}", data_type, name).into());
+    }
+
+    let tensor = Tensor::from_vec(values, &dims)?;
+    Ok((name, tensor))
+}
+
+type Decoder = fn(&[u8]) -> f32;
+
+// The element size of a data type in `raw_data`, which is little-endian, and how to convert
+// one element to f32
+fn raw_decoder(data_type: u64) -> Option<(usize, Decoder)> {
+    Some(match data_type {
+        FLOAT => (4, |b| f32::from_le_bytes(b.try_into().unwrap())),
+        DOUBLE => (8, |b| f64::from_le_bytes(b.try_into().unwrap()) as f32),
+        FLOAT16 => (2, |b| f16_to_f32(u16::from_le_bytes([b[0], b[1]]))),
+        BFLOAT16 => (2, |b| {
+            f32::from_bits((u16::from_le_bytes([b[0], b[1]]) as u32) << 16)
+        }),
+        INT8 => (1, |b| b[0] as i8 as f32),
+        UINT8 | BOOL => (1, |b| b[0] as f32),
+        INT16 => (2, |b| i16::from_le_bytes([b[0], b[1]]) as f32),
+        UINT16 => (2, |b| u16::from_le_bytes([b[0], b[1]]) as f32),
+        INT32 => (4, |b| i32::from_le_bytes(b.try_into().unwrap()) as f32),
+        UINT32 => (4, |b| u32::from_le_bytes(b.try_into().unwrap()) as f32),
+        INT64 => (8, |b| i64::from_le_bytes(b.try_into().unwrap()) as f32),
+        UINT64 => (8, |b| u64::from_le_bytes(b.try_into().unwrap()) as f32),
+        _ => return None,
+    })
+}
+
+fn encode_model(state: &StateDict) -> Vec<u8> {
+    let mut graph = Vec::new();
+    put_bytes(&mut graph, GRAPH_NAME, b"cetana");
+    for (name, tensor) in state.iter() {
+        let mut proto = Vec::new();
+        let mut dims = Vec::new();
+        for &dim in tensor.shape() {
+            put_varint(&mut dims, dim as u64);
+        }
+        put_bytes(&mut proto, TENSOR_DIMS, &dims);
+        put_key(&mut proto, TENSOR_DATA_TYPE, WIRE_VARINT);
+        put_varint(&mut proto, FLOAT);
+        put_bytes(&mut proto, TENSOR_NAME, name.as_bytes());
+        let raw: Vec<u8> = tensor.data().iter().flat_map(|v| v.to_le_bytes()).collect();
+        put_bytes(&mut proto, TENSOR_RAW_DATA, &raw);
+        put_bytes(&mut graph, GRAPH_INITIALIZER, &proto);
+    }
+
+    let mut opset = Vec::new();
+    put_key(&mut opset, OPSET_VERSION, WIRE_VARINT);
+    put_varint(&mut opset, OPSET);
+
+    let mut model = Vec::new();
+    put_key(&mut model, MODEL_IR_VERSION, WIRE_VARINT);
+    put_varint(&mut model, IR_VERSION);
+    put_bytes(&mut model, MODEL_PRODUCER_NAME, b"cetana");
+    put_bytes(&mut model, MODEL_GRAPH, &graph);
+    put_bytes(&mut model, MODEL_OPSET_IMPORT, &opset);
+    model
+}
+
+const WIRE_VARINT: u8 = 0;
+const WIRE_FIXED64: u8 = 1;
+const WIRE_LEN: u8 = 2;
+const WIRE_FIXED32: u8 = 5;
+
+fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn put_key(out: &mut Vec<u8>, field: u32, wire: u8) {
+    put_varint(out, (field as u64) << 3 | wire as u64);
+}
+
+fn put_bytes(out: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+    put_key(out, field, WIRE_LEN);
+    put_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+/// One field of a protobuf message, as it appears on the wire.
+enum Field<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Fixed32(u32),
+    Bytes(&'a [u8]),
+}
+
+impl<'a> Field<'a> {
+    fn wrong_type() -> FormatError {
+        FormatError::Invalid("unexpected protobuf wire type".into())
+    }
+
+    fn varint(&self) -> Result<u64, FormatError> {
+        match self {
+            Field::Varint(value) => Ok(*value),
+            _ => Err(Self::wrong_type()),
+        }
+    }
+
+    fn bytes(&self) -> Result<&'a [u8], FormatError> {
+        match self {
+            Field::Bytes(bytes) => Ok(bytes),
+            _ => Err(Self::wrong_type()),
+        }
+    }
+
+    // Repeated scalars come one per field or packed into a single length-delimited field
+
+    fn for_each_varint(&self, mut f: impl FnMut(u64)) -> Result<(), FormatError> {
+        match self {
+            Field::Varint(value) => f(*value),
+            Field::Bytes(packed) => {
+                let mut message = Message::new(packed);
+                while message.pos < packed.len() {
+                    f(message.varint()?);
+                }
+            }
+            _ => return Err(Self::wrong_type()),
+        }
+        Ok(())
+    }
+
+    fn for_each_fixed32(&self, mut f: impl FnMut(u32)) -> Result<(), FormatError> {
+        match self {
+            Field::Fixed32(value) => f(*value),
+            Field::Bytes(packed) if packed.len() % 4 == 0 => packed
+                .chunks_exact(4)
+                .for_each(|v| f(u32::from_le_bytes(v.try_into().unwrap()))),
+            _ => return Err(Self::wrong_type()),
+        }
+        Ok(())
+    }
+
+    fn for_each_fixed64(&self, mut f: impl FnMut(u64)) -> Result<(), FormatError> {
+        match self {
+            Field::Fixed64(value) => f(*value),
+            Field::Bytes(packed) if packed.len() % 8 == 0 => packed
+                .chunks_exact(8)
+                .for_each(|v| f(u64::from_le_bytes(v.try_into().unwrap()))),
+            _ => return Err(Self::wrong_type()),
+        }
+        Ok(())
+    }
+}
+
+/// Walks the fields of an encoded protobuf message.
+struct Message<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Message<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], FormatError> {
+        let available = self.bytes.len() - self.pos;
+        if len > available {
+            return Err(FormatError::Truncated {
+                needed: len,
+                available,
+            });
+        }
+        let bytes = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn varint(&mut self) -> Result<u64, FormatError> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(FormatError::Invalid("protobuf varint is too long".into()))
+    }
+
+    fn next_field(&mut self) -> Result<Option<(u32, Field<'a>)>, FormatError> {
+        if self.pos == self.bytes.len() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        let field = match (key & 7) as u8 {
+            WIRE_VARINT => Field::Varint(self.varint()?),
+            WIRE_FIXED64 => Field::Fixed64(u64::from_le_bytes(self.take(8)?.try_into().unwrap())),
+            WIRE_LEN => {
+                let len = self.varint()?;
+                let len = usize::try_from(len).map_err(|_| Field::wrong_type())?;
+                Field::Bytes(self.take(len)?)
+            }
+            WIRE_FIXED32 => Field::Fixed32(u32::from_le_bytes(self.take(4)?.try_into().unwrap())),
+            _ => return Err(Field::wrong_type()),
+        };
+        Ok(Some(((key >> 3) as u32, field)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_onnx_initializers() -> MlResult<()> {
+        let mut state = StateDict::new();
+        state.insert(
+            "fc.weight",
+            Tensor::from_vec(vec![1.0, -2.0, 3.5, 4.0], &[2, 2])?,
+        );
+        state.insert("fc.bias", Tensor::from_vec(vec![0.25, 0.5], &[2])?);
+        let back = decode_model(&encode_model(&state))?;
+        assert_eq!(back.keys().collect::<Vec<_>>(), ["fc.weight", "fc.bias"]);
+        assert_eq!(back.get("fc.weight").unwrap().shape(), &[2, 2]);
+        assert_eq!(
+            back.get("fc.weight").unwrap().data(),
+            &[1.0, -2.0, 3.5, 4.0]
+        );
+
+        // Typed value fields, with the dims unpacked and the data type after the data
+        let mut tensor = Vec::new();
+        put_key(&mut tensor, TENSOR_DIMS, WIRE_VARINT);
+        put_varint(&mut tensor, 3);
+        put_bytes(&mut tensor, TENSOR_NAME, b"steps");
+        let mut packed = Vec::new();
+        for value in [-1i64, 0, 300] {
+            put_varint(&mut packed, value as u64);
+        }
+        put_bytes(&mut tensor, TENSOR_INT64_DATA, &packed);
+        put_key(&mut tensor, TENSOR_DATA_TYPE, WIRE_VARINT);
+        put_varint(&mut tensor, INT64);
+        let mut graph = Vec::new();
+        put_bytes(&mut graph, GRAPH_INITIALIZER, &tensor);
+        let mut model = Vec::new();
+        put_bytes(&mut model, MODEL_GRAPH, &graph);
+        let state = decode_model(&model)?;
+        assert_eq!(state.get("steps").unwrap().data(), &[-1.0, 0.0, 300.0]);
+
+        // Cut off inside the graph
+        assert!(decode_model(&model[..model.len() - 2]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_onnx_graph() -> MlResult<()> {
+        use crate::nn::Layer;
+
+        let value_info = |name: &str| {
+            let mut info = Vec::new();
+            put_bytes(&mut info, VALUE_INFO_NAME, name.as_bytes());
+            info
+        };
+        let node = |op: &str, inputs: &[&str], output: &str, attributes: &[Vec<u8>]| {
+            let mut node = Vec::new();
+            for input in inputs {
+                put_bytes(&mut node, NODE_INPUT, input.as_bytes());
+            }
+            put_bytes(&mut node, NODE_OUTPUT, output.as_bytes());
+            put_bytes(&mut node, NODE_OP_TYPE, op.as_bytes());
+            for attribute in attributes {
+                put_bytes(&mut node, NODE_ATTRIBUTE, attribute);
+            }
+            node
+        };
+
+        // relu(x @ w^T + b) as a Gemm with transB, the initializers in their own graph field
+        let mut state = StateDict::new();
+        state.insert(
+            "w",
+            Tensor::from_vec(vec![1.0, 0.0, 1.0, 2.0, 1.0, 0.5], &[2, 3])?,
+        );
+        state.insert("b", Tensor::from_vec(vec![0.5, -2.0], &[2])?);
+        let mut model = encode_model(&state);
+        let mut trans_b = Vec::new();
+        put_bytes(&mut trans_b, ATTRIBUTE_NAME, b"transB");
+        put_key(&mut trans_b, ATTRIBUTE_I, WIRE_VARINT);
+        put_varint(&mut trans_b, 1);
+        let mut graph = Vec::new();
+        put_bytes(&mut graph, GRAPH_INPUT, &value_info("x"));
+        put_bytes(
+            &mut graph,
+            GRAPH_NODE,
+            &node("Gemm", &["x", "w", "b"], "h", &[trans_b]),
+        );
+        put_bytes(&mut graph, GRAPH_NODE, &node("Relu", &["h"], "y", &[]));
+        put_bytes(&mut graph, GRAPH_OUTPUT, &value_info("y"));
+        put_bytes(&mut model, MODEL_GRAPH, &graph);
+
+        let mut graph = decode_graph(&model)?;
+        assert_eq!(graph.num_inputs(), 1);
+        let x = Tensor::from_vec(vec![1.0, 2.0, 3.0], &[1, 3])?;
+        assert_eq!(graph.forward(&x)?.data(), &[4.5, 3.5]);
+
+        // Optimizing transposes the weight once, ahead of time
+        graph.optimize()?;
+        assert!(graph.nodes().iter().all(|node| node.op != Op::Transpose));
+        assert_eq!(graph.forward(&x)?.data(), &[4.5, 3.5]);
+
+        let mut graph = Vec::new();
+        put_bytes(&mut graph, GRAPH_INPUT, &value_info("x"));
+        put_bytes(&mut graph, GRAPH_NODE, &node("Softmax", &["x"], "y", &[]));
+        let mut model = Vec::new();
+        put_bytes(&mut model, MODEL_GRAPH, &graph);
+        assert!(decode_graph(&model).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,646 @@
+//! Importing PyTorch checkpoints saved with `torch.save`.
+//!
+//! Since PyTorch 1.6 a checkpoint is a zip archive holding `data.pkl`, a pickle of the saved
+//! object, and one `data/<key>` entry per tensor storage. The pickle is read by a restricted
+//! unpickler that understands plain containers, numbers and strings plus the handful of
+//! `torch` and `collections` callables a state dict is built from, and refuses anything else,
+//! so loading a checkpoint can never run code from it.
+//!
+//! Tensors of any dtype are converted to f32. Nested dicts are flattened with `.` between
+//! keys, so `{"model": {"fc.weight": ...}}` gives `model.fc.weight`; non-tensor values such
+//! as epoch numbers are left out.
+
+use std::collections::HashMap;
+use std::io::Read;
+#[cfg(feature = "fs")]
+use std::path::Path;
+use std::rc::Rc;
+
+use super::format;
+use super::npy::f16_to_f32;
+use super::zip::{ZipEntry, ZipReader};
+use super::StateDict;
+use crate::tensor::Tensor;
+use crate::MlResult;
+
+/// Reads the tensors in the PyTorch checkpoint at `path`, keyed by their state dict names.
+///
+/// The result loads into a layer with [`Layer::load_state_dict`](crate::nn::Layer::load_state_dict).
+#[cfg(feature = "fs")]
+pub fn load_pt<P: AsRef<Path>>(path: P) -> MlResult<StateDict> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to read file: {}", e))?;
+    decode(&bytes)
+}
+
+/// Like [`load_pt`], reading the checkpoint's bytes from `reader` to its end.
+pub fn read_pt<R: Read>(reader: R) -> MlResult<StateDict> {
+    decode(&format::read_to_end(reader)?)
+}
+
+fn decode(bytes: &[u8]) -> MlResult<StateDict> {
+    if !bytes.starts_with(b"PK\x03\x04") {
+        return Err(
+            "Not a zip-based PyTorch checkpoint; files from before PyTorch 1.6 need to be \
+             re-saved with torch.save"
+                .into(),
+        );
+    }
+
+    let archive = ZipReader { bytes };
+    let entries = archive.entries()?;
+    let pickle = entries
+        .iter()
+        .find(|entry| entry.name == "data.pkl" || entry.name.ends_with("/data.pkl"))
+        .ok_or("PyTorch checkpoint has no data.pkl")?;
+    let prefix = &pickle.name[..pickle.name.len() - "data.pkl".len()];
+
+    let big_endian = match find_entry(&entries, &format!("{}byteorder", prefix)) {
+        Some(entry) => archive.extract(entry)? == b"big",
+        None => false,
+    };
+
+    let mut unpickler = Unpickler {
+        data: &archive.extract(pickle)?,
+        pos: 0,
+        stack: Vec::new(),
+        marks: Vec::new(),
+        memo: HashMap::new(),
+        storages: HashMap::new(),
+        load_storage: &mut |key: &str, kind: StorageKind| {
+            let name = format!("{}data/{}", prefix, key);
+            let entry = find_entry(&entries, &name)
+                .ok_or_else(|| format!("PyTorch checkpoint is missing storage {}", key))?;
+            Ok(kind.decode(&archive.extract(entry)?, big_endian))
+        },
+    };
+
+    let value = unpickler.load()?;
+    drop(unpickler);
+
+    let mut state = StateDict::new();
+    flatten(value, "", &mut state)?;
+    Ok(state)
+}
+
+fn find_entry<'a>(entries: &'a [ZipEntry], name: &str) -> Option<&'a ZipEntry> {
+    entries.iter().find(|entry| entry.name == name)
+}
+
+fn flatten(value: Value, prefix: &str, state: &mut StateDict) -> MlResult<()> {
+    let Value::Dict(items) = value else {
+        return Err("PyTorch checkpoint doesn't hold a dict of tensors".into());
+    };
+
+    for (key, value) in items {
+        let key = match key {
+            Value::Str(key) => format!("{}{}", prefix, key),
+            Value::Int(key) => format!("{}{}", prefix, key),
+            _ => continue,
+        };
+        match value {
+            Value::Tensor(tensor) => {
+                state.insert(key, Rc::try_unwrap(tensor).unwrap_or_else(|t| (*t).clone()));
+            }
+            Value::Dict(_) => flatten(value, &format!("{}.", key), state)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// The element type of a storage, from its `torch.*Storage` class.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StorageKind {
+    F32,
+    F64,
+    F16,
+    BF16,
+    I64,
+    I32,
+    I16,
+    I8,
+    U8,
+    Bool,
+}
+
+impl StorageKind {
+    fn from_class(name: &str) -> Option<Self> {
+        Some(match name {
+            "torch.FloatStorage" => StorageKind::F32,
+            "torch.DoubleStorage" => StorageKind::F64,
+            "torch.HalfStorage" => StorageKind::F16,
+            "torch.BFloat16Storage" => StorageKind::BF16,
+            "torch.LongStorage" => StorageKind::I64,
+            "torch.IntStorage" => StorageKind::I32,
+            "torch.ShortStorage" => StorageKind::I16,
+            "torch.CharStorage" => StorageKind::I8,
+            "torch.ByteStorage" => StorageKind::U8,
+            "torch.BoolStorage" => StorageKind::Bool,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            StorageKind::F64 | StorageKind::I64 => 8,
+            StorageKind::F32 | StorageKind::I32 => 4,
+            StorageKind::F16 | StorageKind::BF16 | StorageKind::I16 => 2,
+            StorageKind::I8 | StorageKind::U8 | StorageKind::Bool => 1,
+        }
+    }
+
+    fn decode(self, bytes: &[u8], big_endian: bool) -> Vec<f32> {
+        bytes
+            .chunks_exact(self.size())
+            .map(|chunk| {
+                let mut raw = [0u8; 8];
+                raw[..chunk.len()].copy_from_slice(chunk);
+                if big_endian {
+                    raw[..chunk.len()].reverse();
+                }
+                match self {
+                    StorageKind::F32 => f32::from_le_bytes(raw[..4].try_into().unwrap()),
+                    StorageKind::F64 => f64::from_le_bytes(raw) as f32,
+                    StorageKind::F16 => f16_to_f32(u16::from_le_bytes([raw[0], raw[1]])),
+                    StorageKind::BF16 => {
+                        f32::from_bits((u16::from_le_bytes([raw[0], raw[1]]) as u32) << 16)
+                    }
+                    StorageKind::I64 => i64::from_le_bytes(raw) as f32,
+                    StorageKind::I32 => i32::from_le_bytes(raw[..4].try_into().unwrap()) as f32,
+                    StorageKind::I16 => i16::from_le_bytes([raw[0], raw[1]]) as f32,
+                    StorageKind::I8 => raw[0] as i8 as f32,
+                    StorageKind::U8 => raw[0] as f32,
+                    StorageKind::Bool => (raw[0] != 0) as u8 as f32,
+                }
+            })
+            .collect()
+    }
+}
+
+/// The values a state dict pickle is made of.
+#[derive(Debug, Clone)]
+enum Value {
+    None,
+    Int(i64),
+    Str(String),
+    /// Booleans, floats and bytes, which a state dict only uses for things skipped here.
+    Other,
+    Tuple(Vec<Value>),
+    List(Vec<Value>),
+    Dict(Vec<(Value, Value)>),
+    /// A class or function allowed by [`ALLOWED_GLOBALS`], by its qualified name.
+    Global(String),
+    Storage(String),
+    /// Shared, since the memo keeps a reference to every tensor.
+    Tensor(Rc<Tensor>),
+}
+
+const ALLOWED_GLOBALS: &[&str] = &[
+    "collections.OrderedDict",
+    "torch._utils._rebuild_tensor",
+    "torch._utils._rebuild_tensor_v2",
+    "torch._utils._rebuild_parameter",
+    "torch._utils._rebuild_parameter_with_state",
+];
+
+type StorageLoader<'a> = dyn FnMut(&str, StorageKind) -> MlResult<Vec<f32>> + 'a;
+
+struct Unpickler<'a> {
+    data: &'a [u8],
+    pos: usize,
+    stack: Vec<Value>,
+    marks: Vec<usize>,
+    memo: HashMap<u32, Value>,
+    /// Storages already read, shared by every tensor that views them.
+    storages: HashMap<String, Vec<f32>>,
+    load_storage: &'a mut StorageLoader<'a>,
+}
+
+impl Unpickler<'_> {
+    fn take(&mut self, len: usize) -> MlResult<&[u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.data.len())
+            .ok_or("Truncated pickle in PyTorch checkpoint")?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> MlResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn uint(&mut self, len: usize) -> MlResult<u64> {
+        let mut raw = [0u8; 8];
+        raw[..len].copy_from_slice(self.take(len)?);
+        Ok(u64::from_le_bytes(raw))
+    }
+
+    fn line(&mut self) -> MlResult<String> {
+        let len = self.data[self.pos..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or("Truncated pickle in PyTorch checkpoint")?;
+        let line = String::from_utf8_lossy(self.take(len)?).into_owned();
+        self.pos += 1;
+        Ok(line)
+    }
+
+    fn string(&mut self, len: usize) -> MlResult<Value> {
+        let bytes = self.take(len)?;
+        Ok(Value::Str(String::from_utf8_lossy(bytes).into_owned()))
+    }
+
+    fn pop(&mut self) -> MlResult<Value> {
+        self.stack
+            .pop()
+            .ok_or_else(|| "Corrupt pickle in PyTorch checkpoint: stack underflow".into())
+    }
+
+    fn pop_mark(&mut self) -> MlResult<Vec<Value>> {
+        let mark = self
+            .marks
+            .pop()
+            .ok_or("Corrupt pickle in PyTorch checkpoint: missing mark")?;
+        if mark > self.stack.len() {
+            return Err("Corrupt pickle in PyTorch checkpoint: missing mark".into());
+        }
+        Ok(self.stack.split_off(mark))
+    }
+
+    fn top(&mut self) -> MlResult<&mut Value> {
+        self.stack
+            .last_mut()
+            .ok_or_else(|| "Corrupt pickle in PyTorch checkpoint: stack underflow".into())
+    }
+
+    fn global(&self, name: String) -> MlResult<Value> {
+        if ALLOWED_GLOBALS.contains(&name.as_str()) || StorageKind::from_class(&name).is_some() {
+            Ok(Value::Global(name))
+        } else {
+            Err(format!(
+                "Refusing to load {} from a PyTorch checkpoint: only tensor state dicts are supported",
+                name
+            )
+            .into())
+        }
+    }
+
+    fn memo_key(&self) -> u32 {
+        self.memo.len() as u32
+    }
+
+    fn load(&mut self) -> MlResult<Value> {
+        loop {
+            let op = self.u8()?;
+            match op {
+                0x80 => {
+                    self.u8()?; // PROTO
+                }
+                0x95 => {
+                    self.take(8)?; // FRAME
+                }
+                b'.' => return self.pop(),
+                b'(' => self.marks.push(self.stack.len()),
+                b'N' => self.stack.push(Value::None),
+                0x88 | 0x89 => self.stack.push(Value::Other),
+                b'J' => {
+                    let value = self.uint(4)? as u32 as i32;
+                    self.stack.push(Value::Int(value as i64));
+                }
+                b'K' => {
+                    let value = self.uint(1)?;
+                    self.stack.push(Value::Int(value as i64));
+                }
+                b'M' => {
+                    let value = self.uint(2)?;
+                    self.stack.push(Value::Int(value as i64));
+                }
+                0x8a => {
+                    // LONG1: little-endian two's complement of the given length
+                    let len = self.u8()? as usize;
+                    if len > 8 {
+                        return Err("Integer too large in PyTorch checkpoint".into());
+                    }
+                    let bytes = self.take(len)?;
+                    let fill = if bytes.last().is_some_and(|&b| b & 0x80 != 0) {
+                        0xff
+                    } else {
+                        0
+                    };
+                    let mut raw = [fill; 8];
+                    raw[..len].copy_from_slice(bytes);
+                    self.stack.push(Value::Int(i64::from_le_bytes(raw)));
+                }
+                b'G' => {
+                    self.take(8)?;
+                    self.stack.push(Value::Other);
+                }
+                b'X' | b'T' => {
+                    let len = self.uint(4)? as usize;
+                    let value = self.string(len)?;
+                    self.stack.push(value);
+                }
+                0x8c | b'U' => {
+                    let len = self.u8()? as usize;
+                    let value = self.string(len)?;
+                    self.stack.push(value);
+                }
+                0x8d => {
+                    let len = self.uint(8)? as usize;
+                    let value = self.string(len)?;
+                    self.stack.push(value);
+                }
+                b'B' | b'C' | 0x8e => {
+                    let len = match op {
+                        b'B' => self.uint(4)?,
+                        b'C' => self.uint(1)?,
+                        _ => self.uint(8)?,
+                    } as usize;
+                    self.take(len)?;
+                    self.stack.push(Value::Other);
+                }
+                b')' => self.stack.push(Value::Tuple(Vec::new())),
+                b't' => {
+                    let items = self.pop_mark()?;
+                    self.stack.push(Value::Tuple(items));
+                }
+                0x85..=0x87 => {
+                    let len = (op - 0x84) as usize;
+                    if self.stack.len() < len {
+                        return Err("Corrupt pickle in PyTorch checkpoint: stack underflow".into());
+                    }
+                    let items = self.stack.split_off(self.stack.len() - len);
+                    self.stack.push(Value::Tuple(items));
+                }
+                b']' => self.stack.push(Value::List(Vec::new())),
+                b'l' => {
+                    let items = self.pop_mark()?;
+                    self.stack.push(Value::List(items));
+                }
+                b'}' => self.stack.push(Value::Dict(Vec::new())),
+                b'd' => {
+                    let items = self.pop_mark()?;
+                    let pairs = pairs(items)?;
+                    self.stack.push(Value::Dict(pairs));
+                }
+                b'a' => {
+                    let item = self.pop()?;
+                    match self.top()? {
+                        Value::List(list) => list.push(item),
+                        _ => return Err("Corrupt pickle in PyTorch checkpoint: APPEND".into()),
+                    }
+                }
+                b'e' => {
+                    let items = self.pop_mark()?;
+                    match self.top()? {
+                        Value::List(list) => list.extend(items),
+                        _ => return Err("Corrupt pickle in PyTorch checkpoint: APPENDS".into()),
+                    }
+                }
+                b's' => {
+                    let value = self.pop()?;
+                    let key = self.pop()?;
+                    match self.top()? {
+                        Value::Dict(dict) => dict.push((key, value)),
+                        _ => return Err("Corrupt pickle in PyTorch checkpoint: SETITEM".into()),
+                    }
+                }
+                b'u' => {
+                    let items = pairs(self.pop_mark()?)?;
+                    match self.top()? {
+                        Value::Dict(dict) => dict.extend(items),
+                        _ => return Err("Corrupt pickle in PyTorch checkpoint: SETITEMS".into()),
+                    }
+                }
+                b'q' | b'r' | 0x94 => {
+                    let key = match op {
+                        b'q' => self.uint(1)? as u32,
+                        b'r' => self.uint(4)? as u32,
+                        _ => self.memo_key(),
+                    };
+                    let value = self.top()?.clone();
+                    self.memo.insert(key, value);
+                }
+                b'h' | b'j' => {
+                    let key = if op == b'h' {
+                        self.uint(1)?
+                    } else {
+                        self.uint(4)?
+                    } as u32;
+                    let value = self
+                        .memo
+                        .get(&key)
+                        .cloned()
+                        .ok_or("Corrupt pickle in PyTorch checkpoint: unknown memo key")?;
+                    self.stack.push(value);
+                }
+                b'c' => {
+                    let module = self.line()?;
+                    let name = self.line()?;
+                    let value = self.global(format!("{}.{}", module, name))?;
+                    self.stack.push(value);
+                }
+                0x93 => {
+                    let name = self.pop()?;
+                    let module = self.pop()?;
+                    let (Value::Str(module), Value::Str(name)) = (module, name) else {
+                        return Err("Corrupt pickle in PyTorch checkpoint: STACK_GLOBAL".into());
+                    };
+                    let value = self.global(format!("{}.{}", module, name))?;
+                    self.stack.push(value);
+                }
+                b'Q' => {
+                    let pid = self.pop()?;
+                    let value = self.persistent_load(pid)?;
+                    self.stack.push(value);
+                }
+                b'R' | 0x81 => {
+                    let args = self.pop()?;
+                    let callable = self.pop()?;
+                    let value = self.call(callable, args)?;
+                    self.stack.push(value);
+                }
+                b'b' => {
+                    // BUILD sets attributes such as a state dict's `_metadata`, which
+                    // nothing here needs
+                    self.pop()?;
+                }
+                _ => {
+                    return Err(format!(
+                        "Unsupported pickle opcode 0x{:02x} in PyTorch checkpoint",
+                        op
+                    )
+                    .into())
+                }
+            }
+        }
+    }
+
+    // ('storage', storage class, key, location, numel)
+    fn persistent_load(&mut self, pid: Value) -> MlResult<Value> {
+        let Value::Tuple(pid) = pid else {
+            return Err("Unsupported persistent id in PyTorch checkpoint".into());
+        };
+        let (Some(Value::Global(class)), Some(Value::Str(key))) = (pid.get(1), pid.get(2)) else {
+            return Err("Unsupported persistent id in PyTorch checkpoint".into());
+        };
+        let kind = StorageKind::from_class(class)
+            .ok_or_else(|| format!("Unsupported storage type {} in PyTorch checkpoint", class))?;
+
+        if !self.storages.contains_key(key) {
+            let data = (self.load_storage)(key, kind)?;
+            self.storages.insert(key.clone(), data);
+        }
+        Ok(Value::Storage(key.clone()))
+    }
+
+    fn call(&mut self, callable: Value, args: Value) -> MlResult<Value> {
+        let (Value::Global(name), Value::Tuple(args)) = (callable, args) else {
+            return Err("Unsupported call in PyTorch checkpoint".into());
+        };
+
+        match name.as_str() {
+            "collections.OrderedDict" => Ok(Value::Dict(Vec::new())),
+            "torch._utils._rebuild_tensor" | "torch._utils._rebuild_tensor_v2" => {
+                self.rebuild_tensor(&args)
+            }
+            "torch._utils._rebuild_parameter" | "torch._utils._rebuild_parameter_with_state" => {
+                args.into_iter()
+                    .next()
+                    .ok_or_else(|| "Corrupt parameter in PyTorch checkpoint".into())
+            }
+            _ => Err(format!("Unsupported call to {} in PyTorch checkpoint", name).into()),
+        }
+    }
+
+    // (storage, storage_offset, size, stride, ...): gathers the strided view into a
+    // contiguous tensor
+    fn rebuild_tensor(&self, args: &[Value]) -> MlResult<Value> {
+        let (Some(Value::Storage(key)), Some(Value::Int(offset)), Some(size), Some(stride)) =
+            (args.first(), args.get(1), args.get(2), args.get(3))
+        else {
+            return Err("Corrupt tensor in PyTorch checkpoint".into());
+        };
+        let storage = &self.storages[key];
+        let shape = dims(size)?;
+        let strides = dims(stride)?;
+        if shape.len() != strides.len() {
+            return Err("Corrupt tensor in PyTorch checkpoint".into());
+        }
+
+        let count: usize = shape.iter().product();
+        let mut data = Vec::with_capacity(count);
+        let mut index = vec![0; shape.len()];
+        for _ in 0..count {
+            let at = *offset as usize
+                + index
+                    .iter()
+                    .zip(&strides)
+                    .map(|(i, s)| i * s)
+                    .sum::<usize>();
+            data.push(
+                *storage
+                    .get(at)
+                    .ok_or("Tensor reaches past its storage in PyTorch checkpoint")?,
+            );
+            for axis in (0..shape.len()).rev() {
+                index[axis] += 1;
+                if index[axis] < shape[axis] {
+                    break;
+                }
+                index[axis] = 0;
+            }
+        }
+        Ok(Value::Tensor(Rc::new(Tensor::from_vec(data, &shape)?)))
+    }
+}
+
+fn pairs(items: Vec<Value>) -> MlResult<Vec<(Value, Value)>> {
+    if !items.len().is_multiple_of(2) {
+        return Err("Corrupt pickle in PyTorch checkpoint: odd number of dict items".into());
+    }
+    let mut items = items.into_iter();
+    let mut pairs = Vec::new();
+    while let (Some(key), Some(value)) = (items.next(), items.next()) {
+        pairs.push((key, value));
+    }
+    Ok(pairs)
+}
+
+fn dims(value: &Value) -> MlResult<Vec<usize>> {
+    let Value::Tuple(items) = value else {
+        return Err("Corrupt tensor shape in PyTorch checkpoint".into());
+    };
+    items
+        .iter()
+        .map(|item| match item {
+            Value::Int(dim) if *dim >= 0 => Ok(*dim as usize),
+            _ => Err("Corrupt tensor shape in PyTorch checkpoint".into()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::{Layer, Linear};
+    use crate::serialize::zip::write_stored;
+
+    // What `torch.save({"weight": w, "bias": b, "epoch": 3}, path)` pickles, for a [2, 3]
+    // float weight that is a transposed view of its storage and a half precision bias
+    fn state_dict_pickle() -> Vec<u8> {
+        let mut p = vec![0x80, 0x02, b'}', b'q', 0, b'('];
+
+        let rebuild = b"ctorch._utils\n_rebuild_tensor_v2\nq\x01";
+        p.extend_from_slice(b"X\x06\x00\x00\x00weight");
+        p.extend_from_slice(rebuild);
+        p.extend_from_slice(b"((X\x07\x00\x00\x00storageq\x02ctorch\nFloatStorage\nq\x03");
+        p.extend_from_slice(b"X\x01\x00\x00\x000X\x03\x00\x00\x00cpuK\x06tQ");
+        p.extend_from_slice(b"K\x00K\x02K\x03\x86K\x01K\x02\x86\x89");
+        p.extend_from_slice(b"ccollections\nOrderedDict\n)Rq\x04tRq\x05");
+
+        p.extend_from_slice(b"X\x04\x00\x00\x00bias");
+        p.extend_from_slice(b"h\x01((h\x02ctorch\nHalfStorage\n");
+        p.extend_from_slice(b"X\x01\x00\x00\x001X\x03\x00\x00\x00cpuK\x02tQ");
+        p.extend_from_slice(b"K\x00K\x02\x85K\x01\x85\x89h\x04tR");
+
+        p.extend_from_slice(b"X\x05\x00\x00\x00epochK\x03u.");
+        p
+    }
+
+    #[test]
+    fn test_load_pt() -> MlResult<()> {
+        let path = "test_load.pt";
+        let weight: Vec<u8> = [1.0f32, 4.0, 2.0, 5.0, 3.0, 6.0]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let entries = vec![
+            ("model/data.pkl".to_string(), state_dict_pickle()),
+            ("model/byteorder".to_string(), b"little".to_vec()),
+            ("model/data/0".to_string(), weight),
+            ("model/data/1".to_string(), vec![0x00, 0x3c, 0x00, 0xc0]),
+        ];
+        std::fs::write(path, write_stored(&entries)?).expect("Failed to write test file");
+
+        let state = load_pt(path)?;
+        assert_eq!(state.keys().collect::<Vec<_>>(), ["weight", "bias"]);
+        let mut layer = Linear::new(3, 2, true)?;
+        assert!(layer.load_state_dict(&state, true)?.is_clean());
+        assert_eq!(layer.weight().data(), &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(layer.bias().unwrap().data(), &[1.0, -2.0]);
+
+        // Anything outside the state dict subset is refused rather than run
+        let evil = b"\x80\x02cos\nsystem\nX\x02\x00\x00\x00ls\x85R.".to_vec();
+        let entries = vec![("model/data.pkl".to_string(), evil)];
+        std::fs::write(path, write_stored(&entries)?).expect("Failed to write test file");
+        let error = load_pt(path).unwrap_err().to_string();
+        assert!(error.contains("Refusing to load os.system"));
+
+        std::fs::remove_file(path).expect("Failed to remove test file");
+        Ok(())
+    }
+}
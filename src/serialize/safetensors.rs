@@ -0,0 +1,274 @@
+//! Hugging Face `.safetensors` files.
+//!
+//! A file is a little-endian u64 header length, a JSON header mapping each tensor name to
+//! its dtype, shape and byte range, and then the tensor data. Float, integer and boolean
+//! tensors are read and converted to f32; tensors are written as `F32`, or as `F16` with
+//! the `half` feature. The optional `__metadata__` entry is skipped when reading.
+
+use std::io::{Read, Write};
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+use super::format;
+use super::StateDict;
+use crate::tensor::Tensor;
+use crate::MlResult;
+
+/// Reads every tensor of a `.safetensors` file, in the order of their data in the file.
+#[cfg(feature = "fs")]
+pub fn load_safetensors<P: AsRef<Path>>(path: P) -> MlResult<StateDict> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to read file: {}", e))?;
+    decode(&bytes)
+}
+
+/// Writes `state` to a `.safetensors` file with every tensor as `F32`.
+#[cfg(feature = "fs")]
+pub fn save_safetensors<P: AsRef<Path>>(path: P, state: &StateDict) -> MlResult<()> {
+    std::fs::write(path, encode(state)).map_err(|e| format!("Failed to write file: {}", e).into())
+}
+
+/// Like [`load_safetensors`], reading the file's bytes from `reader` to its end, e.g. a
+/// response body.
+pub fn read_safetensors<R: Read>(reader: R) -> MlResult<StateDict> {
+    decode(&format::read_to_end(reader)?)
+}
+
+/// Like [`save_safetensors`], writing the file's bytes to `writer`.
+pub fn write_safetensors<W: Write>(writer: W, state: &StateDict) -> MlResult<()> {
+    format::write_all(writer, &encode(state))
+}
+
+/// Reads every tensor of a `.safetensors` file as half-precision values, with each one's
+/// shape, in the order of their data in the file. `F16` tensors are taken bit for bit
+/// without passing through f32; other dtypes are converted.
+#[cfg(all(feature = "half", feature = "fs"))]
+pub fn load_safetensors_f16<P: AsRef<Path>>(path: P) -> MlResult<HalfTensors> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to read file: {}", e))?;
+    decode_f16(&bytes)
+}
+
+/// Writes `state` to a `.safetensors` file with every tensor as `F16`, rounded to the
+/// nearest value.
+#[cfg(all(feature = "half", feature = "fs"))]
+pub fn save_safetensors_f16<P: AsRef<Path>>(path: P, state: &StateDict) -> MlResult<()> {
+    std::fs::write(path, encode_f16(state))
+        .map_err(|e| format!("Failed to write file: {}", e).into())
+}
+
+/// Like [`load_safetensors_f16`], reading the file's bytes from `reader` to its end.
+#[cfg(feature = "half")]
+pub fn read_safetensors_f16<R: Read>(reader: R) -> MlResult<HalfTensors> {
+    decode_f16(&format::read_to_end(reader)?)
+}
+
+/// Like [`save_safetensors_f16`], writing the file's bytes to `writer`.
+#[cfg(feature = "half")]
+pub fn write_safetensors_f16<W: Write>(writer: W, state: &StateDict) -> MlResult<()> {
+    format::write_all(writer, &encode_f16(state))
+}
+
+/// Named half-precision tensors with their shapes, as read by [`read_safetensors_f16`].
+#[cfg(feature = "half")]
+pub type HalfTensors = Vec<(String, Vec<usize>, Vec<half::f16>)>;
+
+#[cfg(feature = "half")]
+fn decode_f16(bytes: &[u8]) -> MlResult<HalfTensors> {
+    use half::f16;
+    use half::prelude::*;
+
+    cetana_core::safetensors::raw_tensors(bytes)?
+        .into_iter()
+        .map(|raw| {
+            let data = if raw.dtype == "F16" {
+                raw.data
+                    .chunks_exact(2)
+                    .map(|b| f16::from_bits(u16::from_le_bytes([b[0], b[1]])))
+                    .collect()
+            } else {
+                Vec::from_f32_slice(raw.to_tensor()?.data())
+            };
+            Ok((raw.name, raw.shape, data))
+        })
+        .collect()
+}
+
+#[cfg(feature = "half")]
+fn encode_f16(state: &StateDict) -> Vec<u8> {
+    encode_as(state, "F16", 2, |tensor, bytes| {
+        let halves = tensor.to_f16_vec();
+        bytes.extend(
+            halves
+                .iter()
+                .flat_map(|value| value.to_bits().to_le_bytes()),
+        )
+    })
+}
+
+fn decode(bytes: &[u8]) -> MlResult<StateDict> {
+    cetana_core::safetensors::decode(bytes)?
+        .into_iter()
+        .map(|(name, tensor)| Ok((name, Tensor::from_core(tensor)?)))
+        .collect()
+}
+
+fn encode(state: &StateDict) -> Vec<u8> {
+    encode_as(state, "F32", 4, |tensor, bytes| {
+        bytes.extend(tensor.data().iter().flat_map(|value| value.to_le_bytes()))
+    })
+}
+
+// Every tensor as `dtype`, with `size` bytes per element written by `write`
+fn encode_as(
+    state: &StateDict,
+    dtype: &str,
+    size: usize,
+    write: impl Fn(&Tensor, &mut Vec<u8>),
+) -> Vec<u8> {
+    let mut header = String::from("{");
+    let mut offset = 0;
+    for (i, (name, tensor)) in state.iter().enumerate() {
+        let len = tensor.data().len() * size;
+        let shape: Vec<String> = tensor.shape().iter().map(ToString::to_string).collect();
+        if i > 0 {
+            header.push(',');
+        }
+        header.push_str(&format!(
+            "{}:{{\"dtype\":\"{}\",\"shape\":[{}],\"data_offsets\":[{},{}]}}",
+            quote(name),
+            dtype,
+            shape.join(","),
+            offset,
+            offset + len
+        ));
+        offset += len;
+    }
+    header.push('}');
+    // The data is 8-byte aligned when the header is padded with spaces
+    while header.len() % 8 != 0 {
+        header.push(' ');
+    }
+
+    let mut bytes = Vec::with_capacity(8 + header.len() + offset);
+    bytes.extend_from_slice(&(header.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(header.as_bytes());
+    for (_, tensor) in state.iter() {
+        write(tensor, &mut bytes);
+    }
+    bytes
+}
+
+/// `s` as a JSON string literal.
+pub(crate) fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_safetensors_round_trip() -> MlResult<()> {
+        let mut state = StateDict::new();
+        state.insert(
+            "fc.weight",
+            Tensor::from_vec(vec![1.0, -2.0, 3.5, 4.0], &[2, 2])?,
+        );
+        state.insert("fc.\"bias\"", Tensor::from_vec(vec![0.25, 0.5], &[2])?);
+        let mut bytes = Vec::new();
+        write_safetensors(&mut bytes, &state)?;
+        assert_eq!((bytes.len() - 8 - 24) % 8, 0);
+
+        let back = read_safetensors(bytes.as_slice())?;
+        assert_eq!(
+            back.keys().collect::<Vec<_>>(),
+            ["fc.weight", "fc.\"bias\""]
+        );
+        assert_eq!(
+            back.get("fc.weight").unwrap().data(),
+            &[1.0, -2.0, 3.5, 4.0]
+        );
+        assert_eq!(back.get("fc.weight").unwrap().shape(), &[2, 2]);
+
+        // As written by the Python library: metadata, other dtypes, data out of key order
+        let header = r#"{"__metadata__":{"format":"pt"},"b":{"dtype":"BF16","shape":[2],"data_offsets":[2,6]},"a":{"dtype":"I8","shape":[1,2],"data_offsets":[0,2]}}"#;
+        let mut bytes = (header.len() as u64).to_le_bytes().to_vec();
+        bytes.extend_from_slice(header.as_bytes());
+        bytes.extend_from_slice(&[0xff, 0x07]);
+        bytes.extend_from_slice(&[0x80, 0x3f, 0x20, 0xc0]);
+        let state = decode(&bytes)?;
+        assert_eq!(state.keys().collect::<Vec<_>>(), ["a", "b"]);
+        assert_eq!(state.get("a").unwrap().data(), &[-1.0, 7.0]);
+        assert_eq!(state.get("b").unwrap().data(), &[1.0, -2.5]);
+
+        // Offsets past the data or disagreeing with the shape are rejected
+        let last = bytes.len() - 1;
+        assert!(decode(&bytes[..last]).is_err());
+        let bad = header.replace("[2,6]", "[2,5]");
+        let mut bytes = (bad.len() as u64).to_le_bytes().to_vec();
+        bytes.extend_from_slice(bad.as_bytes());
+        bytes.extend_from_slice(&[0; 6]);
+        assert!(decode(&bytes).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_safetensors_core_inference() -> MlResult<()> {
+        use crate::nn::{Layer, Linear};
+        use cetana_core::Layer as _;
+
+        let linear = Linear::new(3, 2, true)?;
+        let mut state = StateDict::new();
+        for (name, param) in linear.named_parameters() {
+            state.insert(format!("fc.{}", name), param.clone());
+        }
+        let mut bytes = Vec::new();
+        write_safetensors(&mut bytes, &state)?;
+
+        let mut core_state = cetana_core::safetensors::decode(&bytes)?;
+        let core_linear = cetana_core::Linear::from_state(&mut core_state, "fc")?;
+        let input = Tensor::from_vec(vec![0.5, -1.0, 2.0, 1.0, 0.0, -0.5], &[2, 3])?;
+        let expected = linear.forward(&input)?;
+        let output = core_linear.forward(&input.clone().into_core()?)?;
+        assert_eq!(output.shape(), expected.shape());
+        for (a, b) in output.data().iter().zip(expected.data()) {
+            assert!((a - b).abs() < 1e-6);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "half")]
+    #[test]
+    fn test_safetensors_f16() -> MlResult<()> {
+        let mut state = StateDict::new();
+        state.insert("w", Tensor::from_vec(vec![1.0, -0.5, 1.0 / 3.0], &[3])?);
+        let mut bytes = Vec::new();
+        write_safetensors_f16(&mut bytes, &state)?;
+
+        // Read back as f32 through the F16 dtype, and as halves bit for bit
+        let back = read_safetensors(bytes.as_slice())?;
+        let halves = read_safetensors_f16(bytes.as_slice())?;
+        assert_eq!(halves.len(), 1);
+        let (name, shape, data) = &halves[0];
+        assert_eq!((name.as_str(), shape.as_slice()), ("w", &[3][..]));
+        assert_eq!(data, &state.get("w").unwrap().to_f16_vec());
+        assert_eq!(back.get("w").unwrap().data()[..2], [1.0, -0.5]);
+
+        // Other dtypes are converted
+        let mut bytes = Vec::new();
+        write_safetensors(&mut bytes, &state)?;
+        let (_, _, data) = &read_safetensors_f16(bytes.as_slice())?[0];
+        assert_eq!(data[1], half::f16::from_f32(-0.5));
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+//! Choosing which tensors of a checkpoint to load.
+//!
+//! ```ignore
+//! // Only the encoder, e.g. to fine-tune a fresh head on top of it
+//! let selector = TensorSelector::new().prefix("encoder.");
+//! let encoder = StateDict::load_selected("model.spn", &selector)?;
+//!
+//! // One layer of a file too large to load whole
+//! let mapped = MappedStateDict::open("huge.spn")?;
+//! let block = mapped.load_selected(&TensorSelector::new().name("blocks.7.attn.weight"))?;
+//! ```
+
+use std::fmt::{Debug, Formatter};
+
+use crate::MlResult;
+
+enum Rule {
+    Name(String),
+    Prefix(String),
+    #[cfg(feature = "regex")]
+    Regex(regex::Regex),
+    Filter(Box<dyn Fn(&str) -> bool + Send + Sync>),
+}
+
+/// A set of rules for tensor names. A name is selected when any rule matches it; a selector
+/// with no rules selects nothing, and [`TensorSelector::all`] selects everything.
+#[derive(Default)]
+pub struct TensorSelector {
+    rules: Vec<Rule>,
+    all: bool,
+}
+
+impl TensorSelector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Selects every tensor.
+    pub fn all() -> Self {
+        Self {
+            rules: Vec::new(),
+            all: true,
+        }
+    }
+
+    /// Selects the tensor called exactly `name`.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.rules.push(Rule::Name(name.into()));
+        self
+    }
+
+    /// Selects every tensor whose name starts with `prefix`, such as `"encoder."`.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.rules.push(Rule::Prefix(prefix.into()));
+        self
+    }
+
+    /// Selects every tensor whose name matches the regular expression `pattern` anywhere;
+    /// anchor it with `^...$` to match whole names.
+    #[cfg(feature = "regex")]
+    pub fn regex(mut self, pattern: &str) -> MlResult<Self> {
+        let regex = regex::Regex::new(pattern)
+            .map_err(|e| format!("Invalid tensor name pattern {}: {}", pattern, e))?;
+        self.rules.push(Rule::Regex(regex));
+        Ok(self)
+    }
+
+    /// Selects every tensor whose name `filter` accepts.
+    pub fn filter(mut self, filter: impl Fn(&str) -> bool + Send + Sync + 'static) -> Self {
+        self.rules.push(Rule::Filter(Box::new(filter)));
+        self
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        self.all
+            || self.rules.iter().any(|rule| match rule {
+                Rule::Name(exact) => name == exact,
+                Rule::Prefix(prefix) => name.starts_with(prefix.as_str()),
+                #[cfg(feature = "regex")]
+                Rule::Regex(regex) => regex.is_match(name),
+                Rule::Filter(filter) => filter(name),
+            })
+    }
+
+    /// Fails unless every exact name this selector asks for is among `available`, so a typo
+    /// in a name doesn't silently load nothing.
+    pub fn check_names<'a>(&self, available: impl IntoIterator<Item = &'a str>) -> MlResult<()> {
+        let available: Vec<_> = available.into_iter().collect();
+        for rule in &self.rules {
+            if let Rule::Name(name) = rule {
+                if !available.contains(&name.as_str()) {
+                    return Err(format!("No tensor named {}", name).into());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Debug for TensorSelector {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.all {
+            return write!(f, "TensorSelector(all)");
+        }
+        let rules: Vec<String> = self
+            .rules
+            .iter()
+            .map(|rule| match rule {
+                Rule::Name(name) => format!("name {:?}", name),
+                Rule::Prefix(prefix) => format!("prefix {:?}", prefix),
+                #[cfg(feature = "regex")]
+                Rule::Regex(regex) => format!("regex {:?}", regex.as_str()),
+                Rule::Filter(_) => "filter".to_string(),
+            })
+            .collect();
+        f.debug_tuple("TensorSelector").field(&rules).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialize::{MappedStateDict, StateDict};
+    use crate::tensor::Tensor;
+
+    #[test]
+    fn test_selective_loading() -> MlResult<()> {
+        let path = "test_selected.spn";
+        let mut state = StateDict::new();
+        for (i, name) in [
+            "encoder.0.weight",
+            "encoder.0.bias",
+            "head.weight",
+            "head.bias",
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            state.insert(name, Tensor::from_vec(vec![i as f32; 2], &[2])?);
+        }
+        state.save(path)?;
+
+        let selector = TensorSelector::new().prefix("encoder.").name("head.bias");
+        let loaded = StateDict::load_selected(path, &selector)?;
+        assert_eq!(
+            loaded.keys().collect::<Vec<_>>(),
+            ["encoder.0.weight", "encoder.0.bias", "head.bias"]
+        );
+        assert_eq!(loaded.get("head.bias").unwrap().data(), &[3.0, 3.0]);
+
+        let mapped = MappedStateDict::open(path)?;
+        let weights =
+            mapped.load_selected(&TensorSelector::new().filter(|n| n.ends_with(".weight")))?;
+        assert_eq!(
+            weights.keys().collect::<Vec<_>>(),
+            ["encoder.0.weight", "head.weight"]
+        );
+        assert!(mapped
+            .load_selected(&TensorSelector::new().name("head.wieght"))
+            .is_err());
+        assert!(StateDict::load_selected(path, &TensorSelector::new())?.is_empty());
+
+        std::fs::remove_file(path).expect("Failed to remove test file");
+        Ok(())
+    }
+}
@@ -0,0 +1,428 @@
+use std::fmt::{Display, Formatter};
+#[cfg(feature = "fs")]
+use std::fs::File;
+#[cfg(feature = "fs")]
+use std::io::{BufReader, BufWriter};
+use std::io::{Read, Write};
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+use super::format::{self, ByteReader, FormatError};
+use super::{Deserialize, Serialize, TensorSelector};
+use crate::tensor::{QuantizedTensor, Tensor};
+use crate::MlResult;
+
+pub(crate) const STATE_DICT_MAGIC: &[u8; 4] = b"SPSD";
+
+/// A layer's tensors keyed by parameter name, in the order the layer reports them.
+///
+/// Serialized as `SPSD`, an entry count, then each name and tensor record with a length
+/// prefix, so a state dict can be read back without knowing the layer that produced it.
+///
+/// Quantized tensors are kept apart from the float ones, after them in [`StateDict::keys`]
+/// and on disk; [`StateDict::get`] and [`StateDict::iter`] see only the float tensors.
+#[derive(Debug, Clone, Default)]
+pub struct StateDict {
+    entries: Vec<(String, Tensor)>,
+    quantized: Vec<(String, QuantizedTensor)>,
+}
+
+impl StateDict {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `tensor` under `name`. An existing entry keeps its position and is returned; a
+    /// quantized entry of the same name is dropped.
+    pub fn insert(&mut self, name: impl Into<String>, tensor: Tensor) -> Option<Tensor> {
+        let name = name.into();
+        self.remove_quantized(&name);
+        match self.entries.iter_mut().find(|(key, _)| *key == name) {
+            Some((_, existing)) => Some(std::mem::replace(existing, tensor)),
+            None => {
+                self.entries.push((name, tensor));
+                None
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Tensor> {
+        self.entries
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, tensor)| tensor)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Tensor> {
+        let index = self.entries.iter().position(|(key, _)| key == name)?;
+        Some(self.entries.remove(index).1)
+    }
+
+    /// Adds quantized `tensor` under `name`, like [`StateDict::insert`] for float tensors.
+    pub fn insert_quantized(
+        &mut self,
+        name: impl Into<String>,
+        tensor: QuantizedTensor,
+    ) -> Option<QuantizedTensor> {
+        let name = name.into();
+        self.remove(&name);
+        match self.quantized.iter_mut().find(|(key, _)| *key == name) {
+            Some((_, existing)) => Some(std::mem::replace(existing, tensor)),
+            None => {
+                self.quantized.push((name, tensor));
+                None
+            }
+        }
+    }
+
+    pub fn get_quantized(&self, name: &str) -> Option<&QuantizedTensor> {
+        self.quantized
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, tensor)| tensor)
+    }
+
+    pub fn remove_quantized(&mut self, name: &str) -> Option<QuantizedTensor> {
+        let index = self.quantized.iter().position(|(key, _)| key == name)?;
+        Some(self.quantized.remove(index).1)
+    }
+
+    pub fn iter_quantized(&self) -> impl Iterator<Item = (&str, &QuantizedTensor)> {
+        self.quantized
+            .iter()
+            .map(|(key, tensor)| (key.as_str(), tensor))
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some() || self.get_quantized(name).is_some()
+    }
+
+    /// The names of every entry, float then quantized.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.entries
+            .iter()
+            .map(|(key, _)| key.as_str())
+            .chain(self.quantized.iter().map(|(key, _)| key.as_str()))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Tensor)> {
+        self.entries
+            .iter()
+            .map(|(key, tensor)| (key.as_str(), tensor))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len() + self.quantized.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty() && self.quantized.is_empty()
+    }
+
+    /// The number of bytes the state dict serializes to, without serializing it.
+    pub fn serialized_len(&self) -> u64 {
+        let records = self
+            .iter()
+            .map(|(name, tensor)| (name, format::tensor_record_len(tensor.shape())))
+            .chain(
+                self.iter_quantized()
+                    .map(|(name, tensor)| (name, tensor.record_len())),
+            );
+        records.fold(8, |len, (name, record)| {
+            len + 12 + name.len() as u64 + record as u64
+        })
+    }
+
+    /// Stores a single value as a one element tensor, for counters and hyperparameters.
+    pub fn insert_scalar(&mut self, name: impl Into<String>, value: f32) -> MlResult<()> {
+        self.insert(name, Tensor::from_vec(vec![value], &[1])?);
+        Ok(())
+    }
+
+    /// Reads back a value stored with [`StateDict::insert_scalar`].
+    pub fn scalar(&self, name: &str) -> MlResult<f32> {
+        match self.get(name).map(|tensor| tensor.data()) {
+            Some(&[value]) => Ok(value),
+            Some(_) => Err(FormatError::Invalid(format!("{} is not a scalar", name)).into()),
+            None => Err(FormatError::Invalid(format!("missing {}", name)).into()),
+        }
+    }
+
+    /// Writes the state dict to `path`, in the same versioned, checksummed container as
+    /// [`Model::save`](super::Model::save).
+    #[cfg(feature = "fs")]
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> MlResult<()> {
+        let file = File::create(path).map_err(|e| format!("Failed to create file: {}", e))?;
+        self.save_to(BufWriter::new(file))
+    }
+
+    #[cfg(feature = "fs")]
+    pub fn load<P: AsRef<Path>>(path: P) -> MlResult<Self> {
+        let file = File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
+        Self::load_from(BufReader::new(file))
+    }
+
+    /// Writes the same file as [`StateDict::save`] to `writer`.
+    pub fn save_to<W: Write>(&self, writer: W) -> MlResult<()> {
+        format::write_file(writer, self)
+    }
+
+    /// Reads one state dict file from `reader`, such as bytes fetched over the network.
+    pub fn load_from<R: Read>(reader: R) -> MlResult<Self> {
+        format::read_file(reader)
+    }
+
+    /// Loads only the tensors `selector` matches from the file at `path`. The others are
+    /// skipped over without being decoded, though the whole file is still checksummed.
+    #[cfg(feature = "fs")]
+    pub fn load_selected<P: AsRef<Path>>(path: P, selector: &TensorSelector) -> MlResult<Self> {
+        let file = File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
+        format::read_file_with(BufReader::new(file), |payload| {
+            read_entries(payload, selector)
+        })
+    }
+
+    /// Whether `bytes` look like a serialized state dict rather than some older layout.
+    pub(crate) fn is_state_dict(bytes: &[u8]) -> bool {
+        bytes.starts_with(STATE_DICT_MAGIC)
+    }
+}
+
+impl FromIterator<(String, Tensor)> for StateDict {
+    fn from_iter<I: IntoIterator<Item = (String, Tensor)>>(iter: I) -> Self {
+        let mut state = Self::new();
+        for (name, tensor) in iter {
+            state.insert(name, tensor);
+        }
+        state
+    }
+}
+
+/// Yields the float tensors; quantized ones are dropped.
+impl IntoIterator for StateDict {
+    type Item = (String, Tensor);
+    type IntoIter = std::vec::IntoIter<(String, Tensor)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl Serialize for StateDict {
+    fn serialize(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.serialized_len() as usize);
+        self.serialize_into(&mut bytes)
+            .expect("writing to a Vec can't fail");
+        bytes
+    }
+
+    fn serialize_into<W: Write>(&self, writer: W) -> MlResult<()> {
+        write_all_entries(
+            writer,
+            &self.iter().collect::<Vec<_>>(),
+            &self.iter_quantized().collect::<Vec<_>>(),
+        )
+    }
+}
+
+impl Deserialize for StateDict {
+    fn deserialize(bytes: &[u8]) -> MlResult<Self> {
+        let mut reader = ByteReader::new(bytes);
+        if reader.take(4)? != STATE_DICT_MAGIC {
+            return Err(FormatError::BadMagic.into());
+        }
+
+        let count = reader.u32()? as usize;
+        let mut state = Self::new();
+        for _ in 0..count {
+            let name = std::str::from_utf8(reader.prefixed()?)
+                .map_err(|_| FormatError::Invalid("parameter name is not UTF-8".into()))?;
+            let len = reader.u64()? as usize;
+            let record = reader.take(len)?;
+            if state.contains_key(name) {
+                return Err(FormatError::Invalid(format!("duplicate parameter {}", name)).into());
+            }
+            if format::is_quantized(record) {
+                state.insert_quantized(name, QuantizedTensor::deserialize(record)?);
+            } else {
+                state.insert(name, Tensor::deserialize(record)?);
+            }
+        }
+        reader.finish()?;
+
+        Ok(state)
+    }
+
+    fn deserialize_from<R: Read>(reader: R) -> MlResult<Self> {
+        read_entries(reader, &TensorSelector::all())
+    }
+}
+
+/// Reads the state dict layout from `reader`, decoding only the entries `selector` matches
+/// and skipping over the rest.
+pub(crate) fn read_entries<R: Read>(
+    mut reader: R,
+    selector: &TensorSelector,
+) -> MlResult<StateDict> {
+    let mut magic = [0; 4];
+    format::read_exact(&mut reader, &mut magic)?;
+    if &magic != STATE_DICT_MAGIC {
+        return Err(FormatError::BadMagic.into());
+    }
+
+    let count = format::read_u32(&mut reader)? as usize;
+    let mut state = StateDict::new();
+    let mut seen = Vec::new();
+    for _ in 0..count {
+        let len = format::read_u32(&mut reader)? as u64;
+        let mut name = Vec::new();
+        (&mut reader)
+            .take(len)
+            .read_to_end(&mut name)
+            .map_err(|e| format!("Failed to read data: {}", e))?;
+        let name = String::from_utf8(name)
+            .map_err(|_| FormatError::Invalid("parameter name is not UTF-8".into()))?;
+        if name.len() as u64 != len {
+            return Err(FormatError::Truncated {
+                needed: len as usize,
+                available: name.len(),
+            }
+            .into());
+        }
+        if seen.contains(&name) {
+            return Err(FormatError::Invalid(format!("duplicate parameter {}", name)).into());
+        }
+
+        let len = format::read_u64(&mut reader)?;
+        let mut record = (&mut reader).take(len);
+        if selector.matches(&name) {
+            // Peek at the magic to tell quantized records from float ones
+            let mut magic = Vec::with_capacity(4);
+            (&mut record)
+                .take(4)
+                .read_to_end(&mut magic)
+                .map_err(|e| format!("Failed to read data: {}", e))?;
+            let mut chained = std::io::Cursor::new(&magic).chain(&mut record);
+            if format::is_quantized(&magic) {
+                let tensor = QuantizedTensor::deserialize_from(&mut chained)?;
+                state.insert_quantized(name.clone(), tensor);
+            } else {
+                let tensor = Tensor::deserialize_from(&mut chained)?;
+                state.insert(name.clone(), tensor);
+            }
+            if record.limit() != 0 {
+                return Err(FormatError::Invalid(format!("record length of {}", name)).into());
+            }
+        } else {
+            let skipped = std::io::copy(&mut record, &mut std::io::sink())
+                .map_err(|e| format!("Failed to read data: {}", e))?;
+            if skipped != len {
+                return Err(FormatError::Truncated {
+                    needed: len as usize,
+                    available: skipped as usize,
+                }
+                .into());
+            }
+        }
+        seen.push(name);
+    }
+    selector.check_names(seen.iter().map(String::as_str))?;
+
+    Ok(state)
+}
+
+/// Streams the state dict layout for `entries` to `writer`, so layers can write their
+/// parameters without copying them into a [`StateDict`] first.
+pub(crate) fn write_entries<W: Write>(writer: W, entries: &[(&str, &Tensor)]) -> MlResult<()> {
+    write_all_entries(writer, entries, &[])
+}
+
+// Streams float `entries` followed by `quantized` ones.
+fn write_all_entries<W: Write>(
+    mut writer: W,
+    entries: &[(&str, &Tensor)],
+    quantized: &[(&str, &QuantizedTensor)],
+) -> MlResult<()> {
+    let mut header = STATE_DICT_MAGIC.to_vec();
+    header.extend_from_slice(&((entries.len() + quantized.len()) as u32).to_le_bytes());
+    format::write_all(&mut writer, &header)?;
+
+    for &(name, tensor) in entries {
+        let mut prefix = Vec::with_capacity(12 + name.len());
+        prefix.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        prefix.extend_from_slice(name.as_bytes());
+        prefix.extend_from_slice(&(format::tensor_record_len(tensor.shape()) as u64).to_le_bytes());
+        format::write_all(&mut writer, &prefix)?;
+        tensor.serialize_into(&mut writer)?;
+    }
+    for &(name, tensor) in quantized {
+        let record = tensor.serialize();
+        let mut prefix = Vec::with_capacity(12 + name.len());
+        prefix.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        prefix.extend_from_slice(name.as_bytes());
+        prefix.extend_from_slice(&(record.len() as u64).to_le_bytes());
+        format::write_all(&mut writer, &prefix)?;
+        format::write_all(&mut writer, &record)?;
+    }
+    Ok(())
+}
+
+/// Keys that didn't line up in a [`Layer::load_state_dict`](crate::nn::Layer::load_state_dict).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoadReport {
+    /// Parameters of the layer that the state dict had no entry for; they keep their values.
+    pub missing_keys: Vec<String>,
+    /// Entries of the state dict that the layer has no parameter for; they are ignored.
+    pub unexpected_keys: Vec<String>,
+}
+
+impl LoadReport {
+    /// True when every parameter was loaded and every entry was used.
+    pub fn is_clean(&self) -> bool {
+        self.missing_keys.is_empty() && self.unexpected_keys.is_empty()
+    }
+}
+
+impl Display for LoadReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "missing keys {:?}, unexpected keys {:?}",
+            self.missing_keys, self.unexpected_keys
+        )
+    }
+}
+
+/// Pairs a layer's parameters with the entries on offer, both given as names and shapes, and
+/// lists the keys that don't line up. Fails on a shape mismatch, and in `strict` mode on any
+/// missing or unexpected key.
+pub(crate) fn match_keys(
+    params: &[(&str, &[usize])],
+    entries: &[(&str, &[usize])],
+    strict: bool,
+) -> MlResult<LoadReport> {
+    let mut report = LoadReport::default();
+    for &(name, shape) in params {
+        match entries.iter().find(|(key, _)| *key == name) {
+            Some(&(_, found)) if found != shape => {
+                return Err(format!(
+                    "Shape mismatch for {}: expected {:?}, got {:?}",
+                    name, shape, found
+                )
+                .into());
+            }
+            Some(_) => {}
+            None => report.missing_keys.push(name.to_string()),
+        }
+    }
+    report.unexpected_keys = entries
+        .iter()
+        .filter(|(key, _)| !params.iter().any(|(name, _)| name == key))
+        .map(|(key, _)| key.to_string())
+        .collect();
+
+    if strict && !report.is_clean() {
+        return Err(format!("Failed to load state dict: {}", report).into());
+    }
+    Ok(report)
+}
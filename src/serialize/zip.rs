@@ -0,0 +1,202 @@
+//! Reading and writing the zip archives `.npz` files and PyTorch checkpoints are stored in.
+
+use crate::MlResult;
+use flate2::read::DeflateDecoder;
+use std::io::Read;
+
+pub(crate) struct ZipEntry {
+    pub(crate) name: String,
+    method: u16,
+    crc: u32,
+    compressed_size: u64,
+    size: u64,
+    local_offset: u64,
+}
+
+// Just enough of the zip format for zip archives: a single disk, stored or deflated entries,
+// and the zip64 extensions numpy uses for large arrays.
+pub(crate) struct ZipReader<'a> {
+    pub(crate) bytes: &'a [u8],
+}
+
+impl ZipReader<'_> {
+    fn field(&self, offset: usize, len: usize) -> MlResult<&[u8]> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(offset..end))
+            .ok_or_else(|| "Truncated zip archive".into())
+    }
+
+    fn u16(&self, offset: usize) -> MlResult<u16> {
+        Ok(u16::from_le_bytes(
+            self.field(offset, 2)?.try_into().unwrap(),
+        ))
+    }
+
+    fn u32(&self, offset: usize) -> MlResult<u32> {
+        Ok(u32::from_le_bytes(
+            self.field(offset, 4)?.try_into().unwrap(),
+        ))
+    }
+
+    fn u64(&self, offset: usize) -> MlResult<u64> {
+        Ok(u64::from_le_bytes(
+            self.field(offset, 8)?.try_into().unwrap(),
+        ))
+    }
+
+    pub(crate) fn entries(&self) -> MlResult<Vec<ZipEntry>> {
+        // The end of central directory record sits at the end, before a comment of up to 64 KiB
+        let end = (22..=self.bytes.len().min(22 + u16::MAX as usize))
+            .map(|back| self.bytes.len() - back)
+            .find(|&offset| self.bytes[offset..].starts_with(b"PK\x05\x06"))
+            .ok_or("Not a zip archive")?;
+
+        let mut count = self.u16(end + 10)? as u64;
+        let mut offset = self.u32(end + 16)? as u64;
+        if (count == 0xffff || offset == 0xffff_ffff) && end >= 20 {
+            let locator = end - 20;
+            if self.field(locator, 4)? == b"PK\x06\x07" {
+                let end64 = self.u64(locator + 8)? as usize;
+                if self.field(end64, 4)? != b"PK\x06\x06" {
+                    return Err("Corrupt zip64 record in zip archive".into());
+                }
+                count = self.u64(end64 + 32)?;
+                offset = self.u64(end64 + 48)?;
+            }
+        }
+
+        let mut entries = Vec::new();
+        let mut pos = offset as usize;
+        for _ in 0..count {
+            if self.field(pos, 4)? != b"PK\x01\x02" {
+                return Err("Corrupt central directory in zip archive".into());
+            }
+            let name_len = self.u16(pos + 28)? as usize;
+            let extra_len = self.u16(pos + 30)? as usize;
+            let comment_len = self.u16(pos + 32)? as usize;
+            let name = String::from_utf8_lossy(self.field(pos + 46, name_len)?).into_owned();
+
+            let mut entry = ZipEntry {
+                name,
+                method: self.u16(pos + 10)?,
+                crc: self.u32(pos + 16)?,
+                compressed_size: self.u32(pos + 20)? as u64,
+                size: self.u32(pos + 24)? as u64,
+                local_offset: self.u32(pos + 42)? as u64,
+            };
+            self.apply_zip64_extra(&mut entry, pos + 46 + name_len, extra_len)?;
+            entries.push(entry);
+
+            pos += 46 + name_len + extra_len + comment_len;
+        }
+        Ok(entries)
+    }
+
+    // Fields that overflowed 32 bits are stored in the zip64 extra field, in this order
+    fn apply_zip64_extra(&self, entry: &mut ZipEntry, start: usize, len: usize) -> MlResult<()> {
+        let mut pos = start;
+        while pos + 4 <= start + len {
+            let id = self.u16(pos)?;
+            let size = self.u16(pos + 2)? as usize;
+            if id == 0x0001 {
+                let mut field = pos + 4;
+                for value in [
+                    &mut entry.size,
+                    &mut entry.compressed_size,
+                    &mut entry.local_offset,
+                ] {
+                    if *value == 0xffff_ffff {
+                        *value = self.u64(field)?;
+                        field += 8;
+                    }
+                }
+            }
+            pos += 4 + size;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn extract(&self, entry: &ZipEntry) -> MlResult<Vec<u8>> {
+        let local = entry.local_offset as usize;
+        if self.field(local, 4)? != b"PK\x03\x04" {
+            return Err(format!("Corrupt local header for '{}' in zip archive", entry.name).into());
+        }
+        let start = local + 30 + self.u16(local + 26)? as usize + self.u16(local + 28)? as usize;
+        let raw = self.field(start, entry.compressed_size as usize)?;
+
+        let data = match entry.method {
+            0 => raw.to_vec(),
+            8 => {
+                let mut data = Vec::with_capacity(entry.size as usize);
+                DeflateDecoder::new(raw)
+                    .read_to_end(&mut data)
+                    .map_err(|e| format!("Failed to inflate '{}': {}", entry.name, e))?;
+                data
+            }
+            method => {
+                return Err(format!(
+                    "Unsupported compression method {} for '{}' in zip archive",
+                    method, entry.name
+                )
+                .into())
+            }
+        };
+
+        if crc32fast::hash(&data) != entry.crc {
+            return Err(format!("Checksum mismatch for '{}' in zip archive", entry.name).into());
+        }
+        Ok(data)
+    }
+}
+
+/// Builds a zip archive of uncompressed `entries`, dated 1980-01-01 00:00.
+pub(crate) fn write_stored(entries: &[(String, Vec<u8>)]) -> MlResult<Vec<u8>> {
+    if entries.len() > u16::MAX as usize {
+        return Err("zip archives are limited to 65535 entries".into());
+    }
+
+    let mut archive = Vec::new();
+    let mut central = Vec::new();
+    for (file_name, data) in entries {
+        if data.len() > u32::MAX as usize || archive.len() + data.len() > u32::MAX as usize {
+            return Err("zip archives over 4 GiB aren't supported".into());
+        }
+
+        let mut fields = Vec::with_capacity(26);
+        fields.extend_from_slice(&20u16.to_le_bytes()); // version needed to extract
+        fields.extend_from_slice(&0u16.to_le_bytes()); // flags
+        fields.extend_from_slice(&0u16.to_le_bytes()); // compression method
+        fields.extend_from_slice(&0u16.to_le_bytes()); // modification time
+        fields.extend_from_slice(&0x21u16.to_le_bytes()); // modification date
+        fields.extend_from_slice(&crc32fast::hash(data).to_le_bytes());
+        fields.extend_from_slice(&(data.len() as u32).to_le_bytes()); // compressed size
+        fields.extend_from_slice(&(data.len() as u32).to_le_bytes()); // uncompressed size
+        fields.extend_from_slice(&(file_name.len() as u16).to_le_bytes());
+        fields.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+
+        central.extend_from_slice(b"PK\x01\x02");
+        central.extend_from_slice(&20u16.to_le_bytes()); // version made by
+        central.extend_from_slice(&fields);
+        central.extend_from_slice(&[0; 6]); // comment length, disk, internal attributes
+        central.extend_from_slice(&0u32.to_le_bytes()); // external attributes
+        central.extend_from_slice(&(archive.len() as u32).to_le_bytes());
+        central.extend_from_slice(file_name.as_bytes());
+
+        archive.extend_from_slice(b"PK\x03\x04");
+        archive.extend_from_slice(&fields);
+        archive.extend_from_slice(file_name.as_bytes());
+        archive.extend_from_slice(data);
+    }
+
+    let central_offset = archive.len() as u32;
+    archive.extend_from_slice(&central);
+    archive.extend_from_slice(b"PK\x05\x06");
+    archive.extend_from_slice(&[0; 4]); // disk numbers
+    archive.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    archive.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    archive.extend_from_slice(&(central.len() as u32).to_le_bytes());
+    archive.extend_from_slice(&central_offset.to_le_bytes());
+    archive.extend_from_slice(&0u16.to_le_bytes()); // comment length
+    Ok(archive)
+}
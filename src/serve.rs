@@ -0,0 +1,421 @@
+//! Serving a model behind a request queue, behind the `threads` feature.
+//!
+//! A [`SessionPool`] loads a model on each of its worker threads. Requests are
+//! `[rows, ...]` arrays; a worker takes the oldest one, waits up to
+//! [`SessionPoolBuilder::max_latency`] for more with the same row shape, runs them through
+//! the model as one batch of up to [`SessionPoolBuilder::max_batch`] rows, and hands each
+//! request its own rows of the output. A request that has waited `max_latency` is run
+//! with whatever else has arrived.
+//!
+//! ```ignore
+//! let pool = SessionPool::builder()
+//!     .workers(2)
+//!     .max_batch(64)
+//!     .max_latency(Duration::from_millis(5))
+//!     .build(|| load_model("model.safetensors"))?;
+//!
+//! // From a request handler
+//! let scores = pool.predict(HostTensor::new(features, &[1, 16])?)?;
+//! let scores = pool.predict_async(HostTensor::new(features, &[1, 16])?).await?;
+//! ```
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::data::HostTensor;
+use crate::nn::Layer;
+use crate::tensor::Tensor;
+use crate::MlResult;
+
+type LoadFn = dyn Fn() -> MlResult<Box<dyn Layer>> + Send + Sync;
+
+/// Builds a [`SessionPool`]; see [`SessionPool::builder`].
+#[derive(Debug, Clone)]
+pub struct SessionPoolBuilder {
+    workers: usize,
+    max_batch: usize,
+    max_latency: Duration,
+}
+
+impl Default for SessionPoolBuilder {
+    fn default() -> Self {
+        Self {
+            workers: 1,
+            max_batch: 32,
+            max_latency: Duration::from_millis(5),
+        }
+    }
+}
+
+impl SessionPoolBuilder {
+    /// The number of worker threads, each with its own copy of the model. Defaults to one.
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// The most rows run through the model at once. A single request with more rows is
+    /// run on its own. Defaults to 32.
+    pub fn max_batch(mut self, rows: usize) -> Self {
+        self.max_batch = rows.max(1);
+        self
+    }
+
+    /// How long a request waits for others to batch with. Defaults to 5 ms.
+    pub fn max_latency(mut self, latency: Duration) -> Self {
+        self.max_latency = latency;
+        self
+    }
+
+    /// Starts the workers, each loading its model with `load`. Models are loaded on the
+    /// worker threads because tensors can't be sent between threads; the first error from
+    /// `load` is returned once every worker has tried.
+    pub fn build<L, F>(self, load: F) -> MlResult<SessionPool>
+    where
+        L: Layer + 'static,
+        F: Fn() -> MlResult<L> + Send + Sync + 'static,
+    {
+        let load: Arc<LoadFn> = Arc::new(move || Ok(Box::new(load()?) as Box<dyn Layer>));
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue::default()),
+            arrived: Condvar::new(),
+            max_batch: self.max_batch,
+            max_latency: self.max_latency,
+        });
+
+        let (loaded, results) = channel();
+        let workers = (0..self.workers)
+            .map(|_| {
+                let shared = Arc::clone(&shared);
+                let load = Arc::clone(&load);
+                let loaded = loaded.clone();
+                std::thread::spawn(move || {
+                    let model = catch_unwind(AssertUnwindSafe(|| load()))
+                        .unwrap_or_else(|_| Err("Loading the model panicked".into()));
+                    let model = match model {
+                        Ok(model) => model,
+                        Err(e) => {
+                            let _ = loaded.send(Err(e));
+                            return;
+                        }
+                    };
+                    let _ = loaded.send(Ok(()));
+                    drop(loaded);
+                    shared.serve(model.as_ref());
+                })
+            })
+            .collect();
+        drop(loaded);
+
+        let pool = SessionPool { shared, workers };
+        for result in results {
+            result?;
+        }
+        Ok(pool)
+    }
+}
+
+/// A model served by worker threads that batch concurrent requests together.
+///
+/// Dropping the pool stops the workers once the requests already queued are answered.
+pub struct SessionPool {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl SessionPool {
+    pub fn builder() -> SessionPoolBuilder {
+        SessionPoolBuilder::default()
+    }
+
+    /// The number of requests waiting for a worker.
+    pub fn queued(&self) -> usize {
+        self.shared.lock().requests.len()
+    }
+
+    /// Queues `input`, a `[rows, ...]` array, and returns its prediction, the model's
+    /// output rows for it, when a worker has run it.
+    pub fn predict_async(&self, input: HostTensor) -> Prediction {
+        let reply = Arc::new(Reply::default());
+        if input.shape().first().is_none_or(|&rows| rows == 0) {
+            reply.send(Err(format!(
+                "Requests need a leading row dimension of at least one row, got shape {:?}",
+                input.shape()
+            )
+            .into()));
+            return Prediction { reply };
+        }
+
+        self.shared.lock().requests.push_back(Request {
+            input,
+            arrived: Instant::now(),
+            reply: Arc::clone(&reply),
+        });
+        self.shared.arrived.notify_one();
+        Prediction { reply }
+    }
+
+    /// Queues `input` and blocks until its prediction is ready.
+    pub fn predict(&self, input: HostTensor) -> MlResult<HostTensor> {
+        self.predict_async(input).wait()
+    }
+}
+
+impl Drop for SessionPool {
+    fn drop(&mut self) {
+        self.shared.lock().closed = true;
+        self.shared.arrived.notify_all();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// The pending output of a [`SessionPool::predict_async`] request. Await it, or block on
+/// it with [`Prediction::wait`].
+pub struct Prediction {
+    reply: Arc<Reply>,
+}
+
+impl Prediction {
+    /// Blocks until the prediction is ready.
+    pub fn wait(self) -> MlResult<HostTensor> {
+        let mut state = self.reply.lock();
+        loop {
+            if let Some(result) = state.result.take() {
+                return result;
+            }
+            state = self
+                .reply
+                .done
+                .wait(state)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
+
+impl Future for Prediction {
+    type Output = MlResult<HostTensor>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.reply.lock();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Reply {
+    state: Mutex<ReplyState>,
+    done: Condvar,
+}
+
+#[derive(Default)]
+struct ReplyState {
+    result: Option<MlResult<HostTensor>>,
+    waker: Option<Waker>,
+}
+
+impl Reply {
+    fn lock(&self) -> MutexGuard<'_, ReplyState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn send(&self, result: MlResult<HostTensor>) {
+        let mut state = self.lock();
+        state.result = Some(result);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        self.done.notify_all();
+    }
+}
+
+struct Request {
+    input: HostTensor,
+    arrived: Instant,
+    reply: Arc<Reply>,
+}
+
+#[derive(Default)]
+struct Queue {
+    requests: VecDeque<Request>,
+    closed: bool,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    arrived: Condvar,
+    max_batch: usize,
+    max_latency: Duration,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Runs batches until the pool is dropped and the queue is empty
+    fn serve(&self, model: &dyn Layer) {
+        while let Some(batch) = self.next_batch() {
+            let result = catch_unwind(AssertUnwindSafe(|| run(model, &batch)))
+                .unwrap_or_else(|_| Err("The model panicked".into()));
+            match result {
+                Ok(outputs) => {
+                    for (request, output) in batch.iter().zip(outputs) {
+                        request.reply.send(Ok(output));
+                    }
+                }
+                Err(e) => {
+                    let message = e.to_string();
+                    for request in &batch {
+                        request.reply.send(Err(message.clone().into()));
+                    }
+                }
+            }
+        }
+    }
+
+    // Waits for a request, then for more with the same row shape until the batch is full
+    // or the first one has waited `max_latency`
+    fn next_batch(&self) -> Option<Vec<Request>> {
+        let mut queue = self.lock();
+        let first = loop {
+            if let Some(first) = queue.requests.pop_front() {
+                break first;
+            }
+            if queue.closed {
+                return None;
+            }
+            queue = self.arrived.wait(queue).unwrap_or_else(|e| e.into_inner());
+        };
+
+        let deadline = first.arrived + self.max_latency;
+        let row_shape = first.input.shape()[1..].to_vec();
+        let mut rows = first.input.shape()[0];
+        let mut batch = vec![first];
+        while rows < self.max_batch {
+            match queue.requests.front() {
+                Some(next) if next.input.shape()[1..] == row_shape[..] => {
+                    if rows + next.input.shape()[0] > self.max_batch {
+                        break;
+                    }
+                    rows += next.input.shape()[0];
+                    batch.extend(queue.requests.pop_front());
+                }
+                Some(_) => break,
+                None => {
+                    let now = Instant::now();
+                    if queue.closed || now >= deadline {
+                        break;
+                    }
+                    queue = self
+                        .arrived
+                        .wait_timeout(queue, deadline - now)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0;
+                }
+            }
+        }
+        // Another worker may be able to start on what's left
+        if !queue.requests.is_empty() {
+            self.arrived.notify_one();
+        }
+        Some(batch)
+    }
+}
+
+// Runs the requests through the model as one batch and splits the output between them
+fn run(model: &dyn Layer, batch: &[Request]) -> MlResult<Vec<HostTensor>> {
+    let rows: Vec<usize> = batch.iter().map(|r| r.input.shape()[0]).collect();
+    let total: usize = rows.iter().sum();
+    let mut shape = batch[0].input.shape().to_vec();
+    shape[0] = total;
+    let data: Vec<f32> = batch
+        .iter()
+        .flat_map(|request| request.input.data().iter().copied())
+        .collect();
+
+    let output = model.forward(&Tensor::from_vec(data, &shape)?)?;
+    match output.shape().first() {
+        Some(&n) if n == total => {}
+        _ => {
+            return Err(format!(
+                "Expected the model to return {} rows, got shape {:?}",
+                total,
+                output.shape()
+            )
+            .into())
+        }
+    }
+
+    let row_len = output.data().len() / total.max(1);
+    let mut out_shape = output.shape().to_vec();
+    let mut start = 0;
+    rows.into_iter()
+        .map(|n| {
+            out_shape[0] = n;
+            let data = output.data()[start * row_len..(start + n) * row_len].to_vec();
+            start += n;
+            HostTensor::new(data, &out_shape)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Doubles its input, recording the rows of each batch
+    struct Double(Arc<Mutex<Vec<usize>>>);
+
+    impl Layer for Double {
+        fn forward(&self, input: &Tensor) -> MlResult<Tensor> {
+            self.0.lock().unwrap().push(input.shape()[0]);
+            input.mul_scalar(2.0)
+        }
+
+        fn backward(&mut self, _: &Tensor, grad: &Tensor, _: f32) -> MlResult<Tensor> {
+            grad.mul_scalar(2.0)
+        }
+    }
+
+    #[test]
+    fn test_session_pool() -> MlResult<()> {
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&batches);
+        let pool = SessionPool::builder()
+            .max_batch(3)
+            .max_latency(Duration::from_secs(10))
+            .build(move || Ok(Double(Arc::clone(&recorded))))?;
+
+        // Queued together, the requests fill one batch without waiting out the latency
+        let a = pool.predict_async(HostTensor::new(vec![1.0, 2.0], &[1, 2])?);
+        let b = pool.predict_async(HostTensor::new(vec![3.0, 4.0, 5.0, 6.0], &[2, 2])?);
+        assert_eq!(a.wait()?.data(), &[2.0, 4.0]);
+        let b = b.wait()?;
+        assert_eq!(b.shape(), &[2, 2]);
+        assert_eq!(b.data(), &[6.0, 8.0, 10.0, 12.0]);
+        assert_eq!(*batches.lock().unwrap(), [3]);
+
+        assert!(pool.predict(HostTensor::new(vec![1.0], &[])?).is_err());
+        drop(pool);
+
+        let failing =
+            SessionPool::builder().build(|| -> MlResult<Double> { Err("no weights".into()) });
+        assert!(failing.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+use super::{Tensor, TensorError};
+use crate::MlResult;
+
+/// The shape elementwise ops give tensors of shapes `a` and `b`, following NumPy and
+/// PyTorch broadcasting: shapes are aligned at their last axes, missing leading axes count
+/// as size 1, and each pair of sizes must be equal or one of them 1.
+pub fn broadcast_shapes(a: &[usize], b: &[usize]) -> MlResult<Vec<usize>> {
+    let rank = a.len().max(b.len());
+    let size = |shape: &[usize], axis: usize| {
+        (axis + shape.len())
+            .checked_sub(rank)
+            .map_or(1, |axis| shape[axis])
+    };
+    (0..rank)
+        .map(|axis| match (size(a, axis), size(b, axis)) {
+            (x, y) if x == y || y == 1 => Ok(x),
+            (1, y) => Ok(y),
+            _ => Err(TensorError::BroadcastError {
+                left_shape: a.to_vec(),
+                right_shape: b.to_vec(),
+            }
+            .into()),
+        })
+        .collect()
+}
+
+// The strides for reading a contiguous tensor of `shape` as one of the broadcast `target`:
+// 0 along the axes it is repeated over
+fn broadcast_strides(shape: &[usize], target: &[usize]) -> Vec<usize> {
+    let mut strides = vec![0; target.len()];
+    let mut stride = 1;
+    let offset = target.len() - shape.len();
+    for axis in (0..shape.len()).rev() {
+        if shape[axis] != 1 {
+            strides[offset + axis] = stride;
+        }
+        stride *= shape[axis];
+    }
+    strides
+}
+
+impl Tensor {
+    // `f` applied to the elements of `self` and `other` broadcast to a common shape,
+    // computed on the host
+    pub(super) fn broadcast_with(
+        &self,
+        other: &Tensor,
+        f: impl Fn(f32, f32) -> f32,
+    ) -> MlResult<Tensor> {
+        let shape = broadcast_shapes(&self.shape, &other.shape)?;
+        let strides = [
+            broadcast_strides(&self.shape, &shape),
+            broadcast_strides(&other.shape, &shape),
+        ];
+        let (a, b) = (self.data(), other.data());
+
+        // Walks the output in order, moving each input's offset along with the index
+        let len = shape.iter().product();
+        let mut result = Vec::with_capacity(len);
+        let mut index = vec![0; shape.len()];
+        let mut offsets = [0, 0];
+        for _ in 0..len {
+            result.push(f(a[offsets[0]], b[offsets[1]]));
+            for axis in (0..shape.len()).rev() {
+                index[axis] += 1;
+                offsets[0] += strides[0][axis];
+                offsets[1] += strides[1][axis];
+                if index[axis] < shape[axis] {
+                    break;
+                }
+                offsets[0] -= strides[0][axis] * shape[axis];
+                offsets[1] -= strides[1][axis] * shape[axis];
+                index[axis] = 0;
+            }
+        }
+        Ok(self.with_data(result, &shape))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{register_backend, unregister_backend, DeviceType, HostBackend};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_broadcasting() -> MlResult<()> {
+        assert_eq!(broadcast_shapes(&[3, 1, 5], &[4, 5])?, [3, 4, 5]);
+        assert_eq!(broadcast_shapes(&[1], &[2, 3])?, [2, 3]);
+        assert_eq!(broadcast_shapes(&[], &[2])?, [2]);
+        assert!(matches!(
+            broadcast_shapes(&[2, 3], &[3, 2]),
+            Err(crate::MlError::TensorError(
+                TensorError::BroadcastError { .. }
+            ))
+        ));
+
+        let a = Tensor::from_vec((0..6).map(|i| i as f32).collect(), &[3, 1, 2])?;
+        let b = Tensor::from_vec(vec![10.0, 20.0, 30.0, 40.0], &[2, 2])?;
+        let product = a.mul(&b)?;
+        assert_eq!(product.shape(), &[3, 2, 2]);
+        assert_eq!(&product.data()[..4], &[0.0, 20.0, 0.0, 40.0]);
+        assert_eq!(&product.data()[8..], &[40.0, 100.0, 120.0, 200.0]);
+
+        // Column minus row gives every difference
+        let column = Tensor::from_vec(vec![1.0, 2.0], &[2, 1])?;
+        let row = Tensor::from_vec(vec![1.0, 10.0, 100.0], &[3])?;
+        assert_eq!(
+            column.sub(&row)?.data(),
+            &[0.0, -9.0, -99.0, 1.0, -8.0, -98.0]
+        );
+        assert_eq!(row.div(&column)?.shape(), &[2, 3]);
+        assert_eq!(row.add(&column)?.data()[3..], [3.0, 12.0, 102.0]);
+        assert!(row.add(&b).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_broadcast_keeps_device() -> MlResult<()> {
+        let device = DeviceType::Custom("test-broadcast");
+        register_backend(
+            "test-broadcast",
+            Arc::new(HostBackend::new("test-broadcast")),
+        )?;
+
+        let lhs = Tensor::from_vec(vec![1.0, 2.0], &[2, 1])?.to_device(device)?;
+        let rhs = Tensor::from_vec(vec![10.0, 20.0, 30.0], &[3])?.to_device(device)?;
+        let out = lhs.add(&rhs)?;
+        assert_eq!(out.device(), lhs.device());
+        assert_eq!(out.data(), &[11.0, 21.0, 31.0, 12.0, 22.0, 32.0]);
+        assert_eq!(lhs.maximum(&rhs)?.device(), device);
+
+        unregister_backend("test-broadcast");
+        Ok(())
+    }
+}
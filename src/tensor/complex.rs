@@ -0,0 +1,176 @@
+use crate::tensor::{Tensor, TensorError};
+use crate::MlResult;
+
+/// A tensor of complex64 values.
+///
+/// The real and imaginary parts are kept apart as two f32 tensors of the same shape, so
+/// arithmetic and matmul run on the backend's real kernels. [`ComplexTensor::from_interleaved`]
+/// and [`ComplexTensor::to_interleaved`] convert from and to the `re, im, re, im, ...` layout
+/// other libraries use.
+#[derive(Debug, Clone)]
+pub struct ComplexTensor {
+    re: Tensor,
+    im: Tensor,
+}
+
+impl ComplexTensor {
+    /// Pairs a real and an imaginary part, which must have the same shape.
+    pub fn new(re: Tensor, im: Tensor) -> MlResult<Self> {
+        if re.shape() != im.shape() {
+            return Err(TensorError::InvalidShape {
+                expected: re.shape().to_vec(),
+                got: im.shape().to_vec(),
+            }
+            .into());
+        }
+        Ok(Self { re, im })
+    }
+
+    /// The complex tensor with real part `re` and no imaginary part.
+    pub fn from_real(re: Tensor) -> MlResult<Self> {
+        let im =
+            Tensor::from_vec(vec![0.0; re.data().len()], re.shape())?.to_device(re.device())?;
+        Self::new(re, im)
+    }
+
+    /// Builds the tensor from `2 * len` values holding each element's real then imaginary
+    /// part.
+    pub fn from_interleaved(data: &[f32], shape: &[usize]) -> MlResult<Self> {
+        let len: usize = shape.iter().product();
+        if data.len() != 2 * len {
+            return Err(TensorError::InvalidDataLength {
+                expected: 2 * len,
+                got: data.len(),
+            }
+            .into());
+        }
+        let re = data.iter().step_by(2).copied().collect();
+        let im = data.iter().skip(1).step_by(2).copied().collect();
+        Self::new(Tensor::from_vec(re, shape)?, Tensor::from_vec(im, shape)?)
+    }
+
+    /// The values as real, imaginary pairs.
+    pub fn to_interleaved(&self) -> Vec<f32> {
+        self.re
+            .data()
+            .iter()
+            .zip(self.im.data())
+            .flat_map(|(&re, &im)| [re, im])
+            .collect()
+    }
+
+    pub fn re(&self) -> &Tensor {
+        &self.re
+    }
+
+    pub fn im(&self) -> &Tensor {
+        &self.im
+    }
+
+    pub fn into_parts(self) -> (Tensor, Tensor) {
+        (self.re, self.im)
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        self.re.shape()
+    }
+
+    pub fn add(&self, other: &ComplexTensor) -> MlResult<ComplexTensor> {
+        Self::new(self.re.add(&other.re)?, self.im.add(&other.im)?)
+    }
+
+    pub fn sub(&self, other: &ComplexTensor) -> MlResult<ComplexTensor> {
+        Self::new(self.re.sub(&other.re)?, self.im.sub(&other.im)?)
+    }
+
+    /// Elementwise product: `(a + bi)(c + di) = (ac - bd) + (ad + bc)i`.
+    pub fn mul(&self, other: &ComplexTensor) -> MlResult<ComplexTensor> {
+        let re = self.re.mul(&other.re)?.sub(&self.im.mul(&other.im)?)?;
+        let im = self.re.mul(&other.im)?.add(&self.im.mul(&other.re)?)?;
+        Self::new(re, im)
+    }
+
+    /// Elementwise quotient, as the product with `other`'s conjugate over `|other|^2`.
+    pub fn div(&self, other: &ComplexTensor) -> MlResult<ComplexTensor> {
+        let numerator = self.mul(&other.conj()?)?;
+        let norm = other.norm_sqr()?;
+        Self::new(numerator.re.div(&norm)?, numerator.im.div(&norm)?)
+    }
+
+    pub fn conj(&self) -> MlResult<ComplexTensor> {
+        Self::new(self.re.clone(), self.im.neg()?)
+    }
+
+    /// The magnitude of each element.
+    pub fn abs(&self) -> MlResult<Tensor> {
+        self.norm_sqr()?.sqrt()
+    }
+
+    /// The argument of each element in radians, in `-pi..=pi`.
+    pub fn angle(&self) -> MlResult<Tensor> {
+        let data = self
+            .re
+            .data()
+            .iter()
+            .zip(self.im.data())
+            .map(|(&re, &im)| im.atan2(re))
+            .collect();
+        Tensor::from_vec(data, self.shape())?.to_device(self.re.device())
+    }
+
+    /// The matrix product, from four real ones:
+    /// `(A + Bi)(C + Di) = (AC - BD) + (AD + BC)i`.
+    pub fn matmul(&self, other: &ComplexTensor) -> MlResult<ComplexTensor> {
+        let re = self
+            .re
+            .matmul(&other.re)?
+            .sub(&self.im.matmul(&other.im)?)?;
+        let im = self
+            .re
+            .matmul(&other.im)?
+            .add(&self.im.matmul(&other.re)?)?;
+        Self::new(re, im)
+    }
+
+    fn norm_sqr(&self) -> MlResult<Tensor> {
+        self.re.mul(&self.re)?.add(&self.im.mul(&self.im)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_complex_arithmetic() -> MlResult<()> {
+        // 1 + 2i, 3 + 4i and 3 - i, i
+        let a = ComplexTensor::from_interleaved(&[1.0, 2.0, 3.0, 4.0], &[2])?;
+        let b = ComplexTensor::from_interleaved(&[3.0, -1.0, 0.0, 1.0], &[2])?;
+        assert_eq!(a.re().data(), &[1.0, 3.0]);
+        assert_eq!(a.im().data(), &[2.0, 4.0]);
+
+        assert_eq!(a.add(&b)?.to_interleaved(), [4.0, 1.0, 3.0, 5.0]);
+        assert_eq!(a.sub(&b)?.to_interleaved(), [-2.0, 3.0, 3.0, 3.0]);
+        assert_eq!(a.mul(&b)?.to_interleaved(), [5.0, 5.0, -4.0, 3.0]);
+        let back = a.mul(&b)?.div(&b)?.to_interleaved();
+        for (x, y) in back.iter().zip(a.to_interleaved()) {
+            assert!((x - y).abs() < 1e-6);
+        }
+        assert_eq!(a.conj()?.to_interleaved(), [1.0, -2.0, 3.0, -4.0]);
+        assert_eq!(a.abs()?.data()[1], 5.0);
+        let angle = b.angle()?;
+        assert!((angle.data()[1] - std::f32::consts::FRAC_PI_2).abs() < 1e-6);
+
+        // [[1, i], [2, -i]] @ [[i], [1]] = [[2i], [i]]
+        let m =
+            ComplexTensor::from_interleaved(&[1.0, 0.0, 0.0, 1.0, 2.0, 0.0, 0.0, -1.0], &[2, 2])?;
+        let v = ComplexTensor::from_interleaved(&[0.0, 1.0, 1.0, 0.0], &[2, 1])?;
+        assert_eq!(m.matmul(&v)?.to_interleaved(), [0.0, 2.0, 0.0, 1.0]);
+
+        let real = ComplexTensor::from_real(Tensor::from_vec(vec![2.0, -1.0], &[2])?)?;
+        assert_eq!(real.to_interleaved(), [2.0, 0.0, -1.0, 0.0]);
+        assert!(ComplexTensor::from_interleaved(&[1.0, 2.0, 3.0], &[2]).is_err());
+        assert!(ComplexTensor::new(a.re().clone(), m.im().clone()).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+use super::{Tensor, TensorError};
+use crate::{MlError, MlResult};
+
+impl Tensor {
+    /// 1D convolution (cross-correlation) of this `[batch, channels, length]` tensor with a
+    /// `[out_channels, channels, kernel]` weight. `padding` is the number of zeros before and
+    /// after the sequence, so `(span - 1, 0)` keeps it causal, and `dilation` spaces the
+    /// kernel's taps that many positions apart, making it span
+    /// `dilation * (kernel - 1) + 1` positions.
+    pub fn conv1d(
+        &self,
+        weight: &Tensor,
+        stride: usize,
+        padding: (usize, usize),
+        dilation: usize,
+    ) -> MlResult<Tensor> {
+        let weight: &Tensor = &*self.colocated("conv1d", weight)?;
+        let (batch, channels, length, out_channels, kernel) =
+            match (self.shape.as_slice(), weight.shape.as_slice()) {
+                (&[n, c, l], &[oc, ic, k]) if c == ic && k > 0 => (n, c, l, oc, k),
+                (input, weight) => {
+                    return Err(MlError::TensorError(TensorError::InvalidOperation {
+                        op: "conv1d",
+                        reason: format!(
+                            "expected [batch, channels, length] input and a weight with as \
+                             many channels, got {:?} and {:?}",
+                            input, weight
+                        ),
+                    }))
+                }
+            };
+
+        let span = dilation * (kernel - 1) + 1;
+        let padded = padding.0 + length + padding.1;
+        if stride == 0 || dilation == 0 || padded < span {
+            return Err(MlError::TensorError(TensorError::InvalidOperation {
+                op: "conv1d",
+                reason: format!(
+                    "a kernel spanning {} positions with stride {} doesn't fit a padded \
+                     length of {}",
+                    span, stride, padded
+                ),
+            }));
+        }
+        let out_length = (padded - span) / stride + 1;
+
+        let input = self.data();
+        let weight = weight.data();
+        let mut output = vec![0.0; batch * out_channels * out_length];
+        for (row, out) in output.chunks_mut(out_length).enumerate() {
+            let (b, oc) = (row / out_channels, row % out_channels);
+            for c in 0..channels {
+                let sequence = &input[(b * channels + c) * length..][..length];
+                let taps = &weight[(oc * channels + c) * kernel..][..kernel];
+                for (j, &tap) in taps.iter().enumerate() {
+                    for (t, value) in out.iter_mut().enumerate() {
+                        // Positions in the padding read as zero
+                        let position = (t * stride + j * dilation).wrapping_sub(padding.0);
+                        if position < length {
+                            *value += tap * sequence[position];
+                        }
+                    }
+                }
+            }
+        }
+        Ok(self.with_data(output, &[batch, out_channels, out_length]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conv1d() -> MlResult<()> {
+        let input = Tensor::from_vec(vec![1.0, 2.0, 3.0, 4.0, 5.0], &[1, 1, 5])?;
+        let weight = Tensor::from_vec(vec![1.0, 10.0], &[1, 1, 2])?;
+
+        let output = input.conv1d(&weight, 1, (0, 0), 1)?;
+        assert_eq!(output.data(), &[21.0, 32.0, 43.0, 54.0]);
+
+        // Causal padding: each output only sees its own position and earlier ones
+        let causal = input.conv1d(&weight, 1, (1, 0), 1)?;
+        assert_eq!(causal.data(), &[10.0, 21.0, 32.0, 43.0, 54.0]);
+
+        // Dilation 2 pairs each position with the one two steps back
+        let dilated = input.conv1d(&weight, 1, (2, 0), 2)?;
+        assert_eq!(dilated.data(), &[10.0, 20.0, 31.0, 42.0, 53.0]);
+        assert_eq!(
+            input.conv1d(&weight, 2, (2, 0), 2)?.data(),
+            &[10.0, 31.0, 53.0]
+        );
+
+        // Matches conv2d over a height of one
+        let channels = Tensor::from_vec((0..12).map(|x| x as f32).collect(), &[1, 2, 6])?;
+        let filters = Tensor::from_vec((0..12).map(|x| x as f32 * 0.5).collect(), &[2, 2, 3])?;
+        let expected = channels.reshape(&[1, 2, 1, 6])?.conv2d(
+            &filters.reshape(&[2, 2, 1, 3])?,
+            (1, 1),
+            (0, 1),
+        )?;
+        assert_eq!(
+            channels.conv1d(&filters, 1, (1, 1), 1)?.data(),
+            expected.data()
+        );
+
+        assert!(input.conv1d(&weight, 1, (0, 0), 5).is_err());
+        assert!(input.conv1d(&weight, 0, (0, 0), 1).is_err());
+        assert!(channels.conv1d(&weight, 1, (0, 0), 1).is_err());
+        Ok(())
+    }
+}
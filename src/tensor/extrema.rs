@@ -0,0 +1,166 @@
+use super::{Tensor, TensorError};
+use crate::backend::split_axis;
+use crate::{MlError, MlResult};
+
+impl Tensor {
+    /// The largest element and its index in row-major order. Ties go to the first one.
+    pub fn max_all(&self) -> MlResult<(f32, usize)> {
+        self.extreme_all("max_all", |a, b| a > b)
+    }
+
+    /// The smallest element and its index in row-major order. Ties go to the first one.
+    pub fn min_all(&self) -> MlResult<(f32, usize)> {
+        self.extreme_all("min_all", |a, b| a < b)
+    }
+
+    /// The largest elements along `dim` and where along it each one is, the first on ties,
+    /// so `max_with_indices(1, false)` of `[batch, classes]` scores gives each row's best
+    /// score and class. With `keepdim` the axis stays as a dimension of size 1.
+    pub fn max_with_indices(&self, dim: usize, keepdim: bool) -> MlResult<(Tensor, Tensor)> {
+        self.extreme_along("max_with_indices", dim, keepdim, |a, b| a > b)
+    }
+
+    /// Like [`Tensor::max_with_indices`], for the smallest elements.
+    pub fn min_with_indices(&self, dim: usize, keepdim: bool) -> MlResult<(Tensor, Tensor)> {
+        self.extreme_along("min_with_indices", dim, keepdim, |a, b| a < b)
+    }
+
+    /// Where along `dim` the largest elements are, as whole numbers.
+    pub fn argmax(&self, dim: usize, keepdim: bool) -> MlResult<Tensor> {
+        Ok(self.max_with_indices(dim, keepdim)?.1)
+    }
+
+    /// Where along `dim` the smallest elements are, as whole numbers.
+    pub fn argmin(&self, dim: usize, keepdim: bool) -> MlResult<Tensor> {
+        Ok(self.min_with_indices(dim, keepdim)?.1)
+    }
+
+    /// The larger of each pair of elements, broadcasting the shapes like [`Tensor::add`].
+    pub fn maximum(&self, other: &Tensor) -> MlResult<Tensor> {
+        let other: &Tensor = &*self.colocated("maximum", other)?;
+        self.broadcast_with(other, f32::max)
+    }
+
+    /// The smaller of each pair of elements, broadcasting the shapes like [`Tensor::add`].
+    pub fn minimum(&self, other: &Tensor) -> MlResult<Tensor> {
+        let other: &Tensor = &*self.colocated("minimum", other)?;
+        self.broadcast_with(other, f32::min)
+    }
+
+    // The element `better` prefers over all the others, and its index
+    fn extreme_all(
+        &self,
+        op: &'static str,
+        better: impl Fn(f32, f32) -> bool,
+    ) -> MlResult<(f32, usize)> {
+        let data = self.data();
+        let Some(&first) = data.first() else {
+            return Err(MlError::TensorError(TensorError::InvalidOperation {
+                op,
+                reason: "Cannot reduce an empty tensor".to_string(),
+            }));
+        };
+        Ok((data.iter().enumerate()).fold((first, 0), |best, (i, &x)| {
+            if better(x, best.0) {
+                (x, i)
+            } else {
+                best
+            }
+        }))
+    }
+
+    // The elements `better` prefers along `dim`, and their indices along it
+    fn extreme_along(
+        &self,
+        op: &'static str,
+        dim: usize,
+        keepdim: bool,
+        better: impl Fn(f32, f32) -> bool,
+    ) -> MlResult<(Tensor, Tensor)> {
+        self.check_axis(dim)?;
+        let (outer, len, inner) = split_axis(&self.shape, dim);
+        if len == 0 {
+            return Err(MlError::TensorError(TensorError::InvalidOperation {
+                op,
+                reason: format!("Cannot reduce dimension {} of size 0", dim),
+            }));
+        }
+
+        let data = self.data();
+        let mut values = Vec::with_capacity(outer * inner);
+        let mut indices = Vec::with_capacity(outer * inner);
+        for o in 0..outer {
+            for j in 0..inner {
+                let at = |k: usize| data[(o * len + k) * inner + j];
+                let (value, index) = (1..len).fold((at(0), 0), |best, k| {
+                    if better(at(k), best.0) {
+                        (at(k), k)
+                    } else {
+                        best
+                    }
+                });
+                values.push(value);
+                indices.push(index as f32);
+            }
+        }
+
+        let mut shape = self.shape.clone();
+        if keepdim {
+            shape[dim] = 1;
+        } else {
+            shape.remove(dim);
+        }
+        Ok((
+            self.with_data(values, &shape),
+            self.with_data(indices, &shape),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_min_and_max() -> MlResult<()> {
+        let a = Tensor::from_vec(vec![3.0, -1.0, 4.0, -1.0, 5.0, 9.0], &[2, 3])?;
+        assert_eq!(a.max_all()?, (9.0, 5));
+        assert_eq!(a.min_all()?, (-1.0, 1));
+
+        // Each row's best score and class, and each column's smallest entry
+        let (values, indices) = a.max_with_indices(1, false)?;
+        assert_eq!(values.shape(), &[2]);
+        assert_eq!(
+            (values.data(), indices.data()),
+            (&[4.0, 9.0][..], &[2.0, 2.0][..])
+        );
+        let (values, indices) = a.min_with_indices(0, true)?;
+        assert_eq!(values.shape(), &[1, 3]);
+        assert_eq!(values.data(), &[-1.0, -1.0, 4.0]);
+        assert_eq!(indices.data(), &[1.0, 0.0, 0.0]);
+        assert_eq!(a.argmax(0, false)?.data(), &[0.0, 1.0, 1.0]);
+        assert_eq!(a.argmin(1, true)?.data(), &[1.0, 0.0]);
+        assert_eq!(
+            a.max_with_indices(1, true)?.0.data(),
+            a.max_along_axis(1)?.data()
+        );
+
+        // Views reduce in their logical order
+        assert_eq!(
+            a.transpose(0, 1)?.argmax(1, false)?.data(),
+            &[0.0, 1.0, 1.0]
+        );
+
+        // Elementwise, with broadcasting
+        let b = Tensor::from_vec(vec![0.0, 2.0, 8.0], &[3])?;
+        assert_eq!(a.maximum(&b)?.data(), &[3.0, 2.0, 8.0, 0.0, 5.0, 9.0]);
+        assert_eq!(a.minimum(&b)?.data(), &[0.0, -1.0, 4.0, -1.0, 2.0, 8.0]);
+
+        let empty = Tensor::from_vec(vec![], &[2, 0])?;
+        assert!(empty.max_all().is_err());
+        assert!(empty.argmin(1, false).is_err());
+        assert!(a.argmax(2, false).is_err());
+        assert!(a.maximum(&Tensor::from_vec(vec![0.0; 2], &[2])?).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,207 @@
+use super::storage::Storage;
+use super::{Tensor, TensorError};
+use crate::backend::{transfer_policy, FusedOp, TransferPolicy};
+use crate::{MlError, MlResult};
+use std::borrow::Cow;
+use std::sync::Arc;
+
+/// A chain of elementwise ops that is evaluated in a single pass, built with `Tensor::fuse`.
+///
+/// Nothing is computed until `eval`, which writes the result straight into one output
+/// buffer: on the host there are no intermediate tensors, and backends that support fusion
+/// run the whole chain as one kernel.
+///
+/// ```ignore
+/// let z = x.fuse().mul(&y).add_scalar(1.0).exp().eval()?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Fused<'a> {
+    inputs: Vec<&'a Tensor>,
+    ops: Cow<'a, [FusedOp]>,
+}
+
+impl Tensor {
+    /// Starts a fused elementwise chain from this tensor.
+    pub fn fuse(&self) -> Fused<'_> {
+        Fused {
+            inputs: vec![self],
+            ops: Cow::Borrowed(&[]),
+        }
+    }
+}
+
+// Named after the `Tensor` methods they fuse rather than the operator traits
+#[allow(clippy::should_implement_trait)]
+impl<'a> Fused<'a> {
+    pub fn add(self, other: &'a Tensor) -> Self {
+        self.binary(other, FusedOp::Add)
+    }
+
+    pub fn sub(self, other: &'a Tensor) -> Self {
+        self.binary(other, FusedOp::Sub)
+    }
+
+    pub fn mul(self, other: &'a Tensor) -> Self {
+        self.binary(other, FusedOp::Mul)
+    }
+
+    pub fn div(self, other: &'a Tensor) -> Self {
+        self.binary(other, FusedOp::Div)
+    }
+
+    pub fn add_scalar(self, scalar: f32) -> Self {
+        self.push(FusedOp::AddScalar(scalar))
+    }
+
+    pub fn mul_scalar(self, scalar: f32) -> Self {
+        self.push(FusedOp::MulScalar(scalar))
+    }
+
+    pub fn pow(self, power: f32) -> Self {
+        self.push(FusedOp::Pow(power))
+    }
+
+    pub fn neg(self) -> Self {
+        self.push(FusedOp::Neg)
+    }
+
+    pub fn exp(self) -> Self {
+        self.push(FusedOp::Exp)
+    }
+
+    pub fn log(self) -> Self {
+        self.push(FusedOp::Log)
+    }
+
+    pub fn sqrt(self) -> Self {
+        self.push(FusedOp::Sqrt)
+    }
+
+    /// The steps recorded so far.
+    pub fn ops(&self) -> &[FusedOp] {
+        &self.ops
+    }
+
+    // A chain of `ops` over `inputs`, the first of which it starts from
+    pub(crate) fn from_ops(inputs: Vec<&'a Tensor>, ops: &'a [FusedOp]) -> Self {
+        Self {
+            inputs,
+            ops: Cow::Borrowed(ops),
+        }
+    }
+
+    /// Runs the chain and returns its result, on the first input's device.
+    pub fn eval(&self) -> MlResult<Tensor> {
+        self.eval_into(Vec::new())
+    }
+
+    // `eval`, writing the result into `buffer` when it is computed on the host
+    pub(crate) fn eval_into(&self, mut buffer: Vec<f32>) -> MlResult<Tensor> {
+        let first = self.inputs[0];
+        if let Some(other) = self.inputs.iter().find(|t| t.shape != first.shape) {
+            return Err(MlError::TensorError(TensorError::InvalidShape {
+                expected: first.shape.clone(),
+                got: other.shape.clone(),
+            }));
+        }
+
+        if let Some(other) = self.inputs.iter().find(|t| t.device() != first.device()) {
+            // Auto transfer needs no copies: the host path reads every input from the host
+            // and leaves the result on the first input's device
+            if transfer_policy() == TransferPolicy::Error {
+                return Err(MlError::TensorError(TensorError::DeviceMismatch {
+                    op: "fused",
+                    left: first.device(),
+                    right: other.device(),
+                }));
+            }
+        }
+
+        if let Some(result) = self.eval_on_device() {
+            return Ok(result);
+        }
+
+        let data: Vec<&[f32]> = self.inputs.iter().map(|t| t.data()).collect();
+        buffer.clear();
+        buffer.extend((0..first.len()).map(|i| {
+            self.ops
+                .iter()
+                .fold(data[0][i], |value, op| op.apply(value, &data, i))
+        }));
+
+        Ok(Tensor::from_storage(
+            Storage::from_host(buffer),
+            first.shape.clone(),
+            first.backend.clone(),
+        ))
+    }
+
+    fn eval_on_device(&self) -> Option<Tensor> {
+        let first = self.inputs[0];
+        let device = first.backend.device();
+        if self.inputs.iter().any(|t| t.backend.device() != device) {
+            return None;
+        }
+
+        let buffers = self
+            .inputs
+            .iter()
+            .map(|t| {
+                t.dense_storage()
+                    .device(t.backend.as_ref())
+                    .map(Arc::as_ref)
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let result = first.backend.execute_fused(&self.ops, &buffers)?;
+        Some(Tensor::from_storage(
+            Storage::from_device(result),
+            first.shape.clone(),
+            first.backend.clone(),
+        ))
+    }
+
+    // Tensors used more than once in the chain are only passed to the kernel once
+    fn binary(mut self, other: &'a Tensor, op: fn(usize) -> FusedOp) -> Self {
+        let index = match self.inputs.iter().position(|t| std::ptr::eq(*t, other)) {
+            Some(index) => index,
+            None => {
+                self.inputs.push(other);
+                self.inputs.len() - 1
+            }
+        };
+        self.push(op(index))
+    }
+
+    fn push(mut self, op: FusedOp) -> Self {
+        self.ops.to_mut().push(op);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fused_chain_matches_unfused() -> MlResult<()> {
+        let x = Tensor::from_vec(vec![0.5, -1.0, 2.0, 0.0], &[2, 2])?;
+        let y = Tensor::from_vec(vec![2.0, 3.0, -0.5, 4.0], &[2, 2])?;
+
+        let fused = x.fuse().mul(&y).add_scalar(1.0).exp().eval()?;
+        let unfused = x.mul(&y)?.add_scalar(1.0)?.exp()?;
+        assert_eq!(fused.shape(), unfused.shape());
+        for (a, b) in fused.data().iter().zip(unfused.data()) {
+            assert!((a - b).abs() < 1e-5);
+        }
+
+        // Reusing an input doesn't pass it twice
+        let squared = x.fuse().mul(&x);
+        assert_eq!(squared.ops(), &[FusedOp::Mul(0)]);
+        assert_eq!(squared.eval()?.data(), &[0.25, 1.0, 4.0, 0.0]);
+
+        let z = Tensor::from_vec(vec![1.0, 2.0], &[2])?;
+        assert!(x.fuse().add(&z).eval().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,58 @@
+//! Conversions between [`Tensor`] and `half::f16`/`half::bf16` buffers, behind the `half`
+//! feature.
+//!
+//! Tensors compute in f32, so each conversion is one pass over the data with `half`'s
+//! vectorized slice conversions, straight into the destination buffer.
+
+use half::prelude::*;
+use half::{bf16, f16};
+
+use super::Tensor;
+use crate::MlResult;
+
+impl Tensor {
+    /// Creates a tensor on the default device from half-precision values.
+    pub fn from_f16_vec(data: Vec<f16>, shape: &[usize]) -> MlResult<Tensor> {
+        Tensor::from_vec(data.to_f32_vec(), shape)
+    }
+
+    /// Creates a tensor on the default device from bfloat16 values.
+    pub fn from_bf16_vec(data: Vec<bf16>, shape: &[usize]) -> MlResult<Tensor> {
+        Tensor::from_vec(data.to_f32_vec(), shape)
+    }
+
+    /// The data rounded to half precision, to the nearest value with ties to even.
+    pub fn to_f16_vec(&self) -> Vec<f16> {
+        Vec::from_f32_slice(self.data())
+    }
+
+    /// The data rounded to bfloat16, to the nearest value with ties to even.
+    pub fn to_bf16_vec(&self) -> Vec<bf16> {
+        Vec::from_f32_slice(self.data())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_half_conversions() -> MlResult<()> {
+        let values = [1.0, -2.5, 65504.0, 1.0 / 3.0];
+        let tensor = Tensor::from_vec(values.to_vec(), &[2, 2])?;
+
+        let halves = tensor.to_f16_vec();
+        assert_eq!(halves[1], f16::from_f32(-2.5));
+        let back = Tensor::from_f16_vec(halves, &[2, 2])?;
+        assert_eq!(back.shape(), &[2, 2]);
+        assert_eq!(&back.data()[..3], &values[..3]);
+        assert!((back.data()[3] - 1.0 / 3.0).abs() < 1e-3);
+
+        let brains = tensor.to_bf16_vec();
+        assert_eq!(brains[0].to_bits(), 0x3f80);
+        let back = Tensor::from_bf16_vec(brains, &[4])?;
+        assert!((back.data()[3] - 1.0 / 3.0).abs() < 1e-2);
+        assert!(Tensor::from_f16_vec(vec![f16::from_f32(0.0); 3], &[2, 2]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,233 @@
+use super::view::contiguous_strides;
+use super::{Tensor, TensorError};
+use crate::backend::split_axis;
+use crate::{MlError, MlResult};
+
+impl Tensor {
+    /// The entries at `indices` along `dim`, in their order, so selecting rows of a
+    /// `[vocab, dim]` table with token ids looks up their embeddings. `indices` is a 1D
+    /// tensor of whole numbers and may repeat them; its length replaces the size of `dim`.
+    pub fn index_select(&self, dim: usize, indices: &Tensor) -> MlResult<Tensor> {
+        self.check_axis(dim)?;
+        if indices.shape.len() != 1 {
+            return Err(MlError::TensorError(TensorError::InvalidOperation {
+                op: "index_select",
+                reason: format!("Expected 1D indices, got shape {:?}", indices.shape),
+            }));
+        }
+
+        let (outer, len, inner) = split_axis(&self.shape, dim);
+        let indices = indices
+            .data()
+            .iter()
+            .map(|&i| index_value("index_select", i, len))
+            .collect::<MlResult<Vec<_>>>()?;
+
+        let data = self.data();
+        let mut result = Vec::with_capacity(outer * indices.len() * inner);
+        for block in data.chunks((len * inner).max(1)).take(outer) {
+            for &i in &indices {
+                result.extend_from_slice(&block[i * inner..(i + 1) * inner]);
+            }
+        }
+
+        let mut shape = self.shape.clone();
+        shape[dim] = indices.len();
+        Ok(self.with_data(result, &shape))
+    }
+
+    /// Picks one entry along `dim` for each element of `index`: the result has `index`'s
+    /// shape, and for `dim = 1` of a 2D tensor `out[i][j] = self[i][index[i][j]]`, which
+    /// gathers each row's score for its label. `index` has as many dimensions as the tensor
+    /// and is no larger along the others.
+    pub fn gather(&self, dim: usize, index: &Tensor) -> MlResult<Tensor> {
+        let data = self.data();
+        let mut result = Vec::with_capacity(index.len());
+        self.walk_index("gather", dim, index, |_, offset| result.push(data[offset]))?;
+        Ok(self.with_data(result, &index.shape))
+    }
+
+    /// The tensor with each element of `src` written to the place along `dim` its entry in
+    /// `index` names: for `dim = 1` of a 2D tensor `out[i][index[i][j]] = src[i][j]`. Scattering
+    /// ones into zeros one-hot encodes labels. `src` has `index`'s shape, which fits the
+    /// tensor as it does for [`Tensor::gather`]; where indices repeat, the last write wins.
+    pub fn scatter(&self, dim: usize, index: &Tensor, src: &Tensor) -> MlResult<Tensor> {
+        self.scatter_with("scatter", dim, index, src, |out, value| *out = value)
+    }
+
+    /// Like [`Tensor::scatter`], but adds `src` to the tensor's elements, accumulating where
+    /// indices repeat: segment sums, and the backward pass of [`Tensor::gather`].
+    pub fn scatter_add(&self, dim: usize, index: &Tensor, src: &Tensor) -> MlResult<Tensor> {
+        self.scatter_with("scatter_add", dim, index, src, |out, value| *out += value)
+    }
+
+    fn scatter_with(
+        &self,
+        op: &'static str,
+        dim: usize,
+        index: &Tensor,
+        src: &Tensor,
+        combine: impl Fn(&mut f32, f32),
+    ) -> MlResult<Tensor> {
+        if src.shape != index.shape {
+            return Err(MlError::TensorError(TensorError::InvalidOperation {
+                op,
+                reason: format!(
+                    "Source of shape {:?} doesn't match index of shape {:?}",
+                    src.shape, index.shape
+                ),
+            }));
+        }
+
+        let values = src.data();
+        let mut result = self.data().to_vec();
+        self.walk_index(op, dim, index, |i, offset| {
+            combine(&mut result[offset], values[i])
+        })?;
+        Ok(self.with_data(result, &self.shape))
+    }
+
+    // Calls `f` with the position of each element of `index` and the offset, in the
+    // tensor's row-major data, of the element it picks along `dim`
+    fn walk_index(
+        &self,
+        op: &'static str,
+        dim: usize,
+        index: &Tensor,
+        mut f: impl FnMut(usize, usize),
+    ) -> MlResult<()> {
+        self.check_axis(dim)?;
+        let fits = index.shape.len() == self.shape.len()
+            && (index.shape.iter().zip(&self.shape))
+                .enumerate()
+                .all(|(axis, (&i, &s))| axis == dim || i <= s);
+        if !fits {
+            return Err(MlError::TensorError(TensorError::InvalidOperation {
+                op,
+                reason: format!(
+                    "Index of shape {:?} doesn't fit a tensor of shape {:?} along dimension {}",
+                    index.shape, self.shape, dim
+                ),
+            }));
+        }
+
+        let strides = contiguous_strides(&self.shape);
+        let mut position = vec![0; index.shape.len()];
+        for (n, &i) in index.data().iter().enumerate() {
+            let i = index_value(op, i, self.shape[dim])?;
+            let offset: usize = (position.iter().zip(&strides).enumerate())
+                .map(|(axis, (&p, &stride))| (if axis == dim { i } else { p }) * stride)
+                .sum();
+            f(n, offset);
+
+            for axis in (0..position.len()).rev() {
+                position[axis] += 1;
+                if position[axis] < index.shape[axis] {
+                    break;
+                }
+                position[axis] = 0;
+            }
+        }
+        Ok(())
+    }
+
+    // Fails unless the tensor has a dimension `axis`
+    pub(super) fn check_axis(&self, axis: usize) -> MlResult<()> {
+        if axis >= self.shape.len() {
+            return Err(MlError::TensorError(TensorError::InvalidAxis {
+                axis,
+                shape: self.shape.clone(),
+            }));
+        }
+        Ok(())
+    }
+}
+
+// `value` as an index into a dimension of `size`
+fn index_value(op: &'static str, value: f32, size: usize) -> MlResult<usize> {
+    if value < 0.0 || value.fract() != 0.0 || value >= size as f32 {
+        return Err(MlError::TensorError(TensorError::InvalidOperation {
+            op,
+            reason: format!(
+                "Index {} is out of range for a dimension of size {}",
+                value, size
+            ),
+        }));
+    }
+    Ok(value as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_select_and_gather() -> MlResult<()> {
+        // Embedding lookup: rows of the table for each token id
+        let table = Tensor::from_vec((0..8).map(|x| x as f32).collect(), &[4, 2])?;
+        let ids = Tensor::from_vec(vec![3.0, 0.0, 3.0], &[3])?;
+        let embedded = table.index_select(0, &ids)?;
+        assert_eq!(embedded.shape(), &[3, 2]);
+        assert_eq!(embedded.data(), &[6.0, 7.0, 0.0, 1.0, 6.0, 7.0]);
+        let columns = table.index_select(1, &Tensor::from_vec(vec![1.0], &[1])?)?;
+        assert_eq!(columns.data(), &[1.0, 3.0, 5.0, 7.0]);
+
+        // Each row's score for its label
+        let scores = Tensor::from_vec(vec![0.1, 0.2, 0.7, 0.6, 0.3, 0.1], &[2, 3])?;
+        let labels = Tensor::from_vec(vec![2.0, 0.0], &[2, 1])?;
+        let picked = scores.gather(1, &labels)?;
+        assert_eq!(picked.shape(), &[2, 1]);
+        assert_eq!(picked.data(), &[0.7, 0.6]);
+        let index = Tensor::from_vec(vec![1.0, 0.0, 0.0], &[1, 3])?;
+        assert_eq!(scores.gather(0, &index)?.data(), &[0.6, 0.2, 0.7]);
+
+        // Views are read in their logical order
+        let flipped = Tensor::from_vec(vec![1.0, 0.0], &[2, 1])?;
+        assert_eq!(
+            scores.transpose(0, 1)?.gather(1, &flipped)?.data(),
+            &[0.6, 0.2]
+        );
+
+        assert!(table
+            .index_select(0, &Tensor::from_vec(vec![4.0], &[1])?)
+            .is_err());
+        assert!(table.index_select(2, &ids).is_err());
+        assert!(scores
+            .gather(1, &Tensor::from_vec(vec![0.5], &[1, 1])?)
+            .is_err());
+        assert!(scores.gather(1, &ids).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_scatter() -> MlResult<()> {
+        // One-hot encoding of labels 2 and 0
+        let labels = Tensor::from_vec(vec![2.0, 0.0], &[2, 1])?;
+        let zeros = Tensor::from_vec(vec![0.0; 6], &[2, 3])?;
+        let ones = Tensor::from_vec(vec![1.0; 2], &[2, 1])?;
+        let one_hot = zeros.scatter(1, &labels, &ones)?;
+        assert_eq!(one_hot.data(), &[0.0, 0.0, 1.0, 1.0, 0.0, 0.0]);
+
+        // Segment sums: values added into the slot of their segment
+        let segments = Tensor::from_vec(vec![0.0, 1.0, 0.0, 1.0, 1.0], &[5])?;
+        let values = Tensor::from_vec(vec![1.0, 2.0, 3.0, 4.0, 5.0], &[5])?;
+        let sums = Tensor::from_vec(vec![0.0; 2], &[2])?.scatter_add(0, &segments, &values)?;
+        assert_eq!(sums.data(), &[4.0, 11.0]);
+
+        // scatter_add undoes gather for the gradient
+        let scores = Tensor::from_vec(vec![0.1, 0.2, 0.7, 0.6, 0.3, 0.1], &[2, 3])?;
+        let grad = Tensor::from_vec(vec![1.0, 1.0], &[2, 1])?;
+        assert_eq!(scores.gather(1, &labels)?.shape(), grad.shape());
+        assert_eq!(zeros.scatter_add(1, &labels, &grad)?.data(), one_hot.data());
+
+        let out_of_range = Tensor::from_vec(vec![3.0, 0.0], &[2, 1])?;
+        match zeros.scatter(1, &out_of_range, &ones) {
+            Err(MlError::TensorError(TensorError::InvalidOperation { op, .. })) => {
+                assert_eq!(op, "scatter")
+            }
+            other => panic!("expected an out-of-range error, got {:?}", other),
+        }
+        assert!(zeros.scatter_add(1, &labels, &values).is_err());
+        Ok(())
+    }
+}
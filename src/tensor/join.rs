@@ -0,0 +1,225 @@
+use super::{Tensor, TensorError};
+use crate::backend::split_axis;
+use crate::{MlError, MlResult};
+
+impl Tensor {
+    /// Joins `tensors` end to end along `dim`. They must have the same rank and the same
+    /// sizes along every other dimension, so `[2, 3]` and `[2, 5]` joined along dimension 1
+    /// give `[2, 8]`, and joining along dimension 0 builds a batch from smaller ones.
+    pub fn cat(tensors: &[&Tensor], dim: usize) -> MlResult<Tensor> {
+        let Some(first) = tensors.first() else {
+            return Err(MlError::TensorError(TensorError::InvalidOperation {
+                op: "cat",
+                reason: "Cannot concatenate an empty list of tensors".to_string(),
+            }));
+        };
+        first.check_axis(dim)?;
+
+        let mut shape = first.shape.clone();
+        shape[dim] = 0;
+        let mut parts = Vec::with_capacity(tensors.len());
+        for &tensor in tensors {
+            let matches = tensor.shape.len() == shape.len()
+                && (tensor.shape.iter().zip(&first.shape))
+                    .enumerate()
+                    .all(|(axis, (a, b))| axis == dim || a == b);
+            if !matches {
+                return Err(MlError::TensorError(TensorError::InvalidOperation {
+                    op: "cat",
+                    reason: format!(
+                        "Cannot join shapes {:?} and {:?} along dimension {}",
+                        first.shape, tensor.shape, dim
+                    ),
+                }));
+            }
+            shape[dim] += tensor.shape[dim];
+            parts.push(first.colocated("cat", tensor)?);
+        }
+
+        // Each tensor contributes a contiguous block to every outer index
+        let (outer, _, inner) = split_axis(&first.shape, dim);
+        let mut data = Vec::with_capacity(shape.iter().product());
+        for o in 0..outer {
+            for part in &parts {
+                let block = part.shape[dim] * inner;
+                data.extend_from_slice(&part.data()[o * block..(o + 1) * block]);
+            }
+        }
+        Ok(first.with_data(data, &shape))
+    }
+
+    /// Joins `tensors` of one shape along a new dimension inserted at `dim`, unlike
+    /// [`Tensor::cat`], so per-sample `[features]` outputs stacked at 0 form a
+    /// `[samples, features]` batch. `dim` can be at most the tensors' rank.
+    pub fn stack(tensors: &[&Tensor], dim: usize) -> MlResult<Tensor> {
+        let Some(first) = tensors.first() else {
+            return Err(MlError::TensorError(TensorError::InvalidOperation {
+                op: "stack",
+                reason: "Cannot stack an empty list of tensors".to_string(),
+            }));
+        };
+        if dim > first.shape.len() {
+            return Err(MlError::TensorError(TensorError::InvalidAxis {
+                axis: dim,
+                shape: first.shape.clone(),
+            }));
+        }
+
+        let mut shape = first.shape.clone();
+        shape.insert(dim, 1);
+        let parts = tensors
+            .iter()
+            .map(|tensor| {
+                if tensor.shape != first.shape {
+                    return Err(MlError::TensorError(TensorError::InvalidShape {
+                        expected: first.shape.clone(),
+                        got: tensor.shape.clone(),
+                    }));
+                }
+                tensor.reshape(&shape)
+            })
+            .collect::<MlResult<Vec<_>>>()?;
+        Tensor::cat(&parts.iter().collect::<Vec<_>>(), dim)
+    }
+
+    /// Splits the tensor along `dim` into pieces of `split_size`, the last one smaller if
+    /// the size doesn't divide evenly. The pieces are views, and [`Tensor::cat`] joins them
+    /// back together.
+    pub fn split(&self, split_size: usize, dim: usize) -> MlResult<Vec<Tensor>> {
+        self.check_axis(dim)?;
+        if split_size == 0 {
+            return Err(MlError::TensorError(TensorError::InvalidOperation {
+                op: "split",
+                reason: "Cannot split into pieces of size 0".to_string(),
+            }));
+        }
+
+        let len = self.shape[dim];
+        (0..len.max(1))
+            .step_by(split_size)
+            .map(|start| self.slice(dim, start, len.min(start + split_size)))
+            .collect()
+    }
+
+    /// Splits the tensor along `dim` into `chunks` pieces of equal size, the last one
+    /// smaller if the size doesn't divide evenly, so a `[batch, 3 * dim]` QKV projection
+    /// chunked 3 ways along dimension 1 gives the queries, keys and values. Like
+    /// [`Tensor::split`] there may be fewer pieces than asked for when the size is small.
+    pub fn chunk(&self, chunks: usize, dim: usize) -> MlResult<Vec<Tensor>> {
+        self.check_axis(dim)?;
+        if chunks == 0 {
+            return Err(MlError::TensorError(TensorError::InvalidOperation {
+                op: "chunk",
+                reason: "Cannot split into 0 chunks".to_string(),
+            }));
+        }
+        self.split(self.shape[dim].div_ceil(chunks).max(1), dim)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{register_backend, unregister_backend, DeviceType, HostBackend};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_cat() -> MlResult<()> {
+        let a = Tensor::from_vec(vec![1.0, 2.0, 3.0, 4.0], &[2, 2])?;
+        let b = Tensor::from_vec(vec![5.0, 6.0], &[1, 2])?;
+        let rows = Tensor::cat(&[&a, &b], 0)?;
+        assert_eq!(rows.shape(), &[3, 2]);
+        assert_eq!(rows.data(), &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+
+        let columns = Tensor::cat(&[&a, &b.reshape(&[2, 1])?, &a], 1)?;
+        assert_eq!(columns.shape(), &[2, 5]);
+        assert_eq!(
+            columns.data(),
+            &[1.0, 2.0, 5.0, 1.0, 2.0, 3.0, 4.0, 6.0, 3.0, 4.0]
+        );
+
+        // Any rank, and views read in their logical order
+        let c = Tensor::from_vec((0..8).map(|x| x as f32).collect(), &[2, 2, 2])?;
+        let joined = Tensor::cat(&[&c, &c.narrow(1, 1, 1)?], 1)?;
+        assert_eq!(joined.shape(), &[2, 3, 2]);
+        assert_eq!(joined.data()[..6], [0.0, 1.0, 2.0, 3.0, 2.0, 3.0]);
+        assert_eq!(
+            Tensor::cat(&[&a.transpose(0, 1)?], 0)?.data(),
+            &[1.0, 3.0, 2.0, 4.0]
+        );
+
+        assert!(Tensor::cat(&[&a, &b], 1).is_err());
+        assert!(Tensor::cat(&[&a, &c], 0).is_err());
+        assert!(Tensor::cat(&[&a], 2).is_err());
+        assert!(Tensor::cat(&[], 0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_stack() -> MlResult<()> {
+        let a = Tensor::from_vec(vec![1.0, 2.0, 3.0], &[3])?;
+        let b = Tensor::from_vec(vec![4.0, 5.0, 6.0], &[3])?;
+
+        let batch = Tensor::stack(&[&a, &b], 0)?;
+        assert_eq!(batch.shape(), &[2, 3]);
+        assert_eq!(batch.data(), &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        let pairs = Tensor::stack(&[&a, &b], 1)?;
+        assert_eq!(pairs.shape(), &[3, 2]);
+        assert_eq!(pairs.data(), &[1.0, 4.0, 2.0, 5.0, 3.0, 6.0]);
+
+        // Stacking owned outputs
+        let outputs = [batch.clone(), batch.mul_scalar(2.0)?];
+        let stacked = Tensor::stack(&outputs.iter().collect::<Vec<_>>(), 2)?;
+        assert_eq!(stacked.shape(), &[2, 3, 2]);
+        assert_eq!(stacked.data()[..4], [1.0, 2.0, 2.0, 4.0]);
+
+        assert!(Tensor::stack(&[&a, &batch], 0).is_err());
+        assert!(Tensor::stack(&[&a], 2).is_err());
+        assert!(Tensor::stack(&[], 0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cat_keeps_device() -> MlResult<()> {
+        let device = DeviceType::Custom("test-cat");
+        register_backend("test-cat", Arc::new(HostBackend::new("test-cat")))?;
+
+        let a = Tensor::from_vec(vec![1.0, 2.0], &[2])?.to_device(device)?;
+        let b = Tensor::from_vec(vec![3.0, 4.0], &[2])?.to_device(device)?;
+        let joined = Tensor::cat(&[&a, &b], 0)?;
+        assert_eq!(joined.device(), device);
+        assert_eq!(joined.data(), &[1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(Tensor::stack(&[&a, &b], 0)?.device(), device);
+
+        unregister_backend("test-cat");
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_and_chunk() -> MlResult<()> {
+        let a = Tensor::from_vec((0..10).map(|x| x as f32).collect(), &[5, 2])?;
+
+        let pieces = a.split(2, 0)?;
+        let shapes: Vec<_> = pieces.iter().map(|p| p.shape().to_vec()).collect();
+        assert_eq!(shapes, [vec![2, 2], vec![2, 2], vec![1, 2]]);
+        assert_eq!(pieces[2].data(), &[8.0, 9.0]);
+        let joined = Tensor::cat(&pieces.iter().collect::<Vec<_>>(), 0)?;
+        assert_eq!(joined.data(), a.data());
+
+        // Queries, keys and values of a fused projection
+        let qkv = Tensor::from_vec((0..12).map(|x| x as f32).collect(), &[2, 6])?;
+        let parts = qkv.chunk(3, 1)?;
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[1].shape(), &[2, 2]);
+        assert_eq!(parts[1].data(), &[2.0, 3.0, 8.0, 9.0]);
+
+        // Fewer chunks when they would otherwise be empty
+        assert_eq!(a.chunk(4, 0)?.len(), 3);
+        assert_eq!(a.chunk(3, 1)?.len(), 2);
+
+        assert!(a.split(0, 0).is_err());
+        assert!(a.chunk(0, 0).is_err());
+        assert!(a.split(1, 2).is_err());
+        Ok(())
+    }
+}
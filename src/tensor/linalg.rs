@@ -0,0 +1,154 @@
+use super::{Tensor, TensorError};
+use crate::{MlError, MlResult};
+
+impl Tensor {
+    /// The inverse of each `[n, n]` matrix in this `[..., n, n]` tensor, so a `[batch, n, n]`
+    /// stack of covariances is inverted in one call. Fails if any matrix is singular.
+    pub fn inverse(&self) -> MlResult<Tensor> {
+        let n = self.square_size("inverse")?;
+        let data = self.data();
+        let mut result = Vec::with_capacity(data.len());
+        for (i, matrix) in data.chunks(n * n).enumerate() {
+            let mut identity = vec![0.0; n * n];
+            for j in 0..n {
+                identity[j * n + j] = 1.0;
+            }
+            gauss_jordan(matrix.to_vec(), &mut identity, n, n)
+                .ok_or_else(|| singular("inverse", i))?;
+            result.extend(identity);
+        }
+        Ok(self.with_data(result, &self.shape))
+    }
+
+    /// Solves `self · x = b` for each `[n, n]` matrix in this `[..., n, n]` tensor. `b` is
+    /// either `[..., n]`, one right-hand side per matrix, or `[..., n, k]`, and `x` has its
+    /// shape. This avoids forming the inverse, for instance for Mahalanobis distances.
+    pub fn solve(&self, b: &Tensor) -> MlResult<Tensor> {
+        let n = self.square_size("solve")?;
+        let b: &Tensor = &*self.colocated("solve", b)?;
+        let batch = &self.shape[..self.shape.len() - 2];
+        let same_batch = b.shape.starts_with(batch);
+        let k = match b.shape.get(batch.len()..) {
+            Some(&[rows]) if same_batch && rows == n => 1,
+            Some(&[rows, k]) if same_batch && rows == n => k,
+            _ => {
+                return Err(MlError::TensorError(TensorError::InvalidOperation {
+                    op: "solve",
+                    reason: format!(
+                        "Right-hand side of shape {:?} doesn't match matrices of shape {:?}",
+                        b.shape, self.shape
+                    ),
+                }))
+            }
+        };
+
+        let mut result = Vec::with_capacity(b.len());
+        let rhs = b.data().chunks(n * k);
+        for (i, (matrix, rhs)) in self.data().chunks(n * n).zip(rhs).enumerate() {
+            let mut x = rhs.to_vec();
+            gauss_jordan(matrix.to_vec(), &mut x, n, k).ok_or_else(|| singular("solve", i))?;
+            result.extend(x);
+        }
+        Ok(self.with_data(result, &b.shape))
+    }
+
+    // The size `n` of the matrices of a `[..., n, n]` tensor
+    fn square_size(&self, op: &'static str) -> MlResult<usize> {
+        match self.shape.as_slice() {
+            [.., rows, cols] if rows == cols => Ok(*rows),
+            shape => Err(MlError::TensorError(TensorError::InvalidOperation {
+                op,
+                reason: format!(
+                    "Expected square matrices [..., n, n], got shape {:?}",
+                    shape
+                ),
+            })),
+        }
+    }
+}
+
+fn singular(op: &'static str, index: usize) -> MlError {
+    MlError::TensorError(TensorError::InvalidOperation {
+        op,
+        reason: format!("Matrix {} of the batch is singular", index),
+    })
+}
+
+// Reduces the `[n, n]` matrix `a` to the identity with partial pivoting, applying the same
+// row operations to the `[n, k]` matrix `b`, which ends up as the solution of `a · x = b`.
+// `None` if a pivot is negligible next to the largest entry of `a`.
+fn gauss_jordan(mut a: Vec<f32>, b: &mut [f32], n: usize, k: usize) -> Option<()> {
+    let scale = a.iter().fold(0.0f32, |max, x| max.max(x.abs()));
+    let tolerance = scale * n as f32 * f32::EPSILON;
+
+    for col in 0..n {
+        let pivot_row =
+            (col..n).max_by(|&i, &j| a[i * n + col].abs().total_cmp(&a[j * n + col].abs()))?;
+        let pivot = a[pivot_row * n + col];
+        if !pivot.is_finite() || pivot.abs() <= tolerance {
+            return None;
+        }
+        if pivot_row != col {
+            for j in 0..n {
+                a.swap(col * n + j, pivot_row * n + j);
+            }
+            for j in 0..k {
+                b.swap(col * k + j, pivot_row * k + j);
+            }
+        }
+
+        for j in 0..n {
+            a[col * n + j] /= pivot;
+        }
+        for j in 0..k {
+            b[col * k + j] /= pivot;
+        }
+        for row in (0..n).filter(|&row| row != col) {
+            let factor = a[row * n + col];
+            if factor == 0.0 {
+                continue;
+            }
+            for j in 0..n {
+                a[row * n + j] -= factor * a[col * n + j];
+            }
+            for j in 0..k {
+                b[row * k + j] -= factor * b[col * k + j];
+            }
+        }
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::assert_close;
+
+    #[test]
+    fn test_batched_inverse_and_solve() -> MlResult<()> {
+        // A matrix that needs a row swap, and a diagonal one
+        let a = Tensor::from_vec(vec![0.0, 2.0, 1.0, 3.0, 2.0, 0.0, 0.0, 4.0], &[2, 2, 2])?;
+        let inverse = a.inverse()?;
+        assert_eq!(inverse.shape(), &[2, 2, 2]);
+        assert_close(inverse.data(), &[-1.5, 1.0, 0.5, 0.0, 0.5, 0.0, 0.0, 0.25]);
+        assert_close(inverse.inverse()?.data(), a.data());
+
+        // One right-hand side per matrix, or several
+        let x = a.solve(&Tensor::from_vec(vec![2.0, 4.0, 1.0, 1.0], &[2, 2])?)?;
+        assert_eq!(x.shape(), &[2, 2]);
+        assert_close(x.data(), &[1.0, 1.0, 0.5, 0.25]);
+        let eye = Tensor::from_vec(vec![1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0], &[2, 2, 2])?;
+        assert_close(a.solve(&eye)?.data(), inverse.data());
+
+        // An unbatched matrix is a batch of one
+        let single = Tensor::from_vec(vec![4.0, 7.0, 2.0, 6.0], &[2, 2])?;
+        assert_close(single.inverse()?.data(), &[0.6, -0.7, -0.2, 0.4]);
+
+        let degenerate =
+            Tensor::from_vec(vec![1.0, 0.0, 0.0, 1.0, 1.0, 2.0, 2.0, 4.0], &[2, 2, 2])?;
+        assert!(degenerate.inverse().is_err());
+        assert!(Tensor::from_vec(vec![0.0; 6], &[2, 3])?.inverse().is_err());
+        assert!(a.solve(&Tensor::from_vec(vec![1.0; 3], &[3])?).is_err());
+        Ok(())
+    }
+}
@@ -1,18 +1,49 @@
+use std::borrow::Cow;
 use std::fmt::Display;
 
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
+mod broadcast;
 // mod builder;
+mod complex;
+mod conv;
 mod display;
-
+mod extrema;
+mod fusion;
+#[cfg(feature = "half")]
+mod half;
+mod indexing;
+mod join;
+mod linalg;
+#[cfg(feature = "ndarray")]
+mod ndarray;
+mod quantized;
+mod sampling;
+#[cfg(feature = "serde")]
+mod serde;
+mod storage;
+mod view;
+
+pub use broadcast::broadcast_shapes;
 // pub use builder::*;
-
-use crate::serialize::{Deserialize, Serialize};
+pub use complex::ComplexTensor;
+pub use fusion::Fused;
+pub(crate) use quantized::{affine_params, quantize_value};
+pub use quantized::{QuantDtype, QuantParams, QuantizedTensor};
+
+use crate::amp::{autocast_precision, Precision};
+use crate::graph::{jit, Op};
+use crate::log::{log_debug, record_fallback};
+use crate::serialize::{format, Deserialize, Serialize};
 use crate::{MlError, MlResult};
 
-use crate::backend::Backend;
+use crate::backend::{
+    depthwise_conv2d, split_axis, Backend, BackendCapabilities, Conv2dShape, DeviceOp, ReduceOp,
+};
 
-use crate::backend::{Device, DeviceType};
+use crate::backend::{
+    check_deterministic, registered_backend, transfer_policy, Device, DeviceType, TransferPolicy,
+};
 
 #[cfg(feature = "cpu")]
 use crate::backend::CpuBackend;
@@ -20,8 +51,14 @@ use crate::backend::CpuBackend;
 use crate::backend::CudaBackend;
 #[cfg(any(feature = "vulkan", feature = "cuda", feature = "mps", feature = "cpu"))]
 use crate::backend::DeviceManager;
+#[cfg(feature = "mps")]
+use crate::backend::MpsBackend;
+#[cfg(feature = "opencl")]
+use crate::backend::OpenClBackend;
 #[cfg(feature = "vulkan")]
 use crate::backend::VulkanBackend;
+#[cfg(feature = "wgpu")]
+use crate::backend::WgpuBackend;
 
 #[derive(Debug, Clone)]
 pub enum TensorError {
@@ -48,6 +85,15 @@ pub enum TensorError {
     InvalidBackend {
         backend: DeviceType,
     },
+    BroadcastError {
+        left_shape: Vec<usize>,
+        right_shape: Vec<usize>,
+    },
+    DeviceMismatch {
+        op: &'static str,
+        left: DeviceType,
+        right: DeviceType,
+    },
 }
 
 impl std::error::Error for TensorError {}
@@ -76,14 +122,47 @@ impl Display for TensorError {
             TensorError::InvalidBackend { backend } => {
                 write!(f, "Invalid backend: {}", backend)
             }
+            TensorError::BroadcastError {
+                left_shape,
+                right_shape,
+            } => {
+                write!(
+                    f,
+                    "Shapes {:?} and {:?} cannot be broadcast together",
+                    left_shape, right_shape
+                )
+            }
+            TensorError::DeviceMismatch { op, left, right } => {
+                write!(
+                    f,
+                    "Operation '{}' got tensors on different devices: {} and {}. Move one with \
+                     to_device or enable TransferPolicy::Auto",
+                    op, left, right
+                )
+            }
         }
     }
 }
 
+use storage::{Storage, TensorId};
+use view::contiguous_strides;
+
+/// An n-dimensional array of f32 values.
+///
+/// A tensor reads its elements from a storage through its strides and offset, so several
+/// tensors can share one: [`Tensor::reshape`] and [`Tensor::transpose`] return views of the
+/// same storage rather than copies. Tensors are never modified in place, so sharing is
+/// invisible apart from the memory it saves.
 #[derive(Debug, Clone)]
 pub struct Tensor {
-    data: Vec<f32>,
+    storage: Arc<Storage>,
     shape: Vec<usize>,
+    strides: Vec<usize>,
+    offset: usize,
+    // The elements of a view that doesn't cover its storage densely, gathered when first
+    // needed
+    dense: OnceLock<Arc<Storage>>,
+    id: TensorId,
     backend: Arc<dyn Backend>,
 }
 
@@ -93,51 +172,23 @@ impl Tensor {
         let flat_data: Vec<f32> = data.into_iter().flatten().collect();
 
         let device_type = DeviceManager::get_default_device();
-        println!("Creating tensor with device: {:?}", device_type);
-
-        let backend: Arc<dyn Backend> = match device_type {
-            #[cfg(feature = "cuda")]
-            DeviceType::Cuda => {
-                println!("Attempting to create CudaBackend...");
-                match CudaBackend::new() {
-                    Ok(backend) => {
-                        println!("Successfully created CudaBackend");
-                        Arc::new(backend)
-                    }
-                    Err(e) => {
-                        println!("Failed to create CudaBackend: {:?}, falling back to CPU", e);
-                        Arc::new(CpuBackend::new()?)
-                    }
-                }
-            }
-            #[cfg(feature = "vulkan")]
-            DeviceType::Vulkan => {
-                println!("Attempting to create VulkanBackend...");
-                match VulkanBackend::new() {
-                    Ok(backend) => {
-                        println!("Successfully created VulkanBackend");
-                        Arc::new(backend)
-                    }
-                    Err(e) => {
-                        println!(
-                            "Failed to create VulkanBackend: {:?}, falling back to CPU",
-                            e
-                        );
-                        Arc::new(CpuBackend::new()?)
-                    }
-                }
-            }
-            _ => {
-                println!("Using CpuBackend");
+        log_debug!("Creating tensor on {}", device_type);
+
+        let backend = match create_backend(device_type) {
+            Ok(backend) => backend,
+            Err(e) => {
+                record_fallback(device_type, DeviceType::Cpu, e.to_string());
                 Arc::new(CpuBackend::new()?)
             }
         };
 
-        Ok(Self {
-            data: flat_data,
+        check_deterministic(backend.as_ref())?;
+
+        Ok(Self::from_storage(
+            Storage::from_host(flat_data),
             shape,
             backend,
-        })
+        ))
     }
 
     pub fn from_vec(data: Vec<f32>, shape: &[usize]) -> MlResult<Self> {
@@ -149,33 +200,196 @@ impl Tensor {
             }));
         }
 
-        let device_type = DeviceManager::get_default_device();
-        let backend: Arc<dyn Backend> = match device_type {
-            DeviceType::Cpu => Arc::new(CpuBackend::new()?),
-            #[cfg(feature = "cuda")]
-            DeviceType::Cuda => Arc::new(CpuBackend::new()?),
-            #[cfg(feature = "mps")]
-            DeviceType::Mps => Arc::new(CpuBackend::new()?),
-            #[cfg(feature = "vulkan")]
-            DeviceType::Vulkan => Arc::new(VulkanBackend::new()?),
-        };
+        Ok(Self::from_storage(
+            Storage::from_host(data),
+            shape.to_vec(),
+            backend_for(DeviceManager::get_default_device())?,
+        ))
+    }
 
-        Ok(Self {
-            data,
-            shape: shape.to_vec(),
+    // A dense tensor of `shape` over all of `storage`
+    fn from_storage(storage: Storage, shape: Vec<usize>, backend: Arc<dyn Backend>) -> Self {
+        Self {
+            storage: Arc::new(storage),
+            strides: contiguous_strides(&shape),
+            offset: 0,
+            dense: OnceLock::new(),
+            id: TensorId::new(shape.iter().product()),
+            shape,
             backend,
-        })
+        }
+    }
+
+    /// The device the tensor's backend runs on.
+    pub fn device(&self) -> DeviceType {
+        self.backend.device()
+    }
+
+    /// What the tensor's backend supports.
+    pub fn capabilities(&self) -> BackendCapabilities {
+        self.backend.capabilities()
+    }
+
+    /// Copies the tensor to `device`. The data passes through host memory once.
+    pub fn to_device(&self, device: DeviceType) -> MlResult<Tensor> {
+        Ok(Tensor::from_storage(
+            Storage::from_host(self.try_data()?.to_vec()),
+            self.shape.clone(),
+            backend_for(device)?,
+        ))
     }
 
     pub fn shape(&self) -> &[usize] {
         &self.shape
     }
 
+    /// Host view of the tensor's data. Tensors produced on a GPU are copied back the first
+    /// time this is called; later calls reuse the copy. Panics if that copy fails;
+    /// [`Tensor::try_data`] returns the error instead.
     pub fn data(&self) -> &[f32] {
-        &self.data
+        if self.is_contiguous() {
+            return &self.storage.host()[self.offset..self.offset + self.len()];
+        }
+        self.dense_storage().host()
     }
 
+    /// [`Tensor::data`], failing with the device's error if the data can't be copied back.
+    pub fn try_data(&self) -> MlResult<&[f32]> {
+        let host = self.storage.try_host()?;
+        if self.is_contiguous() {
+            return Ok(&host[self.offset..self.offset + self.len()]);
+        }
+        // The strided copy reads the storage, which is now on the host
+        self.dense_storage().try_host()
+    }
+
+    /// The tensor's data, moved out without a copy if it is already on the host and no
+    /// other tensor shares it.
+    pub fn into_data(self) -> Vec<f32> {
+        let storage = Arc::clone(self.dense_storage());
+        drop(self);
+        match Arc::try_unwrap(storage) {
+            Ok(storage) => storage.into_host(),
+            Err(storage) => storage.host().to_vec(),
+        }
+    }
+
+    /// A tensor from the `no_std` inference core, on the default backend.
+    pub fn from_core(tensor: cetana_core::Tensor) -> MlResult<Tensor> {
+        let shape = tensor.shape().to_vec();
+        Tensor::from_vec(tensor.into_data(), &shape)
+    }
+
+    /// This tensor as a `no_std` inference core tensor, in host memory.
+    pub fn into_core(self) -> MlResult<cetana_core::Tensor> {
+        let shape = self.shape.clone();
+        Ok(cetana_core::Tensor::from_vec(self.into_data(), &shape)?)
+    }
+
+    /// Whether the data currently has a copy in device memory.
+    pub fn is_on_device(&self) -> bool {
+        self.dense.get().unwrap_or(&self.storage).is_on_device()
+    }
+
+    /// Copies the tensor to host memory on the CPU backend.
+    pub fn to_cpu(&self) -> MlResult<Tensor> {
+        self.to_device(DeviceType::Cpu)
+    }
+
+    // Runs `f`, the op `op` applied to `self` and `others`, recording it when a trace is
+    // being captured on this thread
+    fn traced(
+        &self,
+        op: Op,
+        others: &[&Tensor],
+        f: impl FnOnce() -> MlResult<Tensor>,
+    ) -> MlResult<Tensor> {
+        jit::record(op, self, others, f)
+    }
+
+    // The tensor's id, which no other live tensor shares, even one viewing the same storage
+    pub(crate) fn tensor_id(&self) -> u64 {
+        self.id.get()
+    }
+
+    // `other`, on `self`'s device. A tensor on another device is copied over if the
+    // transfer policy allows it, otherwise `op` fails naming both devices.
+    fn colocated<'a>(&self, op: &'static str, other: &'a Tensor) -> MlResult<Cow<'a, Tensor>> {
+        let (left, right) = (self.device(), other.device());
+        if left == right {
+            return Ok(Cow::Borrowed(other));
+        }
+        match transfer_policy() {
+            TransferPolicy::Auto => Ok(Cow::Owned(other.to_device(left)?)),
+            TransferPolicy::Error => Err(MlError::TensorError(TensorError::DeviceMismatch {
+                op,
+                left,
+                right,
+            })),
+        }
+    }
+
+    // Runs `op` without leaving the device when the backend supports it and every operand
+    // is on the same kind of device as `self`. The result stays on the device.
+    fn on_device(&self, op: DeviceOp, others: &[&Tensor], shape: &[usize]) -> Option<Tensor> {
+        let device = self.backend.device();
+        if others.iter().any(|other| other.backend.device() != device) {
+            return None;
+        }
+
+        let mut inputs = vec![self.dense_storage().device(self.backend.as_ref())?.as_ref()];
+        for other in others {
+            inputs.push(
+                other
+                    .dense_storage()
+                    .device(other.backend.as_ref())?
+                    .as_ref(),
+            );
+        }
+
+        let result = self.backend.execute_on_device(op, &inputs)?;
+        Some(Tensor::from_storage(
+            Storage::from_device(result),
+            shape.to_vec(),
+            self.backend.clone(),
+        ))
+    }
+
+    // A tensor of `shape` holding `data`, computed on the host, on `self`'s backend so the
+    // result stays on the same device as its operands
+    fn with_data(&self, data: Vec<f32>, shape: &[usize]) -> Tensor {
+        Tensor::from_storage(
+            Storage::from_host(data),
+            shape.to_vec(),
+            self.backend.clone(),
+        )
+    }
+
+    /// Rounds every element to the nearest value representable in `precision`. The data
+    /// stays in f32.
+    pub fn round_to(&self, precision: Precision) -> Tensor {
+        let data = self.data().iter().map(|&x| precision.round(x)).collect();
+        self.with_data(data, &self.shape)
+    }
+
+    /// Matrix product. Inside an autocast scope the operands and result are rounded to the
+    /// autocast precision.
     pub fn matmul(&self, other: &Tensor) -> MlResult<Tensor> {
+        self.traced(Op::MatMul, &[other], || {
+            let other: &Tensor = &*self.colocated("matmul", other)?;
+            match autocast_precision() {
+                Some(precision) => {
+                    let result = self
+                        .round_to(precision)
+                        .matmul_f32(&other.round_to(precision))?;
+                    Ok(result.round_to(precision))
+                }
+                None => self.matmul_f32(other),
+            }
+        })
+    }
+
+    fn matmul_f32(&self, other: &Tensor) -> MlResult<Tensor> {
         if self.shape[1] != other.shape[0] {
             return Err(MlError::TensorError(
                 TensorError::MatrixMultiplicationError {
@@ -189,84 +403,96 @@ impl Tensor {
         let n = other.shape[1];
         let k = self.shape[1];
 
-        let result = self.backend.matmul(&self.data, &other.data, m, k, n);
-        Tensor::from_vec(result, &[m, n])
-    }
-
-    pub fn transpose(&self) -> MlResult<Tensor> {
-        if self.shape.len() != 2 {
-            return Err(MlError::TensorError(TensorError::InvalidShape {
-                expected: vec![2],
-                got: self.shape.clone(),
-            }));
+        if let Some(result) = self.on_device(DeviceOp::MatMul { m, n: k, k: n }, &[other], &[m, n])
+        {
+            return Ok(result);
         }
 
-        let (m, n) = (self.shape[0], self.shape[1]);
-        let mut result = vec![0.0; self.data.len()];
+        let result = self.backend.matmul(self.data(), other.data(), m, k, n);
+        Ok(self.with_data(result, &[m, n]))
+    }
 
-        for i in 0..m {
-            for j in 0..n {
-                result[j * m + i] = self.data[i * n + j];
+    pub fn add(&self, other: &Tensor) -> MlResult<Tensor> {
+        self.traced(Op::Add, &[other], || {
+            let other: &Tensor = &*self.colocated("add", other)?;
+            if self.shape != other.shape {
+                return self.broadcast_with(other, |a, b| a + b);
             }
-        }
 
-        Tensor::from_vec(result, &[n, m])
+            if let Some(result) = self.on_device(DeviceOp::Add, &[other], &self.shape) {
+                return Ok(result);
+            }
+
+            let result = self.backend.add(self.data(), other.data());
+            Ok(self.with_data(result, &self.shape))
+        })
     }
 
-    pub fn add(&self, other: &Tensor) -> MlResult<Tensor> {
-        if self.shape.len() == 2 && other.shape.len() == 1 && self.shape[1] == other.shape[0] {
-            let (_batch_size, features) = (self.shape[0], self.shape[1]);
-            let mut result = vec![0.0; self.data.len()];
+    pub fn sub(&self, other: &Tensor) -> MlResult<Tensor> {
+        self.traced(Op::Sub, &[other], || {
+            let other: &Tensor = &*self.colocated("sub", other)?;
+            if self.shape != other.shape {
+                return self.broadcast_with(other, |a, b| a - b);
+            }
 
-            for (i, chunk) in result.chunks_mut(features).enumerate() {
-                for (j, val) in chunk.iter_mut().enumerate() {
-                    *val = self.data[i * features + j] + other.data[j];
-                }
+            if let Some(result) = self.on_device(DeviceOp::Sub, &[other], &self.shape) {
+                return Ok(result);
             }
-            return Tensor::from_vec(result, &self.shape);
-        }
 
-        if self.shape != other.shape {
-            return Err(MlError::TensorError(TensorError::InvalidShape {
-                expected: self.shape.clone(),
-                got: other.shape.clone(),
-            }));
-        }
+            let result = self.backend.sub(self.data(), other.data());
+            Ok(self.with_data(result, &self.shape))
+        })
+    }
 
-        let result = self.backend.add(&self.data, &other.data);
-        Tensor::from_vec(result, &self.shape)
+    pub fn mul_scalar(&self, scalar: f32) -> MlResult<Tensor> {
+        self.traced(Op::MulScalar(scalar), &[], || {
+            let data: Vec<f32> = self.data().iter().map(|&x| x * scalar).collect();
+            Ok(self.with_data(data, &self.shape))
+        })
     }
 
-    pub fn sub(&self, other: &Tensor) -> MlResult<Tensor> {
-        if self.shape.len() == 2 && other.shape.len() == 1 && self.shape[1] == other.shape[0] {
-            let mut result = vec![0.0; self.data.len()];
-            let (batch_size, features) = (self.shape[0], self.shape[1]);
+    /// Sums along each of `dims`. With `keepdim` they stay as dimensions of size 1,
+    /// otherwise they are removed.
+    pub fn sum(&self, dims: &[usize], keepdim: bool) -> MlResult<Tensor> {
+        self.reduce_dims(ReduceOp::Sum, dims, keepdim)
+    }
 
-            for i in 0..batch_size {
-                for j in 0..features {
-                    result[i * features + j] = self.data[i * features + j] - other.data[j];
-                }
+    /// Means along each of `dims`, keeping them as dimensions of size 1 with `keepdim`.
+    pub fn mean_along(&self, dims: &[usize], keepdim: bool) -> MlResult<Tensor> {
+        self.reduce_dims(ReduceOp::Mean, dims, keepdim)
+    }
+
+    /// Reduces along each of `dims` with `op`, as [`Tensor::reduce`] does along one axis.
+    /// Each dimension may appear only once; an empty `dims` leaves the tensor as it is.
+    pub fn reduce_dims(&self, op: ReduceOp, dims: &[usize], keepdim: bool) -> MlResult<Tensor> {
+        let mut sorted = dims.to_vec();
+        sorted.sort_unstable();
+        for (i, &axis) in sorted.iter().enumerate() {
+            if axis >= self.shape.len() {
+                return Err(MlError::TensorError(TensorError::InvalidAxis {
+                    axis,
+                    shape: self.shape.clone(),
+                }));
+            }
+            if i > 0 && sorted[i - 1] == axis {
+                return Err(MlError::TensorError(TensorError::InvalidOperation {
+                    op: "reduce",
+                    reason: format!("Axis {} appears more than once", axis),
+                }));
             }
-            return Tensor::from_vec(result, &self.shape);
         }
 
-        if self.shape != other.shape {
-            return Err(MlError::TensorError(TensorError::InvalidShape {
-                expected: self.shape.clone(),
-                got: other.shape.clone(),
-            }));
+        // From the last axis back, so removing one doesn't move those still to reduce
+        let mut result = self.clone();
+        for &axis in sorted.iter().rev() {
+            result = result.reduce(op, axis, keepdim)?;
         }
-
-        let result = self.backend.sub(&self.data, &other.data);
-        Tensor::from_vec(result, &self.shape)
-    }
-
-    pub fn mul_scalar(&self, scalar: f32) -> MlResult<Tensor> {
-        let data: Vec<f32> = self.data.iter().map(|&x| x * scalar).collect();
-        Tensor::from_vec(data, &self.shape)
+        Ok(result)
     }
 
-    pub fn sum(&self, axis: usize) -> MlResult<Tensor> {
+    /// Reduces along `axis` with `op`. With `keepdim` the axis stays as a dimension of
+    /// size 1, otherwise it is removed.
+    pub fn reduce(&self, op: ReduceOp, axis: usize, keepdim: bool) -> MlResult<Tensor> {
         if axis >= self.shape.len() {
             return Err(MlError::TensorError(TensorError::InvalidAxis {
                 axis,
@@ -274,233 +500,349 @@ impl Tensor {
             }));
         }
 
-        if self.shape.len() != 2 {
-            return Err(MlError::TensorError(TensorError::InvalidOperation {
-                op: "sum",
-                reason: "Sum operation currently only supports 2D tensors".to_string(),
-            }));
+        let mut shape = self.shape.clone();
+        if keepdim {
+            shape[axis] = 1;
+        } else {
+            shape.remove(axis);
         }
 
-        let (rows, cols) = (self.shape[0], self.shape[1]);
-        let _total_sum = self.backend.sum(&self.data);
-
-        match axis {
-            0 => {
-                let mut result = vec![0.0; cols];
-                for j in 0..cols {
-                    let mut sum = 0.0;
-                    for i in 0..rows {
-                        sum += self.data[i * cols + j];
-                    }
-                    result[j] = sum;
+        let (outer, len, inner) = split_axis(&self.shape, axis);
+        let device_op = DeviceOp::Reduce {
+            op,
+            outer,
+            axis: len,
+            inner,
+        };
+        if let Some(result) = self.on_device(device_op, &[], &shape) {
+            return Ok(result);
+        }
+
+        let result = self.backend.reduce(op, self.data(), &self.shape, axis);
+        Ok(self.with_data(result, &shape))
+    }
+
+    /// 2D convolution (cross-correlation) of this `[batch, channels, height, width]` tensor
+    /// with a `[out_channels, channels, kh, kw]` weight, computed by the tensor's backend.
+    pub fn conv2d(
+        &self,
+        weight: &Tensor,
+        stride: (usize, usize),
+        padding: (usize, usize),
+    ) -> MlResult<Tensor> {
+        self.conv2d_grouped(weight, stride, padding, 1)
+    }
+
+    /// [`Tensor::conv2d`] with the channels split into `groups`: each group of
+    /// `channels / groups` input channels is convolved with its own `out_channels / groups`
+    /// filters, so the weight is `[out_channels, channels / groups, kh, kw]`. With `groups`
+    /// equal to `channels` this is a depthwise convolution, which runs a direct kernel
+    /// instead of unfolding the input.
+    pub fn conv2d_grouped(
+        &self,
+        weight: &Tensor,
+        stride: (usize, usize),
+        padding: (usize, usize),
+        groups: usize,
+    ) -> MlResult<Tensor> {
+        let op = Op::Conv2d {
+            stride,
+            padding,
+            groups,
+        };
+        self.traced(op, &[weight], || {
+            let weight: &Tensor = &*self.colocated("conv2d", weight)?;
+            let ([n, c, h, w], [oc, ic, kh, kw]) = match (self.shape.as_slice(), weight.shape()) {
+                (&[n, c, h, w], &[oc, ic, kh, kw]) => ([n, c, h, w], [oc, ic, kh, kw]),
+                (input, weight) => {
+                    return Err(MlError::TensorError(TensorError::InvalidOperation {
+                        op: "conv2d",
+                        reason: format!(
+                            "expected 4D input and weight, got {:?} and {:?}",
+                            input, weight
+                        ),
+                    }))
                 }
-                Tensor::from_vec(result, &[1, cols])
+            };
+            if groups == 0 || !oc.is_multiple_of(groups) || ic * groups != c {
+                return Err(MlError::TensorError(TensorError::InvalidOperation {
+                    op: "conv2d",
+                    reason: format!(
+                        "{} groups don't divide input {:?} and weight {:?}",
+                        groups, self.shape, weight.shape
+                    ),
+                }));
             }
-            1 => {
-                let mut result = vec![0.0; rows];
-                for (i, chunk) in self.data.chunks(cols).enumerate() {
-                    result[i] = chunk.iter().sum();
+
+            // The geometry of one group, which for a single group is the whole convolution
+            let group =
+                Conv2dShape::new([n, ic, h, w], [oc / groups, ic, kh, kw], stride, padding)?;
+            let [_, _, out_h, out_w] = group.output();
+            let shape = [n, oc, out_h, out_w];
+
+            if groups == 1 {
+                if let Some(result) = self.on_device(DeviceOp::Conv2d(group), &[weight], &shape) {
+                    return Ok(result);
                 }
-                Tensor::from_vec(result, &[rows, 1])
+                let result = self.backend.conv2d(self.data(), weight.data(), &group);
+                return Ok(self.with_data(result, &shape));
             }
-            _ => Err(MlError::TensorError(TensorError::InvalidAxis {
-                axis,
-                shape: self.shape.clone(),
-            })),
-        }
+
+            if ic == 1 {
+                let result = depthwise_conv2d(self.data(), weight.data(), &group, groups);
+                return Ok(self.with_data(result, &shape));
+            }
+
+            let outputs = (0..groups)
+                .map(|g| {
+                    let input = self.narrow(1, g * ic, ic)?;
+                    let weight = weight.narrow(0, g * (oc / groups), oc / groups)?;
+                    let result = self.backend.conv2d(input.data(), weight.data(), &group);
+                    Ok(self.with_data(result, &group.output()))
+                })
+                .collect::<MlResult<Vec<_>>>()?;
+            Tensor::cat(&outputs.iter().collect::<Vec<_>>(), 1)
+        })
     }
 
     pub fn reshape(&self, new_shape: &[usize]) -> MlResult<Tensor> {
-        let new_size: usize = new_shape.iter().product();
-        let current_size: usize = self.data.len();
-
-        if new_size != current_size {
-            return Err(MlError::TensorError(TensorError::InvalidShape {
-                expected: new_shape.to_vec(),
-                got: vec![current_size],
-            }));
-        }
+        self.traced(Op::Reshape(new_shape.to_vec()), &[], || {
+            let new_size: usize = new_shape.iter().product();
+            let current_size = self.len();
+
+            if new_size != current_size {
+                return Err(MlError::TensorError(TensorError::InvalidShape {
+                    expected: new_shape.to_vec(),
+                    got: vec![current_size],
+                }));
+            }
 
-        Ok(Tensor {
-            data: self.data.clone(),
-            shape: new_shape.to_vec(),
-            backend: self.backend.clone(),
+            let strides = contiguous_strides(new_shape);
+            if self.is_contiguous() {
+                return Ok(self.view(new_shape.to_vec(), strides, self.offset));
+            }
+            // Only a dense layout can be read with other dimensions
+            Ok(self.contiguous().view(new_shape.to_vec(), strides, 0))
         })
     }
 
     pub fn clip(&self, min: f32, max: f32) -> MlResult<Tensor> {
-        let data: Vec<f32> = self.data.iter().map(|&x| x.clamp(min, max)).collect();
+        self.traced(Op::Clip { min, max }, &[], || {
+            let data: Vec<f32> = self.data().iter().map(|&x| x.clamp(min, max)).collect();
 
-        Tensor::from_vec(data, &self.shape)
+            Ok(self.with_data(data, &self.shape))
+        })
     }
 
     pub fn log(&self) -> MlResult<Tensor> {
-        let data: Vec<f32> = self.data.iter().map(|&x| x.ln()).collect();
+        self.traced(Op::Log, &[], || {
+            let data: Vec<f32> = self.data().iter().map(|&x| x.ln()).collect();
 
-        Tensor::from_vec(data, &self.shape)
+            Ok(self.with_data(data, &self.shape))
+        })
     }
 
     pub fn neg(&self) -> MlResult<Tensor> {
-        let data: Vec<f32> = self.data.iter().map(|&x| -x).collect();
+        self.traced(Op::Neg, &[], || {
+            let data: Vec<f32> = self.data().iter().map(|&x| -x).collect();
 
-        Tensor::from_vec(data, &self.shape)
+            Ok(self.with_data(data, &self.shape))
+        })
     }
 
     pub fn mul(&self, other: &Tensor) -> MlResult<Tensor> {
-        if self.shape != other.shape {
-            return Err(MlError::TensorError(TensorError::InvalidShape {
-                expected: self.shape.clone(),
-                got: other.shape.clone(),
-            }));
-        }
+        self.traced(Op::Mul, &[other], || {
+            let other: &Tensor = &*self.colocated("mul", other)?;
+            if self.shape != other.shape {
+                return self.broadcast_with(other, |a, b| a * b);
+            }
 
-        let result = self.backend.multiply(&self.data, &other.data);
-        Tensor::from_vec(result, &self.shape)
+            if let Some(result) = self.on_device(DeviceOp::Mul, &[other], &self.shape) {
+                return Ok(result);
+            }
+
+            let result = self.backend.multiply(self.data(), other.data());
+            Ok(self.with_data(result, &self.shape))
+        })
     }
 
     pub fn add_scalar(&self, scalar: f32) -> MlResult<Tensor> {
-        let data: Vec<f32> = self.data.iter().map(|&x| x + scalar).collect();
+        self.traced(Op::AddScalar(scalar), &[], || {
+            let data: Vec<f32> = self.data().iter().map(|&x| x + scalar).collect();
 
-        Tensor::from_vec(data, &self.shape)
+            Ok(self.with_data(data, &self.shape))
+        })
     }
 
     pub fn mean(&self) -> MlResult<f32> {
-        if self.data.is_empty() {
+        if self.len() == 0 {
             return Err(MlError::TensorError(TensorError::InvalidOperation {
                 op: "mean",
                 reason: "Cannot compute mean of empty tensor".to_string(),
             }));
         }
 
-        Ok(self.backend.mean(&self.data))
+        if let Some(total) = self.on_device(DeviceOp::Sum, &[], &[1]) {
+            return Ok(total.data()[0] / self.len() as f32);
+        }
+
+        Ok(self.backend.mean(self.data()))
     }
 
     pub fn exp(&self) -> MlResult<Tensor> {
-        let result = self.backend.exp(&self.data);
-        Tensor::from_vec(result, &self.shape)
+        self.traced(Op::Exp, &[], || {
+            if let Some(result) = self.on_device(DeviceOp::Exp, &[], &self.shape) {
+                return Ok(result);
+            }
+
+            let result = self.backend.exp(self.data());
+            Ok(self.with_data(result, &self.shape))
+        })
     }
 
     pub fn div(&self, other: &Tensor) -> MlResult<Tensor> {
-        if self.shape != other.shape {
-            return Err(MlError::TensorError(TensorError::InvalidShape {
-                expected: self.shape.clone(),
-                got: other.shape.clone(),
-            }));
-        }
+        self.traced(Op::Div, &[other], || {
+            let other: &Tensor = &*self.colocated("div", other)?;
+            if self.shape != other.shape {
+                return self.broadcast_with(other, |a, b| a / b);
+            }
+
+            if let Some(result) = self.on_device(DeviceOp::Div, &[other], &self.shape) {
+                return Ok(result);
+            }
 
-        let result = self.backend.div(&self.data, &other.data);
-        Tensor::from_vec(result, &self.shape)
+            let result = self.backend.div(self.data(), other.data());
+            Ok(self.with_data(result, &self.shape))
+        })
     }
 
     pub fn pow(&self, power: f32) -> MlResult<Tensor> {
-        let result = self.backend.pow(&self.data, power);
-        Tensor::from_vec(result, &self.shape)
+        self.traced(Op::Pow(power), &[], || {
+            if let Some(result) = self.on_device(DeviceOp::Pow(power), &[], &self.shape) {
+                return Ok(result);
+            }
+
+            let result = self.backend.pow(self.data(), power);
+            Ok(self.with_data(result, &self.shape))
+        })
     }
 
     pub fn sqrt(&self) -> MlResult<Tensor> {
-        let result = self.backend.sqrt(&self.data);
-        Tensor::from_vec(result, &self.shape)
+        self.traced(Op::Sqrt, &[], || {
+            if let Some(result) = self.on_device(DeviceOp::Sqrt, &[], &self.shape) {
+                return Ok(result);
+            }
+
+            let result = self.backend.sqrt(self.data());
+            Ok(self.with_data(result, &self.shape))
+        })
     }
 
     pub fn sum_all(&self) -> MlResult<f32> {
-        Ok(self.backend.sum(&self.data))
+        if let Some(total) = self.on_device(DeviceOp::Sum, &[], &[1]) {
+            return Ok(total.data()[0]);
+        }
+
+        Ok(self.backend.sum(self.data()))
     }
 
+    /// Maximum along `axis`, keeping it as a dimension of size 1.
     pub fn max_along_axis(&self, axis: usize) -> MlResult<Tensor> {
-        if axis >= self.shape.len() {
-            return Err(MlError::TensorError(TensorError::InvalidAxis {
-                axis,
-                shape: self.shape.clone(),
-            }));
-        }
+        self.reduce(ReduceOp::Max, axis, true)
+    }
 
-        if self.shape.len() != 2 {
-            return Err(MlError::TensorError(TensorError::InvalidOperation {
-                op: "max_along_axis",
-                reason: "Operation currently only supports 2D tensors".to_string(),
-            }));
-        }
+    /// Minimum along `axis`, keeping it as a dimension of size 1.
+    pub fn min_along_axis(&self, axis: usize) -> MlResult<Tensor> {
+        self.reduce(ReduceOp::Min, axis, true)
+    }
 
-        let (rows, cols) = (self.shape[0], self.shape[1]);
-        match axis {
-            0 => {
-                let mut result = vec![f32::NEG_INFINITY; cols];
-                for (j, max) in result.iter_mut().enumerate().take(cols) {
-                    for i in 0..rows {
-                        *max = max.max(self.data[i * cols + j]);
-                    }
-                }
-                Tensor::from_vec(result, &[1, cols])
-            }
-            1 => {
-                let mut result = vec![f32::NEG_INFINITY; rows];
-                for (i, max) in result.iter_mut().enumerate().take(rows) {
-                    for j in 0..cols {
-                        *max = max.max(self.data[i * cols + j]);
-                    }
+    /// `log(sum(exp(x)))` along `dim`, computed as `max + log(sum(exp(x - max)))` so large
+    /// values don't overflow and very negative ones don't all underflow to `log(0)`. With
+    /// `keepdim` the axis stays as a dimension of size 1.
+    pub fn logsumexp(&self, dim: usize, keepdim: bool) -> MlResult<Tensor> {
+        let max = self.reduce(ReduceOp::Max, dim, true)?;
+        let (outer, len, inner) = split_axis(&self.shape, dim);
+        let data = self.data();
+        let result = (0..outer * inner)
+            .zip(max.data())
+            .map(|(i, &max)| {
+                // All -inf, or an inf that would give inf - inf
+                if max.is_infinite() {
+                    return max;
                 }
-                Tensor::from_vec(result, &[rows, 1])
-            }
-            _ => Err(MlError::TensorError(TensorError::InvalidAxis {
-                axis,
-                shape: self.shape.clone(),
-            })),
+                let (o, j) = (i / inner, i % inner);
+                let sum: f32 = (0..len)
+                    .map(|k| (data[(o * len + k) * inner + j] - max).exp())
+                    .sum();
+                max + sum.ln()
+            })
+            .collect();
+
+        let mut shape = self.shape.clone();
+        if keepdim {
+            shape[dim] = 1;
+        } else {
+            shape.remove(dim);
         }
+        Ok(self.with_data(result, &shape))
     }
+
+    /// `log(1 + exp(x))` of every element, a smooth ReLU, computed as
+    /// `max(x, 0) + log(1 + exp(-|x|))` so it neither overflows for large `x` nor loses the
+    /// small values of very negative ones.
+    pub fn softplus(&self) -> MlResult<Tensor> {
+        let data = self
+            .data()
+            .iter()
+            .map(|&x| x.max(0.0) + (-x.abs()).exp().ln_1p())
+            .collect();
+        Ok(self.with_data(data, &self.shape))
+    }
+}
+
+pub(crate) fn backend_for(device_type: DeviceType) -> MlResult<Arc<dyn Backend>> {
+    let backend = create_backend(device_type)?;
+    check_deterministic(backend.as_ref())?;
+    Ok(backend)
+}
+
+fn create_backend(device_type: DeviceType) -> MlResult<Arc<dyn Backend>> {
+    Ok(match device_type {
+        DeviceType::Cpu => Arc::new(CpuBackend::new()?),
+        #[cfg(feature = "cuda")]
+        DeviceType::Cuda(index) => CudaBackend::shared(index)?,
+        #[cfg(feature = "mps")]
+        DeviceType::Mps => MpsBackend::shared()?,
+        #[cfg(feature = "vulkan")]
+        DeviceType::Vulkan(index) => Arc::new(VulkanBackend::with_adapter(index)?),
+        #[cfg(feature = "wgpu")]
+        DeviceType::Wgpu => WgpuBackend::shared()?,
+        #[cfg(feature = "opencl")]
+        DeviceType::OpenCl => OpenClBackend::shared()?,
+        DeviceType::Custom(name) => registered_backend(name)
+            .ok_or_else(|| format!("No backend registered as {:?}", name))?,
+    })
 }
 
 // Implement serialization for Tensor
 impl Serialize for Tensor {
     fn serialize(&self) -> Vec<u8> {
-        let mut bytes = Vec::new();
-
-        // Serialize shape
-        let shape_len = self.shape().len() as u32;
-        bytes.extend_from_slice(&shape_len.to_le_bytes());
-
-        for &dim in self.shape() {
-            bytes.extend_from_slice(&(dim as u32).to_le_bytes());
-        }
-
-        // Serialize data
-        for &value in self.data() {
-            bytes.extend_from_slice(&value.to_le_bytes());
-        }
+        format::encode_tensor(self.shape(), self.data())
+    }
 
-        bytes
+    fn serialize_into<W: std::io::Write>(&self, writer: W) -> MlResult<()> {
+        format::write_tensor(writer, self.shape(), self.try_data()?)
     }
 }
 
 impl Deserialize for Tensor {
     fn deserialize(bytes: &[u8]) -> MlResult<Self> {
-        let mut cursor = 0;
-
-        // Read shape length
-        if bytes.len() < 4 {
-            return Err("Invalid tensor data".into());
-        }
-        let shape_len = u32::from_le_bytes(bytes[0..4].try_into().unwrap()) as usize;
-        cursor += 4;
-
-        // Read shape
-        let mut shape = Vec::with_capacity(shape_len);
-        for _ in 0..shape_len {
-            if cursor + 4 > bytes.len() {
-                return Err("Invalid tensor data".into());
-            }
-            let dim = u32::from_le_bytes(bytes[cursor..cursor + 4].try_into().unwrap()) as usize;
-            shape.push(dim);
-            cursor += 4;
-        }
-
-        // Read data
-        let mut data = Vec::new();
-        while cursor + 4 <= bytes.len() {
-            let value = f32::from_le_bytes(bytes[cursor..cursor + 4].try_into().unwrap());
-            data.push(value);
-            cursor += 4;
-        }
+        let (shape, data) = format::decode_tensor(bytes)?;
+        Tensor::from_vec(data, &shape)
+    }
 
+    fn deserialize_from<R: std::io::Read>(reader: R) -> MlResult<Self> {
+        let (shape, data) = format::read_tensor(reader)?;
         Tensor::from_vec(data, &shape)
     }
 }
@@ -508,6 +850,7 @@ impl Deserialize for Tensor {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::backend::{register_backend, unregister_backend, HostBackend};
 
     #[test]
     fn test_tensor_creation() -> MlResult<()> {
@@ -530,7 +873,7 @@ mod tests {
     #[test]
     fn test_transpose() -> MlResult<()> {
         let a = Tensor::new(vec![vec![1.0, 2.0], vec![3.0, 4.0]])?;
-        let b = a.transpose()?;
+        let b = a.transpose(0, 1)?;
         assert_eq!(b.shape(), &[2, 2]);
         assert_eq!(b.data(), &[1.0, 3.0, 2.0, 4.0]);
         Ok(())
@@ -569,17 +912,131 @@ mod tests {
         let a = Tensor::new(vec![vec![1.0, 2.0], vec![3.0, 4.0]])?;
 
         // Sum along axis 0 (columns)
-        let sum_0 = a.sum(0)?;
+        let sum_0 = a.sum(&[0], true)?;
         assert_eq!(sum_0.shape(), &[1, 2]);
         assert_eq!(sum_0.data(), &[4.0, 6.0]);
 
         // Sum along axis 1 (rows)
-        let sum_1 = a.sum(1)?;
+        let sum_1 = a.sum(&[1], true)?;
         assert_eq!(sum_1.shape(), &[2, 1]);
         assert_eq!(sum_1.data(), &[3.0, 7.0]);
         Ok(())
     }
 
+    #[test]
+    fn test_reduce_along_axis() -> MlResult<()> {
+        let a = Tensor::from_vec((0..12).map(|x| x as f32).collect(), &[2, 3, 2])?;
+
+        let sum = a.reduce(ReduceOp::Sum, 1, false)?;
+        assert_eq!(sum.shape(), &[2, 2]);
+        assert_eq!(sum.data(), &[6.0, 9.0, 24.0, 27.0]);
+
+        let max = a.reduce(ReduceOp::Max, 2, true)?;
+        assert_eq!(max.shape(), &[2, 3, 1]);
+        assert_eq!(max.data(), &[1.0, 3.0, 5.0, 7.0, 9.0, 11.0]);
+
+        let min = a.reduce(ReduceOp::Min, 0, false)?;
+        assert_eq!(min.data(), &[0.0, 1.0, 2.0, 3.0, 4.0, 5.0]);
+        assert_eq!(
+            a.reduce(ReduceOp::Mean, 1, false)?.data(),
+            &[2.0, 3.0, 8.0, 9.0]
+        );
+
+        assert!(a.reduce(ReduceOp::Sum, 3, false).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_reduce_dims() -> MlResult<()> {
+        let a = Tensor::from_vec((0..24).map(|x| x as f32).collect(), &[2, 3, 4])?;
+
+        let sum = a.sum(&[2, 0], false)?;
+        assert_eq!(sum.shape(), &[3]);
+        assert_eq!(sum.data(), &[60.0, 92.0, 124.0]);
+        assert_eq!(a.sum(&[0, 2], true)?.shape(), &[1, 3, 1]);
+        assert_eq!(a.sum(&[], false)?.shape(), &[2, 3, 4]);
+
+        let mean = a.mean_along(&[1, 2], false)?;
+        assert_eq!(mean.data(), &[5.5, 17.5]);
+        assert_eq!(a.min_along_axis(1)?.shape(), &[2, 1, 4]);
+        assert_eq!(a.min_along_axis(1)?.data()[4..], [12.0, 13.0, 14.0, 15.0]);
+
+        assert!(a.sum(&[1, 1], false).is_err());
+        assert!(a.mean_along(&[3], true).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_logsumexp_and_softplus() -> MlResult<()> {
+        let a = Tensor::from_vec(vec![0.0, 0.0, 1000.0, 1000.0, -1000.0, -1000.0], &[3, 2])?;
+        let lse = a.logsumexp(1, false)?;
+        assert_eq!(lse.shape(), &[3]);
+        let ln2 = 2f32.ln();
+        for (value, expected) in lse.data().iter().zip([ln2, 1000.0 + ln2, -1000.0 + ln2]) {
+            assert!((value - expected).abs() < 1e-3, "{} != {}", value, expected);
+        }
+        assert_eq!(a.logsumexp(0, true)?.shape(), &[1, 2]);
+
+        let masked = Tensor::from_vec(vec![f32::NEG_INFINITY, f32::NEG_INFINITY], &[1, 2])?;
+        assert_eq!(masked.logsumexp(1, false)?.data(), &[f32::NEG_INFINITY]);
+        assert!(a.logsumexp(2, false).is_err());
+
+        let x = Tensor::from_vec(vec![-100.0, 0.0, 100.0], &[3])?;
+        let softplus = x.softplus()?;
+        assert!(softplus.data()[0] > 0.0 && softplus.data()[0] < 1e-40);
+        assert!((softplus.data()[1] - ln2).abs() < 1e-6);
+        assert_eq!(softplus.data()[2], 100.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_conv2d() -> MlResult<()> {
+        let input = Tensor::from_vec((1..=9).map(|x| x as f32).collect(), &[1, 1, 3, 3])?;
+        let weight = Tensor::from_vec(vec![1.0, 0.0, 0.0, 1.0, 1.0, 1.0, 1.0, 1.0], &[2, 1, 2, 2])?;
+
+        let output = input.conv2d(&weight, (1, 1), (0, 0))?;
+        assert_eq!(output.shape(), &[1, 2, 2, 2]);
+        assert_eq!(
+            output.data(),
+            &[6.0, 8.0, 12.0, 14.0, 12.0, 16.0, 24.0, 28.0]
+        );
+
+        let diagonal = Tensor::from_vec(vec![1.0, 0.0, 0.0, 1.0], &[1, 1, 2, 2])?;
+        let strided = input.conv2d(&diagonal, (2, 2), (1, 1))?;
+        assert_eq!(strided.shape(), &[1, 1, 2, 2]);
+        assert_eq!(strided.data(), &[1.0, 3.0, 7.0, 14.0]);
+
+        assert!(input.conv2d(&diagonal, (0, 1), (0, 0)).is_err());
+        assert!(input
+            .conv2d(&diagonal.reshape(&[2, 2])?, (1, 1), (0, 0))
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_grouped_conv2d_keeps_device() -> MlResult<()> {
+        let device = DeviceType::Custom("test-grouped-conv");
+        register_backend(
+            "test-grouped-conv",
+            Arc::new(HostBackend::new("test-grouped-conv")),
+        )?;
+
+        // Two groups of two channels are joined after convolving, and four groups of one
+        // run the depthwise kernel
+        let input = Tensor::from_vec((0..16).map(|x| x as f32).collect(), &[1, 4, 2, 2])?;
+        let input = input.to_device(device)?;
+        let weight = Tensor::from_vec(vec![1.0; 4], &[2, 2, 1, 1])?.to_device(device)?;
+        let grouped = input.conv2d_grouped(&weight, (1, 1), (0, 0), 2)?;
+        assert_eq!(grouped.device(), device);
+        assert_eq!(grouped.data()[..4], [4.0, 6.0, 8.0, 10.0]);
+        let depthwise = weight.reshape(&[4, 1, 1, 1])?;
+        let depthwise = input.conv2d_grouped(&depthwise, (1, 1), (0, 0), 4)?;
+        assert_eq!(depthwise.device(), device);
+
+        unregister_backend("test-grouped-conv");
+        Ok(())
+    }
+
     #[test]
     fn test_reshape() -> MlResult<()> {
         // Create a 2x3 tensor
@@ -624,8 +1081,8 @@ mod tests {
         let a = Tensor::new(vec![vec![1.0, 2.0]])?;
         let b = a.exp()?;
         assert_eq!(b.shape(), &[1, 2]);
-        assert!((b.data()[0] - 2.718281828).abs() < 1e-6);
-        assert!((b.data()[1] - 7.389056099).abs() < 1e-6);
+        assert!((b.data()[0] - std::f32::consts::E).abs() < 1e-6);
+        assert!((b.data()[1] - 7.389_056).abs() < 1e-6);
         Ok(())
     }
 
@@ -653,4 +1110,27 @@ mod tests {
         assert_eq!(b.data(), &[2.0, 3.0]);
         Ok(())
     }
+
+    #[test]
+    fn test_to_device() -> MlResult<()> {
+        let a = Tensor::from_vec(vec![1.0, 2.0, 3.0], &[3])?;
+        let b = a.to_device(DeviceType::Cpu)?;
+        assert_eq!(b.device(), DeviceType::Cpu);
+        assert_eq!(b.shape(), a.shape());
+        assert_eq!(b.data(), a.data());
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_cpu() -> MlResult<()> {
+        let a = Tensor::new(vec![vec![1.0, 2.0], vec![3.0, 4.0]])?;
+        let b = a.add(&a)?.mul(&a)?;
+
+        let host = b.to_cpu()?;
+        assert!(!host.is_on_device());
+        assert_eq!(host.shape(), &[2, 2]);
+        assert_eq!(host.data(), &[2.0, 8.0, 18.0, 32.0]);
+        assert_eq!(host.sum_all()?, 60.0);
+        Ok(())
+    }
 }
@@ -0,0 +1,87 @@
+//! Conversions between [`Tensor`] and `ndarray::ArrayD<f32>`, behind the `ndarray` feature.
+//!
+//! Both sides keep their data as one row-major buffer, so moving a tensor that is on the host
+//! into an array, or a standard-layout array into a tensor, hands over the buffer without
+//! copying it. Arrays in any other layout, such as transposed views made owned, are copied
+//! in logical order.
+
+use ndarray::{ArrayD, IxDyn};
+
+use super::Tensor;
+use crate::{MlError, MlResult};
+
+impl TryFrom<ArrayD<f32>> for Tensor {
+    type Error = MlError;
+
+    /// Creates a tensor on the default device, like [`Tensor::from_vec`].
+    fn try_from(array: ArrayD<f32>) -> MlResult<Self> {
+        let shape = array.shape().to_vec();
+        let data = if array.is_standard_layout() {
+            match array.into_raw_vec_and_offset() {
+                (data, None | Some(0)) => data,
+                // A slice of a larger buffer, so only part of it belongs to the array
+                (data, Some(offset)) => {
+                    let len = shape.iter().product::<usize>();
+                    data[offset..offset + len].to_vec()
+                }
+            }
+        } else {
+            array.iter().copied().collect()
+        };
+        Tensor::from_vec(data, &shape)
+    }
+}
+
+impl TryFrom<&ArrayD<f32>> for Tensor {
+    type Error = MlError;
+
+    fn try_from(array: &ArrayD<f32>) -> MlResult<Self> {
+        Tensor::from_vec(array.iter().copied().collect(), array.shape())
+    }
+}
+
+impl TryFrom<Tensor> for ArrayD<f32> {
+    type Error = MlError;
+
+    fn try_from(tensor: Tensor) -> MlResult<Self> {
+        let shape = tensor.shape().to_vec();
+        to_array(&shape, tensor.into_data())
+    }
+}
+
+impl TryFrom<&Tensor> for ArrayD<f32> {
+    type Error = MlError;
+
+    fn try_from(tensor: &Tensor) -> MlResult<Self> {
+        to_array(tensor.shape(), tensor.data().to_vec())
+    }
+}
+
+fn to_array(shape: &[usize], data: Vec<f32>) -> MlResult<ArrayD<f32>> {
+    ArrayD::from_shape_vec(IxDyn(shape), data)
+        .map_err(|e| format!("Failed to convert a tensor of shape {:?}: {}", shape, e).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ndarray_conversions() -> MlResult<()> {
+        let data: Vec<f32> = (0..6).map(|i| i as f32).collect();
+        let array = ArrayD::from_shape_vec(IxDyn(&[2, 3]), data.clone()).unwrap();
+
+        let tensor = Tensor::try_from(&array)?;
+        assert_eq!((tensor.shape(), tensor.data()), (&[2, 3][..], &data[..]));
+        let tensor = Tensor::try_from(array.clone())?;
+        assert_eq!(tensor.data(), data);
+        assert_eq!(ArrayD::try_from(&tensor)?, array);
+        assert_eq!(ArrayD::try_from(tensor)?, array);
+
+        // A transposed array is copied into row-major order
+        let transposed = Tensor::try_from(array.reversed_axes())?;
+        assert_eq!(transposed.shape(), [3, 2]);
+        assert_eq!(transposed.data(), [0.0, 3.0, 1.0, 4.0, 2.0, 5.0]);
+        Ok(())
+    }
+}
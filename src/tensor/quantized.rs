@@ -0,0 +1,389 @@
+use crate::serialize::format::{self, QuantizedRecord};
+use crate::serialize::{Deserialize, Serialize};
+use crate::tensor::{Tensor, TensorError};
+use crate::MlResult;
+
+/// The integer type of a [`QuantizedTensor`]'s values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuantDtype {
+    /// Values in `-128..=127`.
+    Int8,
+    /// Values in `-8..=7`, stored two to a byte when serialized.
+    Int4,
+}
+
+impl QuantDtype {
+    pub fn min(self) -> i32 {
+        match self {
+            QuantDtype::Int8 => -128,
+            QuantDtype::Int4 => -8,
+        }
+    }
+
+    pub fn max(self) -> i32 {
+        match self {
+            QuantDtype::Int8 => 127,
+            QuantDtype::Int4 => 7,
+        }
+    }
+}
+
+/// How a [`QuantizedTensor`]'s values map back to reals: `real = scale * (q - zero_point)`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum QuantParams {
+    /// One scale and zero point for the whole tensor.
+    PerTensor { scale: f32, zero_point: i32 },
+    /// A scale and zero point for each index along `axis`, usually the output channels of a
+    /// weight.
+    PerChannel {
+        axis: usize,
+        scales: Vec<f32>,
+        zero_points: Vec<i32>,
+    },
+}
+
+/// A tensor of int8 or int4 values with the affine parameters that dequantize them.
+///
+/// Values are kept one per `i8` in memory whatever the dtype.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuantizedTensor {
+    dtype: QuantDtype,
+    shape: Vec<usize>,
+    values: Vec<i8>,
+    params: QuantParams,
+}
+
+impl QuantizedTensor {
+    /// Builds a quantized tensor from its parts, checking that the values fit `dtype` and the
+    /// shape, and that the parameters cover the tensor.
+    pub fn from_parts(
+        dtype: QuantDtype,
+        shape: &[usize],
+        values: Vec<i8>,
+        params: QuantParams,
+    ) -> MlResult<Self> {
+        let expected: usize = shape.iter().product();
+        if values.len() != expected {
+            return Err(TensorError::InvalidDataLength {
+                expected,
+                got: values.len(),
+            }
+            .into());
+        }
+        let in_range = |q: i32| (dtype.min()..=dtype.max()).contains(&q);
+        if let Some(&q) = values.iter().find(|&&q| !in_range(q as i32)) {
+            return Err(invalid(format!(
+                "value {} is out of range for {:?}",
+                q, dtype
+            )));
+        }
+
+        let (scales, zero_points) = match &params {
+            QuantParams::PerTensor { scale, zero_point } => (
+                std::slice::from_ref(scale),
+                std::slice::from_ref(zero_point),
+            ),
+            QuantParams::PerChannel {
+                axis,
+                scales,
+                zero_points,
+            } => {
+                let channels = *shape.get(*axis).ok_or_else(|| TensorError::InvalidAxis {
+                    axis: *axis,
+                    shape: shape.to_vec(),
+                })?;
+                if scales.len() != channels || zero_points.len() != channels {
+                    return Err(invalid(format!(
+                        "{} channels need as many scales and zero points, got {} and {}",
+                        channels,
+                        scales.len(),
+                        zero_points.len()
+                    )));
+                }
+                (scales.as_slice(), zero_points.as_slice())
+            }
+        };
+        if let Some(scale) = scales.iter().find(|s| !s.is_finite() || **s <= 0.0) {
+            return Err(invalid(format!("scale {} is not positive", scale)));
+        }
+        if let Some(zero_point) = zero_points.iter().find(|&&z| !in_range(z)) {
+            return Err(invalid(format!(
+                "zero point {} is out of range for {:?}",
+                zero_point, dtype
+            )));
+        }
+
+        Ok(Self {
+            dtype,
+            shape: shape.to_vec(),
+            values,
+            params,
+        })
+    }
+
+    /// Quantizes `tensor` with one scale and zero point chosen to cover its range.
+    pub fn quantize(tensor: &Tensor, dtype: QuantDtype) -> MlResult<Self> {
+        let data = tensor.data();
+        let (scale, zero_point) = affine_params(data.iter().copied(), dtype);
+        let values = data
+            .iter()
+            .map(|&x| quantize_value(x, scale, zero_point, dtype))
+            .collect();
+        Self::from_parts(
+            dtype,
+            tensor.shape(),
+            values,
+            QuantParams::PerTensor { scale, zero_point },
+        )
+    }
+
+    /// Quantizes `tensor` with a scale and zero point for each index along `axis`, so a
+    /// channel of small weights keeps its precision next to one of large weights.
+    pub fn quantize_per_channel(tensor: &Tensor, dtype: QuantDtype, axis: usize) -> MlResult<Self> {
+        let shape = tensor.shape();
+        let channels = *shape.get(axis).ok_or_else(|| TensorError::InvalidAxis {
+            axis,
+            shape: shape.to_vec(),
+        })?;
+        let inner: usize = shape[axis + 1..].iter().product();
+        let channel_of = |i: usize| (i / inner.max(1)) % channels;
+
+        let data = tensor.data();
+        let (scales, zero_points): (Vec<_>, Vec<_>) = (0..channels)
+            .map(|c| {
+                let values = data.iter().enumerate().filter(|(i, _)| channel_of(*i) == c);
+                affine_params(values.map(|(_, &x)| x), dtype)
+            })
+            .unzip();
+        let values = data
+            .iter()
+            .enumerate()
+            .map(|(i, &x)| {
+                let c = channel_of(i);
+                quantize_value(x, scales[c], zero_points[c], dtype)
+            })
+            .collect();
+
+        Self::from_parts(
+            dtype,
+            shape,
+            values,
+            QuantParams::PerChannel {
+                axis,
+                scales,
+                zero_points,
+            },
+        )
+    }
+
+    /// Maps the values back to f32.
+    pub fn dequantize(&self) -> MlResult<Tensor> {
+        let data = match &self.params {
+            QuantParams::PerTensor { scale, zero_point } => self
+                .values
+                .iter()
+                .map(|&q| scale * (q as i32 - zero_point) as f32)
+                .collect(),
+            QuantParams::PerChannel {
+                axis,
+                scales,
+                zero_points,
+            } => {
+                let inner: usize = self.shape[axis + 1..].iter().product();
+                let channels = self.shape[*axis];
+                self.values
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &q)| {
+                        let c = (i / inner.max(1)) % channels;
+                        scales[c] * (q as i32 - zero_points[c]) as f32
+                    })
+                    .collect()
+            }
+        };
+        Tensor::from_vec(data, &self.shape)
+    }
+
+    pub fn dtype(&self) -> QuantDtype {
+        self.dtype
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    pub fn values(&self) -> &[i8] {
+        &self.values
+    }
+
+    pub fn params(&self) -> &QuantParams {
+        &self.params
+    }
+
+    /// The size of this tensor's serialized record.
+    pub(crate) fn record_len(&self) -> usize {
+        let header = 16 + 8 * self.shape.len() + 4;
+        let params = match &self.params {
+            QuantParams::PerTensor { .. } => 8,
+            QuantParams::PerChannel { scales, .. } => 4 + 8 * scales.len(),
+        };
+        let data = match self.dtype {
+            QuantDtype::Int8 => self.values.len(),
+            QuantDtype::Int4 => self.values.len().div_ceil(2),
+        };
+        header + params + data
+    }
+}
+
+fn invalid(reason: String) -> crate::MlError {
+    TensorError::InvalidOperation {
+        op: "quantize",
+        reason,
+    }
+    .into()
+}
+
+// The scale and zero point that map the range of `values`, widened to include zero so zero
+// is exact, onto the whole range of `dtype`
+pub(crate) fn affine_params(values: impl Iterator<Item = f32>, dtype: QuantDtype) -> (f32, i32) {
+    let (min, max) = values.fold((0.0f32, 0.0f32), |(lo, hi), x| (lo.min(x), hi.max(x)));
+    let levels = (dtype.max() - dtype.min()) as f32;
+    let scale = if max > min { (max - min) / levels } else { 1.0 };
+    let zero_point = (dtype.min() as f32 - min / scale).round() as i32;
+    (scale, zero_point.clamp(dtype.min(), dtype.max()))
+}
+
+pub(crate) fn quantize_value(x: f32, scale: f32, zero_point: i32, dtype: QuantDtype) -> i8 {
+    let q = (x / scale).round_ties_even() as i32;
+    q.saturating_add(zero_point).clamp(dtype.min(), dtype.max()) as i8
+}
+
+impl Serialize for QuantizedTensor {
+    fn serialize(&self) -> Vec<u8> {
+        let (axis, scales, zero_points) = match &self.params {
+            QuantParams::PerTensor { scale, zero_point } => (None, vec![*scale], vec![*zero_point]),
+            QuantParams::PerChannel {
+                axis,
+                scales,
+                zero_points,
+            } => (Some(*axis), scales.clone(), zero_points.clone()),
+        };
+
+        let data: Vec<u8> = match self.dtype {
+            QuantDtype::Int8 => self.values.iter().map(|&q| q as u8).collect(),
+            QuantDtype::Int4 => self
+                .values
+                .chunks(2)
+                .map(|pair| {
+                    let low = pair[0] as u8 & 0x0f;
+                    let high = pair.get(1).map_or(0, |&q| q as u8 & 0x0f);
+                    low | high << 4
+                })
+                .collect(),
+        };
+
+        format::encode_quantized(&QuantizedRecord {
+            int4: self.dtype == QuantDtype::Int4,
+            shape: self.shape.clone(),
+            axis,
+            scales,
+            zero_points,
+            data,
+        })
+    }
+}
+
+impl Deserialize for QuantizedTensor {
+    fn deserialize(bytes: &[u8]) -> MlResult<Self> {
+        let record = format::decode_quantized(bytes)?;
+        let dtype = if record.int4 {
+            QuantDtype::Int4
+        } else {
+            QuantDtype::Int8
+        };
+
+        let len: usize = record.shape.iter().product();
+        let values = match dtype {
+            QuantDtype::Int8 => record.data.iter().map(|&b| b as i8).collect(),
+            // Sign-extend each nibble, low nibble first
+            QuantDtype::Int4 => record
+                .data
+                .iter()
+                .flat_map(|&b| [(b << 4) as i8 >> 4, b as i8 >> 4])
+                .take(len)
+                .collect(),
+        };
+        let params = match record.axis {
+            None => QuantParams::PerTensor {
+                scale: record.scales[0],
+                zero_point: record.zero_points[0],
+            },
+            Some(axis) => QuantParams::PerChannel {
+                axis,
+                scales: record.scales,
+                zero_points: record.zero_points,
+            },
+        };
+        Self::from_parts(dtype, &record.shape, values, params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialize::StateDict;
+
+    #[test]
+    fn test_quantized_round_trip() -> MlResult<()> {
+        let weight = Tensor::from_vec(vec![0.5, -1.0, 0.25, 100.0, -40.0, 20.0], &[2, 3])?;
+
+        let per_tensor = QuantizedTensor::quantize(&weight, QuantDtype::Int8)?;
+        let back = QuantizedTensor::deserialize(&per_tensor.serialize())?;
+        assert_eq!(back, per_tensor);
+
+        // Per channel, the small first row keeps its precision
+        let per_channel = QuantizedTensor::quantize_per_channel(&weight, QuantDtype::Int4, 0)?;
+        let error = |q: &QuantizedTensor| -> MlResult<f32> {
+            Ok((q.dequantize()?.data()[1] - -1.0f32).abs())
+        };
+        assert!(error(&per_channel)? < 0.1);
+        assert!(error(&QuantizedTensor::quantize(&weight, QuantDtype::Int4)?)? > 0.5);
+
+        // Odd element counts pack into a half-filled last byte
+        let odd = QuantizedTensor::quantize_per_channel(
+            &Tensor::from_vec(vec![-3.0, 1.0, 2.0], &[3, 1])?,
+            QuantDtype::Int4,
+            0,
+        )?;
+        assert_eq!(QuantizedTensor::deserialize(&odd.serialize())?, odd);
+
+        // Inside a state dict, next to float tensors, and through a file
+        let path = "test_quantized.spn";
+        let mut state = StateDict::new();
+        state.insert("bias", Tensor::from_vec(vec![0.5, -0.5], &[2])?);
+        state.insert_quantized("weight", per_channel.clone());
+        state.save(path)?;
+        let loaded = StateDict::load(path)?;
+        assert_eq!(loaded.keys().collect::<Vec<_>>(), ["bias", "weight"]);
+        assert_eq!(loaded.get_quantized("weight"), Some(&per_channel));
+        assert_eq!(
+            crate::serialize::MappedStateDict::open(path)?
+                .get("weight")?
+                .data(),
+            per_channel.dequantize()?.data()
+        );
+        std::fs::remove_file(path).expect("Failed to remove test file");
+
+        assert!(QuantizedTensor::from_parts(
+            QuantDtype::Int4,
+            &[2],
+            vec![3, 9],
+            QuantParams::PerTensor {
+                scale: 1.0,
+                zero_point: 0
+            }
+        )
+        .is_err());
+        Ok(())
+    }
+}
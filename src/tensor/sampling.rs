@@ -0,0 +1,136 @@
+use super::{Tensor, TensorError};
+use crate::nn::random::{with_global_rng, SimpleRng};
+use crate::MlResult;
+
+impl Tensor {
+    /// Draws `num_samples` category indices from each row of this `[categories]` or
+    /// `[rows, categories]` tensor of non-negative weights, which need not sum to 1. The
+    /// indices come back as `[num_samples]` or `[rows, num_samples]`.
+    ///
+    /// Without `replacement` each category is drawn at most once per row, so a row needs
+    /// at least `num_samples` categories of nonzero weight. Draws come from the calling
+    /// thread's generator, so [`seed_all`](crate::seed_all) makes them repeatable.
+    pub fn multinomial(&self, num_samples: usize, replacement: bool) -> MlResult<Tensor> {
+        let categories = match self.shape.as_slice() {
+            &[categories] | &[_, categories] => categories,
+            shape => {
+                return Err(TensorError::InvalidOperation {
+                    op: "multinomial",
+                    reason: format!("Expected a 1D or 2D tensor, got shape {:?}", shape),
+                }
+                .into())
+            }
+        };
+
+        if categories == 0 {
+            return Err(TensorError::InvalidOperation {
+                op: "multinomial",
+                reason: "Cannot draw from zero categories".to_string(),
+            }
+            .into());
+        }
+
+        let data = self.data();
+        for row in data.chunks(categories) {
+            if row.iter().any(|&w| !w.is_finite() || w < 0.0) {
+                return Err(TensorError::InvalidOperation {
+                    op: "multinomial",
+                    reason: "Weights must be finite and non-negative".to_string(),
+                }
+                .into());
+            }
+            let nonzero = row.iter().filter(|&&w| w > 0.0).count();
+            if nonzero == 0 || (!replacement && nonzero < num_samples) {
+                let replacement = if replacement { "with" } else { "without" };
+                return Err(TensorError::InvalidOperation {
+                    op: "multinomial",
+                    reason: format!(
+                        "Cannot draw {} samples {} replacement from a row with {} nonzero weights",
+                        num_samples, replacement, nonzero
+                    ),
+                }
+                .into());
+            }
+        }
+
+        let samples = with_global_rng(|rng| {
+            data.chunks(categories)
+                .flat_map(|row| draw(row, num_samples, replacement, rng))
+                .collect()
+        });
+        let mut shape = self.shape.clone();
+        let last = shape.len() - 1;
+        shape[last] = num_samples;
+        Ok(self.with_data(samples, &shape))
+    }
+}
+
+// `num_samples` indices into `weights`, drawn in proportion to them
+fn draw(weights: &[f32], num_samples: usize, replacement: bool, rng: &mut SimpleRng) -> Vec<f32> {
+    let mut weights = weights.to_vec();
+    let mut total: f32 = weights.iter().sum();
+    (0..num_samples)
+        .map(|_| {
+            let target = rng.next_f32() * total;
+            let mut cumulative = 0.0;
+            // Rounding can leave the target past the last sum, so that weight takes it
+            let mut index = weights.iter().rposition(|&w| w > 0.0).unwrap_or(0);
+            for (i, &w) in weights.iter().enumerate() {
+                cumulative += w;
+                if w > 0.0 && target < cumulative {
+                    index = i;
+                    break;
+                }
+            }
+            if !replacement {
+                total -= weights[index];
+                weights[index] = 0.0;
+            }
+            index as f32
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::seed_all;
+
+    #[test]
+    fn test_multinomial() -> MlResult<()> {
+        seed_all(7);
+        let probs = Tensor::from_vec(vec![0.1, 0.0, 0.9], &[3])?;
+        let samples = probs.multinomial(2000, true)?;
+        assert_eq!(samples.shape(), &[2000]);
+        let counts = samples.data().iter().fold([0; 3], |mut counts, &i| {
+            counts[i as usize] += 1;
+            counts
+        });
+        assert_eq!(counts[1], 0);
+        assert!((1700..1900).contains(&counts[2]), "{:?}", counts);
+
+        // Without replacement each row is a permutation of its nonzero categories
+        let weights = Tensor::from_vec(vec![1.0, 0.0, 2.0, 3.0, 0.5, 0.5, 0.0, 4.0], &[2, 4])?;
+        let samples = weights.multinomial(3, false)?;
+        assert_eq!(samples.shape(), &[2, 3]);
+        let mut first = samples.data()[..3].to_vec();
+        first.sort_by(f32::total_cmp);
+        assert_eq!(first, [0.0, 2.0, 3.0]);
+        assert!(!samples.data()[3..].contains(&2.0));
+
+        // The same seed draws the same samples
+        seed_all(11);
+        let a = weights.multinomial(5, true)?;
+        seed_all(11);
+        assert_eq!(a.data(), weights.multinomial(5, true)?.data());
+
+        assert!(weights.multinomial(4, false).is_err());
+        assert!(Tensor::from_vec(vec![0.0, 0.0], &[2])?
+            .multinomial(1, true)
+            .is_err());
+        assert!(Tensor::from_vec(vec![-1.0, 2.0], &[2])?
+            .multinomial(1, true)
+            .is_err());
+        Ok(())
+    }
+}
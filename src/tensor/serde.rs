@@ -0,0 +1,67 @@
+//! `serde` support, behind the `serde` feature.
+//!
+//! A tensor is written as its shape and its elements in row-major order, e.g.
+//! `{"shape":[2,2],"data":[1.0,2.0,3.0,4.0]}` in JSON. The device isn't recorded: a
+//! deserialized tensor is created on the default device, like [`Tensor::from_vec`].
+
+use serde::de::Error;
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::Tensor;
+
+impl Serialize for Tensor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Tensor", 2)?;
+        state.serialize_field("shape", self.shape())?;
+        state.serialize_field("data", self.data())?;
+        state.end()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "Tensor")]
+struct TensorRepr {
+    shape: Vec<usize>,
+    data: Vec<f32>,
+}
+
+impl<'de> Deserialize<'de> for Tensor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = TensorRepr::deserialize(deserializer)?;
+        Tensor::from_vec(repr.data, &repr.shape).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::{Linear, PaddingMode, Pooling, PoolingType};
+    use crate::MlResult;
+
+    #[test]
+    fn test_json_round_trip() -> MlResult<()> {
+        let tensor = Tensor::from_vec(vec![1.0, -2.5, 3.0, 4.25], &[2, 2])?;
+        let json = serde_json::to_string(&tensor).unwrap();
+        assert_eq!(json, r#"{"shape":[2,2],"data":[1.0,-2.5,3.0,4.25]}"#);
+        let back: Tensor = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.shape(), tensor.shape());
+        assert_eq!(back.data(), tensor.data());
+
+        // The element count has to match the shape
+        assert!(serde_json::from_str::<Tensor>(r#"{"shape":[3],"data":[1.0]}"#).is_err());
+
+        let layer = Linear::new(3, 2, true)?;
+        let back: Linear = serde_json::from_str(&serde_json::to_string(&layer).unwrap()).unwrap();
+        assert_eq!(back.weight().data(), layer.weight().data());
+        assert_eq!(back.bias().unwrap().data(), layer.bias().unwrap().data());
+
+        let json = serde_json::to_string(&Pooling::new(2, 2, PoolingType::Max)).unwrap();
+        assert_eq!(json, r#"{"kernel_size":2,"stride":2,"pooling_type":"Max"}"#);
+        assert_eq!(
+            serde_json::to_string(&PaddingMode::Same).unwrap(),
+            r#""Same""#
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,180 @@
+use crate::backend::{Backend, DeviceBuffer};
+use crate::memory;
+use crate::MlResult;
+use std::sync::{Arc, OnceLock};
+
+/// Tensor data held on the host, on a backend's device, or both.
+///
+/// Either side is filled in lazily from the other: results of device ops only exist on the
+/// device until something reads them from the host, and host data is uploaded the first
+/// time it feeds a device op.
+///
+/// Host data is counted in [`memory::host_memory`] for as long as it is held. Tensors
+/// share a storage through an `Arc`, so views of it don't copy or count the data again.
+#[derive(Debug)]
+pub(crate) struct Storage {
+    len: usize,
+    host: OnceLock<Vec<f32>>,
+    device: OnceLock<Arc<dyn DeviceBuffer>>,
+}
+
+impl Storage {
+    pub fn from_host(data: Vec<f32>) -> Self {
+        memory::host_allocated(data.len());
+        Self {
+            len: data.len(),
+            host: OnceLock::from(data),
+            device: OnceLock::new(),
+        }
+    }
+
+    pub fn from_device(buffer: Arc<dyn DeviceBuffer>) -> Self {
+        Self {
+            len: buffer.len(),
+            host: OnceLock::new(),
+            device: OnceLock::from(buffer),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_on_device(&self) -> bool {
+        self.device.get().is_some()
+    }
+
+    /// Host view of the data, downloading it on first access. Panics if the download
+    /// fails; [`Storage::try_host`] returns the error instead.
+    pub fn host(&self) -> &[f32] {
+        self.try_host()
+            .unwrap_or_else(|e| panic!("Failed to copy tensor data to the host: {}", e))
+    }
+
+    /// Host view of the data, downloading it on first access. A failed download isn't
+    /// kept, so the next access tries again.
+    pub fn try_host(&self) -> MlResult<&[f32]> {
+        if let Some(data) = self.host.get() {
+            return Ok(data);
+        }
+
+        let buffer = self
+            .device
+            .get()
+            .ok_or("Tensor storage holds no data on the host or a device")?;
+        let data = buffer.to_host()?;
+        Ok(self.host.get_or_init(|| {
+            memory::host_allocated(self.len);
+            data
+        }))
+    }
+
+    /// Device buffer for `backend`, uploading the host data on first access. `None` if the
+    /// backend keeps data on the host.
+    pub fn device(&self, backend: &dyn Backend) -> Option<&Arc<dyn DeviceBuffer>> {
+        if let Some(buffer) = self.device.get() {
+            return Some(buffer);
+        }
+
+        let buffer = backend.upload(self.host())?;
+        Some(self.device.get_or_init(|| buffer))
+    }
+
+    /// Takes the host data out, downloading it first if needed. It stops being counted as
+    /// tensor memory.
+    pub fn into_host(mut self) -> Vec<f32> {
+        self.host();
+        let data = self.host.take().unwrap_or_default();
+        memory::host_freed(self.len);
+        data
+    }
+}
+
+impl Drop for Storage {
+    fn drop(&mut self) {
+        if self.host.get().is_some() {
+            memory::host_freed(self.len);
+        }
+    }
+}
+
+/// A tensor's place in [`memory::host_memory`]'s live tensor count and in leak checks,
+/// from its creation until it is dropped. Views and clones of a tensor are tensors of
+/// their own, so each gets a new one.
+#[derive(Debug)]
+pub(crate) struct TensorId {
+    id: u64,
+    elements: usize,
+}
+
+impl TensorId {
+    pub fn new(elements: usize) -> Self {
+        Self {
+            id: memory::tensor_created(elements),
+            elements,
+        }
+    }
+
+    pub fn get(&self) -> u64 {
+        self.id
+    }
+}
+
+impl Clone for TensorId {
+    fn clone(&self) -> Self {
+        Self::new(self.elements)
+    }
+}
+
+impl Drop for TensorId {
+    fn drop(&mut self) {
+        memory::tensor_dropped(self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{CpuBackend, Device};
+    use crate::tensor::Tensor;
+    use std::any::Any;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    // A device buffer that was lost, so it can't be read back
+    #[derive(Debug)]
+    struct LostBuffer;
+
+    impl DeviceBuffer for LostBuffer {
+        fn len(&self) -> usize {
+            2
+        }
+
+        fn to_host(&self) -> MlResult<Vec<f32>> {
+            Err("device lost".into())
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    #[test]
+    fn test_failed_download() -> MlResult<()> {
+        let storage = Storage::from_device(Arc::new(LostBuffer));
+        // The error is returned each time rather than zeros being kept
+        for _ in 0..2 {
+            let message = storage.try_host().unwrap_err().to_string();
+            assert!(message.contains("device lost"), "{}", message);
+        }
+        assert!(catch_unwind(AssertUnwindSafe(|| storage.host().to_vec())).is_err());
+
+        let tensor = Tensor::from_storage(
+            Storage::from_device(Arc::new(LostBuffer)),
+            vec![2],
+            Arc::new(CpuBackend::new()?),
+        );
+        assert!(tensor.try_data().is_err());
+        assert!(tensor.to_cpu().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,303 @@
+use std::ops::Range;
+use std::sync::{Arc, OnceLock};
+
+use super::storage::{Storage, TensorId};
+use super::{Tensor, TensorError};
+use crate::graph::Op;
+use crate::{MlError, MlResult};
+
+/// The strides of a dense row-major tensor of `shape`.
+pub(super) fn contiguous_strides(shape: &[usize]) -> Vec<usize> {
+    let mut strides = vec![1; shape.len()];
+    for axis in (0..shape.len().saturating_sub(1)).rev() {
+        strides[axis] = strides[axis + 1] * shape[axis + 1];
+    }
+    strides
+}
+
+impl Tensor {
+    // A view of `self`'s storage with its own shape and layout
+    pub(super) fn view(&self, shape: Vec<usize>, strides: Vec<usize>, offset: usize) -> Tensor {
+        Tensor {
+            storage: Arc::clone(&self.storage),
+            id: TensorId::new(shape.iter().product()),
+            shape,
+            strides,
+            offset,
+            dense: OnceLock::new(),
+            backend: self.backend.clone(),
+        }
+    }
+
+    /// How far apart consecutive elements along each axis are in the tensor's storage.
+    /// Views like [`Tensor::transpose`] share their storage with the tensor they came
+    /// from, so their strides are those of the original layout rearranged.
+    pub fn strides(&self) -> &[usize] {
+        &self.strides
+    }
+
+    /// Whether the elements lie in row-major order in the storage, with no gaps, so they
+    /// can be read without gathering them first.
+    pub fn is_contiguous(&self) -> bool {
+        let mut expected = 1;
+        for (&size, &stride) in self.shape.iter().zip(&self.strides).rev() {
+            if size != 1 && stride != expected {
+                return false;
+            }
+            expected *= size;
+        }
+        true
+    }
+
+    /// The tensor with its elements in a dense row-major storage of their own, for ops
+    /// that need that layout. A tensor that already has one is returned as a view of it.
+    pub fn contiguous(&self) -> Tensor {
+        let storage = self.dense_storage();
+        if Arc::ptr_eq(storage, &self.storage) {
+            return self.view(self.shape.clone(), self.strides.clone(), 0);
+        }
+        Tensor {
+            storage: Arc::clone(storage),
+            id: TensorId::new(self.len()),
+            shape: self.shape.clone(),
+            strides: contiguous_strides(&self.shape),
+            offset: 0,
+            dense: OnceLock::new(),
+            backend: self.backend.clone(),
+        }
+    }
+
+    /// The elements from `start` up to `end` along `dim`, as a view of the same storage.
+    pub fn slice(&self, dim: usize, start: usize, end: usize) -> MlResult<Tensor> {
+        if dim >= self.shape.len() {
+            return Err(MlError::TensorError(TensorError::InvalidAxis {
+                axis: dim,
+                shape: self.shape.clone(),
+            }));
+        }
+        let ranges: Vec<_> = self.shape[..dim]
+            .iter()
+            .map(|&size| 0..size)
+            .chain(std::iter::once(start..end))
+            .collect();
+        self.index(&ranges)
+    }
+
+    /// The `length` elements from `start` along `dim`, like [`Tensor::slice`].
+    pub fn narrow(&self, dim: usize, start: usize, length: usize) -> MlResult<Tensor> {
+        self.slice(dim, start, start + length)
+    }
+
+    /// The sub-tensor covering `ranges` of the leading dimensions, one range per dimension,
+    /// and all of the dimensions after them. Like [`Tensor::slice`] it shares the storage.
+    ///
+    /// ```ignore
+    /// // Rows 2..4 and the first three columns of every image in the batch
+    /// let window = images.index(&[0..batch, 0..channels, 2..4, 0..3])?;
+    /// ```
+    pub fn index(&self, ranges: &[Range<usize>]) -> MlResult<Tensor> {
+        if ranges.len() > self.shape.len() {
+            return Err(MlError::TensorError(TensorError::InvalidOperation {
+                op: "index",
+                reason: format!(
+                    "{} ranges given for a tensor with shape {:?}",
+                    ranges.len(),
+                    self.shape
+                ),
+            }));
+        }
+
+        let mut shape = self.shape.clone();
+        let mut offset = self.offset;
+        for (axis, range) in ranges.iter().enumerate() {
+            if range.start > range.end || range.end > self.shape[axis] {
+                return Err(MlError::TensorError(TensorError::InvalidOperation {
+                    op: "index",
+                    reason: format!(
+                        "Range {:?} is out of bounds for dimension {} of size {}",
+                        range, axis, self.shape[axis]
+                    ),
+                }));
+            }
+            shape[axis] = range.len();
+            offset += range.start * self.strides[axis];
+        }
+        Ok(self.view(shape, self.strides.clone(), offset))
+    }
+
+    /// The tensor with its dimensions reordered, as a view of the same storage: dimension
+    /// `i` of the result is dimension `axes[i]` of the tensor, so `[0, 2, 3, 1]` turns an
+    /// NCHW batch into NHWC. `axes` lists every dimension once.
+    pub fn permute(&self, axes: &[usize]) -> MlResult<Tensor> {
+        let mut seen = vec![false; self.shape.len()];
+        let valid = axes.len() == self.shape.len()
+            && axes
+                .iter()
+                .all(|&axis| axis < seen.len() && !std::mem::replace(&mut seen[axis], true));
+        if !valid {
+            return Err(MlError::TensorError(TensorError::InvalidOperation {
+                op: "permute",
+                reason: format!(
+                    "{:?} is not a permutation of the dimensions of shape {:?}",
+                    axes, self.shape
+                ),
+            }));
+        }
+
+        // Swapping the two axes of a matrix keeps its own op, which graph passes know
+        let op = match axes {
+            [1, 0] => Op::Transpose,
+            _ => Op::Permute(axes.to_vec()),
+        };
+        self.traced(op, &[], || {
+            let shape = axes.iter().map(|&axis| self.shape[axis]).collect();
+            let strides = axes.iter().map(|&axis| self.strides[axis]).collect();
+            Ok(self.view(shape, strides, self.offset))
+        })
+    }
+
+    /// The tensor with dimensions `dim0` and `dim1` swapped, as a view like
+    /// [`Tensor::permute`]: `transpose(0, 1)` of a matrix, or `transpose(1, 2)` of
+    /// `[batch, seq, heads, head_dim]` to bring the heads before the sequence.
+    pub fn transpose(&self, dim0: usize, dim1: usize) -> MlResult<Tensor> {
+        self.check_axis(dim0)?;
+        self.check_axis(dim1)?;
+        let mut axes: Vec<usize> = (0..self.shape.len()).collect();
+        axes.swap(dim0, dim1);
+        self.permute(&axes)
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.shape.iter().product()
+    }
+
+    // A storage holding exactly the tensor's elements in row-major order: its own, or one
+    // gathered from it the first time it is needed
+    pub(super) fn dense_storage(&self) -> &Arc<Storage> {
+        if self.offset == 0 && self.storage.len() == self.len() && self.is_contiguous() {
+            return &self.storage;
+        }
+        self.dense
+            .get_or_init(|| Arc::new(Storage::from_host(self.read_strided())))
+    }
+
+    // The elements in row-major order, walking the storage by the strides
+    fn read_strided(&self) -> Vec<f32> {
+        let source = self.storage.host();
+        let len = self.len();
+        let mut result = Vec::with_capacity(len);
+        let mut index = vec![0; self.shape.len()];
+        let mut position = self.offset;
+        for _ in 0..len {
+            result.push(source[position]);
+            for axis in (0..self.shape.len()).rev() {
+                index[axis] += 1;
+                position += self.strides[axis];
+                if index[axis] < self.shape[axis] {
+                    break;
+                }
+                position -= self.strides[axis] * self.shape[axis];
+                index[axis] = 0;
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MlResult;
+
+    #[test]
+    fn test_views() -> MlResult<()> {
+        let a = Tensor::from_vec((0..6).map(|x| x as f32).collect(), &[2, 3])?;
+        assert_eq!(a.strides(), &[3, 1]);
+
+        // Transposing and reshaping share the storage instead of copying it
+        let t = a.transpose(0, 1)?;
+        assert!(Arc::ptr_eq(&a.storage, &t.storage));
+        assert_eq!((t.shape(), t.strides()), (&[3, 2][..], &[1, 3][..]));
+        assert!(!t.is_contiguous());
+        assert_eq!(t.data(), &[0.0, 3.0, 1.0, 4.0, 2.0, 5.0]);
+        let back = t.transpose(0, 1)?;
+        assert!(back.is_contiguous() && Arc::ptr_eq(&a.storage, back.dense_storage()));
+
+        let r = a.reshape(&[3, 1, 2])?;
+        assert!(Arc::ptr_eq(&a.storage, &r.storage));
+        assert_eq!(r.strides(), &[2, 2, 1]);
+
+        // A view that isn't contiguous is copied once when reshaped or made contiguous
+        let c = t.contiguous();
+        assert!(c.is_contiguous() && !Arc::ptr_eq(&a.storage, &c.storage));
+        assert_eq!(c.data(), t.data());
+        assert_eq!(t.reshape(&[6])?.data(), &[0.0, 3.0, 1.0, 4.0, 2.0, 5.0]);
+
+        // Ops read views in their logical order
+        assert_eq!(t.matmul(&a)?.data()[..3], [9.0, 12.0, 15.0]);
+        assert_eq!(t.add_scalar(1.0)?.shape(), &[3, 2]);
+        assert_eq!(t.into_data(), vec![0.0, 3.0, 1.0, 4.0, 2.0, 5.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_slicing() -> MlResult<()> {
+        let a = Tensor::from_vec((0..24).map(|x| x as f32).collect(), &[2, 3, 4])?;
+
+        // Slices share the storage; only those of the first dimension stay contiguous
+        assert!(a.slice(0, 1, 2)?.is_contiguous());
+        let rows = a.slice(1, 1, 3)?;
+        assert!(Arc::ptr_eq(&a.storage, &rows.storage));
+        assert_eq!(rows.shape(), &[2, 2, 4]);
+        assert_eq!(rows.data()[..4], [4.0, 5.0, 6.0, 7.0]);
+        let columns = a.narrow(2, 1, 2)?;
+        assert!(!columns.is_contiguous());
+        assert_eq!(columns.data()[..4], [1.0, 2.0, 5.0, 6.0]);
+
+        let window = a.index(&[1..2, 0..2, 2..4])?;
+        assert_eq!(window.shape(), &[1, 2, 2]);
+        assert_eq!(window.data(), &[14.0, 15.0, 18.0, 19.0]);
+        #[allow(clippy::single_range_in_vec_init)]
+        let row = a.index(&[1..2])?;
+        assert_eq!(row.data(), a.slice(0, 1, 2)?.data());
+
+        // Slices of slices add up their offsets
+        assert_eq!(rows.slice(2, 3, 4)?.data(), &[7.0, 11.0, 19.0, 23.0]);
+        assert_eq!(a.slice(1, 2, 2)?.shape(), &[2, 0, 4]);
+
+        assert!(a.slice(3, 0, 1).is_err());
+        assert!(a.slice(2, 3, 5).is_err());
+        assert!(a.index(&[0..1, 0..1, 0..1, 0..1]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_permute() -> MlResult<()> {
+        // NCHW to NHWC and back, without copying
+        let nchw = Tensor::from_vec((0..12).map(|x| x as f32).collect(), &[1, 3, 2, 2])?;
+        let nhwc = nchw.permute(&[0, 2, 3, 1])?;
+        assert!(Arc::ptr_eq(&nchw.storage, &nhwc.storage));
+        assert_eq!(
+            (nhwc.shape(), nhwc.strides()),
+            (&[1, 2, 2, 3][..], &[12, 2, 1, 4][..])
+        );
+        assert_eq!(nhwc.data()[..6], [0.0, 4.0, 8.0, 1.0, 5.0, 9.0]);
+        let back = nhwc.permute(&[0, 3, 1, 2])?;
+        assert!(back.is_contiguous());
+        assert_eq!(back.data(), nchw.data());
+
+        // Splitting attention heads: [batch, seq, heads, head_dim] to [batch, heads, seq, ..]
+        let x = Tensor::from_vec((0..8).map(|x| x as f32).collect(), &[1, 2, 2, 2])?;
+        let heads = x.transpose(1, 2)?;
+        assert_eq!(heads.shape(), &[1, 2, 2, 2]);
+        assert_eq!(heads.data(), &[0.0, 1.0, 4.0, 5.0, 2.0, 3.0, 6.0, 7.0]);
+        assert_eq!(heads.transpose(2, 1)?.data(), x.data());
+        assert_eq!(x.transpose(3, 3)?.data(), x.data());
+
+        assert!(x.permute(&[0, 1, 2]).is_err());
+        assert!(x.permute(&[0, 1, 1, 2]).is_err());
+        assert!(x.permute(&[0, 1, 2, 4]).is_err());
+        assert!(x.transpose(0, 4).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,16 @@
+//! Helpers shared by the unit tests.
+
+/// Asserts that two float slices have the same length and agree elementwise to within 1e-5.
+#[track_caller]
+pub(crate) fn assert_close(actual: &[f32], expected: &[f32]) {
+    assert_close_within(actual, expected, 1e-5);
+}
+
+/// Like [`assert_close`], for results that accumulate more rounding error.
+#[track_caller]
+pub(crate) fn assert_close_within(actual: &[f32], expected: &[f32], tolerance: f32) {
+    assert_eq!(actual.len(), expected.len());
+    for (a, e) in actual.iter().zip(expected) {
+        assert!((a - e).abs() < tolerance, "{:?} vs {:?}", actual, expected);
+    }
+}
@@ -0,0 +1,270 @@
+use super::{History, Logs};
+use crate::MlResult;
+
+/// The part of a [`Trainer`](super::Trainer) a [`Callback`] sees: the model, the learning
+/// rate and the counters, and a way to end training early.
+pub struct Context<'a, M> {
+    pub(crate) model: &'a mut M,
+    pub(crate) learning_rate: &'a mut f32,
+    pub(crate) stop: &'a mut bool,
+    pub(crate) epoch: usize,
+    pub(crate) step: usize,
+}
+
+impl<M> Context<'_, M> {
+    pub fn model(&self) -> &M {
+        self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut M {
+        self.model
+    }
+
+    pub fn learning_rate(&self) -> f32 {
+        *self.learning_rate
+    }
+
+    /// Changes the learning rate from the next step on.
+    pub fn set_learning_rate(&mut self, learning_rate: f32) {
+        *self.learning_rate = learning_rate;
+    }
+
+    /// The epoch in progress, counting from 0 across [`Trainer::fit`](super::Trainer::fit)
+    /// calls. After an epoch ends, the epoch that ended.
+    pub fn epoch(&self) -> usize {
+        self.epoch
+    }
+
+    /// The number of optimization steps taken so far.
+    pub fn step(&self) -> usize {
+        self.step
+    }
+
+    /// Ends training once the current epoch is over.
+    pub fn stop_training(&mut self) {
+        *self.stop = true;
+    }
+
+    pub fn stop_requested(&self) -> bool {
+        *self.stop
+    }
+}
+
+/// Hooks a [`Trainer`](super::Trainer) calls as training goes on. Every hook does nothing
+/// by default, so a callback implements only the ones it needs.
+///
+/// Callbacks run in the order they were added, and an error from any hook stops training
+/// and is returned from `fit`.
+pub trait Callback<M> {
+    fn on_train_begin(&mut self, _ctx: &mut Context<'_, M>) -> MlResult<()> {
+        Ok(())
+    }
+
+    fn on_train_end(&mut self, _ctx: &mut Context<'_, M>, _history: &History) -> MlResult<()> {
+        Ok(())
+    }
+
+    fn on_epoch_begin(&mut self, _ctx: &mut Context<'_, M>) -> MlResult<()> {
+        Ok(())
+    }
+
+    /// Called with the epoch's logs, validation values included; values inserted here are
+    /// kept in the [`History`].
+    fn on_epoch_end(&mut self, _ctx: &mut Context<'_, M>, _logs: &mut Logs) -> MlResult<()> {
+        Ok(())
+    }
+
+    /// Called after the model has been updated on a batch, with the batch's index in the
+    /// epoch and its loss.
+    fn on_batch_end(
+        &mut self,
+        _ctx: &mut Context<'_, M>,
+        _batch: usize,
+        _loss: f32,
+    ) -> MlResult<()> {
+        Ok(())
+    }
+
+    /// Called by every [`Trainer::train_step`](super::Trainer::train_step) once the layers
+    /// have applied their updates, including steps taken outside `fit`.
+    fn on_backward_end(&mut self, _ctx: &mut Context<'_, M>, _loss: f32) -> MlResult<()> {
+        Ok(())
+    }
+}
+
+/// Stops training once a logged value has stopped improving for `patience` epochs.
+#[derive(Debug, Clone)]
+pub struct EarlyStopping {
+    monitor: String,
+    patience: usize,
+    min_delta: f32,
+    maximize: bool,
+    best: Option<f32>,
+    waited: usize,
+}
+
+impl EarlyStopping {
+    /// Watches `monitor`, such as `"val_loss"`, for a decrease.
+    pub fn new(monitor: impl Into<String>, patience: usize) -> Self {
+        Self {
+            monitor: monitor.into(),
+            patience,
+            min_delta: 0.0,
+            maximize: false,
+            best: None,
+            waited: 0,
+        }
+    }
+
+    /// The smallest change that counts as an improvement. Defaults to 0.
+    pub fn min_delta(mut self, min_delta: f32) -> Self {
+        self.min_delta = min_delta.abs();
+        self
+    }
+
+    /// Watches for an increase instead, for values like accuracy.
+    pub fn maximize(mut self) -> Self {
+        self.maximize = true;
+        self
+    }
+
+    /// The best value seen so far.
+    pub fn best(&self) -> Option<f32> {
+        self.best
+    }
+}
+
+impl<M> Callback<M> for EarlyStopping {
+    fn on_train_begin(&mut self, _ctx: &mut Context<'_, M>) -> MlResult<()> {
+        self.best = None;
+        self.waited = 0;
+        Ok(())
+    }
+
+    fn on_epoch_end(&mut self, ctx: &mut Context<'_, M>, logs: &mut Logs) -> MlResult<()> {
+        let value = logs.get(&self.monitor).ok_or_else(|| {
+            format!(
+                "Early stopping watches {:?}, which is not logged",
+                self.monitor
+            )
+        })?;
+        let improved = match self.best {
+            None => true,
+            Some(best) if self.maximize => value > best + self.min_delta,
+            Some(best) => value < best - self.min_delta,
+        };
+        if improved {
+            self.best = Some(value);
+            self.waited = 0;
+        } else {
+            self.waited += 1;
+            if self.waited >= self.patience {
+                ctx.stop_training();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sets the learning rate at the start of every epoch from the epoch number, logging it as
+/// `"learning_rate"`.
+pub struct LearningRateSchedule<F> {
+    schedule: F,
+}
+
+impl<F: FnMut(usize) -> f32> LearningRateSchedule<F> {
+    pub fn new(schedule: F) -> Self {
+        Self { schedule }
+    }
+}
+
+impl<M, F: FnMut(usize) -> f32> Callback<M> for LearningRateSchedule<F> {
+    fn on_epoch_begin(&mut self, ctx: &mut Context<'_, M>) -> MlResult<()> {
+        let learning_rate = (self.schedule)(ctx.epoch());
+        ctx.set_learning_rate(learning_rate);
+        Ok(())
+    }
+
+    fn on_epoch_end(&mut self, ctx: &mut Context<'_, M>, logs: &mut Logs) -> MlResult<()> {
+        logs.insert("learning_rate", ctx.learning_rate());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{DataLoader, HostTensor, TensorDataset};
+    use crate::loss::MseLoss;
+    use crate::nn::Linear;
+    use crate::train::Trainer;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Records each hook, and logs a `"score"` that stops improving after the first epoch
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+
+    impl<M> Callback<M> for Recorder {
+        fn on_train_begin(&mut self, ctx: &mut Context<'_, M>) -> MlResult<()> {
+            self.0.borrow_mut().push(format!("begin {}", ctx.epoch()));
+            Ok(())
+        }
+
+        fn on_epoch_end(&mut self, ctx: &mut Context<'_, M>, logs: &mut Logs) -> MlResult<()> {
+            logs.insert("score", if ctx.epoch() == 0 { 2.0 } else { 1.0 });
+            self.0.borrow_mut().push(format!("epoch {}", ctx.epoch()));
+            Ok(())
+        }
+
+        fn on_batch_end(
+            &mut self,
+            ctx: &mut Context<'_, M>,
+            batch: usize,
+            _loss: f32,
+        ) -> MlResult<()> {
+            self.0
+                .borrow_mut()
+                .push(format!("batch {} step {}", batch, ctx.step()));
+            Ok(())
+        }
+
+        fn on_train_end(&mut self, _ctx: &mut Context<'_, M>, history: &History) -> MlResult<()> {
+            self.0
+                .borrow_mut()
+                .push(format!("end {}", history.epochs().len()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_callbacks() -> MlResult<()> {
+        let dataset = TensorDataset::from_host(
+            HostTensor::new(vec![0.0, 1.0, 2.0, 3.0], &[4, 1])?,
+            HostTensor::new(vec![0.0, 1.0, 2.0, 3.0], &[4, 1])?,
+        )?;
+        let loader = DataLoader::new(dataset, 2);
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut trainer = Trainer::new(Linear::new(1, 1, true)?, MseLoss)
+            .callback(LearningRateSchedule::new(|epoch| 0.1 / (epoch + 1) as f32))
+            .callback(Recorder(events.clone()))
+            .callback(EarlyStopping::new("score", 2).maximize());
+
+        // The score peaks in the first epoch, so two epochs later training stops
+        let history = trainer.fit(&loader, 10)?;
+        assert_eq!(history.epochs().len(), 3);
+        assert_eq!(history.metric("learning_rate"), [0.1, 0.05, 0.1 / 3.0]);
+        assert_eq!(trainer.step(), 6);
+        let events = events.borrow();
+        assert_eq!(
+            events[..4],
+            ["begin 0", "batch 0 step 1", "batch 1 step 2", "epoch 0"]
+        );
+        assert_eq!(events.last().unwrap(), "end 3");
+
+        assert!(Trainer::new(Linear::new(1, 1, true)?, MseLoss)
+            .callback(EarlyStopping::new("val_loss", 1))
+            .fit(&loader, 1)
+            .is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+use super::{Batch, History, Logs, Trainer};
+use crate::data::{Collate, DataLoader, Dataset, KFold, Subset};
+use crate::loss::Loss;
+use crate::nn::Layer;
+use crate::MlResult;
+
+/// Trains a fresh model on each fold of a [`KFold`] split and evaluates it on the samples
+/// the fold holds out.
+///
+/// ```ignore
+/// let results = CrossValidator::new(KFold::new(5).shuffle(0), || {
+///     Ok(Trainer::new(Linear::new(4, 3, true)?, CrossEntropyLoss).metric(Accuracy::new()))
+/// })
+/// .epochs(20)
+/// .retrain(true)
+/// .run(dataset)?;
+/// println!("{}", results);
+/// let model = results.into_trainer().unwrap().into_model();
+/// ```
+pub struct CrossValidator<F> {
+    folds: KFold,
+    trainer: F,
+    epochs: usize,
+    batch_size: usize,
+    shuffle: bool,
+    retrain: bool,
+}
+
+impl<F> CrossValidator<F> {
+    /// Cross-validates over `folds`, calling `trainer` for a new, untrained trainer for
+    /// each fold.
+    pub fn new(folds: KFold, trainer: F) -> Self {
+        Self {
+            folds,
+            trainer,
+            epochs: 10,
+            batch_size: 32,
+            shuffle: true,
+            retrain: false,
+        }
+    }
+
+    /// The epochs each fold trains for. Defaults to 10.
+    pub fn epochs(mut self, epochs: usize) -> Self {
+        self.epochs = epochs;
+        self
+    }
+
+    /// Defaults to 32.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Whether training batches are shuffled each epoch. Defaults to true.
+    pub fn shuffle(mut self, shuffle: bool) -> Self {
+        self.shuffle = shuffle;
+        self
+    }
+
+    /// Once every fold is done, also trains a model on the whole dataset, kept in the
+    /// results.
+    pub fn retrain(mut self, retrain: bool) -> Self {
+        self.retrain = retrain;
+        self
+    }
+
+    pub fn run<M, L, D>(&mut self, dataset: D) -> MlResult<CrossValidation<M, L>>
+    where
+        F: FnMut() -> MlResult<Trainer<M, L>>,
+        M: Layer,
+        L: Loss,
+        D: Dataset + Send + Sync + 'static,
+        D::Item: Collate + 'static,
+        <D::Item as Collate>::Batch: Batch + Send + 'static,
+    {
+        let dataset = Arc::new(dataset);
+        let mut folds = Vec::new();
+        let mut histories = Vec::new();
+        for fold in self.folds.split(dataset.len())? {
+            let train = Subset::new(dataset.clone(), fold.train)?;
+            let validation = Subset::new(dataset.clone(), fold.validation)?;
+            let train = DataLoader::new(train, self.batch_size).shuffle(self.shuffle);
+            let validation = DataLoader::new(validation, self.batch_size);
+
+            let mut trainer = (self.trainer)()?;
+            histories.push(trainer.fit(&train, self.epochs)?);
+            folds.push(trainer.evaluate(&validation)?);
+        }
+
+        let trainer = if self.retrain {
+            let mut trainer = (self.trainer)()?;
+            let all = DataLoader::new(dataset, self.batch_size).shuffle(self.shuffle);
+            trainer.fit(&all, self.epochs)?;
+            Some(trainer)
+        } else {
+            None
+        };
+        Ok(CrossValidation {
+            folds,
+            histories,
+            trainer,
+        })
+    }
+}
+
+/// The mean and standard deviation of a value over folds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub mean: f32,
+    /// The population standard deviation, dividing by the number of folds.
+    pub std: f32,
+}
+
+impl Summary {
+    fn of(values: &[f32]) -> Self {
+        let n = values.len() as f32;
+        let mean = values.iter().sum::<f32>() / n;
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / n;
+        Self {
+            mean,
+            std: variance.sqrt(),
+        }
+    }
+}
+
+/// What a [`CrossValidator`] found: each fold's validation logs and training history, and
+/// the model retrained on all the data if it was asked to.
+pub struct CrossValidation<M, L> {
+    folds: Vec<Logs>,
+    histories: Vec<History>,
+    trainer: Option<Trainer<M, L>>,
+}
+
+impl<M, L> CrossValidation<M, L> {
+    /// Each fold's loss and metrics on the samples it held out, in fold order.
+    pub fn folds(&self) -> &[Logs] {
+        &self.folds
+    }
+
+    /// Each fold's training history.
+    pub fn histories(&self) -> &[History] {
+        &self.histories
+    }
+
+    /// The mean and standard deviation of `name` over the folds that logged it.
+    pub fn summary(&self, name: &str) -> Option<Summary> {
+        let values: Vec<f32> = self
+            .folds
+            .iter()
+            .filter_map(|logs| logs.get(name))
+            .collect();
+        (!values.is_empty()).then(|| Summary::of(&values))
+    }
+
+    /// The summary of every value the folds logged, by name.
+    pub fn summaries(&self) -> BTreeMap<String, Summary> {
+        let names: Vec<&str> = self
+            .folds
+            .iter()
+            .flat_map(|logs| logs.iter())
+            .map(|(n, _)| n)
+            .collect();
+        names
+            .into_iter()
+            .filter_map(|name| Some((name.to_string(), self.summary(name)?)))
+            .collect()
+    }
+
+    /// The trainer retrained on the whole dataset, with
+    /// [`CrossValidator::retrain`].
+    pub fn trainer(&self) -> Option<&Trainer<M, L>> {
+        self.trainer.as_ref()
+    }
+
+    pub fn into_trainer(self) -> Option<Trainer<M, L>> {
+        self.trainer
+    }
+}
+
+impl<M, L> Display for CrossValidation<M, L> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-fold cross-validation", self.folds.len())?;
+        for (name, summary) in self.summaries() {
+            write!(f, "\n  {}: {:.4} ± {:.4}", name, summary.mean, summary.std)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{HostTensor, TensorDataset};
+    use crate::loss::MseLoss;
+    use crate::nn::Linear;
+
+    #[test]
+    fn test_cross_validation() -> MlResult<()> {
+        let x: Vec<f32> = (0..30).map(|i| i as f32 / 30.0).collect();
+        let y: Vec<f32> = x.iter().map(|x| 2.0 * x - 0.5).collect();
+        let dataset =
+            TensorDataset::from_host(HostTensor::new(x, &[30, 1])?, HostTensor::new(y, &[30, 1])?)?;
+
+        let mut built = 0;
+        let results = CrossValidator::new(KFold::new(3).shuffle(1), || {
+            built += 1;
+            Ok(Trainer::new(Linear::new(1, 1, true)?, MseLoss).learning_rate(0.5))
+        })
+        .epochs(60)
+        .batch_size(5)
+        .retrain(true)
+        .run(dataset)?;
+        assert_eq!(built, 4);
+
+        assert_eq!(results.folds().len(), 3);
+        assert!(results.histories().iter().all(|h| h.epochs().len() == 60));
+        let loss = results.summary("loss").unwrap();
+        assert!(loss.mean < 0.01, "{:?}", loss);
+        assert!(loss.std >= 0.0);
+        assert!(results.summary("accuracy").is_none());
+        assert!(results
+            .to_string()
+            .starts_with("3-fold cross-validation\n  loss: "));
+        assert_eq!(results.trainer().unwrap().epoch(), 60);
+
+        let summary = Summary::of(&[1.0, 3.0]);
+        assert_eq!((summary.mean, summary.std), (2.0, 1.0));
+        Ok(())
+    }
+}
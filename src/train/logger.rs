@@ -0,0 +1,392 @@
+//! Metrics files and checkpoints kept in a run directory.
+//!
+//! A [`RunDir`] gives each training run a directory of its own:
+//!
+//! ```text
+//! runs/mnist/
+//!     config.json          hyperparameters, from RunDir::write_config
+//!     metrics/epochs.csv   one row per epoch, from MetricsLogger
+//!     metrics/steps.csv    one row every few steps, with MetricsLogger::log_steps
+//!     checkpoints/         epoch-0001.ckpt, ..., with last.ckpt and best.ckpt linking to them
+//! ```
+//!
+//! The files are plain CSV or JSON Lines, so a spreadsheet, pandas or `jq` can read them
+//! while training is still going.
+
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use super::{Callback, Context, History, Logs};
+use crate::nn::Layer;
+use crate::serialize::safetensors::quote;
+use crate::serialize::{Checkpoint, TrainingRun};
+use crate::MlResult;
+
+/// The directory of one training run.
+#[derive(Debug, Clone)]
+pub struct RunDir {
+    path: PathBuf,
+}
+
+impl RunDir {
+    /// Creates `root/name` with its `metrics` and `checkpoints` directories. If a run of
+    /// that name exists, the new one is `name-2`, `name-3` and so on, so runs never
+    /// overwrite each other.
+    pub fn create<P: AsRef<Path>>(root: P, name: &str) -> MlResult<Self> {
+        let root = root.as_ref();
+        fs::create_dir_all(root).map_err(|e| format!("Failed to create directory: {}", e))?;
+        let mut path = root.join(name);
+        let mut attempt = 1;
+        loop {
+            match fs::create_dir(&path) {
+                Ok(()) => break,
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    attempt += 1;
+                    path = root.join(format!("{}-{}", name, attempt));
+                }
+                Err(e) => return Err(format!("Failed to create directory: {}", e).into()),
+            }
+        }
+        let run = Self { path };
+        for dir in [run.metrics_dir(), run.checkpoints_dir()] {
+            fs::create_dir(dir).map_err(|e| format!("Failed to create directory: {}", e))?;
+        }
+        Ok(run)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn metrics_dir(&self) -> PathBuf {
+        self.path.join("metrics")
+    }
+
+    pub fn checkpoints_dir(&self) -> PathBuf {
+        self.path.join("checkpoints")
+    }
+
+    /// Writes `config.json`, a snapshot of the settings the run was started with. Values
+    /// are stored as the strings they display as.
+    pub fn write_config<K, V>(&self, config: impl IntoIterator<Item = (K, V)>) -> MlResult<()>
+    where
+        K: AsRef<str>,
+        V: Display,
+    {
+        let entries: Vec<String> = config
+            .into_iter()
+            .map(|(key, value)| format!("  {}: {}", quote(key.as_ref()), quote(&value.to_string())))
+            .collect();
+        let json = format!("{{\n{}\n}}\n", entries.join(",\n"));
+        fs::write(self.path.join("config.json"), json)
+            .map_err(|e| format!("Failed to write file: {}", e).into())
+    }
+}
+
+/// The layout of metrics files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// Comma-separated values with a header row taken from the first row written.
+    #[default]
+    Csv,
+    /// One JSON object per line.
+    Jsonl,
+}
+
+impl LogFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            LogFormat::Csv => "csv",
+            LogFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+// A metrics file written a row at a time
+struct MetricsFile {
+    writer: BufWriter<File>,
+    format: LogFormat,
+    columns: Option<Vec<String>>,
+}
+
+impl MetricsFile {
+    fn create(path: PathBuf, format: LogFormat) -> MlResult<Self> {
+        let file = File::create(path).map_err(|e| format!("Failed to create file: {}", e))?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+            format,
+            columns: None,
+        })
+    }
+
+    // Values the CSV header doesn't name are left out, and columns a row lacks are empty
+    fn write(&mut self, row: &[(&str, f64)]) -> MlResult<()> {
+        let line = match self.format {
+            LogFormat::Jsonl => {
+                let fields: Vec<String> = row
+                    .iter()
+                    .map(|(name, value)| format!("{}:{}", quote(name), json_number(*value)))
+                    .collect();
+                format!("{{{}}}\n", fields.join(","))
+            }
+            LogFormat::Csv => {
+                let mut line = String::new();
+                let columns = self.columns.get_or_insert_with(|| {
+                    let names: Vec<String> = row.iter().map(|(name, _)| csv_field(name)).collect();
+                    line = format!("{}\n", names.join(","));
+                    row.iter().map(|(name, _)| name.to_string()).collect()
+                });
+                let values: Vec<String> = columns
+                    .iter()
+                    .map(|column| {
+                        row.iter()
+                            .find(|(name, _)| name == column)
+                            .map(|(_, value)| value.to_string())
+                            .unwrap_or_default()
+                    })
+                    .collect();
+                line.push_str(&values.join(","));
+                line.push('\n');
+                line
+            }
+        };
+        self.writer
+            .write_all(line.as_bytes())
+            .map_err(|e| format!("Failed to write file: {}", e).into())
+    }
+
+    fn flush(&mut self) -> MlResult<()> {
+        self.writer
+            .flush()
+            .map_err(|e| format!("Failed to write file: {}", e).into())
+    }
+}
+
+fn json_number(value: f64) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Writes each epoch's logs to `metrics/epochs.csv` (or `.jsonl`) in a [`RunDir`], and
+/// optionally the loss every few steps to `metrics/steps.csv`. Epoch rows are flushed as
+/// they are written.
+pub struct MetricsLogger {
+    dir: PathBuf,
+    format: LogFormat,
+    epochs: MetricsFile,
+    steps: Option<(MetricsFile, usize)>,
+}
+
+impl MetricsLogger {
+    pub fn new(run: &RunDir, format: LogFormat) -> MlResult<Self> {
+        let dir = run.metrics_dir();
+        let epochs =
+            MetricsFile::create(dir.join(format!("epochs.{}", format.extension())), format)?;
+        Ok(Self {
+            dir,
+            format,
+            epochs,
+            steps: None,
+        })
+    }
+
+    /// Also logs the epoch, batch and loss of every `every`th step.
+    pub fn log_steps(mut self, every: usize) -> MlResult<Self> {
+        let path = self.dir.join(format!("steps.{}", self.format.extension()));
+        self.steps = Some((MetricsFile::create(path, self.format)?, every.max(1)));
+        Ok(self)
+    }
+}
+
+impl<M> Callback<M> for MetricsLogger {
+    fn on_batch_end(&mut self, ctx: &mut Context<'_, M>, batch: usize, loss: f32) -> MlResult<()> {
+        match &mut self.steps {
+            Some((file, every)) if ctx.step().is_multiple_of(*every) => file.write(&[
+                ("step", ctx.step() as f64),
+                ("epoch", ctx.epoch() as f64),
+                ("batch", batch as f64),
+                ("loss", loss as f64),
+            ]),
+            _ => Ok(()),
+        }
+    }
+
+    fn on_epoch_end(&mut self, ctx: &mut Context<'_, M>, logs: &mut Logs) -> MlResult<()> {
+        let mut row = vec![("epoch", ctx.epoch() as f64), ("step", ctx.step() as f64)];
+        row.extend(logs.iter().map(|(name, value)| (name, value as f64)));
+        self.epochs.write(&row)?;
+        self.epochs.flush()?;
+        match &mut self.steps {
+            Some((file, _)) => file.flush(),
+            None => Ok(()),
+        }
+    }
+
+    fn on_train_end(&mut self, _ctx: &mut Context<'_, M>, _history: &History) -> MlResult<()> {
+        self.epochs.flush()
+    }
+}
+
+/// Saves a [`Checkpoint`] of the model and the [`seed_all`](crate::seed_all) generator at
+/// the end of every epoch to the `checkpoints` directory of a [`RunDir`], as
+/// `epoch-0001.ckpt` and so on. `last.ckpt` links to the newest and, when a value is
+/// monitored, `best.ckpt` to the best.
+///
+/// Links are symbolic where the platform has them and copies elsewhere.
+pub struct ModelCheckpoint {
+    dir: PathBuf,
+    monitor: Option<String>,
+    maximize: bool,
+    best: Option<f32>,
+}
+
+impl ModelCheckpoint {
+    pub fn new(run: &RunDir) -> Self {
+        Self {
+            dir: run.checkpoints_dir(),
+            monitor: None,
+            maximize: false,
+            best: None,
+        }
+    }
+
+    /// Keeps `best.ckpt` on the epoch with the lowest `monitor`, such as `"val_loss"`.
+    pub fn monitor(mut self, monitor: impl Into<String>) -> Self {
+        self.monitor = Some(monitor.into());
+        self
+    }
+
+    /// Keeps the epoch with the highest value as the best instead.
+    pub fn maximize(mut self) -> Self {
+        self.maximize = true;
+        self
+    }
+
+    /// The best monitored value so far.
+    pub fn best(&self) -> Option<f32> {
+        self.best
+    }
+
+    fn link(&self, file: &str, name: &str) -> MlResult<()> {
+        let link = self.dir.join(name);
+        if fs::symlink_metadata(&link).is_ok() {
+            fs::remove_file(&link).map_err(|e| format!("Failed to replace {}: {}", name, e))?;
+        }
+        #[cfg(unix)]
+        let linked = std::os::unix::fs::symlink(file, &link);
+        #[cfg(not(unix))]
+        let linked = fs::copy(self.dir.join(file), &link).map(|_| ());
+        linked.map_err(|e| format!("Failed to link {}: {}", name, e).into())
+    }
+}
+
+impl<M: Layer> Callback<M> for ModelCheckpoint {
+    fn on_epoch_end(&mut self, ctx: &mut Context<'_, M>, logs: &mut Logs) -> MlResult<()> {
+        // Counters are those to resume from: the epoch after this one
+        let (epoch, step) = (ctx.epoch() as u64 + 1, ctx.step() as u64);
+        let file = format!("epoch-{:04}.ckpt", epoch);
+        Checkpoint::capture(&TrainingRun::new(ctx.model_mut()).global_rng(), epoch, step)?
+            .save(self.dir.join(&file))?;
+        self.link(&file, "last.ckpt")?;
+
+        if let Some(monitor) = &self.monitor {
+            let value = logs
+                .get(monitor)
+                .ok_or_else(|| format!("Checkpoints monitor {:?}, which is not logged", monitor))?;
+            let improved = match self.best {
+                None => true,
+                Some(best) if self.maximize => value > best,
+                Some(best) => value < best,
+            };
+            if improved {
+                self.best = Some(value);
+                self.link(&file, "best.ckpt")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{DataLoader, HostTensor, TensorDataset};
+    use crate::loss::MseLoss;
+    use crate::nn::Linear;
+    use crate::train::{EarlyStopping, Trainer};
+
+    #[test]
+    fn test_run_dir_logging() -> MlResult<()> {
+        let root = std::env::temp_dir().join("cetana_test_run_dir");
+        let _ = fs::remove_dir_all(&root);
+        let run = RunDir::create(&root, "line")?;
+        assert_eq!(RunDir::create(&root, "line")?.path(), root.join("line-2"));
+        run.write_config([("learning_rate", "0.1"), ("model", "linear \"1x1\"")])?;
+        assert_eq!(
+            fs::read_to_string(run.path().join("config.json")).unwrap(),
+            "{\n  \"learning_rate\": \"0.1\",\n  \"model\": \"linear \\\"1x1\\\"\"\n}\n"
+        );
+
+        let dataset = TensorDataset::from_host(
+            HostTensor::new(vec![0.0, 1.0, 2.0, 3.0], &[4, 1])?,
+            HostTensor::new(vec![1.0, 3.0, 5.0, 7.0], &[4, 1])?,
+        )?;
+        let loader = DataLoader::new(dataset, 2);
+        let mut trainer = Trainer::new(Linear::new(1, 1, true)?, MseLoss)
+            .learning_rate(0.02)
+            .callback(MetricsLogger::new(&run, LogFormat::Csv)?.log_steps(3)?)
+            .callback(ModelCheckpoint::new(&run).monitor("loss"))
+            .callback(EarlyStopping::new("loss", 1));
+        let epochs = trainer.fit(&loader, 4)?.epochs().len();
+
+        let csv = fs::read_to_string(run.metrics_dir().join("epochs.csv")).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "epoch,step,loss");
+        assert_eq!(lines.len(), epochs + 1);
+        assert!(lines[1].starts_with("0,2,"));
+        let steps = fs::read_to_string(run.metrics_dir().join("steps.csv")).unwrap();
+        assert!(steps.starts_with("step,epoch,batch,loss\n3,1,0,"));
+
+        let checkpoints = run.checkpoints_dir();
+        let last = Checkpoint::load(checkpoints.join("last.ckpt"))?;
+        assert_eq!(last.epoch, epochs as u64);
+        assert_eq!(
+            last.model.get("weight").unwrap().data(),
+            trainer.model().state_dict().get("weight").unwrap().data()
+        );
+        assert!(Checkpoint::load(checkpoints.join("best.ckpt")).is_ok());
+
+        let mut jsonl = MetricsLogger::new(&run, LogFormat::Jsonl)?;
+        let (mut model, mut learning_rate, mut stop) = (Linear::new(1, 1, true)?, 0.1, false);
+        let mut ctx = Context {
+            model: &mut model,
+            learning_rate: &mut learning_rate,
+            stop: &mut stop,
+            epoch: 2,
+            step: 8,
+        };
+        let mut logs = Logs::new();
+        logs.insert("loss", 0.5);
+        logs.insert("val_loss", f32::NAN);
+        jsonl.on_epoch_end(&mut ctx, &mut logs)?;
+        assert_eq!(
+            fs::read_to_string(run.metrics_dir().join("epochs.jsonl")).unwrap(),
+            "{\"epoch\":2,\"step\":8,\"loss\":0.5,\"val_loss\":null}\n"
+        );
+        fs::remove_dir_all(&root).unwrap();
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+//! Training loops.
+//!
+//! A [`Trainer`] runs the usual loop over a [`DataLoader`](crate::data::DataLoader): move
+//! each batch to the device, run the model forward, take the loss and its gradient, and
+//! backpropagate, averaging the loss over each epoch.
+//!
+//! ```ignore
+//! let mut trainer = Trainer::new(model, CrossEntropyLoss).learning_rate(0.05);
+//! let history = trainer.fit_validated(&train_loader, &val_loader, 10)?;
+//! println!("final validation loss {:?}", history.last().and_then(|l| l.get("val_loss")));
+//! let predictions = trainer.predict(&test_loader)?;
+//! ```
+//!
+//! [`Callback`]s hook into the loop at the start and end of training, of each epoch and of
+//! each batch, to log, adjust the learning rate or stop early:
+//!
+//! ```ignore
+//! let mut trainer = Trainer::new(model, MseLoss)
+//!     .callback(LearningRateSchedule::new(|epoch| 0.1 * 0.9f32.powi(epoch as i32)))
+//!     .callback(EarlyStopping::new("val_loss", 3));
+//! ```
+
+use std::collections::BTreeMap;
+
+use crate::data::HostTensor;
+
+pub mod callback;
+pub mod cross_validation;
+#[cfg(feature = "fs")]
+pub mod logger;
+pub mod progress;
+pub mod trainer;
+
+pub use callback::{Callback, Context, EarlyStopping, LearningRateSchedule};
+pub use cross_validation::{CrossValidation, CrossValidator, Summary};
+#[cfg(feature = "fs")]
+pub use logger::{LogFormat, MetricsLogger, ModelCheckpoint, RunDir};
+#[cfg(feature = "progress")]
+pub use progress::ProgressBar;
+pub use progress::{Progress, ProgressReporter, Silent};
+pub use trainer::Trainer;
+
+/// A batch a [`Trainer`] can use: model inputs, and for training and evaluation, targets.
+pub trait Batch {
+    fn inputs(&self) -> &HostTensor;
+
+    fn targets(&self) -> Option<&HostTensor>;
+}
+
+impl Batch for HostTensor {
+    fn inputs(&self) -> &HostTensor {
+        self
+    }
+
+    fn targets(&self) -> Option<&HostTensor> {
+        None
+    }
+}
+
+impl Batch for (HostTensor, HostTensor) {
+    fn inputs(&self) -> &HostTensor {
+        &self.0
+    }
+
+    fn targets(&self) -> Option<&HostTensor> {
+        Some(&self.1)
+    }
+}
+
+/// Named values summarizing an epoch, such as `"loss"` and, with validation,
+/// `"val_loss"`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Logs {
+    values: BTreeMap<String, f32>,
+}
+
+impl Logs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<f32> {
+        self.values.get(name).copied()
+    }
+
+    pub fn insert(&mut self, name: impl Into<String>, value: f32) {
+        self.values.insert(name.into(), value);
+    }
+
+    /// The values in name order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, f32)> {
+        self.values
+            .iter()
+            .map(|(name, &value)| (name.as_str(), value))
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+/// The [`Logs`] of every epoch of a [`Trainer::fit`] call, in order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct History {
+    epochs: Vec<Logs>,
+}
+
+impl History {
+    pub fn epochs(&self) -> &[Logs] {
+        &self.epochs
+    }
+
+    pub fn last(&self) -> Option<&Logs> {
+        self.epochs.last()
+    }
+
+    /// The value of `name` in each epoch that logged it.
+    pub fn metric(&self, name: &str) -> Vec<f32> {
+        self.epochs
+            .iter()
+            .filter_map(|logs| logs.get(name))
+            .collect()
+    }
+
+    pub(crate) fn push(&mut self, logs: Logs) {
+        self.epochs.push(logs);
+    }
+}
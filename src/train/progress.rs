@@ -0,0 +1,295 @@
+use std::time::{Duration, Instant};
+
+use super::Logs;
+
+/// Where a [`Trainer::fit`](super::Trainer::fit) call has got to, reported after every
+/// batch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Progress {
+    /// The epoch in progress, counting from 0 within this `fit` call.
+    pub epoch: usize,
+    pub epochs: usize,
+    /// The batches finished in this epoch.
+    pub batch: usize,
+    pub batches: usize,
+    /// Optimization steps per second since `fit` started.
+    pub steps_per_second: f32,
+    /// The time left for the remaining epochs at the current rate, once there is one.
+    pub eta: Option<Duration>,
+    /// The mean loss of the epoch so far.
+    pub loss: f32,
+}
+
+/// Receives [`Progress`] from a [`Trainer`](super::Trainer) given one with
+/// [`Trainer::progress`](super::Trainer::progress). Without one, training reports nothing.
+pub trait ProgressReporter {
+    /// Called after every training batch.
+    fn update(&mut self, progress: &Progress);
+
+    /// Called after every epoch with its logs, validation values included.
+    fn epoch_end(&mut self, _progress: &Progress, _logs: &Logs) {}
+
+    /// Called once the `fit` call is over.
+    fn finish(&mut self) {}
+}
+
+/// Reports nothing, for CI logs and other places a progress bar only gets in the way.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Silent;
+
+impl ProgressReporter for Silent {
+    fn update(&mut self, _progress: &Progress) {}
+}
+
+// Times a fit call and turns batch counts into progress
+pub(crate) struct ProgressTracker {
+    start: Instant,
+    epochs: usize,
+    batches: usize,
+    epoch: usize,
+    steps: usize,
+}
+
+impl ProgressTracker {
+    pub(crate) fn new(epochs: usize, batches: usize) -> Self {
+        Self {
+            start: Instant::now(),
+            epochs,
+            batches,
+            epoch: 0,
+            steps: 0,
+        }
+    }
+
+    pub(crate) fn step(&mut self, batch: usize, loss: f32) -> Progress {
+        self.steps += 1;
+        self.progress(batch, loss)
+    }
+
+    pub(crate) fn next_epoch(&mut self) {
+        self.epoch += 1;
+    }
+
+    pub(crate) fn progress(&self, batch: usize, loss: f32) -> Progress {
+        let elapsed = self.start.elapsed().as_secs_f32();
+        let steps_per_second = if elapsed > 0.0 {
+            self.steps as f32 / elapsed
+        } else {
+            0.0
+        };
+        let remaining = (self.epochs.saturating_sub(self.epoch + 1) * self.batches)
+            + self.batches.saturating_sub(batch);
+        let eta = (steps_per_second > 0.0)
+            .then(|| Duration::from_secs_f32(remaining as f32 / steps_per_second));
+        Progress {
+            epoch: self.epoch,
+            epochs: self.epochs,
+            batch,
+            batches: self.batches,
+            steps_per_second,
+            eta,
+            loss,
+        }
+    }
+}
+
+#[cfg(feature = "progress")]
+pub use bar::ProgressBar;
+
+#[cfg(feature = "progress")]
+mod bar {
+    use std::io::{IsTerminal, Write};
+    use std::time::{Duration, Instant};
+
+    use super::{Progress, ProgressReporter};
+    use crate::train::Logs;
+
+    /// A progress bar drawn on stderr, redrawn in place at most ten times a second:
+    ///
+    /// ```text
+    /// epoch 2/10 [=========>          ] 46/100  38.2 it/s  eta 00:21  loss 0.4127
+    /// ```
+    ///
+    /// When stderr is not a terminal, as in CI, only a line per epoch is printed.
+    pub struct ProgressBar {
+        writer: Box<dyn Write>,
+        width: usize,
+        interactive: bool,
+        drawn: Option<Instant>,
+    }
+
+    impl ProgressBar {
+        pub fn new() -> Self {
+            let interactive = std::io::stderr().is_terminal();
+            Self::with_writer(std::io::stderr(), interactive)
+        }
+
+        /// Draws on `writer` instead, redrawing in place if `interactive`.
+        pub fn with_writer(writer: impl Write + 'static, interactive: bool) -> Self {
+            Self {
+                writer: Box::new(writer),
+                width: 30,
+                interactive,
+                drawn: None,
+            }
+        }
+
+        /// The width of the bar in characters. Defaults to 30.
+        pub fn width(mut self, width: usize) -> Self {
+            self.width = width.max(1);
+            self
+        }
+
+        pub(crate) fn render(&self, progress: &Progress) -> String {
+            let filled = match progress.batches {
+                0 => self.width,
+                batches => self.width * progress.batch.min(batches) / batches,
+            };
+            let arrow = if filled < self.width { ">" } else { "" };
+            let eta = progress
+                .eta
+                .map(format_duration)
+                .unwrap_or_else(|| "--:--".to_string());
+            format!(
+                "epoch {}/{} [{}{}{}] {}/{}  {:.1} it/s  eta {}  loss {:.4}",
+                progress.epoch + 1,
+                progress.epochs,
+                "=".repeat(filled),
+                arrow,
+                " ".repeat(self.width - filled - arrow.len()),
+                progress.batch,
+                progress.batches,
+                progress.steps_per_second,
+                eta,
+                progress.loss
+            )
+        }
+    }
+
+    impl Default for ProgressBar {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl ProgressReporter for ProgressBar {
+        fn update(&mut self, progress: &Progress) {
+            if !self.interactive {
+                return;
+            }
+            let due = self
+                .drawn
+                .is_none_or(|drawn| drawn.elapsed() >= Duration::from_millis(100));
+            if due || progress.batch == progress.batches {
+                let line = self.render(progress);
+                // Progress output is best effort; a closed stderr shouldn't stop training
+                let _ = write!(self.writer, "\r{}\x1b[K", line);
+                let _ = self.writer.flush();
+                self.drawn = Some(Instant::now());
+            }
+        }
+
+        fn epoch_end(&mut self, progress: &Progress, logs: &Logs) {
+            let mut line = self.render(progress);
+            for (name, value) in logs.iter().filter(|(name, _)| *name != "loss") {
+                line.push_str(&format!("  {} {:.4}", name, value));
+            }
+            let start = if self.interactive { "\r" } else { "" };
+            let _ = writeln!(self.writer, "{}{}\x1b[K", start, line);
+            let _ = self.writer.flush();
+            self.drawn = None;
+        }
+    }
+
+    // As mm:ss, or h:mm:ss from an hour up
+    fn format_duration(duration: Duration) -> String {
+        let seconds = duration.as_secs();
+        let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+        if hours > 0 {
+            format!("{}:{:02}:{:02}", hours, minutes, seconds)
+        } else {
+            format!("{:02}:{:02}", minutes, seconds)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{DataLoader, HostTensor, TensorDataset};
+    use crate::loss::MseLoss;
+    use crate::nn::Linear;
+    use crate::train::Trainer;
+    use crate::MlResult;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    struct Recorder(Rc<RefCell<Vec<Progress>>>);
+
+    impl ProgressReporter for Recorder {
+        fn update(&mut self, progress: &Progress) {
+            self.0.borrow_mut().push(progress.clone());
+        }
+    }
+
+    #[test]
+    fn test_progress_reporting() -> MlResult<()> {
+        let dataset = TensorDataset::from_host(
+            HostTensor::new(vec![0.0, 1.0, 2.0, 3.0, 4.0], &[5, 1])?,
+            HostTensor::new(vec![0.0, 1.0, 2.0, 3.0, 4.0], &[5, 1])?,
+        )?;
+        let loader = DataLoader::new(dataset, 2);
+        let updates = Rc::new(RefCell::new(Vec::new()));
+        let mut trainer = Trainer::new(Linear::new(1, 1, true)?, MseLoss)
+            .learning_rate(0.01)
+            .progress(Recorder(updates.clone()));
+        trainer.fit(&loader, 2)?;
+
+        let updates = updates.borrow();
+        let counts: Vec<(usize, usize, usize)> = updates
+            .iter()
+            .map(|p| (p.epoch, p.batch, p.batches))
+            .collect();
+        assert_eq!(
+            counts,
+            [
+                (0, 1, 3),
+                (0, 2, 3),
+                (0, 3, 3),
+                (1, 1, 3),
+                (1, 2, 3),
+                (1, 3, 3)
+            ]
+        );
+        assert!(updates.iter().all(|p| p.epochs == 2 && p.loss.is_finite()));
+        let last = updates.last().unwrap();
+        assert!(last.eta.is_none_or(|eta| eta.is_zero()));
+
+        // Halfway through the first of two epochs, three quarters of the work is left
+        let mut tracker = ProgressTracker::new(2, 4);
+        tracker.steps = 2;
+        tracker.start -= Duration::from_secs(1);
+        let progress = tracker.progress(2, 0.5);
+        let eta = progress.eta.unwrap().as_secs_f32();
+        assert!((eta - 6.0 / progress.steps_per_second).abs() < 1e-3);
+
+        #[cfg(feature = "progress")]
+        {
+            let bar = ProgressBar::with_writer(std::io::sink(), false).width(10);
+            let progress = Progress {
+                epoch: 1,
+                epochs: 10,
+                batch: 5,
+                batches: 10,
+                steps_per_second: 12.5,
+                eta: Some(Duration::from_secs(3725)),
+                loss: 0.25,
+            };
+            assert_eq!(
+                bar.render(&progress),
+                "epoch 2/10 [=====>    ] 5/10  12.5 it/s  eta 1:02:05  loss 0.2500"
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,377 @@
+use super::progress::ProgressTracker;
+use super::{Batch, Callback, Context, History, Logs, ProgressReporter};
+use crate::backend::DeviceType;
+use crate::data::{DataLoader, Dataset, HostTensor};
+use crate::loss::Loss;
+use crate::metrics::Metric;
+use crate::nn::Layer;
+use crate::tensor::Tensor;
+use crate::MlResult;
+
+/// Trains and runs a model with a loss, logging any [`Metric`]s alongside it and calling
+/// any [`Callback`]s as it goes.
+///
+/// Layers apply their updates as they backpropagate, so the learning rate is the optimizer
+/// and there are no gradients to zero between steps. Losses are averaged over each epoch
+/// weighted by batch size, so a short last batch counts for what it holds.
+pub struct Trainer<M, L> {
+    model: M,
+    loss: L,
+    learning_rate: f32,
+    device: Option<DeviceType>,
+    metrics: Vec<Box<dyn Metric>>,
+    callbacks: Vec<Box<dyn Callback<M>>>,
+    progress: Option<Box<dyn ProgressReporter>>,
+    epoch: usize,
+    step: usize,
+    stop: bool,
+}
+
+impl<M: Layer, L: Loss> Trainer<M, L> {
+    pub fn new(model: M, loss: L) -> Self {
+        Self {
+            model,
+            loss,
+            learning_rate: 0.01,
+            device: None,
+            metrics: Vec::new(),
+            callbacks: Vec::new(),
+            progress: None,
+            epoch: 0,
+            step: 0,
+            stop: false,
+        }
+    }
+
+    /// The step size passed to [`Layer::backward`]. Defaults to 0.01.
+    pub fn learning_rate(mut self, learning_rate: f32) -> Self {
+        self.learning_rate = learning_rate;
+        self
+    }
+
+    /// Moves every batch to `device`; otherwise batches are made on the default device.
+    pub fn device(mut self, device: DeviceType) -> Self {
+        self.device = Some(device);
+        self
+    }
+
+    /// Computes `metric` over every training and evaluation epoch, logged under its name.
+    pub fn metric(mut self, metric: impl Metric + 'static) -> Self {
+        self.metrics.push(Box::new(metric));
+        self
+    }
+
+    /// Adds `callback`, called after those added before it.
+    pub fn callback(mut self, callback: impl Callback<M> + 'static) -> Self {
+        self.callbacks.push(Box::new(callback));
+        self
+    }
+
+    /// Reports progress to `reporter` after every batch of [`Trainer::fit`]; by default
+    /// training is silent.
+    pub fn progress(mut self, reporter: impl ProgressReporter + 'static) -> Self {
+        self.progress = Some(Box::new(reporter));
+        self
+    }
+
+    pub fn set_learning_rate(&mut self, learning_rate: f32) {
+        self.learning_rate = learning_rate;
+    }
+
+    pub fn get_learning_rate(&self) -> f32 {
+        self.learning_rate
+    }
+
+    pub fn model(&self) -> &M {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut M {
+        &mut self.model
+    }
+
+    pub fn into_model(self) -> M {
+        self.model
+    }
+
+    /// The number of epochs trained so far, across [`Trainer::fit`] calls.
+    pub fn epoch(&self) -> usize {
+        self.epoch
+    }
+
+    /// The number of optimization steps taken so far.
+    pub fn step(&self) -> usize {
+        self.step
+    }
+
+    /// One optimization step on a batch already made into tensors, returning its loss.
+    pub fn train_step(&mut self, inputs: &Tensor, targets: &Tensor) -> MlResult<f32> {
+        let predictions = self.model.forward(inputs)?;
+        let loss = self.loss.loss(&predictions, targets)?;
+        let grad = self.loss.gradient(&predictions, targets)?;
+        self.model.backward(inputs, &grad, self.learning_rate)?;
+        self.step += 1;
+        for metric in &mut self.metrics {
+            metric.update(&predictions, targets)?;
+        }
+        self.run_callbacks(|callback, ctx| callback.on_backward_end(ctx, loss))?;
+        Ok(loss)
+    }
+
+    /// Trains for `epochs` passes over `train`, logging each epoch's mean `"loss"` and its
+    /// metrics. Ends early if a callback stops training.
+    pub fn fit<D, B>(&mut self, train: &DataLoader<D, B>, epochs: usize) -> MlResult<History>
+    where
+        D: Dataset + Send + Sync + 'static,
+        B: Batch + Send + 'static,
+    {
+        self.run(train, None::<&DataLoader<D, B>>, epochs)
+    }
+
+    /// Like [`Trainer::fit`], evaluating on `validation` after every epoch and logging its
+    /// values with a `val_` prefix.
+    pub fn fit_validated<D, B, V, C>(
+        &mut self,
+        train: &DataLoader<D, B>,
+        validation: &DataLoader<V, C>,
+        epochs: usize,
+    ) -> MlResult<History>
+    where
+        D: Dataset + Send + Sync + 'static,
+        B: Batch + Send + 'static,
+        V: Dataset + Send + Sync + 'static,
+        C: Batch + Send + 'static,
+    {
+        self.run(train, Some(validation), epochs)
+    }
+
+    /// The mean `"loss"` and the metrics over `loader`, without training.
+    pub fn evaluate<D, B>(&mut self, loader: &DataLoader<D, B>) -> MlResult<Logs>
+    where
+        D: Dataset + Send + Sync + 'static,
+        B: Batch + Send + 'static,
+    {
+        self.reset_metrics();
+        let mut mean = RunningMean::default();
+        for batch in loader.iter() {
+            let batch = batch?;
+            let (inputs, targets) = self.tensors(&batch)?;
+            let predictions = self.model.forward(&inputs)?;
+            mean.add(self.loss.loss(&predictions, &targets)?, batch_len(&batch));
+            for metric in &mut self.metrics {
+                metric.update(&predictions, &targets)?;
+            }
+        }
+        Ok(self.logs(&mean))
+    }
+
+    /// The model's outputs for every sample of `loader`, in order, stacked along the first
+    /// axis. Targets, if the batches have any, are ignored.
+    pub fn predict<D, B>(&self, loader: &DataLoader<D, B>) -> MlResult<Tensor>
+    where
+        D: Dataset + Send + Sync + 'static,
+        B: Batch + Send + 'static,
+    {
+        let mut data = Vec::new();
+        let mut shape: Option<Vec<usize>> = None;
+        for batch in loader.iter() {
+            let inputs = self.to_device(batch?.inputs())?;
+            let outputs = self.model.forward(&inputs)?;
+            let rows = shape.get_or_insert_with(|| {
+                let mut shape = outputs.shape().to_vec();
+                shape[0] = 0;
+                shape
+            });
+            rows[0] += outputs.shape()[0];
+            data.extend_from_slice(outputs.data());
+        }
+        Tensor::from_vec(data, &shape.unwrap_or_else(|| vec![0]))
+    }
+
+    fn run<D, B, V, C>(
+        &mut self,
+        train: &DataLoader<D, B>,
+        validation: Option<&DataLoader<V, C>>,
+        epochs: usize,
+    ) -> MlResult<History>
+    where
+        D: Dataset + Send + Sync + 'static,
+        B: Batch + Send + 'static,
+        V: Dataset + Send + Sync + 'static,
+        C: Batch + Send + 'static,
+    {
+        self.stop = false;
+        self.run_callbacks(|callback, ctx| callback.on_train_begin(ctx))?;
+        let mut history = History::default();
+        let mut tracker = ProgressTracker::new(epochs, train.len());
+        for _ in 0..epochs {
+            if self.stop {
+                break;
+            }
+            self.run_callbacks(|callback, ctx| callback.on_epoch_begin(ctx))?;
+            let mut logs = self.train_epoch(train, &mut tracker)?;
+            if let Some(validation) = validation {
+                for (name, value) in self.evaluate(validation)?.iter() {
+                    logs.insert(format!("val_{}", name), value);
+                }
+            }
+            self.run_callbacks(|callback, ctx| callback.on_epoch_end(ctx, &mut logs))?;
+            if let Some(reporter) = &mut self.progress {
+                let loss = logs.get("loss").unwrap_or(f32::NAN);
+                reporter.epoch_end(&tracker.progress(train.len(), loss), &logs);
+            }
+            tracker.next_epoch();
+            self.epoch += 1;
+            history.push(logs);
+        }
+        if let Some(reporter) = &mut self.progress {
+            reporter.finish();
+        }
+        self.run_callbacks(|callback, ctx| callback.on_train_end(ctx, &history))?;
+        Ok(history)
+    }
+
+    fn train_epoch<D, B>(
+        &mut self,
+        train: &DataLoader<D, B>,
+        tracker: &mut ProgressTracker,
+    ) -> MlResult<Logs>
+    where
+        D: Dataset + Send + Sync + 'static,
+        B: Batch + Send + 'static,
+    {
+        self.reset_metrics();
+        let mut mean = RunningMean::default();
+        for (index, batch) in train.iter().enumerate() {
+            let batch = batch?;
+            let (inputs, targets) = self.tensors(&batch)?;
+            let loss = self.train_step(&inputs, &targets)?;
+            mean.add(loss, batch_len(&batch));
+            let progress = tracker.step(index + 1, mean.value());
+            if let Some(reporter) = &mut self.progress {
+                reporter.update(&progress);
+            }
+            self.run_callbacks(|callback, ctx| callback.on_batch_end(ctx, index, loss))?;
+        }
+        Ok(self.logs(&mean))
+    }
+
+    // Calls `hook` on each callback in turn, stopping at the first error
+    fn run_callbacks(
+        &mut self,
+        mut hook: impl FnMut(&mut dyn Callback<M>, &mut Context<'_, M>) -> MlResult<()>,
+    ) -> MlResult<()> {
+        let mut ctx = Context {
+            model: &mut self.model,
+            learning_rate: &mut self.learning_rate,
+            stop: &mut self.stop,
+            epoch: self.epoch,
+            step: self.step,
+        };
+        self.callbacks
+            .iter_mut()
+            .try_for_each(|callback| hook(callback.as_mut(), &mut ctx))
+    }
+
+    fn reset_metrics(&mut self) {
+        for metric in &mut self.metrics {
+            metric.reset();
+        }
+    }
+
+    fn logs(&self, loss: &RunningMean) -> Logs {
+        let mut logs = Logs::new();
+        logs.insert("loss", loss.value());
+        for metric in &self.metrics {
+            logs.insert(metric.name(), metric.compute());
+        }
+        logs
+    }
+
+    // A batch's inputs and targets on the trainer's device
+    fn tensors<B: Batch>(&self, batch: &B) -> MlResult<(Tensor, Tensor)> {
+        let targets = batch
+            .targets()
+            .ok_or("Training and evaluation need batches with targets")?;
+        Ok((self.to_device(batch.inputs())?, self.to_device(targets)?))
+    }
+
+    fn to_device(&self, host: &HostTensor) -> MlResult<Tensor> {
+        let tensor = host.to_tensor()?;
+        match self.device {
+            Some(device) if device != tensor.device() => tensor.to_device(device),
+            _ => Ok(tensor),
+        }
+    }
+}
+
+fn batch_len<B: Batch>(batch: &B) -> usize {
+    batch.inputs().shape().first().copied().unwrap_or(1)
+}
+
+// A mean of per-batch values weighted by the number of samples in each batch
+#[derive(Debug, Default)]
+struct RunningMean {
+    total: f64,
+    count: usize,
+}
+
+impl RunningMean {
+    fn add(&mut self, value: f32, samples: usize) {
+        self.total += value as f64 * samples as f64;
+        self.count += samples;
+    }
+
+    fn value(&self) -> f32 {
+        if self.count == 0 {
+            return f32::NAN;
+        }
+        (self.total / self.count as f64) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::TensorDataset;
+    use crate::loss::MseLoss;
+    use crate::nn::Linear;
+
+    fn line(samples: usize) -> MlResult<TensorDataset> {
+        let x: Vec<f32> = (0..samples * 2)
+            .map(|i| ((i * 37) % 19) as f32 / 19.0 - 0.5)
+            .collect();
+        let y: Vec<f32> = x.chunks_exact(2).map(|p| 2.0 * p[0] - p[1] + 0.5).collect();
+        TensorDataset::from_host(
+            HostTensor::new(x, &[samples, 2])?,
+            HostTensor::new(y, &[samples, 1])?,
+        )
+    }
+
+    #[test]
+    fn test_trainer_fits_a_line() -> MlResult<()> {
+        let train = DataLoader::new(line(64)?, 8).shuffle(true).seed(1);
+        let validation = DataLoader::new(line(20)?, 7);
+        let mut trainer = Trainer::new(Linear::new(2, 1, true)?, MseLoss)
+            .learning_rate(0.3)
+            .device(DeviceType::Cpu);
+
+        let before = trainer.evaluate(&validation)?.get("loss").unwrap();
+        let history = trainer.fit_validated(&train, &validation, 40)?;
+        assert_eq!(history.epochs().len(), 40);
+        assert_eq!(trainer.epoch(), 40);
+        let losses = history.metric("val_loss");
+        assert!(losses[39] < before * 0.05, "{} -> {}", before, losses[39]);
+        assert!(history.metric("loss")[39] < history.metric("loss")[0]);
+
+        let predictions = trainer.predict(&validation)?;
+        assert_eq!(predictions.shape(), &[20, 1]);
+        let unlabelled = DataLoader::with_collate(line(3)?, 2, |items| {
+            let inputs: Vec<HostTensor> = items.into_iter().map(|(x, _)| x).collect();
+            HostTensor::stack(&inputs)
+        });
+        assert_eq!(trainer.predict(&unlabelled)?.shape(), &[3, 1]);
+        assert!(trainer.evaluate(&unlabelled).is_err());
+        Ok(())
+    }
+}